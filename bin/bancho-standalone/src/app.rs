@@ -8,13 +8,25 @@ use core_geoip::*;
 use core_signature::*;
 use infra_services::IntoService;
 use peace_api::{ApiFrameConfig, WebApplication};
+use peace_cfg::{
+    check_rpc_peer, validate_rpc_client_config, ConfigError, ConfigErrors,
+    Secret, ValidateConfig,
+};
 use peace_db::{
     peace::{Peace, PeaceDbConfig},
     DbConfig, DbConnection,
 };
-use peace_repositories::users::{DynUsersRepository, UsersRepositoryImpl};
+use peace_difficulty::{
+    BeatmapFiles, CliBeatmapFilesConfigs, DifficultyServiceImpl,
+    DynDifficultyService,
+};
+use peace_repositories::{
+    channels::{ChannelsRepositoryImpl, DynChannelsRepository},
+    scores::{DynScoresRepository, ScoresRepositoryImpl},
+    users::{DynUsersRepository, UsersRepositoryImpl},
+};
 use peace_runtime::cfg::RuntimeConfig;
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, path::Path, sync::Arc, time::Duration};
 use utoipa::OpenApi;
 
 /// PEACE Bancho standalone (web) service
@@ -39,6 +51,18 @@ pub struct BanchoStandaloneConfig {
     #[arg(long)]
     pub debug_endpoints: bool,
 
+    #[command(flatten)]
+    pub bancho_routing: CliBanchoRoutingServiceConfigs,
+
+    #[command(flatten)]
+    pub bancho_login_limiter: CliBanchoLoginLimiterConfigs,
+
+    #[command(flatten)]
+    pub bancho_client_version: CliBanchoClientVersionConfigs,
+
+    #[command(flatten)]
+    pub bancho_state_service_configs: CliBanchoStateServiceConfigs,
+
     #[command(flatten)]
     pub bancho_state_background_service_configs:
         CliBanchoStateBackgroundServiceConfigs,
@@ -46,9 +70,18 @@ pub struct BanchoStandaloneConfig {
     #[command(flatten)]
     pub bancho_background_service_configs: CliBanchoBackgroundServiceConfigs,
 
+    #[command(flatten)]
+    pub bancho_service_configs: CliBanchoServiceConfigs,
+
+    #[command(flatten)]
+    pub beatmap_files: CliBeatmapFilesConfigs,
+
     #[command(flatten)]
     pub chat_background_service_configs: CliChatBackgroundServiceConfigs,
 
+    #[command(flatten)]
+    pub chat_service_configs: CliChatServiceConfigs,
+
     #[command(flatten)]
     pub geoip: GeoipRpcConfig,
 
@@ -66,6 +99,66 @@ pub struct BanchoStandaloneConfig {
 
     #[command(flatten)]
     pub bancho_state_snapshot: CliBanchoStateServiceSnapshotConfigs,
+
+    #[command(flatten)]
+    pub bancho_state_incremental_snapshot:
+        CliBanchoStateIncrementalSnapshotConfigs,
+}
+
+#[async_trait]
+impl ValidateConfig for BanchoStandaloneConfig {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        errors.merge(self.bancho_routing.validate());
+        errors.merge(self.bancho_login_limiter.validate());
+        errors.merge(self.bancho_service_configs.validate());
+        errors.merge(self.bancho_state_service_configs.validate());
+        errors.merge(self.bancho_state_background_service_configs.validate());
+        errors.merge(self.bancho_state_snapshot.validate());
+        errors.merge(self.bancho_state_incremental_snapshot.validate());
+
+        // Services fall back to the remote ones without local files
+        match self.geo_db_path.as_deref() {
+            Some(path) => {
+                errors.check_file("geo_db_path", Some(Path::new(path)))
+            },
+            None => {
+                errors.merge(validate_rpc_client_config(&self.geoip, "geoip"))
+            },
+        }
+
+        match self.ed25519_private_key_path.as_deref() {
+            Some(path) => errors
+                .check_file("ed25519_private_key_path", Some(Path::new(path))),
+            None => errors.merge(validate_rpc_client_config(
+                &self.signature_rpc_cfg,
+                "signature",
+            )),
+        }
+
+        errors.into_result()
+    }
+
+    async fn check_peers(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        if let Err(err) = self.peace_db.connect().await {
+            errors.push("peace_db", format!("failed to connect: {err}"));
+        }
+
+        if self.geo_db_path.is_none() {
+            errors.merge(check_rpc_peer(&self.geoip, "geoip").await);
+        }
+
+        if self.ed25519_private_key_path.is_none() {
+            errors.merge(
+                check_rpc_peer(&self.signature_rpc_cfg, "signature").await,
+            );
+        }
+
+        errors.into_result()
+    }
 }
 
 #[derive(Clone)]
@@ -76,7 +169,11 @@ pub struct App {
     pub signature_service: DynSignatureService,
     pub bancho_state_service: DynBanchoStateService,
     pub users_repository: DynUsersRepository,
+    pub scores_repository: DynScoresRepository,
+    pub channels_repository: DynChannelsRepository,
+    pub difficulty_service: DynDifficultyService,
     pub password_service: DynPasswordService,
+    pub leaderboard_service: DynLeaderboardService,
     pub geoip_service: DynGeoipService,
     pub chat_service: DynChatService,
     pub chat_background_service: DynChatBackgroundService,
@@ -110,6 +207,8 @@ impl App {
 
         let bancho_state_service = BanchoStateServiceSnapshotLoader::load(
             &cfg.bancho_state_snapshot,
+            &cfg.bancho_state_incremental_snapshot,
+            &cfg.bancho_state_service_configs,
             signature_service.clone(),
         )
         .await;
@@ -122,10 +221,29 @@ impl App {
         let users_repository =
             UsersRepositoryImpl::new(peace_db_conn.clone()).into_service();
 
+        let scores_repository =
+            ScoresRepositoryImpl::new(peace_db_conn.clone()).into_service();
+
+        let channels_repository =
+            ChannelsRepositoryImpl::new(peace_db_conn.clone()).into_service();
+
+        let difficulty_service = DifficultyServiceImpl::new(
+            BeatmapFiles::with_cfg(&cfg.beatmap_files).with_lookup(Arc::new(
+                ScoresRepositoryImpl::new(peace_db_conn.clone()),
+            )),
+        )
+        .into_service();
+
         let password_service = PasswordServiceImpl::default();
         let password_cache_store = password_service.cache_store().clone();
         let password_service = password_service.into_service();
 
+        let leaderboard_service = LeaderboardServiceImpl::with_cfg(
+            scores_repository.clone(),
+            &cfg.bancho_service_configs,
+        )
+        .into_service();
+
         let geoip_service =
             GeoipServiceBuilder::build::<GeoipServiceImpl, GeoipServiceRemote>(
                 cfg.geo_db_path.as_deref(),
@@ -135,7 +253,9 @@ impl App {
 
         let chat_service = ChatServiceSnapshotLoader::load(
             &cfg.chat_snapshot,
+            &cfg.chat_service_configs,
             users_repository.clone(),
+            channels_repository.clone(),
         )
         .await
         .into_service();
@@ -166,11 +286,13 @@ impl App {
         let bancho_state_background_service =
             Arc::new(BanchoStateBackgroundServiceImpl::new(
                 user_sessions_service.clone(),
+                bancho_state_service.clone(),
             ));
 
         let bancho_state_background_service_config =
             BanchoStateBackgroundServiceConfigs::with_cfg(
                 &cfg.bancho_state_background_service_configs,
+                &cfg.bancho_state_incremental_snapshot,
             );
 
         chat_background_service
@@ -182,11 +304,23 @@ impl App {
 
         let bancho_service = BanchoServiceImpl::new(
             users_repository.clone(),
+            scores_repository.clone(),
             bancho_state_service.clone(),
             password_service.clone(),
             bancho_background_service.clone(),
             geoip_service.clone(),
             chat_service.clone(),
+            difficulty_service.clone(),
+            leaderboard_service.clone(),
+            Arc::new(cfg.bancho_service_configs.email_validator()),
+            Arc::new(cfg.bancho_service_configs.message_limiter()),
+            cfg.bancho_service_configs.session_packet_history_size,
+            cfg.bancho_service_configs.max_favourite_beatmapsets,
+            cfg.bancho_service_configs
+                .client_hashes_salt
+                .clone()
+                .map(Secret::into_inner),
+            cfg.bancho_service_configs.geoip_default_country.clone(),
         )
         .into_service();
 
@@ -194,12 +328,17 @@ impl App {
             bancho_service.clone(),
             bancho_state_service.clone(),
             chat_service.clone(),
+            Arc::new(login_limiter(&cfg.bancho_login_limiter)),
+            Arc::new(ClientVersionPolicy::with_cfg(&cfg.bancho_client_version)),
         )
         .into_service();
 
-        let bancho_routing_service =
-            BanchoRoutingServiceImpl::new(bancho_handler_service.clone())
-                .into_service();
+        let bancho_routing_service = BanchoRoutingServiceImpl::with_cfg(
+            bancho_handler_service.clone(),
+            difficulty_service.clone(),
+            &cfg.bancho_routing,
+        )
+        .into_service();
 
         Self {
             cfg,
@@ -208,7 +347,11 @@ impl App {
             signature_service,
             bancho_state_service,
             users_repository,
+            scores_repository,
+            channels_repository,
+            difficulty_service,
             password_service,
+            leaderboard_service,
             geoip_service,
             chat_service,
             chat_background_service,
@@ -239,12 +382,15 @@ impl WebApplication for App {
     }
 
     async fn router<T: Clone + Sync + Send + 'static>(&self) -> Router<T> {
-        let mut router =
-            BanchoRouter::new_router(self.bancho_routing_service.clone());
+        let mut router = BanchoRouter::new_router(
+            self.bancho_routing_service.clone(),
+            self.cfg.bancho_routing.max_bancho_request_size,
+        );
 
         if self.cfg.debug_endpoints {
             router = router.merge(BanchoDebugRouter::new_router(
                 self.bancho_state_service.clone(),
+                self.bancho_service.clone(),
             ))
         }
 
@@ -260,4 +406,23 @@ impl WebApplication for App {
 
         docs
     }
+
+    async fn admin_router(&self) -> Option<Router> {
+        Some(BanchoAdminRouter::new_router(
+            self.bancho_state_service.clone(),
+            self.bancho_service.clone(),
+        ))
+    }
+
+    fn admin_apidocs(&self) -> Option<utoipa::openapi::OpenApi> {
+        Some(BanchoAdminEndpointsDocs::openapi())
+    }
+
+    /// Refuse the new logins, then wait for the clients to dequeue their
+    /// pending packets.
+    async fn drain(&self) {
+        self.user_sessions_service
+            .wait_packets_drained(Duration::from_millis(500))
+            .await
+    }
 }
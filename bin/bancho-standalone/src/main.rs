@@ -24,7 +24,7 @@ pub async fn run(
             .chat_service
             .save_service_snapshot(
                 cfg.chat_snapshot.snapshot_type(),
-                cfg.chat_snapshot.snapshot_path(),
+                &cfg.chat_snapshot.snapshot_file_path(),
             )
             .await;
     }
@@ -34,7 +34,7 @@ pub async fn run(
             .bancho_state_service
             .save_service_snapshot(
                 cfg.bancho_state_snapshot.snapshot_type(),
-                cfg.bancho_state_snapshot.snapshot_path(),
+                &cfg.bancho_state_snapshot.snapshot_file_path(),
             )
             .await;
     }
@@ -47,6 +47,10 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     tools::main_startup_info!();
 
     let cfg = BanchoStandaloneConfig::get();
+
+    // Validate the configuration, or only check it with `--check-config`.
+    peace_runtime::check_config_or_exit(&cfg.runtime_cfg, &*cfg);
+
     // Initialize the logger.
     peace_logs::init(&cfg.frame_cfg);
 
@@ -10,13 +10,14 @@ use infra_services::IntoService;
 use pb_bancho_state::{
     bancho_state_rpc_server::BanchoStateRpcServer, BANCHO_STATE_DESCRIPTOR_SET,
 };
-use peace_rpc::{RpcApplication, RpcFrameConfig};
-use peace_runtime::cfg::RuntimeConfig;
-use std::{net::SocketAddr, sync::Arc};
-use tonic::{
-    async_trait,
-    transport::{server::Router, Server},
+use peace_cfg::{
+    check_rpc_peer, validate_rpc_client_config, ConfigError, ConfigErrors,
+    ValidateConfig,
 };
+use peace_rpc::{RpcApplication, RpcFrameConfig, RpcRouter, RpcServer};
+use peace_runtime::cfg::RuntimeConfig;
+use std::{net::SocketAddr, path::Path, sync::Arc, time::Duration};
+use tonic::async_trait;
 
 /// BanchoState gRPC service
 #[peace_config]
@@ -34,6 +35,9 @@ pub struct BanchoStateConfig {
     #[command(flatten)]
     pub frame_cfg: RpcFrameConfig,
 
+    #[command(flatten)]
+    pub bancho_state_service_configs: CliBanchoStateServiceConfigs,
+
     #[command(flatten)]
     pub bancho_state_background_service_configs:
         CliBanchoStateBackgroundServiceConfigs,
@@ -46,6 +50,41 @@ pub struct BanchoStateConfig {
 
     #[command(flatten)]
     pub bancho_state_snapshot: CliBanchoStateServiceSnapshotConfigs,
+
+    #[command(flatten)]
+    pub bancho_state_incremental_snapshot:
+        CliBanchoStateIncrementalSnapshotConfigs,
+}
+
+#[async_trait]
+impl ValidateConfig for BanchoStateConfig {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        errors.merge(self.frame_cfg.validate());
+        errors.merge(self.bancho_state_service_configs.validate());
+        errors.merge(self.bancho_state_background_service_configs.validate());
+        errors.merge(self.bancho_state_snapshot.validate());
+        errors.merge(self.bancho_state_incremental_snapshot.validate());
+
+        match self.ed25519_private_key_path.as_deref() {
+            Some(path) => errors
+                .check_file("ed25519_private_key_path", Some(Path::new(path))),
+            None => errors.merge(validate_rpc_client_config(
+                &self.signature_rpc_cfg,
+                "signature",
+            )),
+        }
+
+        errors.into_result()
+    }
+
+    async fn check_peers(&self) -> Result<(), Vec<ConfigError>> {
+        match self.ed25519_private_key_path {
+            Some(_) => Ok(()),
+            None => check_rpc_peer(&self.signature_rpc_cfg, "signature").await,
+        }
+    }
 }
 
 /// The BanchoState application struct.
@@ -77,6 +116,8 @@ impl App {
 
         let bancho_state_service = BanchoStateServiceSnapshotLoader::load(
             &cfg.bancho_state_snapshot,
+            &cfg.bancho_state_incremental_snapshot,
+            &cfg.bancho_state_service_configs,
             signature_service.clone(),
         )
         .await;
@@ -89,11 +130,13 @@ impl App {
         let bancho_state_background_service =
             Arc::new(BanchoStateBackgroundServiceImpl::new(
                 user_sessions_service.clone(),
+                bancho_state_service.clone(),
             ));
 
         let bancho_state_background_service_config =
             BanchoStateBackgroundServiceConfigs::with_cfg(
                 &cfg.bancho_state_background_service_configs,
+                &cfg.bancho_state_incremental_snapshot,
             );
 
         bancho_state_background_service
@@ -132,10 +175,51 @@ impl RpcApplication for App {
     }
 
     /// Start the BanchoState application and return a Router.
-    async fn service(&self, mut configured_server: Server) -> Router {
+    async fn service(&self, mut configured_server: RpcServer) -> RpcRouter {
         // Add the BanchoState service to the server.
         configured_server.add_service(BanchoStateRpcServer::new(
             self.bancho_state_rpc.clone(),
         ))
     }
+
+    /// Refuse the new logins, then wait for the clients to dequeue their
+    /// pending packets.
+    async fn drain(&self) {
+        self.user_sessions_service
+            .wait_packets_drained(Duration::from_millis(500))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_validate_bancho_state_config() {
+        assert!(BanchoStateConfig::parse_from(["bancho_state"])
+            .validate()
+            .is_ok());
+
+        let cfg = BanchoStateConfig::parse_from([
+            "bancho_state",
+            "--bancho-batch-push-concurrency",
+            "0",
+            "--bancho-quiet-online-statuses",
+            "1,200",
+            "--ed25519-private-key-path",
+            "not-exists.pem",
+        ]);
+
+        let errors = cfg.validate().unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.field.as_str()).collect::<Vec<_>>(),
+            vec![
+                "bancho_batch_push_concurrency",
+                "bancho_quiet_online_statuses",
+                "ed25519_private_key_path",
+            ]
+        );
+    }
 }
@@ -7,6 +7,7 @@ extern crate peace_rpc;
 
 pub mod app;
 pub mod rpc;
+pub mod snapshot;
 
 pub use app::*;
 pub use rpc::*;
@@ -27,7 +28,7 @@ pub async fn run(
             .bancho_state_service
             .save_service_snapshot(
                 cfg.bancho_state_snapshot.snapshot_type(),
-                cfg.bancho_state_snapshot.snapshot_path(),
+                &cfg.bancho_state_snapshot.snapshot_file_path(),
             )
             .await;
     }
@@ -37,9 +38,18 @@ pub async fn run(
 
 /// The main entry point of the application.
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // `snapshot` subcommands run without starting the service.
+    if std::env::args().nth(1).as_deref() == Some("snapshot") {
+        return snapshot::main(std::env::args().skip(1));
+    }
+
     tools::main_startup_info!();
 
     let cfg = BanchoStateConfig::get();
+
+    // Validate the configuration, or only check it with `--check-config`.
+    peace_runtime::check_config_or_exit(&cfg.runtime_cfg, &*cfg);
+
     // Initialize the logger.
     peace_logs::init(&cfg.frame_cfg);
 
@@ -31,6 +31,18 @@ impl bancho_state_rpc_server::BanchoStateRpc for BanchoStateRpcImpl {
         Ok(Response::new(res))
     }
 
+    async fn broadcast_announcement(
+        &self,
+        request: Request<BroadcastAnnouncementRequest>,
+    ) -> Result<Response<BroadcastAnnouncementResponse>, Status> {
+        let res = self
+            .bancho_state_service
+            .broadcast_announcement(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
     async fn enqueue_bancho_packets(
         &self,
         request: Request<EnqueueBanchoPacketsRequest>,
@@ -67,6 +79,30 @@ impl bancho_state_rpc_server::BanchoStateRpc for BanchoStateRpcImpl {
         Ok(Response::new(res))
     }
 
+    async fn record_packets(
+        &self,
+        request: Request<RecordPacketsRequest>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let res = self
+            .bancho_state_service
+            .record_packets(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn get_packet_history(
+        &self,
+        request: Request<RawUserQuery>,
+    ) -> Result<Response<PacketHistoryResponse>, Status> {
+        let res = self
+            .bancho_state_service
+            .get_packet_history(request.into_inner().into_user_query()?)
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
     async fn create_user_session(
         &self,
         request: Request<CreateUserSessionRequest>,
@@ -91,6 +127,18 @@ impl bancho_state_rpc_server::BanchoStateRpc for BanchoStateRpcImpl {
         Ok(Response::new(res))
     }
 
+    async fn kick_users_by_ip(
+        &self,
+        request: Request<KickUsersByIpRequest>,
+    ) -> Result<Response<KickUsersByIpResponse>, Status> {
+        let res = self
+            .bancho_state_service
+            .kick_users_by_ip(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
     async fn check_user_token(
         &self,
         request: Request<CheckUserTokenRequest>,
@@ -135,6 +183,30 @@ impl bancho_state_rpc_server::BanchoStateRpc for BanchoStateRpcImpl {
         Ok(Response::new(res))
     }
 
+    async fn get_user_sessions_with_fields(
+        &self,
+        request: Request<RawUserQueriesWithFields>,
+    ) -> Result<Response<GetUserSessionsResponse>, Status> {
+        let res = self
+            .bancho_state_service
+            .get_user_sessions_with_fields(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn get_user_session_presence(
+        &self,
+        request: Request<RawUserQuery>,
+    ) -> Result<Response<GetUserSessionPresenceResponse>, Status> {
+        let res = self
+            .bancho_state_service
+            .get_user_session_presence(request.into_inner().into_user_query()?)
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
     async fn get_user_session_with_fields(
         &self,
         request: Request<RawUserQueryWithFields>,
@@ -156,6 +228,39 @@ impl bancho_state_rpc_server::BanchoStateRpc for BanchoStateRpcImpl {
         Ok(Response::new(res))
     }
 
+    async fn get_sessions_page(
+        &self,
+        request: Request<GetSessionsPageRequest>,
+    ) -> Result<Response<GetSessionsPageResponse>, Status> {
+        let res = self
+            .bancho_state_service
+            .get_sessions_page(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn get_sessions_stats(
+        &self,
+        _: Request<GetSessionsStatsRequest>,
+    ) -> Result<Response<SessionsStats>, Status> {
+        let res = self.bancho_state_service.get_sessions_stats().await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn get_online_summary(
+        &self,
+        request: Request<GetOnlineSummaryRequest>,
+    ) -> Result<Response<OnlineSummary>, Status> {
+        let res = self
+            .bancho_state_service
+            .get_online_summary(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
     async fn send_user_stats_packet(
         &self,
         request: Request<SendUserStatsPacketRequest>,
@@ -204,6 +309,18 @@ impl bancho_state_rpc_server::BanchoStateRpc for BanchoStateRpcImpl {
         Ok(Response::new(res))
     }
 
+    async fn update_silence_end(
+        &self,
+        request: Request<UpdateSilenceEndRequest>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let res = self
+            .bancho_state_service
+            .update_silence_end(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
     async fn update_user_bancho_status(
         &self,
         request: Request<UpdateUserBanchoStatusRequest>,
@@ -227,4 +344,144 @@ impl bancho_state_rpc_server::BanchoStateRpc for BanchoStateRpcImpl {
 
         Ok(Response::new(res))
     }
+
+    async fn join_lobby(
+        &self,
+        request: Request<RawUserQuery>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let res = self
+            .bancho_state_service
+            .join_lobby(request.into_inner().into_user_query()?)
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn part_lobby(
+        &self,
+        request: Request<RawUserQuery>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let res = self
+            .bancho_state_service
+            .part_lobby(request.into_inner().into_user_query()?)
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn create_match(
+        &self,
+        request: Request<CreateMatchRequest>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let res = self
+            .bancho_state_service
+            .create_match(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn join_match(
+        &self,
+        request: Request<JoinMatchRequest>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let res =
+            self.bancho_state_service.join_match(request.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn part_match(
+        &self,
+        request: Request<RawUserQuery>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let res = self
+            .bancho_state_service
+            .part_match(request.into_inner().into_user_query()?)
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn start_match(
+        &self,
+        request: Request<RawUserQuery>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let res = self
+            .bancho_state_service
+            .start_match(request.into_inner().into_user_query()?)
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn match_load_complete(
+        &self,
+        request: Request<RawUserQuery>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let res = self
+            .bancho_state_service
+            .match_load_complete(request.into_inner().into_user_query()?)
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn match_player_complete(
+        &self,
+        request: Request<RawUserQuery>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let res = self
+            .bancho_state_service
+            .match_player_complete(request.into_inner().into_user_query()?)
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn relay_score_frame(
+        &self,
+        request: Request<RelayScoreFrameRequest>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let res = self
+            .bancho_state_service
+            .relay_score_frame(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn get_match(
+        &self,
+        request: Request<GetMatchRequest>,
+    ) -> Result<Response<GetMatchResponse>, Status> {
+        let res =
+            self.bancho_state_service.get_match(request.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn start_spectating(
+        &self,
+        request: Request<StartSpectatingRequest>,
+    ) -> Result<Response<StartSpectatingResponse>, Status> {
+        let res = self
+            .bancho_state_service
+            .start_spectating(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn stop_spectating(
+        &self,
+        request: Request<RawUserQuery>,
+    ) -> Result<Response<StopSpectatingResponse>, Status> {
+        let res = self
+            .bancho_state_service
+            .stop_spectating(request.into_inner().into_user_query()?)
+            .await?;
+
+        Ok(Response::new(res))
+    }
 }
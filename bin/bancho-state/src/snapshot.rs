@@ -0,0 +1,124 @@
+use clap::{Args, Parser, Subcommand};
+use core_bancho_state::{BanchoSessionData, BanchoStateServiceSnapshot};
+use peace_snapshot::{
+    decode_snapshot, snapshot_version, SnapshotCompression, SnapshotSchema,
+    SnapshotType,
+};
+use std::{error::Error, fs};
+
+/// Inspect bancho state snapshots without starting the service.
+#[derive(Debug, Parser)]
+#[command(name = "bancho-state-server snapshot", version, about)]
+pub struct SnapshotCli {
+    #[command(subcommand)]
+    pub command: SnapshotCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SnapshotCommand {
+    /// Print the session counts and index sizes of a snapshot file.
+    Inspect(InspectArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct InspectArgs {
+    /// Snapshot file path, the compression is determined by the extension.
+    pub path: String,
+
+    /// Snapshot type, detected from the content if not set.
+    #[arg(long, value_enum)]
+    pub snapshot_type: Option<SnapshotType>,
+
+    /// Also print the sessions.
+    #[arg(long)]
+    pub sessions: bool,
+
+    /// Only print the sessions of this user id.
+    #[arg(long)]
+    pub user_id: Option<i32>,
+
+    /// Only print the sessions whose username contains this.
+    #[arg(long)]
+    pub username: Option<String>,
+}
+
+impl InspectArgs {
+    #[inline]
+    fn dump_sessions(&self) -> bool {
+        self.sessions || self.user_id.is_some() || self.username.is_some()
+    }
+
+    #[inline]
+    fn is_target(&self, session: &BanchoSessionData) -> bool {
+        self.user_id.map_or(true, |id| session.base.user_id == id)
+            && self.username.as_deref().map_or(true, |name| {
+                session.base.username.contains(name)
+                    || session
+                        .base
+                        .username_unicode
+                        .as_deref()
+                        .map_or(false, |n| n.contains(name))
+            })
+    }
+}
+
+/// Entry of the `snapshot` subcommand, `args` starts at `snapshot`.
+pub fn main(
+    args: impl IntoIterator<Item = String>,
+) -> Result<(), Box<dyn Error>> {
+    match SnapshotCli::parse_from(args).command {
+        SnapshotCommand::Inspect(args) => inspect(&args),
+    }
+}
+
+pub fn inspect(args: &InspectArgs) -> Result<(), Box<dyn Error>> {
+    let content = SnapshotCompression::from_path(&args.path)
+        .decompress(fs::read(&args.path)?)?;
+
+    let snapshot_type =
+        args.snapshot_type.unwrap_or_else(|| SnapshotType::detect(&content));
+    let version = snapshot_version(snapshot_type, &content)?;
+
+    let snapshot: BanchoStateServiceSnapshot =
+        decode_snapshot(snapshot_type, &content)?;
+    let summary = snapshot.summary();
+
+    println!("path:             {}", args.path);
+    println!("type:             {snapshot_type:?}");
+    println!(
+        "version:          {version} (current: {})",
+        BanchoStateServiceSnapshot::VERSION
+    );
+    println!("created at:       {}", summary.create_time);
+    println!("sessions:         {}", summary.sessions);
+    println!("queued packets:   {}", summary.queued_packets);
+    println!("notify queue:     {}", summary.notify_queue);
+    println!("indexes:");
+    println!("  session_id:       {}", summary.session_id_index);
+    println!("  user_id:          {}", summary.user_id_index);
+    println!("  username:         {}", summary.username_index);
+    println!("  username_unicode: {}", summary.username_unicode_index);
+    println!("  ip:               {}", summary.ip_index);
+
+    if args.dump_sessions() {
+        println!("sessions:");
+
+        for session in
+            snapshot.user_sessions.iter().filter(|s| args.is_target(s))
+        {
+            println!(
+                "  {} user {} \"{}\" client {} ip {} created at {} \
+                queued packets {}",
+                session.base.id,
+                session.base.user_id,
+                session.base.username,
+                session.extends.client_version,
+                session.extends.connection_info.ip,
+                session.base.created_at,
+                session.extends.packets_queue.len(),
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -3,30 +3,41 @@ use clap_serde_derive::ClapSerde;
 use core_bancho::*;
 use core_bancho_state::{
     BanchoStateRpcConfig, BanchoStateServiceRemote, DynBanchoStateService,
+    GetSessionsStats,
 };
 use core_chat::{ChatRpcConfig, ChatServiceRemote, DynChatService};
 use core_geoip::{
     DynGeoipService, GeoipRpcConfig, GeoipServiceBuilder, GeoipServiceImpl,
     GeoipServiceRemote,
 };
-use infra_services::{FromRpcClient, IntoService};
+use infra_services::{FromRpcClient, IntoService, ReconnectingClient};
 use pb_bancho::{bancho_rpc_server::BanchoRpcServer, BANCHO_DESCRIPTOR_SET};
 use pb_bancho_state::bancho_state_rpc_client::BanchoStateRpcClient;
 use pb_chat::chat_rpc_client::ChatRpcClient;
+use peace_cfg::{
+    check_rpc_peer, validate_rpc_client_config, ConfigError, ConfigErrors,
+    Secret, ValidateConfig,
+};
 use peace_db::{
     peace::{Peace, PeaceDbConfig},
     DbConfig, DbConnection,
 };
-use peace_repositories::users::{DynUsersRepository, UsersRepositoryImpl};
+use peace_difficulty::{
+    BeatmapFiles, CliBeatmapFilesConfigs, DifficultyServiceImpl,
+    DynDifficultyService,
+};
+use peace_repositories::{
+    scores::{DynScoresRepository, ScoresRepositoryImpl},
+    users::{DynUsersRepository, UsersRepositoryImpl},
+};
 use peace_rpc::{
     interceptor::client_ip, RpcApplication, RpcClientConfig, RpcFrameConfig,
+    RpcRouter, RpcServer,
 };
 use peace_runtime::cfg::RuntimeConfig;
-use std::{net::SocketAddr, sync::Arc};
-use tonic::{
-    async_trait,
-    transport::{server::Router, Channel, Server},
-};
+use std::{net::SocketAddr, path::Path, sync::Arc};
+use tonic::async_trait;
+use tools::{health::HealthStatus, tonic_utils::TracedChannel};
 
 /// PEACE Bancho gRPC service
 #[peace_config]
@@ -53,21 +64,74 @@ pub struct BanchoConfig {
     #[command(flatten)]
     pub bancho_background_service_configs: CliBanchoBackgroundServiceConfigs,
 
+    #[command(flatten)]
+    pub bancho_service_configs: CliBanchoServiceConfigs,
+
+    #[command(flatten)]
+    pub beatmap_files: CliBeatmapFilesConfigs,
+
     #[arg(long, short = 'P')]
     pub geo_db_path: Option<String>,
 }
 
+#[async_trait]
+impl ValidateConfig for BanchoConfig {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        errors.merge(self.frame_cfg.validate());
+        errors.merge(self.bancho_service_configs.validate());
+        errors.merge(validate_rpc_client_config(
+            &self.bancho_state,
+            "bancho_state",
+        ));
+        errors.merge(validate_rpc_client_config(&self.chat, "chat"));
+
+        // The geoip service falls back to the remote one without a database
+        match self.geo_db_path.as_deref() {
+            Some(path) => {
+                errors.check_file("geo_db_path", Some(Path::new(path)))
+            },
+            None => {
+                errors.merge(validate_rpc_client_config(&self.geoip, "geoip"))
+            },
+        }
+
+        errors.into_result()
+    }
+
+    async fn check_peers(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        if let Err(err) = self.peace_db.connect().await {
+            errors.push("peace_db", format!("failed to connect: {err}"));
+        }
+
+        errors.merge(check_rpc_peer(&self.bancho_state, "bancho_state").await);
+        errors.merge(check_rpc_peer(&self.chat, "chat").await);
+
+        if self.geo_db_path.is_none() {
+            errors.merge(check_rpc_peer(&self.geoip, "geoip").await);
+        }
+
+        errors.into_result()
+    }
+}
+
 #[derive(Clone)]
 pub struct App {
     pub cfg: Arc<BanchoConfig>,
     pub peace_db_conn: DbConnection<Peace>,
-    pub bancho_state_rpc_client: BanchoStateRpcClient<Channel>,
-    pub chat_rpc_client: ChatRpcClient<Channel>,
+    pub bancho_state_rpc_client: BanchoStateRpcClient<TracedChannel>,
+    pub chat_rpc_client: ChatRpcClient<TracedChannel>,
     pub geoip_service: DynGeoipService,
     pub users_repository: DynUsersRepository,
+    pub scores_repository: DynScoresRepository,
+    pub difficulty_service: DynDifficultyService,
     pub bancho_state_service: DynBanchoStateService,
     pub chat_service: DynChatService,
     pub password_service: DynPasswordService,
+    pub leaderboard_service: DynLeaderboardService,
     pub bancho_background_service: DynBanchoBackgroundService,
     pub bancho_background_service_config: BanchoBackgroundServiceConfigs,
     pub bancho_service: DynBanchoService,
@@ -96,8 +160,24 @@ impl App {
         let users_repository =
             UsersRepositoryImpl::new(peace_db_conn.clone()).into_service();
 
-        let bancho_state_service = BanchoStateServiceRemote::from_client(
-            bancho_state_rpc_client.clone(),
+        let scores_repository =
+            ScoresRepositoryImpl::new(peace_db_conn.clone()).into_service();
+
+        let difficulty_service = DifficultyServiceImpl::new(
+            BeatmapFiles::with_cfg(&cfg.beatmap_files).with_lookup(Arc::new(
+                ScoresRepositoryImpl::new(peace_db_conn.clone()),
+            )),
+        )
+        .into_service();
+
+        let bancho_state_service = BanchoStateServiceRemote::new(
+            ReconnectingClient::new(bancho_state_rpc_client.clone(), {
+                let cfg = cfg.clone();
+                move || {
+                    let cfg = cfg.clone();
+                    async move { cfg.bancho_state.try_connect().await }
+                }
+            }),
         )
         .into_service();
 
@@ -109,6 +189,12 @@ impl App {
         let password_cache_store = password_service.cache_store().clone();
         let password_service = password_service.into_service();
 
+        let leaderboard_service = LeaderboardServiceImpl::with_cfg(
+            scores_repository.clone(),
+            &cfg.bancho_service_configs,
+        )
+        .into_service();
+
         let bancho_background_service =
             BanchoBackgroundServiceImpl::new(password_cache_store)
                 .into_service();
@@ -124,11 +210,23 @@ impl App {
 
         let bancho_service = BanchoServiceImpl::new(
             users_repository.clone(),
+            scores_repository.clone(),
             bancho_state_service.clone(),
             password_service.clone(),
             bancho_background_service.clone(),
             geoip_service.clone(),
             chat_service.clone(),
+            difficulty_service.clone(),
+            leaderboard_service.clone(),
+            Arc::new(cfg.bancho_service_configs.email_validator()),
+            Arc::new(cfg.bancho_service_configs.message_limiter()),
+            cfg.bancho_service_configs.session_packet_history_size,
+            cfg.bancho_service_configs.max_favourite_beatmapsets,
+            cfg.bancho_service_configs
+                .client_hashes_salt
+                .clone()
+                .map(Secret::into_inner),
+            cfg.bancho_service_configs.geoip_default_country.clone(),
         )
         .into_service();
 
@@ -141,9 +239,12 @@ impl App {
             chat_rpc_client,
             geoip_service,
             users_repository,
+            scores_repository,
+            difficulty_service,
             bancho_state_service,
             chat_service,
             password_service,
+            leaderboard_service,
             bancho_background_service,
             bancho_background_service_config,
             bancho_service,
@@ -166,10 +267,49 @@ impl RpcApplication for App {
         Some(&[BANCHO_DESCRIPTOR_SET])
     }
 
-    async fn service(&self, mut configured_server: Server) -> Router {
+    async fn service(&self, mut configured_server: RpcServer) -> RpcRouter {
         configured_server.add_service(BanchoRpcServer::with_interceptor(
             self.bancho_rpc.clone(),
             client_ip,
         ))
     }
+
+    /// Bancho can not login or handle packets without bancho state.
+    async fn health_check(&self) -> HealthStatus {
+        match self.bancho_state_service.get_sessions_stats().await {
+            Ok(_) => HealthStatus::Ok,
+            Err(err) => HealthStatus::Unavailable(format!(
+                "bancho state service unavailable: {err}"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_validate_bancho_config() {
+        let cfg = BanchoConfig::parse_from([
+            "bancho",
+            "--bancho-state-uri",
+            "127.0.0.1",
+            "--leaderboard-size",
+            "0",
+            "--geoip-default-country",
+            "ZZZ",
+        ]);
+
+        let errors = cfg.validate().unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.field.as_str()).collect::<Vec<_>>(),
+            vec![
+                "leaderboard_size",
+                "geoip_default_country",
+                "bancho_state_uri"
+            ]
+        );
+    }
 }
@@ -29,6 +29,10 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     tools::main_startup_info!();
 
     let cfg = BanchoConfig::get();
+
+    // Validate the configuration, or only check it with `--check-config`.
+    peace_runtime::check_config_or_exit(&cfg.runtime_cfg, &*cfg);
+
     // Initialize the logger.
     peace_logs::init(&cfg.frame_cfg);
 
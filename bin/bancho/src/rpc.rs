@@ -3,6 +3,7 @@ use core_bancho::DynBanchoService;
 use pb_bancho::*;
 use pb_bancho_state::RawUserQuery;
 use peace_rpc::extensions::ClientIp;
+use std::net::IpAddr;
 use tonic::{Request, Response, Status};
 
 #[derive(Clone)]
@@ -22,9 +23,14 @@ impl bancho_rpc_server::BanchoRpc for BanchoRpcImpl {
         &self,
         request: Request<BatchProcessBanchoPacketsRequest>,
     ) -> Result<Response<HandleCompleted>, Status> {
+        let client_ip = ClientIp::from_request(&request).ok();
+
         let res = self
             .bancho_service
-            .batch_process_bancho_packets(request.into_inner())
+            .batch_process_bancho_packets(
+                client_ip.map(IpAddr::from),
+                request.into_inner(),
+            )
             .await?;
 
         Ok(Response::new(res))
@@ -71,6 +77,114 @@ impl bancho_rpc_server::BanchoRpc for BanchoRpcImpl {
         Ok(Response::new(res))
     }
 
+    async fn client_register(
+        &self,
+        request: Request<ClientRegisterRequest>,
+    ) -> Result<Response<ClientRegisterResponse>, Status> {
+        let res =
+            self.bancho_service.client_register(request.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn add_favourite_beatmapset(
+        &self,
+        request: Request<AddFavouriteBeatmapsetRequest>,
+    ) -> Result<Response<AddFavouriteBeatmapsetResponse>, Status> {
+        let res = self
+            .bancho_service
+            .add_favourite_beatmapset(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn get_favourite_beatmapsets(
+        &self,
+        request: Request<GetFavouriteBeatmapsetsRequest>,
+    ) -> Result<Response<GetFavouriteBeatmapsetsResponse>, Status> {
+        let res = self
+            .bancho_service
+            .get_favourite_beatmapsets(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn get_users_sharing_hardware(
+        &self,
+        request: Request<GetUsersSharingHardwareRequest>,
+    ) -> Result<Response<GetUsersSharingHardwareResponse>, Status> {
+        let res = self
+            .bancho_service
+            .get_users_sharing_hardware(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn silence_user(
+        &self,
+        request: Request<SilenceUserRequest>,
+    ) -> Result<Response<SilenceUserResponse>, Status> {
+        let res =
+            self.bancho_service.silence_user(request.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn submit_score(
+        &self,
+        request: Request<SubmitScoreRequest>,
+    ) -> Result<Response<SubmitScoreResponse>, Status> {
+        let res =
+            self.bancho_service.submit_score(request.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn get_beatmap_scores(
+        &self,
+        request: Request<GetBeatmapScoresRequest>,
+    ) -> Result<Response<GetBeatmapScoresResponse>, Status> {
+        let res = self
+            .bancho_service
+            .get_beatmap_scores(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn authenticate_user(
+        &self,
+        request: Request<AuthenticateUserRequest>,
+    ) -> Result<Response<AuthenticateUserResponse>, Status> {
+        let res =
+            self.bancho_service.authenticate_user(request.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn get_score_md5(
+        &self,
+        request: Request<GetScoreMd5Request>,
+    ) -> Result<Response<GetScoreMd5Response>, Status> {
+        let res =
+            self.bancho_service.get_score_md5(request.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn add_replay_view(
+        &self,
+        request: Request<AddReplayViewRequest>,
+    ) -> Result<Response<AddReplayViewResponse>, Status> {
+        let res =
+            self.bancho_service.add_replay_view(request.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
     async fn request_status_update(
         &self,
         raw_user_query: Request<RawUserQuery>,
@@ -163,6 +277,16 @@ impl bancho_rpc_server::BanchoRpc for BanchoRpcImpl {
         Ok(Response::new(res))
     }
 
+    async fn spectate_start(
+        &self,
+        request: Request<SpectateStartRequest>,
+    ) -> Result<Response<HandleCompleted>, Status> {
+        let res =
+            self.bancho_service.spectate_start(request.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
     async fn spectate_stop(
         &self,
         raw_user_query: Request<RawUserQuery>,
@@ -210,4 +334,137 @@ impl bancho_rpc_server::BanchoRpc for BanchoRpcImpl {
 
         Ok(Response::new(res))
     }
+
+    async fn match_create(
+        &self,
+        request: Request<MatchCreateRequest>,
+    ) -> Result<Response<HandleCompleted>, Status> {
+        let res =
+            self.bancho_service.match_create(request.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn match_join(
+        &self,
+        request: Request<MatchJoinRequest>,
+    ) -> Result<Response<HandleCompleted>, Status> {
+        let res = self.bancho_service.match_join(request.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn match_part(
+        &self,
+        raw_user_query: Request<RawUserQuery>,
+    ) -> Result<Response<HandleCompleted>, Status> {
+        let res = self
+            .bancho_service
+            .match_part(raw_user_query.into_inner().into_user_query()?)
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn match_start(
+        &self,
+        raw_user_query: Request<RawUserQuery>,
+    ) -> Result<Response<HandleCompleted>, Status> {
+        let res = self
+            .bancho_service
+            .match_start(raw_user_query.into_inner().into_user_query()?)
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn match_load_complete(
+        &self,
+        raw_user_query: Request<RawUserQuery>,
+    ) -> Result<Response<HandleCompleted>, Status> {
+        let res = self
+            .bancho_service
+            .match_load_complete(raw_user_query.into_inner().into_user_query()?)
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn match_complete(
+        &self,
+        raw_user_query: Request<RawUserQuery>,
+    ) -> Result<Response<HandleCompleted>, Status> {
+        let res = self
+            .bancho_service
+            .match_complete(raw_user_query.into_inner().into_user_query()?)
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn match_score_update(
+        &self,
+        request: Request<MatchScoreUpdateRequest>,
+    ) -> Result<Response<HandleCompleted>, Status> {
+        let res = self
+            .bancho_service
+            .match_score_update(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn tournament_match_info(
+        &self,
+        request: Request<TournamentMatchRequest>,
+    ) -> Result<Response<HandleCompleted>, Status> {
+        let res = self
+            .bancho_service
+            .tournament_match_info(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn tournament_join_match_channel(
+        &self,
+        request: Request<TournamentMatchRequest>,
+    ) -> Result<Response<HandleCompleted>, Status> {
+        let res = self
+            .bancho_service
+            .tournament_join_match_channel(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn tournament_leave_match_channel(
+        &self,
+        request: Request<TournamentMatchRequest>,
+    ) -> Result<Response<HandleCompleted>, Status> {
+        let res = self
+            .bancho_service
+            .tournament_leave_match_channel(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn get_packet_metrics(
+        &self,
+        _: Request<GetPacketMetricsRequest>,
+    ) -> Result<Response<PacketMetricsResponse>, Status> {
+        let res = self.bancho_service.get_packet_metrics().await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn get_leaderboard_cache_metrics(
+        &self,
+        _: Request<GetLeaderboardCacheMetricsRequest>,
+    ) -> Result<Response<LeaderboardCacheMetricsResponse>, Status> {
+        let res = self.bancho_service.get_leaderboard_cache_metrics().await?;
+
+        Ok(Response::new(res))
+    }
 }
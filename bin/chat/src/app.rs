@@ -2,18 +2,19 @@ use crate::ChatRpcImpl;
 use clap_serde_derive::ClapSerde;
 use core_chat::*;
 use pb_chat::{chat_rpc_server::ChatRpcServer, CHAT_DESCRIPTOR_SET};
+use peace_cfg::{ConfigError, ValidateConfig};
 use peace_db::{
     peace::{Peace, PeaceDbConfig},
     DbConfig, DbConnection,
 };
-use peace_repositories::users::{DynUsersRepository, UsersRepositoryImpl};
-use peace_rpc::{RpcApplication, RpcFrameConfig};
+use peace_repositories::{
+    channels::{ChannelsRepositoryImpl, DynChannelsRepository},
+    users::{DynUsersRepository, UsersRepositoryImpl},
+};
+use peace_rpc::{RpcApplication, RpcFrameConfig, RpcRouter, RpcServer};
 use peace_runtime::cfg::RuntimeConfig;
 use std::{net::SocketAddr, sync::Arc};
-use tonic::{
-    async_trait,
-    transport::{server::Router, Server},
-};
+use tonic::async_trait;
 
 /// PEACE Chat gRPC service
 #[peace_config]
@@ -28,6 +29,9 @@ pub struct ChatServiceConfig {
     #[command(flatten)]
     pub peace_db: PeaceDbConfig,
 
+    #[command(flatten)]
+    pub chat_service_configs: CliChatServiceConfigs,
+
     #[command(flatten)]
     pub chat_background_service_configs: CliChatBackgroundServiceConfigs,
 
@@ -35,11 +39,28 @@ pub struct ChatServiceConfig {
     pub chat_snapshot: CliChatServiceSnapshotConfigs,
 }
 
+#[async_trait]
+impl ValidateConfig for ChatServiceConfig {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        self.frame_cfg.validate()
+    }
+
+    async fn check_peers(&self) -> Result<(), Vec<ConfigError>> {
+        self.peace_db.connect().await.map(|_| ()).map_err(|err| {
+            vec![ConfigError::new(
+                "peace_db",
+                format!("failed to connect: {err}"),
+            )]
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct App {
     pub cfg: Arc<ChatServiceConfig>,
     pub peace_db_conn: DbConnection<Peace>,
     pub users_repository: DynUsersRepository,
+    pub channels_repository: DynChannelsRepository,
     pub chat_service: DynChatService,
     pub chat_background_service: DynChatBackgroundService,
     pub chat_background_service_config: ChatBackgroundServiceConfigs,
@@ -57,9 +78,14 @@ impl App {
         let users_repository =
             UsersRepositoryImpl::new(peace_db_conn.clone()).into_service();
 
+        let channels_repository =
+            ChannelsRepositoryImpl::new(peace_db_conn.clone()).into_service();
+
         let chat_service = ChatServiceSnapshotLoader::load(
             &cfg.chat_snapshot,
+            &cfg.chat_service_configs,
             users_repository.clone(),
+            channels_repository.clone(),
         )
         .await
         .into_service();
@@ -86,6 +112,7 @@ impl App {
             cfg,
             peace_db_conn,
             users_repository,
+            channels_repository,
             chat_service,
             chat_background_service,
             chat_background_service_config,
@@ -108,7 +135,7 @@ impl RpcApplication for App {
         Some(&[CHAT_DESCRIPTOR_SET])
     }
 
-    async fn service(&self, mut configured_server: Server) -> Router {
+    async fn service(&self, mut configured_server: RpcServer) -> RpcRouter {
         configured_server.add_service(ChatRpcServer::new(self.chat_rpc.clone()))
     }
 }
@@ -27,7 +27,7 @@ pub async fn run(
             .chat_service
             .save_service_snapshot(
                 cfg.chat_snapshot.snapshot_type(),
-                cfg.chat_snapshot.snapshot_path(),
+                &cfg.chat_snapshot.snapshot_file_path(),
             )
             .await;
     }
@@ -40,6 +40,10 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     tools::main_startup_info!();
 
     let cfg = ChatServiceConfig::get();
+
+    // Validate the configuration, or only check it with `--check-config`.
+    peace_runtime::check_config_or_exit(&cfg.runtime_cfg, &*cfg);
+
     // Initialize the logger.
     peace_logs::init(&cfg.frame_cfg);
 
@@ -2,6 +2,7 @@ use core_chat::{ChatError, DynChatService};
 use pb_bancho_state::{BanchoPackets, RawUserQuery};
 use pb_base::ExecSuccess;
 use pb_chat::*;
+use std::time::Duration;
 use tonic::{Request, Response, Status};
 
 #[derive(Clone)]
@@ -40,6 +41,40 @@ impl chat_rpc_server::ChatRpc for ChatRpcImpl {
         Ok(Response::new(res))
     }
 
+    async fn join_platforms(
+        &self,
+        request: Request<JoinPlatformsRequest>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let JoinPlatformsRequest { user_query, platforms } =
+            request.into_inner();
+        let user_query =
+            user_query.ok_or(ChatError::InvalidArgument)?.into_user_query()?;
+
+        let res = self
+            .chat_service
+            .join_platforms(user_query, platforms.into())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn update_silence_end(
+        &self,
+        request: Request<UpdateSilenceEndRequest>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let UpdateSilenceEndRequest { user_query, silence_end } =
+            request.into_inner();
+        let user_query =
+            user_query.ok_or(ChatError::InvalidArgument)?.into_user_query()?;
+
+        let res = self
+            .chat_service
+            .update_silence_end(user_query, silence_end)
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
     async fn get_public_channels(
         &self,
         _: Request<GetPublicChannelsRequest>,
@@ -58,6 +93,73 @@ impl chat_rpc_server::ChatRpc for ChatRpcImpl {
         Ok(Response::new(res))
     }
 
+    async fn create_channel(
+        &self,
+        request: Request<CreateChannelRequest>,
+    ) -> Result<Response<ChannelInfo>, Status> {
+        let res =
+            self.chat_service.create_channel(request.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn create_public_channel(
+        &self,
+        request: Request<CreatePublicChannelRequest>,
+    ) -> Result<Response<ChannelInfo>, Status> {
+        let res = self
+            .chat_service
+            .create_public_channel(request.into_inner())
+            .await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn delete_channel(
+        &self,
+        request: Request<DeleteChannelRequest>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let channel_query = request
+            .into_inner()
+            .channel_query
+            .ok_or(ChatError::InvalidArgument)?
+            .into_channel_query()?;
+
+        let res = self.chat_service.delete_channel(channel_query).await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn get_channel_members(
+        &self,
+        request: Request<GetChannelMembersRequest>,
+    ) -> Result<Response<Users>, Status> {
+        let GetChannelMembersRequest { channel_query, platforms } =
+            request.into_inner();
+        let channel_query = channel_query
+            .ok_or(ChatError::InvalidArgument)?
+            .into_channel_query()?;
+
+        let users = self
+            .chat_service
+            .get_channel_members(channel_query, platforms.into())
+            .await?;
+
+        Ok(Response::new(Users { users }))
+    }
+
+    async fn get_user_channels(
+        &self,
+        request: Request<GetUserChannelsRequest>,
+    ) -> Result<Response<GetUserChannelsResponse>, Status> {
+        let channels = self
+            .chat_service
+            .get_user_channels(request.into_inner().user_id)
+            .await?;
+
+        Ok(Response::new(GetUserChannelsResponse { channels }))
+    }
+
     async fn join_channel(
         &self,
         request: Request<JoinChannelRequest>,
@@ -85,6 +187,26 @@ impl chat_rpc_server::ChatRpc for ChatRpcImpl {
         Ok(Response::new(res))
     }
 
+    async fn delete_message(
+        &self,
+        request: Request<ModerateMessageRequest>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let res =
+            self.chat_service.delete_message(request.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn redact_message(
+        &self,
+        request: Request<ModerateMessageRequest>,
+    ) -> Result<Response<ExecSuccess>, Status> {
+        let res =
+            self.chat_service.redact_message(request.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
     async fn pull_chat_packets(
         &self,
         request: Request<RawUserQuery>,
@@ -96,4 +218,24 @@ impl chat_rpc_server::ChatRpc for ChatRpcImpl {
 
         Ok(Response::new(res))
     }
+
+    async fn wait_chat_packets(
+        &self,
+        request: Request<WaitChatPacketsRequest>,
+    ) -> Result<Response<BanchoPackets>, Status> {
+        let WaitChatPacketsRequest { user_query, wait_ms } =
+            request.into_inner();
+        let user_query =
+            user_query.ok_or(ChatError::InvalidArgument)?.into_user_query()?;
+
+        let res = self
+            .chat_service
+            .wait_chat_packets(
+                user_query,
+                Duration::from_millis(wait_ms.into()),
+            )
+            .await?;
+
+        Ok(Response::new(res))
+    }
 }
@@ -1,11 +1,23 @@
+mod recompute_pp;
+
 use clap::{Parser, Subcommand};
 use clap4 as clap;
+use domain_bancho::GameMode;
 use domain_users::{
     CreateUser, Email, Password, UsernameAscii, UsernameUnicode,
 };
 use dotenvy::dotenv;
-use peace_db::{Database, DbConnection};
-use peace_repositories::users::{UsersRepository, UsersRepositoryImpl};
+use peace_db::{
+    peace::entity::sea_orm_active_enums::PpVersion, ActiveEnum, Database,
+    DbConnection,
+};
+use peace_difficulty::BeatmapFiles;
+use peace_repositories::{
+    scores::ScoresRepositoryImpl,
+    users::{UsersRepository, UsersRepositoryImpl},
+};
+use recompute_pp::RecomputePp;
+use std::{sync::Arc, time::Duration};
 
 #[derive(Debug, Parser)]
 #[clap(version, author, about = "Peace db CLI")]
@@ -53,6 +65,38 @@ pub enum Commands {
         #[arg(long)]
         md5_password: Option<String>,
     },
+    #[clap(
+        about = "[peace] Recompute the pp of scores with the current calculator"
+    )]
+    RecomputePp {
+        /// Mode of the scores, e.g. `standard`, `taiko_relax`
+        #[arg(long)]
+        mode: String,
+
+        /// Scores having pp of this version are recomputed, e.g. `v1`
+        #[arg(long)]
+        from_version: String,
+
+        #[arg(long, default_value = "500")]
+        batch_size: u64,
+
+        /// Pause between batches
+        #[arg(long, default_value = "100")]
+        batch_interval_ms: u64,
+
+        /// Default to `.data/recompute-pp-{mode}-{from_version}.checkpoint`
+        #[arg(long)]
+        checkpoint_file: Option<String>,
+
+        /// Directory of the `.osu` files
+        #[arg(long, default_value = ".data/beatmaps")]
+        beatmaps_dir: String,
+
+        /// Download url of missing `.osu` files, `{id}` is replaced with
+        /// the beatmap id
+        #[arg(long)]
+        beatmap_download_url: Option<String>,
+    },
 }
 
 #[tokio::main]
@@ -154,5 +198,57 @@ async fn main() {
             .unwrap();
             println!("Success")
         },
+        Commands::RecomputePp {
+            mode,
+            from_version,
+            batch_size,
+            batch_interval_ms,
+            checkpoint_file,
+            beatmaps_dir,
+            beatmap_download_url,
+        } => {
+            let mode = GameMode::from_table(&mode)
+                .unwrap_or_else(|| panic!("invalid mode: {mode}"));
+            let from_version = PpVersion::try_from_value(&from_version)
+                .unwrap_or_else(|_| {
+                    panic!("invalid pp version: {from_version}")
+                });
+
+            let db = DbConnection::from(
+                Database::connect(
+                    cli.database_url.expect("database-url is required."),
+                )
+                .await
+                .unwrap(),
+            );
+
+            let job = RecomputePp {
+                scores_repository: ScoresRepositoryImpl::new(db.clone())
+                    .into_service(),
+                beatmap_files: BeatmapFiles::new(
+                    beatmaps_dir,
+                    beatmap_download_url,
+                )
+                .with_lookup(Arc::new(ScoresRepositoryImpl::new(db))),
+                mode,
+                checkpoint_file: checkpoint_file
+                    .map(Into::into)
+                    .unwrap_or_else(|| {
+                        RecomputePp::default_checkpoint_file(
+                            mode,
+                            &from_version,
+                        )
+                    }),
+                from_version,
+                batch_size,
+                batch_interval: Duration::from_millis(batch_interval_ms),
+            };
+
+            let progress = job.run().await.unwrap();
+            println!(
+                "Success, recomputed: {}, skipped: {}, failed: {}",
+                progress.recomputed, progress.skipped, progress.failed
+            )
+        },
     }
 }
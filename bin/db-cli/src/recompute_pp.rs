@@ -0,0 +1,230 @@
+use domain_bancho::GameMode;
+use peace_db::{
+    peace::entity::sea_orm_active_enums::PpVersion, prelude::Decimal,
+    ActiveEnum, DbErr,
+};
+use peace_difficulty::{
+    calculate_pp, parse_beatmap, Beatmap, BeatmapFiles, DifficultyError,
+    ScoreHits,
+};
+use peace_repositories::scores::{
+    DynScoresRepository, PpScore, CURRENT_PP_VERSION,
+};
+use std::{
+    collections::HashMap,
+    io,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::fs;
+
+#[derive(thiserror::Error, Debug)]
+pub enum RecomputePpError {
+    #[error(transparent)]
+    DbErr(#[from] DbErr),
+    #[error(transparent)]
+    DifficultyError(#[from] DifficultyError),
+    #[error("failed to access checkpoint file: {0}")]
+    CheckpointError(#[from] io::Error),
+    #[error("invalid checkpoint file: {0}")]
+    InvalidCheckpoint(String),
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct RecomputePpProgress {
+    pub last_score_id: i64,
+    pub recomputed: u64,
+    /// Scores whose beatmap is unknown.
+    pub skipped: u64,
+    pub failed: u64,
+}
+
+/// Recalculate the pp of the scores of a mode having a pp row of
+/// `from_version`, the results are written as [`CURRENT_PP_VERSION`] rows,
+/// the rows of the other versions are kept for comparison.
+///
+/// Scores are processed by batches ordered by score id, the last processed
+/// id is saved into `checkpoint_file` after each batch so an interrupted
+/// job resumes from there. The checkpoint is removed once the job is done.
+pub struct RecomputePp {
+    pub scores_repository: DynScoresRepository,
+    pub beatmap_files: BeatmapFiles,
+    pub mode: GameMode,
+    pub from_version: PpVersion,
+    pub batch_size: u64,
+    /// Pause between batches, so the job does not saturate the database.
+    pub batch_interval: Duration,
+    pub checkpoint_file: PathBuf,
+}
+
+impl RecomputePp {
+    /// Default checkpoint file of a job, e.g.
+    /// `.data/recompute-pp-standard-v1.checkpoint`.
+    #[inline]
+    pub fn default_checkpoint_file(
+        mode: GameMode,
+        from_version: &PpVersion,
+    ) -> PathBuf {
+        PathBuf::from(format!(
+            ".data/recompute-pp-{}-{}.checkpoint",
+            mode.as_table(),
+            from_version.to_value()
+        ))
+    }
+
+    pub async fn run(&self) -> Result<RecomputePpProgress, RecomputePpError> {
+        let mut progress = RecomputePpProgress {
+            last_score_id: self.read_checkpoint().await?,
+            ..Default::default()
+        };
+        let started_at = Instant::now();
+
+        println!(
+            "Recomputing {} pp from {} to {}, starting after score {}...",
+            self.mode.as_table(),
+            self.from_version.to_value(),
+            CURRENT_PP_VERSION.to_value(),
+            progress.last_score_id
+        );
+
+        loop {
+            let scores = self
+                .scores_repository
+                .get_scores_with_pp_version(
+                    self.mode,
+                    self.from_version.clone(),
+                    progress.last_score_id,
+                    self.batch_size,
+                )
+                .await?;
+
+            let last_score_id = match scores.last() {
+                Some(score) => score.score_id,
+                None => break,
+            };
+
+            let mut beatmaps = HashMap::new();
+            for score in scores {
+                match self.recompute(&score, &mut beatmaps).await {
+                    Ok(true) => progress.recomputed += 1,
+                    Ok(false) => progress.skipped += 1,
+                    Err(err) => {
+                        progress.failed += 1;
+                        eprintln!(
+                            "Failed to recompute pp of score {}: {err}",
+                            score.score_id
+                        );
+                    },
+                }
+            }
+
+            progress.last_score_id = last_score_id;
+            self.write_checkpoint(last_score_id).await?;
+
+            let processed =
+                progress.recomputed + progress.skipped + progress.failed;
+            println!(
+                "[{:.0}s] recomputed: {}, skipped: {}, failed: {}, \
+                last score id: {} ({:.1} scores/s)",
+                started_at.elapsed().as_secs_f64(),
+                progress.recomputed,
+                progress.skipped,
+                progress.failed,
+                progress.last_score_id,
+                processed as f64 / started_at.elapsed().as_secs_f64()
+            );
+
+            tokio::time::sleep(self.batch_interval).await;
+        }
+
+        match fs::remove_file(&self.checkpoint_file).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                return Err(err.into())
+            },
+            _ => {},
+        }
+
+        Ok(progress)
+    }
+
+    /// Returns `false` if the beatmap of the score is unknown.
+    async fn recompute(
+        &self,
+        score: &PpScore,
+        beatmaps: &mut HashMap<i32, Arc<Beatmap>>,
+    ) -> Result<bool, RecomputePpError> {
+        let beatmap_id = match score.beatmap_id {
+            Some(beatmap_id) => beatmap_id,
+            None => return Ok(false),
+        };
+
+        let beatmap = match beatmaps.get(&beatmap_id) {
+            Some(beatmap) => beatmap.clone(),
+            None => {
+                let beatmap = match self.beatmap_files.load(beatmap_id).await {
+                    Ok(data) => Arc::new(parse_beatmap(&data)?),
+                    Err(DifficultyError::BeatmapNotFound(_)) => {
+                        return Ok(false)
+                    },
+                    Err(err) => return Err(err.into()),
+                };
+                beatmaps.insert(beatmap_id, beatmap.clone());
+                beatmap
+            },
+        };
+
+        let hits = |n: i32| n.max(0) as usize;
+        let result = calculate_pp(
+            &beatmap,
+            self.mode.vanilla_mode(),
+            score.mods as u32,
+            ScoreHits {
+                combo: hits(score.combo),
+                n300: hits(score.n300),
+                n100: hits(score.n100),
+                n50: hits(score.n50),
+                n_misses: hits(score.miss),
+                n_geki: hits(score.geki),
+                n_katu: hits(score.katu),
+            },
+        )?;
+
+        self.scores_repository
+            .upsert_score_pp(
+                self.mode,
+                score.score_id,
+                CURRENT_PP_VERSION,
+                Decimal::from_f64_retain(result.pp)
+                    .unwrap_or_default()
+                    .round_dp(2),
+                Some(serde_json::json!({
+                    "pp": result.pp,
+                    "stars": result.stars,
+                })),
+            )
+            .await?;
+
+        Ok(true)
+    }
+
+    async fn read_checkpoint(&self) -> Result<i64, RecomputePpError> {
+        match fs::read_to_string(&self.checkpoint_file).await {
+            Ok(s) => s.trim().parse().map_err(|_| {
+                RecomputePpError::InvalidCheckpoint(
+                    self.checkpoint_file.display().to_string(),
+                )
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn write_checkpoint(&self, score_id: i64) -> io::Result<()> {
+        if let Some(dir) = self.checkpoint_file.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+
+        fs::write(&self.checkpoint_file, score_id.to_string()).await
+    }
+}
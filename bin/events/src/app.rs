@@ -3,13 +3,11 @@ use clap_serde_derive::ClapSerde;
 use core_events::*;
 use infra_services::IntoService;
 use pb_events::{events_rpc_server::EventsRpcServer, EVENTS_DESCRIPTOR_SET};
-use peace_rpc::{RpcApplication, RpcFrameConfig};
+use peace_cfg::{ConfigError, ValidateConfig};
+use peace_rpc::{RpcApplication, RpcFrameConfig, RpcRouter, RpcServer};
 use peace_runtime::cfg::RuntimeConfig;
 use std::{net::SocketAddr, sync::Arc};
-use tonic::{
-    async_trait,
-    transport::{server::Router, Server},
-};
+use tonic::async_trait;
 
 /// PEACE Events service
 #[peace_config]
@@ -22,6 +20,12 @@ pub struct EventsConfig {
     pub frame_cfg: RpcFrameConfig,
 }
 
+impl ValidateConfig for EventsConfig {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        self.frame_cfg.validate()
+    }
+}
+
 #[derive(Clone)]
 pub struct App {
     pub cfg: Arc<EventsConfig>,
@@ -53,7 +57,7 @@ impl RpcApplication for App {
         Some(&[EVENTS_DESCRIPTOR_SET])
     }
 
-    async fn service(&self, mut configured_server: Server) -> Router {
+    async fn service(&self, mut configured_server: RpcServer) -> RpcRouter {
         configured_server
             .add_service(EventsRpcServer::new(self.events_rpc.clone()))
     }
@@ -7,20 +7,31 @@ use core_bancho_state::{
 use core_chat::{ChatRpcConfig, ChatServiceRemote};
 use core_gateway::{
     bancho_endpoints::{
-        routes::{BanchoDebugRouter, BanchoRouter},
-        BanchoHandlerServiceImpl, BanchoRoutingServiceImpl,
-        DynBanchoHandlerService, DynBanchoRoutingService,
+        login_limiter,
+        routes::{BanchoAdminRouter, BanchoDebugRouter, BanchoRouter},
+        BanchoAdminEndpointsDocs, BanchoHandlerServiceImpl,
+        BanchoRoutingServiceImpl, CliBanchoClientVersionConfigs,
+        CliBanchoLoginLimiterConfigs, CliBanchoRoutingServiceConfigs,
+        ClientVersionPolicy, DynBanchoHandlerService, DynBanchoRoutingService,
     },
     docs::GatewayApiDocs,
 };
-use infra_services::{FromRpcClient, IntoService};
+use infra_services::{FromRpcClient, IntoService, ReconnectingClient};
 use pb_bancho::bancho_rpc_client::BanchoRpcClient;
 use pb_bancho_state::bancho_state_rpc_client::BanchoStateRpcClient;
 use pb_chat::chat_rpc_client::ChatRpcClient;
 use peace_api::{ApiFrameConfig, RpcClientConfig, WebApplication};
+use peace_cfg::{
+    check_rpc_peer, validate_rpc_client_config, ConfigError, ConfigErrors,
+    ValidateConfig,
+};
+use peace_difficulty::{
+    BeatmapFiles, CliBeatmapFilesConfigs, DifficultyServiceImpl,
+};
 use peace_runtime::cfg::RuntimeConfig;
 use std::{net::SocketAddr, sync::Arc};
-use tonic::transport::Channel;
+use tools::tonic_utils::TracedChannel;
+use utoipa::OpenApi;
 
 /// PEACE Gateway service
 #[peace_config]
@@ -41,16 +52,56 @@ pub struct GatewayConfig {
     #[command(flatten)]
     pub chat: ChatRpcConfig,
 
+    #[command(flatten)]
+    pub bancho_routing: CliBanchoRoutingServiceConfigs,
+
+    #[command(flatten)]
+    pub beatmap_files: CliBeatmapFilesConfigs,
+
+    #[command(flatten)]
+    pub bancho_login_limiter: CliBanchoLoginLimiterConfigs,
+
+    #[command(flatten)]
+    pub bancho_client_version: CliBanchoClientVersionConfigs,
+
     #[arg(long)]
     pub debug_endpoints: bool,
 }
 
+#[async_trait]
+impl ValidateConfig for GatewayConfig {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        errors.merge(validate_rpc_client_config(&self.bancho, "bancho"));
+        errors.merge(validate_rpc_client_config(
+            &self.bancho_state,
+            "bancho_state",
+        ));
+        errors.merge(validate_rpc_client_config(&self.chat, "chat"));
+        errors.merge(self.bancho_routing.validate());
+        errors.merge(self.bancho_login_limiter.validate());
+
+        errors.into_result()
+    }
+
+    async fn check_peers(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        errors.merge(check_rpc_peer(&self.bancho, "bancho").await);
+        errors.merge(check_rpc_peer(&self.bancho_state, "bancho_state").await);
+        errors.merge(check_rpc_peer(&self.chat, "chat").await);
+
+        errors.into_result()
+    }
+}
+
 #[derive(Clone)]
 pub struct App {
     pub cfg: Arc<GatewayConfig>,
-    pub bancho_rpc_client: BanchoRpcClient<Channel>,
-    pub bancho_state_rpc_client: BanchoStateRpcClient<Channel>,
-    pub chat_rpc_client: ChatRpcClient<Channel>,
+    pub bancho_rpc_client: BanchoRpcClient<TracedChannel>,
+    pub bancho_state_rpc_client: BanchoStateRpcClient<TracedChannel>,
+    pub chat_rpc_client: ChatRpcClient<TracedChannel>,
     pub bancho_state_service: DynBanchoStateService,
     pub bancho_service: DynBanchoService,
     pub bancho_handler_service: DynBanchoHandlerService,
@@ -65,8 +116,14 @@ impl App {
 
         let chat_rpc_client = cfg.chat.connect().await;
 
-        let bancho_state_service = BanchoStateServiceRemote::from_client(
-            bancho_state_rpc_client.clone(),
+        let bancho_state_service = BanchoStateServiceRemote::new(
+            ReconnectingClient::new(bancho_state_rpc_client.clone(), {
+                let cfg = cfg.clone();
+                move || {
+                    let cfg = cfg.clone();
+                    async move { cfg.bancho_state.try_connect().await }
+                }
+            }),
         )
         .into_service();
 
@@ -82,12 +139,22 @@ impl App {
             bancho_service.clone(),
             bancho_state_service.clone(),
             chat_service.clone(),
+            Arc::new(login_limiter(&cfg.bancho_login_limiter)),
+            Arc::new(ClientVersionPolicy::with_cfg(&cfg.bancho_client_version)),
         )
         .into_service();
 
-        let bancho_routing_service =
-            BanchoRoutingServiceImpl::new(bancho_handler_service.clone())
-                .into_service();
+        let difficulty_service = DifficultyServiceImpl::new(
+            BeatmapFiles::with_cfg(&cfg.beatmap_files),
+        )
+        .into_service();
+
+        let bancho_routing_service = BanchoRoutingServiceImpl::with_cfg(
+            bancho_handler_service.clone(),
+            difficulty_service,
+            &cfg.bancho_routing,
+        )
+        .into_service();
 
         Self {
             cfg,
@@ -117,12 +184,15 @@ impl WebApplication for App {
     }
 
     async fn router<T: Clone + Sync + Send + 'static>(&self) -> Router<T> {
-        let mut router =
-            BanchoRouter::new_router(self.bancho_routing_service.clone());
+        let mut router = BanchoRouter::new_router(
+            self.bancho_routing_service.clone(),
+            self.cfg.bancho_routing.max_bancho_request_size,
+        );
 
         if self.cfg.debug_endpoints {
             router = router.merge(BanchoDebugRouter::new_router(
                 self.bancho_state_service.clone(),
+                self.bancho_service.clone(),
             ))
         }
 
@@ -132,4 +202,15 @@ impl WebApplication for App {
     fn apidocs(&self) -> utoipa::openapi::OpenApi {
         GatewayApiDocs::new_docs(self.cfg.debug_endpoints)
     }
+
+    async fn admin_router(&self) -> Option<Router> {
+        Some(BanchoAdminRouter::new_router(
+            self.bancho_state_service.clone(),
+            self.bancho_service.clone(),
+        ))
+    }
+
+    fn admin_apidocs(&self) -> Option<utoipa::openapi::OpenApi> {
+        Some(BanchoAdminEndpointsDocs::openapi())
+    }
 }
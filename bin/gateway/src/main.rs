@@ -22,6 +22,10 @@ pub fn main() {
     tools::main_startup_info!();
 
     let cfg = GatewayConfig::get();
+
+    // Validate the configuration, or only check it with `--check-config`.
+    peace_runtime::check_config_or_exit(&cfg.runtime_cfg, &*cfg);
+
     // Initialize the logger.
     peace_logs::init(&cfg.frame_cfg);
 
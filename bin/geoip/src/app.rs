@@ -3,13 +3,11 @@ use clap_serde_derive::ClapSerde;
 use core_geoip::{DynGeoipService, FromGeoDbPath, GeoipServiceImpl};
 use infra_services::IntoService;
 use pb_geoip::{geoip_rpc_server::GeoipRpcServer, GEOIP_DESCRIPTOR_SET};
-use peace_rpc::{RpcApplication, RpcFrameConfig};
+use peace_cfg::{ConfigError, ConfigErrors, ValidateConfig};
+use peace_rpc::{RpcApplication, RpcFrameConfig, RpcRouter, RpcServer};
 use peace_runtime::cfg::RuntimeConfig;
-use std::{net::SocketAddr, sync::Arc};
-use tonic::{
-    async_trait,
-    transport::{server::Router, Server},
-};
+use std::{net::SocketAddr, path::Path, process, sync::Arc};
+use tonic::async_trait;
 
 /// PEACE Geo-ip gRPC service
 #[peace_config]
@@ -25,6 +23,25 @@ pub struct GeoipConfig {
     pub geo_db_path: Option<String>,
 }
 
+impl ValidateConfig for GeoipConfig {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        errors.merge(self.frame_cfg.validate());
+        match self.geo_db_path.as_deref() {
+            Some(path) => {
+                errors.check_file("geo_db_path", Some(Path::new(path)))
+            },
+            None => errors.push(
+                "geo_db_path",
+                "is required, e.g. `--geo-db-path GeoLite2-City.mmdb`",
+            ),
+        }
+
+        errors.into_result()
+    }
+}
+
 #[derive(Clone)]
 pub struct App {
     pub cfg: Arc<GeoipConfig>,
@@ -34,12 +51,21 @@ pub struct App {
 
 impl App {
     pub async fn initialize(cfg: Arc<GeoipConfig>) -> Self {
-        let geo_db_path =
-            cfg.geo_db_path.as_ref().expect("geo_db_path is required");
+        let geo_db_path = match cfg.geo_db_path.as_ref() {
+            Some(path) => path,
+            None => {
+                error!("geo_db_path is required, set it with --geo-db-path");
+                process::exit(1)
+            },
+        };
 
-        let geoip_service = GeoipServiceImpl::from_path(geo_db_path.as_str())
-            .unwrap()
-            .into_service();
+        let geoip_service = match GeoipServiceImpl::from_path(geo_db_path) {
+            Ok(service) => service.into_service(),
+            Err(err) => {
+                error!("Failed to initialize Geoip service: {err}");
+                process::exit(1)
+            },
+        };
 
         let geoip_rpc = GeoipRpcImpl::new(geoip_service.clone());
 
@@ -61,8 +87,23 @@ impl RpcApplication for App {
         Some(&[GEOIP_DESCRIPTOR_SET])
     }
 
-    async fn service(&self, mut configured_server: Server) -> Router {
+    async fn service(&self, mut configured_server: RpcServer) -> RpcRouter {
         configured_server
             .add_service(GeoipRpcServer::new(self.geoip_rpc.clone()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_validate_geo_db_path() {
+        let errors = GeoipConfig::parse_from(["geoip"]).validate().unwrap_err();
+        assert_eq!(errors[0].field, "geo_db_path");
+
+        let cfg = GeoipConfig::parse_from(["geoip", "-P", "not-exists.mmdb"]);
+        assert_eq!(cfg.validate().unwrap_err()[0].field, "geo_db_path");
+    }
+}
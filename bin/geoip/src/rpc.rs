@@ -1,7 +1,6 @@
-use core_geoip::DynGeoipService;
+use core_geoip::{parse_ip_address, DynGeoipService};
 use pb_base::ExecSuccess;
 use pb_geoip::{GeoipData as RpcGeoipData, *};
-use std::net::IpAddr;
 use tonic::{Request, Response, Status};
 
 #[derive(Clone)]
@@ -21,11 +20,7 @@ impl geoip_rpc_server::GeoipRpc for GeoipRpcImpl {
         &self,
         request: Request<IpAddress>,
     ) -> Result<Response<RpcGeoipData>, Status> {
-        let ip_addr = request
-            .into_inner()
-            .ip
-            .parse::<IpAddr>()
-            .map_err(|err| Status::internal(err.to_string()))?;
+        let ip_addr = parse_ip_address(&request.into_inner().ip)?;
 
         let res = self.geoip_service.lookup_with_ip_address(ip_addr).await?;
 
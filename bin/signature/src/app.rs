@@ -4,13 +4,11 @@ use core_signature::*;
 use pb_signature::{
     signature_rpc_server::SignatureRpcServer, SIGNATURE_DESCRIPTOR_SET,
 };
-use peace_rpc::{RpcApplication, RpcFrameConfig};
+use peace_cfg::{ConfigError, ConfigErrors, ValidateConfig};
+use peace_rpc::{RpcApplication, RpcFrameConfig, RpcRouter, RpcServer};
 use peace_runtime::cfg::RuntimeConfig;
-use std::{net::SocketAddr, sync::Arc};
-use tonic::{
-    async_trait,
-    transport::{server::Router, Server},
-};
+use std::{net::SocketAddr, path::Path, sync::Arc};
+use tonic::async_trait;
 
 /// PEACE Signature service
 #[peace_config]
@@ -26,6 +24,20 @@ pub struct SignatureConfig {
     pub ed25519_private_key_path: Option<String>,
 }
 
+impl ValidateConfig for SignatureConfig {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        errors.merge(self.frame_cfg.validate());
+        errors.check_file(
+            "ed25519_private_key_path",
+            self.ed25519_private_key_path.as_deref().map(Path::new),
+        );
+
+        errors.into_result()
+    }
+}
+
 #[derive(Clone)]
 pub struct App {
     pub cfg: Arc<SignatureConfig>,
@@ -62,7 +74,7 @@ impl RpcApplication for App {
         Some(&[SIGNATURE_DESCRIPTOR_SET])
     }
 
-    async fn service(&self, mut configured_server: Server) -> Router {
+    async fn service(&self, mut configured_server: RpcServer) -> RpcRouter {
         configured_server
             .add_service(SignatureRpcServer::new(self.signature_rpc.clone()))
     }
@@ -15,6 +15,8 @@ pub struct Model {
     #[sea_orm(column_type = "Text", nullable)]
     pub content_html: Option<String>,
     pub is_action: bool,
+    pub deleted_at: Option<DateTimeWithTimeZone>,
+    pub redacted_at: Option<DateTimeWithTimeZone>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
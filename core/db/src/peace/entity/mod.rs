@@ -20,6 +20,7 @@ pub mod leaderboard_standard_relax;
 pub mod leaderboard_taiko;
 pub mod leaderboard_taiko_relax;
 pub mod privileges;
+pub mod replay_views;
 pub mod score_pp_fruits;
 pub mod score_pp_fruits_relax;
 pub mod score_pp_mania;
@@ -37,6 +38,7 @@ pub mod scores_standard_relax;
 pub mod scores_taiko;
 pub mod scores_taiko_relax;
 pub mod sea_orm_active_enums;
+pub mod user_login_records;
 pub mod user_pp_fruits;
 pub mod user_pp_fruits_relax;
 pub mod user_pp_mania;
@@ -47,6 +49,7 @@ pub mod user_pp_taiko;
 pub mod user_pp_taiko_relax;
 pub mod user_privileges;
 pub mod user_settings;
+pub mod user_silences;
 pub mod user_stats_fruits;
 pub mod user_stats_fruits_relax;
 pub mod user_stats_mania;
@@ -18,6 +18,7 @@ pub use super::leaderboard_standard_relax::Entity as LeaderboardStandardRelax;
 pub use super::leaderboard_taiko::Entity as LeaderboardTaiko;
 pub use super::leaderboard_taiko_relax::Entity as LeaderboardTaikoRelax;
 pub use super::privileges::Entity as Privileges;
+pub use super::replay_views::Entity as ReplayViews;
 pub use super::score_pp_fruits::Entity as ScorePpFruits;
 pub use super::score_pp_fruits_relax::Entity as ScorePpFruitsRelax;
 pub use super::score_pp_mania::Entity as ScorePpMania;
@@ -34,6 +35,7 @@ pub use super::scores_standard_autopilot::Entity as ScoresStandardAutopilot;
 pub use super::scores_standard_relax::Entity as ScoresStandardRelax;
 pub use super::scores_taiko::Entity as ScoresTaiko;
 pub use super::scores_taiko_relax::Entity as ScoresTaikoRelax;
+pub use super::user_login_records::Entity as UserLoginRecords;
 pub use super::user_pp_fruits::Entity as UserPpFruits;
 pub use super::user_pp_fruits_relax::Entity as UserPpFruitsRelax;
 pub use super::user_pp_mania::Entity as UserPpMania;
@@ -44,6 +46,7 @@ pub use super::user_pp_taiko::Entity as UserPpTaiko;
 pub use super::user_pp_taiko_relax::Entity as UserPpTaikoRelax;
 pub use super::user_privileges::Entity as UserPrivileges;
 pub use super::user_settings::Entity as UserSettings;
+pub use super::user_silences::Entity as UserSilences;
 pub use super::user_stats_fruits::Entity as UserStatsFruits;
 pub use super::user_stats_fruits_relax::Entity as UserStatsFruitsRelax;
 pub use super::user_stats_mania::Entity as UserStatsMania;
@@ -63,6 +63,8 @@ pub enum Relation {
     ScoresTaiko,
     #[sea_orm(has_many = "super::scores_taiko_relax::Entity")]
     ScoresTaikoRelax,
+    #[sea_orm(has_many = "super::user_login_records::Entity")]
+    UserLoginRecords,
     #[sea_orm(has_many = "super::user_pp_fruits::Entity")]
     UserPpFruits,
     #[sea_orm(has_many = "super::user_pp_fruits_relax::Entity")]
@@ -81,6 +83,8 @@ pub enum Relation {
     UserPpTaikoRelax,
     #[sea_orm(has_many = "super::user_settings::Entity")]
     UserSettings,
+    #[sea_orm(has_one = "super::user_silences::Entity")]
+    UserSilences,
     #[sea_orm(has_many = "super::user_stats_fruits::Entity")]
     UserStatsFruits,
     #[sea_orm(has_many = "super::user_stats_fruits_relax::Entity")]
@@ -215,6 +219,12 @@ impl Related<super::scores_taiko_relax::Entity> for Entity {
     }
 }
 
+impl Related<super::user_login_records::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserLoginRecords.def()
+    }
+}
+
 impl Related<super::user_pp_fruits::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::UserPpFruits.def()
@@ -269,6 +279,12 @@ impl Related<super::user_settings::Entity> for Entity {
     }
 }
 
+impl Related<super::user_silences::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserSilences.def()
+    }
+}
+
 impl Related<super::user_stats_fruits::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::UserStatsFruits.def()
@@ -10,6 +10,10 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(versions::init_tables::Migration),
             Box::new(versions::create_seed_data::Migration),
+            Box::new(versions::create_user_login_records::Migration),
+            Box::new(versions::create_replay_views::Migration),
+            Box::new(versions::create_user_silences::Migration),
+            Box::new(versions::alter_chat_messages_moderation::Migration),
         ]
     }
 }
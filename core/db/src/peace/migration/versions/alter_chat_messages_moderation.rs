@@ -0,0 +1,96 @@
+use sea_orm_migration::prelude::*;
+
+use super::init_tables::chat_messages::ChatMessages;
+
+const INDEX_CHANNEL_ID: &str = "IDX_chat_msg_channel_id";
+
+#[derive(Iden)]
+enum ChatMessagesModeration {
+    DeletedAt,
+    RedactedAt,
+}
+
+/// Channels can store more than one message, and messages can be deleted
+/// or redacted by the moderators.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                sea_query::Index::drop()
+                    .table(ChatMessages::Table)
+                    .name(INDEX_CHANNEL_ID)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                sea_query::Index::create()
+                    .name(INDEX_CHANNEL_ID)
+                    .table(ChatMessages::Table)
+                    .col(ChatMessages::ChannelId)
+                    .to_owned(),
+            )
+            .await?;
+
+        for column in [
+            ChatMessagesModeration::DeletedAt,
+            ChatMessagesModeration::RedactedAt,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ChatMessages::Table)
+                        .add_column(
+                            ColumnDef::new(column)
+                                .timestamp_with_time_zone()
+                                .null(),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            ChatMessagesModeration::DeletedAt,
+            ChatMessagesModeration::RedactedAt,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(ChatMessages::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .drop_index(
+                sea_query::Index::drop()
+                    .table(ChatMessages::Table)
+                    .name(INDEX_CHANNEL_ID)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                sea_query::Index::create()
+                    .name(INDEX_CHANNEL_ID)
+                    .table(ChatMessages::Table)
+                    .col(ChatMessages::ChannelId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+}
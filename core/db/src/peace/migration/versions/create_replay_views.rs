@@ -0,0 +1,51 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(Iden)]
+pub enum ReplayViews {
+    Table,
+    ScoreMd5,
+    Views,
+    UpdatedAt,
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ReplayViews::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ReplayViews::ScoreMd5)
+                            .char()
+                            .char_len(32)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ReplayViews::Views)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(ReplayViews::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ReplayViews::Table).to_owned())
+            .await
+    }
+}
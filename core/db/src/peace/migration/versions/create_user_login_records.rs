@@ -0,0 +1,99 @@
+use sea_orm_migration::prelude::*;
+
+use super::init_tables::users::Users;
+
+const FOREIGN_KEY_USER_ID: &str = "FK_user_login_records_user_id";
+const INDEX_USER_ID: &str = "IDX_user_login_records_user_id";
+
+#[derive(Iden)]
+pub enum UserLoginRecords {
+    Table,
+    Id,
+    UserId,
+    Ip,
+    ClientVersion,
+    CountryCode,
+    City,
+    CreatedAt,
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserLoginRecords::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserLoginRecords::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(UserLoginRecords::UserId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserLoginRecords::Ip)
+                            .string()
+                            .string_len(45)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserLoginRecords::ClientVersion)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserLoginRecords::CountryCode)
+                            .char()
+                            .char_len(2)
+                            .null(),
+                    )
+                    .col(ColumnDef::new(UserLoginRecords::City).string().null())
+                    .col(
+                        ColumnDef::new(UserLoginRecords::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                sea_query::ForeignKey::create()
+                    .name(FOREIGN_KEY_USER_ID)
+                    .from(UserLoginRecords::Table, UserLoginRecords::UserId)
+                    .to(Users::Table, Users::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                sea_query::Index::create()
+                    .name(INDEX_USER_ID)
+                    .table(UserLoginRecords::Table)
+                    .col(UserLoginRecords::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserLoginRecords::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+
+use super::init_tables::users::Users;
+
+const FOREIGN_KEY_USER_ID: &str = "FK_user_silences_user_id";
+
+#[derive(Iden)]
+pub enum UserSilences {
+    Table,
+    UserId,
+    SilenceEnd,
+    Reason,
+    CreatedAt,
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserSilences::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserSilences::UserId)
+                            .integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(UserSilences::SilenceEnd)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(UserSilences::Reason).string().null())
+                    .col(
+                        ColumnDef::new(UserSilences::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                sea_query::ForeignKey::create()
+                    .name(FOREIGN_KEY_USER_ID)
+                    .from(UserSilences::Table, UserSilences::UserId)
+                    .to(Users::Table, Users::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserSilences::Table).to_owned())
+            .await
+    }
+}
@@ -1,2 +1,6 @@
+pub mod alter_chat_messages_moderation;
+pub mod create_replay_views;
 pub mod create_seed_data;
+pub mod create_user_login_records;
+pub mod create_user_silences;
 pub mod init_tables;
@@ -24,11 +24,195 @@ pub enum GameMode {
     StandardScoreV2     = 12,
 }
 
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("Unknown game mode: {0}")]
+pub struct ParseGameModeError(pub String);
+
 impl GameMode {
+    const ALL: [Self; 9] = [
+        Self::Standard,
+        Self::Taiko,
+        Self::Fruits,
+        Self::Mania,
+        Self::StandardRelax,
+        Self::TaikoRelax,
+        Self::FruitsRelax,
+        Self::StandardAutopilot,
+        Self::StandardScoreV2,
+    ];
+
     #[inline]
     pub fn val(&self) -> u8 {
         *self as u8
     }
+
+    /// Mode byte known by the osu! client (0: standard, 1: taiko,
+    /// 2: fruits, 3: mania), relax, autopilot and score v2 are folded into
+    /// their vanilla mode.
+    #[inline]
+    pub fn vanilla_mode(&self) -> u8 {
+        self.val() % 4
+    }
+
+    /// Suffix of the tables storing the mode, e.g. `standard_relax` for
+    /// `scores_standard_relax` and `user_stats_standard_relax`.
+    #[inline]
+    pub fn as_table(&self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::Taiko => "taiko",
+            Self::Fruits => "fruits",
+            Self::Mania => "mania",
+            Self::StandardRelax => "standard_relax",
+            Self::TaikoRelax => "taiko_relax",
+            Self::FruitsRelax => "fruits_relax",
+            Self::StandardAutopilot => "standard_autopilot",
+            Self::StandardScoreV2 => "standard_score_v2",
+        }
+    }
+
+    /// Parse a table suffix, see [`GameMode::as_table`].
+    #[inline]
+    pub fn from_table(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.as_table() == s)
+    }
+
+    /// Stable lowercase name of the mode, e.g. `taiko_rx`, for chat
+    /// commands and web apis.
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::Taiko => "taiko",
+            Self::Fruits => "fruits",
+            Self::Mania => "mania",
+            Self::StandardRelax => "standard_rx",
+            Self::TaikoRelax => "taiko_rx",
+            Self::FruitsRelax => "fruits_rx",
+            Self::StandardAutopilot => "standard_ap",
+            Self::StandardScoreV2 => "standard_v2",
+        }
+    }
+
+    /// Parse a mode name, case insensitive.
+    ///
+    /// Accepts [`GameMode::name`] and [`GameMode::as_table`], `std` and
+    /// `osu` for standard, `ctb` and `catch` for fruits, and the `relax`,
+    /// `autopilot` and `scorev2` suffixes, e.g. `ctb_relax`.
+    pub fn from_name(name: &str) -> Result<Self, ParseGameModeError> {
+        let unknown = || ParseGameModeError(name.to_owned());
+        let lowercase = name.trim().to_ascii_lowercase();
+
+        if let Some(mode) = Self::from_table(&lowercase) {
+            return Ok(mode);
+        }
+
+        let (vanilla, variant) = match lowercase.split_once('_') {
+            Some((vanilla, variant)) => (vanilla, Some(variant)),
+            None => (lowercase.as_str(), None),
+        };
+
+        let vanilla = match vanilla {
+            "standard" | "std" | "osu" => Self::Standard,
+            "taiko" => Self::Taiko,
+            "fruits" | "ctb" | "catch" => Self::Fruits,
+            "mania" => Self::Mania,
+            _ => return Err(unknown()),
+        };
+
+        Ok(match (vanilla, variant) {
+            (mode, None) => mode,
+            (Self::Standard, Some("rx" | "relax")) => Self::StandardRelax,
+            (Self::Taiko, Some("rx" | "relax")) => Self::TaikoRelax,
+            (Self::Fruits, Some("rx" | "relax")) => Self::FruitsRelax,
+            (Self::Standard, Some("ap" | "autopilot")) => {
+                Self::StandardAutopilot
+            },
+            (Self::Standard, Some("v2" | "scorev2")) => Self::StandardScoreV2,
+            _ => return Err(unknown()),
+        })
+    }
+
+    /// Mode of the tables storing a score played in the vanilla mode with
+    /// the mods, e.g. relax standard scores are stored in `standard_relax`.
+    /// Score v2 scores are stored with the other scores of the mode, only
+    /// their score version differs.
+    #[inline]
+    pub fn from_score(vanilla_mode: u8, mods: Mods) -> Option<Self> {
+        let mode = match vanilla_mode {
+            0 => Self::Standard,
+            1 => Self::Taiko,
+            2 => Self::Fruits,
+            3 => Self::Mania,
+            _ => return None,
+        };
+
+        Some(match mode {
+            Self::Standard if mods.contains(Mods::Relax) => Self::StandardRelax,
+            Self::Taiko if mods.contains(Mods::Relax) => Self::TaikoRelax,
+            Self::Fruits if mods.contains(Mods::Relax) => Self::FruitsRelax,
+            Self::Standard if mods.contains(Mods::AutoPilot) => {
+                Self::StandardAutopilot
+            },
+            mode => mode,
+        })
+    }
+
+    /// Each mode table has its own score ids, so the ids of the relax and
+    /// autopilot scores are offset by this in the ids sent to the client.
+    /// The client only sends back the score id and the vanilla mode, e.g.
+    /// when requesting a replay.
+    pub const CLIENT_SCORE_ID_OFFSET: i64 = 1 << 40;
+
+    /// Score id sent to the client, see
+    /// [`GameMode::CLIENT_SCORE_ID_OFFSET`].
+    #[inline]
+    pub fn client_score_id(&self, score_id: i64) -> i64 {
+        let variant = match self {
+            Self::StandardRelax | Self::TaikoRelax | Self::FruitsRelax => 1,
+            Self::StandardAutopilot => 2,
+            _ => 0,
+        };
+
+        variant * Self::CLIENT_SCORE_ID_OFFSET + score_id
+    }
+
+    /// Mode and score id of a score id sent by the client, the reverse of
+    /// [`GameMode::client_score_id`].
+    #[inline]
+    pub fn from_client_score_id(
+        vanilla_mode: u8,
+        client_score_id: i64,
+    ) -> Option<(Self, i64)> {
+        if client_score_id < 0 {
+            return None;
+        }
+
+        let score_id = client_score_id % Self::CLIENT_SCORE_ID_OFFSET;
+        let mods = match client_score_id / Self::CLIENT_SCORE_ID_OFFSET {
+            0 => Mods::NoMod,
+            1 => Mods::Relax,
+            2 => Mods::AutoPilot,
+            _ => return None,
+        };
+
+        let mode = Self::from_score(vanilla_mode, mods)?;
+        // e.g. mania has no relax scores
+        if mode.client_score_id(score_id) != client_score_id {
+            return None;
+        }
+
+        Some((mode, score_id))
+    }
+}
+
+impl FromStr for GameMode {
+    type Err = ParseGameModeError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_name(s)
+    }
 }
 
 #[rustfmt::skip]
@@ -44,6 +228,35 @@ pub enum BanchoPrivileges {
     Tournament      = 1 << 5,
 }
 
+impl BanchoPrivileges {
+    /// Privileges of the users granted the privilege `name` (the `privileges`
+    /// table), unknown privileges are normal users.
+    pub fn from_privilege_name(name: &str) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "supporter" => Self::Normal.or(Self::Supporter),
+            "tournament" => Self::Normal.or(Self::Tournament),
+            "moderator" => Self::Normal.or(Self::Moderator),
+            "administrator" | "admin" => {
+                Self::Normal.or(Self::Moderator).or(Self::Administrator)
+            },
+            "developer" => Self::Normal
+                .or(Self::Moderator)
+                .or(Self::Administrator)
+                .or(Self::Developer),
+            _ => Self::Normal,
+        }
+    }
+
+    /// Moderators bypass the privileges of the channels and can moderate
+    /// the messages.
+    #[inline]
+    pub fn is_moderator(&self) -> bool {
+        self.intersects(
+            Self::Moderator.or(Self::Administrator).or(Self::Developer),
+        )
+    }
+}
+
 impl serde::Serialize for BanchoPrivileges {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -126,6 +339,117 @@ pub enum Mods {
         | Self::KeyMods.bits,
 }
 
+impl Mods {
+    /// Acronyms of the mods, in display order.
+    #[rustfmt::skip]
+    const ACRONYMS: &[(Mods, &'static str)] = &[
+        (Self::NoFail, "NF"),       (Self::Easy, "EZ"),
+        (Self::TouchScreen, "TD"),  (Self::Hidden, "HD"),
+        (Self::HardRock, "HR"),     (Self::SuddenDeath, "SD"),
+        (Self::Perfect, "PF"),      (Self::DoubleTime, "DT"),
+        (Self::NightCore, "NC"),    (Self::HalfTime, "HT"),
+        (Self::FlashLight, "FL"),   (Self::Relax, "RX"),
+        (Self::AutoPilot, "AP"),    (Self::SpunOut, "SO"),
+        (Self::Auto, "AT"),         (Self::Cinema, "CN"),
+        (Self::Key1, "1K"),         (Self::Key2, "2K"),
+        (Self::Key3, "3K"),         (Self::Key4, "4K"),
+        (Self::Key5, "5K"),         (Self::Key6, "6K"),
+        (Self::Key7, "7K"),         (Self::Key8, "8K"),
+        (Self::Key9, "9K"),         (Self::KeyCoop, "CP"),
+        (Self::FadeIn, "FI"),       (Self::Random, "RD"),
+        (Self::Mirror, "MR"),       (Self::Target, "TP"),
+        (Self::ScoreV2, "V2"),
+    ];
+
+    /// The single mods set, lowest bit first, e.g. `HDHR` yields `Hidden`
+    /// then `HardRock`.
+    pub fn iter_set(&self) -> impl Iterator<Item = Mods> {
+        let bits = self.bits();
+        (0..u32::BITS)
+            .map(|i| 1 << i)
+            .filter(move |bit| bits & bit != 0)
+            .map(Self::from)
+    }
+
+    /// Clear the mutually exclusive mods and the mods not available in the
+    /// mode, returns the stripped mods.
+    pub fn sanitize(&mut self, mode: GameMode) -> Mods {
+        let original = *self;
+        // 0: standard, 1: taiko, 2: fruits, 3: mania
+        let vanilla_mode = mode.vanilla_mode();
+
+        // Only one speed change, NightCore comes with DoubleTime
+        if self.intersects(Self::DoubleTime.or(Self::NightCore)) {
+            *self = self.and(Self::HalfTime.not());
+        }
+
+        if self.contains(Self::Easy) {
+            *self = self.and(Self::HardRock.not());
+        }
+
+        if self.contains(Self::NoFail) {
+            *self = self.and(Self::SuddenDeath.or(Self::Perfect).not());
+        }
+
+        if self.contains(Self::Auto) {
+            *self = self.and(Self::Relax.or(Self::AutoPilot).not());
+        } else if self.contains(Self::Relax) {
+            *self = self.and(Self::AutoPilot.not());
+        }
+
+        if vanilla_mode != 0 {
+            *self = self.and(Self::StandardOnly.not());
+        }
+
+        if vanilla_mode == 3 {
+            *self = self.and(Self::Relax.not());
+
+            // Keep the lowest key mod only
+            let keys = self.and(Self::KeyMods).bits();
+            if keys.count_ones() > 1 {
+                *self = self
+                    .and(Self::KeyMods.not())
+                    .or(Self::from(keys & keys.wrapping_neg()));
+            }
+        } else {
+            *self = self.and(Self::ManiaOnly.not());
+        }
+
+        original.and(self.not())
+    }
+}
+
+/// The osu! acronyms of the mods, e.g. `HDHR`, `None` if no mod.
+///
+/// Mods implied by another one are omitted: `NC` implies `DT`, `PF`
+/// implies `SD` and `CN` implies `AT`.
+impl std::fmt::Display for Mods {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_none() {
+            return f.write_str("None");
+        }
+
+        let mut implied = Self::none();
+        for (mods, implied_mods) in [
+            (Self::NightCore, Self::DoubleTime),
+            (Self::Perfect, Self::SuddenDeath),
+            (Self::Cinema, Self::Auto),
+        ] {
+            if self.contains(mods) {
+                implied = implied.or(implied_mods);
+            }
+        }
+
+        for (mods, acronym) in Self::ACRONYMS {
+            if self.contains(*mods) && !implied.contains(*mods) {
+                f.write_str(acronym)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl serde::Serialize for Mods {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -144,6 +468,27 @@ impl<'de> serde::Deserialize<'de> for Mods {
     }
 }
 
+#[rustfmt::skip]
+#[derive(Default)]
+#[bitmask(u8)]
+pub enum MatchSlotStatus {
+    #[default]
+    Open          = 1 << 0,
+    Locked        = 1 << 1,
+    NotReady      = 1 << 2,
+    Ready         = 1 << 3,
+    NoMap         = 1 << 4,
+    Playing       = 1 << 5,
+    Complete      = 1 << 6,
+    Quit          = 1 << 7,
+
+    HasPlayer = Self::NotReady.bits
+        | Self::Ready.bits
+        | Self::NoMap.bits
+        | Self::Playing.bits
+        | Self::Complete.bits,
+}
+
 #[rustfmt::skip]
 #[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Primitive, Serialize, Deserialize)]
 pub enum UserOnlineStatus {
@@ -241,6 +586,36 @@ impl BanchoCountryCode {
     }
 }
 
+/// Hardware identifiers sent by the osu! client on login, users sharing
+/// them are likely playing on the same machine.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientHashes {
+    pub path_hash: String,
+    pub adapters: String,
+    pub adapters_hash: String,
+    pub uninstall_id: String,
+    pub disk_id: String,
+}
+
+impl ClientHashes {
+    /// Hash every identifier with the salt (`md5("{salt}:{identifier}")`),
+    /// so that the raw identifiers are never stored while equal identifiers
+    /// still match.
+    pub fn salted(&self, salt: &str) -> Self {
+        let hash = |identifier: &str| {
+            format!("{:x}", md5::compute(format!("{salt}:{identifier}")))
+        };
+
+        Self {
+            path_hash: hash(&self.path_hash),
+            adapters: hash(&self.adapters),
+            adapters_hash: hash(&self.adapters_hash),
+            uninstall_id: hash(&self.uninstall_id),
+            disk_id: hash(&self.disk_id),
+        }
+    }
+}
+
 #[derive(thiserror::Error, Debug, Serialize, Deserialize)]
 pub enum ParseBanchoClientTokenError {
     #[error("Invalid token format")]
@@ -312,3 +687,229 @@ impl std::fmt::Display for BanchoClientToken {
         write!(f, "{}.{}.{}", self.user_id, self.session_id, self.signature)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_game_mode_vanilla_mode_and_table() {
+        let modes = [
+            (GameMode::Standard, 0, "standard"),
+            (GameMode::Taiko, 1, "taiko"),
+            (GameMode::Fruits, 2, "fruits"),
+            (GameMode::Mania, 3, "mania"),
+            (GameMode::StandardRelax, 0, "standard_relax"),
+            (GameMode::TaikoRelax, 1, "taiko_relax"),
+            (GameMode::FruitsRelax, 2, "fruits_relax"),
+            (GameMode::StandardAutopilot, 0, "standard_autopilot"),
+            (GameMode::StandardScoreV2, 0, "standard_score_v2"),
+        ];
+
+        for (mode, vanilla_mode, table) in modes {
+            assert_eq!(mode.vanilla_mode(), vanilla_mode, "{mode:?}");
+            assert_eq!(mode.as_table(), table, "{mode:?}");
+            assert_eq!(GameMode::from_table(table), Some(mode));
+        }
+        assert_eq!(GameMode::from_table("osu"), None);
+    }
+
+    #[test]
+    fn test_game_mode_from_score() {
+        let cases = [
+            (0, Mods::Hidden, Some(GameMode::Standard)),
+            (0, Mods::Relax, Some(GameMode::StandardRelax)),
+            (1, Mods::Relax, Some(GameMode::TaikoRelax)),
+            (2, Mods::Relax, Some(GameMode::FruitsRelax)),
+            (3, Mods::Relax, Some(GameMode::Mania)),
+            (0, Mods::AutoPilot, Some(GameMode::StandardAutopilot)),
+            (1, Mods::AutoPilot, Some(GameMode::Taiko)),
+            (0, Mods::ScoreV2, Some(GameMode::Standard)),
+            (4, Mods::NoMod, None),
+        ];
+
+        for (vanilla_mode, mods, mode) in cases {
+            assert_eq!(
+                GameMode::from_score(vanilla_mode, mods),
+                mode,
+                "{vanilla_mode} {mods:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_bancho_privileges_from_privilege_name() {
+        let moderator = BanchoPrivileges::from_privilege_name("Moderator");
+        assert!(moderator.contains(BanchoPrivileges::Normal));
+        assert!(moderator.is_moderator());
+
+        assert!(BanchoPrivileges::from_privilege_name("admin").is_moderator());
+        assert!(BanchoPrivileges::from_privilege_name("developer")
+            .contains(BanchoPrivileges::Administrator));
+
+        let supporter = BanchoPrivileges::from_privilege_name("supporter");
+        assert!(supporter.contains(BanchoPrivileges::Supporter));
+        assert!(!supporter.is_moderator());
+
+        assert_eq!(
+            BanchoPrivileges::from_privilege_name("unknown"),
+            BanchoPrivileges::Normal
+        );
+        assert!(!BanchoPrivileges::Normal.is_moderator());
+    }
+
+    #[test]
+    fn test_client_score_id() {
+        const OFFSET: i64 = GameMode::CLIENT_SCORE_ID_OFFSET;
+
+        for mode in GameMode::ALL {
+            let client_score_id = mode.client_score_id(42);
+            let expected = match mode {
+                // Stored with the standard scores
+                GameMode::StandardScoreV2 => GameMode::Standard,
+                mode => mode,
+            };
+
+            assert_eq!(
+                GameMode::from_client_score_id(
+                    mode.vanilla_mode(),
+                    client_score_id
+                ),
+                Some((expected, 42)),
+                "{mode:?}"
+            );
+        }
+
+        assert_eq!(GameMode::StandardRelax.client_score_id(42), OFFSET + 42);
+        assert_eq!(GameMode::from_client_score_id(3, OFFSET + 42), None);
+        assert_eq!(GameMode::from_client_score_id(1, 2 * OFFSET + 42), None);
+        assert_eq!(GameMode::from_client_score_id(0, 3 * OFFSET), None);
+        assert_eq!(GameMode::from_client_score_id(0, -1), None);
+    }
+
+    #[test]
+    fn test_game_mode_from_name() {
+        for mode in GameMode::ALL {
+            assert_eq!(GameMode::from_name(mode.name()), Ok(mode));
+            assert_eq!(mode.as_table().parse(), Ok(mode));
+        }
+
+        for (name, mode) in [
+            ("std", GameMode::Standard),
+            ("osu", GameMode::Standard),
+            ("OSU", GameMode::Standard),
+            ("ctb", GameMode::Fruits),
+            ("catch", GameMode::Fruits),
+            ("fruits", GameMode::Fruits),
+            ("ctb_relax", GameMode::FruitsRelax),
+            ("std_ap", GameMode::StandardAutopilot),
+            ("osu_scorev2", GameMode::StandardScoreV2),
+        ] {
+            assert_eq!(name.parse::<GameMode>(), Ok(mode), "{name}");
+        }
+
+        for name in ["", "mania_rx", "taiko_ap", "fruits_v2", "std_", "lazer"] {
+            assert_eq!(
+                GameMode::from_name(name),
+                Err(ParseGameModeError(name.to_owned()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_mods_sanitize() {
+        let mut mods = Mods::DoubleTime.or(Mods::HalfTime).or(Mods::Hidden);
+        assert_eq!(mods.sanitize(GameMode::Standard), Mods::HalfTime);
+        assert_eq!(mods, Mods::DoubleTime.or(Mods::Hidden));
+
+        // NightCore comes with DoubleTime
+        let mut mods = Mods::DoubleTime.or(Mods::NightCore);
+        assert!(mods.sanitize(GameMode::Standard).is_none());
+        assert!(mods.contains(Mods::DoubleTime.or(Mods::NightCore)));
+        assert!(!mods.contains(Mods::SpeedChanging));
+
+        let mut mods = Mods::Key4.or(Mods::Mirror).or(Mods::HardRock);
+        assert_eq!(
+            mods.sanitize(GameMode::StandardRelax),
+            Mods::Key4.or(Mods::Mirror)
+        );
+        assert_eq!(mods, Mods::HardRock);
+        assert!(!mods.intersects(Mods::ManiaOnly));
+
+        let mut mods = Mods::Key4.or(Mods::Key7).or(Mods::Mirror);
+        assert_eq!(mods.sanitize(GameMode::Mania), Mods::Key7);
+        assert_eq!(mods, Mods::Key4.or(Mods::Mirror));
+
+        let mut mods = Mods::SpunOut.or(Mods::Easy).or(Mods::HardRock);
+        assert_eq!(
+            mods.sanitize(GameMode::Taiko),
+            Mods::SpunOut.or(Mods::HardRock)
+        );
+        assert_eq!(mods, Mods::Easy);
+
+        let mut mods = Mods::Relax.or(Mods::AutoPilot).or(Mods::NoFail);
+        assert_eq!(mods.sanitize(GameMode::Standard), Mods::AutoPilot);
+
+        let mut mods = Mods::Hidden.or(Mods::HardRock).or(Mods::DoubleTime);
+        assert!(mods.sanitize(GameMode::Standard).is_none());
+    }
+
+    #[test]
+    fn test_mods_iter_set() {
+        assert_eq!(Mods::NoMod.iter_set().count(), 0);
+
+        assert_eq!(
+            Mods::HardRock.or(Mods::Hidden).iter_set().collect::<Vec<_>>(),
+            vec![Mods::Hidden, Mods::HardRock]
+        );
+
+        for bits in [
+            Mods::Hidden.or(Mods::DoubleTime).or(Mods::NightCore).bits(),
+            Mods::KeyMods.or(Mods::Mirror).bits(),
+            Mods::ScoreV2.or(Mods::NoFail).bits(),
+        ] {
+            let mods = Mods::from(bits);
+            assert!(mods.iter_set().all(|m| m.bits().count_ones() == 1));
+            assert_eq!(
+                mods.iter_set().fold(Mods::none(), |acc, m| acc.or(m)).bits(),
+                bits
+            );
+        }
+    }
+
+    #[test]
+    fn test_client_hashes_salted() {
+        let hashes = ClientHashes {
+            path_hash: "0c5d6f0a6d4f4f0a9c1e6c5b0e6f1c2d".into(),
+            adapters: "00-15-5D-01-02-03.".into(),
+            adapters_hash: "1f3870be274f6c49b3e31a0c6728957f".into(),
+            uninstall_id: "c4ca4238a0b923820dcc509a6f75849b".into(),
+            disk_id: "c81e728d9d4c2f636f067f89cc14862c".into(),
+        };
+
+        let salted = hashes.salted("salt");
+        assert_eq!(salted, hashes.salted("salt"));
+        assert_ne!(salted, hashes.salted("pepper"));
+        assert_ne!(salted.disk_id, hashes.disk_id);
+        assert_eq!(salted.adapters.len(), 32);
+    }
+
+    #[test]
+    fn test_mods_display() {
+        assert_eq!(Mods::NoMod.to_string(), "None");
+        assert_eq!(Mods::HardRock.or(Mods::Hidden).to_string(), "HDHR");
+
+        // speed changing mods
+        assert_eq!(Mods::DoubleTime.or(Mods::Hidden).to_string(), "HDDT");
+        assert_eq!(Mods::DoubleTime.or(Mods::NightCore).to_string(), "NC");
+        assert_eq!(Mods::HalfTime.or(Mods::Easy).to_string(), "EZHT");
+        assert_eq!(Mods::SuddenDeath.or(Mods::Perfect).to_string(), "PF");
+
+        // key mods are ordered by key count, not by bit
+        assert_eq!(Mods::Key4.or(Mods::Mirror).to_string(), "4KMR");
+        assert_eq!(
+            Mods::Key2.or(Mods::Key1).or(Mods::Key9).to_string(),
+            "1K2K9K"
+        );
+    }
+}
@@ -11,6 +11,42 @@ pub struct ConnectionInfo {
     pub country: Country,
     pub region: Region,
     pub city: City,
+    /// Unix timestamp (secs) of the login of the session.
+    #[serde(default)]
+    pub connected_at: i64,
+    /// Times the user logged in again while still online (e.g. the client
+    /// lost its session), since its first login.
+    #[serde(default)]
+    pub reconnect_count: u32,
+}
+
+/// [`ConnectionInfo`] of the version 1 snapshots, for decoding their binary
+/// layout.
+#[derive(Deserialize, Debug)]
+pub struct ConnectionInfoV1 {
+    pub ip: String,
+    pub location: Location,
+    pub continent: Continent,
+    pub country: Country,
+    pub region: Region,
+    pub city: City,
+}
+
+impl From<ConnectionInfoV1> for ConnectionInfo {
+    fn from(v1: ConnectionInfoV1) -> Self {
+        let ConnectionInfoV1 { ip, location, continent, country, region, city } =
+            v1;
+
+        Self {
+            ip,
+            location,
+            continent,
+            country,
+            region,
+            city,
+            ..Default::default()
+        }
+    }
 }
 
 impl From<RpcConnectionInfo> for ConnectionInfo {
@@ -25,10 +61,13 @@ impl From<RpcConnectionInfo> for ConnectionInfo {
             country: country.unwrap_or_default().into(),
             region: region.unwrap_or_default().into(),
             city: city.unwrap_or_default().into(),
+            ..Default::default()
         }
     }
 }
 
+/// `connected_at` and `reconnect_count` are set by the bancho state service,
+/// they are not sent over rpc.
 impl From<ConnectionInfo> for RpcConnectionInfo {
     fn from(val: ConnectionInfo) -> Self {
         RpcConnectionInfo {
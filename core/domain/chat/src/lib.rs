@@ -31,10 +31,12 @@ pub enum ChannelType {
 #[bitmask(i32)]
 pub enum Platform {
     #[default]
-    None    = 0,
-    Bancho  = 1,
-    Lazer   = 2,
-    Web     = 3,
+    None      = 0,
+    Bancho    = 1,
+    Lazer     = 2,
+    Web       = 3,
+    /// Bancho packets served over websocket, see the gateway `/ws` route.
+    Websocket = 4,
 }
 
 impl serde::Serialize for Platform {
@@ -57,16 +59,17 @@ impl<'de> serde::Deserialize<'de> for Platform {
 
 impl Platform {
     #[inline]
-    pub const fn all_platforms() -> [Self; 3] {
-        [Self::Bancho, Self::Lazer, Self::Web]
+    pub const fn all_platforms() -> [Self; 4] {
+        [Self::Bancho, Self::Lazer, Self::Web, Self::Websocket]
     }
 
     #[inline]
-    pub fn platforms_array(&self) -> [Option<Self>; 3] {
+    pub fn platforms_array(&self) -> [Option<Self>; 4] {
         [
             self.contains(Self::Bancho).then_some(Self::Bancho),
             self.contains(Self::Lazer).then_some(Self::Lazer),
             self.contains(Self::Web).then_some(Self::Web),
+            self.contains(Self::Websocket).then_some(Self::Websocket),
         ]
     }
 
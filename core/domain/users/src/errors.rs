@@ -8,9 +8,15 @@ pub enum UsernameError {
     UnderscoresAndSpacesNotExistsBoth,
 }
 
-#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
-#[error("Invalid email.")]
-pub struct EmailError;
+#[derive(thiserror::Error, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EmailError {
+    #[error("Invalid email format.")]
+    InvalidFormat,
+    #[error("Invalid email domain.")]
+    InvalidDomain,
+    #[error("Email domain \"{0}\" is not allowed.")]
+    BlockedDomain(String),
+}
 
 #[derive(thiserror::Error, Debug, Serialize, Deserialize)]
 pub enum PasswordError {
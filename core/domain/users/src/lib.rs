@@ -3,7 +3,10 @@ use once_cell::sync::OnceCell;
 use rand::Rng;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::{marker::PhantomData, ops::Deref};
+use std::{
+    collections::HashSet, io, marker::PhantomData, ops::Deref, path::Path,
+    str::FromStr,
+};
 
 pub mod errors;
 pub use errors::*;
@@ -26,11 +29,17 @@ pub struct CreateUser {
 pub struct Email(String);
 
 impl Email {
+    /// Validate the email format and the shape of its domain, use
+    /// [`EmailValidator`] to reject blocked domains as well.
     pub fn new(s: &str) -> Result<Self, EmailError> {
         let s = s.trim().to_ascii_lowercase();
 
         if !Self::regex().is_match(s.as_str()) {
-            return Err(EmailError);
+            return Err(EmailError::InvalidFormat);
+        }
+
+        if !Self::is_valid_domain(Self::domain_of(&s)) {
+            return Err(EmailError::InvalidDomain);
         }
 
         Ok(Self(s))
@@ -39,10 +48,108 @@ impl Email {
     pub fn regex() -> &'static Regex {
         static EMAIL_REGEX: OnceCell<Regex> = OnceCell::new();
         EMAIL_REGEX.get_or_init(|| {
-            Regex::new(r"^[^@\s]{1,200}@[^@\s\.]{1,30}\.[^@\.\s]{2,24}$")
-                .unwrap()
+            Regex::new(r"^[^@\s]{1,200}@[^@\s]{1,253}$").unwrap()
         })
     }
+
+    #[inline]
+    pub fn domain(&self) -> &str {
+        Self::domain_of(&self.0)
+    }
+
+    #[inline]
+    fn domain_of(s: &str) -> &str {
+        s.rsplit_once('@').map(|(_, domain)| domain).unwrap_or_default()
+    }
+
+    /// Check the domain could be resolved as a mail exchanger: at least
+    /// two labels of `1..=63` alphanumerics or hyphens (not leading or
+    /// trailing), and an alphabetic (or punycode) tld.
+    pub fn is_valid_domain(domain: &str) -> bool {
+        let labels = domain.split('.').collect::<Vec<&str>>();
+        if labels.len() < 2 {
+            return false;
+        }
+
+        let label_is_valid = |label: &&str| {
+            (1..=63).contains(&label.len())
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        };
+
+        if !labels.iter().all(label_is_valid) {
+            return false;
+        }
+
+        let tld = labels[labels.len() - 1];
+        tld.starts_with("xn--")
+            || ((2..=24).contains(&tld.len())
+                && tld.chars().all(|c| c.is_ascii_alphabetic()))
+    }
+}
+
+impl FromStr for Email {
+    type Err = EmailError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+/// Validates emails and rejects those from blocked (e.g. disposable)
+/// domains, subdomains of a blocked domain are rejected too.
+#[derive(Clone, Debug, Default)]
+pub struct EmailValidator {
+    pub blocked_domains: HashSet<String>,
+}
+
+impl EmailValidator {
+    #[inline]
+    pub fn new(blocked_domains: HashSet<String>) -> Self {
+        Self {
+            blocked_domains: blocked_domains
+                .into_iter()
+                .map(|d| d.trim().to_ascii_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Load the blocklist from a file, one domain per line.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn from_blocklist_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+
+        Ok(Self::new(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(str::to_owned)
+                .collect(),
+        ))
+    }
+
+    pub fn validate(&self, s: &str) -> Result<Email, EmailError> {
+        let email = Email::new(s)?;
+
+        if let Some(blocked) = self.blocked_domain_of(email.domain()) {
+            return Err(EmailError::BlockedDomain(blocked.to_owned()));
+        }
+
+        Ok(email)
+    }
+
+    /// Returns the blocked domain matching `domain` or one of its parents.
+    fn blocked_domain_of<'a>(&self, mut domain: &'a str) -> Option<&'a str> {
+        loop {
+            if self.blocked_domains.contains(domain) {
+                return Some(domain);
+            }
+            domain = domain.split_once('.')?.1;
+        }
+    }
 }
 
 impl From<Email> for String {
@@ -212,6 +319,21 @@ impl<T> AsRef<String> for Username<T> {
     }
 }
 
+/// Levels of the `privileges.priority` of a user, users without any
+/// privilege are [`PrivilegePriority::NORMAL`].
+pub struct PrivilegePriority;
+
+impl PrivilegePriority {
+    /// Users at or below this priority are banned.
+    pub const BANNED: i32 = 0;
+    pub const NORMAL: i32 = 1;
+
+    #[inline]
+    pub fn is_banned(priority: i32) -> bool {
+        priority <= Self::BANNED
+    }
+}
+
 pub struct PasswordSalt;
 
 impl PasswordSalt {
@@ -274,6 +396,107 @@ impl Password {
     ) -> Result<(), PasswordError> {
         Self::verify_password(&self.0, password)
     }
+
+    /// Verify the password, returns a new hash if the stored one was
+    /// encoded with weaker params than the current [`Config::default()`],
+    /// which should be persisted by the caller.
+    #[inline]
+    pub fn verify_and_maybe_rehash<T: AsRef<[u8]>>(
+        &self,
+        password: T,
+    ) -> Result<Option<Password>, PasswordError> {
+        self.verify(password.as_ref())?;
+
+        if !self.needs_rehash() {
+            return Ok(None);
+        }
+
+        Self::hash_password(password).map(Some)
+    }
+
+    /// Whether the hash was encoded with weaker params than the current
+    /// [`Config::default()`].
+    #[inline]
+    pub fn needs_rehash(&self) -> bool {
+        PasswordHashParams::parse(&self.0)
+            .map(|params| params.is_weaker_than(&PasswordHashParams::current()))
+            .unwrap_or(true)
+    }
+}
+
+/// Argon2 params parsed from a PHC string, e.g.
+/// `$argon2i$v=19$m=4096,t=3,p=1$<salt>$<hash>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PasswordHashParams {
+    pub variant: String,
+    pub version: u32,
+    pub mem_cost: u32,
+    pub time_cost: u32,
+    pub lanes: u32,
+}
+
+impl PasswordHashParams {
+    /// Version used by encodings without the `v=` field.
+    const LEGACY_VERSION: u32 = 0x10;
+
+    #[inline]
+    pub fn current() -> Self {
+        let config = Config::default();
+        Self {
+            variant: config.variant.as_lowercase_str().to_owned(),
+            version: config.version.as_u32(),
+            mem_cost: config.mem_cost,
+            time_cost: config.time_cost,
+            lanes: config.lanes,
+        }
+    }
+
+    pub fn parse(encoded: &str) -> Option<Self> {
+        let mut parts = encoded.split('$').skip(1);
+
+        let variant = parts.next()?.to_owned();
+
+        let mut next = parts.next()?;
+        let version = match next.strip_prefix("v=") {
+            Some(v) => {
+                let version = v.parse().ok()?;
+                next = parts.next()?;
+                version
+            },
+            None => Self::LEGACY_VERSION,
+        };
+
+        let (mut mem_cost, mut time_cost, mut lanes) = (None, None, None);
+        for param in next.split(',') {
+            let (k, v) = param.split_once('=')?;
+            let v = v.parse::<u32>().ok()?;
+            match k {
+                "m" => mem_cost = Some(v),
+                "t" => time_cost = Some(v),
+                "p" => lanes = Some(v),
+                _ => {},
+            }
+        }
+
+        Some(Self {
+            variant,
+            version,
+            mem_cost: mem_cost?,
+            time_cost: time_cost?,
+            lanes: lanes?,
+        })
+    }
+
+    /// A different variant is considered weaker too, as it should be
+    /// migrated to the current one.
+    #[inline]
+    pub fn is_weaker_than(&self, other: &Self) -> bool {
+        self.variant != other.variant
+            || self.version < other.version
+            || self.mem_cost < other.mem_cost
+            || self.time_cost < other.time_cost
+            || self.lanes < other.lanes
+    }
 }
 
 impl From<Password> for String {
@@ -303,3 +526,135 @@ impl AsRef<String> for Password {
         &self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_privilege_priority_banned() {
+        assert!(PrivilegePriority::is_banned(PrivilegePriority::BANNED));
+        assert!(PrivilegePriority::is_banned(-1));
+        assert!(!PrivilegePriority::is_banned(PrivilegePriority::NORMAL));
+    }
+
+    #[test]
+    fn test_email_format() {
+        assert_eq!(
+            "Peace@Example.com ".parse::<Email>().unwrap().as_str(),
+            "peace@example.com"
+        );
+        assert!("peace@mail.example.co.uk".parse::<Email>().is_ok());
+        assert!("peace@xn--fiqs8s.xn--fiqz9s".parse::<Email>().is_ok());
+
+        for invalid in ["peace", "peace@", "@example.com", "a@b@example.com"] {
+            assert_eq!(
+                invalid.parse::<Email>(),
+                Err(EmailError::InvalidFormat)
+            );
+        }
+
+        for invalid in [
+            "peace@localhost",
+            "peace@example.c",
+            "peace@example.123",
+            "peace@-example.com",
+            "peace@example..com",
+            "peace@exa_mple.com",
+        ] {
+            assert_eq!(
+                invalid.parse::<Email>(),
+                Err(EmailError::InvalidDomain)
+            );
+        }
+    }
+
+    #[test]
+    fn test_email_validator_blocklist() {
+        let validator = EmailValidator::new(HashSet::from([
+            "mailinator.com".to_owned(),
+            " Temp-Mail.org".to_owned(),
+        ]));
+
+        assert!(validator.validate("peace@example.com").is_ok());
+        assert_eq!(
+            validator.validate("peace@mailinator.com"),
+            Err(EmailError::BlockedDomain("mailinator.com".into()))
+        );
+        assert_eq!(
+            validator.validate("peace@eu.temp-mail.org"),
+            Err(EmailError::BlockedDomain("temp-mail.org".into()))
+        );
+        assert!(validator.validate("peace@notmailinator.com").is_ok());
+        assert_eq!(
+            validator.validate("not an email"),
+            Err(EmailError::InvalidFormat)
+        );
+    }
+
+    const RAW_PASSWORD: &str = "peace";
+
+    fn hash_with(config: &Config) -> Password {
+        Password::from_hashed(
+            argon2::hash_encoded(
+                RAW_PASSWORD.as_bytes(),
+                PasswordSalt::salt().as_bytes(),
+                config,
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_parse_hash_params() {
+        let params = PasswordHashParams::parse(
+            "$argon2i$v=19$m=4096,t=3,p=1$c2FsdHNhbHQ$aGFzaA",
+        )
+        .unwrap();
+        assert_eq!(
+            params,
+            PasswordHashParams {
+                variant: "argon2i".into(),
+                version: 19,
+                mem_cost: 4096,
+                time_cost: 3,
+                lanes: 1,
+            }
+        );
+
+        let legacy =
+            PasswordHashParams::parse("$argon2i$m=4096,t=3,p=1$c2FsdA$aGFzaA")
+                .unwrap();
+        assert_eq!(legacy.version, 0x10);
+
+        assert!(PasswordHashParams::parse("not a phc string").is_none());
+        assert!(PasswordHashParams::parse("$argon2i$v=19$m=4096$s$h").is_none());
+    }
+
+    #[test]
+    fn test_verify_and_maybe_rehash() {
+        let current = Password::hash_password(RAW_PASSWORD).unwrap();
+        assert!(!current.needs_rehash());
+        assert_eq!(
+            current.verify_and_maybe_rehash(RAW_PASSWORD).unwrap(),
+            None
+        );
+
+        let weaker = hash_with(&Config {
+            mem_cost: Config::default().mem_cost / 2,
+            time_cost: 1,
+            ..Config::default()
+        });
+        assert!(weaker.needs_rehash());
+
+        let rehashed =
+            weaker.verify_and_maybe_rehash(RAW_PASSWORD).unwrap().unwrap();
+        assert!(!rehashed.needs_rehash());
+        assert!(rehashed.verify(RAW_PASSWORD).is_ok());
+
+        assert!(matches!(
+            weaker.verify_and_maybe_rehash("wrong"),
+            Err(PasswordError::InvalidPassword)
+        ));
+    }
+}
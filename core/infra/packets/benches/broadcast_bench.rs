@@ -0,0 +1,126 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use infra_packets::{Packet, PacketsQueue};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    future::Future,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+const SESSIONS: usize = 1000;
+
+/// Counts the allocations made by the benchmark process.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(
+        &self,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(
+            new_size.saturating_sub(layout.size()),
+            Ordering::Relaxed,
+        );
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn broadcast_packet() -> Packet {
+    Packet::new_ptr(bancho_packets::server::Notification::pack(
+        "hello, peace!".into(),
+    ))
+}
+
+/// Consume each packet by `into_iter`, which clones the shared data.
+async fn broadcast_into_iter(queues: &[PacketsQueue]) {
+    let packet = broadcast_packet();
+    for queue in queues.iter() {
+        queue.push_packet(packet.clone()).await;
+    }
+
+    for queue in queues.iter() {
+        let mut buf = Vec::new();
+        let mut queue = queue.queue.lock().await;
+        while let Some(packet) = queue.pop_front() {
+            buf.extend(packet);
+        }
+    }
+}
+
+async fn broadcast_dequeue_all(queues: &[PacketsQueue]) {
+    let packet = broadcast_packet();
+    for queue in queues.iter() {
+        queue.push_packet(packet.clone()).await;
+    }
+
+    for queue in queues.iter() {
+        queue.dequeue_all_packets(None).await;
+    }
+}
+
+/// Returns the `(allocations, bytes)` made by one broadcast round.
+fn count_allocations<F: Future>(
+    rt: &tokio::runtime::Runtime,
+    round: F,
+) -> (usize, usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    rt.block_on(round);
+
+    (
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes,
+    )
+}
+
+fn broadcast_benchmark(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let queues =
+        (0..SESSIONS).map(|_| PacketsQueue::default()).collect::<Vec<_>>();
+
+    // Warm up once so that the queues have grown their buffers.
+    rt.block_on(broadcast_dequeue_all(&queues));
+
+    let into_iter = count_allocations(&rt, broadcast_into_iter(&queues));
+    let dequeue_all = count_allocations(&rt, broadcast_dequeue_all(&queues));
+    println!(
+        "{SESSIONS} sessions broadcast allocations: into_iter {} ({} bytes), \
+         dequeue_all_packets {} ({} bytes)",
+        into_iter.0, into_iter.1, dequeue_all.0, dequeue_all.1
+    );
+    // Dequeuing must not clone the shared packet for each session.
+    assert!(dequeue_all.0 + SESSIONS <= into_iter.0);
+
+    let mut group = c.benchmark_group("broadcast");
+
+    group.bench_function("1000 sessions - into_iter", |b| {
+        b.iter(|| rt.block_on(broadcast_into_iter(&queues)))
+    });
+
+    group.bench_function("1000 sessions - dequeue_all_packets", |b| {
+        b.iter(|| rt.block_on(broadcast_dequeue_all(&queues)))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, broadcast_benchmark);
+criterion_main!(benches);
@@ -2,11 +2,14 @@ use crate::Packet;
 use async_trait::async_trait;
 use peace_snapshot::CreateSnapshot;
 use std::{collections::VecDeque, sync::Arc};
-use tokio::sync::{Mutex, MutexGuard};
+use tokio::sync::{Mutex, MutexGuard, Notify};
 
 #[derive(Debug, Clone, Default)]
 pub struct PacketsQueue {
     pub queue: Arc<Mutex<VecDeque<Packet>>>,
+    /// Notified when packets are pushed, so a transport can flush the queue
+    /// right away instead of polling it.
+    pub notify: Arc<Notify>,
 }
 
 impl From<Vec<Packet>> for PacketsQueue {
@@ -28,7 +31,14 @@ impl From<Vec<u8>> for PacketsQueue {
 impl PacketsQueue {
     #[inline]
     pub fn new(packets: VecDeque<Packet>) -> Self {
-        Self { queue: Arc::new(Mutex::new(packets)) }
+        Self { queue: Arc::new(Mutex::new(packets)), notify: Arc::default() }
+    }
+
+    /// Wait until packets are pushed. Pushes without a waiter are not lost:
+    /// the next wait returns immediately.
+    #[inline]
+    pub async fn notified(&self) {
+        self.notify.notified().await
     }
 
     #[inline]
@@ -40,6 +50,7 @@ impl PacketsQueue {
     pub async fn push_packet(&self, packet: Packet) -> usize {
         let mut queue = self.queue.lock().await;
         queue.push_back(packet);
+        self.notify.notify_one();
         queue.len()
     }
 
@@ -50,6 +61,7 @@ impl PacketsQueue {
     {
         let mut queue = self.queue.lock().await;
         queue.extend(packets);
+        self.notify.notify_one();
         queue.len()
     }
 
@@ -77,7 +89,9 @@ impl PacketsQueue {
             queue_lock: &mut MutexGuard<'_, VecDeque<Packet>>,
         ) {
             while let Some(packet) = queue_lock.pop_front() {
-                buf.extend(packet);
+                // Extend from the slice instead of consuming the packet,
+                // a shared `Ptr` packet would otherwise clone its data.
+                buf.extend_from_slice(&packet);
             }
         }
 
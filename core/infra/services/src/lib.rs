@@ -1,3 +1,10 @@
+#[macro_use]
+extern crate peace_logs;
+
+pub mod reconnect;
+
+pub use reconnect::*;
+
 pub trait FromRpcClient: RpcClient {
     fn from_client(client: Self::Client) -> Self;
 }
@@ -0,0 +1,279 @@
+use crate::{FromRpcClient, RpcClient};
+use arc_swap::ArcSwap;
+use std::{future::Future, pin::Pin, sync::Arc};
+use tonic::{Code, Status};
+
+type ConnectFuture<C> =
+    Pin<Box<dyn Future<Output = Result<C, anyhow::Error>> + Send>>;
+
+type ConnectFn<C> = Arc<dyn Fn() -> ConnectFuture<C> + Send + Sync>;
+
+/// Transport failures (the peer is unreachable, e.g. restarting) can be
+/// retried on a new channel, the other errors are returned by the peer
+/// itself and must be propagated.
+#[inline]
+pub fn is_transport_error(status: &Status) -> bool {
+    status.code() == Code::Unavailable
+}
+
+/// Rpc client that reconnects its channel when a call fails with a
+/// transport error.
+///
+/// Only [`ReconnectingClient::call_idempotent`] retries the call on the new
+/// channel: a failed call may still have been applied by the peer, so
+/// retrying a call that mutates state could apply it twice.
+///
+/// ```rust,ignore
+/// let cfg = cfg.clone();
+/// let client = ReconnectingClient::new(
+///     cfg.bancho_state.connect().await,
+///     move || {
+///         let cfg = cfg.clone();
+///         async move { cfg.bancho_state.try_connect().await }
+///     },
+/// );
+///
+/// let res = client
+///     .call_idempotent(|mut c| {
+///         let query = query.clone();
+///         async move { c.is_user_online(query).await }
+///     })
+///     .await?;
+/// ```
+pub struct ReconnectingClient<C> {
+    client: Arc<ArcSwap<C>>,
+    connect: ConnectFn<C>,
+}
+
+impl<C> Clone for ReconnectingClient<C> {
+    fn clone(&self) -> Self {
+        Self { client: self.client.clone(), connect: self.connect.clone() }
+    }
+}
+
+impl<C: std::fmt::Debug> std::fmt::Debug for ReconnectingClient<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReconnectingClient")
+            .field("client", &self.client.load())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C> ReconnectingClient<C>
+where
+    C: Clone + Send + Sync + 'static,
+{
+    #[inline]
+    pub fn new<F, Fut>(client: C, connect: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<C, anyhow::Error>> + Send + 'static,
+    {
+        Self {
+            client: Arc::new(ArcSwap::from_pointee(client)),
+            connect: Arc::new(move || Box::pin(connect())),
+        }
+    }
+
+    /// Replace the client with a newly connected one.
+    #[inline]
+    pub async fn reconnect(&self) -> Result<(), anyhow::Error> {
+        self.client.store(Arc::new((self.connect)().await?));
+        Ok(())
+    }
+
+    /// Call the rpc, if it fails with a transport error the client is
+    /// reconnected for the next calls and the error is returned.
+    pub async fn call<T, F, Fut>(&self, f: F) -> Result<T, Status>
+    where
+        F: FnOnce(C) -> Fut,
+        Fut: Future<Output = Result<T, Status>>,
+    {
+        let res = f(self.client()).await;
+
+        if let Err(status) = &res {
+            if is_transport_error(status) {
+                self.try_reconnect().await;
+            }
+        }
+
+        res
+    }
+
+    /// Call an idempotent rpc, if it fails with a transport error the client
+    /// is reconnected and the rpc is retried once.
+    ///
+    /// If the reconnection fails, the error of the first call is returned.
+    pub async fn call_idempotent<T, F, Fut>(&self, f: F) -> Result<T, Status>
+    where
+        F: Fn(C) -> Fut,
+        Fut: Future<Output = Result<T, Status>>,
+    {
+        match f(self.client()).await {
+            Err(status) if is_transport_error(&status) => {
+                if !self.try_reconnect().await {
+                    return Err(status);
+                }
+
+                f(self.client()).await
+            },
+            res => res,
+        }
+    }
+
+    #[inline]
+    async fn try_reconnect(&self) -> bool {
+        match self.reconnect().await {
+            Ok(()) => true,
+            Err(err) => {
+                warn!("Failed to reconnect rpc client: {err}");
+                false
+            },
+        }
+    }
+}
+
+impl<C> FromRpcClient for ReconnectingClient<C>
+where
+    C: Clone + Send + Sync + 'static,
+{
+    /// Wrap a client that is never reconnected.
+    #[inline]
+    fn from_client(client: Self::Client) -> Self {
+        Self::new(client, || async {
+            Err(anyhow::anyhow!("reconnection is not configured"))
+        })
+    }
+}
+
+impl<C> RpcClient for ReconnectingClient<C>
+where
+    C: Clone,
+{
+    type Client = C;
+
+    #[inline]
+    fn client(&self) -> Self::Client {
+        self.client.load().as_ref().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// Client whose channel can be dropped.
+    #[derive(Clone)]
+    struct FakeClient {
+        id: usize,
+        dropped: Arc<AtomicBool>,
+    }
+
+    impl FakeClient {
+        async fn echo(self, code: Code) -> Result<usize, Status> {
+            if self.dropped.load(Ordering::SeqCst) {
+                return Err(Status::unavailable("channel dropped"));
+            }
+
+            match code {
+                Code::Ok => Ok(self.id),
+                code => Err(Status::new(code, "application error")),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconnecting_client() {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let first = FakeClient { id: 0, dropped: Default::default() };
+
+        let client = ReconnectingClient::new(first.clone(), {
+            let connects = connects.clone();
+            move || {
+                let id = connects.fetch_add(1, Ordering::SeqCst) + 1;
+                async move { Ok(FakeClient { id, dropped: Default::default() }) }
+            }
+        });
+
+        assert_eq!(
+            client.call_idempotent(|c| c.echo(Code::Ok)).await.unwrap(),
+            0
+        );
+
+        // application errors are propagated without reconnecting
+        assert_eq!(
+            client
+                .call_idempotent(|c| c.echo(Code::NotFound))
+                .await
+                .unwrap_err()
+                .code(),
+            Code::NotFound
+        );
+        assert_eq!(connects.load(Ordering::SeqCst), 0);
+
+        // the dropped channel is replaced, then the call is retried
+        first.dropped.store(true, Ordering::SeqCst);
+        assert_eq!(
+            client.call_idempotent(|c| c.echo(Code::Ok)).await.unwrap(),
+            1
+        );
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+        assert_eq!(client.call(|c| c.echo(Code::Ok)).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_call_not_retried() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let first =
+            FakeClient { id: 0, dropped: Arc::new(AtomicBool::new(true)) };
+
+        let client = ReconnectingClient::new(first, || async {
+            Ok(FakeClient { id: 1, dropped: Default::default() })
+        });
+
+        // the call may have been applied by the peer, it is not retried
+        let res = client
+            .call(|c| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                c.echo(Code::Ok)
+            })
+            .await;
+        assert_eq!(res.unwrap_err().code(), Code::Unavailable);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // but the next calls use the new channel
+        assert_eq!(client.call(|c| c.echo(Code::Ok)).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_failed() {
+        let client = ReconnectingClient::new(
+            FakeClient { id: 0, dropped: Arc::new(AtomicBool::new(true)) },
+            || async { Err(anyhow::anyhow!("connection refused")) },
+        );
+
+        assert_eq!(
+            client
+                .call_idempotent(|c| c.echo(Code::Ok))
+                .await
+                .unwrap_err()
+                .code(),
+            Code::Unavailable
+        );
+    }
+
+    #[tokio::test]
+    async fn test_from_client_never_reconnects() {
+        let client = ReconnectingClient::from_client(FakeClient {
+            id: 0,
+            dropped: Arc::new(AtomicBool::new(true)),
+        });
+
+        assert!(client.reconnect().await.is_err());
+        assert_eq!(
+            client.call(|c| c.echo(Code::Ok)).await.unwrap_err().code(),
+            Code::Unavailable
+        );
+    }
+}
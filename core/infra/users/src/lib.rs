@@ -1,4 +1,5 @@
 use peace_unique_id::Ulid;
+use std::net::IpAddr;
 
 pub mod sessions;
 pub mod users_store;
@@ -12,3 +13,11 @@ pub trait UserKey {
     fn username(&self) -> String;
     fn username_unicode(&self) -> Option<String>;
 }
+
+pub trait SessionIpAddr {
+    /// Ip address the session connected from, sessions returning `None`
+    /// will not be added to the ip index.
+    fn ip_addr(&self) -> Option<IpAddr> {
+        None
+    }
+}
@@ -38,6 +38,7 @@ pub struct BaseSession {
     pub privileges: I32,
     /// The timestamp of when the session was created.
     pub created_at: DateTime<Utc>,
+    /// Unix timestamp (seconds) of the last request of the session.
     pub last_active: U64,
 }
 
@@ -106,6 +107,12 @@ impl BaseSession {
         self.last_active.set(Timestamp::now());
     }
 
+    /// Unix timestamp (seconds) of the last request of the session.
+    #[inline]
+    pub fn last_active(&self) -> u64 {
+        self.last_active.val()
+    }
+
     pub fn to_session_data(&self) -> BaseSessionData {
         BaseSessionData {
             id: self.id,
@@ -165,3 +172,19 @@ impl SessionFilter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_active() {
+        let session = BaseSession::new(1000, "peace".into(), None, 1);
+        session.last_active.set(0);
+
+        session.update_active();
+
+        assert!(session.last_active() > 0);
+        assert!(!session.is_deactive(Timestamp::now(), 60));
+    }
+}
@@ -1,10 +1,11 @@
-use crate::{BaseSession, UserKey};
+use crate::{BaseSession, SessionIpAddr, UserKey};
 use async_trait::async_trait;
 use pb_bancho_state::UserQuery;
 use peace_snapshot::CreateSnapshot;
 use peace_unique_id::Ulid;
 use std::{
     collections::{BTreeMap, HashMap},
+    net::IpAddr,
     ops::Deref,
     sync::Arc,
 };
@@ -28,29 +29,6 @@ impl<T> UserStore<T> {
         Self { indexes: RwLock::new(indexes), len }
     }
 
-    #[inline]
-    pub fn delete_inner(
-        &self,
-        indexes: &mut UserIndexes<T>,
-        user_id: &i32,
-        username: &str,
-        session_id: &Ulid,
-        username_unicode: Option<&str>,
-    ) -> Option<Arc<T>> {
-        let removed = indexes.remove_session(
-            user_id,
-            username,
-            session_id,
-            username_unicode,
-        );
-
-        if removed.is_some() {
-            self.len.sub(1);
-        }
-
-        removed
-    }
-
     #[inline]
     pub async fn get(&self, query: &UserQuery) -> Option<Arc<T>> {
         let indexes = self.indexes.read().await;
@@ -75,6 +53,17 @@ impl<T> UserStore<T> {
         .cloned()
     }
 
+    #[inline]
+    pub async fn get_by_ip(&self, ip: &IpAddr) -> Vec<Arc<T>> {
+        self.indexes
+            .read()
+            .await
+            .ip
+            .get(ip)
+            .map(|sessions| sessions.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
     #[inline]
     pub async fn exists(&self, query: &UserQuery) -> bool {
         let indexes = self.indexes.read().await;
@@ -99,6 +88,7 @@ impl<T> UserStore<T> {
         indexes.username.clear();
         indexes.username_unicode.clear();
         indexes.session_id.clear();
+        indexes.ip.clear();
 
         self.len.set(0);
     }
@@ -111,7 +101,35 @@ impl<T> UserStore<T> {
 
 impl<T> UserStore<T>
 where
-    T: Deref<Target = BaseSession>,
+    T: SessionIpAddr,
+{
+    #[inline]
+    pub fn delete_inner(
+        &self,
+        indexes: &mut UserIndexes<T>,
+        user_id: &i32,
+        username: &str,
+        session_id: &Ulid,
+        username_unicode: Option<&str>,
+    ) -> Option<Arc<T>> {
+        let removed = indexes.remove_session(
+            user_id,
+            username,
+            session_id,
+            username_unicode,
+        );
+
+        if removed.is_some() {
+            self.len.sub(1);
+        }
+
+        removed
+    }
+}
+
+impl<T> UserStore<T>
+where
+    T: Deref<Target = BaseSession> + SessionIpAddr,
 {
     #[inline]
     pub async fn create(&self, item: Arc<T>) -> Arc<T> {
@@ -138,6 +156,26 @@ where
         item
     }
 
+    /// Rename the user's session and move it in the username indexes.
+    ///
+    /// Returns `None` if the user is not online, or if the new name is
+    /// already held by another session.
+    #[inline]
+    pub async fn rename(
+        &self,
+        user_id: i32,
+        username: String,
+        username_unicode: Option<String>,
+    ) -> Option<Arc<T>> {
+        let mut indexes = self.indexes.write().await;
+
+        let item = indexes.user_id.get(&user_id).cloned()?;
+
+        indexes
+            .rename_session(&item, username, username_unicode)
+            .then_some(item)
+    }
+
     #[inline]
     pub async fn delete(&self, query: &UserQuery) -> Option<Arc<T>> {
         let mut indexes = self.indexes.write().await;
@@ -190,6 +228,7 @@ pub struct UserIndexes<T> {
     pub user_id: BTreeMap<i32, Arc<T>>,
     pub username: HashMap<String, Arc<T>>,
     pub username_unicode: HashMap<String, Arc<T>>,
+    pub ip: HashMap<IpAddr, BTreeMap<Ulid, Arc<T>>>,
 }
 
 impl<T> UserIndexes<T> {
@@ -200,6 +239,7 @@ impl<T> UserIndexes<T> {
             user_id: BTreeMap::new(),
             username: HashMap::new(),
             username_unicode: HashMap::new(),
+            ip: HashMap::new(),
         }
     }
 
@@ -209,6 +249,7 @@ impl<T> UserIndexes<T> {
             user_id: BTreeMap::new(),
             username: HashMap::with_capacity(capacity),
             username_unicode: HashMap::with_capacity(capacity),
+            ip: HashMap::with_capacity(capacity),
         }
     }
 
@@ -218,17 +259,27 @@ impl<T> UserIndexes<T> {
         username: String,
         session_id: Ulid,
         username_unicode: Option<String>,
+        ip: Option<IpAddr>,
         item: Arc<T>,
     ) {
         let username_unicode =
             username_unicode.unwrap_or_else(|| username.to_owned());
 
+        if let Some(ip) = ip {
+            self.ip.entry(ip).or_default().insert(session_id, item.clone());
+        }
+
         self.session_id.insert(session_id, item.clone());
         self.user_id.insert(user_id, item.clone());
         self.username.insert(username, item.clone());
         self.username_unicode.insert(username_unicode, item);
     }
+}
 
+impl<T> UserIndexes<T>
+where
+    T: SessionIpAddr,
+{
     pub fn remove_session(
         &mut self,
         user_id: &i32,
@@ -238,6 +289,17 @@ impl<T> UserIndexes<T> {
     ) -> Option<Arc<T>> {
         let mut removed = None;
 
+        if let Some(ip) =
+            self.session_id.get(session_id).and_then(|s| s.ip_addr())
+        {
+            if let Some(sessions) = self.ip.get_mut(&ip) {
+                sessions.remove(session_id);
+                if sessions.is_empty() {
+                    self.ip.remove(&ip);
+                }
+            }
+        }
+
         if let Some(s) = self.user_id.remove(user_id) {
             removed = Some(s);
         }
@@ -262,9 +324,16 @@ impl<T> UserIndexes<T> {
 
 impl<T> UserIndexes<T>
 where
-    T: Deref<Target = BaseSession>,
+    T: Deref<Target = BaseSession> + SessionIpAddr,
 {
     pub fn add_session(&mut self, item: Arc<T>) {
+        if let Some(ip) = item.ip_addr() {
+            self.ip
+                .entry(ip)
+                .or_default()
+                .insert(item.session_id(), item.clone());
+        }
+
         self.session_id.insert(item.session_id(), item.clone());
         self.user_id.insert(item.user_id(), item.clone());
         self.username.insert(item.username(), item.clone());
@@ -273,6 +342,53 @@ where
             item,
         );
     }
+
+    /// Update the session's names and move its entries in the username
+    /// indexes, the old keys are removed.
+    ///
+    /// Returns `false` (nothing changed) if the new name is held by another
+    /// session.
+    pub fn rename_session(
+        &mut self,
+        item: &Arc<T>,
+        username: String,
+        username_unicode: Option<String>,
+    ) -> bool {
+        let new_username_unicode =
+            username_unicode.clone().unwrap_or_else(|| username.to_owned());
+
+        let held_by_other = |session: Option<&Arc<T>>| matches!(session, Some(s) if !Arc::ptr_eq(s, item));
+
+        if held_by_other(self.username.get(&username))
+            || held_by_other(self.username_unicode.get(&new_username_unicode))
+        {
+            return false;
+        }
+
+        let old_username = item.username();
+        let old_username_unicode =
+            item.username_unicode().unwrap_or_else(|| old_username.to_owned());
+
+        if matches!(self.username.get(&old_username), Some(s) if Arc::ptr_eq(s, item))
+        {
+            self.username.remove(&old_username);
+        }
+
+        if matches!(
+            self.username_unicode.get(&old_username_unicode),
+            Some(s) if Arc::ptr_eq(s, item)
+        ) {
+            self.username_unicode.remove(&old_username_unicode);
+        }
+
+        item.username.set(Arc::new(username.to_owned()));
+        item.username_unicode.set(username_unicode.map(Arc::new));
+
+        self.username.insert(username, item.clone());
+        self.username_unicode.insert(new_username_unicode, item.clone());
+
+        true
+    }
 }
 
 impl<T> Default for UserIndexes<T> {
@@ -288,3 +404,222 @@ impl<T> Deref for UserIndexes<T> {
         &self.user_id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct TestSession(BaseSession, Option<IpAddr>);
+
+    impl Deref for TestSession {
+        type Target = BaseSession;
+
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    impl SessionIpAddr for TestSession {
+        fn ip_addr(&self) -> Option<IpAddr> {
+            self.1
+        }
+    }
+
+    fn session(user_id: i32, username: &str) -> Arc<TestSession> {
+        session_with_ip(user_id, username, None)
+    }
+
+    fn session_with_ip(
+        user_id: i32,
+        username: &str,
+        ip: Option<&str>,
+    ) -> Arc<TestSession> {
+        Arc::new(TestSession(
+            BaseSession::new(user_id, username.to_owned(), None, 1),
+            ip.map(|ip| ip.parse().unwrap()),
+        ))
+    }
+
+    async fn user_ids_by_ip(
+        store: &UserStore<TestSession>,
+        ip: &str,
+    ) -> Vec<i32> {
+        let mut user_ids = store
+            .get_by_ip(&ip.parse().unwrap())
+            .await
+            .iter()
+            .map(|s| s.user_id)
+            .collect::<Vec<_>>();
+        user_ids.sort_unstable();
+        user_ids
+    }
+
+    #[tokio::test]
+    async fn test_ip_index() {
+        let store = UserStore::new();
+        store.create(session_with_ip(1, "a", Some("10.0.0.1"))).await;
+        store.create(session_with_ip(2, "b", Some("10.0.0.1"))).await;
+        store.create(session_with_ip(3, "c", Some("::1"))).await;
+        // sessions without ip are not indexed
+        store.create(session(4, "d")).await;
+
+        assert_eq!(user_ids_by_ip(&store, "10.0.0.1").await, vec![1, 2]);
+        assert_eq!(user_ids_by_ip(&store, "::1").await, vec![3]);
+        assert!(user_ids_by_ip(&store, "10.0.0.2").await.is_empty());
+
+        // the deleted sessions leave the index
+        store.delete(&UserQuery::UserId(1)).await.unwrap();
+        assert_eq!(user_ids_by_ip(&store, "10.0.0.1").await, vec![2]);
+
+        // a new login replaces the previous session and its ip
+        store.create(session_with_ip(2, "b", Some("::1"))).await;
+        assert!(user_ids_by_ip(&store, "10.0.0.1").await.is_empty());
+        assert!(!store
+            .read()
+            .await
+            .ip
+            .contains_key(&"10.0.0.1".parse().unwrap()));
+        assert_eq!(user_ids_by_ip(&store, "::1").await, vec![2, 3]);
+
+        store.clear().await;
+        assert!(store.read().await.ip.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rename_reindexes_usernames() {
+        let store = UserStore::new();
+        store.create(session(1, "old")).await;
+
+        let renamed = store.rename(1, "new".to_owned(), None).await.unwrap();
+        assert_eq!(renamed.username(), "new");
+
+        let old = UserQuery::Username("old".to_owned());
+        let new = UserQuery::Username("new".to_owned());
+        assert!(!store.exists(&old).await);
+        assert!(!crate::SessionFilter::session_is_target(&renamed, &old));
+        assert_eq!(store.get(&new).await.unwrap().user_id, 1);
+        assert!(
+            store.exists(&UserQuery::UsernameUnicode("new".to_owned())).await
+        );
+
+        // the renamed session can still be deleted by its new name
+        assert!(store.delete(&new).await.is_some());
+        assert_eq!(store.length(), 0);
+        assert!(store.read().await.username_unicode.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rename_conflict() {
+        let store = UserStore::new();
+        store.create(session(1, "a")).await;
+        store.create(session(2, "b")).await;
+
+        assert!(store.rename(1, "b".to_owned(), None).await.is_none());
+        assert!(store.rename(3, "c".to_owned(), None).await.is_none());
+        assert_eq!(
+            store
+                .get(&UserQuery::Username("b".to_owned()))
+                .await
+                .unwrap()
+                .user_id,
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_username_unicode() {
+        let store = UserStore::new();
+        store
+            .create(Arc::new(TestSession(
+                BaseSession::new(1, "a".to_owned(), Some("甲".to_owned()), 1),
+                None,
+            )))
+            .await;
+
+        store.rename(1, "b".to_owned(), Some("乙".to_owned())).await.unwrap();
+        assert!(
+            !store.exists(&UserQuery::UsernameUnicode("甲".to_owned())).await
+        );
+        assert_eq!(
+            store
+                .get(&UserQuery::UsernameUnicode("乙".to_owned()))
+                .await
+                .unwrap()
+                .user_id,
+            1
+        );
+
+        // renaming to the current name is not a conflict
+        assert!(store.rename(1, "b".to_owned(), None).await.is_some());
+        assert!(store.exists(&UserQuery::Username("b".to_owned())).await);
+        assert!(
+            !store.exists(&UserQuery::UsernameUnicode("乙".to_owned())).await
+        );
+        assert_eq!(store.read().await.username_unicode.len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_rename_and_lookup() {
+        const ROUNDS: usize = 500;
+        const NAMES: [&str; 2] = ["old", "new"];
+
+        let store = Arc::new(UserStore::new());
+        store.create(session(1, NAMES[0])).await;
+
+        let renamer = {
+            let store = store.clone();
+            tokio::spawn(async move {
+                for i in 1..=ROUNDS {
+                    store
+                        .rename(1, NAMES[i % 2].to_owned(), None)
+                        .await
+                        .expect("rename failed");
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+
+        let lookups = (0..3)
+            .map(|_| {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    for _ in 0..ROUNDS {
+                        let by_id = store.get(&UserQuery::UserId(1)).await;
+                        assert!(
+                            NAMES.contains(&by_id.unwrap().username().as_str())
+                        );
+
+                        let indexes = store.read().await;
+                        let found = NAMES.map(|name| {
+                            UserStore::get_inner(
+                                &indexes,
+                                &UserQuery::Username(name.to_owned()),
+                            )
+                            .map(|s| s.username() == name)
+                        });
+                        drop(indexes);
+
+                        // exactly one of the names resolves, to the session
+                        // holding that name
+                        match found {
+                            [Some(true), None] | [None, Some(true)] => {},
+                            found => panic!("half-updated index: {found:?}"),
+                        }
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        renamer.await.unwrap();
+        for lookup in lookups {
+            lookup.await.unwrap();
+        }
+
+        let last = UserQuery::Username(NAMES[ROUNDS % 2].to_owned());
+        assert_eq!(store.get(&last).await.unwrap().user_id, 1);
+        assert_eq!(store.read().await.username.len(), 1);
+        assert_eq!(store.read().await.username_unicode.len(), 1);
+    }
+}
@@ -16,7 +16,7 @@ mod peace {
 
             use self::raw_user_query::QueryType;
             use bitmask_enum::bitmask;
-            use peace_pb::ConvertError;
+            use peace_pb::{required_string, ConvertError};
             use peace_unique_id::Ulid;
             use std::str::FromStr;
 
@@ -26,6 +26,11 @@ mod peace {
                 UserId,
                 Username,
                 UsernameUnicode,
+                BanchoPrivileges,
+                SilenceEnd,
+                LastActive,
+                ConnectedAt,
+                ReconnectCount,
             }
 
             #[derive(
@@ -44,6 +49,30 @@ mod peace {
                 UsernameUnicode(String),
             }
 
+            impl UserQuery {
+                /// Parse a user query from arbitrary input (e.g. admin
+                /// tools or chat commands), in order of precedence:
+                ///
+                /// - all digits (fitting an `i32`): [`UserQuery::UserId`],
+                ///   so a numeric username can not be queried this way
+                /// - a valid ulid: [`UserQuery::SessionId`]
+                /// - anything else: [`UserQuery::Username`]
+                pub fn parse(s: &str) -> Self {
+                    let s = s.trim();
+
+                    if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+                        if let Ok(user_id) = s.parse() {
+                            return Self::UserId(user_id);
+                        }
+                    }
+
+                    match Ulid::from_str(s) {
+                        Ok(session_id) => Self::SessionId(session_id),
+                        Err(_) => Self::Username(s.to_owned()),
+                    }
+                }
+            }
+
             #[allow(clippy::from_over_into)]
             impl Into<String> for UserQuery {
                 fn into(self) -> String {
@@ -80,26 +109,26 @@ mod peace {
                 type Error = ConvertError;
 
                 fn try_from(raw: RawUserQuery) -> Result<Self, Self::Error> {
-                    match raw.query_type() {
-                        QueryType::SessionId => {
-                            Ok(Self::SessionId(Ulid::from_str(
-                                raw.string_val
-                                    .ok_or(ConvertError::InvalidParams)?
-                                    .as_str(),
-                            )?))
-                        },
+                    // `query_type()` would fall back to `SessionId`
+                    let query_type = QueryType::from_i32(raw.query_type)
+                        .ok_or(ConvertError::UnknownQueryType(
+                            raw.query_type,
+                        ))?;
+
+                    match query_type {
+                        QueryType::SessionId => Ok(Self::SessionId(
+                            Ulid::from_str(&required_string(raw.string_val)?)?,
+                        )),
                         QueryType::UserId => Ok(Self::UserId(
                             raw.int_val.ok_or(ConvertError::InvalidParams)?,
                         )),
-                        QueryType::Username => Ok(Self::Username(
-                            raw.string_val
-                                .ok_or(ConvertError::InvalidParams)?,
-                        )),
+                        QueryType::Username => {
+                            Ok(Self::Username(required_string(raw.string_val)?))
+                        },
                         QueryType::UsernameUnicode => {
-                            Ok(Self::UsernameUnicode(
-                                raw.string_val
-                                    .ok_or(ConvertError::InvalidParams)?,
-                            ))
+                            Ok(Self::UsernameUnicode(required_string(
+                                raw.string_val,
+                            )?))
                         },
                     }
                 }
@@ -138,3 +167,89 @@ mod peace {
 }
 
 pub use peace::services::bancho_state::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use peace_pb::ConvertError;
+    use peace_unique_id::Ulid;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_user_query_parse() {
+        let session_id = Ulid::new();
+
+        assert_eq!(UserQuery::parse("1000"), UserQuery::UserId(1000));
+        assert_eq!(
+            UserQuery::parse(&session_id.to_string()),
+            UserQuery::SessionId(session_id)
+        );
+        assert_eq!(
+            UserQuery::parse(" PurePeace "),
+            UserQuery::Username("PurePeace".into())
+        );
+
+        // numeric usernames are parsed as user ids
+        assert_eq!(UserQuery::parse("12345"), UserQuery::UserId(12345));
+        // numbers overflowing `i32` are not user ids
+        assert_eq!(
+            UserQuery::parse("99999999999"),
+            UserQuery::Username("99999999999".into())
+        );
+        // a 26 digits number is a valid ulid
+        assert_eq!(
+            UserQuery::parse("01234567890123456789012345"),
+            UserQuery::SessionId(
+                Ulid::from_str("01234567890123456789012345").unwrap()
+            )
+        );
+        assert_eq!(UserQuery::parse("-1"), UserQuery::Username("-1".into()));
+    }
+
+    #[test]
+    fn test_raw_user_query_try_from() {
+        use raw_user_query::QueryType;
+
+        let raw =
+            |query_type: i32, int_val, string_val: Option<&str>| RawUserQuery {
+                query_type,
+                int_val,
+                string_val: string_val.map(String::from),
+            };
+
+        let session_id = Ulid::new();
+        for query in [
+            UserQuery::SessionId(session_id),
+            UserQuery::UserId(1000),
+            UserQuery::Username("PurePeace".into()),
+            UserQuery::UsernameUnicode("ピュア".into()),
+        ] {
+            assert_eq!(
+                RawUserQuery::from(query.clone()).into_user_query().unwrap(),
+                query
+            );
+        }
+
+        for invalid in [
+            raw(QueryType::UserId as i32, None, Some("1000")),
+            raw(QueryType::Username as i32, None, None),
+            raw(QueryType::Username as i32, None, Some("")),
+            raw(QueryType::SessionId as i32, Some(1000), None),
+        ] {
+            assert!(matches!(
+                invalid.into_user_query(),
+                Err(ConvertError::InvalidParams)
+            ));
+        }
+
+        assert!(matches!(
+            raw(QueryType::SessionId as i32, None, Some("peace"))
+                .into_user_query(),
+            Err(ConvertError::DecodingError(_))
+        ));
+        assert!(matches!(
+            raw(42, Some(1000), None).into_user_query(),
+            Err(ConvertError::UnknownQueryType(42))
+        ));
+    }
+}
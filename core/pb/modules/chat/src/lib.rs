@@ -19,7 +19,7 @@ mod peace {
                 raw_chat_message_target::ChatTarget,
             };
             use pb_bancho_state::UserQuery;
-            use peace_pb::ConvertError;
+            use peace_pb::{required_string, ConvertError};
             use peace_unique_id::Ulid;
             use std::str::FromStr;
 
@@ -50,13 +50,17 @@ mod peace {
                 type Error = ConvertError;
 
                 fn try_from(raw: RawChannelQuery) -> Result<Self, Self::Error> {
-                    match raw.query_type() {
+                    let query_type = QueryType::from_i32(raw.query_type)
+                        .ok_or(ConvertError::UnknownQueryType(
+                            raw.query_type,
+                        ))?;
+
+                    match query_type {
                         QueryType::ChannelId => Ok(Self::ChannelId(
                             raw.int_val.ok_or(ConvertError::InvalidParams)?,
                         )),
                         QueryType::ChannelName => Ok(Self::ChannelName(
-                            raw.string_val
-                                .ok_or(ConvertError::InvalidParams)?,
+                            required_string(raw.string_val)?,
                         )),
                     }
                 }
@@ -108,7 +112,13 @@ mod peace {
                 fn try_from(
                     raw: RawChatMessageTarget,
                 ) -> Result<Self, Self::Error> {
-                    match raw.target_type() {
+                    // `target_type()` would fall back to `ChannelId`
+                    let target_type = ChatTarget::from_i32(raw.target_type)
+                        .ok_or(ConvertError::UnknownQueryType(
+                            raw.target_type,
+                        ))?;
+
+                    match target_type {
                         ChatTarget::ChannelId => {
                             Ok(Self::Channel(ChannelQuery::ChannelId(
                                 raw.int_val
@@ -117,40 +127,63 @@ mod peace {
                         },
                         ChatTarget::ChannelName => {
                             Ok(Self::Channel(ChannelQuery::ChannelName(
-                                raw.string_val
-                                    .ok_or(ConvertError::InvalidParams)?,
+                                required_string(raw.string_val)?,
                             )))
                         },
                         ChatTarget::SessionId => Ok(Self::User(
                             UserQuery::SessionId(Ulid::from_str(
-                                raw.string_val
-                                    .ok_or(ConvertError::InvalidParams)?
-                                    .as_str(),
+                                &required_string(raw.string_val)?,
                             )?),
                         )),
                         ChatTarget::UserId => {
                             Ok(Self::User(UserQuery::UserId(
                                 raw.int_val
-                                    .ok_or(ConvertError::InvalidParams)?
-                                    as i32,
+                                    .and_then(|id| i32::try_from(id).ok())
+                                    .ok_or(ConvertError::InvalidParams)?,
                             )))
                         },
                         ChatTarget::Username => {
                             Ok(Self::User(UserQuery::Username(
-                                raw.string_val
-                                    .ok_or(ConvertError::InvalidParams)?,
+                                required_string(raw.string_val)?,
                             )))
                         },
                         ChatTarget::UsernameUnicode => {
                             Ok(Self::User(UserQuery::UsernameUnicode(
-                                raw.string_val
-                                    .ok_or(ConvertError::InvalidParams)?,
+                                required_string(raw.string_val)?,
                             )))
                         },
                     }
                 }
             }
 
+            /// Only user targets convert, a channel target is a
+            /// [`ConvertError::FromChannelTarget`], so the caller can route
+            /// it to the channels instead of answering invalid argument.
+            impl TryFrom<ChatMessageTarget> for UserQuery {
+                type Error = ConvertError;
+
+                fn try_from(
+                    target: ChatMessageTarget,
+                ) -> Result<Self, Self::Error> {
+                    match target {
+                        ChatMessageTarget::User(query) => Ok(query),
+                        ChatMessageTarget::Channel(_) => {
+                            Err(ConvertError::FromChannelTarget)
+                        },
+                    }
+                }
+            }
+
+            impl TryFrom<RawChatMessageTarget> for UserQuery {
+                type Error = ConvertError;
+
+                fn try_from(
+                    raw: RawChatMessageTarget,
+                ) -> Result<Self, Self::Error> {
+                    ChatMessageTarget::try_from(raw)?.try_into()
+                }
+            }
+
             impl From<ChatMessageTarget> for RawChatMessageTarget {
                 fn from(target: ChatMessageTarget) -> Self {
                     match target {
@@ -205,3 +238,81 @@ mod peace {
 }
 
 pub use peace::services::chat::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pb_bancho_state::UserQuery;
+    use peace_pb::ConvertError;
+    use peace_unique_id::Ulid;
+    use raw_chat_message_target::ChatTarget;
+
+    fn raw(
+        target_type: ChatTarget,
+        int_val: Option<u64>,
+        string_val: Option<&str>,
+    ) -> RawChatMessageTarget {
+        RawChatMessageTarget {
+            target_type: target_type as i32,
+            int_val,
+            string_val: string_val.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_user_query_from_user_targets() {
+        let session_id = Ulid::new();
+
+        for query in [
+            UserQuery::SessionId(session_id),
+            UserQuery::UserId(1000),
+            UserQuery::Username("PurePeace".into()),
+            UserQuery::UsernameUnicode("ピュア".into()),
+        ] {
+            let raw = RawChatMessageTarget::from(ChatMessageTarget::User(
+                query.clone(),
+            ));
+            assert_eq!(UserQuery::try_from(raw).unwrap(), query);
+        }
+    }
+
+    #[test]
+    fn test_user_query_from_channel_targets() {
+        for target in [
+            raw(ChatTarget::ChannelId, Some(1), None),
+            raw(ChatTarget::ChannelName, None, Some("#osu")),
+        ] {
+            assert!(matches!(
+                UserQuery::try_from(target),
+                Err(ConvertError::FromChannelTarget)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_user_query_from_invalid_targets() {
+        for target in [
+            raw(ChatTarget::UserId, None, Some("1000")),
+            raw(ChatTarget::UserId, Some(u64::MAX), None),
+            raw(ChatTarget::Username, None, None),
+            raw(ChatTarget::Username, None, Some("")),
+            raw(ChatTarget::UsernameUnicode, Some(1000), None),
+            // an invalid channel target is not a channel target
+            raw(ChatTarget::ChannelName, None, Some("")),
+        ] {
+            assert!(matches!(
+                UserQuery::try_from(target),
+                Err(ConvertError::InvalidParams)
+            ));
+        }
+
+        assert!(matches!(
+            UserQuery::try_from(RawChatMessageTarget {
+                target_type: 42,
+                int_val: Some(1000),
+                string_val: None,
+            }),
+            Err(ConvertError::UnknownQueryType(42))
+        ));
+    }
+}
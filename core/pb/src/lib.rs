@@ -8,7 +8,9 @@ pub enum ConvertError {
     DecodingError(String),
     #[error("invalid params")]
     InvalidParams,
-    #[error("from Channel target is not support")]
+    #[error("unknown query type: {0}")]
+    UnknownQueryType(i32),
+    #[error("the target is a channel, not a user")]
     FromChannelTarget,
     #[error("TonicError: {0}")]
     TonicError(String),
@@ -20,6 +22,12 @@ impl peace_rpc_error::TonicError for ConvertError {
     }
 }
 
+/// A required string value of a raw query, missing or empty is invalid.
+#[inline]
+pub fn required_string(val: Option<String>) -> Result<String, ConvertError> {
+    val.filter(|s| !s.is_empty()).ok_or(ConvertError::InvalidParams)
+}
+
 impl From<DecodingError> for ConvertError {
     fn from(err: DecodingError) -> Self {
         Self::DecodingError(err.to_string())
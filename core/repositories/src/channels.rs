@@ -0,0 +1,363 @@
+use crate::CreateChannelError;
+use chrono::Utc;
+use peace_db::{
+    peace::{
+        entity::{
+            channel_privileges, channels, chat_messages, privileges,
+            sea_orm_active_enums::{ChannelHandleType, ChannelType},
+        },
+        Peace,
+    },
+    *,
+};
+use std::sync::Arc;
+
+pub type DynChannelsRepository = Arc<dyn ChannelsRepository + Send + Sync>;
+
+/// Priority of the privilege required to handle a channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelPrivilege {
+    pub channel_id: i64,
+    pub handle: ChannelHandleType,
+    pub required_priority: i16,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CreateChannel {
+    pub name: String,
+    pub description: Option<String>,
+    pub auto_join: bool,
+    /// Privilege required to join the channel.
+    pub read_privilege_id: Option<i64>,
+    /// Privilege required to send messages into the channel.
+    pub write_privilege_id: Option<i64>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CreateMessage {
+    pub sender_id: i32,
+    pub channel_id: i64,
+    pub content: String,
+    pub is_action: bool,
+}
+
+#[async_trait]
+pub trait ChannelsRepository {
+    async fn get_public_channels(&self) -> Result<Vec<channels::Model>, DbErr>;
+
+    async fn get_channel_privileges(
+        &self,
+    ) -> Result<Vec<ChannelPrivilege>, DbErr>;
+
+    /// Create a public channel with the next free id, returns the channel
+    /// and its privileges.
+    async fn create_channel(
+        &self,
+        channel: CreateChannel,
+    ) -> Result<(channels::Model, Vec<ChannelPrivilege>), CreateChannelError>;
+
+    /// Returns `false` if the channel not exists.
+    async fn delete_channel(&self, channel_id: i64) -> Result<bool, DbErr>;
+
+    /// Store a message sent into a channel, returns the id of the message.
+    async fn create_message(
+        &self,
+        message: CreateMessage,
+    ) -> Result<i64, DbErr>;
+
+    /// Hide the message from the history, returns `None` if the message not
+    /// exists or is already deleted.
+    async fn delete_message(
+        &self,
+        message_id: i64,
+    ) -> Result<Option<chat_messages::Model>, DbErr>;
+
+    /// Clear the content of the message, which stays in the history.
+    /// Returns `None` if the message not exists or is deleted.
+    async fn redact_message(
+        &self,
+        message_id: i64,
+    ) -> Result<Option<chat_messages::Model>, DbErr>;
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ChannelsRepositoryImpl {
+    pub conn: DbConnection<Peace>,
+}
+
+impl ChannelsRepositoryImpl {
+    pub fn new(conn: DbConnection<Peace>) -> ChannelsRepositoryImpl {
+        Self { conn }
+    }
+
+    pub fn into_service(self) -> DynChannelsRepository {
+        Arc::new(self) as DynChannelsRepository
+    }
+}
+
+#[async_trait]
+impl ChannelsRepository for ChannelsRepositoryImpl {
+    async fn get_public_channels(&self) -> Result<Vec<channels::Model>, DbErr> {
+        channels::Entity::find()
+            .filter(channels::Column::ChannelType.eq(ChannelType::Public))
+            .all(self.conn.as_ref())
+            .await
+    }
+
+    async fn get_channel_privileges(
+        &self,
+    ) -> Result<Vec<ChannelPrivilege>, DbErr> {
+        Ok(channel_privileges::Entity::find()
+            .find_also_related(privileges::Entity)
+            .all(self.conn.as_ref())
+            .await?
+            .into_iter()
+            .filter_map(|(channel_privilege, privilege)| {
+                Some(ChannelPrivilege {
+                    channel_id: channel_privilege.channel_id,
+                    handle: channel_privilege.handle,
+                    required_priority: privilege?.priority,
+                })
+            })
+            .collect())
+    }
+
+    async fn create_channel(
+        &self,
+        channel: CreateChannel,
+    ) -> Result<(channels::Model, Vec<ChannelPrivilege>), CreateChannelError>
+    {
+        let txn = self.conn.as_ref().begin().await?;
+
+        if channels::Entity::find()
+            .filter(channels::Column::Name.eq(channel.name.as_str()))
+            .one(&txn)
+            .await?
+            .is_some()
+        {
+            return Err(CreateChannelError::ChannelExists);
+        }
+
+        let mut required_privileges = Vec::new();
+        for (handle, privilege_id) in [
+            (ChannelHandleType::Join, channel.read_privilege_id),
+            (ChannelHandleType::SendMessage, channel.write_privilege_id),
+        ] {
+            if let Some(privilege_id) = privilege_id {
+                let privilege = privileges::Entity::find_by_id(privilege_id)
+                    .one(&txn)
+                    .await?
+                    .ok_or(CreateChannelError::PrivilegeNotExists(
+                        privilege_id,
+                    ))?;
+
+                required_privileges.push((handle, privilege));
+            }
+        }
+
+        let channel_id = channels::Entity::find()
+            .order_by_desc(channels::Column::Id)
+            .one(&txn)
+            .await?
+            .map(|last| last.id + 1)
+            .unwrap_or_default();
+
+        let model = channels::Entity::insert(channels::ActiveModel {
+            id: Set(channel_id),
+            channel_type: Set(ChannelType::Public),
+            name: Set(Some(channel.name)),
+            description: Set(channel.description),
+            icon: Set(None),
+            auto_join: Set(channel.auto_join),
+            creator_id: Set(None),
+        })
+        .exec_with_returning(&txn)
+        .await?;
+
+        let mut created_privileges =
+            Vec::with_capacity(required_privileges.len());
+        for (handle, privilege) in required_privileges {
+            channel_privileges::Entity::insert(
+                channel_privileges::ActiveModel {
+                    channel_id: Set(channel_id),
+                    handle: Set(handle.clone()),
+                    required_privilege_id: Set(privilege.id),
+                },
+            )
+            .exec_without_returning(&txn)
+            .await?;
+
+            created_privileges.push(ChannelPrivilege {
+                channel_id,
+                handle,
+                required_priority: privilege.priority,
+            });
+        }
+
+        txn.commit().await?;
+
+        Ok((model, created_privileges))
+    }
+
+    async fn delete_channel(&self, channel_id: i64) -> Result<bool, DbErr> {
+        let res = channels::Entity::delete_by_id(channel_id)
+            .exec(self.conn.as_ref())
+            .await?;
+
+        Ok(res.rows_affected > 0)
+    }
+
+    async fn create_message(
+        &self,
+        message: CreateMessage,
+    ) -> Result<i64, DbErr> {
+        Ok(chat_messages::Entity::insert(chat_messages::ActiveModel {
+            sender_id: Set(message.sender_id),
+            channel_id: Set(message.channel_id),
+            timestamp: Set(Utc::now().into()),
+            content_string: Set(message.content),
+            content_html: Set(None),
+            is_action: Set(message.is_action),
+            ..Default::default()
+        })
+        .exec(self.conn.as_ref())
+        .await?
+        .last_insert_id)
+    }
+
+    async fn delete_message(
+        &self,
+        message_id: i64,
+    ) -> Result<Option<chat_messages::Model>, DbErr> {
+        let message = match chat_messages::Entity::find_by_id(message_id)
+            .filter(chat_messages::Column::DeletedAt.is_null())
+            .one(self.conn.as_ref())
+            .await?
+        {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+
+        let mut model = message.into_active_model();
+        model.deleted_at = Set(Some(Utc::now().into()));
+
+        model.update(self.conn.as_ref()).await.map(Some)
+    }
+
+    async fn redact_message(
+        &self,
+        message_id: i64,
+    ) -> Result<Option<chat_messages::Model>, DbErr> {
+        let message = match chat_messages::Entity::find_by_id(message_id)
+            .filter(chat_messages::Column::DeletedAt.is_null())
+            .one(self.conn.as_ref())
+            .await?
+        {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+
+        let mut model = message.into_active_model();
+        model.content_string = Set(String::new());
+        model.content_html = Set(None);
+        model.redacted_at = Set(Some(Utc::now().into()));
+
+        model.update(self.conn.as_ref()).await.map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn repository() -> ChannelsRepositoryImpl {
+        let conn = Database::connect(
+            ConnectOptions::new("sqlite::memory:".to_owned())
+                .max_connections(1)
+                .to_owned(),
+        )
+        .await
+        .unwrap();
+
+        // only the messages table is created
+        conn.execute_unprepared("PRAGMA foreign_keys = OFF").await.unwrap();
+
+        let backend = conn.get_database_backend();
+        conn.execute(
+            backend.build(
+                &Schema::new(backend)
+                    .create_table_from_entity(chat_messages::Entity),
+            ),
+        )
+        .await
+        .unwrap();
+
+        ChannelsRepositoryImpl::new(DbConnection::from(conn))
+    }
+
+    async fn create_message(repository: &ChannelsRepositoryImpl) -> i64 {
+        repository
+            .create_message(CreateMessage {
+                sender_id: 1000,
+                channel_id: 1,
+                content: "hello".to_owned(),
+                is_action: false,
+            })
+            .await
+            .unwrap()
+    }
+
+    async fn find_message(
+        repository: &ChannelsRepositoryImpl,
+        message_id: i64,
+    ) -> chat_messages::Model {
+        chat_messages::Entity::find_by_id(message_id)
+            .one(repository.conn.as_ref())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_delete_message() {
+        let repository = repository().await;
+        let message_id = create_message(&repository).await;
+
+        let deleted =
+            repository.delete_message(message_id).await.unwrap().unwrap();
+        assert_eq!(deleted.id, message_id);
+
+        let stored = find_message(&repository, message_id).await;
+        assert!(stored.deleted_at.is_some());
+        assert!(stored.redacted_at.is_none());
+        assert_eq!(stored.content_string, "hello");
+
+        // already deleted messages can not be deleted nor redacted
+        assert!(repository.delete_message(message_id).await.unwrap().is_none());
+        assert!(repository.redact_message(message_id).await.unwrap().is_none());
+        assert!(repository
+            .delete_message(message_id + 1)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_redact_message() {
+        let repository = repository().await;
+        let message_id = create_message(&repository).await;
+
+        let redacted =
+            repository.redact_message(message_id).await.unwrap().unwrap();
+        assert_eq!(redacted.sender_id, 1000);
+
+        let stored = find_message(&repository, message_id).await;
+        assert!(stored.redacted_at.is_some());
+        assert!(stored.deleted_at.is_none());
+        assert!(stored.content_string.is_empty());
+        assert!(stored.content_html.is_none());
+
+        // redacted messages can still be deleted
+        assert!(repository.delete_message(message_id).await.unwrap().is_some());
+    }
+}
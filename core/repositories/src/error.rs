@@ -13,3 +13,64 @@ impl From<DbErr> for GetUserError {
         Self::DbErr(err.to_string())
     }
 }
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+pub enum CreateUserError {
+    #[error("username already exists")]
+    UsernameExists,
+    #[error("email already exists")]
+    EmailExists,
+    #[error("database err: {0}")]
+    DbErr(String),
+}
+
+impl From<DbErr> for CreateUserError {
+    /// The unique violations of the users table are mapped to the taken
+    /// field, the drivers only report them in the error message.
+    fn from(err: DbErr) -> Self {
+        let msg = err.to_string();
+
+        match unique_violation_key(&msg) {
+            Some(key) if key.contains("email") => Self::EmailExists,
+            Some(key) if key.contains("name") => Self::UsernameExists,
+            _ => Self::DbErr(msg),
+        }
+    }
+}
+
+/// The violated index or column of a unique violation message, e.g.
+/// `UNIQUE constraint failed: users.email` (sqlite), `duplicate key value
+/// violates unique constraint "IDX_users_email"` (postgres) or
+/// `Duplicate entry 'a' for key 'users.email'` (mysql).
+fn unique_violation_key(msg: &str) -> Option<String> {
+    const CONSTRAINT: &str = "constraint";
+    const FOR_KEY: &str = "for key";
+
+    let msg = msg.to_ascii_lowercase();
+    if !msg.contains("unique") && !msg.contains("duplicate") {
+        return None;
+    }
+
+    let start = match msg.rfind(CONSTRAINT) {
+        Some(pos) => pos + CONSTRAINT.len(),
+        None => msg.rfind(FOR_KEY)? + FOR_KEY.len(),
+    };
+
+    Some(msg[start..].to_owned())
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+pub enum CreateChannelError {
+    #[error("channel already exists")]
+    ChannelExists,
+    #[error("privilege {0} not exists")]
+    PrivilegeNotExists(i64),
+    #[error("database err: {0}")]
+    DbErr(String),
+}
+
+impl From<DbErr> for CreateChannelError {
+    fn from(err: DbErr) -> Self {
+        Self::DbErr(err.to_string())
+    }
+}
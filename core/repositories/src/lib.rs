@@ -2,7 +2,9 @@
 #[macro_use]
 extern crate peace_logs;
 
+pub mod channels;
 pub mod error;
+pub mod scores;
 pub mod users;
 
 pub use error::*;
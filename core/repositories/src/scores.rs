@@ -0,0 +1,762 @@
+use domain_bancho::GameMode;
+use peace_db::{
+    peace::{
+        entity::{
+            self, beatmaps, replay_views,
+            sea_orm_active_enums::{
+                PpVersion, ScoreGrade, ScoreStatus, ScoreVersion,
+            },
+        },
+        Peace,
+    },
+    prelude::{Decimal, Json},
+    *,
+};
+use peace_difficulty::{BeatmapLookup, DifficultyError};
+use std::{collections::HashMap, sync::Arc};
+
+pub type DynScoresRepository = Arc<dyn ScoresRepository + Send + Sync>;
+
+/// pp version written by the current calculator.
+pub const CURRENT_PP_VERSION: PpVersion = PpVersion::V2;
+
+/// Run `$body` with the `scores_*` and `score_pp_*` entities of the mode
+/// imported as `$scores` and `$score_pp`.
+macro_rules! with_score_tables {
+    ($mode:expr, $scores:ident, $score_pp:ident => $body:expr) => {
+        match $mode {
+            GameMode::Standard => {
+                #[allow(unused_imports)]
+                use entity::{
+                    score_pp_standard as $score_pp, scores_standard as $scores,
+                };
+                $body
+            },
+            GameMode::Taiko => {
+                #[allow(unused_imports)]
+                use entity::{
+                    score_pp_taiko as $score_pp, scores_taiko as $scores,
+                };
+                $body
+            },
+            GameMode::Fruits => {
+                #[allow(unused_imports)]
+                use entity::{
+                    score_pp_fruits as $score_pp, scores_fruits as $scores,
+                };
+                $body
+            },
+            GameMode::Mania => {
+                #[allow(unused_imports)]
+                use entity::{
+                    score_pp_mania as $score_pp, scores_mania as $scores,
+                };
+                $body
+            },
+            GameMode::StandardRelax => {
+                #[allow(unused_imports)]
+                use entity::{
+                    score_pp_standard_relax as $score_pp,
+                    scores_standard_relax as $scores,
+                };
+                $body
+            },
+            GameMode::TaikoRelax => {
+                #[allow(unused_imports)]
+                use entity::{
+                    score_pp_taiko_relax as $score_pp,
+                    scores_taiko_relax as $scores,
+                };
+                $body
+            },
+            GameMode::FruitsRelax => {
+                #[allow(unused_imports)]
+                use entity::{
+                    score_pp_fruits_relax as $score_pp,
+                    scores_fruits_relax as $scores,
+                };
+                $body
+            },
+            GameMode::StandardAutopilot => {
+                #[allow(unused_imports)]
+                use entity::{
+                    score_pp_standard_autopilot as $score_pp,
+                    scores_standard_autopilot as $scores,
+                };
+                $body
+            },
+            mode @ GameMode::StandardScoreV2 => Err(DbErr::Custom(format!(
+                "no score tables for mode {}",
+                mode.as_table()
+            ))),
+        }
+    };
+}
+
+/// Select the best scores of a beatmap on the leaderboard of `$filter`,
+/// `$scores` is the scores entity of the mode.
+macro_rules! leaderboard_select {
+    ($scores:ident, $map_md5:expr, $filter:expr) => {{
+        let select = $scores::Entity::find()
+            .filter($scores::Column::MapMd5.eq($map_md5))
+            .filter($scores::Column::Status.eq(ScoreStatus::High))
+            .filter($scores::Column::Invisible.eq(false));
+
+        match $filter {
+            LeaderboardFilter::Global => select,
+            LeaderboardFilter::Mods(mods) => {
+                select.filter($scores::Column::Mods.eq(*mods))
+            },
+            LeaderboardFilter::Country(country) => select.filter(
+                $scores::Column::UserId.in_subquery(
+                    sea_query::Query::select()
+                        .column(entity::users::Column::Id)
+                        .from(entity::users::Entity)
+                        .and_where(
+                            entity::users::Column::Country.eq(country.as_str()),
+                        )
+                        .to_owned(),
+                ),
+            ),
+            LeaderboardFilter::Friends(user_ids) => select.filter(
+                $scores::Column::UserId.is_in(user_ids.iter().copied()),
+            ),
+        }
+    }};
+}
+
+/// Which best scores of a beatmap are ranked on a leaderboard.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LeaderboardFilter {
+    Global,
+    /// Scores played with exactly these mods.
+    Mods(i32),
+    /// Scores of the users of the country, e.g. `AU`.
+    Country(String),
+    /// Scores of these users, the requester and their friends.
+    Friends(Vec<i32>),
+}
+
+/// A best score shown on a beatmap leaderboard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaderboardScore {
+    pub score_id: i64,
+    pub user_id: i32,
+    pub username: String,
+    pub score: i32,
+    pub combo: i32,
+    pub mods: i32,
+    pub n300: i32,
+    pub n100: i32,
+    pub n50: i32,
+    pub miss: i32,
+    pub geki: i32,
+    pub katu: i32,
+    pub perfect: bool,
+    /// Unix timestamp of the submission.
+    pub timestamp: i64,
+}
+
+/// A score with the hit results needed to calculate its pp.
+#[derive(Debug, Default, Clone)]
+pub struct PpScore {
+    pub score_id: i64,
+    /// `None` if the beatmap is not in the `beatmaps` table.
+    pub beatmap_id: Option<i32>,
+    pub mods: i32,
+    pub combo: i32,
+    pub n300: i32,
+    pub n100: i32,
+    pub n50: i32,
+    pub miss: i32,
+    pub geki: i32,
+    pub katu: i32,
+}
+
+/// The best score of a user on a beatmap (status `High`), `pp` is of
+/// [`CURRENT_PP_VERSION`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BestScore {
+    pub score_id: i64,
+    pub score: i32,
+    pub combo: i32,
+    pub accuracy: Decimal,
+    pub pp: Option<Decimal>,
+}
+
+/// A submitted score, its status is decided on insert.
+#[derive(Debug, Clone)]
+pub struct CreateScore {
+    pub user_id: i32,
+    pub score_md5: String,
+    pub map_md5: String,
+    pub score_version: ScoreVersion,
+    pub score: i32,
+    pub accuracy: Decimal,
+    pub combo: i32,
+    pub mods: i32,
+    pub n300: i32,
+    pub n100: i32,
+    pub n50: i32,
+    pub miss: i32,
+    pub geki: i32,
+    pub katu: i32,
+    pub playtime: i32,
+    pub perfect: bool,
+    pub passed: bool,
+    pub grade: ScoreGrade,
+    pub client_flags: i32,
+    pub client_version: String,
+    /// pp of [`CURRENT_PP_VERSION`] and its raw results.
+    pub pp: Option<(Decimal, Option<Json>)>,
+    /// Set when the score was flagged on submission, flagged scores stay
+    /// unverified.
+    pub confidence: Option<i32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreatedScore {
+    pub score_id: i64,
+    pub status: ScoreStatus,
+    /// The best score of the user before this one.
+    pub previous_best: Option<BestScore>,
+}
+
+#[async_trait]
+pub trait ScoresRepository {
+    async fn get_beatmap_by_md5(
+        &self,
+        md5: &str,
+    ) -> Result<Option<beatmaps::Model>, DbErr>;
+
+    async fn score_exists(
+        &self,
+        mode: GameMode,
+        score_md5: &str,
+    ) -> Result<bool, DbErr>;
+
+    async fn get_best_score(
+        &self,
+        mode: GameMode,
+        user_id: i32,
+        map_md5: &str,
+    ) -> Result<Option<BestScore>, DbErr>;
+
+    /// Best scores of the beatmap ordered by score, ties are won by the
+    /// first submitted score.
+    async fn get_leaderboard(
+        &self,
+        mode: GameMode,
+        map_md5: &str,
+        filter: &LeaderboardFilter,
+        limit: u64,
+    ) -> Result<Vec<LeaderboardScore>, DbErr>;
+
+    /// Best score of the user on the leaderboard and its rank.
+    async fn get_user_leaderboard_score(
+        &self,
+        mode: GameMode,
+        map_md5: &str,
+        filter: &LeaderboardFilter,
+        user_id: i32,
+    ) -> Result<Option<(LeaderboardScore, u64)>, DbErr>;
+
+    async fn get_score_md5(
+        &self,
+        mode: GameMode,
+        score_id: i64,
+    ) -> Result<Option<String>, DbErr>;
+
+    /// Count a view of the replay of the score, returns the views of the
+    /// replay.
+    async fn add_replay_view(&self, score_md5: &str) -> Result<i32, DbErr>;
+
+    /// Insert the score and its pp row. A passed score becomes the best
+    /// score of the user on the beatmap only if it is higher than the
+    /// previous best, which is then demoted to `Passed`. The submissions of
+    /// a user are serialized, so that two scores can not both become the
+    /// best one.
+    async fn create_score(
+        &self,
+        mode: GameMode,
+        score: CreateScore,
+    ) -> Result<CreatedScore, DbErr>;
+
+    /// Scores having a pp row of `pp_version`, ordered by score id,
+    /// starting after `after_score_id`.
+    async fn get_scores_with_pp_version(
+        &self,
+        mode: GameMode,
+        pp_version: PpVersion,
+        after_score_id: i64,
+        limit: u64,
+    ) -> Result<Vec<PpScore>, DbErr>;
+
+    /// Insert or replace the pp row of the score for `pp_version`, rows of
+    /// the other versions are left untouched.
+    async fn upsert_score_pp(
+        &self,
+        mode: GameMode,
+        score_id: i64,
+        pp_version: PpVersion,
+        pp: Decimal,
+        raw_pp: Option<Json>,
+    ) -> Result<(), DbErr>;
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ScoresRepositoryImpl {
+    pub conn: DbConnection<Peace>,
+}
+
+impl ScoresRepositoryImpl {
+    pub fn new(conn: DbConnection<Peace>) -> ScoresRepositoryImpl {
+        Self { conn }
+    }
+
+    pub fn into_service(self) -> DynScoresRepository {
+        Arc::new(self) as DynScoresRepository
+    }
+}
+
+#[async_trait]
+impl ScoresRepository for ScoresRepositoryImpl {
+    async fn get_beatmap_by_md5(
+        &self,
+        md5: &str,
+    ) -> Result<Option<beatmaps::Model>, DbErr> {
+        beatmaps::Entity::find()
+            .filter(beatmaps::Column::Md5.eq(md5))
+            .one(self.conn.as_ref())
+            .await
+    }
+
+    async fn score_exists(
+        &self,
+        mode: GameMode,
+        score_md5: &str,
+    ) -> Result<bool, DbErr> {
+        with_score_tables!(mode, scores, score_pp => {
+            scores::Entity::find()
+                .filter(scores::Column::ScoreMd5.eq(score_md5))
+                .count(self.conn.as_ref())
+                .await
+                .map(|count| count > 0)
+        })
+    }
+
+    async fn get_best_score(
+        &self,
+        mode: GameMode,
+        user_id: i32,
+        map_md5: &str,
+    ) -> Result<Option<BestScore>, DbErr> {
+        get_best_score(self.conn.as_ref(), mode, user_id, map_md5).await
+    }
+
+    async fn get_leaderboard(
+        &self,
+        mode: GameMode,
+        map_md5: &str,
+        filter: &LeaderboardFilter,
+        limit: u64,
+    ) -> Result<Vec<LeaderboardScore>, DbErr> {
+        with_score_tables!(mode, scores, score_pp => {
+            leaderboard_select!(scores, map_md5, filter)
+                .order_by_desc(scores::Column::Score)
+                .order_by_asc(scores::Column::Id)
+                .limit(limit)
+                .find_also_related(entity::users::Entity)
+                .all(self.conn.as_ref())
+                .await
+                .map(|rows| {
+                    rows.into_iter()
+                        .filter_map(|(s, user)| {
+                            Some(LeaderboardScore {
+                                score_id: s.id,
+                                user_id: s.user_id,
+                                username: user?.name,
+                                score: s.score,
+                                combo: s.combo,
+                                mods: s.mods,
+                                n300: s.n300,
+                                n100: s.n100,
+                                n50: s.n50,
+                                miss: s.miss,
+                                geki: s.geki,
+                                katu: s.katu,
+                                perfect: s.perfect,
+                                timestamp: s.create_at.timestamp(),
+                            })
+                        })
+                        .collect()
+                })
+        })
+    }
+
+    async fn get_user_leaderboard_score(
+        &self,
+        mode: GameMode,
+        map_md5: &str,
+        filter: &LeaderboardFilter,
+        user_id: i32,
+    ) -> Result<Option<(LeaderboardScore, u64)>, DbErr> {
+        with_score_tables!(mode, scores, score_pp => {
+            let (s, user) = match leaderboard_select!(scores, map_md5, filter)
+                .filter(scores::Column::UserId.eq(user_id))
+                .order_by_desc(scores::Column::Score)
+                .find_also_related(entity::users::Entity)
+                .one(self.conn.as_ref())
+                .await?
+            {
+                Some((s, Some(user))) => (s, user),
+                _ => return Ok(None),
+            };
+
+            // Same order as the leaderboard
+            let higher = leaderboard_select!(scores, map_md5, filter)
+                .filter(
+                    Condition::any()
+                        .add(scores::Column::Score.gt(s.score))
+                        .add(
+                            Condition::all()
+                                .add(scores::Column::Score.eq(s.score))
+                                .add(scores::Column::Id.lt(s.id)),
+                        ),
+                )
+                .count(self.conn.as_ref())
+                .await?;
+
+            Ok(Some((
+                LeaderboardScore {
+                    score_id: s.id,
+                    user_id: s.user_id,
+                    username: user.name,
+                    score: s.score,
+                    combo: s.combo,
+                    mods: s.mods,
+                    n300: s.n300,
+                    n100: s.n100,
+                    n50: s.n50,
+                    miss: s.miss,
+                    geki: s.geki,
+                    katu: s.katu,
+                    perfect: s.perfect,
+                    timestamp: s.create_at.timestamp(),
+                },
+                higher + 1,
+            )))
+        })
+    }
+
+    async fn get_score_md5(
+        &self,
+        mode: GameMode,
+        score_id: i64,
+    ) -> Result<Option<String>, DbErr> {
+        with_score_tables!(mode, scores, score_pp => {
+            scores::Entity::find_by_id(score_id)
+                .one(self.conn.as_ref())
+                .await
+                .map(|score| score.map(|s| s.score_md5))
+        })
+    }
+
+    async fn add_replay_view(&self, score_md5: &str) -> Result<i32, DbErr> {
+        replay_views::Entity::insert(replay_views::ActiveModel {
+            score_md5: Set(score_md5.to_owned()),
+            views: Set(1),
+            ..Default::default()
+        })
+        .on_conflict(
+            sea_query::OnConflict::column(replay_views::Column::ScoreMd5)
+                .value(
+                    replay_views::Column::Views,
+                    sea_query::Expr::col((
+                        replay_views::Entity,
+                        replay_views::Column::Views,
+                    ))
+                    .add(1),
+                )
+                .value(
+                    replay_views::Column::UpdatedAt,
+                    sea_query::Expr::current_timestamp(),
+                )
+                .to_owned(),
+        )
+        .exec_with_returning(self.conn.as_ref())
+        .await
+        .map(|row| row.views)
+    }
+
+    async fn create_score(
+        &self,
+        mode: GameMode,
+        score: CreateScore,
+    ) -> Result<CreatedScore, DbErr> {
+        let txn = self.conn.as_ref().begin().await?;
+
+        // Concurrent submissions of the user wait here until this one is
+        // committed, so that they compare against the updated best score
+        lock_user(score.user_id).one(&txn).await?;
+
+        let previous_best =
+            get_best_score(&txn, mode, score.user_id, &score.map_md5).await?;
+
+        let status = if !score.passed {
+            ScoreStatus::Failed
+        } else if previous_best
+            .as_ref()
+            .map(|best| score.score > best.score)
+            .unwrap_or(true)
+        {
+            ScoreStatus::High
+        } else {
+            ScoreStatus::Passed
+        };
+
+        let score_id = with_score_tables!(mode, scores, score_pp => {
+            if let (ScoreStatus::High, Some(best)) = (&status, &previous_best)
+            {
+                scores::Entity::update_many()
+                    .col_expr(
+                        scores::Column::Status,
+                        ScoreStatus::Passed.as_enum(),
+                    )
+                    .filter(scores::Column::Id.eq(best.score_id))
+                    .exec(&txn)
+                    .await?;
+            }
+
+            let score_id = scores::Entity::insert(scores::ActiveModel {
+                user_id: Set(score.user_id),
+                score_md5: Set(score.score_md5),
+                map_md5: Set(score.map_md5),
+                score_version: Set(score.score_version),
+                score: Set(score.score),
+                accuracy: Set(score.accuracy),
+                combo: Set(score.combo),
+                mods: Set(score.mods),
+                n300: Set(score.n300),
+                n100: Set(score.n100),
+                n50: Set(score.n50),
+                miss: Set(score.miss),
+                geki: Set(score.geki),
+                katu: Set(score.katu),
+                playtime: Set(score.playtime),
+                perfect: Set(score.perfect),
+                status: Set(status.clone()),
+                grade: Set(score.grade),
+                client_flags: Set(score.client_flags),
+                client_version: Set(score.client_version),
+                confidence: Set(score.confidence),
+                verified: Set(false),
+                ..Default::default()
+            })
+            .exec(&txn)
+            .await?
+            .last_insert_id;
+
+            if let Some((pp, raw_pp)) = score.pp {
+                score_pp::Entity::insert(score_pp::ActiveModel {
+                    score_id: Set(score_id),
+                    pp_version: Set(CURRENT_PP_VERSION),
+                    pp: Set(pp),
+                    raw_pp: Set(raw_pp),
+                })
+                .exec_without_returning(&txn)
+                .await?;
+            }
+
+            Ok(score_id)
+        })?;
+
+        txn.commit().await?;
+
+        Ok(CreatedScore { score_id, status, previous_best })
+    }
+
+    async fn get_scores_with_pp_version(
+        &self,
+        mode: GameMode,
+        pp_version: PpVersion,
+        after_score_id: i64,
+        limit: u64,
+    ) -> Result<Vec<PpScore>, DbErr> {
+        let scores = with_score_tables!(mode, scores, score_pp => {
+            score_pp::Entity::find()
+                .filter(score_pp::Column::PpVersion.eq(pp_version))
+                .filter(score_pp::Column::ScoreId.gt(after_score_id))
+                .order_by_asc(score_pp::Column::ScoreId)
+                .limit(limit)
+                .find_also_related(scores::Entity)
+                .all(self.conn.as_ref())
+                .await
+                .map(|rows| {
+                    rows.into_iter()
+                        .filter_map(|(_, score)| score)
+                        .map(|s| {
+                            (
+                                s.map_md5,
+                                PpScore {
+                                    score_id: s.id,
+                                    beatmap_id: None,
+                                    mods: s.mods,
+                                    combo: s.combo,
+                                    n300: s.n300,
+                                    n100: s.n100,
+                                    n50: s.n50,
+                                    miss: s.miss,
+                                    geki: s.geki,
+                                    katu: s.katu,
+                                },
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+        })?;
+
+        let beatmap_ids = beatmaps::Entity::find()
+            .filter(
+                beatmaps::Column::Md5
+                    .is_in(scores.iter().map(|(md5, _)| md5.as_str())),
+            )
+            .all(self.conn.as_ref())
+            .await?
+            .into_iter()
+            .map(|b| (b.md5, b.bid))
+            .collect::<HashMap<_, _>>();
+
+        Ok(scores
+            .into_iter()
+            .map(|(md5, score)| PpScore {
+                beatmap_id: beatmap_ids.get(&md5).copied(),
+                ..score
+            })
+            .collect())
+    }
+
+    async fn upsert_score_pp(
+        &self,
+        mode: GameMode,
+        score_id: i64,
+        pp_version: PpVersion,
+        pp: Decimal,
+        raw_pp: Option<Json>,
+    ) -> Result<(), DbErr> {
+        with_score_tables!(mode, scores, score_pp => {
+            score_pp::Entity::insert(score_pp::ActiveModel {
+                score_id: Set(score_id),
+                pp_version: Set(pp_version),
+                pp: Set(pp),
+                raw_pp: Set(raw_pp),
+            })
+            .on_conflict(
+                sea_query::OnConflict::columns([
+                    score_pp::Column::ScoreId,
+                    score_pp::Column::PpVersion,
+                ])
+                .update_columns([score_pp::Column::Pp, score_pp::Column::RawPp])
+                .to_owned(),
+            )
+            .exec_without_returning(self.conn.as_ref())
+            .await
+            .map(|_| ())
+        })
+    }
+}
+
+#[async_trait]
+impl BeatmapLookup for ScoresRepositoryImpl {
+    async fn beatmap_exists(
+        &self,
+        beatmap_id: i32,
+    ) -> Result<bool, DifficultyError> {
+        beatmaps::Entity::find_by_id(beatmap_id)
+            .count(self.conn.as_ref())
+            .await
+            .map(|count| count > 0)
+            .map_err(|err| DifficultyError::LookupFailed(err.to_string()))
+    }
+}
+
+/// Lock the row of the user until the end of the transaction.
+#[inline]
+fn lock_user(user_id: i32) -> Select<entity::users::Entity> {
+    entity::users::Entity::find_by_id(user_id).lock_exclusive()
+}
+
+async fn get_best_score<C: ConnectionTrait>(
+    conn: &C,
+    mode: GameMode,
+    user_id: i32,
+    map_md5: &str,
+) -> Result<Option<BestScore>, DbErr> {
+    with_score_tables!(mode, scores, score_pp => {
+        let best = match scores::Entity::find()
+            .filter(scores::Column::UserId.eq(user_id))
+            .filter(scores::Column::MapMd5.eq(map_md5))
+            .filter(scores::Column::Status.eq(ScoreStatus::High))
+            .order_by_desc(scores::Column::Score)
+            .one(conn)
+            .await?
+        {
+            Some(best) => best,
+            None => return Ok(None),
+        };
+
+        let pp = score_pp::Entity::find_by_id((best.id, CURRENT_PP_VERSION))
+            .one(conn)
+            .await?
+            .map(|row| row.pp);
+
+        Ok(Some(BestScore {
+            score_id: best.id,
+            score: best.score,
+            combo: best.combo,
+            accuracy: best.accuracy,
+            pp,
+        }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entity::scores_standard as scores;
+
+    fn leaderboard_sql(filter: &LeaderboardFilter) -> String {
+        leaderboard_select!(scores, "1cf5b2c2edfafd055536d2cefcb89c0e", filter)
+            .build(DbBackend::Postgres)
+            .to_string()
+    }
+
+    #[test]
+    fn test_leaderboard_filters() {
+        let global = leaderboard_sql(&LeaderboardFilter::Global);
+        assert!(global.contains(
+            r#""scores_standard"."map_md5" = '1cf5b2c2edfafd055536d2cefcb89c0e'"#
+        ));
+        assert!(global.contains(r#""scores_standard"."invisible" = FALSE"#));
+        assert!(!global.contains(r#""scores_standard"."user_id""#));
+        assert!(!global.contains(r#""scores_standard"."mods" ="#));
+
+        let mods = leaderboard_sql(&LeaderboardFilter::Mods(72));
+        assert!(mods.contains(r#""scores_standard"."mods" = 72"#));
+
+        let country = leaderboard_sql(&LeaderboardFilter::Country("AU".into()));
+        assert!(country.contains(r#""scores_standard"."user_id" IN (SELECT"#));
+        assert!(country.contains(r#""users"."country" = 'AU'"#));
+
+        let friends = leaderboard_sql(&LeaderboardFilter::Friends(vec![1, 2]));
+        assert!(friends.contains(r#""scores_standard"."user_id" IN (1, 2)"#));
+    }
+
+    #[test]
+    fn test_lock_user() {
+        let sql = lock_user(1000).build(DbBackend::Postgres).to_string();
+
+        assert!(sql.contains(r#""users"."id" = 1000"#));
+        assert!(sql.ends_with("FOR UPDATE"));
+    }
+}
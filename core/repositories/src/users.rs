@@ -1,13 +1,45 @@
-use crate::GetUserError;
+use crate::{CreateUserError, GetUserError};
+use chrono::{DateTime, Utc};
+use domain_bancho::{BanchoPrivileges, ClientHashes};
 use domain_users::{CreateUser, UsernameAscii, UsernameSafe, UsernameUnicode};
 use peace_db::{
-    peace::{entity::users, Peace},
+    peace::{
+        entity::{
+            bancho_client_hardware_records, favourite_beatmaps, followers,
+            privileges, user_login_records, user_privileges, user_silences,
+            users,
+        },
+        Peace,
+    },
     *,
 };
-use std::sync::Arc;
+use std::{collections::BTreeSet, sync::Arc};
 
 pub type DynUsersRepository = Arc<dyn UsersRepository + Send + Sync>;
 
+#[derive(Debug, Default, Clone)]
+pub struct CreateLoginRecord {
+    pub user_id: i32,
+    pub ip: String,
+    pub client_version: String,
+    pub country_code: Option<String>,
+    pub city: Option<String>,
+}
+
+impl From<CreateLoginRecord> for user_login_records::ActiveModel {
+    /// The id and the creation time are set by the database.
+    fn from(record: CreateLoginRecord) -> Self {
+        Self {
+            user_id: Set(record.user_id),
+            ip: Set(record.ip),
+            client_version: Set(record.client_version),
+            country_code: Set(record.country_code),
+            city: Set(record.city),
+            ..Default::default()
+        }
+    }
+}
+
 #[async_trait]
 pub trait UsersRepository {
     async fn get_user(
@@ -32,10 +64,15 @@ pub trait UsersRepository {
         username_unicode: &str,
     ) -> Result<users::Model, GetUserError>;
 
+    async fn get_user_by_email(
+        &self,
+        email: &str,
+    ) -> Result<users::Model, GetUserError>;
+
     async fn create_user(
         &self,
         creat_user: CreateUser,
-    ) -> Result<InsertResult<users::ActiveModel>, DbErr>;
+    ) -> Result<InsertResult<users::ActiveModel>, CreateUserError>;
 
     async fn change_user_password(
         &self,
@@ -43,7 +80,72 @@ pub trait UsersRepository {
         username: Option<UsernameSafe>,
         username_unicode: Option<UsernameSafe>,
         password: String,
-    ) -> Result<InsertResult<users::ActiveModel>, DbErr>;
+    ) -> Result<users::Model, DbErr>;
+
+    async fn get_favourite_beatmapsets(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<i32>, DbErr>;
+
+    /// Ids of the users followed by the user.
+    async fn get_friend_ids(&self, user_id: i32) -> Result<Vec<i32>, DbErr>;
+
+    /// Priority of the privilege granted to the user, if any.
+    async fn get_privilege_priority(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<i16>, DbErr>;
+
+    /// Bancho privileges of the privilege granted to the user, normal users
+    /// have no privilege granted.
+    async fn get_bancho_privileges(
+        &self,
+        user_id: i32,
+    ) -> Result<BanchoPrivileges, DbErr>;
+
+    /// Returns `false` if the beatmapset is already a favourite of the user.
+    async fn add_favourite_beatmapset(
+        &self,
+        user_id: i32,
+        beatmapset_id: i32,
+    ) -> Result<bool, DbErr>;
+
+    /// Count a login of the user from the hardware, the hashes should be
+    /// salted (see [`ClientHashes::salted`]).
+    async fn record_client_hardware(
+        &self,
+        user_id: i32,
+        time_offset: i32,
+        hashes: ClientHashes,
+    ) -> Result<(), DbErr>;
+
+    /// Other users who logged in with any of the adapters hash, uninstall
+    /// id or disk id used by the user.
+    async fn get_users_sharing_client_hardware(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<i32>, DbErr>;
+
+    /// Returns the id of the login record.
+    async fn create_login_record(
+        &self,
+        record: CreateLoginRecord,
+    ) -> Result<i64, DbErr>;
+
+    /// End of the last silence of the user, it may be already expired.
+    async fn get_silence_end(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<DateTime<Utc>>, DbErr>;
+
+    /// Replace the silence of the user, silencing until a past time lifts
+    /// it.
+    async fn silence_user(
+        &self,
+        user_id: i32,
+        silence_end: DateTime<Utc>,
+        reason: Option<String>,
+    ) -> Result<(), DbErr>;
 }
 
 #[derive(Debug, Default, Clone)]
@@ -135,10 +237,22 @@ impl UsersRepository for UsersRepositoryImpl {
             .ok_or(GetUserError::UserNotExists)
     }
 
+    async fn get_user_by_email(
+        &self,
+        email: &str,
+    ) -> Result<users::Model, GetUserError> {
+        users::Entity::find()
+            .filter(users::Column::Email.eq(email.trim().to_ascii_lowercase()))
+            .one(self.conn.as_ref())
+            .await
+            .map_err(GetUserError::from)?
+            .ok_or(GetUserError::UserNotExists)
+    }
+
     async fn create_user(
         &self,
         creat_user: CreateUser,
-    ) -> Result<InsertResult<users::ActiveModel>, DbErr> {
+    ) -> Result<InsertResult<users::ActiveModel>, CreateUserError> {
         users::Entity::insert(users::ActiveModel {
             name: Set(creat_user.name.as_ref().to_owned()),
             name_safe: Set(creat_user.name.safe_name().into()),
@@ -156,6 +270,7 @@ impl UsersRepository for UsersRepositoryImpl {
         })
         .exec(self.conn.as_ref())
         .await
+        .map_err(CreateUserError::from)
     }
 
     async fn change_user_password(
@@ -164,7 +279,7 @@ impl UsersRepository for UsersRepositoryImpl {
         username: Option<UsernameSafe>,
         username_unicode: Option<UsernameSafe>,
         password: String,
-    ) -> Result<InsertResult<users::ActiveModel>, DbErr> {
+    ) -> Result<users::Model, DbErr> {
         let user = users::Entity::find()
             .filter(
                 Condition::any()
@@ -186,21 +301,325 @@ impl UsersRepository for UsersRepositoryImpl {
 
         model.password = ActiveValue::Set(password);
 
-        model.update(self.conn.as_ref()).await?;
+        model.update(self.conn.as_ref()).await
+    }
+
+    async fn get_favourite_beatmapsets(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<i32>, DbErr> {
+        Ok(favourite_beatmaps::Entity::find()
+            .filter(favourite_beatmaps::Column::UserId.eq(user_id))
+            .order_by_asc(favourite_beatmaps::Column::CreatedAt)
+            .all(self.conn.as_ref())
+            .await?
+            .into_iter()
+            .map(|favourite| favourite.beatmapset_id)
+            .collect())
+    }
+
+    async fn get_friend_ids(&self, user_id: i32) -> Result<Vec<i32>, DbErr> {
+        Ok(followers::Entity::find()
+            .filter(followers::Column::UserId.eq(user_id))
+            .all(self.conn.as_ref())
+            .await?
+            .into_iter()
+            .map(|follower| follower.follow_id)
+            .collect())
+    }
+
+    async fn get_privilege_priority(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<i16>, DbErr> {
+        Ok(user_privileges::Entity::find_by_id(user_id)
+            .find_also_related(privileges::Entity)
+            .one(self.conn.as_ref())
+            .await?
+            .and_then(|(_, privilege)| privilege)
+            .map(|privilege| privilege.priority))
+    }
+
+    async fn get_bancho_privileges(
+        &self,
+        user_id: i32,
+    ) -> Result<BanchoPrivileges, DbErr> {
+        Ok(user_privileges::Entity::find_by_id(user_id)
+            .find_also_related(privileges::Entity)
+            .one(self.conn.as_ref())
+            .await?
+            .and_then(|(_, privilege)| privilege)
+            .map(|privilege| {
+                BanchoPrivileges::from_privilege_name(&privilege.name)
+            })
+            .unwrap_or_default())
+    }
+
+    async fn add_favourite_beatmapset(
+        &self,
+        user_id: i32,
+        beatmapset_id: i32,
+    ) -> Result<bool, DbErr> {
+        let inserted = favourite_beatmaps::Entity::insert(
+            favourite_beatmaps::ActiveModel {
+                user_id: Set(user_id),
+                beatmapset_id: Set(beatmapset_id),
+                ..Default::default()
+            },
+        )
+        .on_conflict(
+            sea_query::OnConflict::columns([
+                favourite_beatmaps::Column::UserId,
+                favourite_beatmaps::Column::BeatmapsetId,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .exec_without_returning(self.conn.as_ref())
+        .await?;
 
-        todo!()
+        Ok(inserted > 0)
+    }
+
+    async fn record_client_hardware(
+        &self,
+        user_id: i32,
+        time_offset: i32,
+        hashes: ClientHashes,
+    ) -> Result<(), DbErr> {
+        use bancho_client_hardware_records::{ActiveModel, Column, Entity};
+
+        Entity::insert(ActiveModel {
+            user_id: Set(user_id),
+            time_offset: Set(time_offset),
+            path_hash: Set(hashes.path_hash),
+            adapters: Set(hashes.adapters),
+            adapters_hash: Set(hashes.adapters_hash),
+            uninstall_id: Set(hashes.uninstall_id),
+            disk_id: Set(hashes.disk_id),
+            ..Default::default()
+        })
+        .on_conflict(
+            sea_query::OnConflict::columns([
+                Column::UserId,
+                Column::PathHash,
+                Column::AdaptersHash,
+                Column::UninstallId,
+                Column::DiskId,
+            ])
+            .update_column(Column::TimeOffset)
+            .value(
+                Column::UsedTimes,
+                sea_query::Expr::col((Entity, Column::UsedTimes)).add(1),
+            )
+            .value(Column::UpdatedAt, sea_query::Expr::current_timestamp())
+            .to_owned(),
+        )
+        .exec_without_returning(self.conn.as_ref())
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_users_sharing_client_hardware(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<i32>, DbErr> {
+        use bancho_client_hardware_records::{Column, Entity};
+
+        let records = Entity::find()
+            .filter(Column::UserId.eq(user_id))
+            .all(self.conn.as_ref())
+            .await?;
+
+        if records.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let shared_hardware =
+            records.into_iter().fold(Condition::any(), |cond, record| {
+                cond.add(Column::AdaptersHash.eq(record.adapters_hash))
+                    .add(Column::UninstallId.eq(record.uninstall_id))
+                    .add(Column::DiskId.eq(record.disk_id))
+            });
+
+        Ok(Entity::find()
+            .filter(
+                Condition::all()
+                    .add(Column::UserId.ne(user_id))
+                    .add(shared_hardware),
+            )
+            .all(self.conn.as_ref())
+            .await?
+            .into_iter()
+            .map(|record| record.user_id)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect())
+    }
+
+    async fn create_login_record(
+        &self,
+        record: CreateLoginRecord,
+    ) -> Result<i64, DbErr> {
+        Ok(user_login_records::Entity::insert(
+            user_login_records::ActiveModel::from(record),
+        )
+        .exec(self.conn.as_ref())
+        .await?
+        .last_insert_id)
+    }
+
+    async fn get_silence_end(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<DateTime<Utc>>, DbErr> {
+        Ok(user_silences::Entity::find_by_id(user_id)
+            .one(self.conn.as_ref())
+            .await?
+            .map(|silence| silence.silence_end.with_timezone(&Utc)))
+    }
+
+    async fn silence_user(
+        &self,
+        user_id: i32,
+        silence_end: DateTime<Utc>,
+        reason: Option<String>,
+    ) -> Result<(), DbErr> {
+        use user_silences::{ActiveModel, Column, Entity};
+
+        Entity::insert(ActiveModel {
+            user_id: Set(user_id),
+            silence_end: Set(silence_end.into()),
+            reason: Set(reason),
+            ..Default::default()
+        })
+        .on_conflict(
+            sea_query::OnConflict::column(Column::UserId)
+                .update_columns([Column::SilenceEnd, Column::Reason])
+                .value(Column::CreatedAt, sea_query::Expr::current_timestamp())
+                .to_owned(),
+        )
+        .exec_without_returning(self.conn.as_ref())
+        .await?;
+
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
-    use domain_users::UsernameAscii;
+    use domain_users::{CreateUser, Email, Password, UsernameAscii};
     use peace_db::{
-        peace::entity::{user_privileges, users},
+        peace::entity::{user_login_records, user_privileges, users},
         *,
     };
 
-    use crate::users::{UsersRepository, UsersRepositoryImpl};
+    use crate::{
+        users::{CreateLoginRecord, UsersRepository, UsersRepositoryImpl},
+        CreateUserError,
+    };
+
+    #[test]
+    fn test_login_record_active_model() {
+        let model = user_login_records::ActiveModel::from(CreateLoginRecord {
+            user_id: 1000,
+            ip: "1.1.1.1".to_owned(),
+            client_version: "b20230326".to_owned(),
+            country_code: Some("AU".to_owned()),
+            city: None,
+        });
+
+        assert_eq!(model.user_id, Set(1000));
+        assert_eq!(model.ip, Set("1.1.1.1".to_owned()));
+        assert_eq!(model.client_version, Set("b20230326".to_owned()));
+        assert_eq!(model.country_code, Set(Some("AU".to_owned())));
+        assert_eq!(model.city, Set(None));
+        // set by the database
+        assert!(model.id.is_not_set());
+        assert!(model.created_at.is_not_set());
+    }
+
+    fn create_user(name: &str, email: &str) -> CreateUser {
+        CreateUser {
+            name: UsernameAscii::new(name).unwrap(),
+            name_unicode: None,
+            password: Password::from_hashed("hashed".to_owned()),
+            email: Email::new(email).unwrap(),
+            country: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_user_taken() {
+        let conn = Database::connect(
+            ConnectOptions::new("sqlite::memory:".to_owned())
+                .max_connections(1)
+                .to_owned(),
+        )
+        .await
+        .unwrap();
+
+        // the table of the migration, the timestamps have defaults
+        let backend = conn.get_database_backend();
+        conn.execute(backend.build(
+            &peace_db::peace::migration::versions::init_tables::users::create(),
+        ))
+        .await
+        .unwrap();
+
+        let repository = UsersRepositoryImpl::new(DbConnection::from(conn));
+        repository
+            .create_user(create_user("peace", "peace@example.com"))
+            .await
+            .unwrap();
+
+        // the insert of a concurrent registration which passed the checks
+        assert!(matches!(
+            repository
+                .create_user(create_user("peace", "other@example.com"))
+                .await,
+            Err(CreateUserError::UsernameExists)
+        ));
+        assert!(matches!(
+            repository
+                .create_user(create_user("other", "peace@example.com"))
+                .await,
+            Err(CreateUserError::EmailExists)
+        ));
+        repository
+            .create_user(create_user("other", "other@example.com"))
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_create_user_error_from_unique_violation() {
+        let from_msg =
+            |msg: &str| CreateUserError::from(DbErr::Custom(msg.to_owned()));
+
+        assert!(matches!(
+            from_msg(
+                r#"duplicate key value violates unique constraint "IDX_users_email""#
+            ),
+            CreateUserError::EmailExists
+        ));
+        assert!(matches!(
+            from_msg("Duplicate entry 'peace' for key 'users.name_safe'"),
+            CreateUserError::UsernameExists
+        ));
+        // the value of the entry is not the violated key
+        assert!(matches!(
+            from_msg(
+                "Duplicate entry 'name@example.com' for key 'users.email'"
+            ),
+            CreateUserError::EmailExists
+        ));
+        assert!(matches!(
+            from_msg("connection refused"),
+            CreateUserError::DbErr(_)
+        ));
+    }
 
     #[tokio::test]
     async fn test_main() {
@@ -22,6 +22,8 @@ pub enum ProcessBanchoPacketError {
     #[error(transparent)]
     BanchoServiceError(#[from] BanchoServiceError),
     #[error(transparent)]
+    BanchoStateError(#[from] BanchoStateError),
+    #[error(transparent)]
     ChatError(#[from] ChatError),
     #[error("TonicError: {0}")]
     TonicError(String),
@@ -39,12 +41,26 @@ pub enum BanchoServiceError {
     PasswordError(#[from] PasswordError),
     #[error(transparent)]
     UserNotExists(#[from] GetUserError),
+    #[error("user is banned")]
+    UserBanned,
     #[error(transparent)]
     BanchoStateError(#[from] BanchoStateError),
     #[error(transparent)]
     ChatError(#[from] ChatError),
     #[error(transparent)]
     ConvertError(#[from] ConvertError),
+    #[error("failed to create user: {0}")]
+    CreateUserError(String),
+    #[error("database error: {0}")]
+    DbError(String),
+    #[error("invalid score: {0}")]
+    InvalidScore(String),
+    #[error("beatmap not submitted")]
+    BeatmapNotSubmitted,
+    #[error("score already submitted")]
+    DuplicateScore,
+    #[error("score rejected: {0}")]
+    ScoreRejected(String),
     #[error("TonicError: {0}")]
     TonicError(String),
 }
@@ -0,0 +1,383 @@
+use crate::ProcessBanchoPacketError;
+use bancho_packets::server;
+use core_bancho_state::{BanchoStateError, BanchoStateService};
+use core_chat::{ChatError, ChatService};
+use domain_bancho::BanchoPrivileges;
+use pb_bancho_state::{RawUserQueryWithFields, UserQuery, UserSessionFields};
+use pb_chat::{ChannelQuery, CreatePublicChannelRequest};
+use peace_repositories::CreateChannelError;
+use rand::Rng;
+use std::{future::Future, pin::Pin};
+
+pub const COMMAND_PREFIX: char = '!';
+pub const COMMAND_BOT_USERNAME: &str = "PeaceBot";
+pub const COMMAND_BOT_USER_ID: i32 = 1;
+
+pub type CommandFuture<'a> = Pin<
+    Box<
+        dyn Future<Output = Result<String, ProcessBanchoPacketError>>
+            + Send
+            + 'a,
+    >,
+>;
+pub type CommandHandler =
+    for<'a> fn(&'a CommandContext<'a>) -> CommandFuture<'a>;
+
+/// A chat command, add an entry to [`COMMANDS`] to register a new one.
+pub struct ChatCommand {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+    /// The sender must have any of these privileges.
+    pub required_privileges: BanchoPrivileges,
+    /// Replies of public commands are sent to the channel the command was
+    /// typed in, the others only to the sender.
+    pub public: bool,
+    pub handler: CommandHandler,
+}
+
+pub const COMMANDS: &[ChatCommand] = &[
+    ChatCommand {
+        name: "help",
+        usage: "!help",
+        description: "List the available commands",
+        required_privileges: BanchoPrivileges::Normal,
+        public: false,
+        handler: help,
+    },
+    ChatCommand {
+        name: "roll",
+        usage: "!roll [max]",
+        description: "Roll a random number between 0 and max (default 100)",
+        required_privileges: BanchoPrivileges::Normal,
+        public: true,
+        handler: roll,
+    },
+    ChatCommand {
+        name: "kick",
+        usage: "!kick <username|user id>",
+        description: "Disconnect a player from the server",
+        required_privileges: BanchoPrivileges::Moderator
+            .or(BanchoPrivileges::Administrator),
+        public: false,
+        handler: kick,
+    },
+    ChatCommand {
+        name: "addchannel",
+        usage: "!addchannel <#channel> [description]",
+        description: "Create a public channel",
+        required_privileges: BanchoPrivileges::Administrator,
+        public: false,
+        handler: add_channel,
+    },
+    ChatCommand {
+        name: "delchannel",
+        usage: "!delchannel <#channel>",
+        description: "Delete a channel and kick its members",
+        required_privileges: BanchoPrivileges::Administrator,
+        public: false,
+        handler: delete_channel,
+    },
+];
+
+pub struct CommandContext<'a> {
+    pub user_id: i32,
+    pub username: String,
+    pub privileges: BanchoPrivileges,
+    pub args: Vec<&'a str>,
+    pub commands: &'a [ChatCommand],
+    pub bancho_state_service: &'a (dyn BanchoStateService + Send + Sync),
+    pub chat_service: &'a (dyn ChatService + Send + Sync),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedCommand<'a> {
+    pub name: &'a str,
+    pub args: Vec<&'a str>,
+}
+
+/// Parse a chat message as a command, returns `None` if it is a regular
+/// message.
+pub fn parse_command(content: &str) -> Option<ParsedCommand<'_>> {
+    let content = content.strip_prefix(COMMAND_PREFIX)?;
+
+    // "! text" is not a command
+    if content.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let mut parts = content.split_whitespace();
+    let name = parts.next()?;
+
+    Some(ParsedCommand { name, args: parts.collect() })
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandReply {
+    pub sender_username: String,
+    pub content: String,
+    pub public: bool,
+}
+
+impl CommandReply {
+    /// Packet of the reply sent by the command bot, shown in `target`.
+    #[inline]
+    pub fn to_bot_packet(&self, target: &str) -> Vec<u8> {
+        bot_message(&self.content, target)
+    }
+}
+
+/// Packet of a message sent by the command bot, shown in `target`.
+#[inline]
+pub fn bot_message(content: &str, target: &str) -> Vec<u8> {
+    server::SendMessage::pack(
+        COMMAND_BOT_USERNAME.into(),
+        content.into(),
+        target.into(),
+        COMMAND_BOT_USER_ID,
+    )
+}
+
+#[derive(Clone)]
+pub struct CommandService<'a> {
+    pub commands: &'a [ChatCommand],
+    pub bancho_state_service: &'a (dyn BanchoStateService + Send + Sync),
+    pub chat_service: &'a (dyn ChatService + Send + Sync),
+}
+
+impl<'a> CommandService<'a> {
+    #[inline]
+    pub fn new(
+        bancho_state_service: &'a (dyn BanchoStateService + Send + Sync),
+        chat_service: &'a (dyn ChatService + Send + Sync),
+    ) -> Self {
+        Self { commands: COMMANDS, bancho_state_service, chat_service }
+    }
+
+    #[inline]
+    pub fn find(&self, name: &str) -> Option<&'a ChatCommand> {
+        self.commands.iter().find(|cmd| cmd.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Handle the message if it is a command, returns `None` if the message
+    /// should be delivered as usual.
+    pub async fn dispatch(
+        &self,
+        user_id: i32,
+        content: &str,
+    ) -> Result<Option<CommandReply>, ProcessBanchoPacketError> {
+        let ParsedCommand { name, args } = match parse_command(content) {
+            Some(parsed) => parsed,
+            None => return Ok(None),
+        };
+
+        let session = self
+            .bancho_state_service
+            .get_user_session_with_fields(RawUserQueryWithFields {
+                user_query: Some(UserQuery::UserId(user_id).into()),
+                fields: (UserSessionFields::Username
+                    | UserSessionFields::BanchoPrivileges)
+                    .bits(),
+            })
+            .await?;
+
+        let ctx = CommandContext {
+            user_id,
+            username: session.username.unwrap_or_default(),
+            privileges: BanchoPrivileges::from(
+                session.bancho_privileges.unwrap_or_default(),
+            ),
+            args,
+            commands: self.commands,
+            bancho_state_service: self.bancho_state_service,
+            chat_service: self.chat_service,
+        };
+
+        let (content, public) = match self.find(name) {
+            Some(cmd) if ctx.privileges.intersects(cmd.required_privileges) => {
+                info!(
+                    target: "bancho::commands",
+                    "{}({}) used command: {content}", ctx.username, user_id
+                );
+
+                ((cmd.handler)(&ctx).await?, cmd.public)
+            },
+            Some(_) => (format!("You are not allowed to use !{name}."), false),
+            None => (format!("No such command: !{name}, try !help."), false),
+        };
+
+        Ok(Some(CommandReply {
+            sender_username: ctx.username,
+            content,
+            public,
+        }))
+    }
+}
+
+fn help<'a>(ctx: &'a CommandContext<'a>) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let mut reply = String::from("Available commands:");
+        for cmd in ctx
+            .commands
+            .iter()
+            .filter(|cmd| ctx.privileges.intersects(cmd.required_privileges))
+        {
+            reply.push_str(&format!("\n{} - {}", cmd.usage, cmd.description));
+        }
+
+        Ok(reply)
+    })
+}
+
+fn roll<'a>(ctx: &'a CommandContext<'a>) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let max = ctx
+            .args
+            .first()
+            .and_then(|arg| arg.parse::<u32>().ok())
+            .filter(|max| *max > 0)
+            .unwrap_or(100);
+
+        let points = rand::thread_rng().gen_range(0..=max);
+
+        // shown as "* username rolls .." by the client
+        Ok(format!("\x01ACTION rolls {points} point(s)\x01"))
+    })
+}
+
+fn kick<'a>(ctx: &'a CommandContext<'a>) -> CommandFuture<'a> {
+    Box::pin(async move {
+        if ctx.args.is_empty() {
+            return Ok("Usage: !kick <username|user id>".to_owned());
+        }
+
+        let user_query = UserQuery::parse(&ctx.args.join(" "));
+        let username = user_query.to_string();
+
+        match ctx.bancho_state_service.delete_user_session(user_query).await {
+            Ok(_) => {
+                warn!(
+                    target: "bancho::commands",
+                    "{}({}) kicked {username}", ctx.username, ctx.user_id
+                );
+
+                Ok(format!("Kicked {username}."))
+            },
+            Err(BanchoStateError::SessionNotExists) => {
+                Ok(format!("{username} is not online."))
+            },
+            Err(err) => Err(err.into()),
+        }
+    })
+}
+
+fn add_channel<'a>(ctx: &'a CommandContext<'a>) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let (name, description) = match ctx.args.split_first() {
+            Some((name, description)) => (name.to_string(), description),
+            None => {
+                return Ok(
+                    "Usage: !addchannel <#channel> [description]".to_owned()
+                )
+            },
+        };
+
+        match ctx
+            .chat_service
+            .create_public_channel(CreatePublicChannelRequest {
+                name: name.clone(),
+                description: (!description.is_empty())
+                    .then(|| description.join(" ")),
+                ..Default::default()
+            })
+            .await
+        {
+            Ok(_) => {
+                info!(
+                    target: "bancho::commands",
+                    "{}({}) created channel {name}", ctx.username, ctx.user_id
+                );
+
+                Ok(format!("Channel {name} created."))
+            },
+            Err(ChatError::CreateChannelError(
+                CreateChannelError::ChannelExists,
+            )) => Ok(format!("Channel {name} already exists.")),
+            Err(ChatError::InvalidArgument) => {
+                Ok(format!("Invalid channel name: {name}"))
+            },
+            Err(err) => Err(err.into()),
+        }
+    })
+}
+
+fn delete_channel<'a>(ctx: &'a CommandContext<'a>) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let name = match ctx.args.first() {
+            Some(name) => name.to_string(),
+            None => return Ok("Usage: !delchannel <#channel>".to_owned()),
+        };
+
+        match ctx
+            .chat_service
+            .delete_channel(ChannelQuery::ChannelName(name.clone()))
+            .await
+        {
+            Ok(_) => {
+                warn!(
+                    target: "bancho::commands",
+                    "{}({}) deleted channel {name}", ctx.username, ctx.user_id
+                );
+
+                Ok(format!("Channel {name} deleted."))
+            },
+            Err(ChatError::ChannelNotExists) => {
+                Ok(format!("Channel {name} not exists."))
+            },
+            Err(err) => Err(err.into()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            parse_command("!roll 1000"),
+            Some(ParsedCommand { name: "roll", args: vec!["1000"] })
+        );
+        assert_eq!(
+            parse_command("!kick  some   player"),
+            Some(ParsedCommand { name: "kick", args: vec!["some", "player"] })
+        );
+        assert_eq!(parse_command("hello !roll"), None);
+        assert_eq!(parse_command("! roll"), None);
+        assert_eq!(parse_command("!"), None);
+    }
+
+    #[test]
+    fn test_commands_table() {
+        let mut names = COMMANDS.iter().map(|cmd| cmd.name).collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), COMMANDS.len());
+
+        let kick = COMMANDS.iter().find(|cmd| cmd.name == "kick").unwrap();
+        assert!(!BanchoPrivileges::Normal.intersects(kick.required_privileges));
+        assert!(BanchoPrivileges::Administrator
+            .intersects(kick.required_privileges));
+
+        // channels are only managed by the administrators
+        for name in ["addchannel", "delchannel"] {
+            let cmd = COMMANDS.iter().find(|cmd| cmd.name == name).unwrap();
+            assert!(!cmd.public, "{name}");
+            assert!(!BanchoPrivileges::Moderator
+                .intersects(cmd.required_privileges));
+            assert!(BanchoPrivileges::Administrator
+                .intersects(cmd.required_privileges));
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use tools::rate_limiter::KeyedRateLimiter;
+
+/// Token bucket limiter of the chat messages, keyed on the sender.
+pub type MessageLimiter = KeyedRateLimiter<i32>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_message_limiter() {
+        const BURST: u32 = 10;
+
+        let limiter = MessageLimiter::new(1.0, BURST);
+
+        for _ in 0..BURST {
+            assert!(limiter.check(1000).is_ok());
+        }
+        assert!(limiter.check(1000).unwrap_err() <= Duration::from_secs(1));
+
+        // other users are not affected
+        assert!(limiter.check(1001).is_ok());
+
+        let unlimited = MessageLimiter::new(0.0, 0);
+        for _ in 0..100 {
+            assert!(unlimited.check(1000).is_ok());
+        }
+    }
+}
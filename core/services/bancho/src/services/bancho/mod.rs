@@ -1,5 +1,19 @@
+pub mod commands;
+pub mod message_limiter;
+pub mod packet_metrics;
 pub mod packet_processor;
+pub mod packet_results;
+pub mod score_submission;
+pub mod score_validator;
 pub mod service;
+pub mod session_geoip;
 
+pub use commands::*;
+pub use message_limiter::*;
+pub use packet_metrics::*;
 pub use packet_processor::*;
+pub use packet_results::*;
+pub use score_submission::*;
+pub use score_validator::*;
 pub use service::*;
+pub use session_geoip::*;
@@ -0,0 +1,259 @@
+use bancho_packets::PacketId;
+use num_traits::FromPrimitive;
+use pb_bancho::{PacketMetric, PacketMetricsResponse};
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+/// Upper bounds (seconds) of the packet handlers latency buckets.
+pub const PACKET_LATENCY_BUCKETS: [f64; 10] =
+    [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0];
+
+/// How a packet handler ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketOutcome {
+    Handled,
+    Errored,
+    /// No handler for the packet.
+    Unhandled,
+}
+
+#[derive(Debug, Default)]
+struct PacketStats {
+    handled: AtomicU64,
+    errored: AtomicU64,
+    unhandled: AtomicU64,
+    /// Not cumulative, the last one counts the slower handlers.
+    latency_buckets: [AtomicU64; PACKET_LATENCY_BUCKETS.len() + 1],
+    latency_sum_nanos: AtomicU64,
+}
+
+impl PacketStats {
+    #[inline]
+    fn count(&self) -> u64 {
+        self.handled.load(Ordering::Relaxed)
+            + self.errored.load(Ordering::Relaxed)
+            + self.unhandled.load(Ordering::Relaxed)
+    }
+}
+
+/// Handled packets and handlers latency, by packet id.
+///
+/// Each packet id has its own counters, so that the packets are observed
+/// without any lock.
+#[derive(Debug)]
+pub struct PacketMetrics {
+    /// Indexed by packet id.
+    packets: Box<[PacketStats]>,
+}
+
+impl Default for PacketMetrics {
+    fn default() -> Self {
+        Self {
+            packets: (0..=u8::MAX).map(|_| PacketStats::default()).collect(),
+        }
+    }
+}
+
+impl PacketMetrics {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(
+        &self,
+        packet_id: PacketId,
+        outcome: PacketOutcome,
+        elapsed: Duration,
+    ) {
+        let bucket = PACKET_LATENCY_BUCKETS
+            .iter()
+            .position(|bound| elapsed.as_secs_f64() <= *bound)
+            .unwrap_or(PACKET_LATENCY_BUCKETS.len());
+
+        let stats = &self.packets[packet_id as usize];
+
+        let counter = match outcome {
+            PacketOutcome::Handled => &stats.handled,
+            PacketOutcome::Errored => &stats.errored,
+            PacketOutcome::Unhandled => &stats.unhandled,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        stats.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        stats.latency_sum_nanos.fetch_add(
+            elapsed.as_nanos().min(u64::MAX as u128) as u64,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Metrics of each packet id seen, the latency buckets are cumulative
+    /// (prometheus histogram).
+    ///
+    /// The counters are read one by one while the packets are observed, so
+    /// a snapshot can be slightly inconsistent.
+    pub fn snapshot(&self) -> PacketMetricsResponse {
+        let packets = self
+            .packets
+            .iter()
+            .enumerate()
+            .filter(|(_, stats)| stats.count() > 0)
+            .filter_map(|(packet_id, stats)| {
+                let packet_id = PacketId::from_u8(packet_id as u8)?;
+
+                Some(PacketMetric {
+                    packet_id: packet_id as i32,
+                    packet_name: packet_id.to_string(),
+                    handled: stats.handled.load(Ordering::Relaxed),
+                    errored: stats.errored.load(Ordering::Relaxed),
+                    unhandled: stats.unhandled.load(Ordering::Relaxed),
+                    latency_bucket_counts: stats.latency_buckets
+                        [..PACKET_LATENCY_BUCKETS.len()]
+                        .iter()
+                        .scan(0, |count, n| {
+                            *count += n.load(Ordering::Relaxed);
+                            Some(*count)
+                        })
+                        .collect(),
+                    latency_sum_secs: Duration::from_nanos(
+                        stats.latency_sum_nanos.load(Ordering::Relaxed),
+                    )
+                    .as_secs_f64(),
+                })
+            })
+            .collect();
+
+        PacketMetricsResponse {
+            latency_buckets_secs: PACKET_LATENCY_BUCKETS.to_vec(),
+            packets,
+        }
+    }
+}
+
+/// Rate limits the warnings of unhandled packets per packet id, so that a
+/// client spamming an unknown packet does not flood the logs.
+#[derive(Debug)]
+pub struct UnhandledPacketWarnings {
+    interval: Duration,
+    /// packet id -> (last warning, warnings suppressed since)
+    warned: Mutex<HashMap<PacketId, (Instant, u64)>>,
+}
+
+impl UnhandledPacketWarnings {
+    #[inline]
+    pub fn new(interval: Duration) -> Self {
+        Self { interval, warned: Mutex::default() }
+    }
+
+    /// Returns the number of warnings suppressed since the last one if the
+    /// packet should be warned about, `None` if it was warned too recently.
+    pub async fn should_warn(&self, packet_id: PacketId) -> Option<u64> {
+        let now = Instant::now();
+        let mut warned = self.warned.lock().await;
+
+        match warned.get_mut(&packet_id) {
+            Some((last, suppressed))
+                if now.duration_since(*last) < self.interval =>
+            {
+                *suppressed += 1;
+                None
+            },
+            Some((last, suppressed)) => {
+                *last = now;
+                Some(std::mem::take(suppressed))
+            },
+            None => {
+                warned.insert(packet_id, (now, 0));
+                Some(0)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_metrics() {
+        let metrics = PacketMetrics::new();
+
+        for (packet_id, outcome, ms) in [
+            (PacketId::OSU_PING, PacketOutcome::Handled, 0),
+            (PacketId::OSU_PING, PacketOutcome::Handled, 3),
+            (PacketId::OSU_PING, PacketOutcome::Errored, 2000),
+            (PacketId::OSU_SPECTATE_FRAMES, PacketOutcome::Unhandled, 0),
+        ] {
+            metrics.observe(packet_id, outcome, Duration::from_millis(ms));
+        }
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.packets.len(), 2);
+
+        let ping = snapshot
+            .packets
+            .iter()
+            .find(|p| p.packet_name == "OSU_PING")
+            .unwrap();
+        assert_eq!((ping.handled, ping.errored, ping.unhandled), (2, 1, 0));
+        // 0ms, 3ms in `0.005`, 2000ms above every bucket
+        assert_eq!(
+            ping.latency_bucket_counts,
+            vec![1, 1, 1, 2, 2, 2, 2, 2, 2, 2]
+        );
+        assert!((ping.latency_sum_secs - 2.003).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_packet_metrics_concurrent() {
+        let metrics = std::sync::Arc::new(PacketMetrics::new());
+
+        let threads = (0..4)
+            .map(|_| {
+                let metrics = metrics.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        metrics.observe(
+                            PacketId::OSU_PING,
+                            PacketOutcome::Handled,
+                            Duration::ZERO,
+                        );
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.packets.len(), 1);
+        assert_eq!(snapshot.packets[0].handled, 4000);
+        assert_eq!(snapshot.packets[0].latency_bucket_counts[0], 4000);
+    }
+
+    #[tokio::test]
+    async fn test_unhandled_packet_warnings() {
+        let warnings = UnhandledPacketWarnings::new(Duration::from_secs(60));
+
+        let unknown = PacketId::OSU_UNKNOWN_PACKET;
+
+        assert_eq!(warnings.should_warn(unknown).await, Some(0));
+        assert_eq!(warnings.should_warn(unknown).await, None);
+        assert_eq!(warnings.should_warn(unknown).await, None);
+        // other packet ids are limited separately
+        assert_eq!(
+            warnings.should_warn(PacketId::OSU_MATCH_LOCK).await,
+            Some(0)
+        );
+
+        let warnings = UnhandledPacketWarnings::new(Duration::ZERO);
+        assert_eq!(warnings.should_warn(unknown).await, Some(0));
+        assert_eq!(warnings.should_warn(unknown).await, Some(0));
+    }
+}
@@ -1,62 +1,133 @@
-use crate::{traits::*, ProcessBanchoPacketError};
+use crate::{
+    bot_message, traits::*, CommandService, MessageLimiter,
+    ProcessBanchoPacketError,
+};
 use async_trait::async_trait;
 use bancho_packets::{
-    BanchoMessage, ClientChangeAction, Packet, PayloadReader,
+    server, BanchoMessage, BanchoPacketRead, ClientChangeAction, Packet,
+    PayloadReader,
 };
 use core_bancho_state::BanchoStateService;
-use core_chat::ChatService;
-use domain_bancho::PresenceFilter;
+use core_chat::{ChatError, ChatService};
+use domain_bancho::{BanchoPrivileges, PresenceFilter};
 use num_traits::FromPrimitive;
 use pb_bancho::*;
-use pb_bancho_state::UserQuery;
+use pb_bancho_state::{RawUserQueryWithFields, UserQuery, UserSessionFields};
 use pb_chat::{
     ChannelQuery, ChatMessageTarget, JoinChannelRequest, LeaveChannelRequest,
     SendMessageRequest,
 };
-use std::fmt::Debug;
+use std::{fmt::Debug, net::IpAddr};
 
 #[derive(Clone)]
 pub struct PacketProcessor<'a> {
     pub user_id: i32,
+    /// Ip of the client which sent the packet, `None` for the packets which
+    /// are not sent by the client directly (e.g. the `ProcessBanchoPacket`
+    /// rpc).
+    pub client_ip: Option<IpAddr>,
     pub packet: Packet<'a>,
     pub bancho_service: &'a (dyn BanchoService + Send + Sync),
     pub bancho_state_service: &'a (dyn BanchoStateService + Send + Sync),
     pub chat_service: &'a (dyn ChatService + Send + Sync),
+    pub message_limiter: &'a MessageLimiter,
 }
 
 impl<'a> Debug for PacketProcessor<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("PacketProcessor")
             .field("user_id", &self.user_id)
+            .field("client_ip", &self.client_ip)
             .field("packet", &self.packet)
             .finish()
     }
 }
 
+/// Read the payload of the packet as `T`, a packet without payload is
+/// distinguished from a payload which can not be decoded.
 #[inline]
-pub fn read_channel_name(
-    payload: Option<&[u8]>,
-) -> Result<String, ProcessBanchoPacketError> {
-    let channel_name = PayloadReader::new(
-        payload.ok_or(ProcessBanchoPacketError::PacketPayloadNotExists)?,
+pub fn read_payload<T>(packet: &Packet) -> Result<T, ProcessBanchoPacketError>
+where
+    T: BanchoPacketRead<T>,
+{
+    PayloadReader::new(
+        packet
+            .payload
+            .ok_or(ProcessBanchoPacketError::PacketPayloadNotExists)?,
     )
-    .read::<String>()
-    .ok_or(ProcessBanchoPacketError::InvalidPacketPayload)?;
-
-    Ok(channel_name)
+    .read::<T>()
+    .ok_or(ProcessBanchoPacketError::InvalidPacketPayload)
 }
 
-#[inline]
-pub fn read_chat_message(
-    payload: Option<&[u8]>,
-) -> Result<BanchoMessage, ProcessBanchoPacketError> {
-    let message = PayloadReader::new(
-        payload.ok_or(ProcessBanchoPacketError::PacketPayloadNotExists)?,
-    )
-    .read::<BanchoMessage>()
-    .ok_or(ProcessBanchoPacketError::InvalidPacketPayload)?;
+impl<'a> PacketProcessor<'a> {
+    #[inline]
+    pub fn command_service(&self) -> CommandService<'a> {
+        CommandService::new(self.bancho_state_service, self.chat_service)
+    }
+
+    /// Send the message through chat, which refuses the messages of
+    /// silenced senders.
+    ///
+    /// Returns the notice replied in `target` if the sender is silenced,
+    /// the client is also sent the remaining silence to grey out the chat.
+    pub async fn send_chat_message(
+        &self,
+        request: SendMessageRequest,
+        target: &str,
+    ) -> Result<Option<HandleCompleted>, ProcessBanchoPacketError> {
+        let remaining = match self.chat_service.send_message(request).await {
+            Ok(_) => return Ok(None),
+            Err(ChatError::Silenced(remaining)) => remaining,
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut packets = bot_message(
+            &format!(
+                "You are silenced, you can chat again in {remaining} seconds."
+            ),
+            target,
+        );
+        packets.extend(server::SilenceEnd::pack(remaining));
 
-    Ok(message)
+        Ok(Some(HandleCompleted { packets: Some(packets) }))
+    }
+
+    /// Take a message of the sender from the limiter, returns the notice
+    /// replied in `target` if the message should be dropped.
+    pub async fn check_message_rate(
+        &self,
+        target: &str,
+    ) -> Result<Option<HandleCompleted>, ProcessBanchoPacketError> {
+        if self.message_limiter.check(self.user_id).is_ok() {
+            return Ok(None);
+        }
+
+        // staff are not limited, only looked up once the limit is reached
+        let session = self
+            .bancho_state_service
+            .get_user_session_with_fields(RawUserQueryWithFields {
+                user_query: Some(UserQuery::UserId(self.user_id).into()),
+                fields: UserSessionFields::BanchoPrivileges.bits(),
+            })
+            .await?;
+
+        if BanchoPrivileges::from(session.bancho_privileges.unwrap_or_default())
+            .intersects(
+                BanchoPrivileges::Moderator
+                    .or(BanchoPrivileges::Administrator)
+                    .or(BanchoPrivileges::Developer),
+            )
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(HandleCompleted {
+            packets: Some(bot_message(
+                "You are sending messages too quickly, slow down!",
+                target,
+            )),
+        }))
+    }
 }
 
 #[async_trait]
@@ -66,13 +137,56 @@ impl<'a> ProcessSendPublicMessage for PacketProcessor<'a> {
         &self,
     ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
         #[allow(unused_mut)]
-        let mut chat_message = read_chat_message(self.packet.payload)?;
+        let mut chat_message = read_payload::<BanchoMessage>(&self.packet)?;
+
+        if let Some(notice) =
+            self.check_message_rate(&chat_message.target).await?
+        {
+            return Ok(notice);
+        }
+
+        if let Some(reply) = self
+            .command_service()
+            .dispatch(self.user_id, &chat_message.content)
+            .await?
+        {
+            if !reply.public {
+                return Ok(HandleCompleted {
+                    packets: Some(reply.to_bot_packet(&chat_message.target)),
+                });
+            }
+
+            // public replies are sent to the channel on behalf of the sender,
+            // who is excluded from the channel queue
+            let packets = server::SendMessage::pack(
+                reply.sender_username.as_str().into(),
+                reply.content.as_str().into(),
+                chat_message.target.as_str().into(),
+                self.user_id,
+            );
+
+            let request = SendMessageRequest {
+                sender: Some(UserQuery::UserId(self.user_id).into()),
+                message: reply.content,
+                target: Some(
+                    ChatMessageTarget::Channel(ChannelQuery::ChannelName(
+                        chat_message.target.clone(),
+                    ))
+                    .into(),
+                ),
+            };
 
+            if let Some(notice) =
+                self.send_chat_message(request, &chat_message.target).await?
+            {
+                return Ok(notice);
+            }
+
+            return Ok(HandleCompleted { packets: Some(packets) });
+        }
+
+        // `#spectator` is resolved to the user's spectator channel by chat
         match chat_message.target.as_str() {
-            "#spectator" => {
-                // TODO: spectator chat
-                todo!("get user's current #spectator channel id")
-            },
             "#multiplayer" => {
                 // TODO: multiplayer chat
                 todo!("get user's current #multiplayer channel id")
@@ -85,15 +199,16 @@ impl<'a> ProcessSendPublicMessage for PacketProcessor<'a> {
             message: chat_message.content,
             target: Some(
                 ChatMessageTarget::Channel(ChannelQuery::ChannelName(
-                    chat_message.target,
+                    chat_message.target.clone(),
                 ))
                 .into(),
             ),
         };
 
-        self.chat_service.send_message(request).await?;
-
-        Ok(HandleCompleted::default())
+        Ok(self
+            .send_chat_message(request, &chat_message.target)
+            .await?
+            .unwrap_or_default())
     }
 }
 
@@ -103,22 +218,40 @@ impl<'a> ProcessSendPrivateMessage for PacketProcessor<'a> {
     async fn send_private_message(
         &self,
     ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
-        let chat_message = read_chat_message(self.packet.payload)?;
+        let chat_message = read_payload::<BanchoMessage>(&self.packet)?;
+
+        if let Some(notice) =
+            self.check_message_rate(&chat_message.target).await?
+        {
+            return Ok(notice);
+        }
+
+        // replies of commands sent in private messages are never public
+        if let Some(reply) = self
+            .command_service()
+            .dispatch(self.user_id, &chat_message.content)
+            .await?
+        {
+            return Ok(HandleCompleted {
+                packets: Some(reply.to_bot_packet(&reply.sender_username)),
+            });
+        }
 
         let request = SendMessageRequest {
             sender: Some(UserQuery::UserId(self.user_id).into()),
             message: chat_message.content,
             target: Some(
                 ChatMessageTarget::User(UserQuery::Username(
-                    chat_message.target,
+                    chat_message.target.clone(),
                 ))
                 .into(),
             ),
         };
 
-        self.chat_service.send_message(request).await?;
-
-        Ok(HandleCompleted::default())
+        Ok(self
+            .send_chat_message(request, &chat_message.target)
+            .await?
+            .unwrap_or_default())
     }
 }
 
@@ -128,7 +261,7 @@ impl<'a> ProcessUserChannelJoin for PacketProcessor<'a> {
     async fn user_channel_join(
         &self,
     ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
-        let channel_name = read_channel_name(self.packet.payload)?;
+        let channel_name = read_payload::<String>(&self.packet)?;
 
         self.chat_service
             .join_channel(JoinChannelRequest {
@@ -149,7 +282,7 @@ impl<'a> ProcessUserChannelPart for PacketProcessor<'a> {
     async fn user_channel_part(
         &self,
     ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
-        let channel_name = read_channel_name(self.packet.payload)?;
+        let channel_name = read_payload::<String>(&self.packet)?;
 
         self.chat_service
             .leave_channel(LeaveChannelRequest {
@@ -198,13 +331,7 @@ impl<'a> ProcessUserStatsRequest for PacketProcessor<'a> {
     async fn user_stats_request(
         &self,
     ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
-        let request_users = PayloadReader::new(
-            self.packet
-                .payload
-                .ok_or(ProcessBanchoPacketError::PacketPayloadNotExists)?,
-        )
-        .read::<Vec<i32>>()
-        .ok_or(ProcessBanchoPacketError::InvalidPacketPayload)?;
+        let request_users = read_payload::<Vec<i32>>(&self.packet)?;
 
         self.bancho_service
             .request_stats(StatsRequest {
@@ -230,13 +357,7 @@ impl<'a> ProcessUserChangeAction for PacketProcessor<'a> {
             mods,
             mode,
             beatmap_id,
-        } = PayloadReader::new(
-            self.packet
-                .payload
-                .ok_or(ProcessBanchoPacketError::PacketPayloadNotExists)?,
-        )
-        .read::<ClientChangeAction>()
-        .ok_or(ProcessBanchoPacketError::InvalidPacketPayload)?;
+        } = read_payload::<ClientChangeAction>(&self.packet)?;
 
         self.bancho_service
             .change_action(ChangeActionRequest {
@@ -260,16 +381,9 @@ impl<'a> ProcessUserReceiveUpdates for PacketProcessor<'a> {
     async fn user_receive_updates(
         &self,
     ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
-        let presence_filter = PresenceFilter::from_i32(
-            PayloadReader::new(
-                self.packet
-                    .payload
-                    .ok_or(ProcessBanchoPacketError::PacketPayloadNotExists)?,
-            )
-            .read::<i32>()
-            .ok_or(ProcessBanchoPacketError::InvalidPacketPayload)?,
-        )
-        .unwrap_or_default();
+        let presence_filter =
+            PresenceFilter::from_i32(read_payload::<i32>(&self.packet)?)
+                .unwrap_or_default();
 
         self.bancho_service
             .receive_updates(ReceiveUpdatesRequest {
@@ -288,14 +402,7 @@ impl<'a> ProcessUserToggleBlockNonFriendDms for PacketProcessor<'a> {
     async fn user_toggle_block_non_friend_dms(
         &self,
     ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
-        let toggle = PayloadReader::new(
-            self.packet
-                .payload
-                .ok_or(ProcessBanchoPacketError::PacketPayloadNotExists)?,
-        )
-        .read::<i32>()
-        .ok_or(ProcessBanchoPacketError::InvalidPacketPayload)?
-            == 1;
+        let toggle = read_payload::<i32>(&self.packet)? == 1;
 
         self.bancho_service
             .toggle_block_non_friend_dms(ToggleBlockNonFriendDmsRequest {
@@ -328,21 +435,299 @@ impl<'a> ProcessUserPresenceRequest for PacketProcessor<'a> {
     async fn user_presence_request(
         &self,
     ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
-        let request_users = PayloadReader::new(
+        let request_users = read_payload::<Vec<i32>>(&self.packet)?;
+
+        self.bancho_service
+            .request_presence(PresenceRequest {
+                user_id: self.user_id,
+                request_users,
+            })
+            .await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl<'a> ProcessUserJoinLobby for PacketProcessor<'a> {
+    #[inline]
+    async fn user_join_lobby(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        self.bancho_service.lobby_join(UserQuery::UserId(self.user_id)).await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl<'a> ProcessUserPartLobby for PacketProcessor<'a> {
+    #[inline]
+    async fn user_part_lobby(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        self.bancho_service.lobby_part(UserQuery::UserId(self.user_id)).await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl<'a> ProcessSpectateStart for PacketProcessor<'a> {
+    #[inline]
+    async fn spectate_start(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        let target_id = read_payload::<i32>(&self.packet)?;
+
+        self.bancho_service
+            .spectate_start(SpectateStartRequest {
+                user_id: self.user_id,
+                target_id,
+            })
+            .await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl<'a> ProcessSpectateStop for PacketProcessor<'a> {
+    #[inline]
+    async fn spectate_stop(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        self.bancho_service
+            .spectate_stop(UserQuery::UserId(self.user_id))
+            .await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl<'a> ProcessMatchCreate for PacketProcessor<'a> {
+    #[inline]
+    async fn match_create(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        // the match data is decoded by bancho state
+        let match_data = self
+            .packet
+            .payload
+            .ok_or(ProcessBanchoPacketError::PacketPayloadNotExists)?
+            .to_vec();
+
+        self.bancho_service
+            .match_create(MatchCreateRequest {
+                user_id: self.user_id,
+                match_data,
+            })
+            .await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl<'a> ProcessMatchJoin for PacketProcessor<'a> {
+    #[inline]
+    async fn match_join(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        let mut reader = PayloadReader::new(
             self.packet
                 .payload
                 .ok_or(ProcessBanchoPacketError::PacketPayloadNotExists)?,
-        )
-        .read::<Vec<i32>>()
-        .ok_or(ProcessBanchoPacketError::InvalidPacketPayload)?;
+        );
+
+        let match_id = reader
+            .read::<i32>()
+            .ok_or(ProcessBanchoPacketError::InvalidPacketPayload)?;
+        // an empty byte is sent instead of the password if not set
+        let password = reader.read::<String>().unwrap_or_default();
 
         self.bancho_service
-            .request_presence(PresenceRequest {
+            .match_join(MatchJoinRequest {
                 user_id: self.user_id,
-                request_users,
+                match_id,
+                password,
+            })
+            .await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl<'a> ProcessMatchPart for PacketProcessor<'a> {
+    #[inline]
+    async fn match_part(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        self.bancho_service.match_part(UserQuery::UserId(self.user_id)).await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl<'a> ProcessMatchStart for PacketProcessor<'a> {
+    #[inline]
+    async fn match_start(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        self.bancho_service
+            .match_start(UserQuery::UserId(self.user_id))
+            .await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl<'a> ProcessMatchLoadComplete for PacketProcessor<'a> {
+    #[inline]
+    async fn match_load_complete(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        self.bancho_service
+            .match_load_complete(UserQuery::UserId(self.user_id))
+            .await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl<'a> ProcessMatchComplete for PacketProcessor<'a> {
+    #[inline]
+    async fn match_complete(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        self.bancho_service
+            .match_complete(UserQuery::UserId(self.user_id))
+            .await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl<'a> ProcessMatchScoreUpdate for PacketProcessor<'a> {
+    #[inline]
+    async fn match_score_update(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        // the frame is stamped with the sender's slot by bancho state
+        let score_frame = self
+            .packet
+            .payload
+            .ok_or(ProcessBanchoPacketError::PacketPayloadNotExists)?
+            .to_vec();
+
+        self.bancho_service
+            .match_score_update(MatchScoreUpdateRequest {
+                user_id: self.user_id,
+                score_frame,
             })
             .await?;
 
         Ok(HandleCompleted::default())
     }
 }
+
+#[async_trait]
+impl<'a> ProcessTournamentMatchInfo for PacketProcessor<'a> {
+    #[inline]
+    async fn tournament_match_info(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        let match_id = read_payload::<i32>(&self.packet)?;
+
+        Ok(self
+            .bancho_service
+            .tournament_match_info(TournamentMatchRequest {
+                user_id: self.user_id,
+                match_id,
+            })
+            .await?)
+    }
+}
+
+#[async_trait]
+impl<'a> ProcessTournamentJoinMatchChannel for PacketProcessor<'a> {
+    #[inline]
+    async fn tournament_join_match_channel(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        let match_id = read_payload::<i32>(&self.packet)?;
+
+        Ok(self
+            .bancho_service
+            .tournament_join_match_channel(TournamentMatchRequest {
+                user_id: self.user_id,
+                match_id,
+            })
+            .await?)
+    }
+}
+
+#[async_trait]
+impl<'a> ProcessTournamentLeaveMatchChannel for PacketProcessor<'a> {
+    #[inline]
+    async fn tournament_leave_match_channel(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        let match_id = read_payload::<i32>(&self.packet)?;
+
+        Ok(self
+            .bancho_service
+            .tournament_leave_match_channel(TournamentMatchRequest {
+                user_id: self.user_id,
+                match_id,
+            })
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bancho_packets::PacketId;
+
+    #[test]
+    fn test_read_payload() {
+        let payload = 7_i32.to_le_bytes();
+        let packet = Packet::with_payload(
+            PacketId::OSU_USER_RECEIVE_UPDATES,
+            Some(&payload),
+        );
+
+        assert_eq!(read_payload::<i32>(&packet).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_read_payload_not_exists() {
+        let packet = Packet::new(PacketId::OSU_USER_RECEIVE_UPDATES);
+
+        assert!(matches!(
+            read_payload::<i32>(&packet),
+            Err(ProcessBanchoPacketError::PacketPayloadNotExists)
+        ));
+    }
+
+    #[test]
+    fn test_read_payload_invalid() {
+        let payload = [1_u8, 2];
+        let packet = Packet::with_payload(
+            PacketId::OSU_USER_RECEIVE_UPDATES,
+            Some(&payload),
+        );
+
+        assert!(matches!(
+            read_payload::<i32>(&packet),
+            Err(ProcessBanchoPacketError::InvalidPacketPayload)
+        ));
+    }
+}
@@ -0,0 +1,122 @@
+use crate::ProcessBanchoPacketError;
+use bancho_packets::{PacketBuilder, PacketId};
+use pb_bancho::HandleCompleted;
+use tools::lazy_init;
+
+/// Results of the packets of a request, in request order.
+#[derive(Debug, Default)]
+pub struct PacketResults {
+    results: Vec<(PacketId, Result<HandleCompleted, ProcessBanchoPacketError>)>,
+}
+
+impl PacketResults {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn push(
+        &mut self,
+        packet_id: PacketId,
+        result: Result<HandleCompleted, ProcessBanchoPacketError>,
+    ) {
+        self.results.push((packet_id, result))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    #[inline]
+    pub fn failed(&self) -> usize {
+        self.results.iter().filter(|(_, res)| res.is_err()).count()
+    }
+
+    /// Concatenate the response packets in request order, failed packets
+    /// are logged and skipped so that they don't drop the responses of the
+    /// other packets. Returns an error only if no packet was processed.
+    pub fn into_response(
+        self,
+        user_id: i32,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        if self.failed() == self.len() {
+            return Err(ProcessBanchoPacketError::FailedToProcessAll);
+        }
+
+        let mut builder = None::<PacketBuilder>;
+
+        for (packet_id, res) in self.results {
+            match res {
+                Ok(HandleCompleted { packets: Some(packets) }) => {
+                    lazy_init!(builder => builder.extend(packets), PacketBuilder::from(packets));
+                },
+                Err(err) => warn!(
+                    target: "bancho::process_packets",
+                    "{packet_id:?}: {err:?} (<{user_id}>)"
+                ),
+                _ => {},
+            }
+        }
+
+        Ok(HandleCompleted { packets: builder.map(|b| b.build()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handled(
+        packets: Option<&[u8]>,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        Ok(HandleCompleted { packets: packets.map(|p| p.to_vec()) })
+    }
+
+    #[test]
+    fn test_mixed_packet_results() {
+        let mut results = PacketResults::new();
+        results.push(PacketId::OSU_PING, handled(Some(&[1, 2])));
+        results.push(
+            PacketId::OSU_SEND_PUBLIC_MESSAGE,
+            Err(ProcessBanchoPacketError::InvalidPacketPayload),
+        );
+        results.push(PacketId::OSU_USER_STATS_REQUEST, handled(None));
+        results.push(
+            PacketId::OSU_USER_CHANNEL_JOIN,
+            Err(ProcessBanchoPacketError::PacketPayloadNotExists),
+        );
+        results.push(PacketId::OSU_USER_PRESENCE_REQUEST, handled(Some(&[3])));
+
+        assert_eq!(results.len(), 5);
+        assert_eq!(results.failed(), 2);
+        assert_eq!(
+            results.into_response(1).unwrap().packets,
+            Some(vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn test_failed_packet_results() {
+        let mut results = PacketResults::new();
+        results.push(
+            PacketId::OSU_SEND_PUBLIC_MESSAGE,
+            Err(ProcessBanchoPacketError::InvalidPacketPayload),
+        );
+
+        assert!(matches!(
+            results.into_response(1),
+            Err(ProcessBanchoPacketError::FailedToProcessAll)
+        ));
+
+        let mut results = PacketResults::new();
+        results.push(PacketId::OSU_PING, handled(None));
+        assert_eq!(results.into_response(1).unwrap().packets, None);
+    }
+}
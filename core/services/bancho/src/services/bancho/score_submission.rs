@@ -0,0 +1,96 @@
+use peace_db::{
+    peace::entity::sea_orm_active_enums::ScoreGrade, prelude::Decimal,
+    ActiveEnum,
+};
+
+/// Hit results of a submitted score.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SubmittedHits {
+    pub n300: i32,
+    pub n100: i32,
+    pub n50: i32,
+    pub geki: i32,
+    pub katu: i32,
+    pub miss: i32,
+}
+
+impl SubmittedHits {
+    /// Accuracy in percent, `mode` is the vanilla mode (0: standard,
+    /// 1: taiko, 2: fruits, 3: mania).
+    pub fn accuracy(&self, mode: u8) -> f64 {
+        let Self { n300, n100, n50, geki, katu, miss } = *self;
+        let [n300, n100, n50, geki, katu, miss] =
+            [n300, n100, n50, geki, katu, miss].map(|n| n.max(0) as f64);
+
+        let (hit, total) = match mode {
+            0 => (
+                n300 * 300.0 + n100 * 100.0 + n50 * 50.0,
+                (n300 + n100 + n50 + miss) * 300.0,
+            ),
+            1 => (n300 + n100 * 0.5, n300 + n100 + miss),
+            2 => (n300 + n100 + n50, n300 + n100 + n50 + katu + miss),
+            3 => (
+                (n300 + geki) * 300.0
+                    + katu * 200.0
+                    + n100 * 100.0
+                    + n50 * 50.0,
+                (n300 + geki + katu + n100 + n50 + miss) * 300.0,
+            ),
+            _ => (0.0, 0.0),
+        };
+
+        if total == 0.0 {
+            return 0.0;
+        }
+
+        hit / total * 100.0
+    }
+}
+
+/// Parse the grade sent by the client, e.g. `XH`, failed scores are graded
+/// `F` whatever the client sent.
+#[inline]
+pub fn parse_score_grade(grade: &str, passed: bool) -> Option<ScoreGrade> {
+    if !passed {
+        return Some(ScoreGrade::F);
+    }
+
+    ScoreGrade::try_from_value(&grade.to_ascii_uppercase()).ok()
+}
+
+/// Round to the 2 decimals stored by the `accuracy` and `pp` columns.
+#[inline]
+pub fn to_decimal(n: f64) -> Decimal {
+    Decimal::from_f64_retain(n).unwrap_or_default().round_dp(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_submitted_hits_accuracy() {
+        let hits = SubmittedHits { n300: 98, n100: 2, ..Default::default() };
+        assert!((hits.accuracy(0) - 98.666).abs() < 0.001);
+        assert_eq!(hits.accuracy(1), 99.0);
+
+        let fc = SubmittedHits { n300: 100, ..Default::default() };
+        for mode in 0..4 {
+            assert_eq!(fc.accuracy(mode), 100.0, "mode {mode}");
+        }
+
+        let missed = SubmittedHits { n300: 1, miss: 1, ..Default::default() };
+        assert_eq!(missed.accuracy(2), 50.0);
+
+        assert_eq!(SubmittedHits::default().accuracy(0), 0.0);
+        assert_eq!(fc.accuracy(4), 0.0);
+    }
+
+    #[test]
+    fn test_parse_score_grade() {
+        assert_eq!(parse_score_grade("XH", true), Some(ScoreGrade::Xh));
+        assert_eq!(parse_score_grade("S", true), Some(ScoreGrade::S));
+        assert_eq!(parse_score_grade("S", false), Some(ScoreGrade::F));
+        assert_eq!(parse_score_grade("Z", true), None);
+    }
+}
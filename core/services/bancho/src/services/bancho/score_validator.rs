@@ -0,0 +1,55 @@
+use async_trait::async_trait;
+use domain_bancho::{GameMode, Mods};
+use pb_bancho::SubmitScoreRequest;
+use std::sync::Arc;
+
+pub type DynScoreValidator = Arc<dyn ScoreValidator + Send + Sync>;
+
+/// A score being submitted, before it is stored.
+#[derive(Debug, Clone, Copy)]
+pub struct SubmittedScore<'a> {
+    pub request: &'a SubmitScoreRequest,
+    pub mode: GameMode,
+    pub mods: Mods,
+    pub beatmap_id: i32,
+    /// Accuracy in percent.
+    pub accuracy: f64,
+    /// `None` if the score failed or its pp could not be calculated.
+    pub pp: Option<f64>,
+    pub stars: Option<f64>,
+}
+
+/// Result of a [`ScoreValidator`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScoreVerdict {
+    Accept,
+    /// The score is stored with `confidence` (how sure the validator is that
+    /// the score is illegitimate) and left unverified for review.
+    Flag {
+        reason: String,
+        confidence: i32,
+    },
+    /// The score is not stored, the client gets a neutral response.
+    Reject {
+        reason: String,
+    },
+}
+
+/// Checks run on each submitted score, e.g. impossible pp or suspicious
+/// client flags, to plug in custom anti-cheat detection.
+#[async_trait]
+pub trait ScoreValidator {
+    async fn validate(&self, score: &SubmittedScore<'_>) -> ScoreVerdict;
+}
+
+/// Accepts every score.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopScoreValidator;
+
+#[async_trait]
+impl ScoreValidator for NoopScoreValidator {
+    #[inline]
+    async fn validate(&self, _score: &SubmittedScore<'_>) -> ScoreVerdict {
+        ScoreVerdict::Accept
+    }
+}
@@ -1,45 +1,442 @@
 use crate::*;
 use bancho_packets::{server, Packet, PacketBuilder, PacketId, PacketReader};
-use core_bancho_state::DynBanchoStateService;
-use core_chat::DynChatService;
+use chrono::Utc;
+use clap_serde_derive::ClapSerde;
+use core_bancho_state::{
+    BanchoExtend, BanchoStateError, DynBanchoStateService, MultiplayerError,
+};
+use core_chat::{Channel as ChatChannel, ChatError, DynChatService};
 use core_geoip::DynGeoipService;
-use domain_bancho::BanchoCountryCode;
-use domain_chat::Platform;
+use domain_bancho::{
+    BanchoCountryCode, BanchoPrivileges, ClientHashes, GameMode, Mods,
+};
+use domain_chat::{ChannelType, Platform};
+use domain_users::{
+    CreateUser, EmailValidator, Password, PrivilegePriority, UsernameAscii,
+};
 use infra_services::{FromRpcClient, IntoService, RpcClient};
+use num_traits::ToPrimitive;
 use pb_bancho::{bancho_rpc_client::BanchoRpcClient, *};
 use pb_bancho_state::*;
-use peace_repositories::users::DynUsersRepository;
-use std::{net::IpAddr, sync::Arc, time::Instant};
-use tonic::{async_trait, transport::Channel};
-use tools::{lazy_init, tonic_utils::RawRequest};
+use pb_chat::{
+    ChannelQuery, CreateChannelRequest, JoinChannelRequest, LeaveChannelRequest,
+};
+use peace_cfg::{ConfigError, ConfigErrors, Secret, ValidateConfig};
+use peace_db::{
+    peace::entity::sea_orm_active_enums::{ScoreStatus, ScoreVersion},
+    DbErr,
+};
+use peace_difficulty::{DynDifficultyService, ScoreHits};
+use peace_repositories::{
+    scores::{BestScore, CreateScore, DynScoresRepository, LeaderboardFilter},
+    users::{CreateLoginRecord, DynUsersRepository},
+    CreateUserError, GetUserError,
+};
+use std::{
+    net::IpAddr,
+    ops::RangeInclusive,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tonic::{async_trait, IntoRequest};
+use tools::tonic_utils::{RawRequest, TracedChannel};
+
+#[derive(Debug, Clone, Parser, ClapSerde, Serialize, Deserialize)]
+pub struct CliBanchoServiceConfigs {
+    /// Path of a file containing blocked (e.g. disposable) email domains,
+    /// one domain per line, used on registration.
+    #[arg(long)]
+    pub email_domain_blocklist: Option<String>,
+
+    /// Max number of favourite beatmapsets of a user.
+    #[default(100)]
+    #[arg(long, default_value = "100")]
+    pub max_favourite_beatmapsets: u32,
+
+    /// Secret salt of the client hardware hashes recorded on login, the
+    /// hashes are only stored salted.
+    ///
+    /// If not configured, client hardware is not recorded.
+    #[arg(long)]
+    pub client_hashes_salt: Option<Secret>,
+
+    /// File containing the client hardware hashes salt, overrides
+    /// `client_hashes_salt`.
+    #[arg(long)]
+    pub client_hashes_salt_file: Option<std::path::PathBuf>,
+
+    /// Country code (e.g. `XX`) shown for sessions whose ip has no known
+    /// country, such as LAN addresses. Login records keep the real country.
+    ///
+    /// If not configured, these sessions have no country.
+    #[arg(long)]
+    pub geoip_default_country: Option<String>,
+
+    /// Number of scores of a beatmap leaderboard.
+    #[default(50)]
+    #[arg(long, default_value = "50")]
+    pub leaderboard_size: u64,
+
+    /// Max seconds a beatmap leaderboard stays cached, leaderboards are
+    /// also refreshed when a new best score is submitted.
+    #[default(300)]
+    #[arg(long, default_value = "300")]
+    pub leaderboard_cache_ttl: u64,
+
+    /// Max number of cached beatmap leaderboards.
+    #[default(10000)]
+    #[arg(long, default_value = "10000")]
+    pub leaderboard_cache_max_entries: usize,
+
+    /// Chat messages a user can send per second, `0` disables the limit.
+    /// Staff are never limited.
+    #[default(1.0)]
+    #[arg(long, default_value = "1.0")]
+    pub chat_messages_per_sec: f64,
+
+    /// Chat messages a user can send in a row before being limited to
+    /// `chat_messages_per_sec`.
+    #[default(10)]
+    #[arg(long, default_value = "10")]
+    pub chat_message_burst: u32,
+
+    /// Number of the last packets kept for each session, to debug stuck
+    /// clients. `0` disables the history.
+    #[default(32)]
+    #[arg(long, default_value = "32")]
+    pub session_packet_history_size: usize,
+}
+
+impl ValidateConfig for CliBanchoServiceConfigs {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        if let Some(path) = self.email_domain_blocklist.as_deref() {
+            if let Err(err) = EmailValidator::from_blocklist_file(path) {
+                errors.push(
+                    "email_domain_blocklist",
+                    format!("failed to load `{path}`: {err}"),
+                );
+            }
+        }
+        errors.check(
+            self.leaderboard_size > 0,
+            "leaderboard_size",
+            "must be greater than 0",
+        );
+        errors.check(
+            self.leaderboard_cache_ttl > 0,
+            "leaderboard_cache_ttl",
+            "must be greater than 0",
+        );
+        errors.check(
+            self.chat_messages_per_sec.is_finite()
+                && self.chat_messages_per_sec >= 0.0,
+            "chat_messages_per_sec",
+            "must be a number, `0` disables the limit",
+        );
+        errors.check(
+            self.chat_messages_per_sec == 0.0 || self.chat_message_burst > 0,
+            "chat_message_burst",
+            "must be greater than 0 when the chat limit is enabled",
+        );
+
+        if let Some(country) = self.geoip_default_country.as_deref() {
+            errors.check(
+                BanchoCountryCode::get_code(country) != 0,
+                "geoip_default_country",
+                format!("unknown country code `{country}`"),
+            );
+        }
+
+        errors.into_result()
+    }
+}
+
+impl CliBanchoServiceConfigs {
+    /// The blocklist file is checked by the config validation at startup,
+    /// no domain is blocked if it fails to load afterwards.
+    pub fn email_validator(&self) -> EmailValidator {
+        let path = match self.email_domain_blocklist.as_deref() {
+            Some(path) => path,
+            None => return EmailValidator::default(),
+        };
+
+        match EmailValidator::from_blocklist_file(path) {
+            Ok(validator) => {
+                info!(
+                    "Loaded {} blocked email domains from \"{path}\"",
+                    validator.blocked_domains.len()
+                );
+                validator
+            },
+            Err(err) => {
+                error!(
+                    "Failed to load email domain blocklist \"{path}\": {err}"
+                );
+                EmailValidator::default()
+            },
+        }
+    }
+
+    #[inline]
+    pub fn message_limiter(&self) -> MessageLimiter {
+        MessageLimiter::new(self.chat_messages_per_sec, self.chat_message_burst)
+    }
+}
 
 #[derive(Clone)]
 pub struct BanchoServiceImpl {
     pub users_repository: DynUsersRepository,
+    pub scores_repository: DynScoresRepository,
     pub bancho_state_service: DynBanchoStateService,
     pub password_service: DynPasswordService,
     pub bancho_background_service: DynBanchoBackgroundService,
     pub geoip_service: DynGeoipService,
     pub chat_service: DynChatService,
+    pub difficulty_service: DynDifficultyService,
+    pub leaderboard_service: DynLeaderboardService,
+    pub email_validator: Arc<EmailValidator>,
+    pub message_limiter: Arc<MessageLimiter>,
+    pub packet_history_size: usize,
+    pub max_favourite_beatmapsets: u32,
+    pub client_hashes_salt: Option<Arc<str>>,
+    pub geoip_default_country: Option<Arc<str>>,
+    pub packet_metrics: Arc<PacketMetrics>,
+    pub unhandled_packet_warnings: Arc<UnhandledPacketWarnings>,
+    pub score_validator: DynScoreValidator,
 }
 
 impl BanchoServiceImpl {
+    /// Min interval between two warnings of the same unhandled packet.
+    pub const UNHANDLED_PACKET_WARNING_INTERVAL: Duration =
+        Duration::from_secs(60);
+
     #[inline]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         users_repository: DynUsersRepository,
+        scores_repository: DynScoresRepository,
         bancho_state_service: DynBanchoStateService,
         password_service: DynPasswordService,
         bancho_background_service: DynBanchoBackgroundService,
         geoip_service: DynGeoipService,
         chat_service: DynChatService,
+        difficulty_service: DynDifficultyService,
+        leaderboard_service: DynLeaderboardService,
+        email_validator: Arc<EmailValidator>,
+        message_limiter: Arc<MessageLimiter>,
+        packet_history_size: usize,
+        max_favourite_beatmapsets: u32,
+        client_hashes_salt: Option<String>,
+        geoip_default_country: Option<String>,
     ) -> Self {
         Self {
             users_repository,
+            scores_repository,
             bancho_state_service,
             password_service,
             bancho_background_service,
             geoip_service,
             chat_service,
+            difficulty_service,
+            leaderboard_service,
+            email_validator,
+            message_limiter,
+            packet_history_size,
+            max_favourite_beatmapsets,
+            client_hashes_salt: client_hashes_salt.map(Into::into),
+            geoip_default_country: geoip_default_country.map(Into::into),
+            packet_metrics: Arc::new(PacketMetrics::new()),
+            unhandled_packet_warnings: Arc::new(UnhandledPacketWarnings::new(
+                Self::UNHANDLED_PACKET_WARNING_INTERVAL,
+            )),
+            score_validator: Arc::new(NoopScoreValidator),
+        }
+    }
+
+    #[inline]
+    pub fn with_score_validator(
+        mut self,
+        score_validator: DynScoreValidator,
+    ) -> Self {
+        self.score_validator = score_validator;
+        self
+    }
+
+    /// Store the salted hardware hashes of the client, failures are only
+    /// logged as they must not fail the login.
+    pub async fn record_client_hardware(
+        &self,
+        user_id: i32,
+        utc_offset: i32,
+        client_hashes: Option<pb_bancho::ClientHashes>,
+    ) {
+        let (salt, client_hashes) =
+            match (self.client_hashes_salt.as_deref(), client_hashes) {
+                (Some(salt), Some(client_hashes)) => (salt, client_hashes),
+                _ => return,
+            };
+
+        let hashes = ClientHashes {
+            path_hash: client_hashes.path_hash,
+            adapters: client_hashes.adapters,
+            adapters_hash: client_hashes.adapters_hash,
+            uninstall_id: client_hashes.uninstall_id,
+            disk_id: client_hashes.disk_id,
+        }
+        .salted(salt);
+
+        if let Err(err) = self
+            .users_repository
+            .record_client_hardware(user_id, utc_offset, hashes)
+            .await
+        {
+            warn!(
+                target: "core_bancho::login",
+                "Failed to record client hardware of user {user_id}: {err}"
+            );
+        }
+    }
+
+    /// Record the received packets in the packet history of the session,
+    /// failures are only logged as the history is for debugging.
+    pub async fn record_packet_history(
+        &self,
+        user_id: i32,
+        packet_ids: Vec<u8>,
+    ) {
+        if let Err(err) = self
+            .bancho_state_service
+            .record_packets(RecordPacketsRequest {
+                user_query: Some(UserQuery::UserId(user_id).into()),
+                packet_ids,
+            })
+            .await
+        {
+            warn!(
+                target: "bancho::process_packets",
+                "Failed to record the packet history of user {user_id}: {err}"
+            );
+        }
+    }
+
+    /// Privilege priority of the user, a user whose privileges can not be
+    /// read is treated as a normal user.
+    pub async fn privilege_priority(&self, user_id: i32) -> i32 {
+        match self.users_repository.get_privilege_priority(user_id).await {
+            Ok(priority) => {
+                priority.map(i32::from).unwrap_or(PrivilegePriority::NORMAL)
+            },
+            Err(err) => {
+                warn!(
+                    target: "core_bancho::privileges",
+                    "Failed to get privileges of user {user_id}: {err}"
+                );
+                PrivilegePriority::NORMAL
+            },
+        }
+    }
+
+    /// Bancho privileges of the user, a user whose privileges can not be
+    /// read is treated as a normal user.
+    pub async fn bancho_privileges(&self, user_id: i32) -> BanchoPrivileges {
+        match self.users_repository.get_bancho_privileges(user_id).await {
+            Ok(privileges) => privileges,
+            Err(err) => {
+                warn!(
+                    target: "core_bancho::privileges",
+                    "Failed to get bancho privileges of user {user_id}: {err}"
+                );
+                BanchoPrivileges::Normal
+            },
+        }
+    }
+
+    /// Persist the login in the background, so that the database can
+    /// neither slow down nor fail the login.
+    pub fn create_login_record(&self, record: CreateLoginRecord) {
+        let users_repository = self.users_repository.clone();
+
+        tokio::spawn(async move {
+            let user_id = record.user_id;
+
+            match users_repository.create_login_record(record).await {
+                Ok(record_id) => debug!(
+                    target: "core_bancho::login",
+                    "Created login record {record_id} of user {user_id}"
+                ),
+                Err(err) => warn!(
+                    target: "core_bancho::login",
+                    "Failed to create login record of user {user_id}: {err}"
+                ),
+            }
+        });
+    }
+
+    /// Get a match requested by a tournament client. Returns `None` if the
+    /// user is not a tournament client or if the match does not exist, the
+    /// client expects no reply in both cases.
+    pub async fn tournament_match(
+        &self,
+        user_id: i32,
+        match_id: i32,
+    ) -> Result<Option<GetMatchResponse>, BanchoServiceError> {
+        let session = self
+            .bancho_state_service
+            .get_user_session_with_fields(RawUserQueryWithFields {
+                user_query: Some(UserQuery::UserId(user_id).into()),
+                fields: UserSessionFields::BanchoPrivileges.bits(),
+            })
+            .await?;
+
+        let privileges = BanchoPrivileges::from(
+            session.bancho_privileges.unwrap_or_default(),
+        );
+
+        if !privileges.contains(BanchoPrivileges::Tournament) {
+            warn!(
+                "User {user_id} requested match {match_id} without the \
+                tournament privilege"
+            );
+            return Ok(None);
+        }
+
+        match self
+            .bancho_state_service
+            .get_match(GetMatchRequest { match_id })
+            .await
+        {
+            Ok(m) => Ok(Some(m)),
+            Err(BanchoStateError::MultiplayerError(
+                MultiplayerError::MatchNotExists,
+            )) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// The spectator channel is disposed by chat once the last spectator
+    /// left it.
+    pub async fn leave_spectator_channel(
+        &self,
+        user_query: UserQuery,
+        host_id: i32,
+    ) -> Result<(), BanchoServiceError> {
+        match self
+            .chat_service
+            .leave_channel(LeaveChannelRequest {
+                channel_query: Some(
+                    ChannelQuery::ChannelId(ChatChannel::spectator_channel_id(
+                        host_id,
+                    ))
+                    .into(),
+                ),
+                user_query: Some(user_query.into()),
+            })
+            .await
+        {
+            Ok(_) | Err(ChatError::ChannelNotExists) => Ok(()),
+            Err(err) => Err(err.into()),
         }
     }
 }
@@ -69,7 +466,7 @@ impl Login for BanchoServiceImpl {
             utc_offset,
             display_city,
             only_friend_pm_allowed,
-            ..
+            client_hashes,
         } = request;
 
         info!(
@@ -81,7 +478,6 @@ impl Login for BanchoServiceImpl {
         // MOCK -------------------
         #[cfg(feature = "bancho-mock-test")]
         let user = {
-            use chrono::Utc;
             use domain_users::{UsernameAscii, UsernameUnicode};
             use tools::atomic::{AtomicOperation, U64};
 
@@ -133,19 +529,68 @@ impl Login for BanchoServiceImpl {
             .get_user(None, Some(username.as_str()), Some(username.as_str()))
             .await?;
 
+        // Migrate the password hash if it was encoded with weaker params
         #[cfg(not(feature = "bancho-mock-test"))]
-        let () = self
+        if let Some(rehashed) = self
             .password_service
-            .verify_password(user.password.as_str(), password.as_str())
-            .await?;
+            .verify_and_maybe_rehash(user.password.as_str(), password.as_str())
+            .await?
+        {
+            if let Err(err) = self
+                .users_repository
+                .change_user_password(
+                    Some(user.id),
+                    None,
+                    None,
+                    rehashed.into(),
+                )
+                .await
+            {
+                warn!(
+                    target: LOG_TARGET,
+                    "Failed to persist rehashed password for user {}({}): {err}",
+                    user.name, user.id
+                );
+            }
+        }
 
-        let geoip_data =
-            self.geoip_service.lookup_with_ip_address(client_ip).await.ok();
+        let geoip = SessionGeoip::lookup(
+            &self.geoip_service,
+            client_ip,
+            self.geoip_default_country.as_deref(),
+        )
+        .await;
+
+        let privileges = self.privilege_priority(user.id).await;
+
+        if PrivilegePriority::is_banned(privileges) {
+            info!(
+                target: LOG_TARGET,
+                "Refused login of banned user {}({})", user.name, user.id
+            );
+            return Err(BanchoServiceError::UserBanned);
+        }
 
-        let country_code = geoip_data
-            .as_ref()
-            .map(|d| BanchoCountryCode::get_code(&d.country.code))
-            .unwrap_or_default();
+        let bancho_privileges = self.bancho_privileges(user.id).await;
+
+        let silence_end =
+            match self.users_repository.get_silence_end(user.id).await {
+                Ok(silence_end) => silence_end
+                    .map(|silence_end| silence_end.timestamp())
+                    .filter(|silence_end| *silence_end > Utc::now().timestamp())
+                    .unwrap_or_default(),
+                Err(err) => {
+                    warn!(
+                        target: LOG_TARGET,
+                        "Failed to get silence of user {}({}): {err}",
+                        user.name, user.id
+                    );
+                    0
+                },
+            };
+
+        let login_record =
+            geoip.login_record(user.id, client_ip, &client_version);
 
         let CreateUserSessionResponse { session_id, signature } = self
             .bancho_state_service
@@ -153,28 +598,32 @@ impl Login for BanchoServiceImpl {
                 user_id: user.id,
                 username: user.name.to_owned(),
                 username_unicode: user.name_unicode.to_owned(),
-                privileges: 1, // todo
+                privileges,
                 client_version,
                 utc_offset,
                 display_city,
                 only_friend_pm_allowed,
-                bancho_privileges: 1, // todo
-                connection_info: Some(ConnectionInfo {
-                    ip: client_ip.to_string(),
-                    geoip_data: geoip_data.map(|g| g.into()),
-                }),
-                country_code: country_code as i32,
+                bancho_privileges: bancho_privileges.bits(),
+                connection_info: Some(geoip.connection_info(client_ip)),
+                country_code: geoip.country_code() as i32,
+                silence_end,
+                packet_history_size: self.packet_history_size as u32,
             })
             .await?;
 
+        self.record_client_hardware(user.id, utc_offset, client_hashes).await;
+        self.create_login_record(login_record);
+
         if let Err(err) = self
             .chat_service
             .login(pb_chat::LoginRequest {
                 user_id: user.id,
                 username: user.name.to_owned(),
                 username_unicode: user.name_unicode,
-                privileges: 1, // todo
+                privileges,
                 platforms: Platform::Bancho.bits(),
+                silence_end,
+                bancho_privileges: bancho_privileges.bits(),
             })
             .await
         {
@@ -187,8 +636,11 @@ impl Login for BanchoServiceImpl {
         let packet_builder = PacketBuilder::new()
             .add(server::ProtocolVersion::new(19))
             .add(server::LoginReply::success(user.id))
-            .add(server::BanchoPrivileges::new(1))
-            .add(server::SilenceEnd::new(0)) // todo
+            .add(server::BanchoPrivileges::new(bancho_privileges.bits()))
+            .add(server::SilenceEnd::new(BanchoExtend::silence_remaining(
+                silence_end,
+                Utc::now().timestamp(),
+            )))
             .add(server::FriendsList::new(&[]));
 
         info!(
@@ -209,63 +661,719 @@ impl Login for BanchoServiceImpl {
     }
 }
 #[async_trait]
-impl BatchProcessPackets for BanchoServiceImpl {
-    async fn batch_process_bancho_packets(
+impl ClientRegister for BanchoServiceImpl {
+    async fn client_register(
         &self,
-        request: BatchProcessBanchoPacketsRequest,
-    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
-        const LOG_TARGET: &str = "bancho::process_packets";
+        request: ClientRegisterRequest,
+    ) -> Result<ClientRegisterResponse, BanchoServiceError> {
+        const LOG_TARGET: &str = "core_bancho::client_register";
+        const USERNAME_LENGTH: RangeInclusive<usize> = 2..=15;
+        const PASSWORD_LENGTH: RangeInclusive<usize> = 8..=128;
+
+        let ClientRegisterRequest { username, email, password, check_only } =
+            request;
+
+        let mut res = ClientRegisterResponse::default();
+
+        let name = match UsernameAscii::new(&username) {
+            Ok(name) if !USERNAME_LENGTH.contains(&name.len()) => {
+                res.username_errors.push(format!(
+                    "Username must be {} to {} characters long.",
+                    USERNAME_LENGTH.start(),
+                    USERNAME_LENGTH.end()
+                ));
+                None
+            },
+            Ok(name) => match self
+                .users_repository
+                .get_user_by_username(name.as_str())
+                .await
+            {
+                Ok(_) => {
+                    res.username_errors
+                        .push("Username is already in use.".into());
+                    None
+                },
+                Err(GetUserError::UserNotExists) => Some(name),
+                Err(err) => return Err(err.into()),
+            },
+            Err(err) => {
+                res.username_errors.push(err.to_string());
+                None
+            },
+        };
 
-        let BatchProcessBanchoPacketsRequest { user_id, packets } = request;
+        let email = match self.email_validator.validate(&email) {
+            Ok(email) => match self
+                .users_repository
+                .get_user_by_email(email.as_str())
+                .await
+            {
+                Ok(_) => {
+                    res.email_errors.push("Email is already in use.".into());
+                    None
+                },
+                Err(GetUserError::UserNotExists) => Some(email),
+                Err(err) => return Err(err.into()),
+            },
+            Err(err) => {
+                res.email_errors.push(err.to_string());
+                None
+            },
+        };
+
+        if !PASSWORD_LENGTH.contains(&password.len()) {
+            res.password_errors.push(format!(
+                "Password must be {} to {} characters long.",
+                PASSWORD_LENGTH.start(),
+                PASSWORD_LENGTH.end()
+            ));
+        } else if password.eq_ignore_ascii_case(&username) {
+            res.password_errors
+                .push("Password cannot be the same as username.".into());
+        }
+
+        let (name, email) = match (name, email) {
+            (Some(name), Some(email)) if res.password_errors.is_empty() => {
+                (name, email)
+            },
+            _ => return Ok(res),
+        };
 
-        let reader = PacketReader::new(&packets);
-        let (mut processed, mut failed) = (0, 0);
+        if check_only {
+            return Ok(res);
+        }
 
-        let mut builder = None::<PacketBuilder>;
+        // Passwords are md5 hashed by the client on login
+        let password =
+            Password::hash_password(format!("{:x}", md5::compute(password)))?;
 
-        for packet in reader {
-            info!(target: LOG_TARGET, "Received: {packet}");
-            let start = Instant::now();
+        let username = name.as_str().to_owned();
 
-            match self.process_bancho_packet(user_id, packet).await {
-                Ok(HandleCompleted { packets: Some(packets) }) => {
-                    lazy_init!(builder => builder.extend(packets), PacketBuilder::from(packets));
-                },
-                Err(err) => {
-                    failed += 1;
+        let user_id = match self
+            .users_repository
+            .create_user(CreateUser {
+                name,
+                name_unicode: None,
+                password,
+                email,
+                country: None,
+            })
+            .await
+        {
+            Ok(res) => res.last_insert_id,
+            // Taken by a concurrent registration since the checks above
+            Err(CreateUserError::UsernameExists) => {
+                res.username_errors.push("Username is already in use.".into());
+                return Ok(res);
+            },
+            Err(CreateUserError::EmailExists) => {
+                res.email_errors.push("Email is already in use.".into());
+                return Ok(res);
+            },
+            Err(err) => {
+                return Err(BanchoServiceError::CreateUserError(
+                    err.to_string(),
+                ))
+            },
+        };
+
+        info!(target: LOG_TARGET, "User registered: {username} [{user_id}]");
+
+        res.user_id = Some(user_id);
+        Ok(res)
+    }
+}
+
+#[async_trait]
+impl AddFavouriteBeatmapset for BanchoServiceImpl {
+    async fn add_favourite_beatmapset(
+        &self,
+        request: AddFavouriteBeatmapsetRequest,
+    ) -> Result<AddFavouriteBeatmapsetResponse, BanchoServiceError> {
+        use add_favourite_beatmapset_response::Status;
+
+        let AddFavouriteBeatmapsetRequest { user_id, beatmapset_id } = request;
+
+        let favourites = self
+            .users_repository
+            .get_favourite_beatmapsets(user_id)
+            .await
+            .map_err(|err| BanchoServiceError::DbError(err.to_string()))?;
+
+        let status = if favourites.contains(&beatmapset_id) {
+            Status::AlreadyAdded
+        } else if favourites.len() >= self.max_favourite_beatmapsets as usize {
+            Status::TooManyFavourites
+        } else if self
+            .users_repository
+            .add_favourite_beatmapset(user_id, beatmapset_id)
+            .await
+            .map_err(|err| BanchoServiceError::DbError(err.to_string()))?
+        {
+            Status::Added
+        } else {
+            Status::AlreadyAdded
+        };
+
+        Ok(AddFavouriteBeatmapsetResponse { status: status as i32 })
+    }
+}
+
+#[async_trait]
+impl GetFavouriteBeatmapsets for BanchoServiceImpl {
+    async fn get_favourite_beatmapsets(
+        &self,
+        request: GetFavouriteBeatmapsetsRequest,
+    ) -> Result<GetFavouriteBeatmapsetsResponse, BanchoServiceError> {
+        let beatmapset_ids = self
+            .users_repository
+            .get_favourite_beatmapsets(request.user_id)
+            .await
+            .map_err(|err| BanchoServiceError::DbError(err.to_string()))?;
+
+        Ok(GetFavouriteBeatmapsetsResponse { beatmapset_ids })
+    }
+}
+
+#[async_trait]
+impl GetUsersSharingHardware for BanchoServiceImpl {
+    async fn get_users_sharing_hardware(
+        &self,
+        request: GetUsersSharingHardwareRequest,
+    ) -> Result<GetUsersSharingHardwareResponse, BanchoServiceError> {
+        let user_ids = self
+            .users_repository
+            .get_users_sharing_client_hardware(request.user_id)
+            .await
+            .map_err(|err| BanchoServiceError::DbError(err.to_string()))?;
+
+        Ok(GetUsersSharingHardwareResponse { user_ids })
+    }
+}
+
+#[async_trait]
+impl SilenceUser for BanchoServiceImpl {
+    async fn silence_user(
+        &self,
+        request: SilenceUserRequest,
+    ) -> Result<SilenceUserResponse, BanchoServiceError> {
+        let SilenceUserRequest { user_id, duration, reason } = request;
+
+        let duration = duration.clamp(0, i32::MAX as i64);
+        let silence_end = Utc::now() + chrono::Duration::seconds(duration);
+
+        self.users_repository
+            .silence_user(user_id, silence_end, reason)
+            .await
+            .map_err(|err| BanchoServiceError::DbError(err.to_string()))?;
+
+        let silence_end =
+            if duration > 0 { silence_end.timestamp() } else { 0 };
+
+        // offline users get the silence when they log in
+        match self
+            .bancho_state_service
+            .update_silence_end(UpdateSilenceEndRequest {
+                user_query: Some(UserQuery::UserId(user_id).into()),
+                silence_end,
+            })
+            .await
+        {
+            Ok(_) | Err(BanchoStateError::SessionNotExists) => {},
+            Err(err) => return Err(err.into()),
+        }
+
+        match self
+            .chat_service
+            .update_silence_end(UserQuery::UserId(user_id), silence_end)
+            .await
+        {
+            Ok(_) | Err(ChatError::SessionNotExists) => {},
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(SilenceUserResponse { silence_end })
+    }
+}
+
+#[async_trait]
+impl SubmitScore for BanchoServiceImpl {
+    async fn submit_score(
+        &self,
+        request: SubmitScoreRequest,
+    ) -> Result<SubmitScoreResponse, BanchoServiceError> {
+        const LOG_TARGET: &str = "core_bancho::submit_score";
+
+        let db_err = |err: DbErr| BanchoServiceError::DbError(err.to_string());
+
+        let mods = Mods::from(request.mods);
+        let mode = u8::try_from(request.mode)
+            .ok()
+            .and_then(|mode| GameMode::from_score(mode, mods))
+            .ok_or_else(|| {
+                BanchoServiceError::InvalidScore(format!(
+                    "invalid mode: {}",
+                    request.mode
+                ))
+            })?;
+        let grade = parse_score_grade(&request.grade, request.passed)
+            .ok_or_else(|| {
+                BanchoServiceError::InvalidScore(format!(
+                    "invalid grade: {}",
+                    request.grade
+                ))
+            })?;
 
-                    warn!(target: LOG_TARGET, "{err:?} (<{user_id}>)")
+        let beatmap = self
+            .scores_repository
+            .get_beatmap_by_md5(&request.beatmap_md5)
+            .await
+            .map_err(db_err)?
+            .ok_or(BanchoServiceError::BeatmapNotSubmitted)?;
+
+        if self
+            .scores_repository
+            .score_exists(mode, &request.score_md5)
+            .await
+            .map_err(db_err)?
+        {
+            return Err(BanchoServiceError::DuplicateScore);
+        }
+
+        let hits = SubmittedHits {
+            n300: request.n300,
+            n100: request.n100,
+            n50: request.n50,
+            geki: request.geki,
+            katu: request.katu,
+            miss: request.miss,
+        };
+        let accuracy = hits.accuracy(mode.vanilla_mode());
+
+        // The hits of failed scores do not cover the whole beatmap
+        let pp = if request.passed {
+            let n = |n: i32| n.max(0) as usize;
+            match self
+                .difficulty_service
+                .pp(
+                    beatmap.bid,
+                    mode.vanilla_mode(),
+                    request.mods,
+                    ScoreHits {
+                        combo: n(request.max_combo),
+                        n300: n(request.n300),
+                        n100: n(request.n100),
+                        n50: n(request.n50),
+                        n_misses: n(request.miss),
+                        n_geki: n(request.geki),
+                        n_katu: n(request.katu),
+                    },
+                )
+                .await
+            {
+                Ok(result) => Some(result),
+                Err(err) => {
+                    warn!(
+                        target: LOG_TARGET,
+                        "Failed to calculate pp of score {} on beatmap {}, \
+                        storing it without pp: {err}",
+                        request.score_md5,
+                        beatmap.bid
+                    );
+                    None
                 },
-                _ => {},
             }
+        } else {
+            None
+        };
 
-            processed += 1;
+        let confidence = match self
+            .score_validator
+            .validate(&SubmittedScore {
+                request: &request,
+                mode,
+                mods,
+                beatmap_id: beatmap.bid,
+                accuracy,
+                pp: pp.as_ref().map(|result| result.pp),
+                stars: pp.as_ref().map(|result| result.stars),
+            })
+            .await
+        {
+            ScoreVerdict::Accept => None,
+            ScoreVerdict::Flag { reason, confidence } => {
+                warn!(
+                    target: LOG_TARGET,
+                    "Score {} of user {} flagged (confidence {confidence}): \
+                    {reason}",
+                    request.score_md5,
+                    request.user_id
+                );
+                Some(confidence)
+            },
+            ScoreVerdict::Reject { reason } => {
+                warn!(
+                    target: LOG_TARGET,
+                    "Score {} of user {} rejected: {reason}",
+                    request.score_md5,
+                    request.user_id
+                );
+                return Err(BanchoServiceError::ScoreRejected(reason));
+            },
+        };
 
-            info!(target: LOG_TARGET, " - Processed in: {:?}", start.elapsed());
+        let created = self
+            .scores_repository
+            .create_score(
+                mode,
+                CreateScore {
+                    user_id: request.user_id,
+                    score_md5: request.score_md5,
+                    map_md5: request.beatmap_md5,
+                    score_version: if mods.contains(Mods::ScoreV2) {
+                        ScoreVersion::V2
+                    } else {
+                        ScoreVersion::V1
+                    },
+                    score: request.score,
+                    accuracy: to_decimal(accuracy),
+                    combo: request.max_combo,
+                    mods: request.mods as i32,
+                    n300: request.n300,
+                    n100: request.n100,
+                    n50: request.n50,
+                    miss: request.miss,
+                    geki: request.geki,
+                    katu: request.katu,
+                    playtime: request.playtime,
+                    perfect: request.perfect,
+                    passed: request.passed,
+                    grade,
+                    client_flags: request.client_flags,
+                    client_version: request.client_version,
+                    pp: pp.map(|result| {
+                        (
+                            to_decimal(result.pp),
+                            Some(serde_json::json!({
+                                "pp": result.pp,
+                                "stars": result.stars,
+                            })),
+                        )
+                    }),
+                    confidence,
+                },
+            )
+            .await
+            .map_err(db_err)?;
+
+        if created.status == ScoreStatus::High {
+            self.leaderboard_service
+                .invalidate_leaderboards(mode, &beatmap.md5)
+                .await;
+        }
+
+        let summary = |best: BestScore| ScoreSummary {
+            score: best.score,
+            max_combo: best.combo,
+            accuracy: best.accuracy.to_f64().unwrap_or_default(),
+            pp: best.pp.and_then(|pp| pp.to_f64()).unwrap_or_default(),
+        };
+
+        Ok(SubmitScoreResponse {
+            score_id: mode.client_score_id(created.score_id),
+            beatmap_id: beatmap.bid,
+            beatmapset_id: beatmap.sid,
+            score: Some(ScoreSummary {
+                score: request.score,
+                max_combo: request.max_combo,
+                accuracy,
+                pp: pp.map(|result| result.pp).unwrap_or_default(),
+            }),
+            previous_best: created.previous_best.map(summary),
+            personal_best: created.status == ScoreStatus::High,
+        })
+    }
+}
+
+#[async_trait]
+impl GetBeatmapScores for BanchoServiceImpl {
+    async fn get_beatmap_scores(
+        &self,
+        request: GetBeatmapScoresRequest,
+    ) -> Result<GetBeatmapScoresResponse, BanchoServiceError> {
+        let db_err = |err: DbErr| BanchoServiceError::DbError(err.to_string());
+
+        let mode = match u8::try_from(request.mode)
+            .ok()
+            .and_then(|mode| GameMode::from_score(mode, request.mods.into()))
+        {
+            Some(mode) => mode,
+            None => return Ok(GetBeatmapScoresResponse::default()),
+        };
+
+        let beatmap = match self
+            .scores_repository
+            .get_beatmap_by_md5(&request.beatmap_md5)
+            .await
+            .map_err(db_err)?
+        {
+            Some(beatmap) => beatmap,
+            None => return Ok(GetBeatmapScoresResponse::default()),
+        };
+
+        let (rank_status, has_leaderboard) =
+            client_rank_status(&beatmap.rank_status);
+        let info = BeatmapScoresInfo {
+            beatmap_id: beatmap.bid,
+            beatmapset_id: beatmap.sid,
+            rank_status,
+            artist: beatmap.artist,
+            title: beatmap.title,
+            version: beatmap.diff_name,
+        };
+
+        if !has_leaderboard {
+            return Ok(GetBeatmapScoresResponse {
+                beatmap: Some(info),
+                ..Default::default()
+            });
         }
 
-        if failed == processed {
-            return Err(ProcessBanchoPacketError::FailedToProcessAll);
+        let filter = match BoardType::from_client(request.board_type) {
+            BoardType::Global => LeaderboardFilter::Global,
+            BoardType::Mods => LeaderboardFilter::Mods(request.mods as i32),
+            BoardType::Friends => friends_filter(
+                request.user_id,
+                self.users_repository
+                    .get_friend_ids(request.user_id)
+                    .await
+                    .map_err(db_err)?,
+            ),
+            BoardType::Country => country_filter(
+                request.user_id,
+                self.users_repository
+                    .get_user_by_id(request.user_id)
+                    .await?
+                    .country,
+            ),
+        };
+
+        let leaderboard = self
+            .leaderboard_service
+            .get_leaderboard(mode, &beatmap.md5, &filter)
+            .await?;
+
+        let personal_best = match leaderboard
+            .iter()
+            .position(|score| score.user_id == request.user_id)
+        {
+            Some(index) => Some((leaderboard[index].clone(), index as u64 + 1)),
+            None => self
+                .scores_repository
+                .get_user_leaderboard_score(
+                    mode,
+                    &beatmap.md5,
+                    &filter,
+                    request.user_id,
+                )
+                .await
+                .map_err(db_err)?,
+        };
+
+        Ok(GetBeatmapScoresResponse {
+            beatmap: Some(info),
+            scores: leaderboard
+                .iter()
+                .enumerate()
+                .map(|(index, score)| {
+                    beatmap_score(mode, score, index as u64 + 1)
+                })
+                .collect(),
+            personal_best: personal_best
+                .map(|(score, rank)| beatmap_score(mode, &score, rank)),
+        })
+    }
+}
+
+#[async_trait]
+impl AuthenticateUser for BanchoServiceImpl {
+    async fn authenticate_user(
+        &self,
+        request: AuthenticateUserRequest,
+    ) -> Result<AuthenticateUserResponse, BanchoServiceError> {
+        let AuthenticateUserRequest { username, password_md5 } = request;
+
+        let user = self
+            .users_repository
+            .get_user(None, Some(username.as_str()), Some(username.as_str()))
+            .await?;
+
+        self.password_service
+            .verify_password(user.password.as_str(), password_md5.as_str())
+            .await?;
+
+        if PrivilegePriority::is_banned(self.privilege_priority(user.id).await)
+        {
+            return Err(BanchoServiceError::UserBanned);
         }
 
-        Ok(HandleCompleted { packets: builder.map(|b| b.build()) })
+        Ok(AuthenticateUserResponse { user_id: user.id })
     }
 }
 
 #[async_trait]
-impl ProcessPackets for BanchoServiceImpl {
-    #[inline]
-    async fn process_bancho_packet(
+impl GetScoreMd5 for BanchoServiceImpl {
+    async fn get_score_md5(
+        &self,
+        request: GetScoreMd5Request,
+    ) -> Result<GetScoreMd5Response, BanchoServiceError> {
+        let GetScoreMd5Request { mode, score_id } = request;
+
+        // Relax and autopilot scores are found from their client score id
+        let (mode, score_id) = match u8::try_from(mode)
+            .ok()
+            .and_then(|mode| GameMode::from_client_score_id(mode, score_id))
+        {
+            Some(mode_score_id) => mode_score_id,
+            None => return Ok(GetScoreMd5Response { score_md5: None }),
+        };
+
+        let score_md5 = self
+            .scores_repository
+            .get_score_md5(mode, score_id)
+            .await
+            .map_err(|err| BanchoServiceError::DbError(err.to_string()))?;
+
+        Ok(GetScoreMd5Response { score_md5 })
+    }
+}
+
+#[async_trait]
+impl AddReplayView for BanchoServiceImpl {
+    async fn add_replay_view(
+        &self,
+        request: AddReplayViewRequest,
+    ) -> Result<AddReplayViewResponse, BanchoServiceError> {
+        let views = self
+            .scores_repository
+            .add_replay_view(&request.score_md5)
+            .await
+            .map_err(|err| BanchoServiceError::DbError(err.to_string()))?;
+
+        Ok(AddReplayViewResponse { views })
+    }
+}
+
+#[async_trait]
+impl BatchProcessPackets for BanchoServiceImpl {
+    async fn batch_process_bancho_packets(
+        &self,
+        client_ip: Option<IpAddr>,
+        request: BatchProcessBanchoPacketsRequest,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        let BatchProcessBanchoPacketsRequest { user_id, packets } = request;
+
+        self.process_bancho_packets(
+            user_id,
+            client_ip,
+            PacketReader::new(&packets),
+        )
+        .await
+        .into_response(user_id)
+    }
+}
+
+impl BanchoServiceImpl {
+    /// Process every packet of the reader, a failed packet does not stop
+    /// the following ones from being processed.
+    pub async fn process_bancho_packets(
+        &self,
+        user_id: i32,
+        client_ip: Option<IpAddr>,
+        reader: PacketReader<'_>,
+    ) -> PacketResults {
+        const LOG_TARGET: &str = "bancho::process_packets";
+
+        let mut results = PacketResults::new();
+        let packets = reader.collect::<Vec<_>>();
+
+        if self.packet_history_size > 0 {
+            self.record_packet_history(
+                user_id,
+                packets.iter().map(|packet| packet.id as u8).collect(),
+            )
+            .await;
+        }
+
+        for packet in packets {
+            info!(target: LOG_TARGET, "Received: {packet}");
+            let (packet_id, start) = (packet.id, Instant::now());
+
+            results.push(
+                packet_id,
+                self.process_packet(user_id, client_ip, packet).await,
+            );
+
+            info!(target: LOG_TARGET, " - Processed in: {:?}", start.elapsed());
+        }
+
+        results
+    }
+
+    /// Run the handler of the packet and record its metrics.
+    async fn process_packet(
         &self,
         user_id: i32,
+        client_ip: Option<IpAddr>,
+        packet: Packet<'_>,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        let packet_id = packet.id;
+        let start = Instant::now();
+
+        let (res, outcome) = match self
+            .dispatch_packet(user_id, client_ip, packet)
+            .await
+        {
+            // Unexpected packets are skipped, so that the other packets of
+            // the request are still processed
+            Err(ProcessBanchoPacketError::UnhandledPacket(packet_id)) => {
+                if let Some(suppressed) =
+                    self.unhandled_packet_warnings.should_warn(packet_id).await
+                {
+                    warn!(
+                        target: "bancho::process_packets",
+                        "Unhandled packet: {packet_id:?} (<{user_id}>), \
+                        {suppressed} warnings suppressed"
+                    );
+                }
+
+                (Ok(HandleCompleted::default()), PacketOutcome::Unhandled)
+            },
+            Ok(res) => (Ok(res), PacketOutcome::Handled),
+            Err(err) => (Err(err), PacketOutcome::Errored),
+        };
+
+        self.packet_metrics.observe(packet_id, outcome, start.elapsed());
+
+        res
+    }
+
+    /// Run the handler of the packet.
+    async fn dispatch_packet(
+        &self,
+        user_id: i32,
+        client_ip: Option<IpAddr>,
         packet: Packet<'_>,
     ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
         let processor = PacketProcessor {
             user_id,
+            client_ip,
             packet,
             bancho_service: self,
             bancho_state_service: self.bancho_state_service.as_ref(),
             chat_service: self.chat_service.as_ref(),
+            message_limiter: &self.message_limiter,
         };
 
         Ok(match processor.packet.id {
@@ -299,49 +1407,44 @@ impl ProcessPackets for BanchoServiceImpl {
             PacketId::OSU_USER_RECEIVE_UPDATES => {
                 processor.user_receive_updates().await?
             },
-            PacketId::OSU_USER_FRIEND_ADD => todo!(),
-            PacketId::OSU_USER_FRIEND_REMOVE => todo!(),
             PacketId::OSU_USER_TOGGLE_BLOCK_NON_FRIEND_DMS => {
                 processor.user_toggle_block_non_friend_dms().await?
             },
             PacketId::OSU_USER_LOGOUT => processor.user_logout().await?,
-            PacketId::OSU_USER_SET_AWAY_MESSAGE => todo!(),
             PacketId::OSU_USER_PRESENCE_REQUEST => {
                 processor.user_presence_request().await?
             },
             // Spectate
-            PacketId::OSU_SPECTATE_START => todo!(),
-            PacketId::OSU_SPECTATE_STOP => todo!(),
-            PacketId::OSU_SPECTATE_CANT => todo!(),
-            PacketId::OSU_SPECTATE_FRAMES => todo!(),
+            PacketId::OSU_SPECTATE_START => processor.spectate_start().await?,
+            PacketId::OSU_SPECTATE_STOP => processor.spectate_stop().await?,
             // Multiplayer
-            PacketId::OSU_USER_PART_LOBBY => todo!(),
-            PacketId::OSU_USER_JOIN_LOBBY => todo!(),
-            PacketId::OSU_USER_PART_MATCH => todo!(),
-            PacketId::OSU_USER_MATCH_READY => todo!(),
-            PacketId::OSU_USER_CREATE_MATCH => todo!(),
-            PacketId::OSU_USER_JOIN_MATCH => todo!(),
-            PacketId::OSU_MATCH_START => todo!(),
-            PacketId::OSU_MATCH_COMPLETE => todo!(),
-            PacketId::OSU_MATCH_LOAD_COMPLETE => todo!(),
-            PacketId::OSU_MATCH_NO_BEATMAP => todo!(),
-            PacketId::OSU_MATCH_NOT_READY => todo!(),
-            PacketId::OSU_MATCH_FAILED => todo!(),
-            PacketId::OSU_MATCH_HAS_BEATMAP => todo!(),
-            PacketId::OSU_MATCH_SKIP_REQUEST => todo!(),
-            PacketId::OSU_MATCH_CHANGE_TEAM => todo!(),
-            PacketId::OSU_MATCH_CHANGE_SLOT => todo!(),
-            PacketId::OSU_MATCH_LOCK => todo!(),
-            PacketId::OSU_MATCH_CHANGE_SETTINGS => todo!(),
-            PacketId::OSU_MATCH_SCORE_UPDATE => todo!(),
-            PacketId::OSU_MATCH_CHANGE_MODS => todo!(),
-            PacketId::OSU_MATCH_TRANSFER_HOST => todo!(),
-            PacketId::OSU_MATCH_INVITE => todo!(),
-            PacketId::OSU_MATCH_CHANGE_PASSWORD => todo!(),
+            PacketId::OSU_USER_PART_LOBBY => {
+                processor.user_part_lobby().await?
+            },
+            PacketId::OSU_USER_JOIN_LOBBY => {
+                processor.user_join_lobby().await?
+            },
+            PacketId::OSU_USER_CREATE_MATCH => processor.match_create().await?,
+            PacketId::OSU_USER_JOIN_MATCH => processor.match_join().await?,
+            PacketId::OSU_USER_PART_MATCH => processor.match_part().await?,
+            PacketId::OSU_MATCH_START => processor.match_start().await?,
+            PacketId::OSU_MATCH_COMPLETE => processor.match_complete().await?,
+            PacketId::OSU_MATCH_LOAD_COMPLETE => {
+                processor.match_load_complete().await?
+            },
+            PacketId::OSU_MATCH_SCORE_UPDATE => {
+                processor.match_score_update().await?
+            },
             // Tournament
-            PacketId::OSU_TOURNAMENT_MATCH_INFO_REQUEST => todo!(),
-            PacketId::OSU_TOURNAMENT_JOIN_MATCH_CHANNEL => todo!(),
-            PacketId::OSU_TOURNAMENT_LEAVE_MATCH_CHANNEL => todo!(),
+            PacketId::OSU_TOURNAMENT_MATCH_INFO_REQUEST => {
+                processor.tournament_match_info().await?
+            },
+            PacketId::OSU_TOURNAMENT_JOIN_MATCH_CHANNEL => {
+                processor.tournament_join_match_channel().await?
+            },
+            PacketId::OSU_TOURNAMENT_LEAVE_MATCH_CHANNEL => {
+                processor.tournament_leave_match_channel().await?
+            },
             _ => {
                 return Err(ProcessBanchoPacketError::UnhandledPacket(
                     processor.packet.id,
@@ -350,6 +1453,38 @@ impl ProcessPackets for BanchoServiceImpl {
         })
     }
 }
+
+#[async_trait]
+impl ProcessPackets for BanchoServiceImpl {
+    /// Packets processed through this rpc are not sent by the client
+    /// directly, so they carry no client ip.
+    #[inline]
+    async fn process_bancho_packet(
+        &self,
+        user_id: i32,
+        packet: Packet<'_>,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        self.process_packet(user_id, None, packet).await
+    }
+}
+
+#[async_trait]
+impl GetPacketMetrics for BanchoServiceImpl {
+    async fn get_packet_metrics(
+        &self,
+    ) -> Result<PacketMetricsResponse, BanchoServiceError> {
+        Ok(self.packet_metrics.snapshot())
+    }
+}
+
+#[async_trait]
+impl GetLeaderboardCacheMetrics for BanchoServiceImpl {
+    async fn get_leaderboard_cache_metrics(
+        &self,
+    ) -> Result<LeaderboardCacheMetricsResponse, BanchoServiceError> {
+        Ok(self.leaderboard_service.get_cache_metrics().await)
+    }
+}
 #[async_trait]
 impl ClientPing for BanchoServiceImpl {
     async fn ping(&self) -> Result<HandleCompleted, BanchoServiceError> {
@@ -513,11 +1648,69 @@ impl RequestPresence for BanchoServiceImpl {
     }
 }
 
+#[async_trait]
+impl SpectateStart for BanchoServiceImpl {
+    async fn spectate_start(
+        &self,
+        request: SpectateStartRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        let SpectateStartRequest { user_id, target_id } = request;
+
+        let res = self
+            .bancho_state_service
+            .start_spectating(StartSpectatingRequest {
+                user_query: Some(UserQuery::UserId(user_id).into()),
+                host_id: target_id,
+            })
+            .await?;
+
+        if let Some(previous_host_id) = res.previous_host_id {
+            self.leave_spectator_channel(
+                UserQuery::UserId(user_id),
+                previous_host_id,
+            )
+            .await?;
+        }
+
+        // created with the first spectator, the host is joined into it too
+        self.chat_service
+            .create_channel(CreateChannelRequest {
+                id: ChatChannel::spectator_channel_id(target_id),
+                name: ChatChannel::spectator_channel_name(target_id),
+                channel_type: ChannelType::Spectaor as i32,
+                description: None,
+                users: vec![target_id, user_id],
+            })
+            .await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
 #[async_trait]
 impl SpectateStop for BanchoServiceImpl {
     async fn spectate_stop(
         &self,
         user_query: UserQuery,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        let res = self
+            .bancho_state_service
+            .stop_spectating(user_query.clone())
+            .await?;
+
+        if let Some(host_id) = res.host_id {
+            self.leave_spectator_channel(user_query, host_id).await?;
+        }
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl SpectateCant for BanchoServiceImpl {
+    async fn spectate_cant(
+        &self,
+        user_query: UserQuery,
     ) -> Result<HandleCompleted, BanchoServiceError> {
         println!("Got a request: {:?}", user_query);
 
@@ -526,43 +1719,217 @@ impl SpectateStop for BanchoServiceImpl {
 }
 
 #[async_trait]
-impl SpectateCant for BanchoServiceImpl {
-    async fn spectate_cant(
+impl LobbyPart for BanchoServiceImpl {
+    async fn lobby_part(
+        &self,
+        user_query: UserQuery,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        self.bancho_state_service.part_lobby(user_query).await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl LobbyJoin for BanchoServiceImpl {
+    async fn lobby_join(
+        &self,
+        user_query: UserQuery,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        self.bancho_state_service.join_lobby(user_query).await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl MatchCreate for BanchoServiceImpl {
+    async fn match_create(
+        &self,
+        request: MatchCreateRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        let MatchCreateRequest { user_id, match_data } = request;
+
+        self.bancho_state_service
+            .create_match(CreateMatchRequest {
+                user_query: Some(UserQuery::UserId(user_id).into()),
+                match_data,
+            })
+            .await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl MatchJoin for BanchoServiceImpl {
+    async fn match_join(
+        &self,
+        request: MatchJoinRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        let MatchJoinRequest { user_id, match_id, password } = request;
+
+        self.bancho_state_service
+            .join_match(JoinMatchRequest {
+                user_query: Some(UserQuery::UserId(user_id).into()),
+                match_id,
+                password,
+            })
+            .await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl MatchPart for BanchoServiceImpl {
+    async fn match_part(
+        &self,
+        user_query: UserQuery,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        self.bancho_state_service.part_match(user_query).await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl MatchStart for BanchoServiceImpl {
+    async fn match_start(
+        &self,
+        user_query: UserQuery,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        self.bancho_state_service.start_match(user_query).await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl MatchLoadComplete for BanchoServiceImpl {
+    async fn match_load_complete(
+        &self,
+        user_query: UserQuery,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        self.bancho_state_service.match_load_complete(user_query).await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl MatchComplete for BanchoServiceImpl {
+    async fn match_complete(
+        &self,
+        user_query: UserQuery,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        self.bancho_state_service.match_player_complete(user_query).await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl MatchScoreUpdate for BanchoServiceImpl {
+    async fn match_score_update(
+        &self,
+        request: MatchScoreUpdateRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        let MatchScoreUpdateRequest { user_id, score_frame } = request;
+
+        self.bancho_state_service
+            .relay_score_frame(RelayScoreFrameRequest {
+                user_query: Some(UserQuery::UserId(user_id).into()),
+                score_frame,
+            })
+            .await?;
+
+        Ok(HandleCompleted::default())
+    }
+}
+
+#[async_trait]
+impl TournamentMatchInfo for BanchoServiceImpl {
+    async fn tournament_match_info(
         &self,
-        user_query: UserQuery,
+        request: TournamentMatchRequest,
     ) -> Result<HandleCompleted, BanchoServiceError> {
-        println!("Got a request: {:?}", user_query);
+        let TournamentMatchRequest { user_id, match_id } = request;
 
-        Ok(HandleCompleted::default())
+        let packets = self
+            .tournament_match(user_id, match_id)
+            .await?
+            .map(|m| m.match_update);
+
+        Ok(HandleCompleted { packets })
     }
 }
 
 #[async_trait]
-impl LobbyPart for BanchoServiceImpl {
-    async fn lobby_part(
+impl TournamentJoinMatchChannel for BanchoServiceImpl {
+    async fn tournament_join_match_channel(
         &self,
-        user_query: UserQuery,
+        request: TournamentMatchRequest,
     ) -> Result<HandleCompleted, BanchoServiceError> {
-        println!("Got a request: {:?}", user_query);
+        let TournamentMatchRequest { user_id, match_id } = request;
 
-        Ok(HandleCompleted::default())
+        let m = match self.tournament_match(user_id, match_id).await? {
+            Some(m) => m,
+            None => return Ok(HandleCompleted::default()),
+        };
+
+        // only joins the chat, no slot is taken in the match
+        match self
+            .chat_service
+            .join_channel(JoinChannelRequest {
+                channel_query: Some(
+                    ChannelQuery::ChannelName(m.channel_name).into(),
+                ),
+                user_query: Some(UserQuery::UserId(user_id).into()),
+            })
+            .await
+        {
+            Ok(_) | Err(ChatError::ChannelNotExists) => {
+                Ok(HandleCompleted::default())
+            },
+            Err(err) => Err(err.into()),
+        }
     }
 }
 
 #[async_trait]
-impl LobbyJoin for BanchoServiceImpl {
-    async fn lobby_join(
+impl TournamentLeaveMatchChannel for BanchoServiceImpl {
+    async fn tournament_leave_match_channel(
         &self,
-        user_query: UserQuery,
+        request: TournamentMatchRequest,
     ) -> Result<HandleCompleted, BanchoServiceError> {
-        println!("Got a request: {:?}", user_query);
+        let TournamentMatchRequest { user_id, match_id } = request;
 
-        Ok(HandleCompleted::default())
+        let m = match self.tournament_match(user_id, match_id).await? {
+            Some(m) => m,
+            None => return Ok(HandleCompleted::default()),
+        };
+
+        match self
+            .chat_service
+            .leave_channel(LeaveChannelRequest {
+                channel_query: Some(
+                    ChannelQuery::ChannelName(m.channel_name).into(),
+                ),
+                user_query: Some(UserQuery::UserId(user_id).into()),
+            })
+            .await
+        {
+            Ok(_) | Err(ChatError::ChannelNotExists) => {
+                Ok(HandleCompleted::default())
+            },
+            Err(err) => Err(err.into()),
+        }
     }
 }
 
 #[derive(Clone)]
-pub struct BanchoServiceRemote(BanchoRpcClient<Channel>);
+pub struct BanchoServiceRemote(BanchoRpcClient<TracedChannel>);
 
 impl BanchoService for BanchoServiceRemote {}
 
@@ -581,7 +1948,7 @@ impl FromRpcClient for BanchoServiceRemote {
 }
 
 impl RpcClient for BanchoServiceRemote {
-    type Client = BanchoRpcClient<Channel>;
+    type Client = BanchoRpcClient<TracedChannel>;
 
     fn client(&self) -> Self::Client {
         self.0.clone()
@@ -602,12 +1969,121 @@ impl Login for BanchoServiceRemote {
             .into_inner())
     }
 }
+#[async_trait]
+impl ClientRegister for BanchoServiceRemote {
+    async fn client_register(
+        &self,
+        request: ClientRegisterRequest,
+    ) -> Result<ClientRegisterResponse, BanchoServiceError> {
+        Ok(self.client().client_register(request).await?.into_inner())
+    }
+}
+
+#[async_trait]
+impl AddFavouriteBeatmapset for BanchoServiceRemote {
+    async fn add_favourite_beatmapset(
+        &self,
+        request: AddFavouriteBeatmapsetRequest,
+    ) -> Result<AddFavouriteBeatmapsetResponse, BanchoServiceError> {
+        Ok(self.client().add_favourite_beatmapset(request).await?.into_inner())
+    }
+}
+
+#[async_trait]
+impl GetFavouriteBeatmapsets for BanchoServiceRemote {
+    async fn get_favourite_beatmapsets(
+        &self,
+        request: GetFavouriteBeatmapsetsRequest,
+    ) -> Result<GetFavouriteBeatmapsetsResponse, BanchoServiceError> {
+        Ok(self.client().get_favourite_beatmapsets(request).await?.into_inner())
+    }
+}
+
+#[async_trait]
+impl GetUsersSharingHardware for BanchoServiceRemote {
+    async fn get_users_sharing_hardware(
+        &self,
+        request: GetUsersSharingHardwareRequest,
+    ) -> Result<GetUsersSharingHardwareResponse, BanchoServiceError> {
+        Ok(self
+            .client()
+            .get_users_sharing_hardware(request)
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl SilenceUser for BanchoServiceRemote {
+    async fn silence_user(
+        &self,
+        request: SilenceUserRequest,
+    ) -> Result<SilenceUserResponse, BanchoServiceError> {
+        Ok(self.client().silence_user(request).await?.into_inner())
+    }
+}
+#[async_trait]
+impl SubmitScore for BanchoServiceRemote {
+    async fn submit_score(
+        &self,
+        request: SubmitScoreRequest,
+    ) -> Result<SubmitScoreResponse, BanchoServiceError> {
+        Ok(self.client().submit_score(request).await?.into_inner())
+    }
+}
+
+#[async_trait]
+impl GetBeatmapScores for BanchoServiceRemote {
+    async fn get_beatmap_scores(
+        &self,
+        request: GetBeatmapScoresRequest,
+    ) -> Result<GetBeatmapScoresResponse, BanchoServiceError> {
+        Ok(self.client().get_beatmap_scores(request).await?.into_inner())
+    }
+}
+
+#[async_trait]
+impl AuthenticateUser for BanchoServiceRemote {
+    async fn authenticate_user(
+        &self,
+        request: AuthenticateUserRequest,
+    ) -> Result<AuthenticateUserResponse, BanchoServiceError> {
+        Ok(self.client().authenticate_user(request).await?.into_inner())
+    }
+}
+
+#[async_trait]
+impl GetScoreMd5 for BanchoServiceRemote {
+    async fn get_score_md5(
+        &self,
+        request: GetScoreMd5Request,
+    ) -> Result<GetScoreMd5Response, BanchoServiceError> {
+        Ok(self.client().get_score_md5(request).await?.into_inner())
+    }
+}
+
+#[async_trait]
+impl AddReplayView for BanchoServiceRemote {
+    async fn add_replay_view(
+        &self,
+        request: AddReplayViewRequest,
+    ) -> Result<AddReplayViewResponse, BanchoServiceError> {
+        Ok(self.client().add_replay_view(request).await?.into_inner())
+    }
+}
+
 #[async_trait]
 impl BatchProcessPackets for BanchoServiceRemote {
     async fn batch_process_bancho_packets(
         &self,
+        client_ip: Option<IpAddr>,
         request: BatchProcessBanchoPacketsRequest,
     ) -> Result<HandleCompleted, ProcessBanchoPacketError> {
+        let request = match client_ip {
+            Some(client_ip) => RawRequest::add_client_ip(request, client_ip),
+            None => request.into_request(),
+        };
+
         Ok(self
             .client()
             .batch_process_bancho_packets(request)
@@ -733,6 +2209,16 @@ impl RequestPresence for BanchoServiceRemote {
     }
 }
 
+#[async_trait]
+impl SpectateStart for BanchoServiceRemote {
+    async fn spectate_start(
+        &self,
+        request: SpectateStartRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        Ok(self.client().spectate_start(request).await?.into_inner())
+    }
+}
+
 #[async_trait]
 impl SpectateStop for BanchoServiceRemote {
     async fn spectate_stop(
@@ -788,3 +2274,476 @@ impl LobbyJoin for BanchoServiceRemote {
             .into_inner())
     }
 }
+
+#[async_trait]
+impl MatchCreate for BanchoServiceRemote {
+    async fn match_create(
+        &self,
+        request: MatchCreateRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        Ok(self.client().match_create(request).await?.into_inner())
+    }
+}
+
+#[async_trait]
+impl MatchJoin for BanchoServiceRemote {
+    async fn match_join(
+        &self,
+        request: MatchJoinRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        Ok(self.client().match_join(request).await?.into_inner())
+    }
+}
+
+#[async_trait]
+impl MatchPart for BanchoServiceRemote {
+    async fn match_part(
+        &self,
+        user_query: UserQuery,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        Ok(self
+            .client()
+            .match_part(Into::<RawUserQuery>::into(user_query))
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl MatchStart for BanchoServiceRemote {
+    async fn match_start(
+        &self,
+        user_query: UserQuery,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        Ok(self
+            .client()
+            .match_start(Into::<RawUserQuery>::into(user_query))
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl MatchLoadComplete for BanchoServiceRemote {
+    async fn match_load_complete(
+        &self,
+        user_query: UserQuery,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        Ok(self
+            .client()
+            .match_load_complete(Into::<RawUserQuery>::into(user_query))
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl MatchComplete for BanchoServiceRemote {
+    async fn match_complete(
+        &self,
+        user_query: UserQuery,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        Ok(self
+            .client()
+            .match_complete(Into::<RawUserQuery>::into(user_query))
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl MatchScoreUpdate for BanchoServiceRemote {
+    async fn match_score_update(
+        &self,
+        request: MatchScoreUpdateRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        Ok(self.client().match_score_update(request).await?.into_inner())
+    }
+}
+
+#[async_trait]
+impl TournamentMatchInfo for BanchoServiceRemote {
+    async fn tournament_match_info(
+        &self,
+        request: TournamentMatchRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        Ok(self.client().tournament_match_info(request).await?.into_inner())
+    }
+}
+
+#[async_trait]
+impl TournamentJoinMatchChannel for BanchoServiceRemote {
+    async fn tournament_join_match_channel(
+        &self,
+        request: TournamentMatchRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        Ok(self
+            .client()
+            .tournament_join_match_channel(request)
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl TournamentLeaveMatchChannel for BanchoServiceRemote {
+    async fn tournament_leave_match_channel(
+        &self,
+        request: TournamentMatchRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError> {
+        Ok(self
+            .client()
+            .tournament_leave_match_channel(request)
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl GetPacketMetrics for BanchoServiceRemote {
+    async fn get_packet_metrics(
+        &self,
+    ) -> Result<PacketMetricsResponse, BanchoServiceError> {
+        Ok(self
+            .client()
+            .get_packet_metrics(GetPacketMetricsRequest::default())
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl GetLeaderboardCacheMetrics for BanchoServiceRemote {
+    async fn get_leaderboard_cache_metrics(
+        &self,
+    ) -> Result<LeaderboardCacheMetricsResponse, BanchoServiceError> {
+        Ok(self
+            .client()
+            .get_leaderboard_cache_metrics(
+                GetLeaderboardCacheMetricsRequest::default(),
+            )
+            .await?
+            .into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bancho_packets::BanchoPacketWrite;
+    use core_bancho_state::{BanchoStateServiceImpl, UserSessionsServiceImpl};
+    use core_chat::{ChatServiceImpl, ChatServiceRemote};
+    use core_geoip::GeoipServiceImpl;
+    use core_signature::SignatureServiceImpl;
+    use pb_chat::chat_rpc_client::ChatRpcClient;
+    use peace_db::{
+        peace::entity::{
+            beatmaps,
+            sea_orm_active_enums::{
+                GameMode as BeatmapMode, PpVersion, RankStatus,
+            },
+        },
+        prelude::{Decimal, Json},
+    };
+    use peace_difficulty::{BeatmapFiles, DifficultyServiceImpl};
+    use peace_repositories::scores::{
+        CreatedScore, LeaderboardScore, PpScore, ScoresRepository,
+    };
+    use peace_repositories::{
+        channels::ChannelsRepositoryImpl, scores::ScoresRepositoryImpl,
+        users::UsersRepositoryImpl,
+    };
+    use std::sync::Mutex;
+    use tonic::transport::Endpoint;
+    use tools::{crypto::SignerManager, tonic_utils::PropagateRequestId};
+
+    /// Service of in memory states, the database is disconnected.
+    fn service(
+        chat_service: Option<DynChatService>,
+        scores_repository: Option<DynScoresRepository>,
+    ) -> BanchoServiceImpl {
+        let users_repository =
+            UsersRepositoryImpl::new(Default::default()).into_service();
+        let scores_repository = scores_repository.unwrap_or_else(|| {
+            ScoresRepositoryImpl::new(Default::default()).into_service()
+        });
+        let chat_service = chat_service.unwrap_or_else(|| {
+            ChatServiceImpl::new(
+                users_repository.clone(),
+                ChannelsRepositoryImpl::new(Default::default()).into_service(),
+                0,
+            )
+            .into_service()
+        });
+        let password_service = PasswordServiceImpl::default();
+
+        BanchoServiceImpl::new(
+            users_repository,
+            scores_repository.clone(),
+            BanchoStateServiceImpl::new(
+                UserSessionsServiceImpl::new().into_service(),
+                SignatureServiceImpl::from(SignerManager::new_rand())
+                    .into_service(),
+                0,
+                1,
+                Duration::ZERO,
+                Vec::new(),
+            )
+            .into_service(),
+            password_service.clone().into_service(),
+            BanchoBackgroundServiceImpl::new(password_service.cache_store)
+                .into_service(),
+            GeoipServiceImpl::default().into_service(),
+            chat_service,
+            DifficultyServiceImpl::new(BeatmapFiles::new(
+                "./.test_beatmaps",
+                None,
+            ))
+            .into_service(),
+            LeaderboardServiceImpl::new(
+                scores_repository,
+                50,
+                Duration::ZERO,
+                0,
+            )
+            .into_service(),
+            Arc::new(EmailValidator::default()),
+            Arc::new(MessageLimiter::new(1.0, 10)),
+            8,
+            100,
+            None,
+            None,
+        )
+    }
+
+    /// Remote chat service whose peer is down, every rpc fails with a
+    /// transport error.
+    fn unavailable_chat_service() -> DynChatService {
+        let channel =
+            Endpoint::from_static("http://127.0.0.1:1").connect_lazy();
+
+        ChatServiceRemote::from_client(ChatRpcClient::with_interceptor(
+            channel,
+            PropagateRequestId,
+        ))
+        .into_service()
+    }
+
+    #[tokio::test]
+    async fn test_channel_join_chat_unavailable() {
+        let service = service(Some(unavailable_chat_service()), None);
+
+        let payload = "#osu".into_packet();
+        let res = service
+            .process_bancho_packet(
+                1000,
+                Packet::with_payload(
+                    PacketId::OSU_USER_CHANNEL_JOIN,
+                    Some(&payload),
+                ),
+            )
+            .await;
+
+        assert!(matches!(
+            res,
+            Err(ProcessBanchoPacketError::ChatError(ChatError::TonicError(_)))
+        ));
+
+        let metrics = service.packet_metrics.snapshot();
+        assert_eq!(metrics.packets[0].errored, 1);
+    }
+
+    /// Scores repository of one beatmap, keeping the created scores.
+    #[derive(Default)]
+    struct CreatedScores(Mutex<Vec<CreateScore>>);
+
+    #[async_trait]
+    impl ScoresRepository for CreatedScores {
+        async fn get_beatmap_by_md5(
+            &self,
+            md5: &str,
+        ) -> Result<Option<beatmaps::Model>, DbErr> {
+            Ok(Some(beatmaps::Model {
+                bid: 1,
+                sid: 1,
+                md5: md5.to_owned(),
+                title: "title".into(),
+                file_name: "beatmap.osu".into(),
+                artist: "artist".into(),
+                diff_name: "diff".into(),
+                origin_server: "peace".into(),
+                mapper_name: "mapper".into(),
+                mapper_id: "1".into(),
+                rank_status: RankStatus::Ranked,
+                game_mode: BeatmapMode::Standard,
+                stars: Decimal::ONE,
+                bpm: Decimal::ONE,
+                cs: Decimal::ONE,
+                od: Decimal::ONE,
+                ar: Decimal::ONE,
+                hp: Decimal::ONE,
+                length: 60,
+                length_drain: 60,
+                source: None,
+                tags: None,
+                genre_id: None,
+                language_id: None,
+                storyboard: None,
+                video: None,
+                object_count: None,
+                slider_count: None,
+                spinner_count: None,
+                max_combo: None,
+                immutable: false,
+                last_update: Utc::now().into(),
+                upload_time: Utc::now().into(),
+                approved_time: None,
+                updated_at: Utc::now().into(),
+            }))
+        }
+
+        async fn score_exists(
+            &self,
+            _mode: GameMode,
+            _score_md5: &str,
+        ) -> Result<bool, DbErr> {
+            Ok(false)
+        }
+
+        async fn get_best_score(
+            &self,
+            _mode: GameMode,
+            _user_id: i32,
+            _map_md5: &str,
+        ) -> Result<Option<BestScore>, DbErr> {
+            unimplemented!()
+        }
+
+        async fn get_leaderboard(
+            &self,
+            _mode: GameMode,
+            _map_md5: &str,
+            _filter: &LeaderboardFilter,
+            _limit: u64,
+        ) -> Result<Vec<LeaderboardScore>, DbErr> {
+            unimplemented!()
+        }
+
+        async fn get_user_leaderboard_score(
+            &self,
+            _mode: GameMode,
+            _map_md5: &str,
+            _filter: &LeaderboardFilter,
+            _user_id: i32,
+        ) -> Result<Option<(LeaderboardScore, u64)>, DbErr> {
+            unimplemented!()
+        }
+
+        async fn get_score_md5(
+            &self,
+            _mode: GameMode,
+            _score_id: i64,
+        ) -> Result<Option<String>, DbErr> {
+            unimplemented!()
+        }
+
+        async fn add_replay_view(
+            &self,
+            _score_md5: &str,
+        ) -> Result<i32, DbErr> {
+            unimplemented!()
+        }
+
+        async fn create_score(
+            &self,
+            _mode: GameMode,
+            score: CreateScore,
+        ) -> Result<CreatedScore, DbErr> {
+            let mut scores = self.0.lock().unwrap();
+            scores.push(score);
+
+            Ok(CreatedScore {
+                score_id: scores.len() as i64,
+                status: ScoreStatus::Failed,
+                previous_best: None,
+            })
+        }
+
+        async fn get_scores_with_pp_version(
+            &self,
+            _mode: GameMode,
+            _pp_version: PpVersion,
+            _after_score_id: i64,
+            _limit: u64,
+        ) -> Result<Vec<PpScore>, DbErr> {
+            unimplemented!()
+        }
+
+        async fn upsert_score_pp(
+            &self,
+            _mode: GameMode,
+            _score_id: i64,
+            _pp_version: PpVersion,
+            _pp: Decimal,
+            _raw_pp: Option<Json>,
+        ) -> Result<(), DbErr> {
+            unimplemented!()
+        }
+    }
+
+    struct FlagEveryScore;
+
+    #[async_trait]
+    impl ScoreValidator for FlagEveryScore {
+        async fn validate(&self, _score: &SubmittedScore<'_>) -> ScoreVerdict {
+            ScoreVerdict::Flag { reason: "suspicious".into(), confidence: 90 }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_submit_flagged_score() {
+        let scores = Arc::new(CreatedScores::default());
+        let service =
+            service(None, Some(scores.clone() as DynScoresRepository))
+                .with_score_validator(Arc::new(FlagEveryScore));
+
+        let res = service
+            .submit_score(SubmitScoreRequest {
+                user_id: 1000,
+                beatmap_md5: "9e107d9d372bb6826bd81d3542a419d6".into(),
+                score_md5: "e4d909c290d0fb1ca068ffaddf22cbd0".into(),
+                score: 1000,
+                n300: 10,
+                miss: 2,
+                grade: "F".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(res.score_id, 1);
+
+        let scores = scores.0.lock().unwrap();
+        assert_eq!(scores.len(), 1);
+        assert_eq!(scores[0].confidence, Some(90));
+    }
+
+    #[tokio::test]
+    async fn test_process_unknown_packet() {
+        let service = service(None, None);
+
+        // made-up packet ids are all unknown packets
+        let res = service
+            .process_bancho_packet(1000, Packet::new(PacketId::new(233)))
+            .await
+            .unwrap();
+        assert_eq!(res, HandleCompleted::default());
+
+        let metrics = service.packet_metrics.snapshot();
+        let unknown = metrics
+            .packets
+            .iter()
+            .find(|p| p.packet_id == PacketId::OSU_UNKNOWN_PACKET as i32)
+            .unwrap();
+        assert_eq!((unknown.handled, unknown.unhandled), (0, 1));
+    }
+}
@@ -0,0 +1,237 @@
+use core_geoip::{DynGeoipService, GeoipError};
+use domain_bancho::BanchoCountryCode;
+use domain_geoip::GeoipData;
+use pb_bancho_state::ConnectionInfo;
+use peace_repositories::users::CreateLoginRecord;
+use std::net::IpAddr;
+
+/// Geolocation of the ip a session is created from.
+///
+/// If the lookup fails (e.g. local or unknown addresses, geoip service
+/// unavailable), the session falls back to a neutral location (`0, 0`)
+/// and the default country, if configured, otherwise no country (code `0`).
+#[derive(Debug, Default, Clone)]
+pub struct SessionGeoip {
+    pub geoip_data: Option<GeoipData>,
+    /// Country shown to clients when the ip has no known country.
+    pub default_country: Option<String>,
+}
+
+impl SessionGeoip {
+    pub async fn lookup(
+        geoip_service: &DynGeoipService,
+        ip: IpAddr,
+        default_country: Option<&str>,
+    ) -> Self {
+        const LOG_TARGET: &str = "core_bancho::session_geoip";
+
+        let geoip_data = match geoip_service.lookup_with_ip_address(ip).await {
+            Ok(geoip_data) => Some(geoip_data),
+            // expected for local and unlisted addresses
+            Err(
+                err @ (GeoipError::ReservedAddr(_)
+                | GeoipError::AddrNotFound(_)),
+            ) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "No geoip data of {ip}, using a neutral location: {err}"
+                );
+                None
+            },
+            Err(err) => {
+                warn!(
+                    target: LOG_TARGET,
+                    "Failed to lookup geoip data of {ip}, \
+                    using a neutral location: {err}"
+                );
+                None
+            },
+        };
+
+        let geoip = Self {
+            geoip_data,
+            default_country: default_country.map(ToOwned::to_owned),
+        };
+
+        if geoip.country().is_none() {
+            if let Some(default_country) = default_country {
+                debug!(
+                    target: LOG_TARGET,
+                    "No country of {ip}, using the default country \
+                    \"{default_country}\""
+                );
+            }
+        }
+
+        geoip
+    }
+
+    /// Bancho country code of the session, the default country's if the
+    /// ip has no known country.
+    #[inline]
+    pub fn country_code(&self) -> u8 {
+        self.geoip_data
+            .as_ref()
+            .map(|d| BanchoCountryCode::get_code(&d.country.code))
+            .filter(|code| *code != 0)
+            .or_else(|| {
+                self.default_country.as_deref().map(BanchoCountryCode::get_code)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Country code as sent by the geoip service, e.g. `AU`, the default
+    /// country is not applied.
+    #[inline]
+    pub fn country(&self) -> Option<String> {
+        self.geoip_data
+            .as_ref()
+            .map(|d| d.country.code.to_owned())
+            .filter(|code| !code.is_empty())
+    }
+
+    #[inline]
+    pub fn city(&self) -> Option<String> {
+        self.geoip_data
+            .as_ref()
+            .map(|d| d.city.name.to_owned())
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Login record of the user logging in from `ip`, the default country is
+    /// not recorded.
+    #[inline]
+    pub fn login_record(
+        &self,
+        user_id: i32,
+        ip: IpAddr,
+        client_version: &str,
+    ) -> CreateLoginRecord {
+        CreateLoginRecord {
+            user_id,
+            ip: ip.to_string(),
+            client_version: client_version.to_owned(),
+            country_code: self.country(),
+            city: self.city(),
+        }
+    }
+
+    #[inline]
+    pub fn connection_info(&self, ip: IpAddr) -> ConnectionInfo {
+        ConnectionInfo {
+            ip: ip.to_string(),
+            geoip_data: self.geoip_data.clone().map(|g| g.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_geoip::{GeoipService, LookupIpAddress, ReloadGeoDb};
+    use domain_geoip::{City, Country, Location};
+    use pb_base::ExecSuccess;
+    use std::sync::Arc;
+    use tonic::async_trait;
+
+    const KNOWN_IP: &str = "1.1.1.1";
+
+    struct FakeGeoipService;
+
+    impl GeoipService for FakeGeoipService {}
+
+    #[async_trait]
+    impl LookupIpAddress for FakeGeoipService {
+        async fn lookup_with_ip_address(
+            &self,
+            ip_addr: IpAddr,
+        ) -> Result<GeoipData, GeoipError> {
+            if ip_addr.to_string() != KNOWN_IP {
+                return Err(GeoipError::AddrNotFound(ip_addr.to_string()));
+            }
+
+            Ok(GeoipData {
+                location: Location {
+                    latitude: -33.494,
+                    longitude: 143.2104,
+                    timezone: "Australia/Sydney".into(),
+                },
+                country: Country {
+                    geoname_id: 2077456,
+                    code: "AU".into(),
+                    name: "Australia".into(),
+                },
+                city: City { geoname_id: 2147714, name: "Sydney".into() },
+                ..Default::default()
+            })
+        }
+    }
+
+    #[async_trait]
+    impl ReloadGeoDb for FakeGeoipService {
+        async fn try_reload(
+            &self,
+            _path: &str,
+        ) -> Result<ExecSuccess, GeoipError> {
+            Err(GeoipError::OnlyLocalService)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_geoip() {
+        let geoip_service: DynGeoipService = Arc::new(FakeGeoipService);
+
+        let ip = KNOWN_IP.parse().unwrap();
+        let geoip = SessionGeoip::lookup(&geoip_service, ip, None).await;
+
+        assert_eq!(geoip.country_code(), BanchoCountryCode::AU as u8);
+        assert_eq!(geoip.country().as_deref(), Some("AU"));
+        assert_eq!(geoip.city().as_deref(), Some("Sydney"));
+
+        let record = geoip.login_record(1000, ip, "b20230326");
+        assert_eq!(record.user_id, 1000);
+        assert_eq!(record.ip, KNOWN_IP);
+        assert_eq!(record.client_version, "b20230326");
+        assert_eq!(record.country_code.as_deref(), Some("AU"));
+        assert_eq!(record.city.as_deref(), Some("Sydney"));
+
+        let location = geoip
+            .connection_info(ip)
+            .geoip_data
+            .and_then(|g| g.location)
+            .unwrap();
+        assert_eq!(location.latitude, Some(-33.494));
+        assert_eq!(location.longitude, Some(143.2104));
+
+        // unknown ips fall back to a neutral location without country
+        let ip = "127.0.0.1".parse().unwrap();
+        let geoip = SessionGeoip::lookup(&geoip_service, ip, None).await;
+
+        assert_eq!(geoip.country_code(), 0);
+        assert_eq!(geoip.country(), None);
+        assert_eq!(geoip.connection_info(ip).geoip_data, None);
+    }
+
+    #[tokio::test]
+    async fn test_session_geoip_default_country() {
+        let geoip_service: DynGeoipService = Arc::new(FakeGeoipService);
+
+        let ip = "192.168.1.10".parse().unwrap();
+        let geoip = SessionGeoip::lookup(&geoip_service, ip, Some("XX")).await;
+
+        assert_eq!(geoip.country_code(), BanchoCountryCode::XX as u8);
+        assert_eq!(geoip.country(), None);
+        assert_eq!(geoip.connection_info(ip).geoip_data, None);
+
+        let record = geoip.login_record(1000, ip, "b20230326");
+        assert_eq!(record.ip, "192.168.1.10");
+        assert_eq!(record.country_code, None);
+        assert_eq!(record.city, None);
+
+        // resolved countries are kept
+        let ip = KNOWN_IP.parse().unwrap();
+        let geoip = SessionGeoip::lookup(&geoip_service, ip, Some("XX")).await;
+
+        assert_eq!(geoip.country_code(), BanchoCountryCode::AU as u8);
+    }
+}
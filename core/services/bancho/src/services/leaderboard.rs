@@ -0,0 +1,597 @@
+use crate::{
+    BanchoServiceError, CliBanchoServiceConfigs, DynLeaderboardService,
+    LeaderboardService,
+};
+use async_trait::async_trait;
+use domain_bancho::GameMode;
+use infra_services::IntoService;
+use pb_bancho::{BeatmapScore, LeaderboardCacheMetricsResponse};
+use peace_db::peace::entity::sea_orm_active_enums::RankStatus;
+use peace_repositories::scores::{
+    DynScoresRepository, LeaderboardFilter, LeaderboardScore,
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+pub type Leaderboard = Arc<Vec<LeaderboardScore>>;
+
+/// Rank status understood by the client, and if the beatmap has
+/// leaderboards.
+#[inline]
+pub fn client_rank_status(status: &RankStatus) -> (i32, bool) {
+    match status {
+        RankStatus::Graveyard | RankStatus::Wip | RankStatus::Pending => {
+            (0, false)
+        },
+        RankStatus::Ranked => (2, true),
+        RankStatus::Approved => (3, true),
+        RankStatus::Qualified => (4, true),
+        RankStatus::Loved => (5, true),
+    }
+}
+
+#[inline]
+pub fn beatmap_score(
+    mode: GameMode,
+    score: &LeaderboardScore,
+    rank: u64,
+) -> BeatmapScore {
+    BeatmapScore {
+        score_id: mode.client_score_id(score.score_id),
+        user_id: score.user_id,
+        username: score.username.clone(),
+        score: score.score,
+        max_combo: score.combo,
+        n300: score.n300,
+        n100: score.n100,
+        n50: score.n50,
+        geki: score.geki,
+        katu: score.katu,
+        miss: score.miss,
+        perfect: score.perfect,
+        mods: score.mods as u32,
+        rank,
+        timestamp: score.timestamp,
+    }
+}
+
+/// Leaderboard selected in the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoardType {
+    Global,
+    /// Scores with the mods selected in the client.
+    Mods,
+    Friends,
+    Country,
+}
+
+impl BoardType {
+    /// `v` of `/web/osu-osz2-getscores.php`, unknown types are global.
+    #[inline]
+    pub fn from_client(v: i32) -> Self {
+        match v {
+            2 => Self::Mods,
+            3 => Self::Friends,
+            4 => Self::Country,
+            _ => Self::Global,
+        }
+    }
+}
+
+/// Friends leaderboards show the scores of the user and of the users they
+/// follow, an empty friend list only shows the user's own score.
+pub fn friends_filter(
+    user_id: i32,
+    mut friend_ids: Vec<i32>,
+) -> LeaderboardFilter {
+    friend_ids.push(user_id);
+    friend_ids.sort_unstable();
+    friend_ids.dedup();
+
+    LeaderboardFilter::Friends(friend_ids)
+}
+
+/// Users without country only see their own score on the country
+/// leaderboard.
+#[inline]
+pub fn country_filter(
+    user_id: i32,
+    country: Option<String>,
+) -> LeaderboardFilter {
+    match country.filter(|country| !country.is_empty()) {
+        Some(country) => LeaderboardFilter::Country(country),
+        None => LeaderboardFilter::Friends(vec![user_id]),
+    }
+}
+
+/// Friends leaderboards are specific to a user and are not cached.
+#[inline]
+fn is_shared(filter: &LeaderboardFilter) -> bool {
+    !matches!(filter, LeaderboardFilter::Friends(_))
+}
+
+#[derive(Debug, Clone)]
+struct CachedLeaderboard {
+    scores: Leaderboard,
+    cached_at: Instant,
+}
+
+/// Cached leaderboards of a beatmap in a mode.
+#[derive(Debug, Default)]
+struct BeatmapLeaderboards {
+    /// Cache generation of the last invalidation, so that a leaderboard
+    /// queried before the invalidation is not cached.
+    generation: u64,
+    invalidated_at: Option<Instant>,
+    boards: HashMap<LeaderboardFilter, CachedLeaderboard>,
+}
+
+#[derive(Debug, Default)]
+struct LeaderboardCacheInner {
+    beatmaps: HashMap<(String, GameMode), BeatmapLeaderboards>,
+    /// Number of cached leaderboards.
+    len: usize,
+    /// Bumped on each invalidation.
+    generation: u64,
+}
+
+impl LeaderboardCacheInner {
+    /// Drop the expired leaderboards, and the beatmaps without leaderboard
+    /// not invalidated for a ttl.
+    fn remove_expired(&mut self, ttl: Duration) {
+        let mut removed = 0;
+
+        self.beatmaps.retain(|_, beatmap| {
+            let len = beatmap.boards.len();
+            beatmap.boards.retain(|_, board| board.cached_at.elapsed() < ttl);
+            removed += len - beatmap.boards.len();
+
+            !beatmap.boards.is_empty()
+                || beatmap
+                    .invalidated_at
+                    .map(|at| at.elapsed() < ttl)
+                    .unwrap_or(false)
+        });
+
+        self.len -= removed;
+    }
+}
+
+/// Top scores of the beatmap leaderboards, by beatmap, mode and filter.
+///
+/// Leaderboards of a beatmap are invalidated when a new best score is
+/// submitted on it, the ttl is only a safety net.
+#[derive(Debug)]
+pub struct LeaderboardCache {
+    pub ttl: Duration,
+    pub max_entries: usize,
+    inner: Mutex<LeaderboardCacheInner>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl LeaderboardCache {
+    #[inline]
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            inner: Mutex::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the cached leaderboard, or the generation to pass to
+    /// [`LeaderboardCache::insert`] on a miss.
+    pub async fn get(
+        &self,
+        beatmap_md5: &str,
+        mode: GameMode,
+        filter: &LeaderboardFilter,
+    ) -> Result<Leaderboard, u64> {
+        let inner = self.inner.lock().await;
+        let beatmap = inner.beatmaps.get(&(beatmap_md5.to_owned(), mode));
+
+        match beatmap.and_then(|beatmap| beatmap.boards.get(filter)) {
+            Some(board) if board.cached_at.elapsed() < self.ttl => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Ok(board.scores.clone())
+            },
+            _ => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                Err(inner.generation)
+            },
+        }
+    }
+
+    /// Cache a leaderboard queried at `generation`, returns `false` if the
+    /// beatmap was invalidated since or the cache is full.
+    pub async fn insert(
+        &self,
+        beatmap_md5: &str,
+        mode: GameMode,
+        filter: LeaderboardFilter,
+        generation: u64,
+        scores: Leaderboard,
+    ) -> bool {
+        let mut inner = self.inner.lock().await;
+
+        if inner.len >= self.max_entries {
+            inner.remove_expired(self.ttl);
+
+            if inner.len >= self.max_entries {
+                return false;
+            }
+        }
+
+        let key = (beatmap_md5.to_owned(), mode);
+        let invalidated = match inner.beatmaps.get(&key) {
+            Some(beatmap) => beatmap.generation > generation,
+            // Uncached beatmaps keep no generation, any invalidation since
+            // the query may have been theirs.
+            None => inner.generation != generation,
+        };
+        if invalidated {
+            return false;
+        }
+
+        let beatmap = inner.beatmaps.entry(key).or_default();
+        let cached = CachedLeaderboard { scores, cached_at: Instant::now() };
+        if beatmap.boards.insert(filter, cached).is_none() {
+            inner.len += 1;
+        }
+
+        true
+    }
+
+    /// Drop the cached leaderboards of the beatmap.
+    pub async fn invalidate(&self, beatmap_md5: &str, mode: GameMode) {
+        let mut inner = self.inner.lock().await;
+        inner.generation += 1;
+        let generation = inner.generation;
+
+        let removed =
+            match inner.beatmaps.get_mut(&(beatmap_md5.to_owned(), mode)) {
+                Some(beatmap) => {
+                    let removed = beatmap.boards.len();
+                    beatmap.boards.clear();
+                    beatmap.generation = generation;
+                    beatmap.invalidated_at = Some(Instant::now());
+                    removed
+                },
+                None => 0,
+            };
+
+        inner.len -= removed;
+    }
+
+    pub async fn metrics(&self) -> LeaderboardCacheMetricsResponse {
+        LeaderboardCacheMetricsResponse {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.inner.lock().await.len as u64,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LeaderboardServiceImpl {
+    pub scores_repository: DynScoresRepository,
+    pub cache: Arc<LeaderboardCache>,
+    /// Number of scores of a leaderboard.
+    pub size: u64,
+}
+
+impl LeaderboardServiceImpl {
+    #[inline]
+    pub fn new(
+        scores_repository: DynScoresRepository,
+        size: u64,
+        cache_ttl: Duration,
+        cache_max_entries: usize,
+    ) -> Self {
+        Self {
+            scores_repository,
+            cache: Arc::new(LeaderboardCache::new(
+                cache_ttl,
+                cache_max_entries,
+            )),
+            size,
+        }
+    }
+
+    async fn query_leaderboard(
+        &self,
+        mode: GameMode,
+        beatmap_md5: &str,
+        filter: &LeaderboardFilter,
+    ) -> Result<Leaderboard, BanchoServiceError> {
+        self.scores_repository
+            .get_leaderboard(mode, beatmap_md5, filter, self.size)
+            .await
+            .map(Arc::new)
+            .map_err(|err| BanchoServiceError::DbError(err.to_string()))
+    }
+
+    #[inline]
+    pub fn with_cfg(
+        scores_repository: DynScoresRepository,
+        cfg: &CliBanchoServiceConfigs,
+    ) -> Self {
+        Self::new(
+            scores_repository,
+            cfg.leaderboard_size,
+            Duration::from_secs(cfg.leaderboard_cache_ttl),
+            cfg.leaderboard_cache_max_entries,
+        )
+    }
+}
+
+impl IntoService<DynLeaderboardService> for LeaderboardServiceImpl {
+    #[inline]
+    fn into_service(self) -> DynLeaderboardService {
+        Arc::new(self) as DynLeaderboardService
+    }
+}
+
+#[async_trait]
+impl LeaderboardService for LeaderboardServiceImpl {
+    async fn get_leaderboard(
+        &self,
+        mode: GameMode,
+        beatmap_md5: &str,
+        filter: &LeaderboardFilter,
+    ) -> Result<Leaderboard, BanchoServiceError> {
+        if !is_shared(filter) {
+            return self.query_leaderboard(mode, beatmap_md5, filter).await;
+        }
+
+        let generation = match self.cache.get(beatmap_md5, mode, filter).await {
+            Ok(scores) => return Ok(scores),
+            Err(generation) => generation,
+        };
+
+        let scores = self.query_leaderboard(mode, beatmap_md5, filter).await?;
+
+        self.cache
+            .insert(
+                beatmap_md5,
+                mode,
+                filter.clone(),
+                generation,
+                scores.clone(),
+            )
+            .await;
+
+        Ok(scores)
+    }
+
+    #[inline]
+    async fn invalidate_leaderboards(&self, mode: GameMode, beatmap_md5: &str) {
+        self.cache.invalidate(beatmap_md5, mode).await
+    }
+
+    #[inline]
+    async fn get_cache_metrics(&self) -> LeaderboardCacheMetricsResponse {
+        self.cache.metrics().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MD5: &str = "1cf5b2c2edfafd055536d2cefcb89c0e";
+
+    fn leaderboard(score_ids: &[i64]) -> Leaderboard {
+        Arc::new(
+            score_ids
+                .iter()
+                .map(|score_id| LeaderboardScore {
+                    score_id: *score_id,
+                    user_id: 1,
+                    username: "peppy".into(),
+                    score: 1000,
+                    combo: 100,
+                    mods: 0,
+                    n300: 100,
+                    n100: 0,
+                    n50: 0,
+                    miss: 0,
+                    geki: 0,
+                    katu: 0,
+                    perfect: true,
+                    timestamp: 0,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_board_filters() {
+        assert_eq!(BoardType::from_client(1), BoardType::Global);
+        assert_eq!(BoardType::from_client(2), BoardType::Mods);
+        assert_eq!(BoardType::from_client(3), BoardType::Friends);
+        assert_eq!(BoardType::from_client(4), BoardType::Country);
+        assert_eq!(BoardType::from_client(0), BoardType::Global);
+
+        assert_eq!(
+            friends_filter(2, vec![3, 1, 2]),
+            LeaderboardFilter::Friends(vec![1, 2, 3])
+        );
+        // no friends, only the own score
+        assert_eq!(
+            friends_filter(2, vec![]),
+            LeaderboardFilter::Friends(vec![2])
+        );
+
+        assert_eq!(
+            country_filter(2, Some("AU".into())),
+            LeaderboardFilter::Country("AU".into())
+        );
+        assert_eq!(
+            country_filter(2, None),
+            LeaderboardFilter::Friends(vec![2])
+        );
+        assert_eq!(
+            country_filter(2, Some("".into())),
+            LeaderboardFilter::Friends(vec![2])
+        );
+
+        assert!(is_shared(&LeaderboardFilter::Global));
+        assert!(is_shared(&LeaderboardFilter::Mods(8)));
+        assert!(is_shared(&LeaderboardFilter::Country("AU".into())));
+        assert!(!is_shared(&LeaderboardFilter::Friends(vec![2])));
+    }
+
+    #[tokio::test]
+    async fn test_leaderboard_cache() {
+        let cache = LeaderboardCache::new(Duration::from_secs(60), 2);
+        let global = LeaderboardFilter::Global;
+        let mode = GameMode::Standard;
+
+        let generation = cache.get(MD5, mode, &global).await.unwrap_err();
+        assert!(
+            cache
+                .insert(
+                    MD5,
+                    mode,
+                    global.clone(),
+                    generation,
+                    leaderboard(&[1])
+                )
+                .await
+        );
+        assert_eq!(cache.get(MD5, mode, &global).await, Ok(leaderboard(&[1])));
+
+        // other modes and filters are cached separately
+        assert!(cache.get(MD5, GameMode::Taiko, &global).await.is_err());
+        assert!(cache
+            .get(MD5, mode, &LeaderboardFilter::Mods(8))
+            .await
+            .is_err());
+
+        // a new score drops the cached leaderboards of the beatmap
+        cache.invalidate(MD5, mode).await;
+        assert!(cache.get(MD5, mode, &global).await.is_err());
+
+        // a leaderboard queried before the invalidation is stale
+        assert!(
+            !cache
+                .insert(
+                    MD5,
+                    mode,
+                    global.clone(),
+                    generation,
+                    leaderboard(&[1])
+                )
+                .await
+        );
+
+        let generation = cache.get(MD5, mode, &global).await.unwrap_err();
+        assert!(
+            cache
+                .insert(
+                    MD5,
+                    mode,
+                    global.clone(),
+                    generation,
+                    leaderboard(&[2])
+                )
+                .await
+        );
+        assert!(
+            cache
+                .insert(
+                    MD5,
+                    mode,
+                    LeaderboardFilter::Mods(8),
+                    1,
+                    leaderboard(&[])
+                )
+                .await
+        );
+
+        // the cache is full
+        assert!(
+            !cache
+                .insert("other", mode, global.clone(), 0, leaderboard(&[]))
+                .await
+        );
+
+        let metrics = cache.metrics().await;
+        assert_eq!((metrics.hits, metrics.misses, metrics.entries), (1, 5, 2));
+    }
+
+    #[tokio::test]
+    async fn test_leaderboard_cache_invalidate_uncached() {
+        let cache = LeaderboardCache::new(Duration::from_secs(60), 8);
+        let global = LeaderboardFilter::Global;
+        let mode = GameMode::Standard;
+
+        // invalidating uncached beatmaps does not add them to the cache
+        cache.invalidate("other", mode).await;
+        assert!(cache.inner.lock().await.beatmaps.is_empty());
+
+        // but still rejects the leaderboards queried before
+        let generation = cache.get(MD5, mode, &global).await.unwrap_err();
+        cache.invalidate(MD5, mode).await;
+        assert!(cache.inner.lock().await.beatmaps.is_empty());
+        assert!(
+            !cache
+                .insert(
+                    MD5,
+                    mode,
+                    global.clone(),
+                    generation,
+                    leaderboard(&[1])
+                )
+                .await
+        );
+
+        let generation = cache.get(MD5, mode, &global).await.unwrap_err();
+        assert!(
+            cache
+                .insert(
+                    MD5,
+                    mode,
+                    global.clone(),
+                    generation,
+                    leaderboard(&[1])
+                )
+                .await
+        );
+
+        // other beatmaps are not affected once cached
+        cache.invalidate("other", mode).await;
+        assert_eq!(cache.get(MD5, mode, &global).await, Ok(leaderboard(&[1])));
+    }
+
+    #[tokio::test]
+    async fn test_leaderboard_cache_ttl() {
+        let cache = LeaderboardCache::new(Duration::ZERO, 1);
+        let global = LeaderboardFilter::Global;
+        let mode = GameMode::Standard;
+
+        assert!(
+            cache.insert(MD5, mode, global.clone(), 0, leaderboard(&[1])).await
+        );
+        assert!(cache.get(MD5, mode, &global).await.is_err());
+
+        // expired leaderboards are dropped when the cache is full
+        assert!(
+            cache
+                .insert("other", mode, global.clone(), 0, leaderboard(&[2]))
+                .await
+        );
+        assert_eq!(cache.metrics().await.entries, 1);
+    }
+}
@@ -1,9 +1,11 @@
 pub mod background;
 pub mod bancho;
+pub mod leaderboard;
 pub mod password;
 pub mod traits;
 
 pub use background::*;
 pub use bancho::*;
+pub use leaderboard::*;
 pub use password::*;
 pub use traits::*;
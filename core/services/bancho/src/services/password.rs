@@ -71,4 +71,21 @@ impl PasswordService for PasswordServiceImpl {
 
         Ok(())
     }
+
+    async fn verify_and_maybe_rehash(
+        &self,
+        hashed_password: &str,
+        password: &str,
+    ) -> Result<Option<Password>, PasswordError> {
+        let stored = Password::from_hashed(hashed_password.to_owned());
+        if !stored.needs_rehash() {
+            return self
+                .verify_password(hashed_password, password)
+                .await
+                .map(|()| None);
+        }
+
+        // Not cached, the stored hash is about to be replaced
+        stored.verify_and_maybe_rehash(password)
+    }
 }
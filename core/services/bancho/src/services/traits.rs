@@ -1,8 +1,10 @@
 use crate::*;
 use bancho_packets::Packet;
-use domain_users::PasswordError;
+use domain_bancho::GameMode;
+use domain_users::{Password, PasswordError};
 use pb_bancho::*;
 use pb_bancho_state::UserQuery;
+use peace_repositories::scores::LeaderboardFilter;
 use std::{net::IpAddr, sync::Arc};
 use tonic::async_trait;
 use tools::async_collections::{
@@ -13,6 +15,7 @@ pub type DynBanchoService = Arc<dyn BanchoService + Send + Sync>;
 pub type DynBanchoBackgroundService =
     Arc<dyn BanchoBackgroundService + Send + Sync>;
 pub type DynPasswordService = Arc<dyn PasswordService + Send + Sync>;
+pub type DynLeaderboardService = Arc<dyn LeaderboardService + Send + Sync>;
 
 #[async_trait]
 pub trait PasswordBackgroundService {
@@ -36,6 +39,31 @@ pub trait PasswordService {
         hashed_password: &str,
         password: &str,
     ) -> Result<(), PasswordError>;
+
+    /// Verify the password, returns a new hash if the stored one was
+    /// encoded with weaker params, see [`Password::verify_and_maybe_rehash`].
+    async fn verify_and_maybe_rehash(
+        &self,
+        hashed_password: &str,
+        password: &str,
+    ) -> Result<Option<Password>, PasswordError>;
+}
+
+#[async_trait]
+pub trait LeaderboardService {
+    /// Top scores of the leaderboard, served from the cache if possible.
+    async fn get_leaderboard(
+        &self,
+        mode: GameMode,
+        beatmap_md5: &str,
+        filter: &LeaderboardFilter,
+    ) -> Result<Leaderboard, BanchoServiceError>;
+
+    /// Drop the cached leaderboards of the beatmap, so that a new best
+    /// score is shown right away.
+    async fn invalidate_leaderboards(&self, mode: GameMode, beatmap_md5: &str);
+
+    async fn get_cache_metrics(&self) -> LeaderboardCacheMetricsResponse;
 }
 
 #[async_trait]
@@ -45,6 +73,16 @@ pub trait BanchoBackgroundService: PasswordBackgroundService {
 
 pub trait BanchoService:
     Login
+    + AuthenticateUser
+    + ClientRegister
+    + AddFavouriteBeatmapset
+    + GetFavouriteBeatmapsets
+    + GetUsersSharingHardware
+    + SilenceUser
+    + SubmitScore
+    + GetBeatmapScores
+    + GetScoreMd5
+    + AddReplayView
     + BatchProcessPackets
     + ProcessPackets
     + ClientPing
@@ -56,10 +94,23 @@ pub trait BanchoService:
     + ToggleBlockNonFriendDms
     + UserLogout
     + RequestPresence
+    + SpectateStart
     + SpectateStop
     + SpectateCant
     + LobbyPart
     + LobbyJoin
+    + MatchCreate
+    + MatchJoin
+    + MatchPart
+    + MatchStart
+    + MatchLoadComplete
+    + MatchComplete
+    + MatchScoreUpdate
+    + TournamentMatchInfo
+    + TournamentJoinMatchChannel
+    + TournamentLeaveMatchChannel
+    + GetPacketMetrics
+    + GetLeaderboardCacheMetrics
 {
 }
 
@@ -72,10 +123,106 @@ pub trait Login {
     ) -> Result<LoginSuccess, BanchoServiceError>;
 }
 
+#[async_trait]
+pub trait AuthenticateUser {
+    /// Check the credentials sent by the client to the web endpoints, which
+    /// do not carry the session token. Banned users are refused.
+    async fn authenticate_user(
+        &self,
+        request: AuthenticateUserRequest,
+    ) -> Result<AuthenticateUserResponse, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait ClientRegister {
+    /// In-game registration, field errors are returned in the response
+    /// instead of as an error.
+    async fn client_register(
+        &self,
+        request: ClientRegisterRequest,
+    ) -> Result<ClientRegisterResponse, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait AddFavouriteBeatmapset {
+    /// Adding a beatmapset twice is not an error, the status of the
+    /// response tells what happened.
+    async fn add_favourite_beatmapset(
+        &self,
+        request: AddFavouriteBeatmapsetRequest,
+    ) -> Result<AddFavouriteBeatmapsetResponse, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait GetFavouriteBeatmapsets {
+    async fn get_favourite_beatmapsets(
+        &self,
+        request: GetFavouriteBeatmapsetsRequest,
+    ) -> Result<GetFavouriteBeatmapsetsResponse, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait SubmitScore {
+    /// Failed scores are stored too, but never replace the best score of
+    /// the user on the beatmap.
+    async fn submit_score(
+        &self,
+        request: SubmitScoreRequest,
+    ) -> Result<SubmitScoreResponse, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait GetBeatmapScores {
+    /// The personal best of the user is returned even if it is not in the
+    /// top scores.
+    async fn get_beatmap_scores(
+        &self,
+        request: GetBeatmapScoresRequest,
+    ) -> Result<GetBeatmapScoresResponse, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait GetScoreMd5 {
+    async fn get_score_md5(
+        &self,
+        request: GetScoreMd5Request,
+    ) -> Result<GetScoreMd5Response, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait AddReplayView {
+    /// Returns the views of the replay after counting this one.
+    async fn add_replay_view(
+        &self,
+        request: AddReplayViewRequest,
+    ) -> Result<AddReplayViewResponse, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait GetUsersSharingHardware {
+    /// Users sharing an adapters hash, uninstall id or disk id with the
+    /// user, to look for multi-accounts.
+    async fn get_users_sharing_hardware(
+        &self,
+        request: GetUsersSharingHardwareRequest,
+    ) -> Result<GetUsersSharingHardwareResponse, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait SilenceUser {
+    /// Store the silence of the user and apply it to the online session,
+    /// the user cannot chat until it expires.
+    async fn silence_user(
+        &self,
+        request: SilenceUserRequest,
+    ) -> Result<SilenceUserResponse, BanchoServiceError>;
+}
+
 #[async_trait]
 pub trait BatchProcessPackets {
     async fn batch_process_bancho_packets(
         &self,
+        client_ip: Option<IpAddr>,
         request: BatchProcessBanchoPacketsRequest,
     ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
 }
@@ -158,6 +305,14 @@ pub trait RequestPresence {
     ) -> Result<HandleCompleted, BanchoServiceError>;
 }
 
+#[async_trait]
+pub trait SpectateStart {
+    async fn spectate_start(
+        &self,
+        request: SpectateStartRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError>;
+}
+
 #[async_trait]
 pub trait SpectateStop {
     async fn spectate_stop(
@@ -190,6 +345,100 @@ pub trait LobbyJoin {
     ) -> Result<HandleCompleted, BanchoServiceError>;
 }
 
+#[async_trait]
+pub trait MatchCreate {
+    async fn match_create(
+        &self,
+        request: MatchCreateRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait MatchJoin {
+    async fn match_join(
+        &self,
+        request: MatchJoinRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait MatchPart {
+    async fn match_part(
+        &self,
+        user_query: UserQuery,
+    ) -> Result<HandleCompleted, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait MatchStart {
+    async fn match_start(
+        &self,
+        user_query: UserQuery,
+    ) -> Result<HandleCompleted, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait MatchLoadComplete {
+    async fn match_load_complete(
+        &self,
+        user_query: UserQuery,
+    ) -> Result<HandleCompleted, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait MatchComplete {
+    async fn match_complete(
+        &self,
+        user_query: UserQuery,
+    ) -> Result<HandleCompleted, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait MatchScoreUpdate {
+    async fn match_score_update(
+        &self,
+        request: MatchScoreUpdateRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait TournamentMatchInfo {
+    async fn tournament_match_info(
+        &self,
+        request: TournamentMatchRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait TournamentJoinMatchChannel {
+    async fn tournament_join_match_channel(
+        &self,
+        request: TournamentMatchRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait TournamentLeaveMatchChannel {
+    async fn tournament_leave_match_channel(
+        &self,
+        request: TournamentMatchRequest,
+    ) -> Result<HandleCompleted, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait GetPacketMetrics {
+    async fn get_packet_metrics(
+        &self,
+    ) -> Result<PacketMetricsResponse, BanchoServiceError>;
+}
+
+#[async_trait]
+pub trait GetLeaderboardCacheMetrics {
+    async fn get_leaderboard_cache_metrics(
+        &self,
+    ) -> Result<LeaderboardCacheMetricsResponse, BanchoServiceError>;
+}
+
 pub trait BanchoPacketProcessor:
     ProcessSendPublicMessage
     + ProcessSendPrivateMessage
@@ -203,6 +452,20 @@ pub trait BanchoPacketProcessor:
     + ProcessUserToggleBlockNonFriendDms
     + ProcessUserLogout
     + ProcessUserPresenceRequest
+    + ProcessSpectateStart
+    + ProcessSpectateStop
+    + ProcessUserJoinLobby
+    + ProcessUserPartLobby
+    + ProcessMatchCreate
+    + ProcessMatchJoin
+    + ProcessMatchPart
+    + ProcessMatchStart
+    + ProcessMatchLoadComplete
+    + ProcessMatchComplete
+    + ProcessMatchScoreUpdate
+    + ProcessTournamentMatchInfo
+    + ProcessTournamentJoinMatchChannel
+    + ProcessTournamentLeaveMatchChannel
 {
 }
 
@@ -289,3 +552,101 @@ pub trait ProcessUserPresenceRequest {
         &self,
     ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
 }
+
+#[async_trait]
+pub trait ProcessUserJoinLobby {
+    async fn user_join_lobby(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
+}
+
+#[async_trait]
+pub trait ProcessSpectateStart {
+    async fn spectate_start(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
+}
+
+#[async_trait]
+pub trait ProcessSpectateStop {
+    async fn spectate_stop(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
+}
+
+#[async_trait]
+pub trait ProcessUserPartLobby {
+    async fn user_part_lobby(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
+}
+
+#[async_trait]
+pub trait ProcessMatchCreate {
+    async fn match_create(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
+}
+
+#[async_trait]
+pub trait ProcessMatchJoin {
+    async fn match_join(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
+}
+
+#[async_trait]
+pub trait ProcessMatchPart {
+    async fn match_part(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
+}
+
+#[async_trait]
+pub trait ProcessMatchStart {
+    async fn match_start(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
+}
+
+#[async_trait]
+pub trait ProcessMatchLoadComplete {
+    async fn match_load_complete(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
+}
+
+#[async_trait]
+pub trait ProcessMatchComplete {
+    async fn match_complete(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
+}
+
+#[async_trait]
+pub trait ProcessMatchScoreUpdate {
+    async fn match_score_update(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
+}
+
+#[async_trait]
+pub trait ProcessTournamentMatchInfo {
+    async fn tournament_match_info(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
+}
+
+#[async_trait]
+pub trait ProcessTournamentJoinMatchChannel {
+    async fn tournament_join_match_channel(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
+}
+
+#[async_trait]
+pub trait ProcessTournamentLeaveMatchChannel {
+    async fn tournament_leave_match_channel(
+        &self,
+    ) -> Result<HandleCompleted, ProcessBanchoPacketError>;
+}
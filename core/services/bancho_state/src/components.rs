@@ -1,20 +1,29 @@
+use crate::{CreateSessionError, PacketHistory};
 use async_trait::async_trait;
 use bancho_packets::server::{UserPresence, UserStats};
 use clap_serde_derive::ClapSerde;
 use domain_bancho::{
     BanchoPrivileges, GameMode, Mods, PresenceFilter, UserOnlineStatus,
 };
-use domain_bancho_state::ConnectionInfo;
+use domain_bancho_state::{ConnectionInfo, ConnectionInfoV1};
 use infra_packets::{Packet, PacketsQueue};
 use infra_users::CreateSessionDto;
-use infra_users::{BaseSession, BaseSessionData, UserIndexes, UserStore};
-use peace_snapshot::{cli_snapshot_config, CreateSnapshot, SnapshotType};
+use infra_users::{
+    BaseSession, BaseSessionData, SessionIpAddr, UserIndexes, UserStore,
+};
+use peace_cfg::{ConfigError, ConfigErrors, ValidateConfig};
+use peace_snapshot::{
+    cli_snapshot_config, CreateSnapshot, SnapshotConfig, SnapshotType,
+};
 use peace_unique_id::Ulid;
 use std::{
-    ops::{Deref, DerefMut},
+    net::IpAddr,
+    ops::{Deref, DerefMut, RangeInclusive},
     sync::Arc,
 };
-use tools::atomic::{Atomic, AtomicOption, AtomicValue, Bool, F32, U32, U64};
+use tools::atomic::{
+    Atomic, AtomicOption, AtomicValue, Bool, F32, I64, U32, U64,
+};
 
 pub type SessionIndexes = UserIndexes<BanchoSession>;
 pub type UserSessions = UserStore<BanchoSession>;
@@ -78,7 +87,7 @@ pub struct UserModeStatSets {
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct BanchoExtend {
     pub client_version: String,
-    pub utc_offset: u8,
+    pub utc_offset: i8,
     pub presence_filter: Atomic<PresenceFilter>,
     pub display_city: bool,
     pub only_friend_pm_allowed: Bool,
@@ -89,6 +98,18 @@ pub struct BanchoExtend {
     pub connection_info: ConnectionInfo,
     pub country_code: u8,
     pub notify_index: Atomic<Ulid>,
+    #[serde(skip)]
+    pub packets_queue_overflowed: Bool,
+    /// Unix timestamp (secs) of the end of the silence of the user, `0` if
+    /// the user is not silenced.
+    ///
+    /// Silences are stored by the users repository and restored on login,
+    /// the snapshots keep them for the sessions restored without login.
+    pub silence_end: I64,
+    /// Not kept by the snapshots, the sessions loaded from a snapshot have
+    /// no history until their next login.
+    #[serde(skip)]
+    pub packet_history: PacketHistory,
 }
 
 impl From<BanchoExtendData> for BanchoExtend {
@@ -106,6 +127,9 @@ impl From<BanchoExtendData> for BanchoExtend {
             connection_info: data.connection_info,
             country_code: data.country_code,
             notify_index: data.notify_index.into(),
+            packets_queue_overflowed: Bool::default(),
+            silence_end: data.silence_end.into(),
+            packet_history: PacketHistory::default(),
         }
     }
 }
@@ -126,22 +150,60 @@ impl CreateSnapshot<BanchoExtendData> for BanchoExtend {
             connection_info: self.connection_info.clone(),
             country_code: self.country_code,
             notify_index: *self.notify_index.load().as_ref(),
+            silence_end: self.silence_end.val(),
         }
     }
 }
 
 impl BanchoExtend {
+    /// Valid utc offsets (hours) of a session.
+    pub const UTC_OFFSET_RANGE: RangeInclusive<i32> = -12..=14;
+
+    #[inline]
+    pub fn validate_utc_offset(
+        utc_offset: i32,
+    ) -> Result<i8, CreateSessionError> {
+        if !Self::UTC_OFFSET_RANGE.contains(&utc_offset) {
+            return Err(CreateSessionError::InvalidUtcOffset(utc_offset));
+        }
+
+        Ok(utc_offset as i8)
+    }
+
+    /// The utc offset as sent in presences, clamped into
+    /// [`Self::UTC_OFFSET_RANGE`].
+    #[inline]
+    pub fn presence_utc_offset(&self) -> u8 {
+        let (min, max) =
+            (*Self::UTC_OFFSET_RANGE.start(), *Self::UTC_OFFSET_RANGE.end());
+        i32::from(self.utc_offset).clamp(min, max) as i8 as u8
+    }
+
+    /// Remaining secs of a silence ending at `silence_end` (unix secs),
+    /// `0` if it is expired at `now`.
+    #[inline]
+    pub fn silence_remaining(silence_end: i64, now: i64) -> i32 {
+        (silence_end - now).clamp(0, i32::MAX as i64) as i32
+    }
+
+    #[inline]
+    pub fn is_silenced(&self, now: i64) -> bool {
+        Self::silence_remaining(self.silence_end.val(), now) > 0
+    }
+
     #[inline]
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         initial_packets: Option<Vec<u8>>,
         client_version: String,
-        utc_offset: u8,
+        utc_offset: i8,
         display_city: bool,
         only_friend_pm_allowed: bool,
         bancho_privileges: BanchoPrivileges,
         connection_info: ConnectionInfo,
         country_code: u8,
+        silence_end: i64,
+        packet_history_size: usize,
     ) -> Self {
         let packets_queue =
             initial_packets.map(PacketsQueue::from).unwrap_or_default();
@@ -155,6 +217,8 @@ impl BanchoExtend {
             packets_queue,
             connection_info,
             country_code,
+            silence_end: silence_end.into(),
+            packet_history: PacketHistory::new(packet_history_size),
             ..Default::default()
         }
     }
@@ -166,6 +230,32 @@ pub struct BanchoSessionData {
     pub extends: BanchoExtendData,
 }
 
+/// [`BanchoSessionData`] of the version 1 snapshots.
+#[derive(Debug, Deserialize)]
+pub struct BanchoSessionDataV1 {
+    pub base: BaseSessionData,
+    pub extends: BanchoExtendDataV1,
+}
+
+impl From<BanchoSessionDataV1> for BanchoSessionData {
+    fn from(v1: BanchoSessionDataV1) -> Self {
+        Self { base: v1.base, extends: v1.extends.into() }
+    }
+}
+
+/// [`BanchoSessionData`] of the version 2 snapshots.
+#[derive(Debug, Deserialize)]
+pub struct BanchoSessionDataV2 {
+    pub base: BaseSessionData,
+    pub extends: BanchoExtendDataV2,
+}
+
+impl From<BanchoSessionDataV2> for BanchoSessionData {
+    fn from(v2: BanchoSessionDataV2) -> Self {
+        Self { base: v2.base, extends: v2.extends.into() }
+    }
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct BanchoSession {
     pub base: BaseSession,
@@ -202,6 +292,13 @@ impl DerefMut for BanchoSession {
     }
 }
 
+impl SessionIpAddr for BanchoSession {
+    #[inline]
+    fn ip_addr(&self) -> Option<IpAddr> {
+        self.extends.connection_info.ip.parse().ok()
+    }
+}
+
 impl BanchoSession {
     pub fn new(
         CreateSessionDto {
@@ -223,6 +320,44 @@ impl BanchoSession {
         }
     }
 
+    /// Push a packet into the session's packets queue, returns the queue
+    /// length after pushing.
+    ///
+    /// If the length exceeds `max_queued_packets` (`0` means unlimited),
+    /// the client is considered as not draining its queue: the queue will
+    /// be dropped and the session marked as overflowed, waiting to be
+    /// disconnected.
+    #[inline]
+    pub async fn push_packet(
+        &self,
+        packet: Packet,
+        max_queued_packets: usize,
+    ) -> usize {
+        let mut queue = self.extends.packets_queue.queue.lock().await;
+        queue.push_back(packet);
+
+        let len = queue.len();
+        if max_queued_packets > 0 && len > max_queued_packets {
+            queue.clear();
+            self.extends.packets_queue_overflowed.set(true);
+        }
+
+        self.extends.packets_queue.notify.notify_one();
+
+        len
+    }
+
+    /// Wait until packets are pushed into the session's packets queue.
+    #[inline]
+    pub async fn packets_pushed(&self) {
+        self.extends.packets_queue.notified().await
+    }
+
+    #[inline]
+    pub fn packets_queue_overflowed(&self) -> bool {
+        self.extends.packets_queue_overflowed.val()
+    }
+
     #[inline]
     pub fn mode_stats(&self) -> Option<Arc<ModeStats>> {
         let stats = &self.extends.mode_stat_sets;
@@ -240,12 +375,26 @@ impl BanchoSession {
     }
 
     #[inline]
-    pub fn user_info_packets(&self) -> Vec<u8> {
+    pub fn user_info_packets(&self, hide_location: bool) -> Vec<u8> {
         let mut info = self.user_stats_packet();
-        info.extend(self.user_presence_packet());
+        info.extend(self.presence_only_packet(hide_location));
         info
     }
 
+    /// Whether the presence filter of this session lets `user_id` see it.
+    ///
+    /// Friend lists are not loaded in bancho state, so a session filtering
+    /// on friends is only visible to itself.
+    #[inline]
+    pub fn presence_visible_to(&self, user_id: i32) -> bool {
+        match self.extends.presence_filter.load().as_ref() {
+            PresenceFilter::Friends => self.user_id == user_id,
+            PresenceFilter::None | PresenceFilter::All => true,
+        }
+    }
+
+    /// Status and stats of the user, sent when they change status or their
+    /// stats are updated.
     #[inline]
     pub fn user_stats_packet(&self) -> Vec<u8> {
         let status = &self.extends.bancho_status;
@@ -258,7 +407,7 @@ impl BanchoSession {
             status.description.to_string().into(),
             status.beatmap_md5.to_string().into(),
             status.mods.load().bits(),
-            status.mode.load().val(),
+            status.mode.load().vanilla_mode(),
             status.beatmap_id.val() as i32,
             stats.map(|s| s.ranked_score.val()).unwrap_or_default() as i64,
             stats.map(|s| s.accuracy.val()).unwrap_or_default(),
@@ -269,16 +418,28 @@ impl BanchoSession {
         )
     }
 
+    /// Presence of the user (name, region, privileges), without the stats.
+    /// Much smaller than [`Self::user_info_packets`], used for the presence
+    /// refreshes.
+    ///
+    /// With `hide_location`, the coordinates are zeroed and only the country
+    /// is sent, the stored connection info is left untouched.
     #[inline]
-    pub fn user_presence_packet(&self) -> Vec<u8> {
+    pub fn presence_only_packet(&self, hide_location: bool) -> Vec<u8> {
+        let location = &self.extends.connection_info.location;
+        let (longitude, latitude) = match hide_location {
+            true => (0.0, 0.0),
+            false => (location.longitude as f32, location.latitude as f32),
+        };
+
         UserPresence::pack(
             self.user_id,
             self.username.to_string().into(),
-            self.extends.utc_offset,
+            self.extends.presence_utc_offset(),
             self.extends.country_code,
             self.extends.bancho_privileges.load().bits(),
-            self.extends.connection_info.location.longitude as f32,
-            self.extends.connection_info.location.latitude as f32,
+            longitude,
+            latitude,
             self.mode_stats().map(|s| s.rank.val()).unwrap_or_default() as i32,
         )
     }
@@ -287,7 +448,7 @@ impl BanchoSession {
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct BanchoExtendData {
     pub client_version: String,
-    pub utc_offset: u8,
+    pub utc_offset: i8,
     pub presence_filter: PresenceFilter,
     pub display_city: bool,
     pub only_friend_pm_allowed: bool,
@@ -298,6 +459,345 @@ pub struct BanchoExtendData {
     pub connection_info: ConnectionInfo,
     pub country_code: u8,
     pub notify_index: Ulid,
+    /// Missing from the json data written before version 3.
+    #[serde(default)]
+    pub silence_end: i64,
+}
+
+/// [`BanchoExtendData`] of the version 2 snapshots.
+#[derive(Debug, Deserialize)]
+pub struct BanchoExtendDataV2 {
+    pub client_version: String,
+    pub utc_offset: i8,
+    pub presence_filter: PresenceFilter,
+    pub display_city: bool,
+    pub only_friend_pm_allowed: bool,
+    pub bancho_status: BanchoStatus,
+    pub bancho_privileges: BanchoPrivileges,
+    pub mode_stat_sets: UserModeStatSets,
+    pub packets_queue: Vec<Packet>,
+    pub connection_info: ConnectionInfo,
+    pub country_code: u8,
+    pub notify_index: Ulid,
+}
+
+impl From<BanchoExtendDataV2> for BanchoExtendData {
+    fn from(v2: BanchoExtendDataV2) -> Self {
+        Self {
+            client_version: v2.client_version,
+            utc_offset: v2.utc_offset,
+            presence_filter: v2.presence_filter,
+            display_city: v2.display_city,
+            only_friend_pm_allowed: v2.only_friend_pm_allowed,
+            bancho_status: v2.bancho_status,
+            bancho_privileges: v2.bancho_privileges,
+            mode_stat_sets: v2.mode_stat_sets,
+            packets_queue: v2.packets_queue,
+            connection_info: v2.connection_info,
+            country_code: v2.country_code,
+            notify_index: v2.notify_index,
+            silence_end: 0,
+        }
+    }
+}
+
+/// [`BanchoExtendData`] of the version 1 snapshots.
+#[derive(Debug, Deserialize)]
+pub struct BanchoExtendDataV1 {
+    pub client_version: String,
+    pub utc_offset: i8,
+    pub presence_filter: PresenceFilter,
+    pub display_city: bool,
+    pub only_friend_pm_allowed: bool,
+    pub bancho_status: BanchoStatus,
+    pub bancho_privileges: BanchoPrivileges,
+    pub mode_stat_sets: UserModeStatSets,
+    pub packets_queue: Vec<Packet>,
+    pub connection_info: ConnectionInfoV1,
+    pub country_code: u8,
+    pub notify_index: Ulid,
+}
+
+impl From<BanchoExtendDataV1> for BanchoExtendData {
+    fn from(v1: BanchoExtendDataV1) -> Self {
+        Self {
+            client_version: v1.client_version,
+            utc_offset: v1.utc_offset,
+            presence_filter: v1.presence_filter,
+            display_city: v1.display_city,
+            only_friend_pm_allowed: v1.only_friend_pm_allowed,
+            bancho_status: v1.bancho_status,
+            bancho_privileges: v1.bancho_privileges,
+            mode_stat_sets: v1.mode_stat_sets,
+            packets_queue: v1.packets_queue,
+            connection_info: v1.connection_info.into(),
+            country_code: v1.country_code,
+            notify_index: v1.notify_index,
+            silence_end: 0,
+        }
+    }
 }
 
 cli_snapshot_config!(service: BanchoState);
+
+impl ValidateConfig for CliBanchoStateServiceSnapshotConfigs {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        if self.should_save_snapshot() || self.should_load_snapshot() {
+            errors.check(
+                !self.snapshot_path().trim().is_empty(),
+                "bancho_state_snapshot_path",
+                "must not be empty",
+            );
+        }
+
+        if self.should_load_snapshot() {
+            errors.check(
+                self.snapshot_expired_secs() > 0,
+                "bancho_state_snapshot_expired_secs",
+                "must be greater than 0, or the snapshot is never loaded",
+            );
+        }
+
+        errors.into_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bancho_packets::PacketId;
+
+    #[tokio::test]
+    async fn test_push_packet_past_max_queued_packets() {
+        const MAX_QUEUED_PACKETS: usize = 8;
+
+        let session = BanchoSession::default();
+
+        for i in 1..=MAX_QUEUED_PACKETS {
+            let len = session
+                .push_packet(Packet::new(vec![i as u8]), MAX_QUEUED_PACKETS)
+                .await;
+            assert_eq!(len, i);
+        }
+        assert!(!session.packets_queue_overflowed());
+
+        let len =
+            session.push_packet(Packet::new(vec![0]), MAX_QUEUED_PACKETS).await;
+        assert_eq!(len, MAX_QUEUED_PACKETS + 1);
+
+        assert!(session.packets_queue_overflowed());
+        assert_eq!(session.extends.packets_queue.queued_packets().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_push_packet_unlimited() {
+        let session = BanchoSession::default();
+
+        for _ in 0..1024 {
+            session.push_packet(Packet::new(vec![0]), 0).await;
+        }
+
+        assert!(!session.packets_queue_overflowed());
+        assert_eq!(session.extends.packets_queue.queued_packets().await, 1024);
+    }
+
+    #[tokio::test]
+    async fn test_push_packet_wakes_waiter() {
+        let session = Arc::new(BanchoSession::default());
+
+        let waiter = tokio::spawn({
+            let session = session.clone();
+            async move { session.packets_pushed().await }
+        });
+        tokio::task::yield_now().await;
+
+        session.push_packet(Packet::new(vec![0]), 0).await;
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), waiter)
+            .await
+            .expect("waiter is not woken")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_session_ip_index() {
+        let session = |user_id: i32, ip: &str| {
+            Arc::new(BanchoSession {
+                base: BaseSession::new(
+                    user_id,
+                    format!("user{user_id}"),
+                    None,
+                    1,
+                ),
+                extends: BanchoExtend {
+                    connection_info: ConnectionInfo {
+                        ip: ip.to_owned(),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            })
+        };
+
+        let ip = "10.0.0.1".parse::<IpAddr>().unwrap();
+        assert_eq!(session(1, "10.0.0.1").ip_addr(), Some(ip));
+        // an unknown ip is not indexed
+        assert_eq!(session(2, "").ip_addr(), None);
+
+        let mut indexes = UserIndexes::new();
+        for s in
+            [session(1, "10.0.0.1"), session(2, "10.0.0.1"), session(3, "")]
+        {
+            indexes.add_session(s);
+        }
+        assert_eq!(indexes.ip.len(), 1);
+        assert_eq!(indexes.ip[&ip].len(), 2);
+
+        let removed = indexes.user_id[&1].clone();
+        indexes.remove_session(&1, "user1", &removed.id, None);
+        assert_eq!(
+            indexes.ip[&ip].values().map(|s| s.user_id).collect::<Vec<_>>(),
+            vec![2]
+        );
+
+        let removed = indexes.user_id[&2].clone();
+        indexes.remove_session(&2, "user2", &removed.id, None);
+        assert!(indexes.ip.is_empty());
+    }
+
+    #[test]
+    fn test_validate_utc_offset() {
+        assert_eq!(BanchoExtend::validate_utc_offset(-12).unwrap(), -12);
+        assert_eq!(BanchoExtend::validate_utc_offset(0).unwrap(), 0);
+        assert_eq!(BanchoExtend::validate_utc_offset(14).unwrap(), 14);
+
+        for invalid in [-13, 15, i32::MIN, i32::MAX] {
+            assert!(matches!(
+                BanchoExtend::validate_utc_offset(invalid),
+                Err(CreateSessionError::InvalidUtcOffset(v)) if v == invalid
+            ));
+        }
+
+        // used to be wrapped into `8` by `as u8`
+        assert!(BanchoExtend::validate_utc_offset(264).is_err());
+    }
+
+    #[test]
+    fn test_silence_active() {
+        const NOW: i64 = 1_700_000_000;
+
+        let extends = BanchoExtend {
+            silence_end: (NOW + 60).into(),
+            ..Default::default()
+        };
+
+        assert!(extends.is_silenced(NOW));
+        assert_eq!(BanchoExtend::silence_remaining(NOW + 60, NOW), 60);
+        assert_eq!(BanchoExtend::silence_remaining(i64::MAX, NOW), i32::MAX);
+    }
+
+    #[test]
+    fn test_silence_expired() {
+        const NOW: i64 = 1_700_000_000;
+
+        let extends = BanchoExtend {
+            silence_end: (NOW - 1).into(),
+            ..Default::default()
+        };
+
+        assert!(!extends.is_silenced(NOW));
+        assert!(!extends.is_silenced(NOW + 1));
+        assert!(!BanchoExtend::default().is_silenced(NOW));
+        assert_eq!(BanchoExtend::silence_remaining(NOW, NOW), 0);
+        assert_eq!(BanchoExtend::silence_remaining(0, NOW), 0);
+    }
+
+    #[test]
+    fn test_presence_timezone_byte() {
+        // header (7) + user_id (4) + username "a" (3)
+        const TIMEZONE_BYTE: usize = 14;
+
+        for (utc_offset, expected) in [(-12, 12), (0, 24), (14, 38)] {
+            let session = BanchoSession::new(CreateSessionDto {
+                user_id: 1,
+                username: "a".to_owned(),
+                extends: BanchoExtend {
+                    utc_offset: BanchoExtend::validate_utc_offset(utc_offset)
+                        .unwrap(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+
+            assert_eq!(
+                session.presence_only_packet(false)[TIMEZONE_BYTE],
+                expected
+            );
+        }
+
+        // out of range offsets (e.g. from old snapshots) are clamped
+        for (utc_offset, expected) in [(i8::MIN, 12), (i8::MAX, 38)] {
+            let extends = BanchoExtend { utc_offset, ..Default::default() };
+            assert_eq!(
+                extends.presence_utc_offset().wrapping_add(24),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_presence_only_packet() {
+        let session = BanchoSession::default();
+
+        let presence = session.presence_only_packet(false);
+        assert_eq!(presence[0], PacketId::BANCHO_USER_PRESENCE as u8);
+        assert_ne!(presence[0], PacketId::BANCHO_USER_STATS as u8);
+
+        // a single packet, the stats are not appended
+        let len = u32::from_le_bytes(presence[3..7].try_into().unwrap());
+        assert_eq!(presence.len(), 7 + len as usize);
+    }
+
+    #[test]
+    fn test_presence_hide_location() {
+        // header (7) + user_id (4) + username "a" (3) + timezone (1)
+        const COUNTRY_BYTE: usize = 15;
+        // country (1) + privileges (1)
+        const LONGITUDE_BYTE: usize = COUNTRY_BYTE + 2;
+        const LATITUDE_BYTE: usize = LONGITUDE_BYTE + 4;
+
+        let mut connection_info = ConnectionInfo::default();
+        connection_info.location.longitude = 151.2;
+        connection_info.location.latitude = -33.8;
+
+        let session = BanchoSession::new(CreateSessionDto {
+            user_id: 1,
+            username: "a".to_owned(),
+            extends: BanchoExtend {
+                connection_info,
+                country_code: 16,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let coord = |presence: &[u8], at: usize| {
+            f32::from_le_bytes(presence[at..at + 4].try_into().unwrap())
+        };
+
+        let presence = session.presence_only_packet(false);
+        assert_eq!(presence[COUNTRY_BYTE], 16);
+        assert_eq!(coord(&presence, LONGITUDE_BYTE), 151.2);
+        assert_eq!(coord(&presence, LATITUDE_BYTE), -33.8);
+
+        let presence = session.presence_only_packet(true);
+        assert_eq!(presence[COUNTRY_BYTE], 16);
+        assert_eq!(coord(&presence, LONGITUDE_BYTE), 0.0);
+        assert_eq!(coord(&presence, LATITUDE_BYTE), 0.0);
+
+        // the stored location is kept
+        assert_eq!(session.extends.connection_info.location.longitude, 151.2);
+    }
+}
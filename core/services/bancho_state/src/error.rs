@@ -7,6 +7,28 @@ use tonic::Status;
 pub enum CreateSessionError {
     #[error("invalid connection info")]
     InvalidConnectionInfo,
+    #[error("invalid utc offset: {0}")]
+    InvalidUtcOffset(i32),
+    #[error("server is shutting down, logins are closed")]
+    LoginsClosed,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+pub enum MultiplayerError {
+    #[error("user is not in a match")]
+    NotInMatch,
+    #[error("only the host can do this")]
+    NotMatchHost,
+    #[error("match is already in progress")]
+    MatchInProgress,
+    #[error("match is not in progress")]
+    MatchNotInProgress,
+    #[error("match not exists")]
+    MatchNotExists,
+    #[error("invalid match password")]
+    InvalidMatchPassword,
+    #[error("match has no free slot")]
+    MatchFull,
 }
 
 #[derive(thiserror::Error, Debug, Serialize, Deserialize, RpcError)]
@@ -20,6 +42,8 @@ pub enum BanchoStateError {
     #[error(transparent)]
     CreateSessionError(#[from] CreateSessionError),
     #[error(transparent)]
+    MultiplayerError(#[from] MultiplayerError),
+    #[error(transparent)]
     ConvertError(#[from] ConvertError),
     #[error("TonicError: {0}")]
     TonicError(String),
@@ -10,11 +10,17 @@ extern crate serde;
 
 pub mod components;
 pub mod error;
+pub mod multiplayer;
+pub mod packet_history;
 pub mod services;
+pub mod spectating;
 
 pub use components::*;
 pub use error::*;
+pub use multiplayer::*;
+pub use packet_history::*;
 pub use services::*;
+pub use spectating::*;
 
 pub mod rpc_config {
     use clap_serde_derive::ClapSerde;
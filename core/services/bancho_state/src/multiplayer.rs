@@ -0,0 +1,710 @@
+use crate::MultiplayerError;
+use bancho_packets::{server, MatchData, ScoreFrame, MATCH_SLOTS};
+use domain_bancho::MatchSlotStatus;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::sync::RwLock;
+
+/// Packets to send after a match changed, with the user ids receiving them.
+pub type MatchNotifications = Vec<(Vec<i32>, Vec<u8>)>;
+
+#[derive(Debug, Default)]
+struct MatchProgress {
+    loaded: HashSet<i32>,
+    all_loaded: bool,
+}
+
+/// What happened to a match after a player left it.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PlayerRemoved {
+    /// The remaining players have all loaded the game.
+    pub all_loaded: bool,
+    /// The remaining players have all completed the game.
+    pub completed: bool,
+    pub new_host: Option<i32>,
+    /// No player is left in the match.
+    pub empty: bool,
+}
+
+#[derive(Debug)]
+pub struct Match {
+    pub id: i32,
+    pub data: RwLock<MatchData>,
+    /// Only locked while holding the `data` write lock.
+    progress: Mutex<MatchProgress>,
+}
+
+impl Match {
+    #[inline]
+    pub fn new(id: i32, mut data: MatchData) -> Self {
+        data.match_id = id;
+        Self { id, data: RwLock::new(data), progress: Mutex::default() }
+    }
+
+    #[inline]
+    fn slot_statuses(
+        data: &MatchData,
+    ) -> impl Iterator<Item = (MatchSlotStatus, i32)> + '_ {
+        data.slot_status
+            .iter()
+            .zip(data.slot_players.iter())
+            .map(|(status, user_id)| (MatchSlotStatus::from(*status), *user_id))
+    }
+
+    #[inline]
+    fn player_slot(data: &MatchData, user_id: i32) -> Option<usize> {
+        Self::slot_statuses(data).position(|(status, id)| {
+            id == user_id && status.intersects(MatchSlotStatus::HasPlayer)
+        })
+    }
+
+    #[inline]
+    fn players_with(data: &MatchData, statuses: MatchSlotStatus) -> Vec<i32> {
+        Self::slot_statuses(data)
+            .filter(|(status, _)| status.intersects(statuses))
+            .map(|(_, user_id)| user_id)
+            .collect()
+    }
+
+    /// Name of the match chat channel, shown as `#multiplayer` by the client.
+    #[inline]
+    pub fn channel_name(&self) -> String {
+        format!("#multi_{}", self.id)
+    }
+
+    /// User ids of the players occupying a slot.
+    pub async fn players(&self) -> Vec<i32> {
+        Self::players_with(&*self.data.read().await, MatchSlotStatus::HasPlayer)
+    }
+
+    /// Slot index of a player.
+    pub async fn slot_of(&self, user_id: i32) -> Option<usize> {
+        Self::player_slot(&*self.data.read().await, user_id)
+    }
+
+    /// Match data shown in the lobby, the password is replaced by an empty
+    /// one so the client only knows the match is locked.
+    pub async fn lobby_data(&self) -> MatchData {
+        let mut data = self.data.read().await.clone();
+        if data.password.is_some() {
+            data.password = Some(String::new());
+        }
+
+        data
+    }
+
+    /// Put a player in the first open slot.
+    pub async fn add_player(
+        &self,
+        user_id: i32,
+        password: &str,
+    ) -> Result<MatchData, MultiplayerError> {
+        let mut data = self.data.write().await;
+
+        match data.password.as_deref() {
+            Some(pw) if !pw.is_empty() && pw != password => {
+                return Err(MultiplayerError::InvalidMatchPassword)
+            },
+            _ => {},
+        }
+
+        if Self::player_slot(&data, user_id).is_none() {
+            let slot = data
+                .slot_status
+                .iter()
+                .position(|status| {
+                    MatchSlotStatus::from(*status) == MatchSlotStatus::Open
+                })
+                .ok_or(MultiplayerError::MatchFull)?;
+
+            data.slot_status[slot] = MatchSlotStatus::NotReady.bits();
+            data.slot_players[slot] = user_id;
+        }
+
+        Ok(data.clone())
+    }
+
+    /// Start the game, every player having the beatmap will play it.
+    /// Returns the match data agreed for the game.
+    pub async fn start(
+        &self,
+        user_id: i32,
+    ) -> Result<MatchData, MultiplayerError> {
+        let mut data = self.data.write().await;
+
+        if data.host_player_id != user_id {
+            return Err(MultiplayerError::NotMatchHost);
+        }
+
+        if data.in_progress {
+            return Err(MultiplayerError::MatchInProgress);
+        }
+
+        for status in data.slot_status.iter_mut() {
+            let s = MatchSlotStatus::from(*status);
+            if s.intersects(MatchSlotStatus::HasPlayer)
+                && !s.intersects(MatchSlotStatus::NoMap)
+            {
+                *status = MatchSlotStatus::Playing.bits();
+            }
+        }
+
+        data.in_progress = true;
+        *self.progress.lock().unwrap() = MatchProgress::default();
+
+        Ok(data.clone())
+    }
+
+    /// Mark a player as loaded, returns `true` when all the players have
+    /// just finished loading.
+    pub async fn load_complete(
+        &self,
+        user_id: i32,
+    ) -> Result<bool, MultiplayerError> {
+        let data = self.data.write().await;
+        Self::ensure_playing(&data, user_id)?;
+
+        let mut progress = self.progress.lock().unwrap();
+        progress.loaded.insert(user_id);
+
+        Ok(Self::check_all_loaded(&data, &mut progress))
+    }
+
+    /// Mark a player as completed, returns `true` when all the players have
+    /// just completed, the match is then back to the not ready state.
+    pub async fn player_complete(
+        &self,
+        user_id: i32,
+    ) -> Result<bool, MultiplayerError> {
+        let mut data = self.data.write().await;
+        Self::ensure_playing(&data, user_id)?;
+
+        if let Some(slot) = Self::player_slot(&data, user_id) {
+            data.slot_status[slot] = MatchSlotStatus::Complete.bits();
+        }
+
+        let mut progress = self.progress.lock().unwrap();
+
+        Ok(Self::check_completed(&mut data, &mut progress))
+    }
+
+    /// Free the slot of a player. The other players are not waiting for
+    /// them anymore if the game is in progress.
+    pub async fn remove_player(&self, user_id: i32) -> Option<PlayerRemoved> {
+        let mut data = self.data.write().await;
+        let slot = Self::player_slot(&data, user_id)?;
+
+        data.slot_status[slot] = MatchSlotStatus::Open.bits();
+        data.slot_players[slot] = 0;
+
+        let mut progress = self.progress.lock().unwrap();
+        progress.loaded.remove(&user_id);
+
+        let players = Self::players_with(&data, MatchSlotStatus::HasPlayer);
+        if players.is_empty() {
+            return Some(PlayerRemoved { empty: true, ..Default::default() });
+        }
+
+        let new_host = (data.host_player_id == user_id).then(|| {
+            data.host_player_id = players[0];
+            players[0]
+        });
+
+        Some(PlayerRemoved {
+            all_loaded: Self::check_all_loaded(&data, &mut progress),
+            completed: Self::check_completed(&mut data, &mut progress),
+            new_host,
+            empty: false,
+        })
+    }
+
+    #[inline]
+    fn ensure_playing(
+        data: &MatchData,
+        user_id: i32,
+    ) -> Result<(), MultiplayerError> {
+        if !data.in_progress {
+            return Err(MultiplayerError::MatchNotInProgress);
+        }
+
+        match Self::player_slot(data, user_id) {
+            Some(slot)
+                if MatchSlotStatus::from(data.slot_status[slot])
+                    .intersects(MatchSlotStatus::Playing) =>
+            {
+                Ok(())
+            },
+            _ => Err(MultiplayerError::NotInMatch),
+        }
+    }
+
+    fn check_all_loaded(
+        data: &MatchData,
+        progress: &mut MatchProgress,
+    ) -> bool {
+        if !data.in_progress || progress.all_loaded {
+            return false;
+        }
+
+        let playing = Self::players_with(data, MatchSlotStatus::Playing);
+        if playing.is_empty()
+            || !playing.iter().all(|id| progress.loaded.contains(id))
+        {
+            return false;
+        }
+
+        progress.all_loaded = true;
+        true
+    }
+
+    fn check_completed(
+        data: &mut MatchData,
+        progress: &mut MatchProgress,
+    ) -> bool {
+        if !data.in_progress
+            || !Self::players_with(data, MatchSlotStatus::Playing).is_empty()
+        {
+            return false;
+        }
+
+        for status in data.slot_status.iter_mut() {
+            if MatchSlotStatus::from(*status)
+                .intersects(MatchSlotStatus::HasPlayer)
+            {
+                *status = MatchSlotStatus::NotReady.bits();
+            }
+        }
+
+        data.in_progress = false;
+        *progress = MatchProgress::default();
+
+        true
+    }
+}
+
+/// Multiplayer lobby members and the matches registry.
+#[derive(Debug, Default)]
+pub struct Multiplayer {
+    /// User ids of the lobby members, they receive the match list updates.
+    pub lobby: RwLock<HashSet<i32>>,
+    pub matches: RwLock<BTreeMap<i32, Arc<Match>>>,
+    match_id_counter: AtomicI32,
+}
+
+impl Multiplayer {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `false` if the user is already in the lobby.
+    #[inline]
+    pub async fn join_lobby(&self, user_id: i32) -> bool {
+        self.lobby.write().await.insert(user_id)
+    }
+
+    /// Returns `false` if the user is not in the lobby.
+    #[inline]
+    pub async fn part_lobby(&self, user_id: i32) -> bool {
+        self.lobby.write().await.remove(&user_id)
+    }
+
+    #[inline]
+    pub async fn in_lobby(&self, user_id: i32) -> bool {
+        self.lobby.read().await.contains(&user_id)
+    }
+
+    /// Register the match created by a user, the creator is the host and
+    /// takes the first slot. The user leaves their previous match.
+    pub async fn host_match(
+        &self,
+        user_id: i32,
+        mut data: MatchData,
+    ) -> (Arc<Match>, MatchNotifications) {
+        let notifications = self.leave_match(user_id).await;
+
+        data.in_progress = false;
+        data.host_player_id = user_id;
+        data.slot_status.resize(MATCH_SLOTS, MatchSlotStatus::Open.bits());
+        data.slot_teams.resize(MATCH_SLOTS, 0);
+        data.slot_players = vec![0; MATCH_SLOTS];
+        data.player_mods.resize(MATCH_SLOTS, 0);
+
+        for status in data.slot_status.iter_mut() {
+            if MatchSlotStatus::from(*status)
+                .intersects(MatchSlotStatus::HasPlayer)
+            {
+                *status = MatchSlotStatus::Open.bits();
+            }
+        }
+
+        data.slot_status[0] = MatchSlotStatus::NotReady.bits();
+        data.slot_players[0] = user_id;
+
+        (self.create_match(data).await, notifications)
+    }
+
+    /// Register a new match, its id is assigned here.
+    pub async fn create_match(&self, data: MatchData) -> Arc<Match> {
+        // the client reads match ids as u16
+        let id = self.match_id_counter.fetch_add(1, Ordering::Relaxed)
+            % u16::MAX as i32
+            + 1;
+
+        let m = Arc::new(Match::new(id, data));
+        self.matches.write().await.insert(id, m.clone());
+
+        m
+    }
+
+    #[inline]
+    pub async fn get_match(&self, match_id: i32) -> Option<Arc<Match>> {
+        self.matches.read().await.get(&match_id).cloned()
+    }
+
+    #[inline]
+    pub async fn remove_match(&self, match_id: i32) -> Option<Arc<Match>> {
+        self.matches.write().await.remove(&match_id)
+    }
+
+    /// Remove a match, the lobby members and the remaining players are told
+    /// the match is disbanded.
+    pub async fn dispose_match(
+        &self,
+        match_id: i32,
+    ) -> Option<(Arc<Match>, MatchNotifications)> {
+        let m = self.remove_match(match_id).await?;
+
+        let notifications = vec![(
+            self.match_audience(&m).await,
+            server::DisbandMatch::pack(match_id),
+        )];
+
+        Some((m, notifications))
+    }
+
+    #[inline]
+    pub async fn all_matches(&self) -> Vec<Arc<Match>> {
+        self.matches.read().await.values().cloned().collect()
+    }
+
+    /// The match a user is playing in.
+    pub async fn find_player_match(&self, user_id: i32) -> Option<Arc<Match>> {
+        for m in self.all_matches().await {
+            if m.slot_of(user_id).await.is_some() {
+                return Some(m);
+            }
+        }
+
+        None
+    }
+
+    /// Users receiving the updates of a match: the lobby members and the
+    /// players of the match, sorted by user id.
+    pub async fn match_audience(&self, m: &Match) -> Vec<i32> {
+        let mut users =
+            self.lobby.read().await.iter().copied().collect::<BTreeSet<_>>();
+        users.extend(m.players().await);
+
+        users.into_iter().collect()
+    }
+
+    async fn match_updated(&self, m: &Match) -> (Vec<i32>, Vec<u8>) {
+        let data = m.data.read().await.clone();
+
+        (self.match_audience(m).await, server::UpdateMatch::pack(data))
+    }
+
+    /// Join a match, the user leaves their previous match.
+    pub async fn join_match(
+        &self,
+        user_id: i32,
+        match_id: i32,
+        password: &str,
+    ) -> Result<MatchNotifications, MultiplayerError> {
+        let m = self
+            .get_match(match_id)
+            .await
+            .ok_or(MultiplayerError::MatchNotExists)?;
+
+        let mut notifications = match self.find_player_match(user_id).await {
+            Some(current) if current.id != match_id => {
+                self.leave_match(user_id).await
+            },
+            _ => vec![],
+        };
+
+        let data = m.add_player(user_id, password).await?;
+
+        notifications
+            .push((vec![user_id], server::MatchJoinSuccess::pack(data)));
+        notifications.push(self.match_updated(&m).await);
+
+        Ok(notifications)
+    }
+
+    /// Start the match of the host.
+    pub async fn start_match(
+        &self,
+        user_id: i32,
+    ) -> Result<MatchNotifications, MultiplayerError> {
+        let m = self
+            .find_player_match(user_id)
+            .await
+            .ok_or(MultiplayerError::NotInMatch)?;
+
+        let data = m.start(user_id).await?;
+        let playing = Match::players_with(&data, MatchSlotStatus::Playing);
+
+        Ok(vec![
+            (playing, server::MatchStart::pack(data)),
+            self.match_updated(&m).await,
+        ])
+    }
+
+    pub async fn match_load_complete(
+        &self,
+        user_id: i32,
+    ) -> Result<MatchNotifications, MultiplayerError> {
+        let m = self
+            .find_player_match(user_id)
+            .await
+            .ok_or(MultiplayerError::NotInMatch)?;
+
+        Ok(if m.load_complete(user_id).await? {
+            vec![(m.players().await, server::MatchAllPlayerLoaded::pack())]
+        } else {
+            vec![]
+        })
+    }
+
+    pub async fn match_player_complete(
+        &self,
+        user_id: i32,
+    ) -> Result<MatchNotifications, MultiplayerError> {
+        let m = self
+            .find_player_match(user_id)
+            .await
+            .ok_or(MultiplayerError::NotInMatch)?;
+
+        Ok(if m.player_complete(user_id).await? {
+            vec![
+                (m.players().await, server::MatchComplete::pack()),
+                self.match_updated(&m).await,
+            ]
+        } else {
+            vec![]
+        })
+    }
+
+    /// Relay a score frame to the other players of the sender's match,
+    /// stamped with the sender's slot. Frames are dropped if the sender has
+    /// no slot or if the game is not in progress.
+    pub async fn relay_score_frame(
+        &self,
+        user_id: i32,
+        mut frame: ScoreFrame,
+    ) -> MatchNotifications {
+        let m = match self.find_player_match(user_id).await {
+            Some(m) => m,
+            None => return vec![],
+        };
+
+        let data = m.data.read().await;
+        if !data.in_progress {
+            return vec![];
+        }
+
+        frame.id = match Match::player_slot(&data, user_id) {
+            Some(slot) => slot as u8,
+            None => return vec![],
+        };
+
+        let others = Match::players_with(&data, MatchSlotStatus::HasPlayer)
+            .into_iter()
+            .filter(|id| *id != user_id)
+            .collect();
+
+        vec![(others, server::MatchScoreUpdate::pack(frame))]
+    }
+
+    /// Remove a user from their match, an empty match is disposed.
+    pub async fn leave_match(&self, user_id: i32) -> MatchNotifications {
+        let m = match self.find_player_match(user_id).await {
+            Some(m) => m,
+            None => return vec![],
+        };
+
+        let removed = match m.remove_player(user_id).await {
+            Some(removed) => removed,
+            None => return vec![],
+        };
+
+        if removed.empty {
+            return self
+                .dispose_match(m.id)
+                .await
+                .map(|(_, notifications)| notifications)
+                .unwrap_or_default();
+        }
+
+        let mut notifications = vec![];
+        let players = m.players().await;
+
+        if removed.all_loaded {
+            notifications
+                .push((players.clone(), server::MatchAllPlayerLoaded::pack()));
+        }
+
+        if removed.completed {
+            notifications.push((players, server::MatchComplete::pack()));
+        }
+
+        if let Some(new_host) = removed.new_host {
+            notifications
+                .push((vec![new_host], server::MatchTransferHost::pack()));
+        }
+
+        notifications.push(self.match_updated(&m).await);
+
+        notifications
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn match_data(players: &[i32]) -> MatchData {
+        let mut data = MatchData {
+            slot_status: vec![MatchSlotStatus::Open.bits(); 16],
+            slot_players: vec![0; 16],
+            password: Some("secret".into()),
+            host_player_id: players[0],
+            ..Default::default()
+        };
+
+        for (slot, user_id) in players.iter().enumerate() {
+            data.slot_status[slot] = MatchSlotStatus::NotReady.bits();
+            data.slot_players[slot] = *user_id;
+        }
+
+        data
+    }
+
+    #[tokio::test]
+    async fn test_match_audience() {
+        let multiplayer = Multiplayer::new();
+
+        assert!(multiplayer.join_lobby(3).await);
+        assert!(!multiplayer.join_lobby(3).await);
+        assert!(multiplayer.join_lobby(1).await);
+
+        let m = multiplayer.create_match(match_data(&[2, 1])).await;
+        assert_eq!(m.data.read().await.match_id, m.id);
+        assert_eq!(multiplayer.match_audience(&m).await, vec![1, 2, 3]);
+
+        assert!(multiplayer.part_lobby(3).await);
+        assert!(!multiplayer.in_lobby(3).await);
+        assert_eq!(multiplayer.match_audience(&m).await, vec![1, 2]);
+
+        assert_eq!(m.lobby_data().await.password.as_deref(), Some(""));
+
+        assert!(multiplayer.remove_match(m.id).await.is_some());
+        assert!(multiplayer.all_matches().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_match_game_lifecycle() {
+        let m = Match::new(1, match_data(&[1, 2]));
+
+        assert!(matches!(
+            m.start(2).await,
+            Err(MultiplayerError::NotMatchHost)
+        ));
+        assert!(matches!(
+            m.load_complete(1).await,
+            Err(MultiplayerError::MatchNotInProgress)
+        ));
+
+        let data = m.start(1).await.unwrap();
+        assert!(data.in_progress);
+        assert_eq!(data.slot_status[0], MatchSlotStatus::Playing.bits());
+
+        assert!(!m.load_complete(1).await.unwrap());
+        assert!(m.load_complete(2).await.unwrap());
+        // notified only once
+        assert!(!m.load_complete(2).await.unwrap());
+
+        assert!(!m.player_complete(1).await.unwrap());
+        assert!(m.player_complete(2).await.unwrap());
+
+        let data = m.data.read().await;
+        assert!(!data.in_progress);
+        assert_eq!(data.slot_status[0], MatchSlotStatus::NotReady.bits());
+        assert_eq!(data.slot_status[1], MatchSlotStatus::NotReady.bits());
+    }
+
+    #[tokio::test]
+    async fn test_relay_score_frame() {
+        let multiplayer = Multiplayer::new();
+        let m = multiplayer.create_match(match_data(&[1, 2, 3])).await;
+
+        // not leaked into the lobby before the game starts
+        assert!(multiplayer
+            .relay_score_frame(2, ScoreFrame::default())
+            .await
+            .is_empty());
+
+        m.start(1).await.unwrap();
+
+        let relayed =
+            multiplayer.relay_score_frame(2, ScoreFrame::default()).await;
+        assert_eq!(relayed.len(), 1);
+        assert_eq!(relayed[0].0, vec![1, 3]);
+        assert_eq!(
+            relayed[0].1,
+            server::MatchScoreUpdate::pack(ScoreFrame {
+                id: 1,
+                ..Default::default()
+            })
+        );
+
+        // not in a slot
+        assert!(multiplayer
+            .relay_score_frame(4, ScoreFrame::default())
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_player_leaves_mid_game() {
+        let multiplayer = Multiplayer::new();
+        let m = multiplayer.create_match(match_data(&[1, 2, 3])).await;
+
+        m.start(1).await.unwrap();
+        m.load_complete(2).await.unwrap();
+        m.load_complete(3).await.unwrap();
+
+        // the host leaves before loading, the others are not waiting anymore
+        let removed = m.remove_player(1).await.unwrap();
+        assert!(removed.all_loaded);
+        assert!(!removed.completed);
+        assert_eq!(removed.new_host, Some(2));
+
+        m.player_complete(2).await.unwrap();
+        let removed = m.remove_player(3).await.unwrap();
+        assert!(removed.completed);
+        assert!(!m.data.read().await.in_progress);
+
+        // the last player leaves
+        assert!(!multiplayer.leave_match(2).await.is_empty());
+        assert!(multiplayer.get_match(m.id).await.is_none());
+        assert!(multiplayer.leave_match(2).await.is_empty());
+    }
+}
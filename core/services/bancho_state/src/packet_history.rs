@@ -0,0 +1,131 @@
+use bancho_packets::PacketId;
+use num_traits::FromPrimitive;
+use pb_bancho_state::{PacketHistoryEntry, PacketHistoryResponse};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Last packets received from a session, for debugging stuck clients.
+///
+/// Each entry packs the receive time (unix ms) and the packet id in a single
+/// atomic, so recording a packet never locks. The history is dropped with
+/// its session, an empty history (the default, e.g. of the sessions loaded
+/// from a snapshot) records nothing.
+#[derive(Debug, Default)]
+pub struct PacketHistory {
+    entries: Box<[AtomicU64]>,
+    next: AtomicUsize,
+}
+
+impl PacketHistory {
+    #[inline]
+    pub fn new(size: usize) -> Self {
+        Self {
+            entries: (0..size).map(|_| AtomicU64::new(0)).collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        !self.entries.is_empty()
+    }
+
+    #[inline]
+    pub fn record(&self, packet_id: PacketId, received_at: i64) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let index = self.next.fetch_add(1, Ordering::Relaxed);
+        self.entries[index % self.entries.len()].store(
+            (received_at as u64) << 8 | packet_id as u64,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Unix ms of the last recorded packet, `0` if none.
+    #[inline]
+    pub fn last_received_at(&self) -> i64 {
+        let index = self.next.load(Ordering::Relaxed);
+        if index == 0 || !self.is_enabled() {
+            return 0;
+        }
+
+        (self.entries[(index - 1) % self.entries.len()].load(Ordering::Relaxed)
+            >> 8) as i64
+    }
+
+    /// Recorded packets, oldest first.
+    pub fn entries(&self) -> Vec<PacketHistoryEntry> {
+        if !self.is_enabled() {
+            return Vec::new();
+        }
+
+        let (len, next) =
+            (self.entries.len(), self.next.load(Ordering::Relaxed));
+
+        (next.saturating_sub(len)..next)
+            .map(|i| self.entries[i % len].load(Ordering::Relaxed))
+            .filter(|entry| *entry != 0)
+            .map(|entry| {
+                let packet_id = (entry & 0xff) as u8;
+
+                PacketHistoryEntry {
+                    packet_id: packet_id as i32,
+                    packet_name: PacketId::from_u8(packet_id)
+                        .map(|id| id.to_string())
+                        .unwrap_or_default(),
+                    received_at: (entry >> 8) as i64,
+                }
+            })
+            .collect()
+    }
+
+    #[inline]
+    pub fn response(&self) -> PacketHistoryResponse {
+        PacketHistoryResponse { packets: self.entries() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_history_wraps() {
+        let history = PacketHistory::new(3);
+
+        for (i, packet_id) in [
+            PacketId::OSU_PING,
+            PacketId::OSU_USER_CHANGE_ACTION,
+            PacketId::OSU_SEND_PUBLIC_MESSAGE,
+            PacketId::OSU_USER_LOGOUT,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            history.record(packet_id, 1000 + i as i64);
+        }
+
+        let entries = history.entries();
+
+        assert_eq!(
+            entries.iter().map(|e| e.packet_id).collect::<Vec<_>>(),
+            vec![
+                PacketId::OSU_USER_CHANGE_ACTION as i32,
+                PacketId::OSU_SEND_PUBLIC_MESSAGE as i32,
+                PacketId::OSU_USER_LOGOUT as i32,
+            ]
+        );
+        assert_eq!(entries[2].received_at, 1003);
+        assert_eq!(history.last_received_at(), 1003);
+    }
+
+    #[test]
+    fn test_packet_history_disabled() {
+        let history = PacketHistory::default();
+        history.record(PacketId::OSU_PING, 1000);
+
+        assert!(history.entries().is_empty());
+        assert_eq!(history.last_received_at(), 0);
+    }
+}
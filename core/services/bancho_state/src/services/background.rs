@@ -1,9 +1,13 @@
 use crate::{
-    traits::*, BanchoSession, DynBanchoStateBackgroundService,
-    NotifyMessagesCleaner, UserSessionsCleaner,
+    traits::*, BanchoSession, CliBanchoStateIncrementalSnapshotConfigs,
+    DynBanchoStateBackgroundService, NotifyMessagesCleaner, SessionDeltaLog,
+    UserSessionsCleaner,
 };
 use async_trait::async_trait;
 use clap_serde_derive::ClapSerde;
+use infra_services::ServiceSnapshot;
+use pb_bancho_state::UserQuery;
+use peace_cfg::{ConfigError, ConfigErrors, ValidateConfig};
 use peace_unique_id::Ulid;
 use std::{
     sync::Arc,
@@ -23,11 +27,13 @@ use tools::{
 pub struct Tasks {
     pub user_sessions_recycle: BackgroundTaskManager,
     pub notify_messages_recycle: BackgroundTaskManager,
+    pub incremental_snapshot: BackgroundTaskManager,
 }
 
 #[derive(Clone)]
 pub struct BanchoStateBackgroundServiceImpl {
     pub user_sessions_service: DynUserSessionsService,
+    pub bancho_state_service: DynBanchoStateService,
     pub tasks: Tasks,
 }
 
@@ -36,8 +42,15 @@ impl BanchoStateBackgroundServiceImpl {
         Arc::new(self) as DynBanchoStateBackgroundService
     }
 
-    pub fn new(user_sessions_service: DynUserSessionsService) -> Self {
-        Self { user_sessions_service, tasks: Tasks::default() }
+    pub fn new(
+        user_sessions_service: DynUserSessionsService,
+        bancho_state_service: DynBanchoStateService,
+    ) -> Self {
+        Self {
+            user_sessions_service,
+            bancho_state_service,
+            tasks: Tasks::default(),
+        }
     }
 
     pub fn user_sessions_recycle_factory(
@@ -76,6 +89,7 @@ impl BanchoStateBackgroundServiceImpl {
 
                         for session in user_sessions.values() {
                             if session.is_deactive(current_timestamp, deadline)
+                                || session.packets_queue_overflowed()
                             {
                                 lazy_init!(sessions_deactive => sessions_deactive.push(session.clone()), vec![session.clone()]);
                             }
@@ -92,28 +106,29 @@ impl BanchoStateBackgroundServiceImpl {
 
                     let removed_deactive_sessions = match sessions_deactive {
                         Some(sessions_deactive) => {
-                            let user_sessions =
-                                user_sessions_service.user_sessions();
+                            let mut removed = 0;
+
+                            // remove deactive sessions through the service,
+                            // so that other users will receive `UserLogout`
+                            for session in sessions_deactive.iter() {
+                                // session may be refreshed after collected
+                                if !session
+                                    .is_deactive(Timestamp::now(), deadline)
+                                    && !session.packets_queue_overflowed()
+                                {
+                                    continue;
+                                }
 
-                            // remove deactive sessions
-                            {
-                                let mut indexes = user_sessions.write().await;
-                                for session in sessions_deactive.iter() {
-                                    user_sessions.delete_inner(
-                                        &mut indexes,
-                                        &session.user_id,
-                                        &session.username.load(),
-                                        &session.id,
-                                        session
-                                            .username_unicode
-                                            .load()
-                                            .as_deref()
-                                            .map(|s| s.as_str()),
-                                    );
+                                if user_sessions_service
+                                    .delete(&UserQuery::SessionId(session.id))
+                                    .await
+                                    .is_some()
+                                {
+                                    removed += 1;
                                 }
-                            };
+                            }
 
-                            sessions_deactive.len()
+                            removed
                         },
                         None => 0,
                     };
@@ -227,6 +242,74 @@ impl BanchoStateBackgroundServiceImpl {
             })
         }))
     }
+
+    pub fn incremental_snapshot_factory(
+        &self,
+        config: Arc<LoopBackgroundTaskConfig>,
+        session_deltas: Arc<SessionDeltaLog>,
+    ) -> BackgroundTaskFactory {
+        const LOG_TARGET: &str =
+            "bancho_state::background_tasks::incremental_snapshot";
+
+        let bancho_state_service = self.bancho_state_service.to_owned();
+
+        BackgroundTaskFactory::new(Arc::new(move |stop: SignalHandle| {
+            let bancho_state_service = bancho_state_service.to_owned();
+            let session_deltas = session_deltas.to_owned();
+            let cfg = config.to_owned();
+
+            let task = async move {
+                loop {
+                    tokio::time::sleep(*cfg.loop_interval.load().as_ref())
+                        .await;
+
+                    let written = match session_deltas.flush().await {
+                        Ok(written) => written,
+                        Err(err) => {
+                            warn!(
+                                target: LOG_TARGET,
+                                "Failed to write deltas to \"{}\", err: {err}",
+                                session_deltas.path
+                            );
+                            continue;
+                        },
+                    };
+
+                    if written < session_deltas.max_deltas {
+                        continue;
+                    }
+
+                    debug!(
+                        target: LOG_TARGET,
+                        "{written} deltas written, rolling into a new base snapshot"
+                    );
+
+                    // the delta log is rolled after the base is saved
+                    let _ = bancho_state_service
+                        .save_service_snapshot(
+                            session_deltas.base_snapshot_type,
+                            &session_deltas.base_snapshot_path,
+                        )
+                        .await;
+                }
+            };
+
+            info!(
+                target: LOG_TARGET,
+                "Service started! (sleep={:?}, max_deltas={})",
+                config.loop_interval.val(),
+                session_deltas.max_deltas
+            );
+
+            Box::pin(async move {
+                tokio::select!(
+                    _ = task => {},
+                    _ = stop.wait_signal() => {}
+                );
+                warn!(target: LOG_TARGET, "Service stopped!");
+            })
+        }))
+    }
 }
 
 #[derive(Debug, Clone, Parser, ClapSerde, Serialize, Deserialize)]
@@ -244,6 +327,31 @@ pub struct CliBanchoStateBackgroundServiceConfigs {
     pub bancho_notify_messages_recycle_interval_secs: u64,
 }
 
+impl ValidateConfig for CliBanchoStateBackgroundServiceConfigs {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        for (field, secs) in [
+            (
+                "bancho_user_sessions_recycle_deactive_secs",
+                self.bancho_user_sessions_recycle_deactive_secs,
+            ),
+            (
+                "bancho_user_sessions_recycle_interval_secs",
+                self.bancho_user_sessions_recycle_interval_secs,
+            ),
+            (
+                "bancho_notify_messages_recycle_interval_secs",
+                self.bancho_notify_messages_recycle_interval_secs,
+            ),
+        ] {
+            errors.check(secs > 0, field, "must be greater than 0");
+        }
+
+        errors.into_result()
+    }
+}
+
 pub struct UserSessionsRecycleConfig;
 
 impl UserSessionsRecycleConfig {
@@ -289,10 +397,33 @@ impl NotifyMessagesRecycleConfig {
     }
 }
 
+pub struct IncrementalSnapshotConfig;
+
+impl IncrementalSnapshotConfig {
+    #[inline]
+    pub fn build(loop_interval: u64) -> Arc<LoopBackgroundTaskConfig> {
+        LoopBackgroundTaskConfig {
+            loop_interval: Atomic::new(Duration::from_secs(loop_interval)),
+            manual_stop: true.into(),
+        }
+        .into()
+    }
+
+    #[inline]
+    pub fn buid_with_cfg(
+        cfg: &CliBanchoStateIncrementalSnapshotConfigs,
+    ) -> Option<Arc<LoopBackgroundTaskConfig>> {
+        cfg.bancho_state_incremental_snapshot.then(|| {
+            Self::build(cfg.bancho_state_incremental_snapshot_interval_secs)
+        })
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct BanchoStateBackgroundServiceConfigs {
     pub user_sessions_recycle: Arc<CommonRecycleBackgroundTaskConfig>,
     pub notify_messages_recyce: Arc<LoopBackgroundTaskConfig>,
+    pub incremental_snapshot: Option<Arc<LoopBackgroundTaskConfig>>,
 }
 
 impl BanchoStateBackgroundServiceConfigs {
@@ -300,12 +431,20 @@ impl BanchoStateBackgroundServiceConfigs {
     pub fn new(
         user_sessions_recycle: Arc<CommonRecycleBackgroundTaskConfig>,
         notify_messages_recyce: Arc<LoopBackgroundTaskConfig>,
+        incremental_snapshot: Option<Arc<LoopBackgroundTaskConfig>>,
     ) -> Self {
-        Self { user_sessions_recycle, notify_messages_recyce }
+        Self {
+            user_sessions_recycle,
+            notify_messages_recyce,
+            incremental_snapshot,
+        }
     }
 
     #[inline]
-    pub fn with_cfg(cfg: &CliBanchoStateBackgroundServiceConfigs) -> Self {
+    pub fn with_cfg(
+        cfg: &CliBanchoStateBackgroundServiceConfigs,
+        incremental_cfg: &CliBanchoStateIncrementalSnapshotConfigs,
+    ) -> Self {
         Self {
             user_sessions_recycle: UserSessionsRecycleConfig::buid_with_cfg(
                 cfg,
@@ -313,6 +452,9 @@ impl BanchoStateBackgroundServiceConfigs {
             notify_messages_recyce: NotifyMessagesRecycleConfig::buid_with_cfg(
                 cfg,
             ),
+            incremental_snapshot: IncrementalSnapshotConfig::buid_with_cfg(
+                incremental_cfg,
+            ),
         }
     }
 }
@@ -322,6 +464,10 @@ impl BanchoStateBackgroundService for BanchoStateBackgroundServiceImpl {
     fn start_all(&self, configs: BanchoStateBackgroundServiceConfigs) {
         self.start_user_sessions_recycle(configs.user_sessions_recycle);
         self.start_notify_messages_recyce(configs.notify_messages_recyce);
+
+        if let Some(config) = configs.incremental_snapshot {
+            self.start_incremental_snapshot(config);
+        }
     }
 }
 
@@ -361,3 +507,30 @@ impl NotifyMessagesCleaner for BanchoStateBackgroundServiceImpl {
         self.tasks.notify_messages_recycle.stop()
     }
 }
+
+#[async_trait]
+impl IncrementalSnapshotWriter for BanchoStateBackgroundServiceImpl {
+    fn start_incremental_snapshot(
+        &self,
+        config: Arc<LoopBackgroundTaskConfig>,
+    ) {
+        let session_deltas = match self.user_sessions_service.session_deltas() {
+            Some(session_deltas) => session_deltas.clone(),
+            None => {
+                warn!("[BanchoStateSnapshot] Incremental snapshot is not enabled on the user sessions service");
+                return;
+            },
+        };
+
+        self.tasks.incremental_snapshot.start(
+            self.incremental_snapshot_factory(config.clone(), session_deltas),
+            config,
+        );
+    }
+
+    fn stop_incremental_snapshot(
+        &self,
+    ) -> Result<Option<Arc<BackgroundTask>>, BackgroundTaskError> {
+        self.tasks.incremental_snapshot.stop()
+    }
+}
@@ -1,74 +1,234 @@
 use crate::*;
 use async_trait::async_trait;
+use bancho_packets::{server, MatchData, PacketId, PayloadReader, ScoreFrame};
 use chrono::{DateTime, Utc};
+use clap_serde_derive::ClapSerde;
 use core_signature::DynSignatureService;
 use domain_bancho::{
     BanchoClientToken, BanchoPrivileges, GameMode, Mods, PresenceFilter,
     UserOnlineStatus,
 };
+use domain_bancho_state::ConnectionInfo;
+use futures::{future, stream, StreamExt};
 use infra_packets::Packet;
 use infra_services::{IntoService, ServiceSnapshot};
-use infra_users::{CreateSessionDto, SessionFilter};
+use infra_users::{CreateSessionDto, SessionFilter, UserIndexes};
 use num_traits::FromPrimitive;
 use pb_bancho_state::*;
 use pb_base::ExecSuccess;
+use peace_cfg::{ConfigError, ConfigErrors, ValidateConfig};
 use peace_message_queue::ReceivedMessages;
 use peace_snapshot::{
-    CreateSnapshot, CreateSnapshotError, LoadSnapshotFrom, SaveSnapshotTo,
-    SnapshotConfig, SnapshotExpired, SnapshotTime, SnapshotType,
+    decode_binary, CreateSnapshot, CreateSnapshotError, LoadSnapshotFrom,
+    SaveSnapshotTo, SnapshotConfig, SnapshotExpired, SnapshotSchema,
+    SnapshotTime, SnapshotType,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    sync::Arc,
+    time::Duration,
 };
-use std::{path::Path, sync::Arc};
 use tools::atomic::AtomicValue;
 
+#[derive(Debug, Clone, Parser, ClapSerde, Serialize, Deserialize)]
+pub struct CliBanchoStateServiceConfigs {
+    /// Max number of packets that can be queued for a session.
+    ///
+    /// A session exceeding it is considered as not polling, its packets
+    /// queue will be dropped and it will be disconnected.
+    /// `0` means unlimited.
+    #[default(4096)]
+    #[arg(long, default_value = "4096")]
+    pub bancho_session_max_queued_packets: usize,
+
+    /// Do not send the coordinates of the users in their presence, only
+    /// their country is shown.
+    #[arg(long)]
+    pub bancho_hide_presence_location: bool,
+
+    /// Max number of sessions whose packets queue is pushed concurrently
+    /// when a packet is sent to many sessions.
+    #[default(64)]
+    #[arg(long, default_value = "64")]
+    pub bancho_batch_push_concurrency: usize,
+
+    /// Max time in ms a poll waits for more packets to be queued before
+    /// returning them, so bursts of small packets (e.g. status updates) are
+    /// sent in fewer responses. `0` disables it.
+    #[default(0)]
+    #[arg(long, default_value = "0")]
+    pub bancho_dequeue_coalesce_window_ms: u64,
+
+    /// Online statuses whose updates are not broadcast to the other users,
+    /// comma separated ids, e.g. `1,9` for `Afk` and `Submitting`.
+    ///
+    /// The status is still stored, users requesting the stats get it.
+    #[default(Vec::new())]
+    #[arg(long, value_delimiter = ',')]
+    pub bancho_quiet_online_statuses: Vec<u8>,
+}
+
+impl CliBanchoStateServiceConfigs {
+    /// Polls wait at most this long for more packets, longer windows would
+    /// stall the clients.
+    pub const MAX_DEQUEUE_COALESCE_WINDOW_MS: u64 = 1000;
+
+    #[inline]
+    pub fn dequeue_coalesce_window(&self) -> Duration {
+        Duration::from_millis(self.bancho_dequeue_coalesce_window_ms)
+    }
+
+    #[inline]
+    pub fn quiet_online_statuses(&self) -> Vec<UserOnlineStatus> {
+        self.bancho_quiet_online_statuses
+            .iter()
+            .filter_map(|status| UserOnlineStatus::from_u8(*status))
+            .collect()
+    }
+}
+
+impl ValidateConfig for CliBanchoStateServiceConfigs {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        errors.check(
+            self.bancho_batch_push_concurrency > 0,
+            "bancho_batch_push_concurrency",
+            "must be greater than 0",
+        );
+        errors.check(
+            self.bancho_dequeue_coalesce_window_ms
+                <= Self::MAX_DEQUEUE_COALESCE_WINDOW_MS,
+            "bancho_dequeue_coalesce_window_ms",
+            format!(
+                "must be at most {} ms",
+                Self::MAX_DEQUEUE_COALESCE_WINDOW_MS
+            ),
+        );
+
+        for status in &self.bancho_quiet_online_statuses {
+            errors.check(
+                UserOnlineStatus::from_u8(*status).is_some(),
+                "bancho_quiet_online_statuses",
+                format!("unknown online status `{status}`"),
+            );
+        }
+
+        errors.into_result()
+    }
+}
+
 pub struct BanchoStateServiceSnapshotLoader;
 
 impl BanchoStateServiceSnapshotLoader {
     pub async fn load(
         cfg: &CliBanchoStateServiceSnapshotConfigs,
+        incremental_cfg: &CliBanchoStateIncrementalSnapshotConfigs,
+        service_cfg: &CliBanchoStateServiceConfigs,
         signature_service: DynSignatureService,
     ) -> BanchoStateServiceImpl {
+        let session_deltas =
+            incremental_cfg.bancho_state_incremental_snapshot.then(|| {
+                Arc::new(SessionDeltaLog::new(
+                    CliBanchoStateIncrementalSnapshotConfigs::deltas_file_path(
+                        cfg.snapshot_path(),
+                    ),
+                    cfg.snapshot_file_path(),
+                    cfg.snapshot_type(),
+                    incremental_cfg
+                        .bancho_state_incremental_snapshot_max_deltas,
+                ))
+            });
+
         if cfg.should_load_snapshot() {
-            let snapshot_path = Path::new(cfg.snapshot_path());
-            if snapshot_path.is_file() {
+            let mut snapshot = None;
+
+            if let Some(snapshot_path) = cfg.find_snapshot_file() {
                 match BanchoStateServiceSnapshot::load_snapshot_from(
                     cfg.snapshot_type(),
-                    cfg.snapshot_path(),
+                    &snapshot_path,
                 )
                 .await
                 {
-                    Ok(snapshot) => {
-                        if !snapshot
-                            .snapshot_expired(cfg.snapshot_expired_secs())
-                        {
-                            info!(
-                                "[BanchoStateSnapshot] Load Bancho state service from snapshot files!"
-                            );
-                            return BanchoStateServiceImpl::from_snapshot(
-                                snapshot,
-                                signature_service,
-                            )
-                            .await;
-                        }
-
-                        info!("[BanchoStateSnapshot] Snapshot file founded but already expired (create at: {})", snapshot.create_time);
-                    },
+                    Ok(s) => snapshot = Some(s),
                     Err(err) => {
-                        warn!("[BanchoStateSnapshot] Failed to load snapshot file from path: \"{}\", err: {}", cfg.snapshot_path(), err);
+                        warn!("[BanchoStateSnapshot] Failed to load snapshot file from path: \"{}\", err: {}", snapshot_path, err);
                     },
                 }
             } else {
                 info!(
                     "[BanchoStateSnapshot] Snapshot file not found, path: \"{}\"",
-                    cfg.snapshot_path(),
+                    cfg.snapshot_file_path(),
                 );
             }
+
+            if let Some(session_deltas) = &session_deltas {
+                Self::replay_deltas(session_deltas, &mut snapshot).await;
+            }
+
+            if let Some(snapshot) = snapshot {
+                if !snapshot.snapshot_expired(cfg.snapshot_expired_secs()) {
+                    info!(
+                        "[BanchoStateSnapshot] Load Bancho state service from snapshot files!"
+                    );
+                    return BanchoStateServiceImpl::from_snapshot(
+                        snapshot,
+                        signature_service,
+                        service_cfg,
+                        session_deltas,
+                    )
+                    .await;
+                }
+
+                info!("[BanchoStateSnapshot] Snapshot file founded but already expired (create at: {})", snapshot.create_time);
+            }
         }
 
         BanchoStateServiceImpl::new(
-            UserSessionsServiceImpl::new().into_service(),
+            UserSessionsServiceImpl::new()
+                .with_session_deltas(session_deltas)
+                .with_hide_presence_location(
+                    service_cfg.bancho_hide_presence_location,
+                )
+                .into_service(),
             signature_service,
+            service_cfg.bancho_session_max_queued_packets,
+            service_cfg.bancho_batch_push_concurrency,
+            service_cfg.dequeue_coalesce_window(),
+            service_cfg.quiet_online_statuses(),
         )
     }
+
+    /// Replay the delta log on the base snapshot, or on an empty one if
+    /// there is no base yet.
+    async fn replay_deltas(
+        session_deltas: &SessionDeltaLog,
+        snapshot: &mut Option<BanchoStateServiceSnapshot>,
+    ) {
+        let records = match session_deltas.read_all().await {
+            Ok(records) => records,
+            Err(err) => {
+                warn!("[BanchoStateSnapshot] Failed to read delta log from path: \"{}\", err: {}", session_deltas.path, err);
+                return;
+            },
+        };
+
+        if !records.is_empty() {
+            let applied = snapshot
+                .get_or_insert_with(BanchoStateServiceSnapshot::empty)
+                .apply_deltas(records);
+
+            info!("[BanchoStateSnapshot] {applied} deltas replayed");
+        }
+
+        // drop the lines broken by a crash, so new deltas can be appended
+        if let Err(err) = session_deltas.roll(DateTime::<Utc>::MIN_UTC).await {
+            warn!(
+                "[BanchoStateSnapshot] Failed to repair delta log, err: {err}"
+            );
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -84,10 +244,120 @@ impl SnapshotTime for BanchoStateServiceSnapshot {
     }
 }
 
+impl SnapshotSchema for BanchoStateServiceSnapshot {
+    /// Version 2 added `connected_at` and `reconnect_count` to the
+    /// connection info of the sessions, version 3 added `silence_end`.
+    const VERSION: u32 = 3;
+
+    fn migrate_binary(old_version: u32, data: &[u8]) -> Result<Self, String> {
+        match old_version {
+            // Unversioned snapshots share the same data as version 1
+            0 | 1 => decode_binary::<BanchoStateServiceSnapshotV1>(data)
+                .map(Self::from),
+            2 => decode_binary::<BanchoStateServiceSnapshotV2>(data)
+                .map(Self::from),
+            _ => Err(format!("unknown snapshot version {old_version}")),
+        }
+    }
+}
+
+/// [`BanchoStateServiceSnapshot`] of the version 2 snapshots.
+#[derive(Debug, Deserialize)]
+struct BanchoStateServiceSnapshotV2 {
+    user_sessions: Vec<BanchoSessionDataV2>,
+    notify_queue: Vec<BanchoMessageData>,
+    create_time: DateTime<Utc>,
+}
+
+impl From<BanchoStateServiceSnapshotV2> for BanchoStateServiceSnapshot {
+    fn from(v2: BanchoStateServiceSnapshotV2) -> Self {
+        Self {
+            user_sessions: v2
+                .user_sessions
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            notify_queue: v2.notify_queue,
+            create_time: v2.create_time,
+        }
+    }
+}
+
+/// [`BanchoStateServiceSnapshot`] of the version 1 snapshots.
+#[derive(Debug, Deserialize)]
+struct BanchoStateServiceSnapshotV1 {
+    user_sessions: Vec<BanchoSessionDataV1>,
+    notify_queue: Vec<BanchoMessageData>,
+    create_time: DateTime<Utc>,
+}
+
+impl From<BanchoStateServiceSnapshotV1> for BanchoStateServiceSnapshot {
+    fn from(v1: BanchoStateServiceSnapshotV1) -> Self {
+        Self {
+            user_sessions: v1
+                .user_sessions
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            notify_queue: v1.notify_queue,
+            create_time: v1.create_time,
+        }
+    }
+}
+
+/// Counts of a [`BanchoStateServiceSnapshot`], to verify it before
+/// restoring.
+///
+/// The index sizes are those of the restored service, an index smaller than
+/// `sessions` means some sessions share the key and only one is kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BanchoStateSnapshotSummary {
+    pub create_time: DateTime<Utc>,
+    pub sessions: usize,
+    pub queued_packets: usize,
+    pub notify_queue: usize,
+    pub session_id_index: usize,
+    pub user_id_index: usize,
+    pub username_index: usize,
+    pub username_unicode_index: usize,
+    pub ip_index: usize,
+}
+
+impl BanchoStateServiceSnapshot {
+    pub fn summary(&self) -> BanchoStateSnapshotSummary {
+        let mut session_indexes =
+            SessionIndexes::with_capacity(self.user_sessions.len());
+
+        for u in self.user_sessions.iter().cloned() {
+            session_indexes.add_session(Arc::new(u.into()));
+        }
+
+        BanchoStateSnapshotSummary {
+            create_time: self.create_time,
+            sessions: self.user_sessions.len(),
+            queued_packets: self
+                .user_sessions
+                .iter()
+                .map(|u| u.extends.packets_queue.len())
+                .sum(),
+            notify_queue: self.notify_queue.len(),
+            session_id_index: session_indexes.session_id.len(),
+            user_id_index: session_indexes.user_id.len(),
+            username_index: session_indexes.username.len(),
+            username_unicode_index: session_indexes.username_unicode.len(),
+            ip_index: session_indexes.ip.len(),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct BanchoStateServiceImpl {
     pub user_sessions_service: DynUserSessionsService,
     pub signature_service: DynSignatureService,
+    pub max_queued_packets: usize,
+    pub batch_push_concurrency: usize,
+    pub dequeue_coalesce_window: Duration,
+    pub quiet_online_statuses: Vec<UserOnlineStatus>,
 }
 
 impl BanchoStateServiceImpl {
@@ -95,14 +365,27 @@ impl BanchoStateServiceImpl {
     pub fn new(
         user_sessions_service: DynUserSessionsService,
         signature_service: DynSignatureService,
+        max_queued_packets: usize,
+        batch_push_concurrency: usize,
+        dequeue_coalesce_window: Duration,
+        quiet_online_statuses: Vec<UserOnlineStatus>,
     ) -> Self {
-        Self { user_sessions_service, signature_service }
+        Self {
+            user_sessions_service,
+            signature_service,
+            max_queued_packets,
+            batch_push_concurrency,
+            dequeue_coalesce_window,
+            quiet_online_statuses,
+        }
     }
 
     #[inline]
     pub async fn from_snapshot(
         snapshot: BanchoStateServiceSnapshot,
         signature_service: DynSignatureService,
+        cfg: &CliBanchoStateServiceConfigs,
+        session_deltas: Option<Arc<SessionDeltaLog>>,
     ) -> Self {
         let mut session_indexes =
             SessionIndexes::with_capacity(snapshot.user_sessions.len());
@@ -118,11 +401,158 @@ impl BanchoStateServiceImpl {
         let notify_queue =
             Arc::new(BanchoMessageQueue::from(snapshot.notify_queue));
 
-        let user_sessions_service =
-            UserSessionsServiceImpl { user_sessions, notify_queue }
-                .into_service();
+        let user_sessions_service = UserSessionsServiceImpl {
+            user_sessions,
+            notify_queue,
+            ..Default::default()
+        }
+        .with_session_deltas(session_deltas)
+        .with_hide_presence_location(cfg.bancho_hide_presence_location)
+        .into_service();
+
+        Self::new(
+            user_sessions_service,
+            signature_service,
+            cfg.bancho_session_max_queued_packets,
+            cfg.bancho_batch_push_concurrency,
+            cfg.dequeue_coalesce_window(),
+            cfg.quiet_online_statuses(),
+        )
+    }
+
+    /// Push the packet to the sessions, at most `batch_push_concurrency`
+    /// at a time, then disconnect the sessions whose queue overflowed.
+    ///
+    /// Each session is pushed once, so its packets stay in order.
+    async fn push_to_sessions(
+        &self,
+        sessions: Vec<Arc<BanchoSession>>,
+        packet: Packet,
+    ) {
+        let overflowed = push_concurrently(
+            sessions,
+            packet,
+            self.max_queued_packets,
+            self.batch_push_concurrency,
+        )
+        .await;
+
+        for session in overflowed {
+            self.disconnect_overflowed(&session).await;
+        }
+    }
+
+    /// Disconnect a session whose packets queue has overflowed.
+    #[inline]
+    async fn disconnect_overflowed(&self, session: &BanchoSession) {
+        const LOG_TARGET: &str = "bancho_state::packets_queue";
+
+        warn!(
+            target: LOG_TARGET,
+            "Packets queue overflowed (max={}), disconnecting: {} [{}] ({})",
+            self.max_queued_packets,
+            session.username.load(),
+            session.user_id,
+            session.id
+        );
+
+        self.user_sessions_service
+            .delete(&UserQuery::SessionId(session.id))
+            .await;
+    }
+
+    #[inline]
+    pub fn multiplayer(&self) -> &Arc<Multiplayer> {
+        self.user_sessions_service.multiplayer()
+    }
+
+    #[inline]
+    pub fn spectating(&self) -> &Arc<Spectating> {
+        self.user_sessions_service.spectating()
+    }
+
+    pub async fn send_match_notifications(
+        &self,
+        notifications: MatchNotifications,
+    ) -> Result<(), BanchoStateError> {
+        for (user_ids, packets) in notifications {
+            if user_ids.is_empty() {
+                continue;
+            }
+
+            self.batch_enqueue_bancho_packets(
+                BatchEnqueueBanchoPacketsRequest {
+                    user_queries: user_ids
+                        .into_iter()
+                        .map(|user_id| UserQuery::UserId(user_id).into())
+                        .collect(),
+                    packets,
+                },
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Send packets to the lobby members and the players of a match.
+    pub async fn broadcast_match_packets(
+        &self,
+        m: &Match,
+        packets: Vec<u8>,
+    ) -> Result<(), BanchoStateError> {
+        let audience = self.multiplayer().match_audience(m).await;
+
+        self.send_match_notifications(vec![(audience, packets)]).await
+    }
+
+    /// Create a match hosted by a user, the creator is sent the match join
+    /// success packet and the lobby members see the new match.
+    pub async fn host_match(
+        &self,
+        user_id: i32,
+        data: MatchData,
+    ) -> Result<Arc<Match>, BanchoStateError> {
+        let (m, mut notifications) =
+            self.multiplayer().host_match(user_id, data).await;
+
+        notifications.push((
+            self.multiplayer().match_audience(&m).await,
+            server::NewMatch::pack(m.lobby_data().await),
+        ));
+        notifications.push((
+            vec![user_id],
+            server::MatchJoinSuccess::pack(m.data.read().await.clone()),
+        ));
+
+        self.send_match_notifications(notifications).await?;
+
+        Ok(m)
+    }
+
+    pub async fn update_match(
+        &self,
+        m: &Match,
+    ) -> Result<(), BanchoStateError> {
+        let data = m.data.read().await.clone();
+
+        self.broadcast_match_packets(m, server::UpdateMatch::pack(data)).await
+    }
 
-        Self { user_sessions_service, signature_service }
+    pub async fn dispose_match(
+        &self,
+        match_id: i32,
+    ) -> Result<Option<Arc<Match>>, BanchoStateError> {
+        let (m, notifications) =
+            match self.multiplayer().dispose_match(match_id).await {
+                Some(disposed) => disposed,
+                None => return Ok(None),
+            };
+
+        // the players are still in the slots, so they are notified too
+        self.send_match_notifications(notifications).await?;
+
+        Ok(Some(m))
     }
 }
 
@@ -144,6 +574,7 @@ impl ServiceSnapshot for BanchoStateServiceImpl {
             "Saving Bancho state snapshot file to path: \"{}\"...",
             snapshot_path
         );
+        let base_time = Utc::now();
         let size = self
             .save_snapshot_to(snapshot_type, snapshot_path)
             .await
@@ -155,6 +586,15 @@ impl ServiceSnapshot for BanchoStateServiceImpl {
             })?;
         info!("[Success] Bancho state snapshot saved, size: {}", size);
 
+        // deltas before the new base are no longer needed
+        if let Some(session_deltas) =
+            self.user_sessions_service.session_deltas()
+        {
+            if let Err(err) = session_deltas.roll(base_time).await {
+                warn!("[Failed] Failed to roll Bancho state delta log, err: {err}");
+            }
+        }
+
         Ok(())
     }
 }
@@ -162,6 +602,9 @@ impl ServiceSnapshot for BanchoStateServiceImpl {
 #[async_trait]
 impl CreateSnapshot<BanchoStateServiceSnapshot> for BanchoStateServiceImpl {
     async fn create_snapshot(&self) -> BanchoStateServiceSnapshot {
+        // taken before capturing, deltas recorded meanwhile will be replayed
+        let create_time = Utc::now();
+
         BanchoStateServiceSnapshot {
             user_sessions: self
                 .user_sessions_service
@@ -173,7 +616,7 @@ impl CreateSnapshot<BanchoStateServiceSnapshot> for BanchoStateServiceImpl {
                 .notify_queue()
                 .create_snapshot()
                 .await,
-            create_time: Utc::now(),
+            create_time,
         }
     }
 }
@@ -208,8 +651,18 @@ impl UpdateUserBanchoStatus for BanchoStateServiceImpl {
 
         let online_status =
             UserOnlineStatus::from_i32(online_status).unwrap_or_default();
-        let mods = Mods::from(mods);
         let mode = GameMode::from_i32(mode).unwrap_or_default();
+        let mut mods = Mods::from(mods);
+
+        let stripped = mods.sanitize(mode);
+        if !stripped.is_none() {
+            warn!(
+                "Stripped illegal mods {} of user {}({})",
+                stripped.bits(),
+                session.username.load(),
+                session.user_id
+            );
+        }
 
         session.extends.bancho_status.update_all(
             online_status,
@@ -220,8 +673,22 @@ impl UpdateUserBanchoStatus for BanchoStateServiceImpl {
             mode,
         );
 
+        if let Some(deltas) = self.user_sessions_service.session_deltas() {
+            deltas
+                .push(SessionDelta::UpdateStatus {
+                    session_id: session.id,
+                    bancho_status: session.extends.bancho_status.clone(),
+                })
+                .await;
+        }
+
         // todo update stats from database
 
+        // Transient statuses are only sent to the users requesting them
+        if self.quiet_online_statuses.contains(&online_status) {
+            return Ok(ExecSuccess::default());
+        }
+
         self.broadcast_bancho_packets(BroadcastBanchoPacketsRequest {
             packets: session.user_stats_packet(),
         })
@@ -257,6 +724,57 @@ impl UpdatePresenceFilter for BanchoStateServiceImpl {
     }
 }
 
+#[async_trait]
+impl UpdateSilenceEnd for BanchoStateServiceImpl {
+    async fn update_silence_end(
+        &self,
+        request: UpdateSilenceEndRequest,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        let query = request
+            .user_query
+            .ok_or(BanchoStateError::InvalidArgument)?
+            .into_user_query()?;
+
+        let session = self
+            .user_sessions_service
+            .get(&query)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        session.extends.silence_end.set(request.silence_end);
+
+        if let Some(deltas) = self.user_sessions_service.session_deltas() {
+            deltas
+                .push(SessionDelta::UpdateSilenceEnd {
+                    session_id: session.id,
+                    silence_end: request.silence_end,
+                })
+                .await;
+        }
+
+        let remaining = BanchoExtend::silence_remaining(
+            request.silence_end,
+            Utc::now().timestamp(),
+        );
+
+        self.enqueue_bancho_packets(EnqueueBanchoPacketsRequest {
+            user_query: Some(query.into()),
+            packets: server::SilenceEnd::pack(remaining),
+        })
+        .await?;
+
+        // Clients clear the messages of the silenced user
+        if remaining > 0 {
+            self.broadcast_bancho_packets(BroadcastBanchoPacketsRequest {
+                packets: server::UserSilenced::pack(session.user_id),
+            })
+            .await?;
+        }
+
+        Ok(ExecSuccess::default())
+    }
+}
+
 #[async_trait]
 impl BatchSendPresences for BanchoStateServiceImpl {
     async fn batch_send_presences(
@@ -301,7 +819,9 @@ impl BatchSendPresences for BanchoStateServiceImpl {
                     continue;
                 };
 
-                presences_packets.extend(session.user_presence_packet());
+                presences_packets.extend(session.presence_only_packet(
+                    self.user_sessions_service.hide_presence_location(),
+                ));
             }
 
             presences_packets
@@ -339,7 +859,9 @@ impl SendAllPresences for BanchoStateServiceImpl {
                     continue;
                 };
 
-                presences_packets.extend(session.user_presence_packet());
+                presences_packets.extend(session.presence_only_packet(
+                    self.user_sessions_service.hide_presence_location(),
+                ));
             }
 
             presences_packets
@@ -355,6 +877,67 @@ impl SendAllPresences for BanchoStateServiceImpl {
     }
 }
 
+/// Push the packet to each session, at most `concurrency` at a time,
+/// returns the sessions whose packets queue overflowed.
+async fn push_concurrently(
+    sessions: Vec<Arc<BanchoSession>>,
+    packet: Packet,
+    max_queued_packets: usize,
+    concurrency: usize,
+) -> Vec<Arc<BanchoSession>> {
+    stream::iter(sessions)
+        .map(|session| {
+            let packet = packet.clone();
+            async move {
+                session.push_packet(packet, max_queued_packets).await;
+                session
+            }
+        })
+        .buffer_unordered(concurrency.max(1))
+        .filter(|session| future::ready(session.packets_queue_overflowed()))
+        .collect()
+        .await
+}
+
+/// Give a burst of packets `window` to be queued, so they are dequeued
+/// together. Only waits if packets are already queued, and never longer than
+/// `window` even if packets keep arriving.
+async fn coalesce_packets(session: &BanchoSession, window: Duration) {
+    if window.is_zero()
+        || session.extends.packets_queue.queued_packets().await == 0
+    {
+        return;
+    }
+
+    tokio::time::sleep(window).await
+}
+
+/// Sessions to send the stats of to `to`, in the order of the queries.
+///
+/// A session queried several times (e.g. by both id and name) is only
+/// returned once. The requester itself and the sessions hiding their
+/// presence from the requester are skipped.
+fn user_stats_targets(
+    indexes: &UserIndexes<BanchoSession>,
+    user_queries: &[UserQuery],
+    to: &UserQuery,
+) -> Vec<Arc<BanchoSession>> {
+    let requester = match UserSessions::get_inner(indexes, to) {
+        Some(requester) => requester,
+        None => return Vec::new(),
+    };
+
+    let mut seen = HashSet::new();
+
+    user_queries
+        .iter()
+        .filter_map(|query| UserSessions::get_inner(indexes, query))
+        .filter(|session| !SessionFilter::session_is_target(session, to))
+        .filter(|session| session.presence_visible_to(requester.user_id))
+        .filter(|session| seen.insert(session.id))
+        .collect()
+}
+
 #[async_trait]
 impl BatchSendUserStatsPacket for BanchoStateServiceImpl {
     async fn batch_send_user_stats_packet(
@@ -369,36 +952,19 @@ impl BatchSendUserStatsPacket for BanchoStateServiceImpl {
             .ok_or(BanchoStateError::InvalidArgument)?
             .into_user_query()?;
 
+        let user_queries = request
+            .user_queries
+            .into_iter()
+            .map(|raw_query| raw_query.into_user_query())
+            .collect::<Result<Vec<_>, _>>()?;
+
         let user_stats_packets = {
             let mut user_stats_packets = Vec::new();
 
             let indexes =
                 self.user_sessions_service.user_sessions().read().await;
 
-            for raw_query in request.user_queries {
-                let query = raw_query.into_user_query()?;
-                let session = match &query {
-                    UserQuery::UserId(user_id) => indexes.user_id.get(user_id),
-                    UserQuery::Username(username) => {
-                        indexes.username.get(username)
-                    },
-                    UserQuery::UsernameUnicode(username_unicode) => {
-                        indexes.username_unicode.get(username_unicode)
-                    },
-                    UserQuery::SessionId(session_id) => {
-                        indexes.session_id.get(session_id)
-                    },
-                };
-
-                let session = match session {
-                    Some(s) => s,
-                    None => continue,
-                };
-
-                if SessionFilter::session_is_target(session, &to) {
-                    continue;
-                };
-
+            for session in user_stats_targets(&indexes, &user_queries, &to) {
                 user_stats_packets.extend(session.user_stats_packet());
             }
 
@@ -484,100 +1050,332 @@ impl GetAllSessions for BanchoStateServiceImpl {
 }
 
 #[async_trait]
-impl GetUserSessionWithFields for BanchoStateServiceImpl {
-    async fn get_user_session_with_fields(
+impl GetSessionsPage for BanchoStateServiceImpl {
+    async fn get_sessions_page(
         &self,
-        request: RawUserQueryWithFields,
-    ) -> Result<GetUserSessionResponse, BanchoStateError> {
-        // Extract the query and fields from the request
-        let query =
-            request.user_query.ok_or(BanchoStateError::InvalidArgument)?;
+        request: GetSessionsPageRequest,
+    ) -> Result<GetSessionsPageResponse, BanchoStateError> {
+        const LOG_TARGET: &str = "bancho_state::get_sessions_page";
+        const MAX_LIMIT: usize = 1000;
+
+        let offset = request.offset as usize;
+        let limit = (request.limit as usize).min(MAX_LIMIT);
+
+        let indexes = self.user_sessions_service.user_sessions().read().await;
+
+        let (total, page) =
+            sessions_page(&indexes, request.index(), offset, limit);
+
+        let sessions = page
+            .into_iter()
+            .filter_map(|session| match serde_json::to_string(session) {
+                Ok(json) => Some(UserData { json }),
+                Err(err) => {
+                    warn!(
+                        target: LOG_TARGET,
+                        "Failed to serialize session ({}), skipped: {err}",
+                        session.id
+                    );
+                    None
+                },
+            })
+            .collect();
 
-        // Get session based on the provided query
-        let session = self
-            .user_sessions_service
-            .get(&query.into_user_query()?)
-            .await
-            .ok_or(BanchoStateError::SessionNotExists)?;
+        Ok(GetSessionsPageResponse { total: total as u64, sessions })
+    }
+}
 
-        // Initialize the response and extract the requested fields
-        let mut res = GetUserSessionResponse::default();
-        let fields = UserSessionFields::from(request.fields);
+#[async_trait]
+impl GetOnlineSummary for BanchoStateServiceImpl {
+    async fn get_online_summary(
+        &self,
+        request: GetOnlineSummaryRequest,
+    ) -> Result<OnlineSummary, BanchoStateError> {
+        const MAX_LIMIT: usize = 1000;
+
+        let offset = request.offset as usize;
+        let limit = (request.limit as usize).min(MAX_LIMIT);
+
+        let indexes = self.user_sessions_service.user_sessions().read().await;
+
+        let users = indexes
+            .user_id
+            .values()
+            .skip(offset)
+            .take(limit)
+            .map(|session| {
+                let status = &session.extends.bancho_status;
+
+                OnlineUser {
+                    user_id: session.user_id,
+                    username: session.username.load().to_string(),
+                    mode: status.mode.load().val() as i32,
+                    online_status: status.online_status.load().val() as i32,
+                    mods: status.mods.load().bits(),
+                    beatmap_id: status.beatmap_id.val(),
+                }
+            })
+            .collect();
 
-        // Set the response fields based on the requested fields
-        if fields.intersects(UserSessionFields::SessionId) {
-            res.session_id = Some(session.id.to_string());
-        }
+        Ok(OnlineSummary { total: indexes.user_id.len() as u64, users })
+    }
+}
 
-        if fields.intersects(UserSessionFields::UserId) {
-            res.user_id = Some(session.user_id);
-        }
+#[async_trait]
+impl GetSessionsStats for BanchoStateServiceImpl {
+    async fn get_sessions_stats(
+        &self,
+    ) -> Result<SessionsStats, BanchoStateError> {
+        let user_sessions = self.user_sessions_service.user_sessions();
 
-        if fields.intersects(UserSessionFields::Username) {
-            res.username = Some(session.username.to_string());
-        }
+        // Snapshot the sessions first, so the read lock is not held
+        // while awaiting each packets queue lock
+        let (mut stats, sessions) = {
+            let indexes = user_sessions.read().await;
+
+            (
+                SessionsStats {
+                    len: user_sessions.length() as u64,
+                    indexed_by_session_id: indexes.session_id.len() as u64,
+                    indexed_by_user_id: indexes.user_id.len() as u64,
+                    indexed_by_username: indexes.username.len() as u64,
+                    indexed_by_username_unicode: indexes.username_unicode.len()
+                        as u64,
+                    queued_packets: 0,
+                },
+                indexes.session_id.values().cloned().collect::<Vec<_>>(),
+            )
+        };
 
-        if fields.intersects(UserSessionFields::UsernameUnicode) {
-            res.username_unicode =
-                session.username_unicode.load().as_ref().map(|s| s.to_string());
+        for session in sessions {
+            stats.queued_packets +=
+                session.extends.packets_queue.queued_packets().await as u64;
         }
 
-        // Return the response
-        Ok(res)
+        Ok(stats)
     }
 }
 
 #[async_trait]
-impl GetUserSession for BanchoStateServiceImpl {
-    async fn get_user_session(
+impl GetUserSessionWithFields for BanchoStateServiceImpl {
+    async fn get_user_session_with_fields(
         &self,
-        query: UserQuery,
+        request: RawUserQueryWithFields,
     ) -> Result<GetUserSessionResponse, BanchoStateError> {
+        // Extract the query and fields from the request
+        let query =
+            request.user_query.ok_or(BanchoStateError::InvalidArgument)?;
+
         // Get session based on the provided query
         let session = self
             .user_sessions_service
-            .get(&query)
+            .get(&query.into_user_query()?)
             .await
             .ok_or(BanchoStateError::SessionNotExists)?;
 
-        // Create a response with the user session details
-        Ok(GetUserSessionResponse {
-            // Copy the session ID into the response
-            session_id: Some(session.id.to_string()),
-            // Copy the user ID into the response
-            user_id: Some(session.user_id),
-            // Copy the username into the response
-            username: Some(session.username.to_string()),
-            // Copy the Unicode username into the response, if it exists
-            username_unicode: session
-                .username_unicode
-                .load()
-                .as_ref()
-                .map(|s| s.to_string()),
-        })
+        Ok(user_session_response(
+            &session,
+            UserSessionFields::from(request.fields),
+        ))
     }
 }
 
-#[async_trait]
-impl IsUserOnline for BanchoStateServiceImpl {
-    async fn is_user_online(
-        &self,
-        query: UserQuery,
-    ) -> Result<UserOnlineResponse, BanchoStateError> {
-        let session = self
-            .user_sessions_service
-            .get(&query)
-            .await
-            .ok_or(BanchoStateError::SessionNotExists)?;
+/// Response with only the requested `fields` of the session set.
+fn user_session_response(
+    session: &BanchoSession,
+    fields: UserSessionFields,
+) -> GetUserSessionResponse {
+    let mut res = GetUserSessionResponse::default();
 
-        Ok(UserOnlineResponse {
-            user_id: session.user_id,
-            session_id: session.id.to_string(),
-        })
+    if fields.intersects(UserSessionFields::SessionId) {
+        res.session_id = Some(session.id.to_string());
     }
-}
 
-#[async_trait]
+    if fields.intersects(UserSessionFields::UserId) {
+        res.user_id = Some(session.user_id);
+    }
+
+    if fields.intersects(UserSessionFields::Username) {
+        res.username = Some(session.username.to_string());
+    }
+
+    if fields.intersects(UserSessionFields::UsernameUnicode) {
+        res.username_unicode =
+            session.username_unicode.load().as_ref().map(|s| s.to_string());
+    }
+
+    if fields.intersects(UserSessionFields::BanchoPrivileges) {
+        res.bancho_privileges =
+            Some(session.extends.bancho_privileges.load().bits());
+    }
+
+    if fields.intersects(UserSessionFields::SilenceEnd) {
+        res.silence_end = Some(session.extends.silence_end.val());
+    }
+
+    if fields.intersects(UserSessionFields::LastActive) {
+        res.last_active = Some(session.last_active());
+    }
+
+    if fields.intersects(UserSessionFields::ConnectedAt) {
+        res.connected_at = Some(session.extends.connection_info.connected_at);
+    }
+
+    if fields.intersects(UserSessionFields::ReconnectCount) {
+        res.reconnect_count =
+            Some(session.extends.connection_info.reconnect_count);
+    }
+
+    res
+}
+
+/// Requested `fields` of the sessions, in the order of the queries. A
+/// missing session is returned with all of its fields empty, so that the
+/// responses still line up with the queries.
+fn user_sessions_with_fields(
+    indexes: &UserIndexes<BanchoSession>,
+    user_queries: &[UserQuery],
+    fields: UserSessionFields,
+) -> Vec<GetUserSessionResponse> {
+    user_queries
+        .iter()
+        .map(|query| match UserSessions::get_inner(indexes, query) {
+            Some(session) => user_session_response(&session, fields),
+            None => GetUserSessionResponse::default(),
+        })
+        .collect()
+}
+
+/// A page of the sessions of `index`, with the total number of sessions in
+/// it. The username indexes are hash maps, they are paged in the order of
+/// the usernames so that the pages don't overlap or skip sessions.
+fn sessions_page(
+    indexes: &UserIndexes<BanchoSession>,
+    index: get_sessions_page_request::SessionIndex,
+    offset: usize,
+    limit: usize,
+) -> (usize, Vec<&Arc<BanchoSession>>) {
+    use get_sessions_page_request::SessionIndex;
+
+    #[inline]
+    fn sorted_page<'a>(
+        values: &'a HashMap<String, Arc<BanchoSession>>,
+        offset: usize,
+        limit: usize,
+    ) -> Vec<&'a Arc<BanchoSession>> {
+        let mut entries = values.iter().collect::<Vec<_>>();
+        entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+        entries
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|(_, session)| session)
+            .collect()
+    }
+
+    match index {
+        SessionIndex::SessionId => (
+            indexes.session_id.len(),
+            indexes.session_id.values().skip(offset).take(limit).collect(),
+        ),
+        SessionIndex::UserId => (
+            indexes.user_id.len(),
+            indexes.user_id.values().skip(offset).take(limit).collect(),
+        ),
+        SessionIndex::Username => (
+            indexes.username.len(),
+            sorted_page(&indexes.username, offset, limit),
+        ),
+        SessionIndex::UsernameUnicode => (
+            indexes.username_unicode.len(),
+            sorted_page(&indexes.username_unicode, offset, limit),
+        ),
+    }
+}
+
+#[async_trait]
+impl GetUserSessionsWithFields for BanchoStateServiceImpl {
+    async fn get_user_sessions_with_fields(
+        &self,
+        request: RawUserQueriesWithFields,
+    ) -> Result<GetUserSessionsResponse, BanchoStateError> {
+        let user_queries = request
+            .user_queries
+            .into_iter()
+            .map(|raw_query| raw_query.into_user_query())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let sessions = user_sessions_with_fields(
+            &*self.user_sessions_service.user_sessions().read().await,
+            &user_queries,
+            UserSessionFields::from(request.fields),
+        );
+
+        Ok(GetUserSessionsResponse { sessions })
+    }
+}
+
+#[async_trait]
+impl GetUserSession for BanchoStateServiceImpl {
+    async fn get_user_session(
+        &self,
+        query: UserQuery,
+    ) -> Result<GetUserSessionResponse, BanchoStateError> {
+        // Get session based on the provided query
+        let session = self
+            .user_sessions_service
+            .get(&query)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        // Create a response with all of the user session details
+        Ok(user_session_response(&session, UserSessionFields::all()))
+    }
+}
+
+#[async_trait]
+impl GetUserSessionPresence for BanchoStateServiceImpl {
+    async fn get_user_session_presence(
+        &self,
+        query: UserQuery,
+    ) -> Result<GetUserSessionPresenceResponse, BanchoStateError> {
+        let session = self
+            .user_sessions_service
+            .get(&query)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        Ok(GetUserSessionPresenceResponse {
+            user_id: session.user_id,
+            presence: session.presence_only_packet(
+                self.user_sessions_service.hide_presence_location(),
+            ),
+            stats: session.user_stats_packet(),
+        })
+    }
+}
+
+#[async_trait]
+impl IsUserOnline for BanchoStateServiceImpl {
+    async fn is_user_online(
+        &self,
+        query: UserQuery,
+    ) -> Result<UserOnlineResponse, BanchoStateError> {
+        let session = self
+            .user_sessions_service
+            .get(&query)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        Ok(UserOnlineResponse {
+            user_id: session.user_id,
+            session_id: session.id.to_string(),
+        })
+    }
+}
+
+#[async_trait]
 impl CheckUserToken for BanchoStateServiceImpl {
     async fn check_user_token(
         &self,
@@ -614,6 +1412,34 @@ impl DeleteUserSession for BanchoStateServiceImpl {
     }
 }
 
+#[async_trait]
+impl KickUsersByIp for BanchoStateServiceImpl {
+    async fn kick_users_by_ip(
+        &self,
+        request: KickUsersByIpRequest,
+    ) -> Result<KickUsersByIpResponse, BanchoStateError> {
+        let ip = request
+            .ip
+            .parse::<IpAddr>()
+            .map_err(|_| BanchoStateError::InvalidArgument)?;
+
+        let mut user_ids = Vec::new();
+
+        for session in self.user_sessions_service.get_by_ip(&ip).await {
+            if self
+                .user_sessions_service
+                .delete(&UserQuery::SessionId(session.id))
+                .await
+                .is_some()
+            {
+                user_ids.push(session.user_id);
+            }
+        }
+
+        Ok(KickUsersByIpResponse { user_ids })
+    }
+}
+
 #[async_trait]
 impl CreateUserSession for BanchoStateServiceImpl {
     async fn create_user_session(
@@ -632,12 +1458,29 @@ impl CreateUserSession for BanchoStateServiceImpl {
             bancho_privileges,
             connection_info,
             country_code,
+            silence_end,
+            packet_history_size,
         } = request;
 
-        let connection_info = connection_info
+        if self.user_sessions_service.is_logins_closed() {
+            return Err(CreateSessionError::LoginsClosed.into());
+        }
+
+        let mut connection_info: ConnectionInfo = connection_info
             .ok_or(CreateSessionError::InvalidConnectionInfo)?
             .into();
 
+        // The previous session is replaced below, it is a reconnect
+        connection_info.connected_at = Utc::now().timestamp();
+        connection_info.reconnect_count = self
+            .user_sessions_service
+            .get(&UserQuery::UserId(user_id))
+            .await
+            .map(|prev| prev.extends.connection_info.reconnect_count + 1)
+            .unwrap_or_default();
+
+        let utc_offset = BanchoExtend::validate_utc_offset(utc_offset)?;
+
         // Create a new user session using the provided request.
         let session = self
             .user_sessions_service
@@ -649,12 +1492,14 @@ impl CreateUserSession for BanchoStateServiceImpl {
                 extends: BanchoExtend::new(
                     None,
                     client_version,
-                    utc_offset as u8,
+                    utc_offset,
                     display_city,
                     only_friend_pm_allowed,
                     BanchoPrivileges::from(bancho_privileges),
                     connection_info,
                     country_code as u8,
+                    silence_end,
+                    packet_history_size as usize,
                 ),
             })
             .await;
@@ -678,8 +1523,9 @@ impl DequeueBanchoPackets for BanchoStateServiceImpl {
         &self,
         request: DequeueBanchoPacketsRequest,
     ) -> Result<BanchoPackets, BanchoStateError> {
-        let user_query = request
-            .user_query
+        let DequeueBanchoPacketsRequest { user_query, coalesce, wait_ms } =
+            request;
+        let user_query = user_query
             .ok_or(BanchoStateError::InvalidArgument)?
             .into_user_query()?;
 
@@ -691,6 +1537,33 @@ impl DequeueBanchoPackets for BanchoStateServiceImpl {
             .await
             .ok_or(BanchoStateError::SessionNotExists)?;
 
+        if session.packets_queue_overflowed() {
+            self.disconnect_overflowed(&session).await;
+            return Err(BanchoStateError::SessionNotExists);
+        }
+
+        if wait_ms > 0
+            && session.extends.packets_queue.queued_packets().await == 0
+        {
+            let notify_pushed =
+                self.user_sessions_service.notify_pushed().notified();
+
+            let _ = tokio::time::timeout(
+                Duration::from_millis(wait_ms.into()),
+                async {
+                    tokio::select! {
+                        _ = session.packets_pushed() => {},
+                        _ = notify_pushed => {},
+                    }
+                },
+            )
+            .await;
+        }
+
+        if coalesce {
+            coalesce_packets(&session, self.dequeue_coalesce_window).await;
+        }
+
         data.extend(
             session.extends.packets_queue.dequeue_all_packets(None).await,
         );
@@ -708,7 +1581,7 @@ impl DequeueBanchoPackets for BanchoStateServiceImpl {
             .await
         {
             for packet in messages {
-                data.extend(packet);
+                data.extend_from_slice(&packet);
             }
 
             session.extends.notify_index.set(last_msg_id.into());
@@ -718,6 +1591,53 @@ impl DequeueBanchoPackets for BanchoStateServiceImpl {
     }
 }
 
+#[async_trait]
+impl RecordPackets for BanchoStateServiceImpl {
+    async fn record_packets(
+        &self,
+        request: RecordPacketsRequest,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        let RecordPacketsRequest { user_query, packet_ids } = request;
+        let user_query = user_query
+            .ok_or(BanchoStateError::InvalidArgument)?
+            .into_user_query()?;
+
+        let session = self
+            .user_sessions_service
+            .get(&user_query)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        let history = &session.extends.packet_history;
+        if history.is_enabled() {
+            let now = Utc::now().timestamp_millis();
+            for packet_id in
+                packet_ids.into_iter().filter_map(PacketId::from_u8)
+            {
+                history.record(packet_id, now);
+            }
+        }
+
+        Ok(ExecSuccess::default())
+    }
+}
+
+#[async_trait]
+impl GetPacketHistory for BanchoStateServiceImpl {
+    async fn get_packet_history(
+        &self,
+        query: UserQuery,
+    ) -> Result<PacketHistoryResponse, BanchoStateError> {
+        let session = self
+            .user_sessions_service
+            .get(&query)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        Ok(session.extends.packet_history.response())
+    }
+}
+
 #[async_trait]
 impl BatchEnqueueBanchoPackets for BanchoStateServiceImpl {
     async fn batch_enqueue_bancho_packets(
@@ -728,21 +1648,26 @@ impl BatchEnqueueBanchoPackets for BanchoStateServiceImpl {
             request;
         let packets = Packet::new_ptr(packets);
 
-        let user_sessions =
-            self.user_sessions_service.user_sessions().read().await;
+        let user_queries = user_queries
+            .into_iter()
+            .map(|raw_query| raw_query.into_user_query())
+            .collect::<Result<Vec<_>, _>>()?;
 
-        for user_query in user_queries {
-            if let Some(session) = UserSessions::get_inner(
-                &user_sessions,
-                &user_query.into_user_query()?,
-            ) {
-                session
-                    .extends
-                    .packets_queue
-                    .push_packet(packets.clone())
-                    .await;
-            }
-        }
+        // Snapshot the sessions first, so the read lock is not held
+        // while awaiting each packets queue lock
+        let sessions = {
+            let user_sessions =
+                self.user_sessions_service.user_sessions().read().await;
+
+            user_queries
+                .iter()
+                .filter_map(|query| {
+                    UserSessions::get_inner(&user_sessions, query)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        self.push_to_sessions(sessions, packets).await;
 
         Ok(ExecSuccess::default())
     }
@@ -760,14 +1685,17 @@ impl EnqueueBanchoPackets for BanchoStateServiceImpl {
             .ok_or(BanchoStateError::InvalidArgument)?
             .into_user_query()?;
 
-        self.user_sessions_service
+        let session = self
+            .user_sessions_service
             .get(&user_query)
             .await
-            .ok_or(BanchoStateError::SessionNotExists)?
-            .extends
-            .packets_queue
-            .push_packet(packets.into())
-            .await;
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        session.push_packet(packets.into(), self.max_queued_packets).await;
+
+        if session.packets_queue_overflowed() {
+            self.disconnect_overflowed(&session).await;
+        }
 
         Ok(ExecSuccess::default())
     }
@@ -786,7 +1714,1309 @@ impl BroadcastBanchoPackets for BanchoStateServiceImpl {
             .write()
             .await
             .push_message(packet, None);
+        self.user_sessions_service.notify_pushed().notify_waiters();
+
+        Ok(ExecSuccess::default())
+    }
+}
+
+#[async_trait]
+impl BroadcastAnnouncement for BanchoStateServiceImpl {
+    async fn broadcast_announcement(
+        &self,
+        request: BroadcastAnnouncementRequest,
+    ) -> Result<BroadcastAnnouncementResponse, BanchoStateError> {
+        use broadcast_announcement_request::Target;
+
+        const LOG_TARGET: &str = "bancho_state::broadcast_announcement";
+
+        let target = Target::from_i32(request.target)
+            .ok_or(BanchoStateError::InvalidArgument)?;
+
+        let packets = bancho_packets::server::Notification::pack(
+            request.message.as_str().into(),
+        );
+
+        let required_privileges = match target {
+            Target::All => {
+                let sent = self.user_sessions_service.length() as u64;
+
+                self.broadcast_bancho_packets(BroadcastBanchoPacketsRequest {
+                    packets,
+                })
+                .await?;
+
+                info!(
+                    target: LOG_TARGET,
+                    "Announcement sent to all sessions ({sent}): {}",
+                    request.message
+                );
+
+                return Ok(BroadcastAnnouncementResponse { sent });
+            },
+            Target::Supporters => BanchoPrivileges::Supporter,
+            Target::Privileges => {
+                BanchoPrivileges::from(request.bancho_privileges)
+            },
+        };
+
+        let packets = Packet::new_ptr(packets);
+
+        // Snapshot the sessions first, so the read lock is not held
+        // while awaiting each packets queue lock
+        let sessions = self
+            .user_sessions_service
+            .user_sessions()
+            .read()
+            .await
+            .values()
+            .filter(|session| {
+                session
+                    .extends
+                    .bancho_privileges
+                    .load()
+                    .intersects(required_privileges)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let sent = sessions.len() as u64;
+        self.push_to_sessions(sessions, packets).await;
+
+        info!(
+            target: LOG_TARGET,
+            "Announcement sent to {sent} sessions ({target:?}): {}",
+            request.message
+        );
+
+        Ok(BroadcastAnnouncementResponse { sent })
+    }
+}
+
+#[async_trait]
+impl JoinLobby for BanchoStateServiceImpl {
+    async fn join_lobby(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        const LOG_TARGET: &str = "bancho_state::multiplayer::join_lobby";
+
+        let session = self
+            .user_sessions_service
+            .get(&query)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        self.multiplayer().join_lobby(session.user_id).await;
+
+        let mut packets = Vec::new();
+        for m in self.multiplayer().all_matches().await {
+            packets.extend(server::NewMatch::pack(m.lobby_data().await));
+        }
+
+        if !packets.is_empty() {
+            session.push_packet(packets.into(), self.max_queued_packets).await;
+
+            if session.packets_queue_overflowed() {
+                self.disconnect_overflowed(&session).await;
+            }
+        }
+
+        info!(
+            target: LOG_TARGET,
+            "{} [{}] joined the lobby",
+            session.username.load(),
+            session.user_id
+        );
+
+        Ok(ExecSuccess::default())
+    }
+}
+
+#[async_trait]
+impl PartLobby for BanchoStateServiceImpl {
+    async fn part_lobby(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        const LOG_TARGET: &str = "bancho_state::multiplayer::part_lobby";
+
+        let session = self
+            .user_sessions_service
+            .get(&query)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        if self.multiplayer().part_lobby(session.user_id).await {
+            info!(
+                target: LOG_TARGET,
+                "{} [{}] left the lobby",
+                session.username.load(),
+                session.user_id
+            );
+        }
 
         Ok(ExecSuccess::default())
     }
 }
+
+#[async_trait]
+impl CreateMatch for BanchoStateServiceImpl {
+    async fn create_match(
+        &self,
+        request: CreateMatchRequest,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        const LOG_TARGET: &str = "bancho_state::multiplayer::create_match";
+
+        let query =
+            request.user_query.ok_or(BanchoStateError::InvalidArgument)?;
+
+        let data = PayloadReader::new(&request.match_data)
+            .read::<MatchData>()
+            .ok_or(BanchoStateError::InvalidArgument)?;
+
+        let session = self
+            .user_sessions_service
+            .get(&query.into_user_query()?)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        let m = self.host_match(session.user_id, data).await?;
+
+        info!(
+            target: LOG_TARGET,
+            "{} [{}] created the match {}",
+            session.username.load(),
+            session.user_id,
+            m.id
+        );
+
+        Ok(ExecSuccess::default())
+    }
+}
+
+#[async_trait]
+impl JoinMatch for BanchoStateServiceImpl {
+    async fn join_match(
+        &self,
+        request: JoinMatchRequest,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        let JoinMatchRequest { user_query, match_id, password } = request;
+
+        let query = user_query.ok_or(BanchoStateError::InvalidArgument)?;
+
+        let session = self
+            .user_sessions_service
+            .get(&query.into_user_query()?)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        match self
+            .multiplayer()
+            .join_match(session.user_id, match_id, &password)
+            .await
+        {
+            Ok(notifications) => {
+                self.send_match_notifications(notifications).await?;
+
+                Ok(ExecSuccess::default())
+            },
+            Err(err) => {
+                session
+                    .push_packet(
+                        server::MatchJoinFail::pack().into(),
+                        self.max_queued_packets,
+                    )
+                    .await;
+
+                Err(err.into())
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl PartMatch for BanchoStateServiceImpl {
+    async fn part_match(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        let session = self
+            .user_sessions_service
+            .get(&query)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        let notifications =
+            self.multiplayer().leave_match(session.user_id).await;
+
+        self.send_match_notifications(notifications).await?;
+
+        Ok(ExecSuccess::default())
+    }
+}
+
+#[async_trait]
+impl StartMatch for BanchoStateServiceImpl {
+    async fn start_match(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        let session = self
+            .user_sessions_service
+            .get(&query)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        let notifications =
+            self.multiplayer().start_match(session.user_id).await?;
+
+        self.send_match_notifications(notifications).await?;
+
+        Ok(ExecSuccess::default())
+    }
+}
+
+#[async_trait]
+impl MatchLoadComplete for BanchoStateServiceImpl {
+    async fn match_load_complete(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        let session = self
+            .user_sessions_service
+            .get(&query)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        let notifications =
+            self.multiplayer().match_load_complete(session.user_id).await?;
+
+        self.send_match_notifications(notifications).await?;
+
+        Ok(ExecSuccess::default())
+    }
+}
+
+#[async_trait]
+impl MatchPlayerComplete for BanchoStateServiceImpl {
+    async fn match_player_complete(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        let session = self
+            .user_sessions_service
+            .get(&query)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        let notifications =
+            self.multiplayer().match_player_complete(session.user_id).await?;
+
+        self.send_match_notifications(notifications).await?;
+
+        Ok(ExecSuccess::default())
+    }
+}
+
+#[async_trait]
+impl RelayScoreFrame for BanchoStateServiceImpl {
+    async fn relay_score_frame(
+        &self,
+        request: RelayScoreFrameRequest,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        let query =
+            request.user_query.ok_or(BanchoStateError::InvalidArgument)?;
+
+        let frame = PayloadReader::new(&request.score_frame)
+            .read::<ScoreFrame>()
+            .ok_or(BanchoStateError::InvalidArgument)?;
+
+        let session = self
+            .user_sessions_service
+            .get(&query.into_user_query()?)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        let notifications =
+            self.multiplayer().relay_score_frame(session.user_id, frame).await;
+
+        self.send_match_notifications(notifications).await?;
+
+        Ok(ExecSuccess::default())
+    }
+}
+
+#[async_trait]
+impl GetMatch for BanchoStateServiceImpl {
+    async fn get_match(
+        &self,
+        request: GetMatchRequest,
+    ) -> Result<GetMatchResponse, BanchoStateError> {
+        let m = self
+            .multiplayer()
+            .get_match(request.match_id)
+            .await
+            .ok_or(MultiplayerError::MatchNotExists)?;
+
+        Ok(GetMatchResponse {
+            match_update: server::UpdateMatch::pack(m.lobby_data().await),
+            channel_name: m.channel_name(),
+        })
+    }
+}
+
+#[async_trait]
+impl StartSpectating for BanchoStateServiceImpl {
+    async fn start_spectating(
+        &self,
+        request: StartSpectatingRequest,
+    ) -> Result<StartSpectatingResponse, BanchoStateError> {
+        const LOG_TARGET: &str = "bancho_state::spectating::start";
+
+        let StartSpectatingRequest { user_query, host_id } = request;
+
+        let query = user_query
+            .ok_or(BanchoStateError::InvalidArgument)?
+            .into_user_query()?;
+
+        let session = self
+            .user_sessions_service
+            .get(&query)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        if !self.user_sessions_service.exists(&UserQuery::UserId(host_id)).await
+        {
+            return Err(BanchoStateError::SessionNotExists);
+        }
+
+        let added = match self
+            .spectating()
+            .add_spectator(session.user_id, host_id)
+            .await
+        {
+            Some(added) => added,
+            None => return Ok(StartSpectatingResponse::default()),
+        };
+
+        self.send_match_notifications(
+            added.notifications(session.user_id, host_id),
+        )
+        .await?;
+
+        info!(
+            target: LOG_TARGET,
+            "{} [{}] started spectating [{}]",
+            session.username.load(),
+            session.user_id,
+            host_id
+        );
+
+        Ok(StartSpectatingResponse {
+            previous_host_id: added.previous.map(|previous| previous.host_id),
+        })
+    }
+}
+
+#[async_trait]
+impl StopSpectating for BanchoStateServiceImpl {
+    async fn stop_spectating(
+        &self,
+        query: UserQuery,
+    ) -> Result<StopSpectatingResponse, BanchoStateError> {
+        const LOG_TARGET: &str = "bancho_state::spectating::stop";
+
+        let session = self
+            .user_sessions_service
+            .get(&query)
+            .await
+            .ok_or(BanchoStateError::SessionNotExists)?;
+
+        let removed =
+            match self.spectating().remove_spectator(session.user_id).await {
+                Some(removed) => removed,
+                None => return Ok(StopSpectatingResponse::default()),
+            };
+
+        self.send_match_notifications(removed.notifications(session.user_id))
+            .await?;
+
+        info!(
+            target: LOG_TARGET,
+            "{} [{}] stopped spectating [{}]",
+            session.username.load(),
+            session.user_id,
+            removed.host_id
+        );
+
+        Ok(StopSpectatingResponse { host_id: Some(removed.host_id) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core_signature::SignatureServiceImpl;
+    use peace_snapshot::{
+        decode_snapshot, encode_snapshot, SnapshotCompression,
+    };
+    use tools::crypto::SignerManager;
+
+    fn session(user_id: i32) -> Arc<BanchoSession> {
+        Arc::new(BanchoSession::new(CreateSessionDto {
+            user_id,
+            username: format!("user{user_id}"),
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn test_user_stats_targets() {
+        let mut indexes = UserIndexes::new();
+        for user_id in 1..=4 {
+            indexes.add_session(session(user_id));
+        }
+
+        // 4 only shows their presence to friends
+        UserSessions::get_inner(&indexes, &UserQuery::UserId(4))
+            .unwrap()
+            .extends
+            .presence_filter
+            .set(PresenceFilter::Friends.into());
+
+        let targets = user_stats_targets(
+            &indexes,
+            &[
+                UserQuery::UserId(3),
+                // the requester
+                UserQuery::UserId(1),
+                UserQuery::Username("user2".into()),
+                // queried twice
+                UserQuery::Username("user3".into()),
+                UserQuery::UserId(4),
+                // offline
+                UserQuery::UserId(5),
+                UserQuery::UserId(2),
+            ],
+            &UserQuery::UserId(1),
+        );
+
+        assert_eq!(
+            targets.iter().map(|s| s.user_id).collect::<Vec<_>>(),
+            vec![3, 2]
+        );
+    }
+
+    #[test]
+    fn test_sessions_page() {
+        use get_sessions_page_request::SessionIndex;
+
+        let mut indexes = UserIndexes::new();
+        for user_id in [3, 1, 5, 2, 4] {
+            indexes.add_session(session(user_id));
+        }
+
+        for index in [
+            SessionIndex::SessionId,
+            SessionIndex::UserId,
+            SessionIndex::Username,
+            SessionIndex::UsernameUnicode,
+        ] {
+            // consecutive pages cover every session exactly once
+            let mut paged = Vec::new();
+            for offset in (0..6).step_by(2) {
+                let (total, page) = sessions_page(&indexes, index, offset, 2);
+                assert_eq!(total, 5);
+                paged.extend(page.iter().map(|s| s.user_id));
+            }
+
+            let mut user_ids = paged.clone();
+            user_ids.sort_unstable();
+            assert_eq!(user_ids, vec![1, 2, 3, 4, 5], "{index:?}");
+
+            // and the same page is returned for the same offset
+            let again = sessions_page(&indexes, index, 2, 2)
+                .1
+                .iter()
+                .map(|s| s.user_id)
+                .collect::<Vec<_>>();
+            assert_eq!(again, paged[2..4], "{index:?}");
+        }
+
+        // the usernames are paged in order
+        let page = sessions_page(&indexes, SessionIndex::Username, 1, 3).1;
+        assert_eq!(
+            page.iter().map(|s| s.user_id).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+
+        assert!(sessions_page(&indexes, SessionIndex::UserId, 5, 2)
+            .1
+            .is_empty());
+    }
+
+    #[test]
+    fn test_user_sessions_with_fields() {
+        let mut indexes = UserIndexes::new();
+        for user_id in 1..=2 {
+            indexes.add_session(session(user_id));
+        }
+
+        let sessions = user_sessions_with_fields(
+            &indexes,
+            &[
+                UserQuery::UserId(2),
+                // offline
+                UserQuery::UserId(3),
+                UserQuery::Username("user1".into()),
+            ],
+            UserSessionFields::UserId.or(UserSessionFields::Username),
+        );
+
+        assert_eq!(
+            sessions,
+            vec![
+                GetUserSessionResponse {
+                    user_id: Some(2),
+                    username: Some("user2".into()),
+                    ..Default::default()
+                },
+                GetUserSessionResponse::default(),
+                GetUserSessionResponse {
+                    user_id: Some(1),
+                    username: Some("user1".into()),
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_push_concurrently() {
+        let sessions = (1..=100).map(session).collect::<Vec<_>>();
+        // already has a queued packet, overflows with the next one
+        sessions[9].push_packet(Packet::new(vec![0]), 1).await;
+
+        let overflowed = push_concurrently(
+            sessions.clone(),
+            Packet::new_ptr(vec![1, 2, 3]),
+            1,
+            8,
+        )
+        .await;
+
+        assert_eq!(
+            overflowed.iter().map(|s| s.user_id).collect::<Vec<_>>(),
+            vec![10]
+        );
+        for session in sessions.iter().filter(|s| s.user_id != 10) {
+            assert_eq!(session.extends.packets_queue.queued_packets().await, 1);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_coalesce_packets_max_wait() {
+        const WINDOW: Duration = Duration::from_millis(5);
+
+        let session = session(1000);
+
+        // nothing to coalesce
+        let start = tokio::time::Instant::now();
+        coalesce_packets(&session, WINDOW).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        session.push_packet(Packet::new(vec![0]), 0).await;
+
+        // packets keep arriving during the whole window
+        let pusher = tokio::spawn({
+            let session = session.clone();
+            async move {
+                loop {
+                    session.push_packet(Packet::new(vec![1]), 0).await;
+                    tokio::time::sleep(Duration::from_micros(500)).await;
+                }
+            }
+        });
+
+        let start = tokio::time::Instant::now();
+        coalesce_packets(&session, WINDOW).await;
+        assert!(start.elapsed() <= WINDOW);
+
+        pusher.abort();
+    }
+
+    #[tokio::test]
+    async fn test_presence_refreshes_presence_only() {
+        use bancho_packets::PacketReader;
+
+        let service = BanchoStateServiceImpl::new(
+            UserSessionsServiceImpl::new().into_service(),
+            SignatureServiceImpl::from(SignerManager::new_rand())
+                .into_service(),
+            0,
+            1,
+            Duration::ZERO,
+            Vec::new(),
+        );
+        for user_id in [1000, 1001, 1002] {
+            service
+                .user_sessions_service
+                .create(CreateSessionDto {
+                    user_id,
+                    username: format!("user{user_id}"),
+                    ..Default::default()
+                })
+                .await;
+        }
+
+        let received = |user_id: i32| {
+            let service = &service;
+            async move {
+                let BanchoPackets { data } = service
+                    .dequeue_bancho_packets(DequeueBanchoPacketsRequest {
+                        user_query: Some(UserQuery::UserId(user_id).into()),
+                        coalesce: false,
+                        ..Default::default()
+                    })
+                    .await
+                    .unwrap();
+
+                PacketReader::new(&data).map(|p| p.id).collect::<Vec<_>>()
+            }
+        };
+        // drain the login packets
+        for user_id in [1000, 1001, 1002] {
+            received(user_id).await;
+        }
+
+        service
+            .send_all_presences(SendAllPresencesRequest {
+                to: Some(UserQuery::UserId(1000).into()),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            received(1000).await,
+            vec![
+                PacketId::BANCHO_USER_PRESENCE,
+                PacketId::BANCHO_USER_PRESENCE
+            ]
+        );
+
+        service
+            .batch_send_presences(BatchSendPresencesRequest {
+                user_queries: vec![UserQuery::UserId(1001).into()],
+                to: Some(UserQuery::UserId(1000).into()),
+            })
+            .await
+            .unwrap();
+        assert_eq!(received(1000).await, vec![PacketId::BANCHO_USER_PRESENCE]);
+
+        // a rename only changes the presence
+        service
+            .user_sessions_service
+            .rename(1001, "renamed".into(), None)
+            .await
+            .unwrap();
+        assert_eq!(received(1002).await, vec![PacketId::BANCHO_USER_PRESENCE]);
+
+        // status changes send the stats
+        service
+            .update_user_bancho_status(UpdateUserBanchoStatusRequest {
+                user_query: Some(UserQuery::UserId(1001).into()),
+                online_status: UserOnlineStatus::Playing as i32,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(received(1002).await, vec![PacketId::BANCHO_USER_STATS]);
+    }
+
+    #[tokio::test]
+    async fn test_quiet_online_status_not_broadcast() {
+        let service = BanchoStateServiceImpl::new(
+            UserSessionsServiceImpl::new().into_service(),
+            SignatureServiceImpl::from(SignerManager::new_rand())
+                .into_service(),
+            0,
+            1,
+            Duration::ZERO,
+            vec![UserOnlineStatus::Afk],
+        );
+        for user_id in [1000, 1001] {
+            service
+                .user_sessions_service
+                .create(CreateSessionDto {
+                    user_id,
+                    username: format!("user{user_id}"),
+                    ..Default::default()
+                })
+                .await;
+        }
+
+        let update_status = |online_status: UserOnlineStatus| {
+            service.update_user_bancho_status(UpdateUserBanchoStatusRequest {
+                user_query: Some(UserQuery::UserId(1000).into()),
+                online_status: online_status as i32,
+                ..Default::default()
+            })
+        };
+        let dequeue = || {
+            service.dequeue_bancho_packets(DequeueBanchoPacketsRequest {
+                user_query: Some(UserQuery::UserId(1001).into()),
+                coalesce: false,
+                ..Default::default()
+            })
+        };
+
+        update_status(UserOnlineStatus::Afk).await.unwrap();
+        assert!(dequeue().await.unwrap().data.is_empty());
+
+        // still stored
+        let session = service
+            .user_sessions_service
+            .get(&UserQuery::UserId(1000))
+            .await
+            .unwrap();
+        assert_eq!(
+            *session.extends.bancho_status.online_status.val(),
+            UserOnlineStatus::Afk
+        );
+
+        update_status(UserOnlineStatus::Playing).await.unwrap();
+        assert!(!dequeue().await.unwrap().data.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_online_summary() {
+        let service = BanchoStateServiceImpl::new(
+            UserSessionsServiceImpl::new().into_service(),
+            SignatureServiceImpl::from(SignerManager::new_rand())
+                .into_service(),
+            0,
+            1,
+            Duration::ZERO,
+            Vec::new(),
+        );
+        for user_id in [1001, 1000] {
+            service
+                .user_sessions_service
+                .create(CreateSessionDto {
+                    user_id,
+                    username: format!("user{user_id}"),
+                    ..Default::default()
+                })
+                .await;
+        }
+
+        service
+            .update_user_bancho_status(UpdateUserBanchoStatusRequest {
+                user_query: Some(UserQuery::UserId(1001).into()),
+                online_status: UserOnlineStatus::Playing as i32,
+                mods: Mods::Hidden.or(Mods::HardRock).bits(),
+                mode: GameMode::Taiko as i32,
+                beatmap_id: 75,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let OnlineSummary { total, users } = service
+            .get_online_summary(GetOnlineSummaryRequest { offset: 1, limit: 1 })
+            .await
+            .unwrap();
+
+        assert_eq!(total, 2);
+        assert_eq!(
+            users,
+            vec![OnlineUser {
+                user_id: 1001,
+                username: "user1001".into(),
+                mode: GameMode::Taiko as i32,
+                online_status: UserOnlineStatus::Playing as i32,
+                mods: Mods::Hidden.or(Mods::HardRock).bits(),
+                beatmap_id: 75,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_relogin_bumps_reconnect_count() {
+        let service = BanchoStateServiceImpl::new(
+            UserSessionsServiceImpl::new().into_service(),
+            SignatureServiceImpl::from(SignerManager::new_rand())
+                .into_service(),
+            0,
+            1,
+            Duration::ZERO,
+            Vec::new(),
+        );
+
+        let login = || {
+            service.create_user_session(CreateUserSessionRequest {
+                user_id: 1000,
+                username: "peace".into(),
+                connection_info: Some(Default::default()),
+                ..Default::default()
+            })
+        };
+        let get_fields = || {
+            service.get_user_session_with_fields(RawUserQueryWithFields {
+                user_query: Some(UserQuery::UserId(1000).into()),
+                fields: UserSessionFields::ConnectedAt
+                    .or(UserSessionFields::ReconnectCount)
+                    .bits(),
+            })
+        };
+
+        login().await.unwrap();
+        let res = get_fields().await.unwrap();
+        assert_eq!(res.reconnect_count, Some(0));
+        assert!(res.connected_at.unwrap() > 0);
+
+        login().await.unwrap();
+        login().await.unwrap();
+        assert_eq!(get_fields().await.unwrap().reconnect_count, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_logins_closed_while_draining() {
+        let service = BanchoStateServiceImpl::new(
+            UserSessionsServiceImpl::new().into_service(),
+            SignatureServiceImpl::from(SignerManager::new_rand())
+                .into_service(),
+            0,
+            1,
+            Duration::ZERO,
+            Vec::new(),
+        );
+
+        let login = |user_id: i32| {
+            service.create_user_session(CreateUserSessionRequest {
+                user_id,
+                username: format!("user{user_id}"),
+                connection_info: Some(Default::default()),
+                ..Default::default()
+            })
+        };
+
+        login(1000).await.unwrap();
+        // the client polls its login packets
+        service
+            .dequeue_bancho_packets(DequeueBanchoPacketsRequest {
+                user_query: Some(UserQuery::UserId(1000).into()),
+                coalesce: false,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        service
+            .user_sessions_service
+            .wait_packets_drained(Duration::from_millis(10))
+            .await;
+
+        assert!(matches!(
+            login(1001).await,
+            Err(BanchoStateError::CreateSessionError(
+                CreateSessionError::LoginsClosed
+            ))
+        ));
+        // the online sessions are kept
+        assert!(
+            service
+                .user_sessions_service
+                .exists(&UserQuery::UserId(1000))
+                .await
+        );
+        assert!(
+            !service
+                .user_sessions_service
+                .exists(&UserQuery::UserId(1001))
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_packet_history_kept_on_session() {
+        let service = BanchoStateServiceImpl::new(
+            UserSessionsServiceImpl::new().into_service(),
+            SignatureServiceImpl::from(SignerManager::new_rand())
+                .into_service(),
+            0,
+            1,
+            Duration::ZERO,
+            Vec::new(),
+        );
+        let query = UserQuery::UserId(1000);
+
+        service
+            .create_user_session(CreateUserSessionRequest {
+                user_id: 1000,
+                username: "peace".into(),
+                connection_info: Some(Default::default()),
+                packet_history_size: 2,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        service
+            .record_packets(RecordPacketsRequest {
+                user_query: Some(query.clone().into()),
+                packet_ids: vec![
+                    PacketId::OSU_PING as u8,
+                    PacketId::OSU_USER_CHANGE_ACTION as u8,
+                    PacketId::OSU_USER_LOGOUT as u8,
+                ],
+            })
+            .await
+            .unwrap();
+
+        let packets =
+            service.get_packet_history(query.clone()).await.unwrap().packets;
+        assert_eq!(
+            packets.iter().map(|p| p.packet_id).collect::<Vec<_>>(),
+            vec![
+                PacketId::OSU_USER_CHANGE_ACTION as i32,
+                PacketId::OSU_USER_LOGOUT as i32,
+            ]
+        );
+
+        // The history goes away with the session
+        service.delete_user_session(query.clone()).await.unwrap();
+        assert!(matches!(
+            service.get_packet_history(query).await,
+            Err(BanchoStateError::SessionNotExists)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_raw() {
+        let service = BanchoStateServiceImpl::new(
+            UserSessionsServiceImpl::new().into_service(),
+            SignatureServiceImpl::from(SignerManager::new_rand())
+                .into_service(),
+            0,
+            1,
+            Duration::ZERO,
+            Vec::new(),
+        );
+        service
+            .user_sessions_service
+            .create(CreateSessionDto {
+                user_id: 1000,
+                username: "peace".into(),
+                ..Default::default()
+            })
+            .await;
+
+        let notification = server::Notification::pack("hello".into());
+
+        service
+            .enqueue_raw(UserQuery::UserId(1000), notification.clone())
+            .await
+            .unwrap();
+
+        let BanchoPackets { data } = service
+            .dequeue_bancho_packets(DequeueBanchoPacketsRequest {
+                user_query: Some(UserQuery::UserId(1000).into()),
+                coalesce: false,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(data, notification);
+
+        assert!(matches!(
+            service.enqueue_raw(UserQuery::UserId(1001), notification).await,
+            Err(BanchoStateError::SessionNotExists)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_dequeue_waits_for_pushed_packets() {
+        let service = Arc::new(BanchoStateServiceImpl::new(
+            UserSessionsServiceImpl::new().into_service(),
+            SignatureServiceImpl::from(SignerManager::new_rand())
+                .into_service(),
+            0,
+            1,
+            Duration::ZERO,
+            Vec::new(),
+        ));
+        service
+            .user_sessions_service
+            .create(CreateSessionDto {
+                user_id: 1000,
+                username: "peace".into(),
+                ..Default::default()
+            })
+            .await;
+
+        let notification = server::Notification::pack("hello".into());
+
+        let pusher = tokio::spawn({
+            let service = service.clone();
+            let notification = notification.clone();
+            async move {
+                service
+                    .enqueue_raw(UserQuery::UserId(1000), notification)
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let BanchoPackets { data } = tokio::time::timeout(
+            Duration::from_secs(1),
+            service.dequeue_bancho_packets(DequeueBanchoPacketsRequest {
+                user_query: Some(UserQuery::UserId(1000).into()),
+                wait_ms: 60_000,
+                ..Default::default()
+            }),
+        )
+        .await
+        .expect("the waiting dequeue is not woken by the push")
+        .unwrap();
+        assert_eq!(data, notification);
+
+        pusher.await.unwrap();
+
+        // broadcasts wake the waiting sessions too
+        let broadcaster = tokio::spawn({
+            let service = service.clone();
+            let notification = notification.clone();
+            async move {
+                service
+                    .broadcast_bancho_packets(BroadcastBanchoPacketsRequest {
+                        packets: notification,
+                    })
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let BanchoPackets { data } = tokio::time::timeout(
+            Duration::from_secs(1),
+            service.dequeue_bancho_packets(DequeueBanchoPacketsRequest {
+                user_query: Some(UserQuery::UserId(1000).into()),
+                wait_ms: 60_000,
+                ..Default::default()
+            }),
+        )
+        .await
+        .expect("the waiting dequeue is not woken by the broadcast")
+        .unwrap();
+        assert_eq!(data, notification);
+
+        broadcaster.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_summary() {
+        let session = |user_id: i32, username: &str| {
+            BanchoSession::new(CreateSessionDto {
+                user_id,
+                username: username.into(),
+                username_unicode: None,
+                privileges: 1,
+                extends: BanchoExtend::default(),
+            })
+        };
+
+        let first = session(1, "peace");
+        first.push_packet(Packet::new(vec![1, 2, 3]), 0).await;
+
+        // a stale session of the same user
+        let snapshot = BanchoStateServiceSnapshot {
+            user_sessions: vec![
+                first.create_snapshot().await,
+                session(1, "peace").create_snapshot().await,
+                session(2, "other").create_snapshot().await,
+            ],
+            notify_queue: vec![],
+            create_time: Utc::now(),
+        };
+
+        let summary = snapshot.summary();
+
+        assert_eq!(summary.sessions, 3);
+        assert_eq!(summary.queued_packets, 1);
+        assert_eq!(summary.session_id_index, 3);
+        assert_eq!(summary.user_id_index, 2);
+        assert_eq!(summary.username_index, 2);
+    }
+
+    #[tokio::test]
+    async fn test_compressed_snapshot_restores_sessions() {
+        let session = BanchoSession::new(CreateSessionDto {
+            user_id: 1,
+            username: "peace".into(),
+            username_unicode: Some("和平".into()),
+            privileges: 1,
+            extends: BanchoExtend {
+                client_version: "b20230101".into(),
+                utc_offset: 8,
+                silence_end: 1_700_000_000.into(),
+                ..Default::default()
+            },
+        });
+        session.push_packet(Packet::new(vec![1, 2, 3]), 0).await;
+
+        let snapshot = BanchoStateServiceSnapshot {
+            user_sessions: vec![session.create_snapshot().await],
+            notify_queue: vec![],
+            create_time: Utc::now(),
+        };
+
+        for compression in
+            [SnapshotCompression::Gzip, SnapshotCompression::Zstd]
+        {
+            for snapshot_type in [SnapshotType::Binary, SnapshotType::Json] {
+                let compressed = compression
+                    .compress(
+                        encode_snapshot(snapshot_type, &snapshot).unwrap(),
+                    )
+                    .unwrap();
+
+                let restored: BanchoStateServiceSnapshot = decode_snapshot(
+                    snapshot_type,
+                    &compression.decompress(compressed).unwrap(),
+                )
+                .unwrap();
+
+                let restored = BanchoSession::from(
+                    restored.user_sessions.into_iter().next().unwrap(),
+                );
+
+                assert_eq!(
+                    serde_json::to_value(&restored).unwrap(),
+                    serde_json::to_value(&session).unwrap()
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_match_packet_flow() {
+        use bancho_packets::{
+            data, BanchoPacketLength, BanchoPacketWrite, PacketId, PacketReader,
+        };
+        use domain_bancho::MatchSlotStatus;
+
+        let service = BanchoStateServiceImpl::new(
+            UserSessionsServiceImpl::new().into_service(),
+            SignatureServiceImpl::from(SignerManager::new_rand())
+                .into_service(),
+            0,
+            1,
+            Duration::ZERO,
+            Vec::new(),
+        );
+        for user_id in [1000, 1001, 1002] {
+            service
+                .user_sessions_service
+                .create(CreateSessionDto {
+                    user_id,
+                    username: format!("user{user_id}"),
+                    ..Default::default()
+                })
+                .await;
+        }
+
+        let received = |user_id: i32| {
+            let service = &service;
+            async move {
+                let BanchoPackets { data } = service
+                    .dequeue_bancho_packets(DequeueBanchoPacketsRequest {
+                        user_query: Some(UserQuery::UserId(user_id).into()),
+                        coalesce: false,
+                        ..Default::default()
+                    })
+                    .await
+                    .unwrap();
+
+                PacketReader::new(&data).map(|p| p.id).collect::<Vec<_>>()
+            }
+        };
+
+        service.join_lobby(UserQuery::UserId(1002)).await.unwrap();
+
+        // payload of the `OSU_USER_CREATE_MATCH` packet sent by the client
+        let mut slot_status = vec![MatchSlotStatus::Open.bits(); 16];
+        slot_status[0] = MatchSlotStatus::NotReady.bits();
+        let match_data = data!(
+            0u16,
+            false,
+            0i8,
+            0u32,
+            "match",
+            "secret",
+            "beatmap",
+            1,
+            "md5",
+            slot_status,
+            vec![0u8; 16],
+            1000,
+            1000,
+            0u8,
+            0u8,
+            0u8,
+            false,
+            0
+        );
+
+        service
+            .create_match(CreateMatchRequest {
+                user_query: Some(UserQuery::UserId(1000).into()),
+                match_data,
+            })
+            .await
+            .unwrap();
+
+        let m = service.multiplayer().find_player_match(1000).await.unwrap();
+        assert_eq!(
+            received(1000).await,
+            vec![
+                PacketId::BANCHO_NEW_MATCH,
+                PacketId::BANCHO_MATCH_JOIN_SUCCESS
+            ]
+        );
+        assert_eq!(received(1002).await, vec![PacketId::BANCHO_NEW_MATCH]);
+
+        let join = |password: &str| {
+            service.join_match(JoinMatchRequest {
+                user_query: Some(UserQuery::UserId(1001).into()),
+                match_id: m.id,
+                password: password.into(),
+            })
+        };
+
+        assert!(join("wrong").await.is_err());
+        assert_eq!(
+            received(1001).await,
+            vec![PacketId::BANCHO_MATCH_JOIN_FAIL]
+        );
+
+        join("secret").await.unwrap();
+        assert_eq!(
+            received(1001).await,
+            vec![
+                PacketId::BANCHO_MATCH_JOIN_SUCCESS,
+                PacketId::BANCHO_UPDATE_MATCH
+            ]
+        );
+        assert_eq!(m.players().await, vec![1000, 1001]);
+
+        // the host leaves, 1001 is the new host
+        service.part_match(UserQuery::UserId(1000)).await.unwrap();
+        assert_eq!(m.data.read().await.host_player_id, 1001);
+        received(1001).await;
+        received(1002).await;
+
+        // the last player logs out, the match is disposed
+        service.user_sessions_service.delete(&UserQuery::UserId(1001)).await;
+        assert!(service.multiplayer().get_match(m.id).await.is_none());
+        assert!(received(1002).await.contains(&PacketId::BANCHO_DISBAND_MATCH));
+    }
+}
@@ -1,28 +1,43 @@
 use crate::*;
 use async_trait::async_trait;
 use domain_bancho::BanchoClientToken;
-use infra_services::{FromRpcClient, IntoService, RpcClient, ServiceSnapshot};
+use infra_services::{
+    FromRpcClient, IntoService, ReconnectingClient, RpcClient, ServiceSnapshot,
+};
 use pb_bancho_state::{bancho_state_rpc_client::BanchoStateRpcClient, *};
 use pb_base::ExecSuccess;
 use peace_snapshot::{CreateSnapshot, CreateSnapshotError, SnapshotType};
 use std::sync::Arc;
-use tonic::transport::Channel;
+use tools::tonic_utils::TracedChannel;
 
 #[derive(Debug, Clone)]
-pub struct BanchoStateServiceRemote(BanchoStateRpcClient<Channel>);
+pub struct BanchoStateServiceRemote(
+    ReconnectingClient<BanchoStateRpcClient<TracedChannel>>,
+);
+
+impl BanchoStateServiceRemote {
+    /// Remote service whose channel is reconnected after transport errors,
+    /// the read-only rpcs are retried on the new channel.
+    #[inline]
+    pub fn new(
+        client: ReconnectingClient<BanchoStateRpcClient<TracedChannel>>,
+    ) -> Self {
+        Self(client)
+    }
+}
 
 impl FromRpcClient for BanchoStateServiceRemote {
     #[inline]
     fn from_client(client: Self::Client) -> Self {
-        Self(client)
+        Self(ReconnectingClient::from_client(client))
     }
 }
 
 impl RpcClient for BanchoStateServiceRemote {
-    type Client = BanchoStateRpcClient<Channel>;
+    type Client = BanchoStateRpcClient<TracedChannel>;
 
     fn client(&self) -> Self::Client {
-        self.0.clone()
+        self.0.client()
     }
 }
 
@@ -60,7 +75,30 @@ impl BroadcastBanchoPackets for BanchoStateServiceRemote {
         &self,
         request: BroadcastBanchoPacketsRequest,
     ) -> Result<ExecSuccess, BanchoStateError> {
-        Ok(self.client().broadcast_bancho_packets(request).await?.into_inner())
+        Ok(
+            self.0
+                .call(|mut c| async move {
+                    c.broadcast_bancho_packets(request).await
+                })
+                .await?
+                .into_inner(),
+        )
+    }
+}
+
+#[async_trait]
+impl BroadcastAnnouncement for BanchoStateServiceRemote {
+    async fn broadcast_announcement(
+        &self,
+        request: BroadcastAnnouncementRequest,
+    ) -> Result<BroadcastAnnouncementResponse, BanchoStateError> {
+        Ok(self
+            .0
+            .call(
+                |mut c| async move { c.broadcast_announcement(request).await },
+            )
+            .await?
+            .into_inner())
     }
 }
 
@@ -70,7 +108,13 @@ impl EnqueueBanchoPackets for BanchoStateServiceRemote {
         &self,
         request: EnqueueBanchoPacketsRequest,
     ) -> Result<ExecSuccess, BanchoStateError> {
-        Ok(self.client().enqueue_bancho_packets(request).await?.into_inner())
+        Ok(self
+            .0
+            .call(
+                |mut c| async move { c.enqueue_bancho_packets(request).await },
+            )
+            .await?
+            .into_inner())
     }
 }
 
@@ -81,8 +125,10 @@ impl BatchEnqueueBanchoPackets for BanchoStateServiceRemote {
         request: BatchEnqueueBanchoPacketsRequest,
     ) -> Result<ExecSuccess, BanchoStateError> {
         Ok(self
-            .client()
-            .batch_enqueue_bancho_packets(request)
+            .0
+            .call(|mut c| async move {
+                c.batch_enqueue_bancho_packets(request).await
+            })
             .await?
             .into_inner())
     }
@@ -94,7 +140,46 @@ impl DequeueBanchoPackets for BanchoStateServiceRemote {
         &self,
         request: DequeueBanchoPacketsRequest,
     ) -> Result<BanchoPackets, BanchoStateError> {
-        Ok(self.client().dequeue_bancho_packets(request).await?.into_inner())
+        Ok(self
+            .0
+            .call(
+                |mut c| async move { c.dequeue_bancho_packets(request).await },
+            )
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl RecordPackets for BanchoStateServiceRemote {
+    async fn record_packets(
+        &self,
+        request: RecordPacketsRequest,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        Ok(self
+            .0
+            .call(|mut c| async move { c.record_packets(request).await })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl GetPacketHistory for BanchoStateServiceRemote {
+    async fn get_packet_history(
+        &self,
+        query: UserQuery,
+    ) -> Result<PacketHistoryResponse, BanchoStateError> {
+        let request = Into::<RawUserQuery>::into(query);
+
+        Ok(self
+            .0
+            .call_idempotent(|mut c| {
+                let request = request.clone();
+                async move { c.get_packet_history(request).await }
+            })
+            .await?
+            .into_inner())
     }
 }
 
@@ -104,7 +189,11 @@ impl CreateUserSession for BanchoStateServiceRemote {
         &self,
         request: CreateUserSessionRequest,
     ) -> Result<CreateUserSessionResponse, BanchoStateError> {
-        Ok(self.client().create_user_session(request).await?.into_inner())
+        Ok(self
+            .0
+            .call(|mut c| async move { c.create_user_session(request).await })
+            .await?
+            .into_inner())
     }
 }
 
@@ -115,8 +204,24 @@ impl DeleteUserSession for BanchoStateServiceRemote {
         query: UserQuery,
     ) -> Result<ExecSuccess, BanchoStateError> {
         Ok(self
-            .client()
-            .delete_user_session(Into::<RawUserQuery>::into(query))
+            .0
+            .call(|mut c| async move {
+                c.delete_user_session(Into::<RawUserQuery>::into(query)).await
+            })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl KickUsersByIp for BanchoStateServiceRemote {
+    async fn kick_users_by_ip(
+        &self,
+        request: KickUsersByIpRequest,
+    ) -> Result<KickUsersByIpResponse, BanchoStateError> {
+        Ok(self
+            .0
+            .call(|mut c| async move { c.kick_users_by_ip(request).await })
             .await?
             .into_inner())
     }
@@ -128,7 +233,14 @@ impl CheckUserToken for BanchoStateServiceRemote {
         &self,
         token: BanchoClientToken,
     ) -> Result<CheckUserTokenResponse, BanchoStateError> {
-        Ok(self.client().check_user_token(token).await?.into_inner())
+        Ok(self
+            .0
+            .call_idempotent(|mut c| {
+                let token = token.clone();
+                async move { c.check_user_token(token).await }
+            })
+            .await?
+            .into_inner())
     }
 }
 
@@ -138,9 +250,14 @@ impl IsUserOnline for BanchoStateServiceRemote {
         &self,
         query: UserQuery,
     ) -> Result<UserOnlineResponse, BanchoStateError> {
+        let request = Into::<RawUserQuery>::into(query);
+
         Ok(self
-            .client()
-            .is_user_online(Into::<RawUserQuery>::into(query))
+            .0
+            .call_idempotent(|mut c| {
+                let request = request.clone();
+                async move { c.is_user_online(request).await }
+            })
             .await?
             .into_inner())
     }
@@ -152,9 +269,50 @@ impl GetUserSession for BanchoStateServiceRemote {
         &self,
         query: UserQuery,
     ) -> Result<GetUserSessionResponse, BanchoStateError> {
+        let request = Into::<RawUserQuery>::into(query);
+
+        Ok(self
+            .0
+            .call_idempotent(|mut c| {
+                let request = request.clone();
+                async move { c.get_user_session(request).await }
+            })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl GetUserSessionsWithFields for BanchoStateServiceRemote {
+    async fn get_user_sessions_with_fields(
+        &self,
+        raw_queries: RawUserQueriesWithFields,
+    ) -> Result<GetUserSessionsResponse, BanchoStateError> {
+        Ok(self
+            .0
+            .call_idempotent(|mut c| {
+                let raw_queries = raw_queries.clone();
+                async move { c.get_user_sessions_with_fields(raw_queries).await }
+            })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl GetUserSessionPresence for BanchoStateServiceRemote {
+    async fn get_user_session_presence(
+        &self,
+        query: UserQuery,
+    ) -> Result<GetUserSessionPresenceResponse, BanchoStateError> {
+        let request = Into::<RawUserQuery>::into(query);
+
         Ok(self
-            .client()
-            .get_user_session(Into::<RawUserQuery>::into(query))
+            .0
+            .call_idempotent(|mut c| {
+                let request = request.clone();
+                async move { c.get_user_session_presence(request).await }
+            })
             .await?
             .into_inner())
     }
@@ -167,8 +325,11 @@ impl GetUserSessionWithFields for BanchoStateServiceRemote {
         request: RawUserQueryWithFields,
     ) -> Result<GetUserSessionResponse, BanchoStateError> {
         Ok(self
-            .client()
-            .get_user_session_with_fields(request)
+            .0
+            .call_idempotent(|mut c| {
+                let request = request.clone();
+                async move { c.get_user_session_with_fields(request).await }
+            })
             .await?
             .into_inner())
     }
@@ -179,9 +340,66 @@ impl GetAllSessions for BanchoStateServiceRemote {
     async fn get_all_sessions(
         &self,
     ) -> Result<GetAllSessionsResponse, BanchoStateError> {
+        let request = GetAllSessionsRequest {};
+
+        Ok(self
+            .0
+            .call_idempotent(|mut c| {
+                let request = request.clone();
+                async move { c.get_all_sessions(request).await }
+            })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl GetSessionsPage for BanchoStateServiceRemote {
+    async fn get_sessions_page(
+        &self,
+        request: GetSessionsPageRequest,
+    ) -> Result<GetSessionsPageResponse, BanchoStateError> {
+        Ok(self
+            .0
+            .call_idempotent(|mut c| {
+                let request = request.clone();
+                async move { c.get_sessions_page(request).await }
+            })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl GetSessionsStats for BanchoStateServiceRemote {
+    async fn get_sessions_stats(
+        &self,
+    ) -> Result<SessionsStats, BanchoStateError> {
+        let request = GetSessionsStatsRequest {};
+
         Ok(self
-            .client()
-            .get_all_sessions(GetAllSessionsRequest {})
+            .0
+            .call_idempotent(|mut c| {
+                let request = request.clone();
+                async move { c.get_sessions_stats(request).await }
+            })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl GetOnlineSummary for BanchoStateServiceRemote {
+    async fn get_online_summary(
+        &self,
+        request: GetOnlineSummaryRequest,
+    ) -> Result<OnlineSummary, BanchoStateError> {
+        Ok(self
+            .0
+            .call_idempotent(|mut c| {
+                let request = request.clone();
+                async move { c.get_online_summary(request).await }
+            })
             .await?
             .into_inner())
     }
@@ -193,7 +411,13 @@ impl SendUserStatsPacket for BanchoStateServiceRemote {
         &self,
         request: SendUserStatsPacketRequest,
     ) -> Result<ExecSuccess, BanchoStateError> {
-        Ok(self.client().send_user_stats_packet(request).await?.into_inner())
+        Ok(self
+            .0
+            .call(
+                |mut c| async move { c.send_user_stats_packet(request).await },
+            )
+            .await?
+            .into_inner())
     }
 }
 
@@ -204,8 +428,10 @@ impl BatchSendUserStatsPacket for BanchoStateServiceRemote {
         request: BatchSendUserStatsPacketRequest,
     ) -> Result<ExecSuccess, BanchoStateError> {
         Ok(self
-            .client()
-            .batch_send_user_stats_packet(request)
+            .0
+            .call(|mut c| async move {
+                c.batch_send_user_stats_packet(request).await
+            })
             .await?
             .into_inner())
     }
@@ -217,7 +443,11 @@ impl SendAllPresences for BanchoStateServiceRemote {
         &self,
         request: SendAllPresencesRequest,
     ) -> Result<ExecSuccess, BanchoStateError> {
-        Ok(self.client().send_all_presences(request).await?.into_inner())
+        Ok(self
+            .0
+            .call(|mut c| async move { c.send_all_presences(request).await })
+            .await?
+            .into_inner())
     }
 }
 
@@ -227,7 +457,11 @@ impl BatchSendPresences for BanchoStateServiceRemote {
         &self,
         request: BatchSendPresencesRequest,
     ) -> Result<ExecSuccess, BanchoStateError> {
-        Ok(self.client().batch_send_presences(request).await?.into_inner())
+        Ok(self
+            .0
+            .call(|mut c| async move { c.batch_send_presences(request).await })
+            .await?
+            .into_inner())
     }
 }
 
@@ -237,7 +471,27 @@ impl UpdatePresenceFilter for BanchoStateServiceRemote {
         &self,
         request: UpdatePresenceFilterRequest,
     ) -> Result<ExecSuccess, BanchoStateError> {
-        Ok(self.client().update_presence_filter(request).await?.into_inner())
+        Ok(self
+            .0
+            .call(
+                |mut c| async move { c.update_presence_filter(request).await },
+            )
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl UpdateSilenceEnd for BanchoStateServiceRemote {
+    async fn update_silence_end(
+        &self,
+        request: UpdateSilenceEndRequest,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        Ok(self
+            .0
+            .call(|mut c| async move { c.update_silence_end(request).await })
+            .await?
+            .into_inner())
     }
 }
 
@@ -247,6 +501,198 @@ impl UpdateUserBanchoStatus for BanchoStateServiceRemote {
         &self,
         request: UpdateUserBanchoStatusRequest,
     ) -> Result<ExecSuccess, BanchoStateError> {
-        Ok(self.client().update_user_bancho_status(request).await?.into_inner())
+        Ok(
+            self.0
+                .call(|mut c| async move {
+                    c.update_user_bancho_status(request).await
+                })
+                .await?
+                .into_inner(),
+        )
+    }
+}
+
+#[async_trait]
+impl JoinLobby for BanchoStateServiceRemote {
+    async fn join_lobby(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        Ok(self
+            .0
+            .call(|mut c| async move {
+                c.join_lobby(Into::<RawUserQuery>::into(query)).await
+            })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl PartLobby for BanchoStateServiceRemote {
+    async fn part_lobby(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        Ok(self
+            .0
+            .call(|mut c| async move {
+                c.part_lobby(Into::<RawUserQuery>::into(query)).await
+            })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl CreateMatch for BanchoStateServiceRemote {
+    async fn create_match(
+        &self,
+        request: CreateMatchRequest,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        Ok(self
+            .0
+            .call(|mut c| async move { c.create_match(request).await })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl JoinMatch for BanchoStateServiceRemote {
+    async fn join_match(
+        &self,
+        request: JoinMatchRequest,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        Ok(self
+            .0
+            .call(|mut c| async move { c.join_match(request).await })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl PartMatch for BanchoStateServiceRemote {
+    async fn part_match(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        Ok(self
+            .0
+            .call(|mut c| async move {
+                c.part_match(Into::<RawUserQuery>::into(query)).await
+            })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl StartMatch for BanchoStateServiceRemote {
+    async fn start_match(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        Ok(self
+            .0
+            .call(|mut c| async move {
+                c.start_match(Into::<RawUserQuery>::into(query)).await
+            })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl MatchLoadComplete for BanchoStateServiceRemote {
+    async fn match_load_complete(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        Ok(self
+            .0
+            .call(|mut c| async move {
+                c.match_load_complete(Into::<RawUserQuery>::into(query)).await
+            })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl MatchPlayerComplete for BanchoStateServiceRemote {
+    async fn match_player_complete(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        Ok(self
+            .0
+            .call(|mut c| async move {
+                c.match_player_complete(Into::<RawUserQuery>::into(query)).await
+            })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl RelayScoreFrame for BanchoStateServiceRemote {
+    async fn relay_score_frame(
+        &self,
+        request: RelayScoreFrameRequest,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        Ok(self
+            .0
+            .call(|mut c| async move { c.relay_score_frame(request).await })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl GetMatch for BanchoStateServiceRemote {
+    async fn get_match(
+        &self,
+        request: GetMatchRequest,
+    ) -> Result<GetMatchResponse, BanchoStateError> {
+        Ok(self
+            .0
+            .call_idempotent(|mut c| {
+                let request = request.clone();
+                async move { c.get_match(request).await }
+            })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl StartSpectating for BanchoStateServiceRemote {
+    async fn start_spectating(
+        &self,
+        request: StartSpectatingRequest,
+    ) -> Result<StartSpectatingResponse, BanchoStateError> {
+        Ok(self
+            .0
+            .call(|mut c| async move { c.start_spectating(request).await })
+            .await?
+            .into_inner())
+    }
+}
+
+#[async_trait]
+impl StopSpectating for BanchoStateServiceRemote {
+    async fn stop_spectating(
+        &self,
+        query: UserQuery,
+    ) -> Result<StopSpectatingResponse, BanchoStateError> {
+        Ok(self
+            .0
+            .call(|mut c| async move {
+                c.stop_spectating(Into::<RawUserQuery>::into(query)).await
+            })
+            .await?
+            .into_inner())
     }
 }
@@ -1,11 +1,13 @@
 pub mod background;
 pub mod bancho_state;
 pub mod bancho_state_remote;
+pub mod session_deltas;
 pub mod traits;
 pub mod user_sessions;
 
 pub use background::*;
 pub use bancho_state::*;
 pub use bancho_state_remote::*;
+pub use session_deltas::*;
 pub use traits::*;
 pub use user_sessions::*;
@@ -0,0 +1,388 @@
+use crate::*;
+use chrono::{DateTime, Utc};
+use clap_serde_derive::ClapSerde;
+use peace_cfg::{ConfigError, ConfigErrors, ValidateConfig};
+use peace_snapshot::SnapshotType;
+use peace_unique_id::Ulid;
+use std::{io, path::Path};
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+
+#[derive(Debug, Clone, Parser, ClapSerde, Serialize, Deserialize)]
+pub struct CliBanchoStateIncrementalSnapshotConfigs {
+    /// Record session changes into a delta log next to the snapshot file,
+    /// a crash can then be recovered by replaying it on the latest base
+    /// snapshot.
+    #[arg(long)]
+    pub bancho_state_incremental_snapshot: bool,
+
+    #[default(5)]
+    #[arg(long, default_value = "5")]
+    pub bancho_state_incremental_snapshot_interval_secs: u64,
+
+    /// Number of deltas after which a new full base snapshot is written
+    /// and the delta log is rolled.
+    #[default(10000)]
+    #[arg(long, default_value = "10000")]
+    pub bancho_state_incremental_snapshot_max_deltas: usize,
+}
+
+impl ValidateConfig for CliBanchoStateIncrementalSnapshotConfigs {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        if self.bancho_state_incremental_snapshot {
+            errors.check(
+                self.bancho_state_incremental_snapshot_interval_secs > 0,
+                "bancho_state_incremental_snapshot_interval_secs",
+                "must be greater than 0",
+            );
+            errors.check(
+                self.bancho_state_incremental_snapshot_max_deltas > 0,
+                "bancho_state_incremental_snapshot_max_deltas",
+                "must be greater than 0",
+            );
+        }
+
+        errors.into_result()
+    }
+}
+
+impl CliBanchoStateIncrementalSnapshotConfigs {
+    /// Path of the delta log of a base snapshot.
+    #[inline]
+    pub fn deltas_file_path(snapshot_path: &str) -> String {
+        format!("{snapshot_path}.deltas")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SessionDelta {
+    Create(BanchoSessionData),
+    Delete {
+        session_id: Ulid,
+    },
+    Rename {
+        session_id: Ulid,
+        username: String,
+        username_unicode: Option<String>,
+    },
+    UpdateStatus {
+        session_id: Ulid,
+        bancho_status: BanchoStatus,
+    },
+    UpdateSilenceEnd {
+        session_id: Ulid,
+        silence_end: i64,
+    },
+    Clear,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionDeltaRecord {
+    pub time: DateTime<Utc>,
+    pub delta: SessionDelta,
+}
+
+/// Append-only log of session changes, written as json lines.
+///
+/// Deltas are buffered in memory and flushed to the file periodically by
+/// the background service.
+#[derive(Debug)]
+pub struct SessionDeltaLog {
+    pub path: String,
+    pub base_snapshot_path: String,
+    pub base_snapshot_type: SnapshotType,
+    pub max_deltas: usize,
+    pending: Mutex<Vec<SessionDeltaRecord>>,
+    /// Deltas written into the file, also serializes the file writes.
+    written: Mutex<usize>,
+}
+
+impl SessionDeltaLog {
+    #[inline]
+    pub fn new(
+        path: String,
+        base_snapshot_path: String,
+        base_snapshot_type: SnapshotType,
+        max_deltas: usize,
+    ) -> Self {
+        Self {
+            path,
+            base_snapshot_path,
+            base_snapshot_type,
+            max_deltas,
+            pending: Mutex::default(),
+            written: Mutex::default(),
+        }
+    }
+
+    #[inline]
+    pub async fn push(&self, delta: SessionDelta) {
+        self.pending
+            .lock()
+            .await
+            .push(SessionDeltaRecord { time: Utc::now(), delta });
+    }
+
+    /// Append the pending deltas to the file, returns the number of deltas
+    /// written since the last roll.
+    pub async fn flush(&self) -> io::Result<usize> {
+        let mut written = self.written.lock().await;
+
+        let records = std::mem::take(&mut *self.pending.lock().await);
+        if records.is_empty() {
+            return Ok(*written);
+        }
+
+        if let Some(parent) = Path::new(&self.path).parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+
+        file.write_all(&encode_records(&records)?).await?;
+        file.flush().await?;
+
+        *written += records.len();
+
+        Ok(*written)
+    }
+
+    /// Drop the deltas already contained in a base snapshot created at
+    /// `base_time`, invalid lines are dropped as well.
+    pub async fn roll(&self, base_time: DateTime<Utc>) -> io::Result<()> {
+        let mut written = self.written.lock().await;
+
+        let kept = read_records(&self.path)
+            .await?
+            .into_iter()
+            .filter(|r| r.time >= base_time)
+            .collect::<Vec<_>>();
+
+        fs::write(&self.path, encode_records(&kept)?).await?;
+
+        *written = kept.len();
+
+        Ok(())
+    }
+
+    #[inline]
+    pub async fn read_all(&self) -> io::Result<Vec<SessionDeltaRecord>> {
+        read_records(&self.path).await
+    }
+}
+
+fn encode_records(records: &[SessionDeltaRecord]) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    for record in records {
+        serde_json::to_writer(&mut buf, record)?;
+        buf.push(b'\n');
+    }
+
+    Ok(buf)
+}
+
+async fn read_records(path: &str) -> io::Result<Vec<SessionDeltaRecord>> {
+    let content = match fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Ok(Vec::new())
+        },
+        Err(err) => return Err(err),
+    };
+
+    Ok(content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(err) => {
+                // the last line may be partially written before a crash
+                warn!("[BanchoStateSnapshot] Skipped invalid delta: {err}");
+                None
+            },
+        })
+        .collect())
+}
+
+impl BanchoStateServiceSnapshot {
+    /// An empty snapshot to replay deltas on, when there is no base.
+    #[inline]
+    pub fn empty() -> Self {
+        Self {
+            user_sessions: Vec::new(),
+            notify_queue: Vec::new(),
+            create_time: DateTime::<Utc>::MIN_UTC,
+        }
+    }
+
+    #[inline]
+    fn session_mut(
+        &mut self,
+        session_id: Ulid,
+    ) -> Option<&mut BanchoSessionData> {
+        self.user_sessions.iter_mut().find(|s| s.base.id == session_id)
+    }
+
+    /// Replay the deltas recorded after this snapshot was created, returns
+    /// the number of deltas applied.
+    pub fn apply_deltas(
+        &mut self,
+        records: impl IntoIterator<Item = SessionDeltaRecord>,
+    ) -> usize {
+        let mut applied = 0;
+
+        for SessionDeltaRecord { time, delta } in records {
+            if time < self.create_time {
+                continue;
+            }
+
+            match delta {
+                SessionDelta::Create(session) => {
+                    // a user has only one session
+                    self.user_sessions
+                        .retain(|s| s.base.user_id != session.base.user_id);
+                    self.user_sessions.push(session);
+                },
+                SessionDelta::Delete { session_id } => {
+                    self.user_sessions.retain(|s| s.base.id != session_id);
+                },
+                SessionDelta::Rename {
+                    session_id,
+                    username,
+                    username_unicode,
+                } => {
+                    if let Some(s) = self.session_mut(session_id) {
+                        s.base.username = username;
+                        s.base.username_unicode = username_unicode;
+                    }
+                },
+                SessionDelta::UpdateStatus { session_id, bancho_status } => {
+                    if let Some(s) = self.session_mut(session_id) {
+                        s.extends.bancho_status = bancho_status;
+                    }
+                },
+                SessionDelta::UpdateSilenceEnd { session_id, silence_end } => {
+                    if let Some(s) = self.session_mut(session_id) {
+                        s.extends.silence_end = silence_end;
+                    }
+                },
+                SessionDelta::Clear => self.user_sessions.clear(),
+            }
+
+            self.create_time = time;
+            applied += 1;
+        }
+
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session_data(user_id: i32) -> BanchoSessionData {
+        let mut data = BanchoSessionData::default();
+        data.base.id = Ulid::new();
+        data.base.user_id = user_id;
+        data.base.username = format!("user{user_id}");
+        data
+    }
+
+    fn record(delta: SessionDelta) -> SessionDeltaRecord {
+        SessionDeltaRecord { time: Utc::now(), delta }
+    }
+
+    #[test]
+    fn test_apply_deltas() {
+        let a = session_data(1);
+        let b = session_data(2);
+        let b_id = b.base.id;
+
+        let mut snapshot = BanchoStateServiceSnapshot::empty();
+        let applied = snapshot.apply_deltas([
+            record(SessionDelta::Create(a.clone())),
+            record(SessionDelta::Create(b)),
+            record(SessionDelta::Rename {
+                session_id: b_id,
+                username: "renamed".into(),
+                username_unicode: None,
+            }),
+            record(SessionDelta::UpdateSilenceEnd {
+                session_id: b_id,
+                silence_end: 1_700_000_000,
+            }),
+            record(SessionDelta::Delete { session_id: a.base.id }),
+        ]);
+
+        assert_eq!(applied, 5);
+        assert_eq!(snapshot.user_sessions.len(), 1);
+        assert_eq!(snapshot.user_sessions[0].base.id, b_id);
+        assert_eq!(snapshot.user_sessions[0].base.username, "renamed");
+        assert_eq!(
+            snapshot.user_sessions[0].extends.silence_end,
+            1_700_000_000
+        );
+
+        // deltas older than the base are already contained in it
+        let stale = SessionDeltaRecord {
+            time: snapshot.create_time - chrono::Duration::seconds(1),
+            delta: SessionDelta::Clear,
+        };
+        assert_eq!(snapshot.apply_deltas([stale]), 0);
+        assert_eq!(snapshot.user_sessions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_flush_and_roll() {
+        let path = std::env::temp_dir()
+            .join(format!("peace_session_deltas_{}", Ulid::new()))
+            .to_string_lossy()
+            .to_string();
+
+        let log = SessionDeltaLog::new(
+            path.clone(),
+            String::new(),
+            SnapshotType::Binary,
+            100,
+        );
+
+        log.push(SessionDelta::Create(session_data(1))).await;
+        log.push(SessionDelta::Create(session_data(2))).await;
+        assert_eq!(log.flush().await.unwrap(), 2);
+
+        // partially written line before a crash
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .await
+            .unwrap()
+            .write_all(b"{\"time\":")
+            .await
+            .unwrap();
+
+        // repaired on startup
+        log.roll(DateTime::<Utc>::MIN_UTC).await.unwrap();
+        assert_eq!(log.read_all().await.unwrap().len(), 2);
+
+        let base_time = Utc::now();
+        log.push(SessionDelta::Clear).await;
+        assert_eq!(log.flush().await.unwrap(), 3);
+
+        let mut snapshot = BanchoStateServiceSnapshot::empty();
+        snapshot.apply_deltas(log.read_all().await.unwrap());
+        assert!(snapshot.user_sessions.is_empty());
+
+        log.roll(base_time).await.unwrap();
+        let records = log.read_all().await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(matches!(records[0].delta, SessionDelta::Clear));
+
+        fs::remove_file(&path).await.unwrap();
+    }
+}
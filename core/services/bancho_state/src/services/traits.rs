@@ -9,7 +9,15 @@ use pb_base::ExecSuccess;
 use peace_message_queue::{MessageData, MessageQueue};
 use peace_snapshot::{CreateSnapshot, SaveSnapshotTo};
 use peace_unique_id::Ulid;
-use std::sync::Arc;
+use std::{
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::Notify;
 use tools::async_collections::{
     BackgroundTask, BackgroundTaskError, CommonRecycleBackgroundTaskConfig,
     LoopBackgroundTaskConfig,
@@ -27,7 +35,7 @@ pub type DynUserSessionsService = Arc<dyn UserSessionsService + Send + Sync>;
 
 #[async_trait]
 pub trait BanchoStateBackgroundService:
-    UserSessionsCleaner + NotifyMessagesCleaner
+    UserSessionsCleaner + NotifyMessagesCleaner + IncrementalSnapshotWriter
 {
     fn start_all(&self, configs: BanchoStateBackgroundServiceConfigs);
 }
@@ -55,12 +63,47 @@ pub trait NotifyMessagesCleaner {
     ) -> Result<Option<Arc<BackgroundTask>>, BackgroundTaskError>;
 }
 
+#[async_trait]
+pub trait IncrementalSnapshotWriter {
+    fn start_incremental_snapshot(&self, config: Arc<LoopBackgroundTaskConfig>);
+
+    fn stop_incremental_snapshot(
+        &self,
+    ) -> Result<Option<Arc<BackgroundTask>>, BackgroundTaskError>;
+}
+
 pub trait UserSessionsStore {
     fn user_sessions(&self) -> &Arc<UserSessions>;
+
+    /// Delta log of session changes, only exists when incremental
+    /// snapshots are enabled.
+    #[inline]
+    fn session_deltas(&self) -> Option<&Arc<SessionDeltaLog>> {
+        None
+    }
+
+    /// Whether presences are sent without the coordinates of the users,
+    /// see [`BanchoSession::presence_only_packet`].
+    #[inline]
+    fn hide_presence_location(&self) -> bool {
+        false
+    }
 }
 
 pub trait NotifyMessagesQueue {
     fn notify_queue(&self) -> &Arc<BanchoMessageQueue>;
+
+    /// Notified when messages are pushed into the notify queue, it wakes
+    /// every session waiting for its packets.
+    fn notify_pushed(&self) -> &Arc<Notify>;
+}
+
+pub trait MultiplayerStore {
+    fn multiplayer(&self) -> &Arc<Multiplayer>;
+}
+
+pub trait SpectatingStore {
+    fn spectating(&self) -> &Arc<Spectating>;
 }
 
 #[async_trait]
@@ -71,6 +114,9 @@ pub trait UserSessionsService:
     + UserSessionsExists
     + UserSessionsClear
     + UserSessionsCount
+    + UserSessionsRename
+    + UserSessionsPresence
+    + UserSessionsDrain
 {
 }
 
@@ -82,10 +128,18 @@ pub trait UserSessionsCount: UserSessionsStore {
 }
 
 #[async_trait]
-pub trait UserSessionsClear: UserSessionsStore {
+pub trait UserSessionsClear:
+    UserSessionsStore + MultiplayerStore + SpectatingStore
+{
     #[inline]
     async fn clear(&self) {
-        self.user_sessions().clear().await
+        self.user_sessions().clear().await;
+        self.multiplayer().lobby.write().await.clear();
+        self.spectating().clear().await;
+
+        if let Some(deltas) = self.session_deltas() {
+            deltas.push(SessionDelta::Clear).await;
+        }
     }
 }
 
@@ -95,20 +149,58 @@ pub trait UserSessionsGet: UserSessionsStore {
     async fn get(&self, query: &UserQuery) -> Option<Arc<BanchoSession>> {
         self.user_sessions().get(query).await
     }
+
+    #[inline]
+    async fn get_by_ip(&self, ip: &IpAddr) -> Vec<Arc<BanchoSession>> {
+        self.user_sessions().get_by_ip(ip).await
+    }
 }
 
 #[async_trait]
-pub trait UserSessionsDelete: UserSessionsStore + NotifyMessagesQueue {
+pub trait UserSessionsDelete:
+    UserSessionsStore + NotifyMessagesQueue + MultiplayerStore + SpectatingStore
+{
     #[inline]
     async fn delete(&self, query: &UserQuery) -> Option<Arc<BanchoSession>> {
         const LOG_TARGET: &str = "bancho_state::user_sessions::delete_session";
 
         let session = self.user_sessions().delete(query).await?;
 
+        if let Some(deltas) = self.session_deltas() {
+            deltas.push(SessionDelta::Delete { session_id: session.id }).await;
+        }
+
+        self.multiplayer().part_lobby(session.user_id).await;
+
+        let mut notifications =
+            self.multiplayer().leave_match(session.user_id).await;
+
+        // the spectators of the user are told by the logout packet
+        self.spectating().remove_host(session.user_id).await;
+
+        if let Some(removed) =
+            self.spectating().remove_spectator(session.user_id).await
+        {
+            notifications.extend(removed.notifications(session.user_id));
+        }
+
+        for (user_ids, packets) in notifications {
+            let packets = Packet::new_ptr(packets);
+            for user_id in user_ids {
+                if let Some(s) =
+                    self.user_sessions().get(&UserQuery::UserId(user_id)).await
+                {
+                    // not limited here, the queue is checked on the next push
+                    s.push_packet(packets.clone(), 0).await;
+                }
+            }
+        }
+
         self.notify_queue().write().await.push_message(
             bancho_packets::server::UserLogout::pack(session.user_id).into(),
             None,
         );
+        self.notify_pushed().notify_waiters();
 
         info!(
             target: LOG_TARGET,
@@ -145,6 +237,7 @@ pub trait UserSessionsCreate: UserSessionsStore + NotifyMessagesQueue {
             [session.user_id],
             Some(Arc::new(move |_| weak.upgrade().is_some())),
         );
+        self.notify_pushed().notify_waiters();
 
         let online_users = {
             self.user_sessions()
@@ -161,7 +254,8 @@ pub trait UserSessionsCreate: UserSessionsStore + NotifyMessagesQueue {
             presence_shard_count += 1
         };
 
-        let session_info = session.user_info_packets();
+        let session_info =
+            session.user_info_packets(self.hide_presence_location());
 
         let pre_alloc_size = session_info.len()
             + (9 + presence_shard_count * PRESENCE_SHARD_SIZE * 4);
@@ -178,6 +272,12 @@ pub trait UserSessionsCreate: UserSessionsStore + NotifyMessagesQueue {
 
         session.extends.packets_queue.enqueue_packets(pending_packets).await;
 
+        if let Some(deltas) = self.session_deltas() {
+            deltas
+                .push(SessionDelta::Create(session.create_snapshot().await))
+                .await;
+        }
+
         info!(
             target: LOG_TARGET,
             "Session created: {} [{}] ({})",
@@ -190,6 +290,142 @@ pub trait UserSessionsCreate: UserSessionsStore + NotifyMessagesQueue {
     }
 }
 
+#[async_trait]
+pub trait UserSessionsPresence:
+    UserSessionsStore + NotifyMessagesQueue
+{
+    /// Broadcast the presence of the session, for the changes which only
+    /// affect the presence (name, region, privileges).
+    ///
+    /// Status and stats changes are sent with
+    /// [`BanchoSession::user_stats_packet`] instead.
+    #[inline]
+    async fn broadcast_presence(&self, session: &Arc<BanchoSession>) {
+        let weak = Arc::downgrade(session);
+
+        self.notify_queue().write().await.push_message(
+            session.presence_only_packet(self.hide_presence_location()).into(),
+            Some(Arc::new(move |_| weak.upgrade().is_some())),
+        );
+        self.notify_pushed().notify_waiters();
+    }
+}
+
+#[async_trait]
+pub trait UserSessionsRename: UserSessionsPresence {
+    /// Rename an online user, the new presence will be broadcast to the
+    /// other sessions.
+    #[inline]
+    async fn rename(
+        &self,
+        user_id: i32,
+        username: String,
+        username_unicode: Option<String>,
+    ) -> Option<Arc<BanchoSession>> {
+        const LOG_TARGET: &str = "bancho_state::user_sessions::rename_session";
+
+        let old_username = self
+            .user_sessions()
+            .get(&UserQuery::UserId(user_id))
+            .await
+            .map(|s| s.username.load().to_string());
+
+        let session = self
+            .user_sessions()
+            .rename(user_id, username, username_unicode)
+            .await?;
+
+        if let Some(deltas) = self.session_deltas() {
+            deltas
+                .push(SessionDelta::Rename {
+                    session_id: session.id,
+                    username: session.username.load().to_string(),
+                    username_unicode: session
+                        .username_unicode
+                        .load()
+                        .as_deref()
+                        .map(|s| s.to_string()),
+                })
+                .await;
+        }
+
+        self.broadcast_presence(&session).await;
+
+        info!(
+            target: LOG_TARGET,
+            "Session renamed: {} -> {} [{}] ({})",
+            old_username.unwrap_or_default(),
+            session.username.load(),
+            session.user_id,
+            session.id
+        );
+
+        Some(session)
+    }
+}
+
+#[async_trait]
+pub trait UserSessionsDrain: UserSessionsStore {
+    /// Set once the service starts draining, no session is created after.
+    fn logins_closed(&self) -> &AtomicBool;
+
+    /// Refuse the new logins, the online sessions are kept.
+    #[inline]
+    fn close_logins(&self) {
+        self.logins_closed().store(true, Ordering::SeqCst);
+    }
+
+    #[inline]
+    fn is_logins_closed(&self) -> bool {
+        self.logins_closed().load(Ordering::SeqCst)
+    }
+
+    /// Number of packets queued in all sessions.
+    async fn queued_packets(&self) -> usize {
+        let sessions = {
+            self.user_sessions()
+                .read()
+                .await
+                .values()
+                .cloned()
+                .collect::<Vec<Arc<BanchoSession>>>()
+        };
+
+        let mut queued = 0;
+        for session in sessions {
+            queued += session.extends.packets_queue.queued_packets().await;
+        }
+
+        queued
+    }
+
+    /// Wait until the clients dequeued all of their pending packets, used
+    /// to drain the service before shutdown.
+    ///
+    /// The logins are closed first, so that no new session queues packets
+    /// while draining.
+    async fn wait_packets_drained(&self, check_interval: Duration) {
+        const LOG_TARGET: &str = "bancho_state::user_sessions::drain";
+
+        self.close_logins();
+
+        loop {
+            let queued = self.queued_packets().await;
+            if queued == 0 {
+                break;
+            }
+
+            debug!(
+                target: LOG_TARGET,
+                "Waiting for {queued} queued packets to be dequeued..."
+            );
+            tokio::time::sleep(check_interval).await;
+        }
+
+        info!(target: LOG_TARGET, "All packets queues drained");
+    }
+}
+
 #[async_trait]
 pub trait UserSessionsExists: UserSessionsStore {
     #[inline]
@@ -202,21 +438,43 @@ pub trait UserSessionsExists: UserSessionsStore {
 pub trait BanchoStateService:
     UpdateUserBanchoStatus
     + UpdatePresenceFilter
+    + UpdateSilenceEnd
     + BatchSendPresences
     + SendAllPresences
     + BatchSendUserStatsPacket
     + SendUserStatsPacket
     + GetAllSessions
+    + GetSessionsPage
+    + GetSessionsStats
+    + GetOnlineSummary
     + GetUserSessionWithFields
+    + GetUserSessionsWithFields
     + GetUserSession
+    + GetUserSessionPresence
     + IsUserOnline
     + CheckUserToken
     + DeleteUserSession
+    + KickUsersByIp
     + CreateUserSession
     + DequeueBanchoPackets
+    + RecordPackets
+    + GetPacketHistory
     + BatchEnqueueBanchoPackets
     + EnqueueBanchoPackets
     + BroadcastBanchoPackets
+    + BroadcastAnnouncement
+    + JoinLobby
+    + PartLobby
+    + CreateMatch
+    + JoinMatch
+    + PartMatch
+    + StartMatch
+    + MatchLoadComplete
+    + MatchPlayerComplete
+    + RelayScoreFrame
+    + GetMatch
+    + StartSpectating
+    + StopSpectating
     + CreateSnapshot<BanchoStateServiceSnapshot>
     + SaveSnapshotTo<BanchoStateServiceSnapshot>
     + ServiceSnapshot
@@ -239,6 +497,16 @@ pub trait UpdatePresenceFilter {
     ) -> Result<ExecSuccess, BanchoStateError>;
 }
 
+#[async_trait]
+pub trait UpdateSilenceEnd {
+    /// Silence the user until `silence_end` (unix secs), `0` lifts the
+    /// silence.
+    async fn update_silence_end(
+        &self,
+        request: UpdateSilenceEndRequest,
+    ) -> Result<ExecSuccess, BanchoStateError>;
+}
+
 #[async_trait]
 pub trait BatchSendPresences {
     async fn batch_send_presences(
@@ -273,11 +541,39 @@ pub trait SendUserStatsPacket {
 
 #[async_trait]
 pub trait GetAllSessions {
+    /// Warning: serializes every session once per index into a single
+    /// response, only suitable for small deployments. Use
+    /// [`GetSessionsPage`] instead on a busy server.
     async fn get_all_sessions(
         &self,
     ) -> Result<GetAllSessionsResponse, BanchoStateError>;
 }
 
+#[async_trait]
+pub trait GetSessionsPage {
+    async fn get_sessions_page(
+        &self,
+        request: GetSessionsPageRequest,
+    ) -> Result<GetSessionsPageResponse, BanchoStateError>;
+}
+
+#[async_trait]
+pub trait GetSessionsStats {
+    async fn get_sessions_stats(
+        &self,
+    ) -> Result<SessionsStats, BanchoStateError>;
+}
+
+#[async_trait]
+pub trait GetOnlineSummary {
+    /// Compact alternative to [`GetSessionsPage`], only the status of the
+    /// sessions is read, nothing is serialized.
+    async fn get_online_summary(
+        &self,
+        request: GetOnlineSummaryRequest,
+    ) -> Result<OnlineSummary, BanchoStateError>;
+}
+
 #[async_trait]
 pub trait GetUserSessionWithFields {
     async fn get_user_session_with_fields(
@@ -286,6 +582,14 @@ pub trait GetUserSessionWithFields {
     ) -> Result<GetUserSessionResponse, BanchoStateError>;
 }
 
+#[async_trait]
+pub trait GetUserSessionsWithFields {
+    async fn get_user_sessions_with_fields(
+        &self,
+        raw_queries: RawUserQueriesWithFields,
+    ) -> Result<GetUserSessionsResponse, BanchoStateError>;
+}
+
 #[async_trait]
 pub trait GetUserSession {
     async fn get_user_session(
@@ -294,6 +598,14 @@ pub trait GetUserSession {
     ) -> Result<GetUserSessionResponse, BanchoStateError>;
 }
 
+#[async_trait]
+pub trait GetUserSessionPresence {
+    async fn get_user_session_presence(
+        &self,
+        query: UserQuery,
+    ) -> Result<GetUserSessionPresenceResponse, BanchoStateError>;
+}
+
 #[async_trait]
 pub trait IsUserOnline {
     async fn is_user_online(
@@ -318,6 +630,14 @@ pub trait DeleteUserSession {
     ) -> Result<ExecSuccess, BanchoStateError>;
 }
 
+#[async_trait]
+pub trait KickUsersByIp {
+    async fn kick_users_by_ip(
+        &self,
+        request: KickUsersByIpRequest,
+    ) -> Result<KickUsersByIpResponse, BanchoStateError>;
+}
+
 #[async_trait]
 pub trait CreateUserSession {
     async fn create_user_session(
@@ -334,6 +654,22 @@ pub trait DequeueBanchoPackets {
     ) -> Result<BanchoPackets, BanchoStateError>;
 }
 
+#[async_trait]
+pub trait RecordPackets {
+    async fn record_packets(
+        &self,
+        request: RecordPacketsRequest,
+    ) -> Result<ExecSuccess, BanchoStateError>;
+}
+
+#[async_trait]
+pub trait GetPacketHistory {
+    async fn get_packet_history(
+        &self,
+        query: UserQuery,
+    ) -> Result<PacketHistoryResponse, BanchoStateError>;
+}
+
 #[async_trait]
 pub trait BatchEnqueueBanchoPackets {
     async fn batch_enqueue_bancho_packets(
@@ -348,6 +684,20 @@ pub trait EnqueueBanchoPackets {
         &self,
         request: EnqueueBanchoPacketsRequest,
     ) -> Result<ExecSuccess, BanchoStateError>;
+
+    /// Enqueue already packed server packets to the user, returns
+    /// [`BanchoStateError::SessionNotExists`] if the user is offline.
+    async fn enqueue_raw(
+        &self,
+        query: UserQuery,
+        packets: Vec<u8>,
+    ) -> Result<ExecSuccess, BanchoStateError> {
+        self.enqueue_bancho_packets(EnqueueBanchoPacketsRequest {
+            user_query: Some(query.into()),
+            packets,
+        })
+        .await
+    }
 }
 
 #[async_trait]
@@ -357,3 +707,107 @@ pub trait BroadcastBanchoPackets {
         request: BroadcastBanchoPacketsRequest,
     ) -> Result<ExecSuccess, BanchoStateError>;
 }
+
+#[async_trait]
+pub trait BroadcastAnnouncement {
+    async fn broadcast_announcement(
+        &self,
+        request: BroadcastAnnouncementRequest,
+    ) -> Result<BroadcastAnnouncementResponse, BanchoStateError>;
+}
+
+#[async_trait]
+pub trait JoinLobby {
+    async fn join_lobby(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError>;
+}
+
+#[async_trait]
+pub trait PartLobby {
+    async fn part_lobby(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError>;
+}
+
+#[async_trait]
+pub trait CreateMatch {
+    async fn create_match(
+        &self,
+        request: CreateMatchRequest,
+    ) -> Result<ExecSuccess, BanchoStateError>;
+}
+
+#[async_trait]
+pub trait JoinMatch {
+    async fn join_match(
+        &self,
+        request: JoinMatchRequest,
+    ) -> Result<ExecSuccess, BanchoStateError>;
+}
+
+#[async_trait]
+pub trait PartMatch {
+    async fn part_match(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError>;
+}
+
+#[async_trait]
+pub trait StartMatch {
+    async fn start_match(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError>;
+}
+
+#[async_trait]
+pub trait MatchLoadComplete {
+    async fn match_load_complete(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError>;
+}
+
+#[async_trait]
+pub trait MatchPlayerComplete {
+    async fn match_player_complete(
+        &self,
+        query: UserQuery,
+    ) -> Result<ExecSuccess, BanchoStateError>;
+}
+
+#[async_trait]
+pub trait RelayScoreFrame {
+    async fn relay_score_frame(
+        &self,
+        request: RelayScoreFrameRequest,
+    ) -> Result<ExecSuccess, BanchoStateError>;
+}
+
+#[async_trait]
+pub trait GetMatch {
+    async fn get_match(
+        &self,
+        request: GetMatchRequest,
+    ) -> Result<GetMatchResponse, BanchoStateError>;
+}
+
+#[async_trait]
+pub trait StartSpectating {
+    async fn start_spectating(
+        &self,
+        request: StartSpectatingRequest,
+    ) -> Result<StartSpectatingResponse, BanchoStateError>;
+}
+
+#[async_trait]
+pub trait StopSpectating {
+    async fn stop_spectating(
+        &self,
+        query: UserQuery,
+    ) -> Result<StopSpectatingResponse, BanchoStateError>;
+}
@@ -1,14 +1,23 @@
 use super::traits::*;
-use crate::{BanchoSessionData, UserSessions};
+use crate::{
+    BanchoSessionData, Multiplayer, SessionDeltaLog, Spectating, UserSessions,
+};
 use async_trait::async_trait;
 use infra_services::IntoService;
 use peace_snapshot::CreateSnapshot;
-use std::sync::Arc;
+use std::sync::{atomic::AtomicBool, Arc};
+use tokio::sync::Notify;
 
 #[derive(Debug, Clone)]
 pub struct UserSessionsServiceImpl {
     pub user_sessions: Arc<UserSessions>,
     pub notify_queue: Arc<BanchoMessageQueue>,
+    pub notify_pushed: Arc<Notify>,
+    pub session_deltas: Option<Arc<SessionDeltaLog>>,
+    pub multiplayer: Arc<Multiplayer>,
+    pub spectating: Arc<Spectating>,
+    pub hide_presence_location: bool,
+    pub logins_closed: Arc<AtomicBool>,
 }
 
 impl UserSessionsServiceImpl {
@@ -16,6 +25,24 @@ impl UserSessionsServiceImpl {
     pub fn new() -> Self {
         Self::default()
     }
+
+    #[inline]
+    pub fn with_session_deltas(
+        mut self,
+        session_deltas: Option<Arc<SessionDeltaLog>>,
+    ) -> Self {
+        self.session_deltas = session_deltas;
+        self
+    }
+
+    #[inline]
+    pub fn with_hide_presence_location(
+        mut self,
+        hide_presence_location: bool,
+    ) -> Self {
+        self.hide_presence_location = hide_presence_location;
+        self
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +66,12 @@ impl Default for UserSessionsServiceImpl {
         Self {
             user_sessions: Arc::new(UserSessions::new()),
             notify_queue: Arc::new(BanchoMessageQueue::default()),
+            notify_pushed: Arc::default(),
+            session_deltas: None,
+            multiplayer: Arc::new(Multiplayer::new()),
+            spectating: Arc::new(Spectating::new()),
+            hide_presence_location: false,
+            logins_closed: Arc::default(),
         }
     }
 }
@@ -55,6 +88,16 @@ impl UserSessionsStore for UserSessionsServiceImpl {
     fn user_sessions(&self) -> &Arc<UserSessions> {
         &self.user_sessions
     }
+
+    #[inline]
+    fn session_deltas(&self) -> Option<&Arc<SessionDeltaLog>> {
+        self.session_deltas.as_ref()
+    }
+
+    #[inline]
+    fn hide_presence_location(&self) -> bool {
+        self.hide_presence_location
+    }
 }
 
 impl NotifyMessagesQueue for UserSessionsServiceImpl {
@@ -62,6 +105,25 @@ impl NotifyMessagesQueue for UserSessionsServiceImpl {
     fn notify_queue(&self) -> &Arc<BanchoMessageQueue> {
         &self.notify_queue
     }
+
+    #[inline]
+    fn notify_pushed(&self) -> &Arc<Notify> {
+        &self.notify_pushed
+    }
+}
+
+impl MultiplayerStore for UserSessionsServiceImpl {
+    #[inline]
+    fn multiplayer(&self) -> &Arc<Multiplayer> {
+        &self.multiplayer
+    }
+}
+
+impl SpectatingStore for UserSessionsServiceImpl {
+    #[inline]
+    fn spectating(&self) -> &Arc<Spectating> {
+        &self.spectating
+    }
 }
 
 #[async_trait]
@@ -82,5 +144,124 @@ impl UserSessionsCreate for UserSessionsServiceImpl {}
 #[async_trait]
 impl UserSessionsExists for UserSessionsServiceImpl {}
 
+#[async_trait]
+impl UserSessionsPresence for UserSessionsServiceImpl {}
+
+#[async_trait]
+impl UserSessionsRename for UserSessionsServiceImpl {}
+
+#[async_trait]
+impl UserSessionsDrain for UserSessionsServiceImpl {
+    #[inline]
+    fn logins_closed(&self) -> &AtomicBool {
+        &self.logins_closed
+    }
+}
+
 #[async_trait]
 impl UserSessionsService for UserSessionsServiceImpl {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BanchoSession;
+    use infra_packets::Packet;
+    use infra_users::CreateSessionDto;
+    use pb_bancho_state::UserQuery;
+    use std::time::Duration;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_packets_drained_within_grace_period() {
+        const GRACE_PERIOD: Duration = Duration::from_secs(3);
+
+        let service = UserSessionsServiceImpl::new();
+        let session = service
+            .user_sessions
+            .create(
+                BanchoSession::new(CreateSessionDto {
+                    user_id: 1000,
+                    username: "user1000".into(),
+                    ..Default::default()
+                })
+                .into(),
+            )
+            .await;
+        session.push_packet(Packet::new(vec![1, 2, 3]), 0).await;
+
+        // Not polled, the grace period elapses
+        assert!(tokio::time::timeout(
+            GRACE_PERIOD,
+            service.wait_packets_drained(Duration::from_millis(500))
+        )
+        .await
+        .is_err());
+
+        // The client polls its queue within the grace period
+        tokio::spawn({
+            let session = session.clone();
+            async move {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                session.extends.packets_queue.dequeue_all_packets(None).await;
+            }
+        });
+
+        assert!(tokio::time::timeout(
+            GRACE_PERIOD,
+            service.wait_packets_drained(Duration::from_millis(500))
+        )
+        .await
+        .is_ok());
+        assert_eq!(service.queued_packets().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_rename_notifies_presence() {
+        let service =
+            UserSessionsServiceImpl::new().with_hide_presence_location(true);
+        for user_id in [1000, 1001] {
+            service
+                .user_sessions
+                .create(
+                    BanchoSession::new(CreateSessionDto {
+                        user_id,
+                        username: format!("user{user_id}"),
+                        ..Default::default()
+                    })
+                    .into(),
+                )
+                .await;
+        }
+
+        let renamed =
+            service.rename(1000, "renamed".into(), None).await.unwrap();
+        assert_eq!(renamed.username(), "renamed");
+        assert!(service
+            .user_sessions
+            .get(&UserQuery::Username("user1000".into()))
+            .await
+            .is_none());
+
+        // taken names and offline users are not renamed or broadcast
+        assert!(service.rename(1000, "user1001".into(), None).await.is_none());
+        assert!(service.rename(2000, "other".into(), None).await.is_none());
+
+        let reader =
+            service.user_sessions.get(&UserQuery::UserId(1001)).await.unwrap();
+        let received = service
+            .notify_queue
+            .read()
+            .await
+            .receive_messages(
+                &reader.user_id,
+                &reader.extends.notify_index.load(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(received.messages.len(), 1);
+        assert_eq!(
+            &received.messages[0][..],
+            renamed.presence_only_packet(true)
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use crate::MatchNotifications;
+use bancho_packets::server;
+use std::collections::{BTreeSet, HashMap};
+use tokio::sync::RwLock;
+
+/// What happened after a spectator stopped watching a host.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SpectatorRemoved {
+    pub host_id: i32,
+    /// Spectators still watching the host.
+    pub spectators: Vec<i32>,
+}
+
+impl SpectatorRemoved {
+    /// Packets telling the host and the remaining spectators that the
+    /// spectator left.
+    pub fn notifications(&self, spectator_id: i32) -> MatchNotifications {
+        vec![
+            (vec![self.host_id], server::SpectatorLeft::pack(spectator_id)),
+            (
+                self.spectators.clone(),
+                server::FellowSpectatorLeft::pack(spectator_id),
+            ),
+        ]
+    }
+}
+
+/// What happened after a user started watching a host.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SpectatorAdded {
+    /// The other spectators of the host.
+    pub fellows: Vec<i32>,
+    /// The host previously watched by the user.
+    pub previous: Option<SpectatorRemoved>,
+}
+
+impl SpectatorAdded {
+    /// Packets telling the host and the other spectators that the spectator
+    /// joined, the spectator is told about the other spectators.
+    pub fn notifications(
+        &self,
+        spectator_id: i32,
+        host_id: i32,
+    ) -> MatchNotifications {
+        let mut notifications = self
+            .previous
+            .as_ref()
+            .map(|previous| previous.notifications(spectator_id))
+            .unwrap_or_default();
+
+        notifications
+            .push((vec![host_id], server::SpectatorJoined::pack(spectator_id)));
+        notifications.push((
+            self.fellows.clone(),
+            server::FellowSpectatorJoined::pack(spectator_id),
+        ));
+
+        if !self.fellows.is_empty() {
+            let mut fellows_joined = Vec::new();
+            for fellow_id in self.fellows.iter() {
+                fellows_joined
+                    .extend(server::FellowSpectatorJoined::pack(*fellow_id));
+            }
+            notifications.push((vec![spectator_id], fellows_joined));
+        }
+
+        notifications
+    }
+}
+
+#[derive(Debug, Default)]
+struct SpectatingIndexes {
+    /// host -> spectators
+    hosts: HashMap<i32, BTreeSet<i32>>,
+    /// spectator -> host
+    spectating: HashMap<i32, i32>,
+}
+
+impl SpectatingIndexes {
+    fn remove_spectator(
+        &mut self,
+        spectator_id: i32,
+    ) -> Option<SpectatorRemoved> {
+        let host_id = self.spectating.remove(&spectator_id)?;
+
+        let spectators = match self.hosts.get_mut(&host_id) {
+            Some(spectators) => {
+                spectators.remove(&spectator_id);
+                spectators.iter().copied().collect::<Vec<_>>()
+            },
+            None => vec![],
+        };
+
+        if spectators.is_empty() {
+            self.hosts.remove(&host_id);
+        }
+
+        Some(SpectatorRemoved { host_id, spectators })
+    }
+}
+
+/// Hosts being spectated and their spectators.
+#[derive(Debug, Default)]
+pub struct Spectating {
+    indexes: RwLock<SpectatingIndexes>,
+}
+
+impl Spectating {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start watching a host, the user stops watching their previous host.
+    /// Returns `None` if the user is already watching the host or tries to
+    /// watch themself.
+    pub async fn add_spectator(
+        &self,
+        spectator_id: i32,
+        host_id: i32,
+    ) -> Option<SpectatorAdded> {
+        if spectator_id == host_id {
+            return None;
+        }
+
+        let mut indexes = self.indexes.write().await;
+
+        if indexes.spectating.get(&spectator_id) == Some(&host_id) {
+            return None;
+        }
+
+        let previous = indexes.remove_spectator(spectator_id);
+
+        let spectators = indexes.hosts.entry(host_id).or_default();
+        let fellows = spectators.iter().copied().collect();
+        spectators.insert(spectator_id);
+
+        indexes.spectating.insert(spectator_id, host_id);
+
+        Some(SpectatorAdded { fellows, previous })
+    }
+
+    /// Stop watching the current host, returns `None` if the user is not
+    /// spectating.
+    #[inline]
+    pub async fn remove_spectator(
+        &self,
+        spectator_id: i32,
+    ) -> Option<SpectatorRemoved> {
+        self.indexes.write().await.remove_spectator(spectator_id)
+    }
+
+    /// Release the spectators of a host, returns them.
+    pub async fn remove_host(&self, host_id: i32) -> Vec<i32> {
+        let mut indexes = self.indexes.write().await;
+
+        let spectators = indexes.hosts.remove(&host_id).unwrap_or_default();
+        for spectator_id in spectators.iter() {
+            indexes.spectating.remove(spectator_id);
+        }
+
+        spectators.into_iter().collect()
+    }
+
+    #[inline]
+    pub async fn spectators(&self, host_id: i32) -> Vec<i32> {
+        self.indexes
+            .read()
+            .await
+            .hosts
+            .get(&host_id)
+            .map(|spectators| spectators.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    #[inline]
+    pub async fn host_of(&self, spectator_id: i32) -> Option<i32> {
+        self.indexes.read().await.spectating.get(&spectator_id).copied()
+    }
+
+    #[inline]
+    pub async fn clear(&self) {
+        let mut indexes = self.indexes.write().await;
+        indexes.hosts.clear();
+        indexes.spectating.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_spectating() {
+        let spectating = Spectating::new();
+
+        assert_eq!(
+            spectating.add_spectator(2, 1).await,
+            Some(SpectatorAdded { fellows: vec![], previous: None })
+        );
+        assert_eq!(
+            spectating.add_spectator(3, 1).await,
+            Some(SpectatorAdded { fellows: vec![2], previous: None })
+        );
+        assert_eq!(spectating.add_spectator(3, 1).await, None);
+        assert_eq!(spectating.add_spectator(1, 1).await, None);
+        assert_eq!(spectating.spectators(1).await, [2, 3]);
+
+        // switching host leaves the previous one
+        assert_eq!(
+            spectating.add_spectator(3, 4).await,
+            Some(SpectatorAdded {
+                fellows: vec![],
+                previous: Some(SpectatorRemoved {
+                    host_id: 1,
+                    spectators: vec![2]
+                }),
+            })
+        );
+        assert_eq!(spectating.host_of(3).await, Some(4));
+
+        assert_eq!(
+            spectating.remove_spectator(2).await,
+            Some(SpectatorRemoved { host_id: 1, spectators: vec![] })
+        );
+        assert_eq!(spectating.remove_spectator(2).await, None);
+        assert!(spectating.spectators(1).await.is_empty());
+
+        assert_eq!(spectating.remove_host(4).await, [3]);
+        assert_eq!(spectating.host_of(3).await, None);
+    }
+}
@@ -1,13 +1,18 @@
-use crate::{BanchoMessageData, BanchoMessageQueue};
+use crate::{
+    lock_order::{self, LockRank, RankedRwLock},
+    BanchoMessageData, BanchoMessageQueue,
+};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use clap_serde_derive::ClapSerde;
+use domain_bancho::BanchoPrivileges;
 use domain_chat::{ChannelType, Platform};
 use infra_packets::{Packet, PacketsQueue};
 use infra_users::{
-    BaseSession, BaseSessionData, CreateSessionDto, UserIndexes, UserStore,
+    BaseSession, BaseSessionData, CreateSessionDto, SessionIpAddr, UserIndexes,
+    UserStore,
 };
-use pb_chat::ChannelQuery;
+use pb_chat::{ChannelInfo, ChannelQuery};
 use peace_snapshot::{cli_snapshot_config, CreateSnapshot, SnapshotType};
 use peace_unique_id::Ulid;
 use std::{
@@ -17,7 +22,8 @@ use std::{
 };
 use tokio::sync::{Mutex, RwLock};
 use tools::atomic::{
-    Atomic, AtomicOperation, AtomicOption, AtomicValue, Usize, U32,
+    Atomic, AtomicOperation, AtomicOption, AtomicValue, Bool, Usize, I32, I64,
+    U32,
 };
 
 pub type SessionIndexes = UserIndexes<ChatSession>;
@@ -29,6 +35,19 @@ pub struct ChatSessionData {
     pub extends: ChatSessionExtendData,
 }
 
+/// [`ChatSessionData`] of the version 1 snapshots.
+#[derive(Debug, Deserialize)]
+pub struct ChatSessionDataV1 {
+    pub base: BaseSessionData,
+    pub extends: ChatSessionExtendDataV1,
+}
+
+impl From<ChatSessionDataV1> for ChatSessionData {
+    fn from(v1: ChatSessionDataV1) -> Self {
+        Self { base: v1.base, extends: v1.extends.into() }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct ChatSession {
     pub base: BaseSession,
@@ -65,7 +84,20 @@ impl DerefMut for ChatSession {
     }
 }
 
+impl SessionIpAddr for ChatSession {}
+
 impl ChatSession {
+    /// Channels joined by the user that still exist.
+    pub async fn joined_channels(&self) -> Vec<Arc<Channel>> {
+        self.extends
+            .joined_channels
+            .read()
+            .await
+            .values()
+            .filter_map(|joined| joined.ptr.load().upgrade())
+            .collect()
+    }
+
     pub fn new(
         CreateSessionDto {
             user_id,
@@ -150,12 +182,29 @@ impl CreateSnapshot<BanchoChatExtData> for BanchoChatExt {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct ChatSessionExtend {
     pub platforms: Atomic<Platform>,
     pub bancho_ext: AtomicOption<BanchoChatExt>,
-    pub joined_channels: RwLock<HashMap<u64, Arc<JoinedChannel>>>,
+    pub joined_channels: RankedRwLock<HashMap<u64, Arc<JoinedChannel>>>,
     pub channel_count: U32,
+    /// Unix timestamp (secs) of the end of the silence of the user, `0` if
+    /// the user is not silenced, checked on each message.
+    pub silence_end: I64,
+    /// Moderators bypass the privileges of the channels.
+    pub bancho_privileges: Atomic<BanchoPrivileges>,
+}
+
+impl Default for ChatSessionExtend {
+    fn default() -> Self {
+        Self::new(
+            Platform::default(),
+            None,
+            None,
+            0,
+            BanchoPrivileges::default(),
+        )
+    }
 }
 
 impl From<ChatSessionExtendData> for ChatSessionExtend {
@@ -164,8 +213,9 @@ impl From<ChatSessionExtendData> for ChatSessionExtend {
         Self {
             platforms: Platform::from(data.platforms).into(),
             bancho_ext: data.bancho_ext.map(|d| d.into()).into(),
-            joined_channels: RwLock::new(HashMap::from_iter(
-                data.joined_channels.into_iter().map(|j| {
+            joined_channels: RankedRwLock::new(
+                LockRank::JoinedChannels,
+                HashMap::from_iter(data.joined_channels.into_iter().map(|j| {
                     (
                         j.channel_id,
                         Arc::new(JoinedChannel {
@@ -174,9 +224,11 @@ impl From<ChatSessionExtendData> for ChatSessionExtend {
                             joined_time: j.joined_time,
                         }),
                     )
-                }),
-            )),
+                })),
+            ),
             channel_count,
+            silence_end: data.silence_end.into(),
+            bancho_privileges: data.bancho_privileges.into(),
         }
     }
 }
@@ -186,6 +238,8 @@ impl ChatSessionExtend {
         platforms: Platform,
         bancho_ext: Option<BanchoChatExt>,
         joined_channels: Option<HashMap<u64, Arc<JoinedChannel>>>,
+        silence_end: i64,
+        bancho_privileges: BanchoPrivileges,
     ) -> Self {
         let joined_channels = joined_channels.unwrap_or_default();
         let channel_count = joined_channels.len();
@@ -193,11 +247,23 @@ impl ChatSessionExtend {
         Self {
             platforms: platforms.into(),
             bancho_ext: bancho_ext.into(),
-            joined_channels: RwLock::new(joined_channels),
+            joined_channels: RankedRwLock::new(
+                LockRank::JoinedChannels,
+                joined_channels,
+            ),
             channel_count: U32::from(channel_count as u32),
+            silence_end: silence_end.into(),
+            bancho_privileges: bancho_privileges.into(),
         }
     }
 
+    /// Remaining secs of the silence of the user, `0` if it is expired at
+    /// `now` (unix secs).
+    #[inline]
+    pub fn silence_remaining(&self, now: i64) -> i32 {
+        (self.silence_end.val() - now).clamp(0, i32::MAX as i64) as i32
+    }
+
     pub async fn collect_joined_channels(&self) -> Vec<JoinedChannelData> {
         let mut channels =
             Vec::with_capacity(self.channel_count.val() as usize);
@@ -219,6 +285,32 @@ pub struct ChatSessionExtendData {
     pub platforms: i32,
     pub bancho_ext: Option<BanchoChatExtData>,
     pub joined_channels: Vec<JoinedChannelData>,
+    /// Missing from the json data written before version 2.
+    #[serde(default)]
+    pub silence_end: i64,
+    /// Missing from the json data written before the bancho privileges.
+    #[serde(default)]
+    pub bancho_privileges: BanchoPrivileges,
+}
+
+/// [`ChatSessionExtendData`] of the version 1 snapshots.
+#[derive(Debug, Deserialize)]
+pub struct ChatSessionExtendDataV1 {
+    pub platforms: i32,
+    pub bancho_ext: Option<BanchoChatExtData>,
+    pub joined_channels: Vec<JoinedChannelData>,
+}
+
+impl From<ChatSessionExtendDataV1> for ChatSessionExtendData {
+    fn from(v1: ChatSessionExtendDataV1) -> Self {
+        Self {
+            platforms: v1.platforms,
+            bancho_ext: v1.bancho_ext,
+            joined_channels: v1.joined_channels,
+            silence_end: 0,
+            bancho_privileges: BanchoPrivileges::default(),
+        }
+    }
 }
 
 #[async_trait]
@@ -231,6 +323,8 @@ impl CreateSnapshot<ChatSessionExtendData> for ChatSessionExtend {
                 None => None,
             },
             joined_channels: self.collect_joined_channels().await,
+            silence_end: self.silence_end.val(),
+            bancho_privileges: *self.bancho_privileges.load().as_ref(),
         }
     }
 }
@@ -294,9 +388,18 @@ pub struct Channel {
     pub name: Atomic<String>,
     pub channel_type: ChannelType,
     pub description: AtomicOption<String>,
-
-    pub users: Arc<RwLock<HashMap<i32, Option<Weak<ChatSession>>>>>,
-    pub user_count: U32,
+    /// Users are joined into the channel on login, loaded from the
+    /// `channels` table so it is not part of the snapshot.
+    pub auto_join: Bool,
+    /// Privilege level required to join the channel, loaded from the
+    /// `channel_privileges` table.
+    pub read_privilege: I32,
+    /// Privilege level required to send messages into the channel.
+    pub write_privilege: I32,
+
+    /// Members of the channel, their counts are computed from it with
+    /// [`Channel::session_count`].
+    pub users: Arc<RankedRwLock<HashMap<i32, Option<Weak<ChatSession>>>>>,
 
     pub min_msg_index: AtomicOption<Ulid>,
     pub message_queue: Arc<BanchoMessageQueue>,
@@ -305,6 +408,74 @@ pub struct Channel {
 }
 
 impl Channel {
+    /// Spectator channel ids are offset, so that they never collide with the
+    /// ids of the public channels.
+    pub const SPECTATOR_CHANNEL_ID_OFFSET: u64 = 1 << 32;
+
+    /// Moderators (see [`BanchoPrivileges::is_moderator`]) bypass the
+    /// privilege required by the channel.
+    #[inline]
+    pub fn can_read(&self, session: &ChatSession) -> bool {
+        session.extends.bancho_privileges.load().is_moderator()
+            || session.privileges.val() >= self.read_privilege.val()
+    }
+
+    #[inline]
+    pub fn can_write(&self, session: &ChatSession) -> bool {
+        session.extends.bancho_privileges.load().is_moderator()
+            || session.privileges.val() >= self.write_privilege.val()
+    }
+
+    #[inline]
+    pub fn spectator_channel_id(host_id: i32) -> u64 {
+        Self::SPECTATOR_CHANNEL_ID_OFFSET + host_id as u32 as u64
+    }
+
+    #[inline]
+    pub fn spectator_channel_name(host_id: i32) -> String {
+        format!("#spec_{host_id}")
+    }
+
+    /// The user being spectated, if this is a spectator channel.
+    #[inline]
+    pub fn spectator_host(&self) -> Option<i32> {
+        if self.channel_type != ChannelType::Spectaor {
+            return None;
+        }
+
+        self.id
+            .checked_sub(Self::SPECTATOR_CHANNEL_ID_OFFSET)
+            .map(|host_id| host_id as i32)
+    }
+
+    /// The name shown by the osu! client, which expects the current
+    /// spectator and multiplayer channels to be named `#spectator` and
+    /// `#multiplayer`.
+    #[inline]
+    pub fn display_name(&self) -> String {
+        match self.channel_type {
+            ChannelType::Spectaor => "#spectator".to_owned(),
+            ChannelType::Multiplayer => "#multiplayer".to_owned(),
+            _ => self.name.to_string(),
+        }
+    }
+
+    #[inline]
+    pub async fn channel_info(&self) -> ChannelInfo {
+        ChannelInfo {
+            id: self.id,
+            name: self.name.to_string(),
+            channel_type: self.channel_type as i32,
+            description: self
+                .description
+                .load()
+                .as_deref()
+                .map(|s| s.to_string()),
+            online_users: self.session_count(Platform::all()).await as u32,
+            users: None,
+        }
+    }
+
     #[inline]
     pub fn new(
         id: u64,
@@ -313,21 +484,21 @@ impl Channel {
         description: Option<String>,
         users: Option<Vec<i32>>,
     ) -> Self {
-        let (user_count, users) = match users {
-            Some(users) => (
-                users.len() as u32,
-                users.into_iter().map(|user_id| (user_id, None)).collect(),
-            ),
-            None => (0, HashMap::new()),
-        };
+        let users = users
+            .map(|users| {
+                users.into_iter().map(|user_id| (user_id, None)).collect()
+            })
+            .unwrap_or_default();
 
         Self {
             id,
             name: name.into(),
             channel_type,
             description: description.into(),
-            users: Arc::new(users.into()),
-            user_count: user_count.into(),
+            auto_join: Bool::new(false),
+            read_privilege: I32::new(0),
+            write_privilege: I32::new(0),
+            users: Arc::new(RankedRwLock::new(LockRank::ChannelUsers, users)),
             min_msg_index: None.into(),
             message_queue: Arc::new(BanchoMessageQueue::default()),
             created_at: Utc::now(),
@@ -335,15 +506,59 @@ impl Channel {
         }
     }
 
-    pub async fn join(session: &Arc<ChatSession>, channel: &Arc<Channel>) {
+    /// Wake the members waiting for their chat packets, except the sender.
+    pub async fn wake_members(&self, sender_id: i32) {
+        for session in self
+            .users
+            .read()
+            .await
+            .iter()
+            .filter(|(user_id, _)| **user_id != sender_id)
+            .filter_map(|(_, session)| session.as_ref()?.upgrade())
+        {
+            if let Some(bancho_ext) = session.extends.bancho_ext.load().as_ref()
+            {
+                bancho_ext.packets_queue.notify.notify_one();
+            }
+        }
+    }
+
+    /// Read cursor of a new member, so that only the last `backlog` queued
+    /// messages of the channel are received, then the new ones.
+    pub async fn backlog_index(&self, backlog: usize) -> Ulid {
+        let _rank = lock_order::acquire(LockRank::ChannelMessageQueue);
+        let message_queue = self.message_queue.read().await;
+        let mut message_ids = message_queue.messages.keys().rev();
+
+        if backlog == 0 {
+            // after the last message, even if it was queued in the current
+            // millisecond
+            return message_ids
+                .next()
+                .map(|last| Ulid::from(u128::from(*last) + 1))
+                .map_or_else(Ulid::default, |next| next.max(Ulid::default()));
+        }
+
+        message_ids.take(backlog).last().copied().unwrap_or_default()
+    }
+
+    /// Join the user into the channel, the user receives the last `backlog`
+    /// messages of the channel.
+    pub async fn join(
+        session: &Arc<ChatSession>,
+        channel: &Arc<Channel>,
+        backlog: usize,
+    ) {
         const LOG_TARGET: &str = "chat::channel::join";
 
-        channel.users.write().await.entry(session.user_id).or_insert_with(
-            || {
-                channel.user_count.add(1);
-                Some(Arc::downgrade(session))
-            },
-        );
+        channel
+            .users
+            .write()
+            .await
+            .entry(session.user_id)
+            .or_insert_with(|| Some(Arc::downgrade(session)));
+
+        let message_index = channel.backlog_index(backlog).await;
 
         session
             .extends
@@ -355,7 +570,7 @@ impl Channel {
                 session.extends.channel_count.add(1);
                 JoinedChannel {
                     ptr: Arc::downgrade(channel).into(),
-                    message_index: Ulid::default().into(),
+                    message_index: message_index.into(),
                     joined_time: Utc::now(),
                 }
                 .into()
@@ -382,9 +597,7 @@ impl Channel {
     pub async fn remove(session: &Arc<ChatSession>, channel: &Arc<Channel>) {
         const LOG_TARGET: &str = "chat::channel::remove";
 
-        if channel.users.write().await.remove(&session.user_id).is_some() {
-            channel.user_count.sub(1);
-        }
+        channel.users.write().await.remove(&session.user_id);
 
         if session
             .extends
@@ -415,32 +628,63 @@ impl Channel {
         );
     }
 
+    /// Ids of the members online on one of the given platforms, collected
+    /// under a single read lock of the member list.
+    pub async fn members(&self, platforms: Platform) -> Vec<i32> {
+        self.users
+            .read()
+            .await
+            .iter()
+            .filter(|(_, session)| {
+                session.as_ref().and_then(Weak::upgrade).map_or(
+                    false,
+                    |session| {
+                        session.extends.platforms.val().intersects(platforms)
+                    },
+                )
+            })
+            .map(|(user_id, _)| *user_id)
+            .collect()
+    }
+
+    /// Number of the members online on one of the given platforms, members
+    /// whose session is gone are not counted.
+    pub async fn session_count(&self, platforms: Platform) -> usize {
+        self.users
+            .read()
+            .await
+            .values()
+            .filter_map(|session| session.as_ref().and_then(Weak::upgrade))
+            .filter(|session| {
+                session.extends.platforms.val().intersects(platforms)
+            })
+            .count()
+    }
+
+    /// Info of the channel shown by the osu! client, only the members on
+    /// bancho are counted.
     #[inline]
-    pub fn info_packets(&self) -> Vec<u8> {
+    pub async fn info_packets(&self) -> Vec<u8> {
         bancho_packets::server::ChannelInfo::pack(
-            self.name.load().as_ref().into(),
+            self.display_name().into(),
             self.description
                 .load()
                 .as_deref()
                 .map(|s| s.to_owned())
                 .unwrap_or_default()
                 .into(),
-            self.user_count.val() as i16,
+            self.session_count(Platform::Bancho).await as i16,
         )
     }
 
     #[inline]
     pub fn join_packets(&self) -> Vec<u8> {
-        bancho_packets::server::ChannelJoin::pack(
-            self.name.load().as_ref().into(),
-        )
+        bancho_packets::server::ChannelJoin::pack(self.display_name().into())
     }
 
     #[inline]
     pub fn kick_packets(&self) -> Vec<u8> {
-        bancho_packets::server::ChannelKick::pack(
-            self.name.load().as_ref().into(),
-        )
+        bancho_packets::server::ChannelKick::pack(self.display_name().into())
     }
 }
 
@@ -577,6 +821,90 @@ impl Channels {
         removed
     }
 
+    /// Remove the user from the channel. Spectator channels are disposed
+    /// once their host or their last spectator left.
+    pub async fn leave_channel(
+        &self,
+        session: &Arc<ChatSession>,
+        channel: &Arc<Channel>,
+    ) {
+        Channel::remove(session, channel).await;
+        channel.updated_at.set(Utc::now().into());
+
+        if let Some(host_id) = channel.spectator_host() {
+            if host_id == session.user_id
+                || channel.users.read().await.len() <= 1
+            {
+                self.dispose_channel(channel).await;
+            }
+        }
+    }
+
+    /// Remove the channel and kick its remaining users.
+    pub async fn dispose_channel(&self, channel: &Arc<Channel>) {
+        const LOG_TARGET: &str = "chat::channel::dispose";
+
+        self.remove_channel(&ChannelQuery::ChannelId(channel.id)).await;
+
+        let users = channel
+            .users
+            .read()
+            .await
+            .values()
+            .filter_map(|session| session.as_ref().and_then(Weak::upgrade))
+            .collect::<Vec<_>>();
+
+        for session in users {
+            Channel::remove(&session, channel).await;
+        }
+
+        info!(
+            target: LOG_TARGET,
+            "Channel disposed: {}({})",
+            channel.name.load(),
+            channel.id
+        );
+    }
+
+    /// Join the user into the auto-join channels not joined yet and readable
+    /// by the user, returns the joined channels.
+    pub async fn join_auto_join_channels(
+        &self,
+        session: &Arc<ChatSession>,
+        backlog: usize,
+    ) -> Vec<Arc<Channel>> {
+        let channels = self
+            .read()
+            .await
+            .values()
+            .filter(|channel| {
+                channel.auto_join.is_true() && channel.can_read(session)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut joined = Vec::with_capacity(channels.len());
+        for channel in channels {
+            if session
+                .extends
+                .joined_channels
+                .read()
+                .await
+                .contains_key(&channel.id)
+            {
+                continue;
+            }
+
+            Channel::join(session, &channel, backlog).await;
+            // notify the new user count
+            channel.updated_at.set(Utc::now().into());
+
+            joined.push(channel);
+        }
+
+        joined
+    }
+
     #[inline]
     pub async fn get_channel(
         &self,
@@ -643,3 +971,390 @@ impl Channels {
 }
 
 cli_snapshot_config!(service: Chat);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(user_id: i32) -> Arc<ChatSession> {
+        session_with_privileges(user_id, 1, BanchoPrivileges::Normal)
+    }
+
+    fn session_with_privileges(
+        user_id: i32,
+        privileges: i32,
+        bancho_privileges: BanchoPrivileges,
+    ) -> Arc<ChatSession> {
+        Arc::new(ChatSession::new(CreateSessionDto {
+            user_id,
+            username: format!("user{user_id}"),
+            username_unicode: None,
+            privileges,
+            extends: ChatSessionExtend::new(
+                Platform::Bancho,
+                Some(BanchoChatExt::default()),
+                None,
+                0,
+                bancho_privileges,
+            ),
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_session_silence_survives_snapshot() {
+        let extends = ChatSessionExtend::new(
+            Platform::Bancho,
+            None,
+            None,
+            1_000,
+            BanchoPrivileges::Moderator,
+        );
+        assert_eq!(extends.silence_remaining(400), 600);
+        assert_eq!(extends.silence_remaining(1_000), 0);
+
+        let restored = ChatSessionExtend::from(extends.create_snapshot().await);
+        assert_eq!(restored.silence_remaining(400), 600);
+        assert!(restored.bancho_privileges.load().is_moderator());
+
+        // json data written before the silences
+        let data: ChatSessionExtendData = serde_json::from_str(
+            r#"{"platforms":1,"bancho_ext":null,"joined_channels":[]}"#,
+        )
+        .unwrap();
+        assert_eq!(data.silence_end, 0);
+        assert_eq!(data.bancho_privileges, BanchoPrivileges::Normal);
+    }
+
+    #[tokio::test]
+    async fn test_join_auto_join_channels() {
+        let channels = Channels::default();
+        for (id, name, auto_join) in
+            [(0, "#osu", true), (1, "#peace", false), (2, "#announce", true)]
+        {
+            let channel = Channel::new(
+                id,
+                name.to_owned(),
+                ChannelType::Public,
+                None,
+                None,
+            );
+            channel.auto_join.set(auto_join);
+            channels.create_channel(channel, false).await;
+        }
+
+        let session = session(1000);
+        let mut joined = channels
+            .join_auto_join_channels(&session, 0)
+            .await
+            .iter()
+            .map(|channel| channel.id)
+            .collect::<Vec<_>>();
+        joined.sort();
+        assert_eq!(joined, [0, 2]);
+
+        let mut joined_ids = session
+            .joined_channels()
+            .await
+            .iter()
+            .map(|channel| channel.id)
+            .collect::<Vec<_>>();
+        joined_ids.sort();
+        assert_eq!(joined_ids, [0, 2]);
+
+        // already joined channels are skipped
+        assert!(channels.join_auto_join_channels(&session, 0).await.is_empty());
+        assert_eq!(session.extends.channel_count.val(), 2);
+
+        let osu = channels.get_channel(&ChannelQuery::ChannelId(0)).await;
+        assert_eq!(osu.unwrap().session_count(Platform::Bancho).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_session_count_after_join_and_part() {
+        let channel = Arc::new(Channel::new(
+            0,
+            "#osu".to_owned(),
+            ChannelType::Public,
+            None,
+            None,
+        ));
+
+        let member = session(1000);
+        Channel::join(&member, &channel, 0).await;
+
+        let before = channel.session_count(Platform::Bancho).await;
+        let info_before = channel.info_packets().await;
+        assert_eq!(before, 1);
+
+        let user = session(1001);
+        Channel::join(&user, &channel, 0).await;
+        // joining twice is counted once
+        Channel::join(&user, &channel, 0).await;
+        assert_eq!(channel.session_count(Platform::Bancho).await, 2);
+        assert_eq!(channel.channel_info().await.online_users, 2);
+        assert_eq!(channel.session_count(Platform::Lazer).await, 0);
+
+        Channel::remove(&user, &channel).await;
+        assert_eq!(channel.session_count(Platform::Bancho).await, before);
+        assert_eq!(channel.channel_info().await.online_users, before as u32);
+        assert_eq!(channel.info_packets().await, info_before);
+    }
+
+    #[tokio::test]
+    async fn test_join_and_remove_lock_order() {
+        // Out of order locks panic in the scope, in debug builds
+        lock_order::scope(async {
+            let channel = Arc::new(Channel::new(
+                0,
+                "#osu".to_owned(),
+                ChannelType::Public,
+                None,
+                None,
+            ));
+            let user = session(1000);
+
+            Channel::join(&user, &channel, 10).await;
+            channel.wake_members(1001).await;
+            Channel::remove(&user, &channel).await;
+
+            assert_eq!(channel.session_count(Platform::Bancho).await, 0);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_dispose_channel() {
+        let channels = Channels::default();
+        let channel = channels
+            .create_channel(
+                Channel::new(
+                    10,
+                    "#custom".to_owned(),
+                    ChannelType::Public,
+                    None,
+                    None,
+                ),
+                false,
+            )
+            .await;
+
+        let members = [session(1000), session(1001)];
+        for member in members.iter() {
+            Channel::join(member, &channel, 0).await;
+        }
+
+        let packets_queue = |session: &Arc<ChatSession>| {
+            session.extends.bancho_ext.load_full().unwrap()
+        };
+
+        // drop the join packets
+        for member in members.iter() {
+            packets_queue(member).packets_queue.dequeue_all_packets(None).await;
+        }
+
+        channels.dispose_channel(&channel).await;
+
+        assert!(channels
+            .get_channel(&ChannelQuery::ChannelName("#custom".to_owned()))
+            .await
+            .is_none());
+        assert_eq!(channels.len.val(), 0);
+        assert_eq!(channel.session_count(Platform::all()).await, 0);
+
+        // the members are kicked
+        for member in members.iter() {
+            assert_eq!(member.extends.channel_count.val(), 0);
+            assert_eq!(
+                packets_queue(member)
+                    .packets_queue
+                    .dequeue_all_packets(None)
+                    .await,
+                channel.kick_packets()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wake_members() {
+        use std::time::Duration;
+
+        let channel = Arc::new(Channel::new(
+            0,
+            "#osu".to_owned(),
+            ChannelType::Public,
+            None,
+            None,
+        ));
+
+        let sender = session(1000);
+        let member = session(1001);
+        Channel::join(&sender, &channel, 0).await;
+        Channel::join(&member, &channel, 0).await;
+
+        channel.wake_members(sender.user_id).await;
+
+        let notified = |session: &Arc<ChatSession>| {
+            let bancho_ext = session.extends.bancho_ext.load_full().unwrap();
+            async move {
+                tokio::time::timeout(
+                    Duration::from_millis(50),
+                    bancho_ext.packets_queue.notified(),
+                )
+                .await
+                .is_ok()
+            }
+        };
+
+        assert!(notified(&member).await);
+        assert!(!notified(&sender).await);
+    }
+
+    #[tokio::test]
+    async fn test_join_backlog() {
+        const BACKLOG: usize = 5;
+
+        let channel = Arc::new(Channel::new(
+            0,
+            "#osu".to_owned(),
+            ChannelType::Public,
+            None,
+            None,
+        ));
+
+        for i in 0..8u8 {
+            channel
+                .message_queue
+                .push_message_excludes(Packet::new(vec![i]), [1], None)
+                .await;
+        }
+
+        let expected = channel
+            .message_queue
+            .read()
+            .await
+            .messages
+            .values()
+            .rev()
+            .take(BACKLOG)
+            .rev()
+            .map(|message| message.content.to_vec())
+            .collect::<Vec<_>>();
+
+        let joiner = session(1001);
+        Channel::join(&joiner, &channel, BACKLOG).await;
+
+        let message_index = *session_message_index(&joiner, &channel).await;
+        let received = channel
+            .message_queue
+            .receive_messages(&joiner.user_id, &message_index, None)
+            .await
+            .unwrap()
+            .messages
+            .iter()
+            .map(|packet| packet.to_vec())
+            .collect::<Vec<_>>();
+
+        assert_eq!(received, expected);
+
+        // no backlog, only the next messages are received
+        let late = session(1002);
+        Channel::join(&late, &channel, 0).await;
+        let message_index = *session_message_index(&late, &channel).await;
+        assert!(channel
+            .message_queue
+            .receive_messages(&late.user_id, &message_index, None)
+            .await
+            .is_none());
+    }
+
+    async fn session_message_index(
+        session: &ChatSession,
+        channel: &Channel,
+    ) -> Arc<Ulid> {
+        session.extends.joined_channels.read().await[&channel.id]
+            .message_index
+            .load_full()
+    }
+
+    #[test]
+    fn test_channel_privileges() {
+        let admin = Channel::new(
+            10,
+            "#admin".to_owned(),
+            ChannelType::Public,
+            None,
+            None,
+        );
+        admin.read_privilege.set(100);
+        admin.write_privilege.set(100);
+
+        let normal = session(1);
+        assert!(!admin.can_read(&normal));
+        assert!(!admin.can_write(&normal));
+
+        // the priority is not enough, only the moderators bypass it
+        let high_priority =
+            session_with_privileges(2, 99, BanchoPrivileges::Supporter);
+        assert!(!admin.can_read(&high_priority));
+
+        let moderator =
+            session_with_privileges(3, 1, BanchoPrivileges::Moderator);
+        assert!(admin.can_read(&moderator));
+        assert!(admin.can_write(&moderator));
+
+        let granted = session_with_privileges(4, 100, BanchoPrivileges::Normal);
+        assert!(admin.can_read(&granted));
+
+        // read only for normal users
+        admin.read_privilege.set(0);
+        assert!(admin.can_read(&normal));
+        assert!(!admin.can_write(&normal));
+    }
+
+    #[tokio::test]
+    async fn test_join_restricted_channel() {
+        let channels = Channels::default();
+        for (id, name, read_privilege) in [(0, "#osu", 0), (1, "#staff", 100)] {
+            let channel = Channel::new(
+                id,
+                name.to_owned(),
+                ChannelType::Public,
+                None,
+                None,
+            );
+            channel.auto_join.set(true);
+            channel.read_privilege.set(read_privilege);
+            channels.create_channel(channel, false).await;
+        }
+
+        let joined_ids = |joined: Vec<Arc<Channel>>| {
+            let mut ids = joined.iter().map(|c| c.id).collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+
+        // blocked for the normal users
+        let normal = session(1);
+        assert_eq!(
+            joined_ids(channels.join_auto_join_channels(&normal, 0).await),
+            [0]
+        );
+
+        // allowed for the moderators and the granted users
+        let moderator =
+            session_with_privileges(2, 1, BanchoPrivileges::Moderator);
+        assert_eq!(
+            joined_ids(channels.join_auto_join_channels(&moderator, 0).await),
+            [0, 1]
+        );
+
+        let granted = session_with_privileges(3, 100, BanchoPrivileges::Normal);
+        assert_eq!(
+            joined_ids(channels.join_auto_join_channels(&granted, 0).await),
+            [0, 1]
+        );
+
+        let staff = channels.get_channel(&ChannelQuery::ChannelId(1)).await;
+        assert_eq!(staff.unwrap().session_count(Platform::Bancho).await, 2);
+    }
+}
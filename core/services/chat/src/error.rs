@@ -1,5 +1,5 @@
 use peace_pb::ConvertError;
-use peace_repositories::GetUserError;
+use peace_repositories::{CreateChannelError, GetUserError};
 use peace_rpc_error::{RpcError, TonicError};
 use tonic::Status;
 
@@ -7,14 +7,25 @@ use tonic::Status;
 pub enum ChatError {
     #[error(transparent)]
     GetUserError(#[from] GetUserError),
+    #[error(transparent)]
+    CreateChannelError(#[from] CreateChannelError),
     #[error("invalid argument")]
     InvalidArgument,
     #[error("chat session not exists")]
     SessionNotExists,
     #[error("channel not exists")]
     ChannelNotExists,
+    #[error("no permission")]
+    NoPermission,
+    /// The sender is silenced for the remaining secs.
+    #[error("silenced for {0} secs")]
+    Silenced(i32),
+    #[error("message not exists")]
+    MessageNotExists,
     #[error(transparent)]
     ConvertError(#[from] ConvertError),
+    #[error("database error: {0}")]
+    DbError(String),
     #[error("bancho state error: {0}")]
     BanchoStateError(String),
     #[error("TonicError: {0}")]
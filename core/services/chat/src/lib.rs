@@ -10,6 +10,7 @@ extern crate serde;
 
 pub mod components;
 pub mod error;
+pub mod lock_order;
 pub mod services;
 
 pub use components::*;
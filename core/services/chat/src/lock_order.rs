@@ -0,0 +1,215 @@
+//! Lock acquisition order of the chat state.
+//!
+//! Locks must be acquired in ascending [`LockRank`] order: a task holding a
+//! lock may only acquire locks of a higher rank. In debug builds, the ranks
+//! held by a task running in [`scope`] are tracked and acquiring a lock out
+//! of order panics. Release builds do not track anything.
+//!
+//! The locks wrapped in a [`RankedRwLock`] are checked on every
+//! acquisition, the other ones are marked by hand:
+//!
+//! ```ignore
+//! let _rank = lock_order::acquire(LockRank::ChannelMessageQueue);
+//! let message_queue = channel.message_queue.read().await;
+//! ```
+use std::{
+    future::Future,
+    ops::{Deref, DerefMut},
+};
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+#[cfg(debug_assertions)]
+use std::cell::RefCell;
+
+#[cfg(debug_assertions)]
+tokio::task_local! {
+    static HELD_RANKS: RefCell<Vec<LockRank>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LockRank {
+    /// Indexes of the [`crate::UserSessions`].
+    UserSessions,
+    /// Indexes of the [`crate::Channels`].
+    Channels,
+    /// Global notify queue of the chat service.
+    NotifyQueue,
+    /// Joined channels of a session.
+    JoinedChannels,
+    /// Channel updates received by a bancho session.
+    ReceiveChannelUpdates,
+    /// Members of a channel.
+    ChannelUsers,
+    /// Message queue of a channel.
+    ChannelMessageQueue,
+}
+
+/// Held until dropped, the lock of the rank must be released before it.
+#[derive(Debug)]
+#[must_use = "the rank is released when the guard is dropped"]
+pub struct RankGuard {
+    #[cfg(debug_assertions)]
+    rank: LockRank,
+}
+
+impl Drop for RankGuard {
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        let _ = HELD_RANKS.try_with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(i) = held.iter().rposition(|r| *r == self.rank) {
+                held.remove(i);
+            }
+        });
+    }
+}
+
+/// Run the future with lock order tracking, in debug builds.
+pub async fn scope<F: Future>(fut: F) -> F::Output {
+    #[cfg(debug_assertions)]
+    {
+        HELD_RANKS.scope(RefCell::default(), fut).await
+    }
+
+    #[cfg(not(debug_assertions))]
+    {
+        fut.await
+    }
+}
+
+/// Mark a lock of the rank as acquired by the current task, call it right
+/// before acquiring the lock.
+///
+/// # Panics
+///
+/// In debug builds, if the task already holds a lock of the same or a higher
+/// rank.
+#[inline]
+pub fn acquire(rank: LockRank) -> RankGuard {
+    #[cfg(debug_assertions)]
+    {
+        let _ = HELD_RANKS.try_with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(highest) = held.iter().max() {
+                assert!(
+                    *highest < rank,
+                    "lock order violation: acquiring {rank:?} while holding \
+                    {highest:?}"
+                );
+            }
+            held.push(rank);
+        });
+
+        RankGuard { rank }
+    }
+
+    #[cfg(not(debug_assertions))]
+    {
+        let _ = rank;
+        RankGuard {}
+    }
+}
+
+/// [`RwLock`] of a [`LockRank`], its guards hold the rank until they are
+/// dropped.
+#[derive(Debug)]
+pub struct RankedRwLock<T> {
+    rank: LockRank,
+    lock: RwLock<T>,
+}
+
+impl<T> RankedRwLock<T> {
+    #[inline]
+    pub fn new(rank: LockRank, value: T) -> Self {
+        Self { rank, lock: RwLock::new(value) }
+    }
+
+    #[inline]
+    pub async fn read(&self) -> RankedGuard<RwLockReadGuard<'_, T>> {
+        let rank = acquire(self.rank);
+        RankedGuard { guard: self.lock.read().await, _rank: rank }
+    }
+
+    #[inline]
+    pub async fn write(&self) -> RankedGuard<RwLockWriteGuard<'_, T>> {
+        let rank = acquire(self.rank);
+        RankedGuard { guard: self.lock.write().await, _rank: rank }
+    }
+}
+
+/// Lock guard of a [`RankedRwLock`], the lock is released before its rank.
+#[derive(Debug)]
+pub struct RankedGuard<G> {
+    guard: G,
+    _rank: RankGuard,
+}
+
+impl<G: Deref> Deref for RankedGuard<G> {
+    type Target = G::Target;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<G: DerefMut> DerefMut for RankedGuard<G> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lock_order_ascending() {
+        scope(async {
+            let _channels = acquire(LockRank::Channels);
+            let _users = acquire(LockRank::ChannelUsers);
+            drop(_users);
+            let _queue = acquire(LockRank::ChannelMessageQueue);
+        })
+        .await;
+    }
+
+    #[cfg(debug_assertions)]
+    #[tokio::test]
+    #[should_panic(expected = "lock order violation")]
+    async fn test_lock_order_violation() {
+        scope(async {
+            let _queue = acquire(LockRank::ChannelMessageQueue);
+            let _users = acquire(LockRank::ChannelUsers);
+        })
+        .await;
+    }
+
+    #[cfg(debug_assertions)]
+    #[tokio::test]
+    #[should_panic(expected = "lock order violation")]
+    async fn test_ranked_lock_order_violation() {
+        let users = RankedRwLock::new(LockRank::ChannelUsers, ());
+        let joined_channels = RankedRwLock::new(LockRank::JoinedChannels, ());
+
+        scope(async {
+            let _users = users.read().await;
+            let _joined_channels = joined_channels.write().await;
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_ranked_guard_releases_rank() {
+        let users = RankedRwLock::new(LockRank::ChannelUsers, ());
+        let joined_channels = RankedRwLock::new(LockRank::JoinedChannels, ());
+
+        scope(async {
+            drop(users.read().await);
+            let _joined_channels = joined_channels.write().await;
+            let _users = users.write().await;
+        })
+        .await;
+    }
+}
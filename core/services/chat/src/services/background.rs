@@ -50,10 +50,12 @@ impl ChatBackgroundServiceImpl {
 
         let user_sessions = self.chat_service.user_sessions().clone();
         let notify_queue = self.chat_service.notify_queue().clone();
+        let channels = self.chat_service.channels().clone();
 
         BackgroundTaskFactory::new(Arc::new(move |stop: SignalHandle| {
             let user_sessions = user_sessions.clone();
             let notify_queue = notify_queue.clone();
+            let channels = channels.clone();
             let cfg = config.clone();
 
             let task = async move {
@@ -98,20 +100,32 @@ impl ChatBackgroundServiceImpl {
 
                     let removed_deactive_sessions = match sessions_deactive {
                         Some(sessions_deactive) => {
-                            let mut indexes = user_sessions.write().await;
-
+                            let () = {
+                                let mut indexes = user_sessions.write().await;
+
+                                for session in sessions_deactive.iter() {
+                                    user_sessions.delete_inner(
+                                        &mut indexes,
+                                        &session.user_id,
+                                        &session.username.load(),
+                                        &session.id,
+                                        session
+                                            .username_unicode
+                                            .load()
+                                            .as_deref()
+                                            .map(|s| s.as_str()),
+                                    );
+                                }
+                            };
+
+                            // the client may be gone without logging out,
+                            // e.g. a spectator channel host
                             for session in sessions_deactive.iter() {
-                                user_sessions.delete_inner(
-                                    &mut indexes,
-                                    &session.user_id,
-                                    &session.username.load(),
-                                    &session.id,
-                                    session
-                                        .username_unicode
-                                        .load()
-                                        .as_deref()
-                                        .map(|s| s.as_str()),
-                                );
+                                for channel in session.joined_channels().await {
+                                    channels
+                                        .leave_channel(session, &channel)
+                                        .await;
+                                }
                             }
 
                             sessions_deactive.len()
@@ -1,34 +1,66 @@
-use crate::*;
+use crate::{
+    lock_order::{LockRank, RankedRwLock},
+    *,
+};
 use async_trait::async_trait;
 use bancho_packets::server;
 use chrono::{DateTime, Utc};
+use clap_serde_derive::ClapSerde;
+use domain_bancho::BanchoPrivileges;
 use domain_chat::{ChannelType, Platform};
 use infra_packets::{Packet, PacketsQueue};
 use infra_services::{FromRpcClient, IntoService, RpcClient, ServiceSnapshot};
 use infra_users::CreateSessionDto;
+use num_traits::FromPrimitive;
 use pb_bancho_state::{BanchoPackets, RawUserQuery, UserQuery};
 use pb_base::ExecSuccess;
 use pb_chat::{
-    chat_rpc_client::ChatRpcClient, ChannelInfo, ChatMessageTarget,
-    GetPublicChannelsRequest, GetPublicChannelsResponse, JoinChannelRequest,
-    LeaveChannelRequest, LoadPublicChannelsRequest, LoginRequest,
-    LogoutRequest, SendMessageRequest, SendMessageResponse,
+    chat_rpc_client::ChatRpcClient, ChannelInfo, ChannelQuery,
+    ChatMessageTarget, CreateChannelRequest, CreatePublicChannelRequest,
+    DeleteChannelRequest, GetChannelMembersRequest, GetPublicChannelsRequest,
+    GetPublicChannelsResponse, GetUserChannelsRequest, JoinChannelRequest,
+    JoinPlatformsRequest, LeaveChannelRequest, LoadPublicChannelsRequest,
+    LoginRequest, LogoutRequest, ModerateMessageRequest, SendMessageRequest,
+    SendMessageResponse, UpdateSilenceEndRequest, WaitChatPacketsRequest,
+};
+use peace_db::peace::entity::{
+    channels, sea_orm_active_enums::ChannelHandleType,
 };
 use peace_message_queue::ReceivedMessages;
-use peace_repositories::users::DynUsersRepository;
+use peace_repositories::{
+    channels::{
+        ChannelPrivilege, CreateChannel, CreateMessage, DynChannelsRepository,
+    },
+    users::DynUsersRepository,
+    CreateChannelError,
+};
 use peace_snapshot::{
-    CreateSnapshot, CreateSnapshotError, LoadSnapshotFrom, SaveSnapshotTo,
-    SnapshotConfig, SnapshotExpired, SnapshotTime, SnapshotType,
+    decode_binary, CreateSnapshot, CreateSnapshotError, LoadSnapshotFrom,
+    SaveSnapshotTo, SnapshotConfig, SnapshotExpired, SnapshotSchema,
+    SnapshotTime, SnapshotType,
 };
 use std::{
     borrow::Cow,
     collections::{HashMap, VecDeque},
-    path::Path,
     sync::Arc,
+    time::Duration,
 };
-use tokio::sync::RwLock;
-use tonic::{transport::Channel as RpcChannel, IntoRequest};
-use tools::atomic::{AtomicValue, U32};
+use tonic::IntoRequest;
+use tools::{
+    atomic::{AtomicValue, Bool, I32},
+    tonic_utils::TracedChannel,
+};
+
+#[derive(Debug, Clone, Parser, ClapSerde, Serialize, Deserialize)]
+pub struct CliChatServiceConfigs {
+    /// Number of the last messages of a channel received by the users
+    /// joining it, `0` to receive only the new messages.
+    ///
+    /// Only the messages still queued in the channel can be received.
+    #[default(10)]
+    #[arg(long, default_value = "10")]
+    pub chat_channel_join_backlog: usize,
+}
 
 #[derive(Clone)]
 pub struct ChatServiceImpl {
@@ -36,16 +68,24 @@ pub struct ChatServiceImpl {
     pub notify_queue: Arc<BanchoMessageQueue>,
     pub channels: Arc<Channels>,
     pub users_repository: DynUsersRepository,
+    pub channels_repository: DynChannelsRepository,
+    pub join_backlog: usize,
 }
 
 impl ChatServiceImpl {
     #[inline]
-    pub fn new(users_repository: DynUsersRepository) -> Self {
+    pub fn new(
+        users_repository: DynUsersRepository,
+        channels_repository: DynChannelsRepository,
+        join_backlog: usize,
+    ) -> Self {
         Self {
             user_sessions: UserSessions::default().into(),
             notify_queue: Arc::new(BanchoMessageQueue::default()),
             channels: Channels::default().into(),
             users_repository,
+            channels_repository,
+            join_backlog,
         }
     }
 
@@ -53,6 +93,8 @@ impl ChatServiceImpl {
     pub async fn from_snapshot(
         snapshot: ChatServiceSnapshot,
         users_repository: DynUsersRepository,
+        channels_repository: DynChannelsRepository,
+        join_backlog: usize,
     ) -> Self {
         let mut session_indexes =
             SessionIndexes::with_capacity(snapshot.user_sessions.len());
@@ -69,29 +111,35 @@ impl ChatServiceImpl {
             ChannelIndexes::with_capacity(snapshot.channels.len());
 
         for ch in snapshot.channels {
-            let user_count = U32::new(ch.users.len() as u32);
-            let users = Arc::new(RwLock::new(HashMap::from_iter(
-                ch.users.into_iter().map(|user_id| {
+            let users =
+                HashMap::from_iter(ch.users.into_iter().map(|user_id| {
                     (user_id, session_indexes.get(&user_id).map(Arc::downgrade))
-                }),
-            )));
+                }));
+            let user_ids = users.keys().copied().collect::<Vec<_>>();
 
             let channel = Arc::new(Channel {
                 id: ch.id,
                 name: ch.name.into(),
                 channel_type: ch.channel_type,
                 description: ch.description.into(),
-                users,
-                user_count,
+                auto_join: Bool::new(false),
+                read_privilege: I32::new(0),
+                write_privilege: I32::new(0),
+                users: Arc::new(RankedRwLock::new(
+                    LockRank::ChannelUsers,
+                    users,
+                )),
                 min_msg_index: ch.min_msg_index.into(),
                 message_queue: Arc::new(ch.message_queue.into()),
                 created_at: ch.created_at,
                 updated_at: ch.updated_at.into(),
             });
 
-            // upgrade user's JoinedChannel to real channel's weak ptr
-            for user_id in channel.users.read().await.keys() {
-                if let Some(session) = session_indexes.get(user_id) {
+            // upgrade user's JoinedChannel to real channel's weak ptr, the
+            // members are collected first as the joined channels are ranked
+            // before the channel users
+            for user_id in user_ids {
+                if let Some(session) = session_indexes.get(&user_id) {
                     let mut joined_channels =
                         session.extends.joined_channels.write().await;
 
@@ -116,7 +164,14 @@ impl ChatServiceImpl {
         let user_sessions =
             Arc::new(UserSessions::from_indexes(session_indexes));
 
-        Self { user_sessions, notify_queue, channels, users_repository }
+        Self {
+            user_sessions,
+            notify_queue,
+            channels,
+            users_repository,
+            channels_repository,
+            join_backlog,
+        }
     }
 
     #[inline]
@@ -124,33 +179,45 @@ impl ChatServiceImpl {
         Arc::new(self) as DynChatService
     }
 
+    /// `#spectator` is resolved to the spectator channel joined by the user.
+    pub async fn resolve_channel(
+        &self,
+        session: &ChatSession,
+        query: &ChannelQuery,
+    ) -> Option<Arc<Channel>> {
+        match query {
+            ChannelQuery::ChannelName(name) if name == "#spectator" => {
+                session.joined_channels().await.into_iter().find(|channel| {
+                    channel.channel_type == ChannelType::Spectaor
+                })
+            },
+            _ => self.channels.get_channel(query).await,
+        }
+    }
+
     #[inline]
     pub async fn login_inner(
         &self,
-        user_id: i32,
-        username: String,
-        username_unicode: Option<String>,
-        privileges: i32,
-        platforms: Platform,
+        request: LoginRequest,
     ) -> Result<Arc<ChatSession>, ChatError> {
+        let LoginRequest {
+            user_id,
+            username,
+            username_unicode,
+            privileges,
+            platforms,
+            silence_end,
+            bancho_privileges,
+        } = request;
+
+        let platforms = Platform::from(platforms);
+
         let bancho_chat_ext = if platforms.contains(Platform::Bancho) {
             // prepare bancho packets
             let mut channel_packets = VecDeque::new();
 
             for channel in self.channels.read().await.public_channels.values() {
-                channel_packets.push_back(
-                    server::ChannelInfo::pack(
-                        channel.name.load().as_ref().into(),
-                        channel
-                            .description
-                            .load()
-                            .as_deref()
-                            .map(|s| s.into())
-                            .unwrap_or_default(),
-                        channel.user_count.val() as i16,
-                    )
-                    .into(),
-                );
+                channel_packets.push_back(channel.info_packets().await.into());
             }
 
             channel_packets.push_back(server::ChannelInfoEnd::pack().into());
@@ -160,19 +227,399 @@ impl ChatServiceImpl {
             None
         };
 
-        let extends = ChatSessionExtend::new(platforms, bancho_chat_ext, None);
+        let extends = ChatSessionExtend::new(
+            platforms,
+            bancho_chat_ext,
+            None,
+            silence_end,
+            BanchoPrivileges::from(bancho_privileges),
+        );
+
+        let session = ChatSession::new(CreateSessionDto {
+            user_id,
+            username,
+            username_unicode,
+            privileges,
+            extends,
+        });
+
+        let session = self.user_sessions.create(session.into()).await;
+
+        Ok(session)
+    }
+
+    async fn check_moderator(&self, user_id: i32) -> Result<(), ChatError> {
+        let bancho_privileges = self
+            .users_repository
+            .get_bancho_privileges(user_id)
+            .await
+            .map_err(|err| ChatError::DbError(err.to_string()))?;
+
+        if !bancho_privileges.is_moderator() {
+            return Err(ChatError::NoPermission);
+        }
+
+        Ok(())
+    }
+
+    /// Best-effort, the sender only receives the notification if online
+    /// on bancho.
+    async fn notify_user(&self, user_id: i32, msg: &str) {
+        let session =
+            match self.user_sessions.get(&UserQuery::UserId(user_id)).await {
+                Some(session) => session,
+                None => return,
+            };
+
+        if let Some(bancho_ext) = session.extends.bancho_ext.load().as_ref() {
+            bancho_ext
+                .packets_queue
+                .push_packet(server::Notification::pack(msg.into()).into())
+                .await;
+        }
+    }
+
+    /// Send the message, the locks are acquired in [`lock_order`].
+    async fn send_message_inner(
+        &self,
+        request: SendMessageRequest,
+    ) -> Result<SendMessageResponse, ChatError> {
+        const LOG_TARGET: &str = "chat::send_message";
+
+        let SendMessageRequest { sender, message, target } = request;
+
+        let sender_query =
+            sender.ok_or(ChatError::InvalidArgument)?.into_user_query()?;
+
+        let target =
+            target.ok_or(ChatError::InvalidArgument)?.into_message_target()?;
+
+        let sender =
+            self.get_session(&sender_query, Some(Platform::all())).await?;
+
+        let silence_remaining =
+            sender.extends.silence_remaining(Utc::now().timestamp());
+        if silence_remaining > 0 {
+            return Err(ChatError::Silenced(silence_remaining));
+        }
+
+        match target {
+            ChatMessageTarget::Channel(channel_query) => {
+                // get channel
+                let channel =
+                    match self.resolve_channel(&sender, &channel_query).await {
+                        Some(channel) => channel,
+                        None => {
+                            todo!("channel not exists")
+                        },
+                    };
+
+                if !channel.can_write(&sender) {
+                    return Err(ChatError::NoPermission);
+                }
+
+                let message_packet = server::SendMessage::pack(
+                    sender.username.load().as_ref().into(),
+                    Cow::Borrowed(message.as_ref()),
+                    channel.display_name().into(),
+                    sender.user_id,
+                )
+                .into();
+
+                // push msg into channel packets queue
+                {
+                    let _rank =
+                        lock_order::acquire(LockRank::ChannelMessageQueue);
+                    channel.message_queue.write().await.push_message_excludes(
+                        Packet::Ptr(message_packet),
+                        [sender.user_id],
+                        None,
+                    );
+                }
+                channel.wake_members(sender.user_id).await;
+
+                info!(
+                    target: LOG_TARGET,
+                    "{}({}) @ {}({}): {}",
+                    sender.username.load(),
+                    sender.user_id,
+                    channel.name.load(),
+                    channel.id,
+                    message
+                );
+
+                // Stored in the background, so that the database does not
+                // delay the delivery of the message
+                if channel.channel_type == ChannelType::Public {
+                    let channels_repository = self.channels_repository.clone();
+                    let message = CreateMessage {
+                        sender_id: sender.user_id,
+                        channel_id: channel.id as i64,
+                        is_action: message.starts_with("\x01ACTION"),
+                        content: message,
+                    };
+
+                    tokio::spawn(async move {
+                        if let Err(err) =
+                            channels_repository.create_message(message).await
+                        {
+                            warn!(
+                                target: LOG_TARGET,
+                                "Failed to store message, err: {err}"
+                            );
+                        }
+                    });
+                }
+            },
+            ChatMessageTarget::User(target_query) => {
+                // get target user session
+                match self.get_session(&target_query, None).await.ok() {
+                    Some(target_user) => {
+                        // push msg packet if target user's bancho packets queue is exists
+                        if let Some(bancho_ext) =
+                            target_user.extends.bancho_ext.load().as_ref()
+                        {
+                            bancho_ext
+                                .packets_queue
+                                .push_packet(
+                                    server::SendMessage::pack(
+                                        sender.username.load().as_ref().into(),
+                                        Cow::Borrowed(message.as_ref()),
+                                        target_user
+                                            .username
+                                            .load()
+                                            .as_ref()
+                                            .into(),
+                                        sender.user_id,
+                                    )
+                                    .into(),
+                                )
+                                .await;
+                        }
+
+                        info!(
+                            target: LOG_TARGET,
+                            "{}({}) @ {}({}): {}",
+                            sender.username.load(),
+                            sender.user_id,
+                            target_user.username.load(),
+                            target_user.user_id,
+                            message
+                        );
+                    },
+                    None => {
+                        todo!("offline msg handle")
+                    },
+                };
+            },
+        }
+
+        Ok(SendMessageResponse::default())
+    }
+
+    /// Join the user into the channel, the locks are acquired in
+    /// [`lock_order`].
+    async fn join_channel_inner(
+        &self,
+        request: JoinChannelRequest,
+    ) -> Result<ExecSuccess, ChatError> {
+        let JoinChannelRequest { channel_query, user_query } = request;
+
+        let user_query =
+            user_query.ok_or(ChatError::InvalidArgument)?.into_user_query()?;
+
+        let channel_query = channel_query
+            .ok_or(ChatError::InvalidArgument)?
+            .into_channel_query()?;
+
+        let session =
+            self.get_session(&user_query, Some(Platform::all())).await?;
+
+        let channel = self
+            .resolve_channel(&session, &channel_query)
+            .await
+            .ok_or(ChatError::ChannelNotExists)?;
+
+        if !channel.can_read(&session) {
+            return Err(ChatError::NoPermission);
+        }
+
+        // add user into channel
+        Channel::join(&session, &channel, self.join_backlog).await;
+
+        // update channel
+        channel.updated_at.set(Utc::now().into());
+
+        Ok(ExecSuccess::default())
+    }
+
+    /// Remove the user from the channel, the locks are acquired in
+    /// [`lock_order`].
+    async fn leave_channel_inner(
+        &self,
+        request: LeaveChannelRequest,
+    ) -> Result<ExecSuccess, ChatError> {
+        let LeaveChannelRequest { channel_query, user_query } = request;
+
+        let user_query =
+            user_query.ok_or(ChatError::InvalidArgument)?.into_user_query()?;
+
+        let channel_query = channel_query
+            .ok_or(ChatError::InvalidArgument)?
+            .into_channel_query()?;
+
+        let session =
+            self.get_session(&user_query, Some(Platform::all())).await?;
+
+        let channel = self
+            .resolve_channel(&session, &channel_query)
+            .await
+            .ok_or(ChatError::ChannelNotExists)?;
+
+        // remove user from channel
+        self.channels.leave_channel(&session, &channel).await;
+
+        Ok(ExecSuccess::default())
+    }
+
+    /// Notifies, channel messages and channel updates of the session, the
+    /// locks are acquired in [`lock_order`].
+    async fn dequeue_packets(
+        &self,
+        query: UserQuery,
+    ) -> Result<BanchoPackets, ChatError> {
+        let session = self.get_session(&query, Some(Platform::Bancho)).await?;
+
+        let bancho_ext = match session.extends.bancho_ext.load_full() {
+            Some(bancho_ext) => bancho_ext,
+            None => todo!("invalid call"),
+        };
+
+        let mut data = Vec::new();
+
+        // receive global notify from queue
+        let rank = lock_order::acquire(LockRank::NotifyQueue);
+        if let Some(ReceivedMessages { messages, last_msg_id }) = self
+            .notify_queue
+            .read()
+            .await
+            .receive_messages(
+                &session.user_id,
+                &bancho_ext.notify_index.load(),
+                None,
+            )
+            .await
+        {
+            for packet in messages {
+                data.extend_from_slice(&packet);
+            }
+
+            bancho_ext.notify_index.set(last_msg_id.into());
+        }
+        drop(rank);
+
+        // get user's joined channels
+        let joined_channels = session
+            .extends
+            .joined_channels
+            .read()
+            .await
+            .iter()
+            .map(|(channel_id, channel)| (*channel_id, channel.clone()))
+            .collect::<Vec<(u64, Arc<JoinedChannel>)>>();
+
+        let mut invalid_channels = Vec::new();
+
+        // receive msg from each channels, and mark invalid channels ptr
+        for (channel_id, joined_channel) in joined_channels {
+            match joined_channel.ptr.load().upgrade() {
+                Some(channel) => {
+                    let _rank =
+                        lock_order::acquire(LockRank::ChannelMessageQueue);
+                    if let Some(ReceivedMessages { messages, last_msg_id }) =
+                        channel
+                            .message_queue
+                            .read()
+                            .await
+                            .receive_messages(
+                                &session.user_id,
+                                &joined_channel.message_index.load(),
+                                None,
+                            )
+                            .await
+                    {
+                        for packet in messages {
+                            data.extend_from_slice(&packet);
+                        }
+
+                        match channel.min_msg_index.load().as_deref() {
+                            Some(prev_channel_min_msg_id) => {
+                                if &last_msg_id < prev_channel_min_msg_id {
+                                    channel
+                                        .min_msg_index
+                                        .set(Some(last_msg_id.into()))
+                                }
+                            },
+                            None => channel
+                                .min_msg_index
+                                .set(Some(last_msg_id.into())),
+                        };
+
+                        joined_channel.message_index.set(last_msg_id.into());
+                    }
+                },
+                None => invalid_channels.push(channel_id),
+            }
+        }
+
+        // remove invalid channels
+        if !invalid_channels.is_empty() {
+            let mut joined_channels =
+                session.extends.joined_channels.write().await;
+
+            for channel_id in invalid_channels {
+                joined_channels.remove(&channel_id);
+            }
+        }
+
+        // TODO: real accessable check
+        let accessable_channels = {
+            let _rank = lock_order::acquire(LockRank::Channels);
+            self.channels
+                .read()
+                .await
+                .values()
+                .cloned()
+                .collect::<Vec<Arc<Channel>>>()
+        };
+
+        // receive latest channels info
+        let () = {
+            let _rank = lock_order::acquire(LockRank::ReceiveChannelUpdates);
+            let mut receive_channel_updates =
+                bancho_ext.receive_channel_updates.lock().await;
+
+            for ch in accessable_channels {
+                if let Some(date) = receive_channel_updates.get(&ch.id) {
+                    // prevent multiple reception
+                    if date == ch.updated_at.load().as_ref() {
+                        continue;
+                    }
+                }
+
+                // send channel info
+                data.extend(ch.info_packets().await);
 
-        let session = ChatSession::new(CreateSessionDto {
-            user_id,
-            username,
-            username_unicode,
-            privileges,
-            extends,
-        });
+                // update receive
+                receive_channel_updates
+                    .insert(ch.id, ch.updated_at.load().as_ref().clone());
+            }
+        };
 
-        let session = self.user_sessions.create(session.into()).await;
+        // receive msg from session queue
+        data.extend(bancho_ext.packets_queue.dequeue_all_packets(None).await);
 
-        Ok(session)
+        Ok(BanchoPackets { data })
     }
 
     pub async fn get_session(
@@ -208,13 +655,37 @@ impl ChatServiceImpl {
                         },
                     }?;
 
-                    self.login_inner(
-                        user.id,
-                        user.name,
-                        user.name_unicode,
-                        1, // todo
-                        platforms,
-                    )
+                    let privileges = self
+                        .users_repository
+                        .get_privilege_priority(user.id)
+                        .await
+                        .map_err(|err| ChatError::DbError(err.to_string()))?
+                        .map(i32::from)
+                        .unwrap_or(1);
+
+                    let bancho_privileges = self
+                        .users_repository
+                        .get_bancho_privileges(user.id)
+                        .await
+                        .map_err(|err| ChatError::DbError(err.to_string()))?;
+
+                    let silence_end = self
+                        .users_repository
+                        .get_silence_end(user.id)
+                        .await
+                        .map_err(|err| ChatError::DbError(err.to_string()))?
+                        .map(|silence_end| silence_end.timestamp())
+                        .unwrap_or_default();
+
+                    self.login_inner(LoginRequest {
+                        user_id: user.id,
+                        username: user.name,
+                        username_unicode: user.name_unicode,
+                        privileges,
+                        platforms: platforms.bits(),
+                        silence_end,
+                        bancho_privileges: bancho_privileges.bits(),
+                    })
                     .await
                 } else {
                     Err(ChatError::SessionNotExists)
@@ -224,19 +695,59 @@ impl ChatServiceImpl {
     }
 }
 
+/// Public channel names are prefixed with `#`, e.g. `#osu`.
+#[inline]
+fn is_public_channel_name(name: &str) -> bool {
+    name.starts_with('#') && name.len() >= 2
+}
+
+/// The public channel of a `channels` row, channels without name are
+/// skipped.
+fn public_channel(ch: channels::Model) -> Option<Channel> {
+    let channel = Channel::new(
+        ch.id as u64,
+        ch.name?,
+        ChannelType::Public,
+        ch.description,
+        None,
+    );
+    channel.auto_join.set(ch.auto_join);
+
+    Some(channel)
+}
+
+/// Set the privilege levels required by the channel, channels without
+/// privileges are open to everyone.
+fn set_channel_privileges(channel: &Channel, privileges: &[ChannelPrivilege]) {
+    let (mut read, mut write) = (0, 0);
+    for p in privileges.iter().filter(|p| p.channel_id as u64 == channel.id) {
+        match p.handle {
+            ChannelHandleType::Join => read = p.required_priority as i32,
+            ChannelHandleType::SendMessage => {
+                write = p.required_priority as i32
+            },
+            _ => {},
+        }
+    }
+
+    channel.read_privilege.set(read);
+    channel.write_privilege.set(write);
+}
+
 pub struct ChatServiceSnapshotLoader;
 
 impl ChatServiceSnapshotLoader {
     pub async fn load(
         cfg: &CliChatServiceSnapshotConfigs,
+        service_cfg: &CliChatServiceConfigs,
         users_repository: DynUsersRepository,
+        channels_repository: DynChannelsRepository,
     ) -> ChatServiceImpl {
         if cfg.should_load_snapshot() {
-            let snapshot_path = Path::new(cfg.snapshot_path());
-            if snapshot_path.is_file() {
+            if let Some(snapshot_path) = cfg.find_snapshot_file() {
                 match ChatServiceSnapshot::load_snapshot_from(
                     cfg.snapshot_type(),
-                    cfg.snapshot_path(),
+                    &snapshot_path,
                 )
                 .await
                 {
@@ -250,6 +761,8 @@ impl ChatServiceSnapshotLoader {
                             return ChatServiceImpl::from_snapshot(
                                 snapshot,
                                 users_repository,
+                                channels_repository,
+                                service_cfg.chat_channel_join_backlog,
                             )
                             .await;
                         }
@@ -257,18 +770,22 @@ impl ChatServiceSnapshotLoader {
                         info!("[ChatSnapshot] Snapshot file founded but already expired (create at: {})", snapshot.create_time);
                     },
                     Err(err) => {
-                        warn!("[ChatSnapshot] Failed to load snapshot file from path: \"{}\", err: {}", cfg.snapshot_path(), err);
+                        warn!("[ChatSnapshot] Failed to load snapshot file from path: \"{}\", err: {}", snapshot_path, err);
                     },
                 }
             } else {
                 info!(
                     "[ChatSnapshot] Snapshot file not found, path: \"{}\"",
-                    cfg.snapshot_path(),
+                    cfg.snapshot_file_path(),
                 );
             }
         }
 
-        ChatServiceImpl::new(users_repository)
+        ChatServiceImpl::new(
+            users_repository,
+            channels_repository,
+            service_cfg.chat_channel_join_backlog,
+        )
     }
 }
 
@@ -286,6 +803,45 @@ impl SnapshotTime for ChatServiceSnapshot {
     }
 }
 
+impl SnapshotSchema for ChatServiceSnapshot {
+    /// Version 2 added `silence_end` to the sessions.
+    const VERSION: u32 = 2;
+
+    fn migrate_binary(old_version: u32, data: &[u8]) -> Result<Self, String> {
+        match old_version {
+            // Unversioned snapshots share the same data as version 1
+            0 | 1 => {
+                decode_binary::<ChatServiceSnapshotV1>(data).map(Self::from)
+            },
+            _ => Err(format!("unknown snapshot version {old_version}")),
+        }
+    }
+}
+
+/// [`ChatServiceSnapshot`] of the version 1 snapshots.
+#[derive(Debug, Deserialize)]
+struct ChatServiceSnapshotV1 {
+    user_sessions: Vec<ChatSessionDataV1>,
+    notify_queue: Vec<BanchoMessageData>,
+    channels: Vec<ChannelData>,
+    create_time: DateTime<Utc>,
+}
+
+impl From<ChatServiceSnapshotV1> for ChatServiceSnapshot {
+    fn from(v1: ChatServiceSnapshotV1) -> Self {
+        Self {
+            user_sessions: v1
+                .user_sessions
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            notify_queue: v1.notify_queue,
+            channels: v1.channels,
+            create_time: v1.create_time,
+        }
+    }
+}
+
 #[async_trait]
 impl CreateSnapshot<ChatServiceSnapshot> for ChatServiceImpl {
     async fn create_snapshot(&self) -> ChatServiceSnapshot {
@@ -348,25 +904,11 @@ impl ChatService for ChatServiceImpl {
     ) -> Result<ExecSuccess, ChatError> {
         const LOG_TARGET: &str = "chat::login";
 
-        let LoginRequest {
-            user_id,
-            username,
-            username_unicode,
-            privileges,
-            platforms,
-        } = request;
-
-        let platforms = Platform::from(platforms);
+        let session = self.login_inner(request).await?;
 
-        let session = self
-            .login_inner(
-                user_id,
-                username,
-                username_unicode,
-                privileges,
-                platforms,
-            )
-            .await?;
+        self.channels
+            .join_auto_join_channels(&session, self.join_backlog)
+            .await;
 
         info!(
             target: LOG_TARGET,
@@ -399,6 +941,8 @@ impl ChatService for ChatServiceImpl {
             session.extends.bancho_ext.set(None);
         }
 
+        // Websocket sessions share the bancho state, only the tag is removed
+
         // TODO: part from other platforms
         if curr_platforms.contains(Platform::Lazer)
             && remove_platforms.contains(Platform::Lazer)
@@ -417,15 +961,8 @@ impl ChatService for ChatServiceImpl {
 
         if platforms.is_none() {
             // leave all channels
-            for channel in session.extends.joined_channels.read().await.values()
-            {
-                if let Some(channel) = channel.ptr.load().upgrade() {
-                    // remove user from channel
-                    Channel::remove(&session, &channel).await;
-
-                    // update channel
-                    channel.updated_at.set(Utc::now().into());
-                }
+            for channel in session.joined_channels().await {
+                self.channels.leave_channel(&session, &channel).await;
             }
 
             // delete user session
@@ -454,375 +991,415 @@ impl ChatService for ChatServiceImpl {
         Ok(ExecSuccess::default())
     }
 
+    async fn join_platforms(
+        &self,
+        query: UserQuery,
+        add_platforms: Platform,
+    ) -> Result<ExecSuccess, ChatError> {
+        const LOG_TARGET: &str = "chat::join_platforms";
+
+        let session = self
+            .user_sessions
+            .get(&query)
+            .await
+            .ok_or(ChatError::SessionNotExists)?;
+
+        let mut platforms = *session.extends.platforms.val();
+        platforms.add(&add_platforms);
+        session.extends.platforms.set(platforms.into());
+
+        info!(
+            target: LOG_TARGET,
+            "User {}({}) joined platforms: {:?} ",
+            session.username.load(),
+            session.user_id,
+            add_platforms.platforms_array()
+        );
+
+        Ok(ExecSuccess::default())
+    }
+
+    async fn update_silence_end(
+        &self,
+        query: UserQuery,
+        silence_end: i64,
+    ) -> Result<ExecSuccess, ChatError> {
+        let session = self
+            .user_sessions
+            .get(&query)
+            .await
+            .ok_or(ChatError::SessionNotExists)?;
+
+        session.extends.silence_end.set(silence_end);
+
+        Ok(ExecSuccess::default())
+    }
+
     async fn send_message(
         &self,
         request: SendMessageRequest,
     ) -> Result<SendMessageResponse, ChatError> {
-        const LOG_TARGET: &str = "chat::send_message";
+        lock_order::scope(self.send_message_inner(request)).await
+    }
 
-        let SendMessageRequest { sender, message, target } = request;
+    async fn join_channel(
+        &self,
+        request: JoinChannelRequest,
+    ) -> Result<ExecSuccess, ChatError> {
+        lock_order::scope(self.join_channel_inner(request)).await
+    }
 
-        let sender_query =
-            sender.ok_or(ChatError::InvalidArgument)?.into_user_query()?;
+    async fn leave_channel(
+        &self,
+        request: LeaveChannelRequest,
+    ) -> Result<ExecSuccess, ChatError> {
+        lock_order::scope(self.leave_channel_inner(request)).await
+    }
 
-        let target =
-            target.ok_or(ChatError::InvalidArgument)?.into_message_target()?;
+    async fn dequeue_chat_packets(
+        &self,
+        query: UserQuery,
+    ) -> Result<BanchoPackets, ChatError> {
+        lock_order::scope(self.dequeue_packets(query)).await
+    }
 
-        let sender =
-            self.get_session(&sender_query, Some(Platform::all())).await?;
+    async fn wait_chat_packets(
+        &self,
+        query: UserQuery,
+        wait: Duration,
+    ) -> Result<BanchoPackets, ChatError> {
+        let session = self.get_session(&query, Some(Platform::Bancho)).await?;
 
-        match target {
-            ChatMessageTarget::Channel(channel_query) => {
-                // get channel
-                let channel =
-                    match self.channels.get_channel(&channel_query).await {
-                        Some(channel) => channel,
-                        None => {
-                            todo!("channel not exists")
-                        },
-                    };
+        if let Some(bancho_ext) = session.extends.bancho_ext.load_full() {
+            // Woken by the channel messages and the private messages
+            let _ =
+                tokio::time::timeout(wait, bancho_ext.packets_queue.notified())
+                    .await;
+        }
 
-                let message_packet = server::SendMessage::pack(
-                    sender.username.load().as_ref().into(),
-                    Cow::Borrowed(message.as_ref()),
-                    channel.name.load().as_ref().into(),
-                    sender.user_id,
-                )
-                .into();
+        self.dequeue_chat_packets(query).await
+    }
 
-                // push msg into channel packets queue
-                channel.message_queue.write().await.push_message_excludes(
-                    Packet::Ptr(message_packet),
-                    [sender.user_id],
-                    None,
-                );
+    async fn load_public_channels(&self) -> Result<ExecSuccess, ChatError> {
+        const LOG_TARGET: &str = "chat::channel::initialize_public_channels";
 
-                info!(
-                    target: LOG_TARGET,
-                    "{}({}) @ {}({}): {}",
-                    sender.username.load(),
-                    sender.user_id,
-                    channel.name.load(),
-                    channel.id,
-                    message
-                );
-            },
-            ChatMessageTarget::User(target_query) => {
-                // get target user session
-                match self.get_session(&target_query, None).await.ok() {
-                    Some(target_user) => {
-                        // push msg packet if target user's bancho packets queue is exists
-                        if let Some(bancho_ext) =
-                            target_user.extends.bancho_ext.load().as_ref()
-                        {
-                            bancho_ext
-                                .packets_queue
-                                .push_packet(
-                                    server::SendMessage::pack(
-                                        sender.username.load().as_ref().into(),
-                                        Cow::Borrowed(message.as_ref()),
-                                        target_user
-                                            .username
-                                            .load()
-                                            .as_ref()
-                                            .into(),
-                                        sender.user_id,
-                                    )
-                                    .into(),
-                                )
-                                .await;
-                        }
+        let mut public_channels = self
+            .channels_repository
+            .get_public_channels()
+            .await
+            .map_err(|err| ChatError::DbError(err.to_string()))?;
+
+        // seed the default channels on the first start
+        if public_channels.is_empty() {
+            for (name, description, auto_join) in [
+                ("#osu", "default channel", true),
+                ("#peace", "peace channel", false),
+            ] {
+                let (channel, _) = self
+                    .channels_repository
+                    .create_channel(CreateChannel {
+                        name: name.to_owned(),
+                        description: Some(description.to_owned()),
+                        auto_join,
+                        ..Default::default()
+                    })
+                    .await?;
+
+                public_channels.push(channel);
+            }
+        }
 
-                        info!(
-                            target: LOG_TARGET,
-                            "{}({}) @ {}({}): {}",
-                            sender.username.load(),
-                            sender.user_id,
-                            target_user.username.load(),
-                            target_user.user_id,
-                            message
-                        );
-                    },
-                    None => {
-                        todo!("offline msg handle")
+        let channel_privileges = self
+            .channels_repository
+            .get_channel_privileges()
+            .await
+            .map_err(|err| ChatError::DbError(err.to_string()))?;
+
+        let () = {
+            let mut indexes = self.channels.write().await;
+            for channel in
+                public_channels.into_iter().filter_map(public_channel)
+            {
+                // loaded again to refresh the channel configs
+                match self.channels.get_channel_inner(
+                    &indexes,
+                    &ChannelQuery::ChannelId(channel.id),
+                ) {
+                    Some(exists) => {
+                        exists.auto_join.set(channel.auto_join.val());
                     },
-                };
-            },
+                    None => self.channels.create_channel_inner(
+                        &mut indexes,
+                        channel.into(),
+                        false,
+                    ),
+                }
+            }
+
+            for channel in indexes.public_channels.values() {
+                set_channel_privileges(channel, &channel_privileges);
+            }
+        };
+
+        info!(target: LOG_TARGET, "Public channels successfully initialized.",);
+
+        Ok(ExecSuccess::default())
+    }
+
+    async fn get_public_channels(
+        &self,
+    ) -> Result<GetPublicChannelsResponse, ChatError> {
+        let channel_indexes = self.channels.read().await;
+
+        let mut channels =
+            Vec::with_capacity(channel_indexes.public_channels.len());
+        for ch in channel_indexes.public_channels.values() {
+            channels.push(ch.channel_info().await);
         }
 
-        Ok(SendMessageResponse::default())
+        Ok(GetPublicChannelsResponse { channels })
     }
 
-    async fn join_channel(
+    async fn delete_message(
         &self,
-        request: JoinChannelRequest,
+        request: ModerateMessageRequest,
     ) -> Result<ExecSuccess, ChatError> {
-        let JoinChannelRequest { channel_query, user_query } = request;
-
-        let user_query =
-            user_query.ok_or(ChatError::InvalidArgument)?.into_user_query()?;
+        const LOG_TARGET: &str = "chat::delete_message";
 
-        let channel_query = channel_query
-            .ok_or(ChatError::InvalidArgument)?
-            .into_channel_query()?;
+        let ModerateMessageRequest { message_id, moderator_id } = request;
 
-        let session =
-            self.get_session(&user_query, Some(Platform::all())).await?;
+        self.check_moderator(moderator_id).await?;
 
-        let channel = match self.channels.get_channel(&channel_query).await {
-            Some(channel) => channel,
-            None => {
-                todo!("channel not exists")
-            },
-        };
+        let message = self
+            .channels_repository
+            .delete_message(message_id as i64)
+            .await
+            .map_err(|err| ChatError::DbError(err.to_string()))?
+            .ok_or(ChatError::MessageNotExists)?;
 
-        // add user into channel
-        Channel::join(&session, &channel).await;
+        info!(
+            target: LOG_TARGET,
+            "Message ({}) of user ({}) deleted by ({})",
+            message.id,
+            message.sender_id,
+            moderator_id
+        );
 
-        // update channel
-        channel.updated_at.set(Utc::now().into());
+        self.notify_user(
+            message.sender_id,
+            "Your message has been deleted by a moderator.",
+        )
+        .await;
 
         Ok(ExecSuccess::default())
     }
 
-    async fn leave_channel(
+    async fn redact_message(
         &self,
-        request: LeaveChannelRequest,
+        request: ModerateMessageRequest,
     ) -> Result<ExecSuccess, ChatError> {
-        let LeaveChannelRequest { channel_query, user_query } = request;
-
-        let user_query =
-            user_query.ok_or(ChatError::InvalidArgument)?.into_user_query()?;
-
-        let channel_query = channel_query
-            .ok_or(ChatError::InvalidArgument)?
-            .into_channel_query()?;
+        const LOG_TARGET: &str = "chat::redact_message";
 
-        let session =
-            self.get_session(&user_query, Some(Platform::all())).await?;
+        let ModerateMessageRequest { message_id, moderator_id } = request;
 
-        let channel = match self.channels.get_channel(&channel_query).await {
-            Some(channel) => channel,
-            None => {
-                todo!("channel not exists")
-            },
-        };
+        self.check_moderator(moderator_id).await?;
 
-        // remove user from channel
-        Channel::remove(&session, &channel).await;
+        let message = self
+            .channels_repository
+            .redact_message(message_id as i64)
+            .await
+            .map_err(|err| ChatError::DbError(err.to_string()))?
+            .ok_or(ChatError::MessageNotExists)?;
 
-        // update channel
-        channel.updated_at.set(Utc::now().into());
+        info!(
+            target: LOG_TARGET,
+            "Message ({}) of user ({}) redacted by ({})",
+            message.id,
+            message.sender_id,
+            moderator_id
+        );
+
+        self.notify_user(
+            message.sender_id,
+            "Your message has been redacted by a moderator.",
+        )
+        .await;
 
         Ok(ExecSuccess::default())
     }
+}
 
-    async fn dequeue_chat_packets(
+#[async_trait]
+impl ChannelService for ChatServiceImpl {
+    async fn create_channel(
         &self,
-        query: UserQuery,
-    ) -> Result<BanchoPackets, ChatError> {
-        let session = self.get_session(&query, Some(Platform::Bancho)).await?;
+        request: CreateChannelRequest,
+    ) -> Result<ChannelInfo, ChatError> {
+        const LOG_TARGET: &str = "chat::channel::create";
 
-        let bancho_ext = match session.extends.bancho_ext.load_full() {
-            Some(bancho_ext) => bancho_ext,
-            None => todo!("invalid call"),
-        };
+        let CreateChannelRequest { id, name, channel_type, description, users } =
+            request;
 
-        let mut data = Vec::new();
+        let channel_type = ChannelType::from_i32(channel_type)
+            .ok_or(ChatError::InvalidArgument)?;
 
-        // receive global notify from queue
-        if let Some(ReceivedMessages { messages, last_msg_id }) = self
-            .notify_queue
-            .read()
-            .await
-            .receive_messages(
-                &session.user_id,
-                &bancho_ext.notify_index.load(),
-                None,
-            )
+        let channel = match self
+            .channels
+            .get_channel(&ChannelQuery::ChannelId(id))
             .await
         {
-            for packet in messages {
-                data.extend(packet);
-            }
+            Some(channel) => channel,
+            None => {
+                let channel = self
+                    .channels
+                    .create_channel(
+                        Channel::new(id, name, channel_type, description, None),
+                        false,
+                    )
+                    .await;
 
-            bancho_ext.notify_index.set(last_msg_id.into());
-        }
+                info!(
+                    target: LOG_TARGET,
+                    "Channel created: {}({})",
+                    channel.name.load(),
+                    channel.id
+                );
 
-        // get user's joined channels
-        let joined_channels = {
-            session
-                .extends
-                .joined_channels
-                .read()
-                .await
-                .iter()
-                .map(|(channel_id, channel)| (*channel_id, channel.clone()))
-                .collect::<Vec<(u64, Arc<JoinedChannel>)>>()
+                channel
+            },
         };
 
-        let mut invalid_channels = Vec::new();
-
-        // receive msg from each channels, and mark invalid channels ptr
-        for (channel_id, joined_channel) in joined_channels {
-            match joined_channel.ptr.load().upgrade() {
-                Some(channel) => {
-                    if let Some(ReceivedMessages { messages, last_msg_id }) =
-                        channel
-                            .message_queue
-                            .read()
-                            .await
-                            .receive_messages(
-                                &session.user_id,
-                                &joined_channel.message_index.load(),
-                                None,
-                            )
-                            .await
-                    {
-                        for packet in messages {
-                            data.extend(packet);
-                        }
+        for user_id in users {
+            if channel.users.read().await.contains_key(&user_id) {
+                continue;
+            }
 
-                        match channel.min_msg_index.load().as_deref() {
-                            Some(prev_channel_min_msg_id) => {
-                                if &last_msg_id < prev_channel_min_msg_id {
-                                    channel
-                                        .min_msg_index
-                                        .set(Some(last_msg_id.into()))
-                                }
-                            },
-                            None => channel
-                                .min_msg_index
-                                .set(Some(last_msg_id.into())),
-                        };
+            let session = self
+                .get_session(&UserQuery::UserId(user_id), Some(Platform::all()))
+                .await?;
 
-                        joined_channel.message_index.set(last_msg_id.into());
-                    }
-                },
-                None => invalid_channels.push(channel_id),
-            }
+            Channel::join(&session, &channel, self.join_backlog).await;
         }
 
-        // remove invalid channels
-        if !invalid_channels.is_empty() {
-            let mut joined_channels =
-                session.extends.joined_channels.write().await;
+        channel.updated_at.set(Utc::now().into());
 
-            for channel_id in invalid_channels {
-                joined_channels.remove(&channel_id);
-            }
-        }
+        Ok(channel.channel_info().await)
+    }
 
-        // TODO: real accessable check
-        let accessable_channels = {
-            self.channels
-                .read()
-                .await
-                .values()
-                .cloned()
-                .collect::<Vec<Arc<Channel>>>()
-        };
+    async fn create_public_channel(
+        &self,
+        request: CreatePublicChannelRequest,
+    ) -> Result<ChannelInfo, ChatError> {
+        const LOG_TARGET: &str = "chat::channel::create_public";
+
+        let CreatePublicChannelRequest {
+            name,
+            description,
+            read_privilege_id,
+            write_privilege_id,
+            auto_join,
+        } = request;
 
-        // receive latest channels info
-        let () = {
-            let mut receive_channel_updates =
-                bancho_ext.receive_channel_updates.lock().await;
+        if !is_public_channel_name(&name) {
+            return Err(ChatError::InvalidArgument);
+        }
 
-            for ch in accessable_channels {
-                if let Some(date) = receive_channel_updates.get(&ch.id) {
-                    // prevent multiple reception
-                    if date == ch.updated_at.load().as_ref() {
-                        continue;
-                    }
-                }
+        if self
+            .channels
+            .is_channel_exists(&ChannelQuery::ChannelName(name.clone()))
+            .await
+        {
+            return Err(CreateChannelError::ChannelExists.into());
+        }
 
-                // send channel info
-                data.extend(ch.info_packets());
+        let (model, privileges) = self
+            .channels_repository
+            .create_channel(CreateChannel {
+                name,
+                description,
+                auto_join,
+                read_privilege_id,
+                write_privilege_id,
+            })
+            .await?;
 
-                // update receive
-                receive_channel_updates
-                    .insert(ch.id, ch.updated_at.load().as_ref().clone());
-            }
-        };
+        let channel =
+            public_channel(model).ok_or(ChatError::InvalidArgument)?;
+        set_channel_privileges(&channel, &privileges);
 
-        // receive msg from session queue
-        data.extend(bancho_ext.packets_queue.dequeue_all_packets(None).await);
+        let channel = self.channels.create_channel(channel, false).await;
 
-        Ok(BanchoPackets { data })
-    }
+        info!(
+            target: LOG_TARGET,
+            "Public channel created: {}({})",
+            channel.name.load(),
+            channel.id
+        );
 
-    async fn load_public_channels(&self) -> Result<ExecSuccess, ChatError> {
-        const LOG_TARGET: &str = "chat::channel::initialize_public_channels";
+        Ok(channel.channel_info().await)
+    }
 
-        // todo: load public channels from database
-        let public_channels = vec![
-            Channel::new(
-                0,
-                "#osu".to_string(),
-                ChannelType::Public,
-                Some("default channel".to_string()),
-                None,
-            ),
-            Channel::new(
-                1,
-                "#peace".to_string(),
-                ChannelType::Public,
-                Some("peace channel".to_string()),
-                None,
-            ),
-        ];
+    async fn delete_channel(
+        &self,
+        channel_query: ChannelQuery,
+    ) -> Result<ExecSuccess, ChatError> {
+        let channel = self
+            .channels
+            .get_channel(&channel_query)
+            .await
+            .ok_or(ChatError::ChannelNotExists)?;
 
-        let () = {
-            let mut indexes = self.channels.write().await;
-            for channel in public_channels {
-                self.channels.create_channel_inner(
-                    &mut indexes,
-                    channel.into(),
-                    false,
-                );
-            }
-        };
+        // kick the members with `ChannelKick` packets
+        self.channels.dispose_channel(&channel).await;
 
-        info!(target: LOG_TARGET, "Public channels successfully initialized.",);
+        if channel.channel_type == ChannelType::Public {
+            self.channels_repository
+                .delete_channel(channel.id as i64)
+                .await
+                .map_err(|err| ChatError::DbError(err.to_string()))?;
+        }
 
         Ok(ExecSuccess::default())
     }
 
-    async fn get_public_channels(
+    async fn get_channel_members(
         &self,
-    ) -> Result<GetPublicChannelsResponse, ChatError> {
-        fn to_channel_info(ch: &Arc<Channel>) -> ChannelInfo {
-            ChannelInfo {
-                id: ch.id,
-                name: ch.name.to_string(),
-                channel_type: ch.channel_type as i32,
-                description: ch
-                    .description
-                    .load()
-                    .as_deref()
-                    .map(|s| s.to_string()),
-                online_users: ch.user_count.val(),
-                users: None,
-            }
-        }
+        channel_query: ChannelQuery,
+        platforms: Platform,
+    ) -> Result<Vec<i32>, ChatError> {
+        let channel = self
+            .channels
+            .get_channel(&channel_query)
+            .await
+            .ok_or(ChatError::ChannelNotExists)?;
 
-        let channel_indexes = self.channels.read().await;
+        Ok(channel.members(platforms).await)
+    }
 
-        let res = GetPublicChannelsResponse {
-            channels: channel_indexes
-                .public_channels
-                .values()
-                .map(to_channel_info)
-                .collect(),
-        };
+    async fn get_user_channels(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<ChannelInfo>, ChatError> {
+        let session = self
+            .user_sessions
+            .get(&UserQuery::UserId(user_id))
+            .await
+            .ok_or(ChatError::SessionNotExists)?;
+
+        let joined_channels = session.joined_channels().await;
+
+        let mut channels = Vec::with_capacity(joined_channels.len());
+        for channel in joined_channels.iter() {
+            channels.push(channel.channel_info().await);
+        }
 
-        Ok(res)
+        Ok(channels)
     }
 }
 
 #[derive(Clone)]
 pub struct ChatServiceRemote {
-    pub client: ChatRpcClient<RpcChannel>,
+    pub client: ChatRpcClient<TracedChannel>,
 }
 
 impl FromRpcClient for ChatServiceRemote {
@@ -833,7 +1410,7 @@ impl FromRpcClient for ChatServiceRemote {
 }
 
 impl RpcClient for ChatServiceRemote {
-    type Client = ChatRpcClient<RpcChannel>;
+    type Client = ChatRpcClient<TracedChannel>;
 
     #[inline]
     fn client(&self) -> Self::Client {
@@ -854,6 +1431,66 @@ impl NotifyMessagesQueue for ChatServiceRemote {}
 
 impl ChannelStore for ChatServiceRemote {}
 
+#[async_trait]
+impl ChannelService for ChatServiceRemote {
+    async fn create_channel(
+        &self,
+        request: CreateChannelRequest,
+    ) -> Result<ChannelInfo, ChatError> {
+        Ok(self
+            .client()
+            .create_channel(request.into_request())
+            .await?
+            .into_inner())
+    }
+
+    async fn create_public_channel(
+        &self,
+        request: CreatePublicChannelRequest,
+    ) -> Result<ChannelInfo, ChatError> {
+        Ok(self
+            .client()
+            .create_public_channel(request.into_request())
+            .await?
+            .into_inner())
+    }
+
+    async fn delete_channel(
+        &self,
+        channel_query: ChannelQuery,
+    ) -> Result<ExecSuccess, ChatError> {
+        let req =
+            DeleteChannelRequest { channel_query: Some(channel_query.into()) };
+
+        Ok(self.client().delete_channel(req).await?.into_inner())
+    }
+
+    async fn get_channel_members(
+        &self,
+        channel_query: ChannelQuery,
+        platforms: Platform,
+    ) -> Result<Vec<i32>, ChatError> {
+        let req = GetChannelMembersRequest {
+            channel_query: Some(channel_query.into()),
+            platforms: platforms.bits(),
+        };
+
+        Ok(self.client().get_channel_members(req).await?.into_inner().users)
+    }
+
+    async fn get_user_channels(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<ChannelInfo>, ChatError> {
+        Ok(self
+            .client()
+            .get_user_channels(GetUserChannelsRequest { user_id })
+            .await?
+            .into_inner()
+            .channels)
+    }
+}
+
 #[async_trait]
 impl CreateSnapshot<ChatServiceSnapshot> for ChatServiceRemote {
     async fn create_snapshot(&self) -> ChatServiceSnapshot {
@@ -895,6 +1532,34 @@ impl ChatService for ChatServiceRemote {
         Ok(self.client().logout(req).await?.into_inner())
     }
 
+    async fn join_platforms(
+        &self,
+        query: UserQuery,
+        platforms: Platform,
+    ) -> Result<ExecSuccess, ChatError> {
+        let req = JoinPlatformsRequest {
+            user_query: Some(query.into()),
+            platforms: platforms.bits(),
+        }
+        .into_request();
+
+        Ok(self.client().join_platforms(req).await?.into_inner())
+    }
+
+    async fn update_silence_end(
+        &self,
+        query: UserQuery,
+        silence_end: i64,
+    ) -> Result<ExecSuccess, ChatError> {
+        let req = UpdateSilenceEndRequest {
+            user_query: Some(query.into()),
+            silence_end,
+        }
+        .into_request();
+
+        Ok(self.client().update_silence_end(req).await?.into_inner())
+    }
+
     async fn send_message(
         &self,
         request: SendMessageRequest,
@@ -906,6 +1571,28 @@ impl ChatService for ChatServiceRemote {
             .into_inner())
     }
 
+    async fn delete_message(
+        &self,
+        request: ModerateMessageRequest,
+    ) -> Result<ExecSuccess, ChatError> {
+        Ok(self
+            .client()
+            .delete_message(request.into_request())
+            .await?
+            .into_inner())
+    }
+
+    async fn redact_message(
+        &self,
+        request: ModerateMessageRequest,
+    ) -> Result<ExecSuccess, ChatError> {
+        Ok(self
+            .client()
+            .redact_message(request.into_request())
+            .await?
+            .into_inner())
+    }
+
     async fn join_channel(
         &self,
         request: JoinChannelRequest,
@@ -939,6 +1626,20 @@ impl ChatService for ChatServiceRemote {
             .into_inner())
     }
 
+    async fn wait_chat_packets(
+        &self,
+        query: UserQuery,
+        wait: Duration,
+    ) -> Result<BanchoPackets, ChatError> {
+        let req = WaitChatPacketsRequest {
+            user_query: Some(query.into()),
+            wait_ms: wait.as_millis() as u32,
+        }
+        .into_request();
+
+        Ok(self.client().wait_chat_packets(req).await?.into_inner())
+    }
+
     async fn load_public_channels(&self) -> Result<ExecSuccess, ChatError> {
         Ok(self
             .client()
@@ -957,3 +1658,67 @@ impl ChatService for ChatServiceRemote {
             .into_inner())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use peace_db::peace::entity::sea_orm_active_enums;
+
+    fn model(id: i64, name: Option<&str>) -> channels::Model {
+        channels::Model {
+            id,
+            channel_type: sea_orm_active_enums::ChannelType::Public,
+            name: name.map(str::to_owned),
+            description: Some("custom channel".to_owned()),
+            icon: None,
+            auto_join: true,
+            creator_id: None,
+        }
+    }
+
+    #[test]
+    fn test_is_public_channel_name() {
+        assert!(is_public_channel_name("#custom"));
+        assert!(!is_public_channel_name("#"));
+        assert!(!is_public_channel_name("custom"));
+        assert!(!is_public_channel_name(""));
+    }
+
+    #[test]
+    fn test_public_channel() {
+        let channel = public_channel(model(10, Some("#custom"))).unwrap();
+        assert_eq!(channel.id, 10);
+        assert_eq!(channel.name.load().as_str(), "#custom");
+        assert_eq!(channel.channel_type, ChannelType::Public);
+        assert!(channel.auto_join.is_true());
+
+        assert!(public_channel(model(11, None)).is_none());
+    }
+
+    #[test]
+    fn test_set_channel_privileges() {
+        let channel = public_channel(model(10, Some("#custom"))).unwrap();
+
+        let privilege = |channel_id, handle, required_priority| {
+            ChannelPrivilege { channel_id, handle, required_priority }
+        };
+
+        set_channel_privileges(
+            &channel,
+            &[
+                privilege(10, ChannelHandleType::Join, 10),
+                privilege(10, ChannelHandleType::SendMessage, 20),
+                // other handles and channels are ignored
+                privilege(10, ChannelHandleType::KickUser, 30),
+                privilege(11, ChannelHandleType::Join, 40),
+            ],
+        );
+        assert_eq!(channel.read_privilege.val(), 10);
+        assert_eq!(channel.write_privilege.val(), 20);
+
+        // the privileges removed from the table are reset
+        set_channel_privileges(&channel, &[]);
+        assert_eq!(channel.read_privilege.val(), 0);
+        assert_eq!(channel.write_privilege.val(), 0);
+    }
+}
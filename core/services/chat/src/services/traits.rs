@@ -8,7 +8,7 @@ use pb_chat::*;
 use peace_message_queue::{MessageData, MessageQueue};
 use peace_snapshot::{CreateSnapshot, SaveSnapshotTo};
 use peace_unique_id::Ulid;
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use tonic::async_trait;
 
 pub type BanchoMessageQueue = MessageQueue<Packet, i32, Ulid>;
@@ -42,6 +42,7 @@ pub trait ChatService:
     UserSessionsStore
     + NotifyMessagesQueue
     + ChannelStore
+    + ChannelService
     + CreateSnapshot<ChatServiceSnapshot>
     + SaveSnapshotTo<ChatServiceSnapshot>
     + ServiceSnapshot
@@ -57,11 +58,40 @@ pub trait ChatService:
         remove_platforms: Platform,
     ) -> Result<ExecSuccess, ChatError>;
 
+    /// Tag an online session with more platforms, it is removed from them
+    /// with [`ChatService::logout`].
+    async fn join_platforms(
+        &self,
+        query: UserQuery,
+        add_platforms: Platform,
+    ) -> Result<ExecSuccess, ChatError>;
+
+    /// Refuse the messages of an online session until `silence_end` (unix
+    /// secs), `0` lifts the silence.
+    async fn update_silence_end(
+        &self,
+        query: UserQuery,
+        silence_end: i64,
+    ) -> Result<ExecSuccess, ChatError>;
+
     async fn send_message(
         &self,
         request: SendMessageRequest,
     ) -> Result<SendMessageResponse, ChatError>;
 
+    /// Hide a stored message from the history, the sender is notified if
+    /// online since the osu! client can not unsend messages.
+    async fn delete_message(
+        &self,
+        request: ModerateMessageRequest,
+    ) -> Result<ExecSuccess, ChatError>;
+
+    /// Clear the content of a stored message, which stays in the history.
+    async fn redact_message(
+        &self,
+        request: ModerateMessageRequest,
+    ) -> Result<ExecSuccess, ChatError>;
+
     async fn join_channel(
         &self,
         request: JoinChannelRequest,
@@ -77,6 +107,14 @@ pub trait ChatService:
         query: UserQuery,
     ) -> Result<BanchoPackets, ChatError>;
 
+    /// [`ChatService::dequeue_chat_packets`], waiting up to `wait` for a
+    /// message to be sent to the session when there are none yet.
+    async fn wait_chat_packets(
+        &self,
+        query: UserQuery,
+        wait: Duration,
+    ) -> Result<BanchoPackets, ChatError>;
+
     async fn load_public_channels(&self) -> Result<ExecSuccess, ChatError>;
 
     async fn get_public_channels(
@@ -85,7 +123,39 @@ pub trait ChatService:
 }
 
 #[async_trait]
-pub trait ChannelService {}
+pub trait ChannelService {
+    /// Create the channel if not exists, then join the users not in it yet.
+    async fn create_channel(
+        &self,
+        request: CreateChannelRequest,
+    ) -> Result<ChannelInfo, ChatError>;
+
+    /// Create a public channel stored in the database, its name must be
+    /// unique.
+    async fn create_public_channel(
+        &self,
+        request: CreatePublicChannelRequest,
+    ) -> Result<ChannelInfo, ChatError>;
+
+    /// Kick all the members, then delete the channel.
+    async fn delete_channel(
+        &self,
+        channel_query: ChannelQuery,
+    ) -> Result<ExecSuccess, ChatError>;
+
+    /// Ids of the channel members online on one of the given platforms.
+    async fn get_channel_members(
+        &self,
+        channel_query: ChannelQuery,
+        platforms: Platform,
+    ) -> Result<Vec<i32>, ChatError>;
+
+    /// Channels joined by the user, on any platform.
+    async fn get_user_channels(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<ChannelInfo>, ChatError>;
+}
 
 #[async_trait]
 pub trait ChatBackgroundService {
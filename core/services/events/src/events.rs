@@ -13,7 +13,10 @@ use tokio::{
 };
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::Status;
-use tools::atomic::{AtomicOperation, Usize};
+use tools::{
+    atomic::{AtomicOperation, Usize},
+    tonic_utils::TracedChannel,
+};
 
 #[derive(Debug)]
 pub struct Subscription<T> {
@@ -206,10 +209,10 @@ impl EventsService for EventsServiceImpl {
 }
 
 /* #[derive(Debug, Clone)]
-pub struct EventsServiceRemote(EventsRpcClient<Channel>);
+pub struct EventsServiceRemote(EventsRpcClient<TracedChannel>);
 
 impl RpcClient for EventsServiceRemote {
-    type Client = EventsRpcClient<Channel>;
+    type Client = EventsRpcClient<TracedChannel>;
 
     #[inline]
     fn client(&self) -> Self::Client {
@@ -0,0 +1,122 @@
+use pb_bancho::{BeatmapScore, GetBeatmapScoresResponse};
+
+/// Rank status of a beatmap not submitted to the server.
+pub const NOT_SUBMITTED: i32 = -1;
+
+fn score_line(score: &BeatmapScore) -> String {
+    format!(
+        "{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|{}|1",
+        score.score_id,
+        score.username,
+        score.score,
+        score.max_combo,
+        score.n50,
+        score.n100,
+        score.n300,
+        score.miss,
+        score.katu,
+        score.geki,
+        score.perfect as u8,
+        score.mods,
+        score.user_id,
+        score.rank,
+        score.timestamp,
+    )
+}
+
+/// The leaderboard of a beatmap as read by the client: a header of `|`
+/// separated beatmap info, the offset, the beatmap name, the rating, the
+/// personal best (empty line if none) and one line per score.
+pub fn beatmap_scores(res: &GetBeatmapScoresResponse) -> String {
+    let beatmap = match res.beatmap.as_ref() {
+        Some(beatmap) => beatmap,
+        None => return format!("{NOT_SUBMITTED}|false"),
+    };
+
+    let header = format!(
+        "{}|false|{}|{}|{}|0|",
+        beatmap.rank_status,
+        beatmap.beatmap_id,
+        beatmap.beatmapset_id,
+        res.scores.len()
+    );
+
+    let mut lines = vec![
+        header,
+        "0".to_owned(),
+        format!("{} - {} [{}]", beatmap.artist, beatmap.title, beatmap.version),
+        "0".to_owned(),
+        res.personal_best.as_ref().map(score_line).unwrap_or_default(),
+    ];
+    lines.extend(res.scores.iter().map(score_line));
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pb_bancho::BeatmapScoresInfo;
+
+    fn score(score_id: i64, user_id: i32, rank: u64) -> BeatmapScore {
+        BeatmapScore {
+            score_id,
+            user_id,
+            username: format!("user{user_id}"),
+            score: 1000,
+            max_combo: 100,
+            n300: 98,
+            n100: 2,
+            perfect: true,
+            mods: 8,
+            rank,
+            timestamp: 1672531200,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_beatmap_scores() {
+        assert_eq!(
+            beatmap_scores(&GetBeatmapScoresResponse::default()),
+            "-1|false"
+        );
+
+        let res = GetBeatmapScoresResponse {
+            beatmap: Some(BeatmapScoresInfo {
+                beatmap_id: 1,
+                beatmapset_id: 2,
+                rank_status: 2,
+                artist: "Artist".into(),
+                title: "Title".into(),
+                version: "Insane".into(),
+            }),
+            scores: vec![score(10, 3, 1), score(11, 4, 2)],
+            personal_best: Some(score(11, 4, 2)),
+        };
+        let lines = beatmap_scores(&res)
+            .split('\n')
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], "2|false|1|2|2|0|");
+        assert_eq!(lines[2], "Artist - Title [Insane]");
+        assert_eq!(
+            lines[4],
+            "11|user4|1000|100|0|2|98|0|0|0|1|8|4|2|1672531200|1"
+        );
+        assert!(lines[5].starts_with("10|user3|"));
+        assert_eq!(lines[6], lines[4]);
+
+        // no personal best
+        let lines = beatmap_scores(&GetBeatmapScoresResponse {
+            personal_best: None,
+            ..res
+        })
+        .split('\n')
+        .map(String::from)
+        .collect::<Vec<_>>();
+        assert_eq!(lines[4], "");
+    }
+}
@@ -0,0 +1,255 @@
+use clap_serde_derive::ClapSerde;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Parser, ClapSerde, Serialize, Deserialize)]
+pub struct CliBanchoClientVersionConfigs {
+    /// Oldest client build date allowed to login, e.g. `20230101`, older
+    /// clients are asked to update.
+    #[arg(long)]
+    pub bancho_min_client_version: Option<u32>,
+
+    /// Client versions refused at login (e.g. known cheat builds), comma
+    /// separated, e.g. `b20230101.2cuttingedge,b20220101`.
+    #[default(Vec::new())]
+    #[arg(long, value_delimiter = ',')]
+    pub bancho_blocked_client_versions: Vec<String>,
+}
+
+/// Release stream of a client, given by the version suffix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ClientStream {
+    Stable,
+    Beta,
+    CuttingEdge,
+    Tourney,
+    Dev,
+}
+
+impl ClientStream {
+    #[inline]
+    pub fn from_suffix(suffix: &str) -> Option<Self> {
+        Some(match suffix.to_ascii_lowercase().as_str() {
+            "" => Self::Stable,
+            "beta" => Self::Beta,
+            "cuttingedge" => Self::CuttingEdge,
+            "tourney" => Self::Tourney,
+            "dev" => Self::Dev,
+            _ => return None,
+        })
+    }
+}
+
+/// Client version parsed from its `b<build date>[.<patch>][<stream>]`
+/// string, e.g. `b20230101.2cuttingedge`.
+///
+/// Versions are ordered by build date then patch, [`Self::Unknown`] is
+/// older than any known version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ClientVersion {
+    Unknown,
+    Known { build_date: u32, patch: u32, stream: ClientStream },
+}
+
+impl ClientVersion {
+    pub fn parse(version: &str) -> Self {
+        let version = version.trim();
+        let version = version.strip_prefix('b').unwrap_or(version);
+
+        let (build_date, rest) = match version.get(..8).zip(version.get(8..)) {
+            Some((date, rest)) if date.bytes().all(|c| c.is_ascii_digit()) => {
+                match date.parse() {
+                    Ok(date) => (date, rest),
+                    Err(_) => return Self::Unknown,
+                }
+            },
+            _ => return Self::Unknown,
+        };
+
+        let (patch, suffix) = match rest.strip_prefix('.') {
+            Some(rest) => {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                match rest[..end].parse() {
+                    Ok(patch) => (patch, &rest[end..]),
+                    Err(_) => return Self::Unknown,
+                }
+            },
+            None => (0, rest),
+        };
+
+        match ClientStream::from_suffix(suffix) {
+            Some(stream) => Self::Known { build_date, patch, stream },
+            None => Self::Unknown,
+        }
+    }
+
+    #[inline]
+    pub fn build_date(&self) -> Option<u32> {
+        match self {
+            Self::Known { build_date, .. } => Some(*build_date),
+            Self::Unknown => None,
+        }
+    }
+
+    #[inline]
+    pub fn stream(&self) -> Option<ClientStream> {
+        match self {
+            Self::Known { stream, .. } => Some(*stream),
+            Self::Unknown => None,
+        }
+    }
+}
+
+impl From<&str> for ClientVersion {
+    #[inline]
+    fn from(version: &str) -> Self {
+        Self::parse(version)
+    }
+}
+
+/// Client versions allowed to login.
+#[derive(Debug, Default, Clone)]
+pub struct ClientVersionPolicy {
+    pub min_build_date: Option<u32>,
+    pub blocked_versions: HashSet<String>,
+}
+
+impl ClientVersionPolicy {
+    #[inline]
+    pub fn new(
+        min_build_date: Option<u32>,
+        blocked_versions: impl IntoIterator<Item = String>,
+    ) -> Self {
+        Self {
+            min_build_date,
+            blocked_versions: blocked_versions
+                .into_iter()
+                .map(|v| v.trim().to_ascii_lowercase())
+                .filter(|v| !v.is_empty())
+                .collect(),
+        }
+    }
+
+    #[inline]
+    pub fn with_cfg(cfg: &CliBanchoClientVersionConfigs) -> Self {
+        Self::new(
+            cfg.bancho_min_client_version,
+            cfg.bancho_blocked_client_versions.iter().cloned(),
+        )
+    }
+
+    /// Malformed versions are only refused when a min version is
+    /// configured.
+    pub fn is_allowed(&self, version: &str) -> bool {
+        if self.blocked_versions.contains(&version.to_ascii_lowercase()) {
+            return false;
+        }
+
+        match self.min_build_date {
+            Some(min) => ClientVersion::parse(version)
+                .build_date()
+                .map_or(false, |date| date >= min),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_version_build_date() {
+        for (version, build_date) in [
+            ("b20230101", Some(20230101)),
+            ("b20230101.2", Some(20230101)),
+            ("b20230101.2cuttingedge", Some(20230101)),
+            ("b20230101beta", Some(20230101)),
+            ("b2023", None),
+            ("stable", None),
+        ] {
+            assert_eq!(
+                ClientVersion::parse(version).build_date(),
+                build_date,
+                "{version}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_client_version_policy() {
+        let policy = ClientVersionPolicy::new(
+            Some(20230101),
+            ["b20230505.1cuttingedge".to_owned()],
+        );
+
+        assert!(policy.is_allowed("b20230101"));
+        assert!(policy.is_allowed("b20230101.1tourney"));
+        assert!(policy.is_allowed("b20230505.2cuttingedge"));
+        assert!(!policy.is_allowed("b20221231.3"));
+        assert!(!policy.is_allowed("b20230505.1CuttingEdge"));
+        assert!(!policy.is_allowed("unknown"));
+        assert!(!policy.is_allowed("b20230505.1nightly"));
+
+        assert!(ClientVersionPolicy::default().is_allowed("unknown"));
+    }
+
+    #[test]
+    fn test_parse_client_version() {
+        use ClientStream::*;
+
+        for (version, build_date, patch, stream) in [
+            ("b20230101", 20230101, 0, Stable),
+            ("b20230326.3", 20230326, 3, Stable),
+            ("b20230418.1beta", 20230418, 1, Beta),
+            ("b20230522cuttingedge", 20230522, 0, CuttingEdge),
+            ("b20230531.2cuttingedge", 20230531, 2, CuttingEdge),
+            ("b20221012.1tourney", 20221012, 1, Tourney),
+            ("b20230101dev", 20230101, 0, Dev),
+            ("20230101.2", 20230101, 2, Stable),
+        ] {
+            assert_eq!(
+                ClientVersion::parse(version),
+                ClientVersion::Known { build_date, patch, stream },
+                "{version}"
+            );
+        }
+
+        for version in [
+            "",
+            "b",
+            "b2023",
+            "stable",
+            "b2023010a",
+            "b20230101.",
+            "b20230101x",
+        ] {
+            assert_eq!(ClientVersion::parse(version), ClientVersion::Unknown);
+        }
+    }
+
+    #[test]
+    fn test_client_version_ordering() {
+        let versions = [
+            "b20230101.2",
+            "b20221231.9",
+            "b20230101",
+            "b20230101.10",
+            "unknown",
+        ]
+        .map(ClientVersion::parse);
+
+        let mut sorted = versions;
+        sorted.sort();
+
+        assert_eq!(
+            sorted,
+            [versions[4], versions[1], versions[2], versions[0], versions[3]]
+        );
+        assert!(
+            ClientVersion::Unknown
+                < ClientVersion::parse("b20000101cuttingedge")
+        );
+    }
+}
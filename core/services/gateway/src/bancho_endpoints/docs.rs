@@ -1,11 +1,12 @@
 use utoipa::OpenApi;
 
-use super::routes::{bancho, debug};
+use super::routes::{admin, bancho, debug};
 
 #[derive(OpenApi)]
 #[openapi(paths(
     bancho::bancho_get,
     bancho::bancho_post,
+    bancho::bancho_ws,
     bancho::get_screenshot,
     bancho::download_beatmapset,
     bancho::client_register,
@@ -34,5 +35,18 @@ use super::routes::{bancho, debug};
 pub struct BanchoEndpointsDocs;
 
 #[derive(OpenApi)]
-#[openapi(paths(debug::test, debug::get_all_sessions,))]
+#[openapi(paths(
+    debug::test,
+    debug::get_all_sessions,
+    debug::get_sessions_page,
+    debug::metrics,
+    debug::packet_history,
+))]
 pub struct BanchoDebugEndpointsDocs;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(admin::announce, admin::users_sharing_hardware, admin::silence_user),
+    components(schemas(admin::AnnounceRequest, admin::SilenceRequest))
+)]
+pub struct BanchoAdminEndpointsDocs;
@@ -1,11 +1,16 @@
-use super::{CHO_PROTOCOL, CHO_TOKEN};
+use super::{
+    ReplayError, ScoreSubmissionError, ScreenshotError, CHO_PROTOCOL, CHO_TOKEN,
+};
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
-use bancho_packets::{server, PacketBuilder};
+use bancho_packets::{server, LoginFailedReason, PacketBuilder};
 use core_bancho::{BanchoServiceError, ProcessBanchoPacketError};
 use core_bancho_state::BanchoStateError;
+use domain_users::PasswordError;
+use peace_difficulty::DifficultyError;
+use peace_repositories::GetUserError;
 use std::string::FromUtf8Error;
 
 #[derive(thiserror::Error, Debug)]
@@ -28,10 +33,53 @@ pub enum LoginError {
     EmptyClientVersion,
     #[error("mismatched client version")]
     MismatchedClientVersion,
+    #[error("your client is not allowed, please update it")]
+    ClientVersionNotAllowed,
+    #[error("too many login attempts, please retry in {0} seconds")]
+    TooManyAttempts(u64),
+    #[error("incorrect username or password")]
+    InvalidCredentials,
+    #[error("your account is banned")]
+    Banned,
+    #[error("server error, please retry later")]
+    ServerError(#[source] BanchoServiceError),
+    #[error("server is restarting, please retry later")]
+    ShuttingDown,
     #[error(transparent)]
     ParseLoginDataError(#[from] ParseLoginDataError),
-    #[error(transparent)]
-    BanchoServiceError(#[from] BanchoServiceError),
+}
+
+impl LoginError {
+    /// The failure reason sent to the client in the login reply.
+    pub fn login_failed_reason(&self) -> LoginFailedReason {
+        match self {
+            Self::ClientVersionNotAllowed => LoginFailedReason::OutdatedClient,
+            Self::Banned => LoginFailedReason::UserBanned,
+            Self::ServerError(_) | Self::ShuttingDown => {
+                LoginFailedReason::ServerError
+            },
+            Self::EmptyClientVersion
+            | Self::MismatchedClientVersion
+            | Self::TooManyAttempts(_)
+            | Self::InvalidCredentials
+            | Self::ParseLoginDataError(_) => {
+                LoginFailedReason::InvalidCredentials
+            },
+        }
+    }
+}
+
+impl From<BanchoServiceError> for LoginError {
+    fn from(err: BanchoServiceError) -> Self {
+        match err {
+            BanchoServiceError::UserNotExists(GetUserError::UserNotExists)
+            | BanchoServiceError::PasswordError(
+                PasswordError::InvalidPassword,
+            ) => Self::InvalidCredentials,
+            BanchoServiceError::UserBanned => Self::Banned,
+            err => Self::ServerError(err),
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -46,21 +94,65 @@ pub enum BanchoHttpError {
     InvalidOsuVersionHeader,
     #[error("invalid `osu-token` header")]
     InvalidOsuTokenHeader,
+    #[error("session expired, please reconnect")]
+    SessionExpired,
+    #[error("unauthorized")]
+    Unauthorized,
     #[error("invalid `user-agent` header")]
     InvalidUserAgentHeader,
     #[error("failed to parse request")]
     ParseRequestError,
+    #[error("request body is too large")]
+    RequestTooLarge,
     #[error("invalid bancho packet")]
     InvalidBanchoPacket,
     #[error("failed to process bancho packets")]
     FailedToProcessBanchoPackets(#[from] ProcessBanchoPacketError),
     #[error(transparent)]
-    BanchoStateError(#[from] BanchoStateError),
+    BanchoStateError(BanchoStateError),
+    #[error(transparent)]
+    BanchoServiceError(#[from] BanchoServiceError),
+    #[error(transparent)]
+    ScreenshotError(#[from] ScreenshotError),
+    #[error(transparent)]
+    ReplayError(#[from] ReplayError),
+    #[error(transparent)]
+    DifficultyError(#[from] DifficultyError),
+    #[error(transparent)]
+    ScoreSubmissionError(#[from] ScoreSubmissionError),
+    #[error("`{0}` is not implemented")]
+    NotImplemented(&'static str),
+}
+
+impl From<BanchoStateError> for BanchoHttpError {
+    fn from(err: BanchoStateError) -> Self {
+        match err {
+            BanchoStateError::SessionNotExists
+            | BanchoStateError::SignatureError(..) => Self::SessionExpired,
+            err => Self::BanchoStateError(err),
+        }
+    }
 }
 
 impl BanchoHttpError {
     fn status_code(&self) -> StatusCode {
-        StatusCode::INTERNAL_SERVER_ERROR
+        match self {
+            Self::InvalidOsuTokenHeader | Self::Unauthorized => {
+                StatusCode::UNAUTHORIZED
+            },
+            Self::RequestTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::NotImplemented(_) => StatusCode::NOT_IMPLEMENTED,
+            Self::ScreenshotError(err) => err.status_code(),
+            Self::ReplayError(err) => err.status_code(),
+            Self::ScoreSubmissionError(err) => err.status_code(),
+            Self::DifficultyError(DifficultyError::BeatmapNotFound(_)) => {
+                StatusCode::NOT_FOUND
+            },
+            Self::DifficultyError(DifficultyError::InvalidMode(_)) => {
+                StatusCode::BAD_REQUEST
+            },
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
     }
 }
 
@@ -74,27 +166,23 @@ impl IntoResponse for BanchoHttpError {
     fn into_response(self) -> Response {
         match self {
             Self::LoginFailed(err) => {
-                let login_reply = match err {
-                    LoginError::BanchoServiceError(
-                        BanchoServiceError::TonicError(..)
-                        | BanchoServiceError::ChatError(..)
-                        | BanchoServiceError::BanchoStateError(..),
-                    ) => server::LoginReply::failed_server_error(),
-                    _ => server::LoginReply::failed_invalid_credentials(),
-                };
+                if let LoginError::ServerError(ref source) = err {
+                    warn!("[BanchoHttpError] Login failed: {source}");
+                }
 
                 let packets = PacketBuilder::new()
-                    .add(login_reply)
+                    .add(server::LoginReply::new(
+                        err.login_failed_reason().into(),
+                    ))
                     .add(server::Notification::new(err.to_string().into()))
                     .build();
 
                 ([(CHO_TOKEN, "failed"), CHO_PROTOCOL], packets).into_response()
             },
 
-            Self::BanchoStateError(
-                BanchoStateError::SessionNotExists
-                | BanchoStateError::SignatureError(..),
-            ) => {
+            // The client reconnects on restart, bancho responses must be
+            // `200` to be processed
+            Self::SessionExpired => {
                 (StatusCode::OK, server::BanchoRestart::pack(0)).into_response()
             },
 
@@ -104,6 +192,34 @@ impl IntoResponse for BanchoHttpError {
                     .into_response()
             },
 
+            // The client shows the error code to the player
+            Self::ScoreSubmissionError(err) => {
+                if let ScoreSubmissionError::ServerError(ref source) = err {
+                    warn!(
+                        "[BanchoHttpError] Score submission failed: {source}"
+                    );
+                }
+
+                (err.status_code(), format!("error: {}", err.response_code()))
+                    .into_response()
+            },
+
+            Self::InvalidOsuTokenHeader
+            | Self::Unauthorized
+            | Self::RequestTooLarge
+            | Self::NotImplemented(_)
+            | Self::ScreenshotError(
+                ScreenshotError::TooLarge(_)
+                | ScreenshotError::UnsupportedFormat
+                | ScreenshotError::NotExists
+                | ScreenshotError::TooManyScreenshots(_),
+            )
+            | Self::ReplayError(ReplayError::NotExists)
+            | Self::DifficultyError(
+                DifficultyError::BeatmapNotFound(_)
+                | DifficultyError::InvalidMode(_),
+            ) => (self.status_code(), self.to_string()).into_response(),
+
             _ => {
                 warn!("[BanchoHttpError] Unhandled error: {self:?}");
                 (self.status_code(), self.to_string()).into_response()
@@ -111,3 +227,107 @@ impl IntoResponse for BanchoHttpError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bancho_packets::{PacketId, PacketReader, PayloadReader};
+
+    #[test]
+    fn test_login_failed_reason() {
+        let cases = [
+            (
+                LoginError::InvalidCredentials,
+                LoginFailedReason::InvalidCredentials,
+            ),
+            (LoginError::Banned, LoginFailedReason::UserBanned),
+            (
+                LoginError::ServerError(BanchoServiceError::TonicError(
+                    "unavailable".into(),
+                )),
+                LoginFailedReason::ServerError,
+            ),
+            (
+                LoginError::TooManyAttempts(5),
+                LoginFailedReason::InvalidCredentials,
+            ),
+            (
+                LoginError::ClientVersionNotAllowed,
+                LoginFailedReason::OutdatedClient,
+            ),
+        ];
+
+        for (err, reason) in cases {
+            assert_eq!(err.login_failed_reason(), reason, "{err:?}");
+        }
+    }
+
+    #[test]
+    fn test_login_error_from_bancho_service_error() {
+        assert!(matches!(
+            LoginError::from(BanchoServiceError::PasswordError(
+                PasswordError::InvalidPassword
+            )),
+            LoginError::InvalidCredentials
+        ));
+        assert!(matches!(
+            LoginError::from(BanchoServiceError::UserNotExists(
+                GetUserError::UserNotExists
+            )),
+            LoginError::InvalidCredentials
+        ));
+        assert!(matches!(
+            LoginError::from(BanchoServiceError::UserNotExists(
+                GetUserError::DbErr("connection lost".into())
+            )),
+            LoginError::ServerError(_)
+        ));
+        assert!(matches!(
+            LoginError::from(BanchoServiceError::UserBanned),
+            LoginError::Banned
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_session_expired_response() {
+        let err = BanchoHttpError::from(BanchoStateError::SessionNotExists);
+        assert!(matches!(err, BanchoHttpError::SessionExpired));
+
+        let res = err.into_response();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let packet = PacketReader::new(&body).next().unwrap();
+        assert_eq!(packet.id, PacketId::BANCHO_RESTART);
+
+        assert!(matches!(
+            BanchoHttpError::from(BanchoStateError::InvalidArgument),
+            BanchoHttpError::BanchoStateError(_)
+        ));
+        assert_eq!(
+            BanchoHttpError::Unauthorized.into_response().status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            BanchoHttpError::NotImplemented("/web/osu-rate.php")
+                .into_response()
+                .status(),
+            StatusCode::NOT_IMPLEMENTED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login_failed_response() {
+        let res =
+            BanchoHttpError::LoginFailed(LoginError::Banned).into_response();
+        assert_eq!(res.headers()[CHO_TOKEN], "failed");
+
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let packet = PacketReader::new(&body).next().unwrap();
+        assert_eq!(packet.id, PacketId::BANCHO_USER_LOGIN_REPLY);
+        assert_eq!(
+            PayloadReader::new(packet.payload.unwrap()).read::<i32>(),
+            Some(LoginFailedReason::UserBanned as i32)
+        );
+    }
+}
@@ -1,10 +1,11 @@
-use super::{parser, BanchoHttpError};
+use super::{parser, BanchoHttpError, ClientVersion};
 use axum::{
     async_trait,
     body::Bytes,
     extract::{FromRequest, FromRequestParts},
     headers::HeaderName,
-    http::{request::Parts, Request},
+    http::{request::Parts, Request, StatusCode},
+    response::IntoResponse,
 };
 use derive_deref::Deref;
 use hyper::header::USER_AGENT;
@@ -70,6 +71,14 @@ where
     }
 }
 
+impl BanchoClientVersion {
+    /// The structured version, the raw string is kept in `.0`.
+    #[inline]
+    pub fn parse(&self) -> ClientVersion {
+        ClientVersion::parse(&self.0)
+    }
+}
+
 impl std::fmt::Display for BanchoClientVersion {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(&self.0)
@@ -129,11 +138,58 @@ where
             return Err(BanchoHttpError::InvalidUserAgentHeader);
         }
 
-        // Extract the request body and wrap it in a `BanchoRequestBody`.
-        Ok(Self(
-            Bytes::from_request(req, state)
-                .await
-                .map_err(|_| BanchoHttpError::ParseRequestError)?,
-        ))
+        // Extract the request body and wrap it in a `BanchoRequestBody`,
+        // the body size is limited by the `DefaultBodyLimit` of the route.
+        Ok(Self(Bytes::from_request(req, state).await.map_err(
+            |rejection| match rejection.into_response().status() {
+                StatusCode::PAYLOAD_TOO_LARGE => {
+                    BanchoHttpError::RequestTooLarge
+                },
+                _ => BanchoHttpError::ParseRequestError,
+            },
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::DefaultBodyLimit, routing::post, Router};
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+    use tower::ServiceExt;
+
+    fn bancho_request(body: Vec<u8>) -> Request<Body> {
+        Request::post("/")
+            .header(USER_AGENT, OSU_USER_AGENT.as_str())
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_bancho_request_body_limit() {
+        let handled = Arc::new(AtomicBool::new(false));
+
+        let router = {
+            let handled = handled.clone();
+            Router::new().route(
+                "/",
+                post(move |BanchoRequestBody(_body)| async move {
+                    handled.store(true, Ordering::SeqCst);
+                })
+                .layer(DefaultBodyLimit::max(16)),
+            )
+        };
+
+        let res =
+            router.clone().oneshot(bancho_request(vec![4; 64])).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(!handled.load(Ordering::SeqCst));
+
+        let res = router.oneshot(bancho_request(vec![4; 16])).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(handled.load(Ordering::SeqCst));
     }
 }
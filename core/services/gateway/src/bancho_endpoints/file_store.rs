@@ -0,0 +1,103 @@
+use std::{io, path::PathBuf};
+
+/// Files of the clients stored on disk (replays, screenshots...), named
+/// after a md5 hex digest, e.g. `{dir}/9e107d9d372bb6826bd81d3542a419d6.osr`.
+///
+/// The names are validated by the stores using it, see [`Self::is_md5`], so
+/// that a path can not escape the directory.
+#[derive(Debug, Clone)]
+pub struct Md5FileStore {
+    pub dir: PathBuf,
+    pub max_size: usize,
+}
+
+impl Md5FileStore {
+    #[inline]
+    pub fn new<P: Into<PathBuf>>(dir: P, max_size: usize) -> Self {
+        Self { dir: dir.into(), max_size }
+    }
+
+    /// Lowercase md5 hex digest.
+    #[inline]
+    pub fn is_md5(s: &str) -> bool {
+        s.len() == 32
+            && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    }
+
+    #[inline]
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// Write the file if it does not exist yet, an existing file is never
+    /// overwritten.
+    pub async fn write_once(&self, name: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.path(name);
+
+        if tokio::fs::metadata(&path).await.is_err() {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            tokio::fs::write(&path, data).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Open the file to stream it, returns the file and its length, `None`
+    /// if it does not exist.
+    pub async fn open(
+        &self,
+        name: &str,
+    ) -> io::Result<Option<(tokio::fs::File, u64)>> {
+        let file = match tokio::fs::File::open(self.path(name)).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(None)
+            },
+            Err(err) => return Err(err),
+        };
+        let len = file.metadata().await?.len();
+
+        Ok(Some((file, len)))
+    }
+
+    /// Content of the file, `None` if it does not exist.
+    pub async fn read(&self, name: &str) -> io::Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(name)).await {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_md5() {
+        assert!(Md5FileStore::is_md5("9e107d9d372bb6826bd81d3542a419d6"));
+        assert!(!Md5FileStore::is_md5("9E107D9D372BB6826BD81D3542A419D6"));
+        assert!(!Md5FileStore::is_md5("../../etc/passwd"));
+        assert!(!Md5FileStore::is_md5(""));
+    }
+
+    #[tokio::test]
+    async fn test_file_store() {
+        let dir = std::env::temp_dir()
+            .join(format!("peace-file-store-{}", std::process::id()));
+        let store = Md5FileStore::new(&dir, 8);
+
+        assert!(store.read("file").await.unwrap().is_none());
+        assert!(store.open("file").await.unwrap().is_none());
+
+        store.write_once("file", b"data").await.unwrap();
+        // the first file is kept
+        store.write_once("file", b"other").await.unwrap();
+
+        assert_eq!(store.read("file").await.unwrap().unwrap(), b"data");
+        assert_eq!(store.open("file").await.unwrap().unwrap().1, 4);
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
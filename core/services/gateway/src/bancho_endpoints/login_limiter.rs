@@ -0,0 +1,94 @@
+use clap_serde_derive::ClapSerde;
+use peace_cfg::{ConfigError, ConfigErrors, ValidateConfig};
+use std::net::IpAddr;
+use tools::rate_limiter::KeyedRateLimiter;
+
+#[derive(Debug, Clone, Parser, ClapSerde, Serialize, Deserialize)]
+pub struct CliBanchoLoginLimiterConfigs {
+    /// Login attempts allowed per minute from an ip address, `0` disables
+    /// the limit. A successful login resets the limit of the ip.
+    #[default(10)]
+    #[arg(long, default_value = "10")]
+    pub bancho_login_rate_per_minute: u32,
+
+    /// Login attempts an ip address can make in a row before being limited
+    /// to `bancho_login_rate_per_minute`.
+    #[default(5)]
+    #[arg(long, default_value = "5")]
+    pub bancho_login_burst: u32,
+}
+
+impl ValidateConfig for CliBanchoLoginLimiterConfigs {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        errors.check(
+            self.bancho_login_rate_per_minute == 0
+                || self.bancho_login_burst > 0,
+            "bancho_login_burst",
+            "must be greater than 0 when the login limit is enabled",
+        );
+
+        errors.into_result()
+    }
+}
+
+/// Limiter of the login attempts, keyed on the client ip. A successful
+/// login resets the limit of the ip.
+pub type LoginLimiter = KeyedRateLimiter<IpAddr>;
+
+#[inline]
+pub fn login_limiter(cfg: &CliBanchoLoginLimiterConfigs) -> LoginLimiter {
+    LoginLimiter::per_minute(
+        cfg.bancho_login_rate_per_minute,
+        cfg.bancho_login_burst,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_login_limiter_configs_reject_zero_burst() {
+        let cfg = |bancho_login_rate_per_minute, bancho_login_burst| {
+            CliBanchoLoginLimiterConfigs {
+                bancho_login_rate_per_minute,
+                bancho_login_burst,
+            }
+        };
+
+        assert!(cfg(10, 5).validate().is_ok());
+        assert!(cfg(0, 0).validate().is_ok());
+
+        let errors = cfg(10, 0).validate().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "bancho_login_burst");
+    }
+
+    #[test]
+    fn test_login_limiter() {
+        const BURST: u32 = 5;
+
+        let limiter = LoginLimiter::per_minute(10, BURST);
+        let ip: IpAddr = "1.1.1.1".parse().unwrap();
+        let other_ip: IpAddr = "2.2.2.2".parse().unwrap();
+
+        for _ in 0..BURST {
+            assert!(limiter.check(ip).is_ok());
+        }
+        assert!(limiter.check(ip).unwrap_err() <= Duration::from_secs(6));
+
+        // other ips are not affected
+        assert!(limiter.check(other_ip).is_ok());
+
+        limiter.reset(ip);
+        assert!(limiter.check(ip).is_ok());
+
+        let unlimited = LoginLimiter::per_minute(0, 0);
+        for _ in 0..100 {
+            assert!(unlimited.check(ip).is_ok());
+        }
+    }
+}
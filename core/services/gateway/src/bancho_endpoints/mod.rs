@@ -1,13 +1,31 @@
+pub mod beatmap_scores;
+pub mod client_version;
 pub mod docs;
 pub mod error;
 pub mod extractors;
+pub mod file_store;
+pub mod login_limiter;
 pub mod parser;
+pub mod replays;
 pub mod routes;
+pub mod score_submission;
+pub mod screenshots;
+pub mod seasonal;
 pub mod services;
+pub mod update_manifest;
 
+pub use beatmap_scores::*;
+pub use client_version::*;
 pub use docs::*;
 pub use error::*;
+pub use file_store::*;
+pub use login_limiter::*;
+pub use replays::*;
+pub use score_submission::*;
+pub use screenshots::*;
+pub use seasonal::*;
 pub use services::*;
+pub use update_manifest::*;
 
 pub const CHO_PROTOCOL: (&str, &str) = ("cho-protocol", "19");
 pub const CHO_TOKEN: &str = "cho-token";
@@ -1,64 +1,210 @@
-use super::ParseLoginDataError;
-use pb_bancho::{ClientHashes, LoginRequest};
+use super::{
+    BanchoHttpError, ParseLoginDataError, ScoreSubmissionError,
+    ScoreSubmissionForm, ScreenshotForm,
+};
+use axum::extract::Multipart;
+use pb_bancho::{ClientHashes, ClientRegisterRequest, LoginRequest};
 
+/// Parse the multipart form sent by osu! client on in-game registration.
+pub async fn parse_client_register_form(
+    mut multipart: Multipart,
+) -> Result<ClientRegisterRequest, BanchoHttpError> {
+    let mut request = ClientRegisterRequest::default();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_| BanchoHttpError::ParseRequestError)?
+    {
+        let name = field.name().unwrap_or_default().to_owned();
+        let value = field
+            .text()
+            .await
+            .map_err(|_| BanchoHttpError::ParseRequestError)?;
+
+        match name.as_str() {
+            "user[username]" => request.username = value,
+            "user[user_email]" => request.email = value,
+            "user[password]" => request.password = value,
+            "check" => request.check_only = value == "1",
+            _ => {},
+        }
+    }
+
+    Ok(request)
+}
+
+/// Parse the multipart form sent by osu! client on score submission, the
+/// `score` field is sent twice: as text (the encrypted score data) and as
+/// file (the replay).
+pub async fn parse_score_submission_form(
+    mut multipart: Multipart,
+) -> Result<ScoreSubmissionForm, ScoreSubmissionError> {
+    let mut form = ScoreSubmissionForm::default();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_| ScoreSubmissionError::InvalidField("form"))?
+    {
+        let name = field.name().unwrap_or_default().to_owned();
+
+        if name == "score" && field.file_name().is_some() {
+            form.replay = field
+                .bytes()
+                .await
+                .map_err(|_| ScoreSubmissionError::InvalidField("score"))?
+                .to_vec();
+            continue;
+        }
+
+        let value = field
+            .text()
+            .await
+            .map_err(|_| ScoreSubmissionError::InvalidField("form"))?;
+
+        match name.as_str() {
+            "score" => form.score_data = value,
+            "iv" => form.iv = value,
+            "osuver" => form.osu_version = value,
+            "pass" => form.password_md5 = value,
+            "st" => form.score_time = value.parse().unwrap_or_default(),
+            "ft" => form.fail_time = value.parse().unwrap_or_default(),
+            "x" => form.exited = value == "1",
+            _ => {},
+        }
+    }
+
+    for (field, value) in [
+        ("score", &form.score_data),
+        ("iv", &form.iv),
+        ("osuver", &form.osu_version),
+        ("pass", &form.password_md5),
+    ] {
+        if value.is_empty() {
+            return Err(ScoreSubmissionError::MissingField(field));
+        }
+    }
+
+    Ok(form)
+}
+
+/// Parse the multipart form sent by osu! client on screenshot upload, the
+/// credentials and the `ss` file field are required.
+pub async fn parse_screenshot_form(
+    mut multipart: Multipart,
+) -> Result<ScreenshotForm, BanchoHttpError> {
+    let mut form = ScreenshotForm::default();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_| BanchoHttpError::ParseRequestError)?
+    {
+        let name = field.name().unwrap_or_default().to_owned();
+
+        if name == "ss" {
+            form.screenshot = field
+                .bytes()
+                .await
+                .map_err(|_| BanchoHttpError::ParseRequestError)?
+                .to_vec();
+            continue;
+        }
+
+        let value = field
+            .text()
+            .await
+            .map_err(|_| BanchoHttpError::ParseRequestError)?;
+
+        match name.as_str() {
+            "u" => form.username = value,
+            "p" => form.password_md5 = value,
+            _ => {},
+        }
+    }
+
+    if form.username.is_empty()
+        || form.password_md5.is_empty()
+        || form.screenshot.is_empty()
+    {
+        return Err(BanchoHttpError::ParseRequestError);
+    }
+
+    Ok(form)
+}
+
+/// Parse the login body sent by osu! client, formatted as
+/// `{username}\n{password md5}\n{client info}\n`, where the client info is
+/// `{version}|{utc offset}|{display city}|{client hashes}|{friend pm only}`
+/// and the client hashes are
+/// `{path}:{adapters}:{adapters md5}:{uninstall id}:{disk id}:`.
 pub fn parse_osu_login_request_body(
     body: Vec<u8>,
 ) -> Result<LoginRequest, ParseLoginDataError> {
     #[inline]
-    fn shift<T>(vec: &mut Vec<T>) -> T {
-        vec.remove(0)
+    fn split_fields(s: &str, sep: char) -> Vec<&str> {
+        s.strip_suffix(sep).unwrap_or(s).split(sep).map(str::trim).collect()
+    }
+
+    #[inline]
+    fn parse_flag(s: &str) -> Option<bool> {
+        match s {
+            "0" => Some(false),
+            "1" => Some(true),
+            _ => None,
+        }
     }
 
     let body = String::from_utf8(body)
         .map_err(ParseLoginDataError::InvalidRequestBody)?;
 
-    let mut lines = tools::split_string(&body, '\n');
-
-    if lines.len() < 3 {
-        return Err(ParseLoginDataError::InvalidLoginData);
-    }
-
-    let username = shift(&mut lines);
-    let password = shift(&mut lines);
+    let [username, password, client_info]: [&str; 3] =
+        split_fields(&body, '\n')
+            .try_into()
+            .map_err(|_| ParseLoginDataError::InvalidLoginData)?;
 
-    if username.is_empty() || password.len() != 32 {
+    if username.is_empty()
+        || password.len() != 32
+        || !password.bytes().all(|b| b.is_ascii_hexdigit())
+    {
         return Err(ParseLoginDataError::InvalidUserInfo);
     }
 
-    let mut client_info = tools::split_string(&shift(&mut lines), '|');
+    let client_info: [&str; 5] = split_fields(client_info, '|')
+        .try_into()
+        .map_err(|_| ParseLoginDataError::InvalidClientInfo)?;
 
-    if client_info.len() < 5 {
-        return Err(ParseLoginDataError::InvalidClientInfo);
-    }
-
-    let client_version = shift(&mut client_info);
+    let [client_version, utc_offset, display_city, client_hashes, friend_pm] =
+        client_info;
 
-    // Parse utc offset
-    let utc_offset = shift(&mut client_info).parse::<i32>().unwrap_or(0);
+    let utc_offset = utc_offset
+        .parse::<i32>()
+        .map_err(|_| ParseLoginDataError::InvalidClientInfo)?;
 
     // Display city in bancho or not
-    let display_city = shift(&mut client_info) == "1";
+    let display_city = parse_flag(display_city)
+        .ok_or(ParseLoginDataError::InvalidClientInfo)?;
+
+    // Only allow friend's pm
+    let only_friend_pm_allowed =
+        parse_flag(friend_pm).ok_or(ParseLoginDataError::InvalidClientInfo)?;
 
-    // Client hashes
-    let mut client_hashes = tools::split_string(&shift(&mut client_info), ':');
+    let client_hashes: [&str; 5] = split_fields(client_hashes, ':')
+        .try_into()
+        .map_err(|_| ParseLoginDataError::InvalidClientHashes)?;
 
-    if client_hashes.len() < 5 {
+    if client_hashes.iter().any(|hash| hash.is_empty()) {
         return Err(ParseLoginDataError::InvalidClientHashes);
     }
 
-    // Only allow friend's pm
-    let only_friend_pm_allowed = shift(&mut client_info) == "1";
-
-    let path_hash = shift(&mut client_hashes);
-    let adapters = shift(&mut client_hashes);
-    let adapters_hash = shift(&mut client_hashes);
-    let uninstall_id = shift(&mut client_hashes);
-    let disk_id = shift(&mut client_hashes);
+    let [path_hash, adapters, adapters_hash, uninstall_id, disk_id] =
+        client_hashes.map(str::to_owned);
 
     Ok(LoginRequest {
-        username,
-        password,
-        client_version,
+        username: username.to_owned(),
+        password: password.to_owned(),
+        client_version: client_version.to_owned(),
         utc_offset,
         display_city,
         only_friend_pm_allowed,
@@ -71,3 +217,81 @@ pub fn parse_osu_login_request_body(
         }),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGIN_BODY: &str = "peppy\n\
+        5f4dcc3b5aa765d61d8327deb882cf99\n\
+        b20230101.2|8|1|\
+        0c5d6f0a6d4f4f0a9c1e6c5b0e6f1c2d:00-15-5D-01-02-03.:\
+        1f3870be274f6c49b3e31a0c6728957f:\
+        c4ca4238a0b923820dcc509a6f75849b:\
+        c81e728d9d4c2f636f067f89cc14862c:|0\n";
+
+    #[test]
+    fn test_parse_login_body() {
+        let request = parse_osu_login_request_body(LOGIN_BODY.into()).unwrap();
+
+        assert_eq!(request.username, "peppy");
+        assert_eq!(request.password, "5f4dcc3b5aa765d61d8327deb882cf99");
+        assert_eq!(request.client_version, "b20230101.2");
+        assert_eq!(request.utc_offset, 8);
+        assert!(request.display_city);
+        assert!(!request.only_friend_pm_allowed);
+
+        let hashes = request.client_hashes.unwrap();
+        assert_eq!(hashes.adapters, "00-15-5D-01-02-03.");
+        assert_eq!(hashes.disk_id, "c81e728d9d4c2f636f067f89cc14862c");
+    }
+
+    #[test]
+    fn test_parse_malformed_login_body() {
+        let replace = |from: &str, to: &str| {
+            parse_osu_login_request_body(
+                LOGIN_BODY.replacen(from, to, 1).into(),
+            )
+        };
+
+        assert!(matches!(
+            replace("peppy\n", "peppy\nextra\n"),
+            Err(ParseLoginDataError::InvalidLoginData)
+        ));
+        assert!(matches!(
+            replace("5f4dcc3b", "zzzzzzzz"),
+            Err(ParseLoginDataError::InvalidUserInfo)
+        ));
+        assert!(matches!(
+            replace("|8|", "|eight|"),
+            Err(ParseLoginDataError::InvalidClientInfo)
+        ));
+        assert!(matches!(
+            replace("|0\n", "|0|1\n"),
+            Err(ParseLoginDataError::InvalidClientInfo)
+        ));
+        assert!(matches!(
+            replace(":|0", "::|0"),
+            Err(ParseLoginDataError::InvalidClientHashes)
+        ));
+        assert!(matches!(
+            parse_osu_login_request_body(vec![0xff, 0xfe]),
+            Err(ParseLoginDataError::InvalidRequestBody(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_truncated_login_body() {
+        // Only the trailing newline is optional
+        for len in 0..LOGIN_BODY.len() - 1 {
+            assert!(
+                parse_osu_login_request_body(LOGIN_BODY[..len].into()).is_err(),
+                "truncated at {len}"
+            );
+        }
+        assert!(parse_osu_login_request_body(
+            LOGIN_BODY[..LOGIN_BODY.len() - 1].into()
+        )
+        .is_ok());
+    }
+}
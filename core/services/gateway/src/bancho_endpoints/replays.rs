@@ -0,0 +1,136 @@
+use super::Md5FileStore;
+use axum::http::StatusCode;
+use std::{io, path::PathBuf};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReplayError {
+    #[error("replay is too large, max size is {0} bytes")]
+    TooLarge(usize),
+    #[error("replay is empty")]
+    Empty,
+    #[error("replay not exists")]
+    NotExists,
+    #[error("failed to access replay: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl ReplayError {
+    #[inline]
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Empty => StatusCode::BAD_REQUEST,
+            Self::NotExists => StatusCode::NOT_FOUND,
+            Self::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Replays stored on disk, named after the md5 of their score, e.g.
+/// `{dir}/9e107d9d372bb6826bd81d3542a419d6.osr`.
+#[derive(Debug, Clone)]
+pub struct ReplayStore {
+    pub files: Md5FileStore,
+}
+
+impl ReplayStore {
+    #[inline]
+    pub fn new<P: Into<PathBuf>>(dir: P, max_size: usize) -> Self {
+        Self { files: Md5FileStore::new(dir, max_size) }
+    }
+
+    /// Store the replay of a score, a score has only one replay so an
+    /// existing replay is never overwritten.
+    pub async fn save(
+        &self,
+        score_md5: &str,
+        data: &[u8],
+    ) -> Result<(), ReplayError> {
+        if data.is_empty() {
+            return Err(ReplayError::Empty);
+        }
+
+        if data.len() > self.files.max_size {
+            return Err(ReplayError::TooLarge(self.files.max_size));
+        }
+
+        let name = Self::file_name(score_md5).ok_or(ReplayError::NotExists)?;
+
+        Ok(self.files.write_once(&name, data).await?)
+    }
+
+    /// Open the replay of a score to stream it, returns the file and its
+    /// length.
+    pub async fn open(
+        &self,
+        score_md5: &str,
+    ) -> Result<(tokio::fs::File, u64), ReplayError> {
+        let name = Self::file_name(score_md5).ok_or(ReplayError::NotExists)?;
+
+        self.files.open(&name).await?.ok_or(ReplayError::NotExists)
+    }
+
+    /// Only md5 hex digests are valid, so that the path can not escape the
+    /// replays directory.
+    #[inline]
+    pub fn file_name(score_md5: &str) -> Option<String> {
+        Md5FileStore::is_md5(score_md5).then(|| format!("{score_md5}.osr"))
+    }
+
+    #[inline]
+    pub fn path(&self, score_md5: &str) -> Option<PathBuf> {
+        Self::file_name(score_md5).map(|name| self.files.path(&name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    const SCORE_MD5: &str = "9e107d9d372bb6826bd81d3542a419d6";
+
+    #[test]
+    fn test_replay_path() {
+        let store = ReplayStore::new("replays", 1024);
+
+        assert_eq!(
+            store.path(SCORE_MD5),
+            Some(PathBuf::from(format!("replays/{SCORE_MD5}.osr")))
+        );
+        assert_eq!(store.path("9E107D9D372BB6826BD81D3542A419D6"), None);
+        assert_eq!(store.path("../../etc/passwd"), None);
+        assert_eq!(store.path(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_replay_store() {
+        let dir = std::env::temp_dir()
+            .join(format!("peace-replays-{}", std::process::id()));
+        let store = ReplayStore::new(&dir, 8);
+
+        assert!(matches!(
+            store.open(SCORE_MD5).await,
+            Err(ReplayError::NotExists)
+        ));
+        assert!(matches!(
+            store.save(SCORE_MD5, b"").await,
+            Err(ReplayError::Empty)
+        ));
+        assert!(matches!(
+            store.save(SCORE_MD5, &[0; 9]).await,
+            Err(ReplayError::TooLarge(8))
+        ));
+
+        store.save(SCORE_MD5, b"replay").await.unwrap();
+        // the first replay of a score is kept
+        store.save(SCORE_MD5, b"other").await.unwrap();
+
+        let (mut file, len) = store.open(SCORE_MD5).await.unwrap();
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).await.unwrap();
+        assert_eq!((data.as_slice(), len), (&b"replay"[..], 6));
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
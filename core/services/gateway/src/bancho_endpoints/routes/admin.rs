@@ -0,0 +1,218 @@
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::*,
+    Extension, Json, Router,
+};
+use core_bancho::DynBanchoService;
+use core_bancho_state::DynBanchoStateService;
+use pb_bancho::{GetUsersSharingHardwareRequest, SilenceUserRequest};
+use pb_bancho_state::{
+    broadcast_announcement_request::Target, BroadcastAnnouncementRequest,
+};
+use std::{sync::Arc, time::Duration};
+use tools::rate_limiter::KeyedRateLimiter;
+use utoipa::ToSchema;
+
+pub struct BanchoAdminRouter;
+
+impl BanchoAdminRouter {
+    pub fn new_router<T: Clone + Sync + Send + 'static>(
+        bancho_state_service: DynBanchoStateService,
+        bancho_service: DynBanchoService,
+    ) -> Router<T> {
+        Router::new()
+            .route("/admin/bancho/announce", post(announce))
+            .route(
+                "/admin/bancho/users/:user_id/shared_hardware",
+                get(users_sharing_hardware),
+            )
+            .route("/admin/bancho/users/:user_id/silence", post(silence_user))
+            .layer(Extension(bancho_state_service))
+            .layer(Extension(bancho_service))
+            .layer(Extension(Arc::new(
+                AnnouncementRateLimit::with_min_interval(
+                    ANNOUNCEMENT_MIN_INTERVAL,
+                ),
+            )))
+    }
+}
+
+pub const ANNOUNCEMENT_MIN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Rejects announcements sent too soon after the previous one, to avoid
+/// spamming the players by accident.
+pub type AnnouncementRateLimit = KeyedRateLimiter<()>;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AnnounceRequest {
+    pub message: String,
+    /// `target` in: `["all", "supporters", "privileges"]`, default `all`
+    pub target: Option<String>,
+    /// Bancho privileges bits, required by target `privileges`, sessions
+    /// having any of them will receive the announcement
+    pub bancho_privileges: Option<i32>,
+}
+
+/// Send an announcement to the online players
+#[utoipa::path(
+    post,
+    path = "/admin/bancho/announce",
+    tag = "bancho_admin",
+    request_body = AnnounceRequest,
+    responses(
+        (status = 200, description = "Number of sessions the announcement was sent to"),
+        (status = 400, description = "Invalid request"),
+        (status = 429, description = "Too many announcements"),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn announce(
+    Extension(bancho_state_service): Extension<DynBanchoStateService>,
+    Extension(rate_limit): Extension<Arc<AnnouncementRateLimit>>,
+    Json(request): Json<AnnounceRequest>,
+) -> Response {
+    if request.message.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "empty message").into_response();
+    }
+
+    let target = match request.target.as_deref() {
+        None | Some("all") => Target::All,
+        Some("supporters") => Target::Supporters,
+        Some("privileges") => Target::Privileges,
+        Some(_) => {
+            return (StatusCode::BAD_REQUEST, "invalid target").into_response()
+        },
+    };
+
+    let bancho_privileges = match (target, request.bancho_privileges) {
+        (Target::Privileges, None) => {
+            return (StatusCode::BAD_REQUEST, "missing bancho_privileges")
+                .into_response()
+        },
+        (_, privileges) => privileges.unwrap_or_default(),
+    };
+
+    if let Err(retry_after) = rate_limit.check(()) {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("retry after {}s", retry_after.as_secs() + 1),
+        )
+            .into_response();
+    }
+
+    bancho_state_service
+        .broadcast_announcement(BroadcastAnnouncementRequest {
+            message: request.message,
+            target: target as i32,
+            bancho_privileges,
+        })
+        .await
+        .map(|res| {
+            Json(serde_json::json!({ "sent": res.sent })).into_response()
+        })
+        .unwrap_or_else(|err| {
+            (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+        })
+}
+
+/// Users who logged in from the same hardware as the user
+///
+/// Matches any of the adapters hash, uninstall id or disk id recorded on
+/// the logins of the user, the matches should be reviewed before acting on
+/// them (e.g. the adapters hash is shared by all the clients under wine).
+#[utoipa::path(
+    get,
+    path = "/admin/bancho/users/{user_id}/shared_hardware",
+    tag = "bancho_admin",
+    params(
+        ("user_id" = i32, Path, description = "user id"),
+    ),
+    responses(
+        (status = 200, description = "Ids of the users sharing hardware with the user"),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn users_sharing_hardware(
+    Extension(bancho_service): Extension<DynBanchoService>,
+    Path(user_id): Path<i32>,
+) -> Response {
+    bancho_service
+        .get_users_sharing_hardware(GetUsersSharingHardwareRequest { user_id })
+        .await
+        .map(|res| {
+            Json(serde_json::json!({ "user_ids": res.user_ids }))
+                .into_response()
+        })
+        .unwrap_or_else(|err| {
+            (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+        })
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SilenceRequest {
+    /// Secs, `0` lifts the silence
+    pub duration: i64,
+    pub reason: Option<String>,
+}
+
+/// Silence a user, the user cannot chat until the silence expires
+#[utoipa::path(
+    post,
+    path = "/admin/bancho/users/{user_id}/silence",
+    tag = "bancho_admin",
+    params(
+        ("user_id" = i32, Path, description = "user id"),
+    ),
+    request_body = SilenceRequest,
+    responses(
+        (status = 200, description = "Unix timestamp of the end of the silence"),
+        (status = 400, description = "Invalid request"),
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn silence_user(
+    Extension(bancho_service): Extension<DynBanchoService>,
+    Path(user_id): Path<i32>,
+    Json(request): Json<SilenceRequest>,
+) -> Response {
+    if request.duration < 0 {
+        return (StatusCode::BAD_REQUEST, "negative duration").into_response();
+    }
+
+    bancho_service
+        .silence_user(SilenceUserRequest {
+            user_id,
+            duration: request.duration,
+            reason: request.reason,
+        })
+        .await
+        .map(|res| {
+            Json(serde_json::json!({ "silence_end": res.silence_end }))
+                .into_response()
+        })
+        .unwrap_or_else(|err| {
+            (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_announcement_rate_limit() {
+        let rate_limit =
+            AnnouncementRateLimit::with_min_interval(Duration::from_secs(60));
+
+        assert!(rate_limit.check(()).is_ok());
+        assert!(rate_limit.check(()).unwrap_err() <= Duration::from_secs(60));
+
+        let rate_limit =
+            AnnouncementRateLimit::with_min_interval(Duration::ZERO);
+
+        assert!(rate_limit.check(()).is_ok());
+        assert!(rate_limit.check(()).is_ok());
+    }
+}
@@ -1,19 +1,31 @@
 use crate::bancho_endpoints::{
     extractors::{BanchoClientVersion, BanchoRequestBody, OsuTokenHeader},
-    BanchoHttpError, DynBanchoRoutingService,
+    parser, BanchoHttpError, DynBanchoRoutingService,
+};
+use axum::{
+    extract::{DefaultBodyLimit, Multipart, Path, Query, WebSocketUpgrade},
+    response::Response,
+    routing::*,
+    Extension, Router,
 };
-use axum::{extract::Path, response::Response, routing::*, Extension, Router};
 use peace_api::extractors::*;
+use utoipa::IntoParams;
 
 pub struct BanchoRouter;
 
 impl BanchoRouter {
     pub fn new_router<T: Clone + Sync + Send + 'static>(
         bancho_routing_service: DynBanchoRoutingService,
+        max_bancho_request_size: usize,
     ) -> Router<T> {
         Router::new()
             .route("/", get(bancho_get))
-            .route("/", post(bancho_post))
+            .route(
+                "/",
+                post(bancho_post)
+                    .layer(DefaultBodyLimit::max(max_bancho_request_size)),
+            )
+            .route("/ws", get(bancho_ws))
             .route("/ss/:screenshot", get(get_screenshot))
             .route("/d/:beatmapset_id", get(download_beatmapset))
             .route("/users", post(client_register))
@@ -39,7 +51,7 @@ impl BanchoRouter {
             .route("/web/osu-markasread.php", get(osu_markasread))
             .route("/web/osu-getseasonal.php", get(osu_getseasonal))
             .route("/web/bancho_connect.php", get(bancho_connect))
-            .route("/web/check-updates", get(check_updates))
+            .route("/web/check-updates.php", get(check_updates))
             .route("/web/maps/:beatmap_file_name", get(update_beatmap))
             .layer(Extension(bancho_routing_service))
     }
@@ -56,7 +68,7 @@ impl BanchoRouter {
 )]
 pub async fn bancho_get(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
+) -> Result<Response, BanchoHttpError> {
     routing_service.bancho_get().await
 }
 
@@ -67,6 +79,7 @@ pub async fn bancho_get(
     tag = "bancho",
     responses(
         (status = 200, description = "Bancho post handler", body = [String]),
+        (status = 413, description = "Request body is too large"),
     )
 )]
 pub async fn bancho_post(
@@ -79,19 +92,57 @@ pub async fn bancho_post(
     routing_service.bancho_post(token, version, ip, body.into()).await
 }
 
+/// Bancho websocket handler
+///
+/// Upgrades a logged in session (`osu-token` of the bancho login) to a
+/// websocket. Binary messages are processed as bancho packets, and the
+/// queued packets of the session are pushed back as binary messages as soon
+/// as they are queued.
+///
+/// The messages are packets of the stable bancho protocol, the osu!lazer
+/// (SignalR) protocol is not supported.
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "bancho",
+    responses(
+        (status = 101, description = "Switching to websocket"),
+        (status = 401, description = "Invalid `osu-token`"),
+    )
+)]
+pub async fn bancho_ws(
+    Extension(routing_service): Extension<DynBanchoRoutingService>,
+    token: OsuTokenHeader,
+    ClientIp(ip): ClientIp,
+    ws: WebSocketUpgrade,
+) -> Result<Response, BanchoHttpError> {
+    routing_service.bancho_ws(token, ip, ws).await
+}
+
 /// Bancho get_screenshot
 #[utoipa::path(
     get,
     path = "/ss/{screenshot}",
     tag = "bancho",
+    params(
+        ("screenshot" = String, Path, description = "screenshot id, e.g. `{md5}.png`"),
+    ),
     responses(
-        (status = 200, description = "Bancho get_screenshot"),
+        (status = 200, description = "Screenshot image"),
+        (status = 404, description = "Screenshot not exists"),
     )
 )]
 pub async fn get_screenshot(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
-    routing_service.get_screenshot().await
+    Path(screenshot): Path<String>,
+) -> Result<Response, BanchoHttpError> {
+    routing_service.get_screenshot(screenshot).await
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DownloadBeatmapsetQuery {
+    /// Download beatmapset without video if present.
+    pub n: Option<String>,
 }
 
 /// Bancho download_beatmapset
@@ -99,30 +150,42 @@ pub async fn get_screenshot(
     get,
     path = "/d/{beatmapset_id}",
     tag = "bancho",
+    params(
+        ("beatmapset_id" = i32, Path, description = "beatmapset id"),
+        DownloadBeatmapsetQuery
+    ),
     responses(
-        (status = 200, description = "Bancho download_beatmapset"),
+        (status = 302, description = "Redirect to the beatmap mirror"),
+        (status = 404, description = "Beatmap mirror is not configured"),
     )
 )]
 pub async fn download_beatmapset(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
     Path(beatmapset_id): Path<i32>,
-) -> Response {
-    routing_service.download_beatmapset(beatmapset_id).await
+    Query(query): Query<DownloadBeatmapsetQuery>,
+) -> Result<Response, BanchoHttpError> {
+    routing_service.download_beatmapset(beatmapset_id, query.n.is_some()).await
 }
 
 /// Bancho client_register
+///
+/// Multipart form fields: `user[username]`, `user[user_email]`,
+/// `user[password]` and `check` (`1` to validate the fields only).
 #[utoipa::path(
     post,
     path = "/users",
     tag = "bancho",
     responses(
-        (status = 200, description = "Bancho client_register", body = [String]),
+        (status = 200, description = "Registered or fields are valid", body = [String]),
+        (status = 400, description = "Field errors in `form_error`"),
     )
 )]
 pub async fn client_register(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
-    routing_service.client_register().await
+    multipart: Multipart,
+) -> Result<Response, BanchoHttpError> {
+    let request = parser::parse_client_register_form(multipart).await?;
+    routing_service.client_register(request).await
 }
 
 /// Bancho ask_peppy
@@ -131,28 +194,45 @@ pub async fn client_register(
     path = "/p/doyoureallywanttoaskpeppy",
     tag = "bancho",
     responses(
-        (status = 200, description = "Bancho ask_peppy"),
+        (status = 501, description = "Not implemented"),
     )
 )]
 pub async fn ask_peppy(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
+) -> Result<Response, BanchoHttpError> {
     routing_service.ask_peppy().await
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DifficultyRatingQuery {
+    pub beatmap_id: i32,
+    /// Vanilla game mode (`0` - `3`), standard beatmaps are converted.
+    #[serde(default)]
+    pub mode: u8,
+    /// Mods bitflags
+    #[serde(default)]
+    pub mods: u32,
+}
+
 /// Bancho difficulty_rating
 #[utoipa::path(
     get,
     path = "/difficulty-rating",
     tag = "bancho",
+    params(DifficultyRatingQuery),
     responses(
-        (status = 200, description = "Bancho difficulty_rating"),
+        (status = 200, description = "Star rating of the beatmap", body = f64),
+        (status = 400, description = "Invalid game mode"),
+        (status = 404, description = "Beatmap not found"),
     )
 )]
 pub async fn difficulty_rating(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
-    routing_service.difficulty_rating().await
+    Query(query): Query<DifficultyRatingQuery>,
+) -> Result<Response, BanchoHttpError> {
+    routing_service
+        .difficulty_rating(query.beatmap_id, query.mode, query.mods)
+        .await
 }
 
 /// Bancho osu_error
@@ -166,23 +246,32 @@ pub async fn difficulty_rating(
 )]
 pub async fn osu_error(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
+) -> Result<Response, BanchoHttpError> {
     routing_service.osu_error().await
 }
 
 /// Bancho osu_screenshot
+///
+/// Multipart form fields: `u` (username), `p` (password md5), `ss` (png or
+/// jpeg image). The uploads of a player are rate limited.
 #[utoipa::path(
     post,
     path = "/web/osu-screenshot.php",
     tag = "bancho",
     responses(
-        (status = 200, description = "Bancho osu_screenshot", body = [String]),
+        (status = 200, description = "Id of the uploaded screenshot", body = [String]),
+        (status = 400, description = "Not a png or jpeg image"),
+        (status = 401, description = "Invalid credentials"),
+        (status = 413, description = "Screenshot is too large"),
+        (status = 429, description = "Too many screenshots"),
     )
 )]
 pub async fn osu_screenshot(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
-    routing_service.osu_screenshot().await
+    multipart: Multipart,
+) -> Result<Response, BanchoHttpError> {
+    let form = parser::parse_screenshot_form(multipart).await?;
+    routing_service.osu_screenshot(form).await
 }
 
 /// Bancho osu_getfriends
@@ -196,7 +285,7 @@ pub async fn osu_screenshot(
 )]
 pub async fn osu_getfriends(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
+) -> Result<Response, BanchoHttpError> {
     routing_service.osu_getfriends().await
 }
 
@@ -206,43 +295,71 @@ pub async fn osu_getfriends(
     path = "/web/osu-getbeatmapinfo.php",
     tag = "bancho",
     responses(
-        (status = 200, description = "Bancho osu_getbeatmapinfo"),
+        (status = 501, description = "Not implemented"),
     )
 )]
 pub async fn osu_getbeatmapinfo(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
+) -> Result<Response, BanchoHttpError> {
     routing_service.osu_getbeatmapinfo().await
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct GetFavouritesQuery {
+    /// Username
+    pub u: String,
+    /// Password md5
+    pub h: String,
+}
+
 /// Bancho osu_getfavourites
+///
+/// The player is authenticated with the `u` and `h` query parameters.
 #[utoipa::path(
     get,
     path = "/web/osu-getfavourites.php",
     tag = "bancho",
+    params(GetFavouritesQuery),
     responses(
-        (status = 200, description = "Bancho osu_getfavourites"),
+        (status = 200, description = "Favourite beatmapset ids, one per line", body = String),
+        (status = 401, description = "Invalid credentials"),
     )
 )]
 pub async fn osu_getfavourites(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
-    routing_service.osu_getfavourites().await
+    Query(query): Query<GetFavouritesQuery>,
+) -> Result<Response, BanchoHttpError> {
+    routing_service.osu_getfavourites(query.u, query.h).await
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct AddFavouriteQuery {
+    /// Username
+    pub u: String,
+    /// Password md5
+    pub h: String,
+    /// Beatmapset id
+    pub a: i32,
 }
 
 /// Bancho osu_addfavourite
+///
+/// The player is authenticated with the `u` and `h` query parameters.
 #[utoipa::path(
     get,
     path = "/web/osu-addfavourite.php",
     tag = "bancho",
+    params(AddFavouriteQuery),
     responses(
-        (status = 200, description = "Bancho osu_addfavourite"),
+        (status = 200, description = "Message shown by the client", body = String),
+        (status = 401, description = "Invalid credentials"),
     )
 )]
 pub async fn osu_addfavourite(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
-    routing_service.osu_addfavourite().await
+    Query(query): Query<AddFavouriteQuery>,
+) -> Result<Response, BanchoHttpError> {
+    routing_service.osu_addfavourite(query.u, query.h, query.a).await
 }
 
 /// Bancho lastfm
@@ -256,7 +373,7 @@ pub async fn osu_addfavourite(
 )]
 pub async fn lastfm(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
+) -> Result<Response, BanchoHttpError> {
     routing_service.lastfm().await
 }
 
@@ -266,12 +383,12 @@ pub async fn lastfm(
     path = "/web/osu-search.php",
     tag = "bancho",
     responses(
-        (status = 200, description = "Bancho osu_search"),
+        (status = 501, description = "Not implemented"),
     )
 )]
 pub async fn osu_search(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
+) -> Result<Response, BanchoHttpError> {
     routing_service.osu_search().await
 }
 
@@ -281,28 +398,45 @@ pub async fn osu_search(
     path = "/web/osu-search-set.php",
     tag = "bancho",
     responses(
-        (status = 200, description = "Bancho osu_search_set"),
+        (status = 501, description = "Not implemented"),
     )
 )]
 pub async fn osu_search_set(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
+) -> Result<Response, BanchoHttpError> {
     routing_service.osu_search_set().await
 }
 
 /// Bancho osu_submit_modular_selector
+///
+/// Multipart form fields: `score` (encrypted score data, and the replay
+/// file), `iv`, `osuver`, `pass`, `st`, `ft`, `x`. The player is
+/// authenticated with the username of the score data and `pass`.
 #[utoipa::path(
     post,
     path = "/web/osu-submit-modular-selector.php",
     tag = "bancho",
     responses(
-        (status = 200, description = "Bancho osu_submit_modular_selector", body = [String]),
+        (status = 200, description = "Submission charts, or `error: {code}` if the score is refused", body = String),
+        (status = 500, description = "Failed to store the score, the client retries"),
     )
 )]
 pub async fn osu_submit_modular_selector(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
-    routing_service.osu_submit_modular_selector().await
+    multipart: Multipart,
+) -> Result<Response, BanchoHttpError> {
+    let form = parser::parse_score_submission_form(multipart).await?;
+    routing_service.osu_submit_modular_selector(form).await
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct GetReplayQuery {
+    /// Score id as sent in the leaderboards, the ids of the relax and
+    /// autopilot scores are offset
+    pub c: i64,
+    /// Vanilla game mode (`0` - `3`)
+    #[serde(default)]
+    pub m: i32,
 }
 
 /// Bancho osu_getreplay
@@ -310,14 +444,17 @@ pub async fn osu_submit_modular_selector(
     get,
     path = "/web/osu-getreplay.php",
     tag = "bancho",
+    params(GetReplayQuery),
     responses(
-        (status = 200, description = "Bancho osu_getreplay"),
+        (status = 200, description = "Replay of the score"),
+        (status = 404, description = "Replay not exists"),
     )
 )]
 pub async fn osu_getreplay(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
-    routing_service.osu_getreplay().await
+    Query(query): Query<GetReplayQuery>,
+) -> Result<Response, BanchoHttpError> {
+    routing_service.osu_getreplay(query.c, query.m).await
 }
 
 /// Bancho osu_rate
@@ -326,28 +463,57 @@ pub async fn osu_getreplay(
     path = "/web/osu-rate.php",
     tag = "bancho",
     responses(
-        (status = 200, description = "Bancho osu_rate"),
+        (status = 501, description = "Not implemented"),
     )
 )]
 pub async fn osu_rate(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
+) -> Result<Response, BanchoHttpError> {
     routing_service.osu_rate().await
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct GetScoresQuery {
+    /// Username
+    pub us: String,
+    /// Password md5
+    pub ha: String,
+    /// Beatmap md5
+    pub c: String,
+    /// Vanilla game mode (`0` - `3`)
+    #[serde(default)]
+    pub m: i32,
+    /// Mods bitflags
+    #[serde(default)]
+    pub mods: u32,
+    /// Leaderboard type (`1`: global, `2`: selected mods, `3`: friends,
+    /// `4`: country)
+    #[serde(default)]
+    pub v: i32,
+}
+
 /// Bancho osu_osz2_getscores
+///
+/// The player is authenticated with the `us` and `ha` query parameters.
 #[utoipa::path(
     get,
     path = "/web/osu-osz2-getscores.php",
     tag = "bancho",
+    params(GetScoresQuery),
     responses(
-        (status = 200, description = "Bancho osu_osz2_getscores"),
+        (status = 200, description = "Leaderboard of the beatmap", body = String),
+        (status = 401, description = "Invalid credentials"),
     )
 )]
 pub async fn osu_osz2_getscores(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
-    routing_service.osu_osz2_getscores().await
+    Query(query): Query<GetScoresQuery>,
+) -> Result<Response, BanchoHttpError> {
+    routing_service
+        .osu_osz2_getscores(
+            query.us, query.ha, query.c, query.m, query.mods, query.v,
+        )
+        .await
 }
 
 /// Bancho osu_comment
@@ -356,12 +522,12 @@ pub async fn osu_osz2_getscores(
     path = "/web/osu-comment.php",
     tag = "bancho",
     responses(
-        (status = 200, description = "Bancho osu_comment", body = [String]),
+        (status = 501, description = "Not implemented"),
     )
 )]
 pub async fn osu_comment(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
+) -> Result<Response, BanchoHttpError> {
     routing_service.osu_comment().await
 }
 
@@ -376,7 +542,7 @@ pub async fn osu_comment(
 )]
 pub async fn osu_markasread(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
+) -> Result<Response, BanchoHttpError> {
     routing_service.osu_markasread().await
 }
 
@@ -386,12 +552,12 @@ pub async fn osu_markasread(
     path = "/web/osu-getseasonal.php",
     tag = "bancho",
     responses(
-        (status = 200, description = "Bancho osu_getseasonal"),
+        (status = 200, description = "Urls of the active seasonal backgrounds", body = [String]),
     )
 )]
 pub async fn osu_getseasonal(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
+) -> Result<Response, BanchoHttpError> {
     routing_service.osu_getseasonal().await
 }
 
@@ -406,23 +572,34 @@ pub async fn osu_getseasonal(
 )]
 pub async fn bancho_connect(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
+) -> Result<Response, BanchoHttpError> {
     routing_service.bancho_connect().await
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CheckUpdatesQuery {
+    /// Release stream of the client, e.g. `stable40`, `beta40`,
+    /// `cuttingedge`, unknown streams fall back to stable.
+    pub stream: Option<String>,
+}
+
 /// Bancho check_updates
+///
+/// Responds the files of the updater manifest for the stream.
 #[utoipa::path(
     get,
     path = "/web/check-updates.php",
     tag = "bancho",
+    params(CheckUpdatesQuery),
     responses(
-        (status = 200, description = "Bancho check_updates"),
+        (status = 200, description = "Files of the updater manifest"),
     )
 )]
 pub async fn check_updates(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
-    routing_service.check_updates().await
+    Query(query): Query<CheckUpdatesQuery>,
+) -> Result<Response, BanchoHttpError> {
+    routing_service.check_updates(query.stream).await
 }
 
 /// Bancho update_beatmap
@@ -436,6 +613,6 @@ pub async fn check_updates(
 )]
 pub async fn update_beatmap(
     Extension(routing_service): Extension<DynBanchoRoutingService>,
-) -> Response {
+) -> Result<Response, BanchoHttpError> {
     routing_service.update_beatmap().await
 }
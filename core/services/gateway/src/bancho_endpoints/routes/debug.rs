@@ -1,22 +1,31 @@
 use axum::{
+    extract::Query,
     response::{IntoResponse, Response},
     routing::*,
     Extension, Router,
 };
+use core_bancho::DynBanchoService;
 use core_bancho_state::DynBanchoStateService;
-use pb_bancho_state::UserData;
+use pb_bancho_state::{GetSessionsPageRequest, UserData, UserQuery};
 use serde_json::{Map, Value};
+use std::collections::HashSet;
+use utoipa::IntoParams;
 
 pub struct BanchoDebugRouter;
 
 impl BanchoDebugRouter {
     pub fn new_router<T: Clone + Sync + Send + 'static>(
         bancho_state_service: DynBanchoStateService,
+        bancho_service: DynBanchoService,
     ) -> Router<T> {
         Router::new()
             .route("/test", get(test))
             .route("/get_all_sessions", get(get_all_sessions))
+            .route("/get_sessions_page", get(get_sessions_page))
+            .route("/metrics", get(metrics))
+            .route("/packet_history", get(packet_history))
             .layer(Extension(bancho_state_service))
+            .layer(Extension(bancho_service))
     }
 }
 
@@ -82,3 +91,287 @@ pub async fn get_all_sessions(
                 .into_response()
         })
 }
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct GetSessionsPageQuery {
+    /// `index` in: `["session_id", "user_id", "username", "username_unicode"]`
+    pub index: Option<String>,
+    pub offset: Option<u64>,
+    /// Max `1000`
+    pub limit: Option<u64>,
+}
+
+/// get a page of sessions from the specified index
+#[utoipa::path(
+    get,
+    path = "/get_sessions_page",
+    tag = "bancho_debug",
+    params(GetSessionsPageQuery),
+    responses(
+        (status = 200, description = "get a page of sessions"),
+    )
+)]
+pub async fn get_sessions_page(
+    Extension(bancho_state_service): Extension<DynBanchoStateService>,
+    Query(query): Query<GetSessionsPageQuery>,
+) -> Response {
+    use pb_bancho_state::get_sessions_page_request::SessionIndex;
+
+    #[derive(Serialize)]
+    struct SessionsPage {
+        total: u64,
+        sessions: Vec<Map<String, Value>>,
+    }
+
+    let index = match query.index.as_deref() {
+        None | Some("session_id") => SessionIndex::SessionId,
+        Some("user_id") => SessionIndex::UserId,
+        Some("username") => SessionIndex::Username,
+        Some("username_unicode") => SessionIndex::UsernameUnicode,
+        Some(_) => {
+            return (axum::http::StatusCode::BAD_REQUEST, "invalid index")
+                .into_response()
+        },
+    };
+
+    bancho_state_service
+        .get_sessions_page(GetSessionsPageRequest {
+            index: index as i32,
+            offset: query.offset.unwrap_or_default(),
+            limit: query.limit.unwrap_or(50),
+        })
+        .await
+        .map(|res| {
+            serde_json::to_string_pretty(&SessionsPage {
+                total: res.total,
+                sessions: res
+                    .sessions
+                    .into_iter()
+                    .filter_map(|i| serde_json::from_str(&i.json).ok())
+                    .collect(),
+            })
+            .unwrap()
+            .into_response()
+        })
+        .unwrap_or_else(|err| {
+            (axum::http::StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+                .into_response()
+        })
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct PacketHistoryQuery {
+    pub user_id: i32,
+}
+
+/// last packets sent by the user session, oldest first
+#[utoipa::path(
+    get,
+    path = "/packet_history",
+    tag = "bancho_debug",
+    params(PacketHistoryQuery),
+    responses(
+        (status = 200, description = "last packets of the session"),
+    )
+)]
+pub async fn packet_history(
+    Extension(bancho_state_service): Extension<DynBanchoStateService>,
+    Query(query): Query<PacketHistoryQuery>,
+) -> Response {
+    #[derive(Serialize)]
+    struct Packet {
+        packet_id: i32,
+        packet_name: String,
+        received_at: i64,
+    }
+
+    bancho_state_service
+        .get_packet_history(UserQuery::UserId(query.user_id))
+        .await
+        .map(|res| {
+            serde_json::to_string_pretty(
+                &res.packets
+                    .into_iter()
+                    .map(|p| Packet {
+                        packet_id: p.packet_id,
+                        packet_name: p.packet_name,
+                        received_at: p.received_at,
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap()
+            .into_response()
+        })
+        .unwrap_or_else(|err| {
+            (axum::http::StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+                .into_response()
+        })
+}
+
+/// bancho state, packet handlers and leaderboard cache metrics in
+/// prometheus text format
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "bancho_debug",
+    responses(
+        (status = 200, description = "bancho metrics"),
+    )
+)]
+pub async fn metrics(
+    Extension(bancho_state_service): Extension<DynBanchoStateService>,
+    Extension(bancho_service): Extension<DynBanchoService>,
+) -> Response {
+    let internal_error = |err: String| {
+        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, err).into_response()
+    };
+
+    let stats = match bancho_state_service.get_sessions_stats().await {
+        Ok(stats) => stats,
+        Err(err) => return internal_error(err.to_string()),
+    };
+
+    let packet_metrics = match bancho_service.get_packet_metrics().await {
+        Ok(packet_metrics) => packet_metrics,
+        Err(err) => return internal_error(err.to_string()),
+    };
+
+    let leaderboard_cache =
+        match bancho_service.get_leaderboard_cache_metrics().await {
+            Ok(leaderboard_cache) => leaderboard_cache,
+            Err(err) => return internal_error(err.to_string()),
+        };
+
+    let mut metrics = String::new();
+    let mut families = HashSet::new();
+
+    let mut sample = |name: &str,
+                      kind: &str,
+                      help: &str,
+                      labels: &str,
+                      val: &dyn std::fmt::Display| {
+        // histograms samples are suffixed, e.g. `_bucket`
+        let family = ["_bucket", "_sum", "_count"]
+            .iter()
+            .find_map(|suffix| name.strip_suffix(suffix))
+            .filter(|_| kind == "histogram")
+            .unwrap_or(name);
+
+        if families.insert(family.to_owned()) {
+            metrics.push_str(&format!(
+                "# HELP {family} {help}\n# TYPE {family} {kind}\n"
+            ));
+        }
+        metrics.push_str(&format!("{name}{labels} {val}\n"));
+    };
+
+    sample(
+        "bancho_state_sessions",
+        "gauge",
+        "Number of online user sessions.",
+        "",
+        &stats.len,
+    );
+
+    for (index, val) in [
+        ("session_id", stats.indexed_by_session_id),
+        ("user_id", stats.indexed_by_user_id),
+        ("username", stats.indexed_by_username),
+        ("username_unicode", stats.indexed_by_username_unicode),
+    ] {
+        sample(
+            "bancho_state_sessions_indexed",
+            "gauge",
+            "Number of user sessions in each index.",
+            &format!("{{index=\"{index}\"}}"),
+            &val,
+        );
+    }
+
+    sample(
+        "bancho_state_queued_packets",
+        "gauge",
+        "Number of packets waiting to be dequeued by clients.",
+        "",
+        &stats.queued_packets,
+    );
+
+    for (result, val) in
+        [("hit", leaderboard_cache.hits), ("miss", leaderboard_cache.misses)]
+    {
+        sample(
+            "bancho_leaderboard_cache_requests_total",
+            "counter",
+            "Number of leaderboard requests, by cache result.",
+            &format!("{{result=\"{result}\"}}"),
+            &val,
+        );
+    }
+
+    sample(
+        "bancho_leaderboard_cache_entries",
+        "gauge",
+        "Number of cached beatmap leaderboards.",
+        "",
+        &leaderboard_cache.entries,
+    );
+
+    for packet in packet_metrics.packets.iter() {
+        let packet_label = format!("packet=\"{}\"", packet.packet_name);
+
+        for (outcome, val) in [
+            ("handled", packet.handled),
+            ("errored", packet.errored),
+            ("unhandled", packet.unhandled),
+        ] {
+            sample(
+                "bancho_packets_total",
+                "counter",
+                "Number of packets processed, by packet and outcome.",
+                &format!("{{{packet_label},outcome=\"{outcome}\"}}"),
+                &val,
+            );
+        }
+
+        let help = "Latency of the packet handlers in seconds.";
+        for (le, count) in packet_metrics
+            .latency_buckets_secs
+            .iter()
+            .zip(packet.latency_bucket_counts.iter())
+        {
+            sample(
+                "bancho_packet_handler_seconds_bucket",
+                "histogram",
+                help,
+                &format!("{{{packet_label},le=\"{le}\"}}"),
+                count,
+            );
+        }
+
+        let count = packet.handled + packet.errored + packet.unhandled;
+        sample(
+            "bancho_packet_handler_seconds_bucket",
+            "histogram",
+            help,
+            &format!("{{{packet_label},le=\"+Inf\"}}"),
+            &count,
+        );
+        sample(
+            "bancho_packet_handler_seconds_sum",
+            "histogram",
+            help,
+            &format!("{{{packet_label}}}"),
+            &packet.latency_sum_secs,
+        );
+        sample(
+            "bancho_packet_handler_seconds_count",
+            "histogram",
+            help,
+            &format!("{{{packet_label}}}"),
+            &count,
+        );
+    }
+
+    ([(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics)
+        .into_response()
+}
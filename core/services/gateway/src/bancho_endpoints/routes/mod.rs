@@ -1,5 +1,7 @@
+pub mod admin;
 pub mod bancho;
 pub mod debug;
 
+pub use admin::BanchoAdminRouter;
 pub use bancho::BanchoRouter;
 pub use debug::BanchoDebugRouter;
@@ -0,0 +1,420 @@
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use core_bancho::BanchoServiceError;
+use pb_bancho::{ScoreSummary, SubmitScoreRequest, SubmitScoreResponse};
+use simple_rijndael::{impls::RijndaelCbc, paddings::ZeroPadding};
+use std::fmt::Display;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ScoreSubmissionError {
+    #[error("missing form field: {0}")]
+    MissingField(&'static str),
+    #[error("invalid form field: {0}")]
+    InvalidField(&'static str),
+    #[error("failed to decrypt score data")]
+    DecryptFailed,
+    #[error("invalid score data: {0}")]
+    InvalidScoreData(String),
+    #[error("beatmap not submitted")]
+    BeatmapNotSubmitted,
+    #[error("score already submitted")]
+    DuplicateScore,
+    #[error("score rejected")]
+    Rejected,
+    #[error("invalid credentials")]
+    InvalidCredentials,
+    #[error("failed to submit score: {0}")]
+    ServerError(#[source] BanchoServiceError),
+}
+
+impl From<BanchoServiceError> for ScoreSubmissionError {
+    fn from(err: BanchoServiceError) -> Self {
+        match err {
+            BanchoServiceError::InvalidScore(reason) => {
+                Self::InvalidScoreData(reason)
+            },
+            BanchoServiceError::BeatmapNotSubmitted => {
+                Self::BeatmapNotSubmitted
+            },
+            BanchoServiceError::DuplicateScore => Self::DuplicateScore,
+            // The reason is only logged by the bancho service
+            BanchoServiceError::ScoreRejected(_) => Self::Rejected,
+            err => Self::ServerError(err),
+        }
+    }
+}
+
+impl ScoreSubmissionError {
+    /// Server errors are `500` so that the client retries the submission,
+    /// the client gives up on the other errors.
+    #[inline]
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::OK,
+        }
+    }
+
+    /// Sent to the client as `error: {code}`.
+    #[inline]
+    pub fn response_code(&self) -> &'static str {
+        match self {
+            Self::BeatmapNotSubmitted => "beatmap",
+            // The client asks the player to log in again
+            Self::InvalidCredentials => "pass",
+            _ => "no",
+        }
+    }
+}
+
+/// Multipart form sent by the client to
+/// `/web/osu-submit-modular-selector.php`.
+#[derive(Debug, Default, Clone)]
+pub struct ScoreSubmissionForm {
+    /// Base64 of the encrypted score data (text field `score`).
+    pub score_data: String,
+    /// Base64 of the encryption iv (`iv`).
+    pub iv: String,
+    /// Client build date, part of the encryption key (`osuver`).
+    pub osu_version: String,
+    /// Md5 of the password of the player (`pass`), the username is part of
+    /// the score data.
+    pub password_md5: String,
+    /// Replay file (file field `score`).
+    pub replay: Vec<u8>,
+    /// Milliseconds played (`st`).
+    pub score_time: i32,
+    /// Milliseconds played before failing or quitting (`ft`).
+    pub fail_time: i32,
+    /// The player quit before the end of the beatmap (`x`).
+    pub exited: bool,
+}
+
+impl ScoreSubmissionForm {
+    #[inline]
+    pub fn decrypt_score_data(&self) -> Result<String, ScoreSubmissionError> {
+        decrypt_score_data(&self.score_data, &self.iv, &self.osu_version)
+    }
+
+    #[inline]
+    pub fn playtime(&self, passed: bool) -> i32 {
+        if passed {
+            self.score_time
+        } else {
+            self.fail_time
+        }
+    }
+}
+
+#[inline]
+fn score_key(osu_version: &str) -> String {
+    format!("osu!-scoreburgr---------{osu_version}")
+}
+
+/// Decrypt the score data with Rijndael (256 bits blocks, CBC), the key is
+/// derived from the client build date.
+pub fn decrypt_score_data(
+    score_data: &str,
+    iv: &str,
+    osu_version: &str,
+) -> Result<String, ScoreSubmissionError> {
+    let score_data = BASE64
+        .decode(score_data)
+        .map_err(|_| ScoreSubmissionError::InvalidField("score"))?;
+    let iv = BASE64
+        .decode(iv)
+        .map_err(|_| ScoreSubmissionError::InvalidField("iv"))?;
+
+    let decrypted =
+        RijndaelCbc::<ZeroPadding>::new(score_key(osu_version).as_bytes(), 32)
+            .and_then(|cipher| cipher.decrypt(&iv, score_data))
+            .map_err(|_| ScoreSubmissionError::DecryptFailed)?;
+
+    String::from_utf8(decrypted)
+        .map(|s| s.trim_end_matches('\0').to_owned())
+        .map_err(|_| ScoreSubmissionError::DecryptFailed)
+}
+
+/// Decrypted score data, formatted as
+/// `{beatmap md5}:{username}:{score md5}:{n300}:{n100}:{n50}:{geki}:{katu}:
+/// {miss}:{score}:{max combo}:{perfect}:{grade}:{mods}:{passed}:{mode}:
+/// {client time}:{client version}`, the client version is padded with one
+/// space per client flag.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ScoreData {
+    pub beatmap_md5: String,
+    pub username: String,
+    pub score_md5: String,
+    pub n300: i32,
+    pub n100: i32,
+    pub n50: i32,
+    pub geki: i32,
+    pub katu: i32,
+    pub miss: i32,
+    pub score: i32,
+    pub max_combo: i32,
+    pub perfect: bool,
+    pub grade: String,
+    pub mods: u32,
+    pub passed: bool,
+    pub mode: i32,
+    pub client_version: String,
+    pub client_flags: i32,
+}
+
+impl ScoreData {
+    pub fn parse(s: &str) -> Result<Self, ScoreSubmissionError> {
+        let invalid =
+            |field: &str| ScoreSubmissionError::InvalidScoreData(field.into());
+
+        let fields = s.split(':').collect::<Vec<_>>();
+        if fields.len() < 18 {
+            return Err(invalid("missing fields"));
+        }
+
+        let num = |i: usize, field: &str| {
+            fields[i].trim().parse::<i32>().map_err(|_| invalid(field))
+        };
+        let flag = |i: usize, field: &str| match fields[i].trim() {
+            "True" => Ok(true),
+            "False" => Ok(false),
+            _ => Err(invalid(field)),
+        };
+
+        let beatmap_md5 = fields[0].trim();
+        let score_md5 = fields[2].trim();
+        if beatmap_md5.is_empty() || score_md5.is_empty() {
+            return Err(invalid("md5"));
+        }
+
+        Ok(Self {
+            beatmap_md5: beatmap_md5.to_owned(),
+            username: fields[1].trim().to_owned(),
+            score_md5: score_md5.to_owned(),
+            n300: num(3, "n300")?,
+            n100: num(4, "n100")?,
+            n50: num(5, "n50")?,
+            geki: num(6, "geki")?,
+            katu: num(7, "katu")?,
+            miss: num(8, "miss")?,
+            score: num(9, "score")?,
+            max_combo: num(10, "max combo")?,
+            perfect: flag(11, "perfect")?,
+            grade: fields[12].trim().to_owned(),
+            mods: fields[13].trim().parse().map_err(|_| invalid("mods"))?,
+            passed: flag(14, "passed")?,
+            mode: num(15, "mode")?,
+            client_version: fields[17].trim().to_owned(),
+            client_flags: fields[17].matches(' ').count() as i32 & !4,
+        })
+    }
+
+    #[inline]
+    pub fn into_request(
+        self,
+        user_id: i32,
+        playtime: i32,
+    ) -> SubmitScoreRequest {
+        SubmitScoreRequest {
+            user_id,
+            beatmap_md5: self.beatmap_md5,
+            score_md5: self.score_md5,
+            mode: self.mode,
+            mods: self.mods,
+            score: self.score,
+            max_combo: self.max_combo,
+            n300: self.n300,
+            n100: self.n100,
+            n50: self.n50,
+            geki: self.geki,
+            katu: self.katu,
+            miss: self.miss,
+            perfect: self.perfect,
+            passed: self.passed,
+            grade: self.grade,
+            client_version: self.client_version,
+            client_flags: self.client_flags,
+            playtime,
+        }
+    }
+}
+
+fn chart_entry<T: Display>(
+    name: &str,
+    before: Option<T>,
+    after: Option<T>,
+) -> String {
+    let value = |v: Option<T>| v.map(|v| v.to_string()).unwrap_or_default();
+
+    format!("{name}Before:{}|{name}After:{}", value(before), value(after))
+}
+
+fn score_chart_entries(
+    before: Option<&ScoreSummary>,
+    after: Option<&ScoreSummary>,
+) -> Vec<String> {
+    vec![
+        chart_entry::<i32>("rank", None, None),
+        chart_entry(
+            "rankedScore",
+            before.map(|s| s.score),
+            after.map(|s| s.score),
+        ),
+        chart_entry(
+            "totalScore",
+            before.map(|s| s.score),
+            after.map(|s| s.score),
+        ),
+        chart_entry(
+            "maxCombo",
+            before.map(|s| s.max_combo),
+            after.map(|s| s.max_combo),
+        ),
+        chart_entry(
+            "accuracy",
+            before.map(|s| format!("{:.2}", s.accuracy)),
+            after.map(|s| format!("{:.2}", s.accuracy)),
+        ),
+        chart_entry(
+            "pp",
+            before.map(|s| s.pp.round() as i64),
+            after.map(|s| s.pp.round() as i64),
+        ),
+    ]
+}
+
+/// The charts shown by the client after a submission, lines of `|`
+/// separated `{key}:{value}` pairs: the beatmap info, the beatmap ranking
+/// chart (the best score of the user before and after the submission) and
+/// the overall ranking chart.
+pub fn submission_charts(res: &SubmitScoreResponse) -> String {
+    let before = res.previous_best.as_ref();
+    let after = if res.personal_best { res.score.as_ref() } else { before };
+
+    let mut charts = vec![
+        format!("beatmapId:{}", res.beatmap_id),
+        format!("beatmapSetId:{}", res.beatmapset_id),
+        "beatmapPlaycount:0".to_owned(),
+        "beatmapPasscount:0".to_owned(),
+        "approvedDate:".to_owned(),
+        "\n".to_owned(),
+        "chartId:beatmap".to_owned(),
+        "chartUrl:".to_owned(),
+        "chartName:Beatmap Ranking".to_owned(),
+    ];
+    charts.extend(score_chart_entries(before, after));
+    charts.push(format!("onlineScoreId:{}", res.score_id));
+    charts.push("\n".to_owned());
+
+    charts.extend([
+        "chartId:overall".to_owned(),
+        "chartUrl:".to_owned(),
+        "chartName:Overall Ranking".to_owned(),
+    ]);
+    charts.extend(score_chart_entries(None, None));
+    charts.push("achievements-new:".to_owned());
+
+    charts.join("|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCORE_DATA: &str = "1cf5b2c2edfafd055536d2cefcb89c0e:peppy :\
+        0123456789abcdef0123456789abcdef:98:2:0:10:1:0:1234567:250:False:S:\
+        72:True:0:230101120000:20230101   ";
+
+    #[test]
+    fn test_parse_score_data() {
+        let data = ScoreData::parse(SCORE_DATA).unwrap();
+
+        assert_eq!(data.beatmap_md5, "1cf5b2c2edfafd055536d2cefcb89c0e");
+        assert_eq!(data.username, "peppy");
+        assert_eq!(data.score_md5, "0123456789abcdef0123456789abcdef");
+        assert_eq!((data.n300, data.n100, data.n50), (98, 2, 0));
+        assert_eq!((data.geki, data.katu, data.miss), (10, 1, 0));
+        assert_eq!((data.score, data.max_combo), (1234567, 250));
+        assert!(!data.perfect);
+        assert_eq!(data.grade, "S");
+        assert_eq!(data.mods, 72);
+        assert!(data.passed);
+        assert_eq!(data.mode, 0);
+        assert_eq!(data.client_version, "20230101");
+        assert_eq!(data.client_flags, 3);
+
+        assert!(ScoreData::parse("").is_err());
+        assert!(ScoreData::parse(&SCORE_DATA.replace(":98:", ":x:")).is_err());
+        assert!(ScoreData::parse(&SCORE_DATA.replace("True", "1")).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_score_data() {
+        let iv = [7u8; 32];
+        let encrypted = RijndaelCbc::<ZeroPadding>::new(
+            score_key("20230101").as_bytes(),
+            32,
+        )
+        .unwrap()
+        .encrypt(&iv, SCORE_DATA.as_bytes().to_vec())
+        .unwrap();
+
+        let form = ScoreSubmissionForm {
+            score_data: BASE64.encode(encrypted),
+            iv: BASE64.encode(iv),
+            osu_version: "20230101".into(),
+            ..Default::default()
+        };
+        assert_eq!(form.decrypt_score_data().unwrap(), SCORE_DATA);
+
+        assert!(matches!(
+            ScoreSubmissionForm { iv: "!".into(), ..form.clone() }
+                .decrypt_score_data(),
+            Err(ScoreSubmissionError::InvalidField("iv"))
+        ));
+        assert!(ScoreSubmissionForm { osu_version: "20230102".into(), ..form }
+            .decrypt_score_data()
+            .map(|data| data != SCORE_DATA)
+            .unwrap_or(true));
+    }
+
+    #[test]
+    fn test_submission_charts() {
+        let summary = |score, pp| ScoreSummary {
+            score,
+            max_combo: 100,
+            accuracy: 98.5,
+            pp,
+        };
+
+        let res = SubmitScoreResponse {
+            score_id: 42,
+            beatmap_id: 1,
+            beatmapset_id: 2,
+            score: Some(summary(2000, 120.4)),
+            previous_best: Some(summary(1000, 100.0)),
+            personal_best: true,
+        };
+        let charts = submission_charts(&res);
+        let lines = charts.split('\n').collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("beatmapId:1|beatmapSetId:2|"));
+        assert!(
+            lines[1].contains("|rankedScoreBefore:1000|rankedScoreAfter:2000|")
+        );
+        assert!(lines[1].contains("|accuracyBefore:98.50|accuracyAfter:98.50|"));
+        assert!(lines[1].contains("|ppBefore:100|ppAfter:120|"));
+        assert!(lines[1].ends_with("|onlineScoreId:42|"));
+        assert!(lines[2].starts_with("|chartId:overall|"));
+
+        // a lower score keeps the previous best
+        let charts = submission_charts(&SubmitScoreResponse {
+            personal_best: false,
+            ..res
+        });
+        assert!(
+            charts.contains("|rankedScoreBefore:1000|rankedScoreAfter:1000|")
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use super::Md5FileStore;
+use axum::http::StatusCode;
+use std::{io, path::PathBuf};
+use tools::rate_limiter::KeyedRateLimiter;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ScreenshotError {
+    #[error("screenshot is too large, max size is {0} bytes")]
+    TooLarge(usize),
+    #[error("screenshot must be a png or jpeg image")]
+    UnsupportedFormat,
+    #[error("screenshot not exists")]
+    NotExists,
+    #[error("too many screenshots, retry in {0} seconds")]
+    TooManyScreenshots(u64),
+    #[error("failed to access screenshot: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl ScreenshotError {
+    #[inline]
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UnsupportedFormat => StatusCode::BAD_REQUEST,
+            Self::NotExists => StatusCode::NOT_FOUND,
+            Self::TooManyScreenshots(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Multipart form sent by the client to `/web/osu-screenshot.php`.
+#[derive(Debug, Default, Clone)]
+pub struct ScreenshotForm {
+    /// Username of the player (`u`).
+    pub username: String,
+    /// Md5 of the password of the player (`p`).
+    pub password_md5: String,
+    /// Png or jpeg image (file field `ss`).
+    pub screenshot: Vec<u8>,
+}
+
+/// Limiter of the screenshot uploads, keyed on the user id.
+pub type ScreenshotLimiter = KeyedRateLimiter<i32>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenshotFormat {
+    Png,
+    Jpeg,
+}
+
+impl ScreenshotFormat {
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    const JPEG_SIGNATURE: &[u8] = b"\xff\xd8\xff";
+
+    /// Detect the format from the leading bytes of the image, the file name
+    /// and content type sent by the client are not trusted.
+    #[inline]
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(Self::PNG_SIGNATURE) {
+            Some(Self::Png)
+        } else if data.starts_with(Self::JPEG_SIGNATURE) {
+            Some(Self::Jpeg)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext {
+            "png" => Some(Self::Png),
+            "jpg" => Some(Self::Jpeg),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+        }
+    }
+
+    #[inline]
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+        }
+    }
+}
+
+/// Screenshots stored on disk, named after the md5 of their content, e.g.
+/// `{dir}/9e107d9d372bb6826bd81d3542a419d6.png`.
+#[derive(Debug, Clone)]
+pub struct ScreenshotStore {
+    pub files: Md5FileStore,
+}
+
+impl ScreenshotStore {
+    #[inline]
+    pub fn new<P: Into<PathBuf>>(dir: P, max_size: usize) -> Self {
+        Self { files: Md5FileStore::new(dir, max_size) }
+    }
+
+    /// Validate and store the screenshot, returns its id (the file name).
+    /// Uploading the same image twice returns the same id.
+    pub async fn save(&self, data: &[u8]) -> Result<String, ScreenshotError> {
+        if data.len() > self.files.max_size {
+            return Err(ScreenshotError::TooLarge(self.files.max_size));
+        }
+
+        let format = ScreenshotFormat::detect(data)
+            .ok_or(ScreenshotError::UnsupportedFormat)?;
+
+        let id = format!("{:x}.{}", md5::compute(data), format.extension());
+        self.files.write_once(&id, data).await?;
+
+        Ok(id)
+    }
+
+    /// Returns the screenshot content and format.
+    pub async fn load(
+        &self,
+        id: &str,
+    ) -> Result<(Vec<u8>, ScreenshotFormat), ScreenshotError> {
+        let format = Self::parse_id(id).ok_or(ScreenshotError::NotExists)?;
+
+        match self.files.read(id).await? {
+            Some(data) => Ok((data, format)),
+            None => Err(ScreenshotError::NotExists),
+        }
+    }
+
+    /// Only ids created by [`ScreenshotStore::save`] are valid, so that the
+    /// id can not escape the screenshots directory.
+    pub fn parse_id(id: &str) -> Option<ScreenshotFormat> {
+        let (hash, ext) = id.split_once('.')?;
+
+        if !Md5FileStore::is_md5(hash) {
+            return None;
+        }
+
+        ScreenshotFormat::from_extension(ext)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_screenshot_format_detect() {
+        assert_eq!(
+            ScreenshotFormat::detect(b"\x89PNG\r\n\x1a\n...."),
+            Some(ScreenshotFormat::Png)
+        );
+        assert_eq!(
+            ScreenshotFormat::detect(b"\xff\xd8\xff\xe0...."),
+            Some(ScreenshotFormat::Jpeg)
+        );
+        assert_eq!(ScreenshotFormat::detect(b"GIF89a"), None);
+        assert_eq!(ScreenshotFormat::detect(b""), None);
+    }
+
+    #[test]
+    fn test_screenshot_parse_id() {
+        assert_eq!(
+            ScreenshotStore::parse_id("9e107d9d372bb6826bd81d3542a419d6.png"),
+            Some(ScreenshotFormat::Png)
+        );
+        assert_eq!(
+            ScreenshotStore::parse_id("9e107d9d372bb6826bd81d3542a419d6.jpg"),
+            Some(ScreenshotFormat::Jpeg)
+        );
+        assert_eq!(
+            ScreenshotStore::parse_id("9e107d9d372bb6826bd81d3542a419d6.gif"),
+            None
+        );
+        assert_eq!(ScreenshotStore::parse_id("../../etc/passwd.png"), None);
+        assert_eq!(
+            ScreenshotStore::parse_id(
+                "../9e107d9d372bb6826bd81d3542a419d6.png"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_screenshot_limiter_per_user() {
+        let limiter = ScreenshotLimiter::per_minute(6, 2);
+
+        assert!(limiter.check(1).is_ok());
+        assert!(limiter.check(1).is_ok());
+        assert!(limiter.check(1).is_err());
+
+        // Other players are not affected
+        assert!(limiter.check(2).is_ok());
+
+        assert!(ScreenshotLimiter::per_minute(0, 0).check(1).is_ok());
+    }
+}
@@ -0,0 +1,158 @@
+use chrono::{Datelike, NaiveDate};
+use std::{io, path::Path};
+
+/// A season of the seasonal backgrounds file.
+#[derive(Debug, Clone, Deserialize)]
+struct SeasonEntry {
+    start: Option<String>,
+    end: Option<String>,
+    backgrounds: Vec<String>,
+}
+
+/// Backgrounds shown by the client menu between `start` and `end`
+/// (inclusive, repeated every year), seasons without date range are used
+/// when no other season is active.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Season {
+    /// `(start, end)` days as `month * 100 + day`.
+    pub range: Option<(u32, u32)>,
+    pub backgrounds: Vec<String>,
+}
+
+impl Season {
+    /// Returns `true` if the day (`month * 100 + day`) is in the season,
+    /// ranges may wrap over the new year, e.g. `12-01` to `02-28`.
+    #[inline]
+    pub fn contains(&self, day: u32) -> bool {
+        match self.range {
+            Some((start, end)) if start <= end => start <= day && day <= end,
+            Some((start, end)) => day >= start || day <= end,
+            None => false,
+        }
+    }
+}
+
+/// Parse a `MM-DD` day as `month * 100 + day`.
+pub fn parse_month_day(s: &str) -> Option<u32> {
+    let (month, day) = s.trim().split_once('-')?;
+    let (month, day) = (month.parse::<u32>().ok()?, day.parse::<u32>().ok()?);
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    Some(month * 100 + day)
+}
+
+/// Backgrounds served by `/web/osu-getseasonal.php`.
+///
+/// The file is a json array of seasons, e.g.
+/// `[{"start": "12-01", "end": "02-28", "backgrounds": [..]}]`.
+#[derive(Debug, Clone, Default)]
+pub struct SeasonalBackgrounds {
+    pub seasons: Vec<Season>,
+}
+
+impl SeasonalBackgrounds {
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let invalid_data =
+            |err: String| io::Error::new(io::ErrorKind::InvalidData, err);
+
+        let entries: Vec<SeasonEntry> = serde_json::from_str(json)
+            .map_err(|err| invalid_data(err.to_string()))?;
+
+        let seasons = entries
+            .into_iter()
+            .map(|entry| {
+                let range = match (entry.start, entry.end) {
+                    (None, None) => None,
+                    (Some(start), Some(end)) => Some((
+                        parse_month_day(&start).ok_or_else(|| {
+                            invalid_data(format!("invalid start: {start}"))
+                        })?,
+                        parse_month_day(&end).ok_or_else(|| {
+                            invalid_data(format!("invalid end: {end}"))
+                        })?,
+                    )),
+                    _ => {
+                        return Err(invalid_data(
+                            "season requires both start and end".into(),
+                        ))
+                    },
+                };
+
+                Ok(Season { range, backgrounds: entry.backgrounds })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self { seasons })
+    }
+
+    #[inline]
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Backgrounds of the seasons active at the date.
+    pub fn active(&self, date: NaiveDate) -> Vec<&str> {
+        let day = date.month() * 100 + date.day();
+
+        let mut backgrounds = self
+            .seasons
+            .iter()
+            .filter(|season| season.contains(day))
+            .flat_map(|season| season.backgrounds.iter())
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+
+        if backgrounds.is_empty() {
+            backgrounds = self
+                .seasons
+                .iter()
+                .filter(|season| season.range.is_none())
+                .flat_map(|season| season.backgrounds.iter())
+                .map(String::as_str)
+                .collect();
+        }
+
+        backgrounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_month_day() {
+        assert_eq!(parse_month_day("12-01"), Some(1201));
+        assert_eq!(parse_month_day("2-28"), Some(228));
+        assert_eq!(parse_month_day("13-01"), None);
+        assert_eq!(parse_month_day("12"), None);
+    }
+
+    #[test]
+    fn test_active_backgrounds() {
+        let seasonal = SeasonalBackgrounds::from_json(
+            r#"[
+                {"start": "12-01", "end": "02-28", "backgrounds": ["winter"]},
+                {"start": "06-01", "end": "08-31", "backgrounds": ["summer"]},
+                {"backgrounds": ["default"]}
+            ]"#,
+        )
+        .unwrap();
+
+        let date = |m, d| NaiveDate::from_ymd_opt(2023, m, d).unwrap();
+
+        assert_eq!(seasonal.active(date(12, 25)), ["winter"]);
+        assert_eq!(seasonal.active(date(1, 15)), ["winter"]);
+        assert_eq!(seasonal.active(date(7, 1)), ["summer"]);
+        assert_eq!(seasonal.active(date(4, 1)), ["default"]);
+
+        assert!(SeasonalBackgrounds::default().active(date(1, 1)).is_empty());
+        assert!(SeasonalBackgrounds::from_json(
+            r#"[{"start": "12-01", "backgrounds": []}]"#
+        )
+        .is_err());
+    }
+}
@@ -1,25 +1,35 @@
 use super::traits::{BanchoHandlerService, DynBanchoHandlerService};
 use crate::bancho_endpoints::{extractors::BanchoClientVersion, *};
 use async_trait::async_trait;
-use axum::response::{IntoResponse, Response};
+use axum::{
+    extract::ws::{Message, WebSocket},
+    response::{IntoResponse, Response},
+};
 use bancho_packets::PacketBuilder;
 use bancho_packets::PacketReader;
-use core_bancho::DynBanchoService;
+use core_bancho::{BanchoServiceError, DynBanchoService};
 use core_bancho_state::{BanchoStateError, DynBanchoStateService};
 use core_chat::{ChatError, DynChatService};
 use domain_bancho::BanchoClientToken;
+use domain_chat::Platform;
 use pb_bancho::*;
 use pb_bancho_state::{
     CheckUserTokenResponse, DequeueBanchoPacketsRequest, UserQuery,
 };
-use std::{net::IpAddr, str::FromStr, sync::Arc};
+use std::{net::IpAddr, str::FromStr, sync::Arc, time::Duration};
 use tools::lazy_init;
 
+/// Max time a websocket session waits for its packets in one request, the
+/// pushed packets are sent right away.
+const WEBSOCKET_MAX_WAIT: Duration = Duration::from_secs(1);
+
 #[derive(Clone)]
 pub struct BanchoHandlerServiceImpl {
     pub bancho_service: DynBanchoService,
     pub bancho_state_service: DynBanchoStateService,
     pub chat_service: DynChatService,
+    pub login_limiter: Arc<LoginLimiter>,
+    pub client_version_policy: Arc<ClientVersionPolicy>,
 }
 
 impl BanchoHandlerServiceImpl {
@@ -27,8 +37,16 @@ impl BanchoHandlerServiceImpl {
         bancho_service: DynBanchoService,
         bancho_state_service: DynBanchoStateService,
         chat_service: DynChatService,
+        login_limiter: Arc<LoginLimiter>,
+        client_version_policy: Arc<ClientVersionPolicy>,
     ) -> Self {
-        Self { bancho_service, bancho_state_service, chat_service }
+        Self {
+            bancho_service,
+            bancho_state_service,
+            chat_service,
+            login_limiter,
+            client_version_policy,
+        }
     }
 
     pub fn into_service(self) -> DynBanchoHandlerService {
@@ -49,25 +67,43 @@ impl BanchoHandlerService for BanchoHandlerServiceImpl {
             return Err(LoginError::EmptyClientVersion);
         }
 
+        // New sessions would not be drained before the shutdown
+        if peace_api::http::shutting_down() {
+            return Err(LoginError::ShuttingDown);
+        }
+
+        if let Err(retry_after) = self.login_limiter.check(client_ip) {
+            return Err(LoginError::TooManyAttempts(retry_after.as_secs() + 1));
+        }
+
         let request = parser::parse_osu_login_request_body(body)?;
         if request.client_version != version.unwrap().as_str() {
             return Err(LoginError::MismatchedClientVersion);
         }
 
-        Ok(self.bancho_service.login(client_ip, request).await?)
+        if !self.client_version_policy.is_allowed(&request.client_version) {
+            return Err(LoginError::ClientVersionNotAllowed);
+        }
+
+        let login_success =
+            self.bancho_service.login(client_ip, request).await?;
+        self.login_limiter.reset(client_ip);
+
+        Ok(login_success)
     }
 
     #[inline]
     async fn handle_logged(
         &self,
         token: String,
+        ip: IpAddr,
         body: Vec<u8>,
     ) -> Result<Response, BanchoHttpError> {
         let token = BanchoClientToken::from_str(&token)
-            .map_err(|_| BanchoHttpError::InvalidOsuTokenHeader)?;
+            .map_err(|_| BanchoHttpError::Unauthorized)?;
 
         if !self.check_user_token(token.clone()).await? {
-            return Err(BanchoStateError::SessionNotExists)?;
+            return Err(BanchoHttpError::SessionExpired);
         }
 
         let BanchoClientToken { user_id, .. } = token;
@@ -75,13 +111,13 @@ impl BanchoHandlerService for BanchoHandlerServiceImpl {
         let mut builder = None::<PacketBuilder>;
 
         if let Some(extra_packets) =
-            self.process_bancho_packets(user_id, body).await?
+            self.process_bancho_packets(user_id, ip, body).await?
         {
             lazy_init!(builder => builder.extend(extra_packets), PacketBuilder::from(extra_packets))
         }
 
         if let Ok(extra_packets) =
-            self.pull_bancho_packets(UserQuery::UserId(user_id)).await
+            self.pull_bancho_packets(UserQuery::UserId(user_id), true).await
         {
             lazy_init!(builder => builder.extend(extra_packets), PacketBuilder::from(extra_packets))
         }
@@ -109,7 +145,7 @@ impl BanchoHandlerService for BanchoHandlerServiceImpl {
             self.bancho_login(body, ip, version).await?;
 
         if let Ok(p) =
-            self.pull_bancho_packets(UserQuery::UserId(user_id)).await
+            self.pull_bancho_packets(UserQuery::UserId(user_id), false).await
         {
             packets.extend(p);
         }
@@ -132,35 +168,176 @@ impl BanchoHandlerService for BanchoHandlerServiceImpl {
     async fn process_bancho_packets(
         &self,
         user_id: i32,
+        client_ip: IpAddr,
         body: Vec<u8>,
     ) -> Result<Option<Vec<u8>>, BanchoHttpError> {
         if PacketReader::new(&body).next().is_none() {
             return Err(BanchoHttpError::InvalidBanchoPacket);
         }
 
-        let HandleCompleted { packets } =
-            self.bancho_service
-                .batch_process_bancho_packets(
-                    BatchProcessBanchoPacketsRequest { user_id, packets: body },
-                )
-                .await?;
+        let HandleCompleted { packets } = self
+            .bancho_service
+            .batch_process_bancho_packets(
+                Some(client_ip),
+                BatchProcessBanchoPacketsRequest { user_id, packets: body },
+            )
+            .await?;
 
         return Ok(packets);
     }
 
+    async fn handle_websocket(
+        &self,
+        user_id: i32,
+        ip: IpAddr,
+        mut socket: WebSocket,
+    ) {
+        const LOG_TARGET: &str = "bancho::websocket";
+
+        // Chat delivery of the session goes through the socket from now on
+        if let Err(err) = self
+            .chat_service
+            .join_platforms(UserQuery::UserId(user_id), Platform::Websocket)
+            .await
+        {
+            warn!(
+                target: LOG_TARGET,
+                "Failed to tag chat session of user {user_id}: {err}"
+            );
+        }
+
+        let query = UserQuery::UserId(user_id);
+
+        // `None` is sent when the session is logged out or expired
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<Option<Vec<u8>>>(16);
+
+        let bancho_pusher = tokio::spawn({
+            let (handler, query, tx) =
+                (self.clone(), query.clone(), tx.clone());
+            async move {
+                while let Ok(packets) = handler
+                    .wait_bancho_packets(query.clone(), WEBSOCKET_MAX_WAIT)
+                    .await
+                {
+                    if !packets.is_empty()
+                        && tx.send(Some(packets)).await.is_err()
+                    {
+                        return;
+                    }
+                }
+
+                let _ = tx.send(None).await;
+            }
+        });
+
+        let chat_pusher = tokio::spawn({
+            let (handler, query) = (self.clone(), query.clone());
+            async move {
+                while let Ok(packets) = handler
+                    .wait_chat_packets(query.clone(), WEBSOCKET_MAX_WAIT)
+                    .await
+                {
+                    if !packets.is_empty()
+                        && tx.send(Some(packets)).await.is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+        });
+
+        loop {
+            let packets = tokio::select! {
+                message = socket.recv() => match message {
+                    Some(Ok(Message::Binary(body))) => match self
+                        .process_bancho_packets(user_id, ip, body)
+                        .await
+                    {
+                        Ok(packets) => packets,
+                        Err(err) => {
+                            debug!(
+                                target: LOG_TARGET,
+                                "Closing websocket of user {user_id}: {err:?}"
+                            );
+                            break;
+                        },
+                    },
+                    Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                    // Pings are answered by axum, text is not bancho packets
+                    Some(Ok(_)) => None,
+                },
+                pushed = rx.recv() => match pushed {
+                    Some(Some(packets)) => Some(packets),
+                    // The session was logged out or expired
+                    Some(None) | None => break,
+                },
+            };
+
+            if let Some(packets) = packets {
+                if socket.send(Message::Binary(packets)).await.is_err() {
+                    break;
+                }
+            }
+        }
+
+        bancho_pusher.abort();
+        chat_pusher.abort();
+
+        let _ = self.chat_service.logout(query, Platform::Websocket).await;
+    }
+
+    #[inline]
+    async fn client_register(
+        &self,
+        request: ClientRegisterRequest,
+    ) -> Result<ClientRegisterResponse, BanchoServiceError> {
+        self.bancho_service.client_register(request).await
+    }
+
     #[inline]
     async fn pull_bancho_packets(
         &self,
         user_query: UserQuery,
+        coalesce: bool,
+    ) -> Result<Vec<u8>, BanchoStateError> {
+        self.bancho_state_service
+            .dequeue_bancho_packets(DequeueBanchoPacketsRequest {
+                user_query: Some(user_query.into()),
+                coalesce,
+                ..Default::default()
+            })
+            .await
+            .map(|resp| resp.data)
+    }
+
+    #[inline]
+    async fn wait_bancho_packets(
+        &self,
+        user_query: UserQuery,
+        wait: Duration,
     ) -> Result<Vec<u8>, BanchoStateError> {
         self.bancho_state_service
             .dequeue_bancho_packets(DequeueBanchoPacketsRequest {
                 user_query: Some(user_query.into()),
+                coalesce: false,
+                wait_ms: wait.as_millis() as u32,
             })
             .await
             .map(|resp| resp.data)
     }
 
+    #[inline]
+    async fn wait_chat_packets(
+        &self,
+        query: UserQuery,
+        wait: Duration,
+    ) -> Result<Vec<u8>, ChatError> {
+        self.chat_service
+            .wait_chat_packets(query, wait)
+            .await
+            .map(|resp| resp.data)
+    }
+
     #[inline]
     async fn pull_chat_packets(
         &self,
@@ -182,4 +359,99 @@ impl BanchoHandlerService for BanchoHandlerServiceImpl {
 
         Ok(is_valid)
     }
+
+    #[inline]
+    async fn authenticate(
+        &self,
+        token: String,
+    ) -> Result<i32, BanchoHttpError> {
+        let token = BanchoClientToken::from_str(&token)
+            .map_err(|_| BanchoHttpError::Unauthorized)?;
+        let user_id = token.user_id;
+
+        // Web requests can not reconnect, expired sessions are unauthorized
+        match self.check_user_token(token).await {
+            Ok(true) => Ok(user_id),
+            Ok(false)
+            | Err(
+                BanchoStateError::SessionNotExists
+                | BanchoStateError::SignatureError(..),
+            ) => Err(BanchoHttpError::Unauthorized),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    #[inline]
+    async fn authenticate_user(
+        &self,
+        username: String,
+        password_md5: String,
+    ) -> Result<i32, BanchoHttpError> {
+        match self
+            .bancho_service
+            .authenticate_user(AuthenticateUserRequest {
+                username,
+                password_md5,
+            })
+            .await
+        {
+            Ok(AuthenticateUserResponse { user_id }) => Ok(user_id),
+            Err(err) => match LoginError::from(err) {
+                LoginError::ServerError(err) => Err(err.into()),
+                _ => Err(BanchoHttpError::Unauthorized),
+            },
+        }
+    }
+
+    #[inline]
+    async fn add_favourite_beatmapset(
+        &self,
+        request: AddFavouriteBeatmapsetRequest,
+    ) -> Result<AddFavouriteBeatmapsetResponse, BanchoServiceError> {
+        self.bancho_service.add_favourite_beatmapset(request).await
+    }
+
+    #[inline]
+    async fn get_favourite_beatmapsets(
+        &self,
+        request: GetFavouriteBeatmapsetsRequest,
+    ) -> Result<GetFavouriteBeatmapsetsResponse, BanchoServiceError> {
+        self.bancho_service.get_favourite_beatmapsets(request).await
+    }
+
+    #[inline]
+    async fn submit_score(
+        &self,
+        request: SubmitScoreRequest,
+    ) -> Result<SubmitScoreResponse, BanchoServiceError> {
+        self.bancho_service.submit_score(request).await
+    }
+
+    #[inline]
+    async fn get_beatmap_scores(
+        &self,
+        request: GetBeatmapScoresRequest,
+    ) -> Result<GetBeatmapScoresResponse, BanchoServiceError> {
+        self.bancho_service.get_beatmap_scores(request).await
+    }
+
+    #[inline]
+    async fn get_score_md5(
+        &self,
+        request: GetScoreMd5Request,
+    ) -> Result<GetScoreMd5Response, BanchoServiceError> {
+        self.bancho_service.get_score_md5(request).await
+    }
+
+    #[inline]
+    async fn add_replay_view(
+        &self,
+        score_md5: String,
+    ) -> Result<i32, BanchoServiceError> {
+        Ok(self
+            .bancho_service
+            .add_replay_view(AddReplayViewRequest { score_md5 })
+            .await?
+            .views)
+    }
 }
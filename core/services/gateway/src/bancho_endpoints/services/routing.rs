@@ -2,20 +2,273 @@ use super::traits::{
     BanchoRoutingService, DynBanchoHandlerService, DynBanchoRoutingService,
 };
 use crate::bancho_endpoints::{
+    beatmap_scores,
     extractors::{BanchoClientVersion, OsuTokenHeader},
-    BanchoHttpError,
+    submission_charts, BanchoHttpError, ReplayError, ReplayStore, ScoreData,
+    ScoreSubmissionError, ScoreSubmissionForm, ScreenshotError, ScreenshotForm,
+    ScreenshotLimiter, ScreenshotStore, SeasonalBackgrounds, UpdateManifest,
+    UpdateStream,
 };
 use async_trait::async_trait;
-use axum::response::{IntoResponse, Response};
-use std::{net::IpAddr, sync::Arc};
+use axum::{
+    body::StreamBody,
+    extract::ws::WebSocketUpgrade,
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE, LOCATION},
+        StatusCode,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use clap_serde_derive::ClapSerde;
+use pb_bancho::{
+    add_favourite_beatmapset_response::Status, AddFavouriteBeatmapsetRequest,
+    ClientRegisterRequest, ClientRegisterResponse, GetBeatmapScoresRequest,
+    GetFavouriteBeatmapsetsRequest, GetFavouriteBeatmapsetsResponse,
+    GetScoreMd5Request,
+};
+use peace_cfg::{ConfigError, ConfigErrors, ValidateConfig};
+use peace_difficulty::DynDifficultyService;
+use std::{io, net::IpAddr, sync::Arc};
+use tokio_util::io::ReaderStream;
+
+#[derive(Debug, Clone, Parser, ClapSerde, Serialize, Deserialize)]
+pub struct CliBanchoRoutingServiceConfigs {
+    /// Base url of the beatmap mirror used by `/d/{beatmapset_id}`,
+    /// e.g. `https://mirror.example.com`.
+    ///
+    /// If not configured, beatmapset downloads will respond `404`.
+    #[arg(long)]
+    pub beatmap_mirror: Option<String>,
+
+    /// Download url template of the beatmap mirror.
+    ///
+    /// `{mirror}` is replaced with `beatmap_mirror`, `{id}` is replaced
+    /// with the requested beatmapset id.
+    #[default("{mirror}/d/{id}".to_owned())]
+    #[arg(long, default_value = "{mirror}/d/{id}")]
+    pub beatmap_mirror_download_url: String,
+
+    /// Query parameter appended to the redirect url when the client
+    /// requests a beatmapset without video.
+    #[default("n".to_owned())]
+    #[arg(long, default_value = "n")]
+    pub beatmap_mirror_no_video_param: String,
+
+    /// Path of the json updater manifest served by
+    /// `/web/check-updates.php`, files are listed per stream, e.g.
+    /// `{"stable": [..], "beta": [..], "cuttingedge": [..]}`.
+    ///
+    /// If not configured, clients are told there is no update.
+    #[arg(long)]
+    pub update_manifest: Option<String>,
+
+    /// Path of the json seasonal backgrounds served by
+    /// `/web/osu-getseasonal.php`, e.g.
+    /// `[{"start": "12-01", "end": "02-28", "backgrounds": [..]}]`.
+    ///
+    /// Seasons without `start` and `end` are used when no other season is
+    /// active. If not configured, no seasonal background is served.
+    #[arg(long)]
+    pub seasonal_backgrounds: Option<String>,
+
+    /// Directory of the screenshots uploaded by the clients.
+    #[default(".data/screenshots".to_owned())]
+    #[arg(long, default_value = ".data/screenshots")]
+    pub screenshots_dir: String,
+
+    /// Max size in bytes of an uploaded screenshot.
+    #[default(2 * 1024 * 1024)]
+    #[arg(long, default_value = "2097152")]
+    pub max_screenshot_size: usize,
+
+    /// Screenshots a player can upload per minute, `0` disables the limit.
+    #[default(6)]
+    #[arg(long, default_value = "6")]
+    pub screenshot_rate_per_minute: u32,
+
+    /// Screenshots a player can upload in a row before being limited to
+    /// `screenshot_rate_per_minute`.
+    #[default(3)]
+    #[arg(long, default_value = "3")]
+    pub screenshot_burst: u32,
+
+    /// Directory of the replays of the submitted scores.
+    #[default(".data/replays".to_owned())]
+    #[arg(long, default_value = ".data/replays")]
+    pub replays_dir: String,
+
+    /// Max size in bytes of a submitted replay.
+    #[default(10 * 1024 * 1024)]
+    #[arg(long, default_value = "10485760")]
+    pub max_replay_size: usize,
+
+    /// Max size in bytes of the body of a bancho POST request, larger
+    /// requests are refused with `413` before their packets are read.
+    #[default(1024 * 1024)]
+    #[arg(long, default_value = "1048576")]
+    pub max_bancho_request_size: usize,
+}
+
+impl ValidateConfig for CliBanchoRoutingServiceConfigs {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        errors.check(
+            self.screenshot_rate_per_minute == 0 || self.screenshot_burst > 0,
+            "screenshot_burst",
+            "must be greater than 0 when the screenshot limit is enabled",
+        );
+
+        if let (Some(path), Err(err)) =
+            (self.update_manifest.as_deref(), self.update_manifest())
+        {
+            errors.push(
+                "update_manifest",
+                format!("failed to load `{path}`: {err}"),
+            );
+        }
+
+        if let (Some(path), Err(err)) =
+            (self.seasonal_backgrounds.as_deref(), self.seasonal_backgrounds())
+        {
+            errors.push(
+                "seasonal_backgrounds",
+                format!("failed to load `{path}`: {err}"),
+            );
+        }
+
+        errors.into_result()
+    }
+}
+
+impl CliBanchoRoutingServiceConfigs {
+    #[inline]
+    pub fn update_manifest(&self) -> io::Result<Option<UpdateManifest>> {
+        let path = match self.update_manifest.as_deref() {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        let manifest = UpdateManifest::from_file(path)?;
+        info!(
+            "Loaded update manifest of {} streams from \"{path}\"",
+            manifest.streams.len()
+        );
+
+        Ok(Some(manifest))
+    }
+
+    #[inline]
+    pub fn seasonal_backgrounds(&self) -> io::Result<SeasonalBackgrounds> {
+        let path = match self.seasonal_backgrounds.as_deref() {
+            Some(path) => path,
+            None => return Ok(SeasonalBackgrounds::default()),
+        };
+
+        let seasonal = SeasonalBackgrounds::from_file(path)?;
+        info!(
+            "Loaded {} seasons of backgrounds from \"{path}\"",
+            seasonal.seasons.len()
+        );
+
+        Ok(seasonal)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BeatmapMirror {
+    pub base_url: String,
+    pub download_url: String,
+    pub no_video_param: String,
+}
+
+impl BeatmapMirror {
+    #[inline]
+    pub fn with_cfg(cfg: &CliBanchoRoutingServiceConfigs) -> Option<Self> {
+        let base_url = cfg.beatmap_mirror.as_deref()?.trim_end_matches('/');
+        if base_url.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            base_url: base_url.to_owned(),
+            download_url: cfg.beatmap_mirror_download_url.to_owned(),
+            no_video_param: cfg.beatmap_mirror_no_video_param.to_owned(),
+        })
+    }
+
+    pub fn beatmapset_download_url(
+        &self,
+        beatmapset_id: i32,
+        no_video: bool,
+    ) -> String {
+        let mut url = self
+            .download_url
+            .replace("{mirror}", &self.base_url)
+            .replace("{id}", &beatmapset_id.to_string());
+
+        if no_video && !self.no_video_param.is_empty() {
+            url.push(if url.contains('?') { '&' } else { '?' });
+            url.push_str(&self.no_video_param);
+            url.push_str("=1");
+        }
+
+        url
+    }
+}
 
 pub struct BanchoRoutingServiceImpl {
     pub bancho_handler_service: DynBanchoHandlerService,
+    pub beatmap_mirror: Option<BeatmapMirror>,
+    pub update_manifest: Option<UpdateManifest>,
+    pub seasonal_backgrounds: SeasonalBackgrounds,
+    pub screenshot_store: ScreenshotStore,
+    pub screenshot_limiter: ScreenshotLimiter,
+    pub replay_store: ReplayStore,
+    pub difficulty_service: DynDifficultyService,
 }
 
 impl BanchoRoutingServiceImpl {
-    pub fn new(bancho_handler_service: DynBanchoHandlerService) -> Self {
-        Self { bancho_handler_service }
+    #[inline]
+    pub fn with_cfg(
+        bancho_handler_service: DynBanchoHandlerService,
+        difficulty_service: DynDifficultyService,
+        cfg: &CliBanchoRoutingServiceConfigs,
+    ) -> Self {
+        // The files are checked by the config validation at startup
+        let update_manifest = cfg.update_manifest().unwrap_or_else(|err| {
+            error!("Failed to load update manifest, serve no update: {err}");
+            None
+        });
+        let seasonal_backgrounds =
+            cfg.seasonal_backgrounds().unwrap_or_else(|err| {
+                error!(
+                    "Failed to load seasonal backgrounds, serve none: {err}"
+                );
+                SeasonalBackgrounds::default()
+            });
+
+        Self {
+            bancho_handler_service,
+            beatmap_mirror: BeatmapMirror::with_cfg(cfg),
+            update_manifest,
+            seasonal_backgrounds,
+            screenshot_store: ScreenshotStore::new(
+                &cfg.screenshots_dir,
+                cfg.max_screenshot_size,
+            ),
+            screenshot_limiter: ScreenshotLimiter::per_minute(
+                cfg.screenshot_rate_per_minute,
+                cfg.screenshot_burst,
+            ),
+            replay_store: ReplayStore::new(
+                &cfg.replays_dir,
+                cfg.max_replay_size,
+            ),
+            difficulty_service,
+        }
     }
 
     pub fn into_service(self) -> DynBanchoRoutingService {
@@ -25,8 +278,8 @@ impl BanchoRoutingServiceImpl {
 
 #[async_trait]
 impl BanchoRoutingService for BanchoRoutingServiceImpl {
-    async fn bancho_get(&self) -> Response {
-        tools::pkg_metadata!().into_response()
+    async fn bancho_get(&self) -> Result<Response, BanchoHttpError> {
+        Ok(tools::pkg_metadata!().into_response())
     }
 
     async fn bancho_post(
@@ -38,7 +291,7 @@ impl BanchoRoutingService for BanchoRoutingServiceImpl {
     ) -> Result<Response, BanchoHttpError> {
         match token {
             Some(OsuTokenHeader(token)) => {
-                self.bancho_handler_service.handle_logged(token, body).await
+                self.bancho_handler_service.handle_logged(token, ip, body).await
             },
             None => {
                 self.bancho_handler_service
@@ -48,99 +301,335 @@ impl BanchoRoutingService for BanchoRoutingServiceImpl {
         }
     }
 
-    async fn get_screenshot(&self) -> Response {
-        unimplemented!()
+    async fn bancho_ws(
+        &self,
+        OsuTokenHeader(token): OsuTokenHeader,
+        ip: IpAddr,
+        ws: WebSocketUpgrade,
+    ) -> Result<Response, BanchoHttpError> {
+        let user_id = self.bancho_handler_service.authenticate(token).await?;
+        let handler = self.bancho_handler_service.clone();
+
+        Ok(ws.on_upgrade(move |socket| async move {
+            handler.handle_websocket(user_id, ip, socket).await
+        }))
+    }
+
+    async fn get_screenshot(
+        &self,
+        screenshot: String,
+    ) -> Result<Response, BanchoHttpError> {
+        let (data, format) = self.screenshot_store.load(&screenshot).await?;
+
+        Ok(([(CONTENT_TYPE, format.content_type())], data).into_response())
     }
 
-    async fn download_beatmapset(&self, _beatmapset_id: i32) -> Response {
-        unimplemented!()
+    async fn download_beatmapset(
+        &self,
+        beatmapset_id: i32,
+        no_video: bool,
+    ) -> Result<Response, BanchoHttpError> {
+        Ok(match self.beatmap_mirror.as_ref() {
+            Some(mirror) => (
+                StatusCode::FOUND,
+                [(
+                    LOCATION,
+                    mirror.beatmapset_download_url(beatmapset_id, no_video),
+                )],
+            )
+                .into_response(),
+            None => (StatusCode::NOT_FOUND, "beatmap mirror is not configured")
+                .into_response(),
+        })
     }
 
-    async fn client_register(&self) -> Response {
-        unimplemented!()
+    async fn client_register(
+        &self,
+        request: ClientRegisterRequest,
+    ) -> Result<Response, BanchoHttpError> {
+        let ClientRegisterResponse {
+            username_errors,
+            email_errors,
+            password_errors,
+            ..
+        } = self.bancho_handler_service.client_register(request).await?;
+
+        if username_errors.is_empty()
+            && email_errors.is_empty()
+            && password_errors.is_empty()
+        {
+            return Ok("ok".into_response());
+        }
+
+        // Field errors in the format expected by osu! client
+        Ok((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "form_error": {
+                    "user": {
+                        "username": username_errors,
+                        "user_email": email_errors,
+                        "password": password_errors,
+                    }
+                }
+            })),
+        )
+            .into_response())
     }
 
-    async fn ask_peppy(&self) -> Response {
-        unimplemented!()
+    async fn ask_peppy(&self) -> Result<Response, BanchoHttpError> {
+        Err(BanchoHttpError::NotImplemented("/p/doyoureallywanttoaskpeppy"))
     }
 
-    async fn difficulty_rating(&self) -> Response {
-        unimplemented!()
+    async fn difficulty_rating(
+        &self,
+        beatmap_id: i32,
+        mode: u8,
+        mods: u32,
+    ) -> Result<Response, BanchoHttpError> {
+        let stars =
+            self.difficulty_service.star_rating(beatmap_id, mode, mods).await?;
+
+        Ok(Json(stars).into_response())
     }
 
-    async fn osu_error(&self) -> Response {
-        "ok".into_response()
+    async fn osu_error(&self) -> Result<Response, BanchoHttpError> {
+        Ok("ok".into_response())
     }
 
-    async fn osu_screenshot(&self) -> Response {
-        unimplemented!()
+    async fn osu_screenshot(
+        &self,
+        form: ScreenshotForm,
+    ) -> Result<Response, BanchoHttpError> {
+        let user_id = self
+            .bancho_handler_service
+            .authenticate_user(form.username, form.password_md5)
+            .await?;
+
+        self.screenshot_limiter.check(user_id).map_err(|retry_after| {
+            ScreenshotError::TooManyScreenshots(retry_after.as_secs() + 1)
+        })?;
+
+        // The client opens `/ss/{id}` with the returned id
+        Ok(self.screenshot_store.save(&form.screenshot).await?.into_response())
     }
 
-    async fn osu_getfriends(&self) -> Response {
-        "".into_response()
+    async fn osu_getfriends(&self) -> Result<Response, BanchoHttpError> {
+        Ok("".into_response())
     }
 
-    async fn osu_getbeatmapinfo(&self) -> Response {
-        unimplemented!()
+    async fn osu_getbeatmapinfo(&self) -> Result<Response, BanchoHttpError> {
+        Err(BanchoHttpError::NotImplemented("/web/osu-getbeatmapinfo.php"))
     }
 
-    async fn osu_getfavourites(&self) -> Response {
-        unimplemented!()
+    async fn osu_getfavourites(
+        &self,
+        username: String,
+        password_md5: String,
+    ) -> Result<Response, BanchoHttpError> {
+        let user_id = self
+            .bancho_handler_service
+            .authenticate_user(username, password_md5)
+            .await?;
+
+        let GetFavouriteBeatmapsetsResponse { beatmapset_ids } = self
+            .bancho_handler_service
+            .get_favourite_beatmapsets(GetFavouriteBeatmapsetsRequest {
+                user_id,
+            })
+            .await?;
+
+        Ok(beatmapset_ids
+            .iter()
+            .map(i32::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+            .into_response())
     }
 
-    async fn osu_addfavourite(&self) -> Response {
-        unimplemented!()
+    async fn osu_addfavourite(
+        &self,
+        username: String,
+        password_md5: String,
+        beatmapset_id: i32,
+    ) -> Result<Response, BanchoHttpError> {
+        let user_id = self
+            .bancho_handler_service
+            .authenticate_user(username, password_md5)
+            .await?;
+
+        let res = self
+            .bancho_handler_service
+            .add_favourite_beatmapset(AddFavouriteBeatmapsetRequest {
+                user_id,
+                beatmapset_id,
+            })
+            .await?;
+
+        // Shown as a notification by the client
+        Ok(match res.status() {
+            Status::Added => "Added favourite!",
+            Status::AlreadyAdded => "You've already favourited this beatmap!",
+            Status::TooManyFavourites => {
+                "You have too many favourite beatmaps!"
+            },
+        }
+        .into_response())
     }
 
-    async fn lastfm(&self) -> Response {
-        "ok".into_response()
+    async fn lastfm(&self) -> Result<Response, BanchoHttpError> {
+        Ok("ok".into_response())
     }
 
-    async fn osu_search(&self) -> Response {
-        unimplemented!()
+    async fn osu_search(&self) -> Result<Response, BanchoHttpError> {
+        Err(BanchoHttpError::NotImplemented("/web/osu-search.php"))
     }
 
-    async fn osu_search_set(&self) -> Response {
-        unimplemented!()
+    async fn osu_search_set(&self) -> Result<Response, BanchoHttpError> {
+        Err(BanchoHttpError::NotImplemented("/web/osu-search-set.php"))
     }
 
-    async fn osu_submit_modular_selector(&self) -> Response {
-        unimplemented!()
+    async fn osu_submit_modular_selector(
+        &self,
+        form: ScoreSubmissionForm,
+    ) -> Result<Response, BanchoHttpError> {
+        let score_data = ScoreData::parse(&form.decrypt_score_data()?)?;
+
+        let user_id = match self
+            .bancho_handler_service
+            .authenticate_user(
+                score_data.username.clone(),
+                form.password_md5.clone(),
+            )
+            .await
+        {
+            Ok(user_id) => user_id,
+            Err(BanchoHttpError::Unauthorized) => {
+                return Err(ScoreSubmissionError::InvalidCredentials.into())
+            },
+            Err(err) => return Err(err),
+        };
+        let passed = score_data.passed;
+        let score_md5 = score_data.score_md5.clone();
+
+        let res = self
+            .bancho_handler_service
+            .submit_score(
+                score_data.into_request(user_id, form.playtime(passed)),
+            )
+            .await
+            .map_err(ScoreSubmissionError::from)?;
+
+        // The score is stored, a missing replay should not make the client
+        // submit it again
+        if passed {
+            if let Err(err) =
+                self.replay_store.save(&score_md5, &form.replay).await
+            {
+                warn!(
+                    "Failed to save the replay of score {}: {err}",
+                    res.score_id
+                );
+            }
+        }
+
+        // Failed scores are stored, but have no chart to show
+        if !passed {
+            return Ok("error: no".into_response());
+        }
+
+        Ok(submission_charts(&res).into_response())
     }
 
-    async fn osu_getreplay(&self) -> Response {
-        unimplemented!()
+    async fn osu_getreplay(
+        &self,
+        score_id: i64,
+        mode: i32,
+    ) -> Result<Response, BanchoHttpError> {
+        let score_md5 = self
+            .bancho_handler_service
+            .get_score_md5(GetScoreMd5Request { mode, score_id })
+            .await?
+            .score_md5
+            .ok_or(ReplayError::NotExists)?;
+
+        let (file, len) = self.replay_store.open(&score_md5).await?;
+
+        if let Err(err) =
+            self.bancho_handler_service.add_replay_view(score_md5).await
+        {
+            warn!("Failed to count the view of replay {score_id}: {err}");
+        }
+
+        Ok((
+            [(CONTENT_LENGTH, len.to_string())],
+            StreamBody::new(ReaderStream::new(file)),
+        )
+            .into_response())
     }
 
-    async fn osu_rate(&self) -> Response {
-        unimplemented!()
+    async fn osu_rate(&self) -> Result<Response, BanchoHttpError> {
+        Err(BanchoHttpError::NotImplemented("/web/osu-rate.php"))
     }
 
-    async fn osu_osz2_getscores(&self) -> Response {
-        unimplemented!()
+    async fn osu_osz2_getscores(
+        &self,
+        username: String,
+        password_md5: String,
+        beatmap_md5: String,
+        mode: i32,
+        mods: u32,
+        board_type: i32,
+    ) -> Result<Response, BanchoHttpError> {
+        let user_id = self
+            .bancho_handler_service
+            .authenticate_user(username, password_md5)
+            .await?;
+
+        let res = self
+            .bancho_handler_service
+            .get_beatmap_scores(GetBeatmapScoresRequest {
+                user_id,
+                beatmap_md5,
+                mode,
+                mods,
+                board_type,
+            })
+            .await?;
+
+        Ok(beatmap_scores(&res).into_response())
     }
 
-    async fn osu_comment(&self) -> Response {
-        unimplemented!()
+    async fn osu_comment(&self) -> Result<Response, BanchoHttpError> {
+        Err(BanchoHttpError::NotImplemented("/web/osu-comment.php"))
     }
 
-    async fn osu_markasread(&self) -> Response {
-        "ok".into_response()
+    async fn osu_markasread(&self) -> Result<Response, BanchoHttpError> {
+        Ok("ok".into_response())
     }
 
-    async fn osu_getseasonal(&self) -> Response {
-        "ok".into_response()
+    async fn osu_getseasonal(&self) -> Result<Response, BanchoHttpError> {
+        Ok(Json(self.seasonal_backgrounds.active(Utc::now().date_naive()))
+            .into_response())
     }
 
-    async fn bancho_connect(&self) -> Response {
-        "ok".into_response()
+    async fn bancho_connect(&self) -> Result<Response, BanchoHttpError> {
+        Ok("ok".into_response())
     }
 
-    async fn check_updates(&self) -> Response {
-        "ok".into_response()
+    async fn check_updates(
+        &self,
+        stream: Option<String>,
+    ) -> Result<Response, BanchoHttpError> {
+        let stream = UpdateStream::from_query(stream.as_deref().unwrap_or(""));
+
+        Ok(match self.update_manifest.as_ref() {
+            Some(manifest) => Json(manifest.files(stream)).into_response(),
+            None => Json(Vec::<()>::new()).into_response(),
+        })
     }
 
-    async fn update_beatmap(&self) -> Response {
-        "ok".into_response()
+    async fn update_beatmap(&self) -> Result<Response, BanchoHttpError> {
+        Ok("ok".into_response())
     }
 }
@@ -3,13 +3,23 @@ use crate::bancho_endpoints::{
     *,
 };
 use async_trait::async_trait;
-use axum::response::Response;
+use axum::{
+    extract::ws::{WebSocket, WebSocketUpgrade},
+    response::Response,
+};
+use core_bancho::BanchoServiceError;
 use core_bancho_state::BanchoStateError;
 use core_chat::ChatError;
 use domain_bancho::BanchoClientToken;
-use pb_bancho::LoginSuccess;
+use pb_bancho::{
+    AddFavouriteBeatmapsetRequest, AddFavouriteBeatmapsetResponse,
+    ClientRegisterRequest, ClientRegisterResponse, GetBeatmapScoresRequest,
+    GetBeatmapScoresResponse, GetFavouriteBeatmapsetsRequest,
+    GetFavouriteBeatmapsetsResponse, GetScoreMd5Request, GetScoreMd5Response,
+    LoginSuccess, SubmitScoreRequest, SubmitScoreResponse,
+};
 use pb_bancho_state::UserQuery;
-use std::{net::IpAddr, sync::Arc};
+use std::{net::IpAddr, sync::Arc, time::Duration};
 
 pub type DynBanchoRoutingService = Arc<dyn BanchoRoutingService + Send + Sync>;
 pub type DynBanchoHandlerService = Arc<dyn BanchoHandlerService + Send + Sync>;
@@ -17,7 +27,7 @@ pub type DynBanchoHandlerService = Arc<dyn BanchoHandlerService + Send + Sync>;
 #[async_trait]
 pub trait BanchoRoutingService {
     /// get `/`
-    async fn bancho_get(&self) -> Response;
+    async fn bancho_get(&self) -> Result<Response, BanchoHttpError>;
 
     /// post `/`
     async fn bancho_post(
@@ -28,77 +38,130 @@ pub trait BanchoRoutingService {
         body: Vec<u8>,
     ) -> Result<Response, BanchoHttpError>;
 
+    /// get `/ws`
+    async fn bancho_ws(
+        &self,
+        token: OsuTokenHeader,
+        ip: IpAddr,
+        ws: WebSocketUpgrade,
+    ) -> Result<Response, BanchoHttpError>;
+
     /// get `/ss/{screenshot}`
-    async fn get_screenshot(&self) -> Response;
+    async fn get_screenshot(
+        &self,
+        screenshot: String,
+    ) -> Result<Response, BanchoHttpError>;
 
     /// get `/d/{beatmapset_id}`
-    async fn download_beatmapset(&self, beatmapset_id: i32) -> Response;
+    async fn download_beatmapset(
+        &self,
+        beatmapset_id: i32,
+        no_video: bool,
+    ) -> Result<Response, BanchoHttpError>;
 
     /// post `/users`
-    async fn client_register(&self) -> Response;
+    async fn client_register(
+        &self,
+        request: ClientRegisterRequest,
+    ) -> Result<Response, BanchoHttpError>;
 
     /// get `/p/doyoureallywanttoaskpeppy`
-    async fn ask_peppy(&self) -> Response;
+    async fn ask_peppy(&self) -> Result<Response, BanchoHttpError>;
 
     /// get `/difficulty-rating`
-    async fn difficulty_rating(&self) -> Response;
+    async fn difficulty_rating(
+        &self,
+        beatmap_id: i32,
+        mode: u8,
+        mods: u32,
+    ) -> Result<Response, BanchoHttpError>;
 
     /// post `/web/osu-error.php`
-    async fn osu_error(&self) -> Response;
+    async fn osu_error(&self) -> Result<Response, BanchoHttpError>;
 
     /// post `/web/osu-screenshot.php`
-    async fn osu_screenshot(&self) -> Response;
+    async fn osu_screenshot(
+        &self,
+        form: ScreenshotForm,
+    ) -> Result<Response, BanchoHttpError>;
 
     /// get `/web/osu-getfriends.php`
-    async fn osu_getfriends(&self) -> Response;
+    async fn osu_getfriends(&self) -> Result<Response, BanchoHttpError>;
 
     /// get `/web/osu-getbeatmapinfo.php`
-    async fn osu_getbeatmapinfo(&self) -> Response;
+    async fn osu_getbeatmapinfo(&self) -> Result<Response, BanchoHttpError>;
 
     /// get `/web/osu-getfavourites.php`
-    async fn osu_getfavourites(&self) -> Response;
+    async fn osu_getfavourites(
+        &self,
+        username: String,
+        password_md5: String,
+    ) -> Result<Response, BanchoHttpError>;
 
     /// get `/web/osu-addfavourite.php`
-    async fn osu_addfavourite(&self) -> Response;
+    async fn osu_addfavourite(
+        &self,
+        username: String,
+        password_md5: String,
+        beatmapset_id: i32,
+    ) -> Result<Response, BanchoHttpError>;
 
     /// get `/web/osu-lastfm.php`
-    async fn lastfm(&self) -> Response;
+    async fn lastfm(&self) -> Result<Response, BanchoHttpError>;
 
     /// get `/web/osu-search.php`
-    async fn osu_search(&self) -> Response;
+    async fn osu_search(&self) -> Result<Response, BanchoHttpError>;
 
     /// get `/web/osu-search-set.php`
-    async fn osu_search_set(&self) -> Response;
+    async fn osu_search_set(&self) -> Result<Response, BanchoHttpError>;
 
     /// post `/web/osu-submit-modular-selector.php`
-    async fn osu_submit_modular_selector(&self) -> Response;
+    async fn osu_submit_modular_selector(
+        &self,
+        form: ScoreSubmissionForm,
+    ) -> Result<Response, BanchoHttpError>;
 
     /// get `/web/osu-getreplay.php`
-    async fn osu_getreplay(&self) -> Response;
+    async fn osu_getreplay(
+        &self,
+        score_id: i64,
+        mode: i32,
+    ) -> Result<Response, BanchoHttpError>;
 
     /// get `/web/osu-rate.php`
-    async fn osu_rate(&self) -> Response;
+    async fn osu_rate(&self) -> Result<Response, BanchoHttpError>;
 
     /// get `/web/osu-osz2-getscores.php`
-    async fn osu_osz2_getscores(&self) -> Response;
+    async fn osu_osz2_getscores(
+        &self,
+        username: String,
+        password_md5: String,
+        beatmap_md5: String,
+        mode: i32,
+        mods: u32,
+        board_type: i32,
+    ) -> Result<Response, BanchoHttpError>;
 
     /// post `/web/osu-comment.php`
-    async fn osu_comment(&self) -> Response;
+    async fn osu_comment(&self) -> Result<Response, BanchoHttpError>;
 
     /// get `/web/osu-markasread.php`
-    async fn osu_markasread(&self) -> Response;
+    async fn osu_markasread(&self) -> Result<Response, BanchoHttpError>;
 
     /// get `/web/osu-getseasonal.php`
-    async fn osu_getseasonal(&self) -> Response;
+    async fn osu_getseasonal(&self) -> Result<Response, BanchoHttpError>;
 
     /// get `/web/bancho_connect.php`
-    async fn bancho_connect(&self) -> Response;
+    async fn bancho_connect(&self) -> Result<Response, BanchoHttpError>;
 
     /// get `/web/check-updates.php`
-    async fn check_updates(&self) -> Response;
+    async fn check_updates(
+        &self,
+        stream: Option<String>,
+    ) -> Result<Response, BanchoHttpError>;
 
     /// get `/web/maps/{beatmap_file_name}`
-    async fn update_beatmap(&self) -> Response;
+    async fn update_beatmap(&self) -> Result<Response, BanchoHttpError>;
 }
 
 #[async_trait]
@@ -113,6 +176,7 @@ pub trait BanchoHandlerService {
     async fn handle_logged(
         &self,
         token: String,
+        ip: IpAddr,
         body: Vec<u8>,
     ) -> Result<Response, BanchoHttpError>;
 
@@ -126,12 +190,30 @@ pub trait BanchoHandlerService {
     async fn process_bancho_packets(
         &self,
         user_id: i32,
+        client_ip: IpAddr,
         body: Vec<u8>,
     ) -> Result<Option<Vec<u8>>, BanchoHttpError>;
 
+    /// Serve the packets of a logged in session over websocket until the
+    /// socket or the session is closed.
+    async fn handle_websocket(
+        &self,
+        user_id: i32,
+        ip: IpAddr,
+        socket: WebSocket,
+    );
+
+    async fn client_register(
+        &self,
+        request: ClientRegisterRequest,
+    ) -> Result<ClientRegisterResponse, BanchoServiceError>;
+
+    /// `coalesce` lets the packets of a burst be sent together, at the cost
+    /// of a small delay, only the poll path uses it.
     async fn pull_bancho_packets(
         &self,
         target: UserQuery,
+        coalesce: bool,
     ) -> Result<Vec<u8>, BanchoStateError>;
 
     async fn pull_chat_packets(
@@ -139,8 +221,67 @@ pub trait BanchoHandlerService {
         query: UserQuery,
     ) -> Result<Vec<u8>, ChatError>;
 
+    /// Pull the bancho packets, waiting up to `wait` for packets to be
+    /// pushed when there are none yet.
+    async fn wait_bancho_packets(
+        &self,
+        target: UserQuery,
+        wait: Duration,
+    ) -> Result<Vec<u8>, BanchoStateError>;
+
+    /// Pull the chat packets, waiting up to `wait` for a message when there
+    /// are none yet.
+    async fn wait_chat_packets(
+        &self,
+        query: UserQuery,
+        wait: Duration,
+    ) -> Result<Vec<u8>, ChatError>;
+
     async fn check_user_token(
         &self,
         token: BanchoClientToken,
     ) -> Result<bool, BanchoStateError>;
+
+    /// Returns the user id of the session if the `osu-token` is valid.
+    async fn authenticate(&self, token: String)
+        -> Result<i32, BanchoHttpError>;
+
+    /// Returns the user id if the credentials sent to a web endpoint are
+    /// valid, the web endpoints do not send the `osu-token`.
+    async fn authenticate_user(
+        &self,
+        username: String,
+        password_md5: String,
+    ) -> Result<i32, BanchoHttpError>;
+
+    async fn add_favourite_beatmapset(
+        &self,
+        request: AddFavouriteBeatmapsetRequest,
+    ) -> Result<AddFavouriteBeatmapsetResponse, BanchoServiceError>;
+
+    async fn get_favourite_beatmapsets(
+        &self,
+        request: GetFavouriteBeatmapsetsRequest,
+    ) -> Result<GetFavouriteBeatmapsetsResponse, BanchoServiceError>;
+
+    async fn submit_score(
+        &self,
+        request: SubmitScoreRequest,
+    ) -> Result<SubmitScoreResponse, BanchoServiceError>;
+
+    async fn get_beatmap_scores(
+        &self,
+        request: GetBeatmapScoresRequest,
+    ) -> Result<GetBeatmapScoresResponse, BanchoServiceError>;
+
+    async fn get_score_md5(
+        &self,
+        request: GetScoreMd5Request,
+    ) -> Result<GetScoreMd5Response, BanchoServiceError>;
+
+    /// Returns the views of the replay after counting this one.
+    async fn add_replay_view(
+        &self,
+        score_md5: String,
+    ) -> Result<i32, BanchoServiceError>;
 }
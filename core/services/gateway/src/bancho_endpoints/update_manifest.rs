@@ -0,0 +1,118 @@
+use std::{collections::HashMap, io, path::Path};
+
+/// Release streams of the osu! client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateStream {
+    Stable,
+    Beta,
+    CuttingEdge,
+}
+
+impl UpdateStream {
+    /// Parse the `stream` query param sent by the client, e.g. `stable40`,
+    /// `beta40`, `cuttingedge`. Unknown streams fall back to stable.
+    pub fn from_query(stream: &str) -> Self {
+        match stream
+            .trim()
+            .trim_end_matches(|c: char| c.is_ascii_digit())
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "beta" => Self::Beta,
+            "cuttingedge" => Self::CuttingEdge,
+            _ => Self::Stable,
+        }
+    }
+}
+
+/// A file of the updater manifest, fields are named as the osu! client
+/// parses them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateFile {
+    pub file_version: String,
+    pub filename: String,
+    pub file_hash: String,
+    pub filesize: String,
+    pub timestamp: String,
+    pub patch_id: Option<String>,
+    pub url_full: String,
+    pub url_patch: Option<String>,
+}
+
+/// Files served by `/web/check-updates.php`, grouped by stream.
+///
+/// The manifest json is an object keyed by stream name, e.g.
+/// `{"stable": [..], "beta": [..], "cuttingedge": [..]}`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct UpdateManifest {
+    pub streams: HashMap<UpdateStream, Vec<UpdateFile>>,
+}
+
+impl UpdateManifest {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+
+        serde_json::from_str(&content)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Files of the stream, streams missing from the manifest fall back to
+    /// stable.
+    pub fn files(&self, stream: UpdateStream) -> &[UpdateFile] {
+        self.streams
+            .get(&stream)
+            .or_else(|| self.streams.get(&UpdateStream::Stable))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_stream_from_query() {
+        assert_eq!(UpdateStream::from_query("stable40"), UpdateStream::Stable);
+        assert_eq!(UpdateStream::from_query("beta40"), UpdateStream::Beta);
+        assert_eq!(
+            UpdateStream::from_query("cuttingedge"),
+            UpdateStream::CuttingEdge
+        );
+        assert_eq!(UpdateStream::from_query("unknown"), UpdateStream::Stable);
+    }
+
+    #[test]
+    fn test_update_manifest_files() {
+        let manifest: UpdateManifest = serde_json::from_str(
+            r#"{
+                "stable": [{
+                    "file_version": "3",
+                    "filename": "osu!.exe",
+                    "file_hash": "d41d8cd98f00b204e9800998ecf8427e",
+                    "filesize": "1024",
+                    "timestamp": "2023-01-01 00:00:00",
+                    "patch_id": null,
+                    "url_full": "https://example.com/osu!.exe",
+                    "url_patch": null
+                }],
+                "cuttingedge": []
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(manifest.files(UpdateStream::Stable).len(), 1);
+        assert!(manifest.files(UpdateStream::CuttingEdge).is_empty());
+        // missing streams fall back to stable
+        assert_eq!(
+            manifest.files(UpdateStream::Beta),
+            manifest.files(UpdateStream::Stable)
+        );
+
+        assert!(UpdateManifest::default()
+            .files(UpdateStream::Stable)
+            .is_empty());
+    }
+}
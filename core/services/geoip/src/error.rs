@@ -1,3 +1,4 @@
+use maxminddb::MaxMindDBError;
 use peace_rpc_error::{RpcError, TonicError};
 use tonic::Status;
 
@@ -5,16 +6,35 @@ use tonic::Status;
 pub enum GeoipError {
     #[error("geo-ip local was not initialized")]
     NotInitialized,
+    #[error("geo-ip database not found: {0}")]
+    DbNotFound(String),
+    #[error("geo-ip database is corrupt: {0}")]
+    DbCorrupt(String),
+    #[error("ip address not found in the geo-ip database: {0}")]
+    AddrNotFound(String),
+    #[error("ip address is private or reserved: {0}")]
+    ReservedAddr(String),
+    #[error("invalid ip address: {0}")]
+    InvalidIp(String),
     #[error("failed to lookup ip address: {0}")]
     LookupError(String),
     #[error("this only for local service")]
     OnlyLocalService,
-    #[error("failed to load geo-ip database: {0}")]
-    FailedToLoadDatabase(String),
     #[error("TonicError: {0}")]
     TonicError(String),
 }
 
+impl From<MaxMindDBError> for GeoipError {
+    fn from(err: MaxMindDBError) -> Self {
+        match err {
+            MaxMindDBError::AddressNotFoundError(addr) => {
+                Self::AddrNotFound(addr)
+            },
+            err => Self::LookupError(err.to_string()),
+        }
+    }
+}
+
 impl TonicError for GeoipError {
     fn tonic_error(s: Status) -> Self {
         Self::TonicError(s.message().to_owned())
@@ -8,7 +8,7 @@ use pb_base::ExecSuccess;
 use pb_geoip::{geoip_rpc_client::GeoipRpcClient, GeoDbPath, IpAddress};
 use peace_cfg::RpcClientConfig;
 use std::{net::IpAddr, path::Path, sync::Arc};
-use tonic::transport::Channel;
+use tools::tonic_utils::TracedChannel;
 
 const LANGUAGE: &str = "en";
 const DEFAULT_GEO_DB_PATH: &str = "GeoLite2-City.mmdb";
@@ -23,7 +23,7 @@ impl GeoipServiceBuilder {
     where
         I: IntoService<DynGeoipService> + FromGeoDbPath + FromGeoDb + Default,
         R: IntoService<DynGeoipService>
-            + FromRpcClient<Client = GeoipRpcClient<Channel>>,
+            + FromRpcClient<Client = GeoipRpcClient<TracedChannel>>,
     {
         info!("initializing Geoip service...");
         let mut service = I::from_path(path.unwrap_or(DEFAULT_GEO_DB_PATH))
@@ -75,14 +75,72 @@ impl GeoipServiceImpl {
     }
 }
 
-#[inline]
 pub fn load_db<P>(path: P) -> Result<GeoDb, GeoipError>
 where
     P: AsRef<Path>,
 {
-    Reader::open_mmap(path)
-        .map(Arc::new)
-        .map_err(|err| GeoipError::FailedToLoadDatabase(err.to_string()))
+    let path = path.as_ref();
+    if !path.is_file() {
+        return Err(GeoipError::DbNotFound(path.display().to_string()));
+    }
+
+    Reader::open_mmap(path).map(Arc::new).map_err(|err| {
+        GeoipError::DbCorrupt(format!("{}: {err}", path.display()))
+    })
+}
+
+/// Parse an ip address, see [`normalize_ip_address`].
+#[inline]
+pub fn parse_ip_address(ip: &str) -> Result<IpAddr, GeoipError> {
+    ip.parse()
+        .map(normalize_ip_address)
+        .map_err(|_| GeoipError::InvalidIp(ip.to_owned()))
+}
+
+/// Dual-stack sockets report ipv4 clients as ipv4-mapped ipv6 addresses
+/// (`::ffff:1.2.3.4`), which are looked up as plain ipv4.
+#[inline]
+pub fn normalize_ip_address(ip_addr: IpAddr) -> IpAddr {
+    match ip_addr {
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => IpAddr::V4(ip),
+            None => IpAddr::V6(ip),
+        },
+        ip => ip,
+    }
+}
+
+/// Private, loopback, link-local and other addresses that are never in the
+/// geo-ip database.
+pub fn is_reserved_ip_address(ip_addr: &IpAddr) -> bool {
+    match ip_addr {
+        IpAddr::V4(ip) => {
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Shared address space (100.64.0.0/10)
+                || (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64)
+        },
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_reserved_ip_address(&IpAddr::V4(ip)),
+            None => {
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local (fc00::/7)
+                    || ip.segments()[0] & 0xfe00 == 0xfc00
+                    // Link-local (fe80::/10)
+                    || ip.segments()[0] & 0xffc0 == 0xfe80
+                    // Documentation (2001:db8::/32)
+                    || (ip.segments()[0] == 0x2001
+                        && ip.segments()[1] == 0xdb8)
+            },
+        },
+    }
 }
 
 impl FromGeoDbPath for GeoipServiceImpl {}
@@ -109,10 +167,13 @@ impl LookupIpAddress for GeoipServiceImpl {
         &self,
         ip_addr: IpAddr,
     ) -> Result<GeoipData, GeoipError> {
+        let ip_addr = normalize_ip_address(ip_addr);
+        if is_reserved_ip_address(&ip_addr) {
+            return Err(GeoipError::ReservedAddr(ip_addr.to_string()));
+        }
+
         let db = self.db.load_full().ok_or(GeoipError::NotInitialized)?;
-        let data = db
-            .lookup::<geoip2::City>(ip_addr)
-            .map_err(|err| GeoipError::LookupError(err.to_string()))?;
+        let data = db.lookup::<geoip2::City>(ip_addr)?;
 
         let location = data
             .location
@@ -202,10 +263,10 @@ impl ReloadGeoDb for GeoipServiceImpl {
 }
 
 #[derive(Debug, Clone)]
-pub struct GeoipServiceRemote(GeoipRpcClient<Channel>);
+pub struct GeoipServiceRemote(GeoipRpcClient<TracedChannel>);
 
 impl RpcClient for GeoipServiceRemote {
-    type Client = GeoipRpcClient<Channel>;
+    type Client = GeoipRpcClient<TracedChannel>;
 
     #[inline]
     fn client(&self) -> Self::Client {
@@ -253,3 +314,80 @@ impl ReloadGeoDb for GeoipServiceRemote {
             .into_inner())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maxminddb::MaxMindDBError;
+    use peace_unique_id::Ulid;
+
+    #[test]
+    fn test_load_db_not_found() {
+        let err = GeoipServiceImpl::from_path("not-exists.mmdb").err();
+        assert!(matches!(err, Some(GeoipError::DbNotFound(_))));
+    }
+
+    #[test]
+    fn test_load_db_corrupt() {
+        let path = std::env::temp_dir()
+            .join(format!("peace-geoip-corrupt-{}.mmdb", Ulid::new()));
+        std::fs::write(&path, b"not a maxmind database").unwrap();
+
+        let err = GeoipServiceImpl::from_path(path.to_str().unwrap()).err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(err, Some(GeoipError::DbCorrupt(_))));
+    }
+
+    #[test]
+    fn test_parse_invalid_ip() {
+        assert!(matches!(
+            parse_ip_address("1.1.1"),
+            Err(GeoipError::InvalidIp(_))
+        ));
+        assert!(parse_ip_address("1.1.1.1").is_ok());
+    }
+
+    #[test]
+    fn test_addr_not_found() {
+        let err = GeoipError::from(MaxMindDBError::AddressNotFoundError(
+            "Address not found in database".into(),
+        ));
+        assert!(matches!(err, GeoipError::AddrNotFound(_)));
+    }
+
+    #[test]
+    fn test_normalize_ip_address() {
+        let v4 = parse_ip_address("1.2.3.4").unwrap();
+        let v6 = parse_ip_address("2606:4700:4700::1111").unwrap();
+        let mapped = parse_ip_address("::ffff:1.2.3.4").unwrap();
+
+        assert_eq!(v4, "1.2.3.4".parse::<IpAddr>().unwrap());
+        assert!(v6.is_ipv6());
+        assert_eq!(mapped, v4);
+        assert_eq!(normalize_ip_address("::ffff:1.2.3.4".parse().unwrap()), v4);
+    }
+
+    #[tokio::test]
+    async fn test_lookup_reserved_ip() {
+        let service = GeoipServiceImpl::default();
+
+        for ip in ["10.0.0.1", "127.0.0.1", "100.64.0.1", "fd00::1", "::1"] {
+            assert!(matches!(
+                service.lookup_with_ip_address(ip.parse().unwrap()).await,
+                Err(GeoipError::ReservedAddr(_))
+            ));
+        }
+
+        let mapped = "::ffff:10.0.0.1".parse().unwrap();
+        assert!(matches!(
+            service.lookup_with_ip_address(mapped).await,
+            Err(GeoipError::ReservedAddr(ip)) if ip == "10.0.0.1"
+        ));
+
+        assert!(matches!(
+            service.lookup_with_ip_address("1.1.1.1".parse().unwrap()).await,
+            Err(GeoipError::NotInitialized)
+        ));
+    }
+}
@@ -16,8 +16,7 @@ use pb_signature::{
 };
 use peace_cfg::RpcClientConfig;
 use std::{borrow::Cow, sync::Arc};
-use tonic::transport::Channel;
-use tools::crypto::SignerManager;
+use tools::{crypto::SignerManager, tonic_utils::TracedChannel};
 
 const DEFAULT_ED25519_PEM_FILE_PATH: &str = "signature_svc_priv_key.pem";
 
@@ -31,7 +30,7 @@ impl SignatureServiceBuilder {
     where
         I: IntoService<DynSignatureService> + From<SignerManager>,
         R: IntoService<DynSignatureService>
-            + FromRpcClient<Client = SignatureRpcClient<Channel>>,
+            + FromRpcClient<Client = SignatureRpcClient<TracedChannel>>,
     {
         info!("initializing Signature service...");
         let mut service = SignerManager::from_pem_file(
@@ -159,10 +158,10 @@ impl RequestPublicKey for SignatureServiceImpl {
 }
 
 #[derive(Debug, Clone)]
-pub struct SignatureServiceRemote(SignatureRpcClient<Channel>);
+pub struct SignatureServiceRemote(SignatureRpcClient<TracedChannel>);
 
 impl RpcClient for SignatureServiceRemote {
-    type Client = SignatureRpcClient<Channel>;
+    type Client = SignatureRpcClient<TracedChannel>;
 
     #[inline]
     fn client(&self) -> Self::Client {
@@ -1,7 +1,10 @@
+use crate::{CachedRoute, RouteConcurrencyLimit};
 use clap_serde_derive::ClapSerde;
-use peace_cfg::{impl_config, peace_config, SingletonConfig, TlsConfig};
+use peace_cfg::{
+    impl_config, peace_config, Secret, SingletonConfig, TlsConfig,
+};
 use peace_logs::LoggingConfigArgs;
-use std::{default::Default, net::SocketAddr, ops::Deref};
+use std::{default::Default, net::SocketAddr, ops::Deref, path::PathBuf};
 
 /// Basic configuration items for `peace-api` framework.
 #[peace_config]
@@ -43,15 +46,41 @@ pub struct ApiServiceConfig {
     #[arg(short = 'A', long)]
     pub admin_endpoints: bool,
 
-    /// Admin api `Authorization` `bearer` token.
+    /// Admin api `Authorization` `bearer` token, `env:VAR` reads it from
+    /// the environment variable.
     #[arg(short, long)]
-    pub admin_token: Option<String>,
+    pub admin_token: Option<Secret>,
+
+    /// File containing the admin api token, overrides `admin_token`.
+    #[arg(long)]
+    pub admin_token_file: Option<PathBuf>,
 
     /// Limit the max number of in-flight requests.
     #[default(1024)]
     #[arg(long, default_value = "1024")]
     pub concurrency_limit: usize,
 
+    /// Limit the max number of in-flight requests of specific routes,
+    /// requests exceeding it are rejected with `503`, comma separated
+    /// `path=limit`, a path ending with `*` matches its prefix, e.g.
+    /// `/web/osu-submit-modular-selector.php=16,/web/*=256`.
+    ///
+    /// The first matching limit applies, all requests still count against
+    /// `concurrency_limit`.
+    #[default(Vec::new())]
+    #[arg(long, value_delimiter = ',')]
+    pub route_concurrency_limits: Vec<RouteConcurrencyLimit>,
+
+    /// Cache the successful `GET` responses of specific routes in memory,
+    /// comma separated `path[?param&param]=ttl_secs`, responses are cached
+    /// per path and values of the listed query params, e.g.
+    /// `/web/osu-getseasonal.php=3600,/web/check-updates.php?action&stream=300`.
+    ///
+    /// Requests with the `x-peace-cache: bypass` header skip the cache.
+    #[default(Vec::new())]
+    #[arg(long, value_delimiter = ',')]
+    pub response_cache_routes: Vec<CachedRoute>,
+
     /// Fail requests that take longer than timeout (secs).
     #[default(10)]
     #[arg(short, long, default_value = "10")]
@@ -94,6 +123,12 @@ pub struct ApiServiceConfig {
     #[arg(long)]
     pub tcp_keepalive_retries: Option<u32>,
 
+    /// Max seconds to drain the app and close the connections after
+    /// receiving a shutdown signal.
+    #[default(10)]
+    #[arg(long, default_value = "10")]
+    pub shutdown_grace_period_secs: u64,
+
     /// The `swagger ui` base uri path.
     #[arg(long, default_value = "/swagger-ui")]
     pub swagger_path: String,
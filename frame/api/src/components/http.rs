@@ -4,9 +4,10 @@ use axum_server::{AddrIncomingConfig, Handle};
 use once_cell::sync::OnceCell;
 use std::{
     net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
     time::Duration,
 };
-use tools::async_collections::shutdown_signal;
+use tools::async_collections::{shutdown_signal, SignalHandle};
 
 pub const DEFAULT_BINDING_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
 
@@ -42,7 +43,11 @@ pub async fn serve(app: impl WebApplication) {
         .https_addr
         .unwrap_or(app.default_https_addr().unwrap_or(DEFAULT_HTTPS_ADDR));
 
-    let app = router::app(app).await;
+    let shutdown_grace_period =
+        Duration::from_secs(cfg.shutdown_grace_period_secs);
+
+    let web_app = app;
+    let app = router::app(web_app.clone()).await;
 
     let incoming_config = AddrIncomingConfig::new()
         .tcp_nodelay(cfg.tcp_nodelay)
@@ -76,26 +81,26 @@ pub async fn serve(app: impl WebApplication) {
             tokio::join!(
                 tls::launch_ssl_redirect_server(http_addr, https_addr),
                 https,
-                shutdown_signal(shutdown)
+                wait_shutdown(&web_app, shutdown_grace_period)
             );
         } else {
             tokio::join!(
                 launch_http_server(app, http_addr, incoming_config),
                 https,
-                shutdown_signal(shutdown)
+                wait_shutdown(&web_app, shutdown_grace_period)
             );
         }
     } else {
         tokio::join!(
             launch_http_server(app, http_addr, incoming_config),
-            shutdown_signal(shutdown)
+            wait_shutdown(&web_app, shutdown_grace_period)
         );
     }
 
     #[cfg(not(feature = "tls"))]
     tokio::join!(
         launch_http_server(app, http_addr, incoming_config),
-        shutdown_signal(shutdown)
+        wait_shutdown(&web_app, shutdown_grace_period)
     );
     warn!("!!! SERVER STOPPED !!!")
 }
@@ -239,7 +244,77 @@ pub mod tls {
     }
 }
 
+/// A shutdown requested by the admin api.
+#[derive(Debug, Default)]
+pub struct ShutdownRequest {
+    signal: SignalHandle,
+    grace_period_secs: AtomicU64,
+}
+
+impl ShutdownRequest {
+    /// Request the server to shutdown within `grace_period`.
+    pub fn trigger(&self, grace_period: Duration) {
+        self.grace_period_secs.store(grace_period.as_secs(), Ordering::SeqCst);
+        self.signal.trigger();
+    }
+
+    /// Waits until shutdown is requested, returns the grace period.
+    pub async fn wait(&self) -> Duration {
+        self.signal.wait_signal().await;
+        Duration::from_secs(self.grace_period_secs.load(Ordering::SeqCst))
+    }
+}
+
+pub fn shutdown_request() -> &'static ShutdownRequest {
+    static REQUEST: OnceCell<ShutdownRequest> = OnceCell::new();
+    REQUEST.get_or_init(ShutdownRequest::default)
+}
+
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Whether the server is shutting down, apps should refuse to start new
+/// work (e.g. logins) so that draining can finish.
+#[inline]
+pub fn shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// Waits for a shutdown signal or a shutdown request, then shuts down the
+/// server gracefully.
+///
+/// Signals use `default_grace_period`, requests carry their own.
+pub async fn wait_shutdown(
+    app: &impl WebApplication,
+    default_grace_period: Duration,
+) {
+    let grace_period = tokio::select! {
+        _ = shutdown_signal(shutdown) => default_grace_period,
+        grace_period = shutdown_request().wait() => grace_period,
+    };
+
+    graceful_shutdown(app, grace_period).await
+}
+
+/// Drains the application, then stops accepting connections and serves the
+/// in-flight requests, all of it within `grace_period`.
+pub async fn graceful_shutdown(
+    app: &impl WebApplication,
+    grace_period: Duration,
+) {
+    let deadline = tokio::time::Instant::now() + grace_period;
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+
+    // The server keeps serving while draining, so the pending queues can
+    // still be consumed by the clients polling them.
+    if tokio::time::timeout_at(deadline, app.drain()).await.is_err() {
+        warn!(">> Drain not finished in {:?}, stop anyway.", grace_period);
+    }
+
+    server_handle().graceful_shutdown(Some(
+        deadline.saturating_duration_since(tokio::time::Instant::now()),
+    ));
+}
+
 fn shutdown(s: &str) {
     warn!(">> [{}] Signal received, shutdown.", s);
-    server_handle().shutdown();
 }
@@ -3,7 +3,11 @@ pub mod docs;
 pub mod error;
 pub mod http;
 pub mod responder;
+pub mod response_cache;
+pub mod route_limit;
 pub mod router;
 
 pub use cfg::*;
 pub use docs::*;
+pub use response_cache::{CachedRoute, ResponseCache};
+pub use route_limit::{RouteConcurrencyLimit, RouteConcurrencyLimits};
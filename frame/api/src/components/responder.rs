@@ -1,15 +1,16 @@
 use crate::{
-    components::{error::Error, http::server_handle},
+    components::{error::Error, http::shutdown_request},
     WebApplication,
 };
 use axum::{
     body::{Body, BoxBody},
     extract::{Host, Path},
-    http::Request,
+    http::{Request, StatusCode},
     response::{IntoResponse, Response},
     Router,
 };
 use std::time::Duration;
+use tools::health::HealthStatus;
 use tower::{load_shed, timeout, BoxError, ServiceExt};
 
 /// Route `/` handler.
@@ -17,7 +18,21 @@ pub async fn app_root() -> Response {
     tools::pkg_metadata!().into_response()
 }
 
+/// Route `/healthz` handler, `503` with the reason if the app is unhealthy.
+pub async fn healthz(app: impl WebApplication) -> Response {
+    match app.health_check().await {
+        HealthStatus::Ok => "ok".into_response(),
+        HealthStatus::Unavailable(reason) => {
+            (StatusCode::SERVICE_UNAVAILABLE, reason).into_response()
+        },
+    }
+}
+
 /// Stop the server within a specified time `grace_period_secs`.
+///
+/// The app is drained the same way as on receiving a shutdown signal: the
+/// pending queues are flushed first, then the server stops accepting
+/// connections, `grace_period_secs` bounds the whole shutdown.
 #[utoipa::path(
     delete,
     context_path = "/admin",
@@ -36,8 +51,7 @@ pub async fn shutdown_server(Path(grace_period_secs): Path<u64>) -> Response {
         "!!! [api::shutdown_server]: The server will stop in [{}] seconds !!!",
         grace_period_secs
     );
-    server_handle()
-        .graceful_shutdown(Some(Duration::from_secs(grace_period_secs)));
+    shutdown_request().trigger(Duration::from_secs(grace_period_secs));
     "ok".into_response()
 }
 
@@ -0,0 +1,379 @@
+use crate::{error::Error, route_limit::path_matches};
+use axum::{
+    body::{boxed, Bytes, Full},
+    extract::State,
+    http::{
+        header::SET_COOKIE, HeaderMap, HeaderValue, Method, Request,
+        StatusCode, Uri,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Request header `bypass` skips the cache, response header telling whether
+/// the response was served from the cache (`hit`, `miss` or `bypass`).
+pub const CACHE_HEADER: &str = "x-peace-cache";
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum CachedRouteError {
+    #[error("expected `path[?param&param]=ttl_secs`, got `{0}`")]
+    InvalidFormat(String),
+    #[error("path pattern should start with `/`, got `{0}`")]
+    InvalidPattern(String),
+    #[error("ttl should be a positive integer, got `{0}`")]
+    InvalidTtl(String),
+}
+
+/// `GET` routes whose successful responses are cached for `ttl_secs`,
+/// written as `path[?param&param]=ttl_secs`.
+///
+/// The path is matched like [`crate::RouteConcurrencyLimit`], responses are
+/// cached per path and values of the listed query params, the other params
+/// are ignored, e.g. `/web/check-updates.php?action&stream=300`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CachedRoute {
+    pub pattern: String,
+    pub key_params: Vec<String>,
+    pub ttl_secs: u64,
+}
+
+impl CachedRoute {
+    #[inline]
+    pub fn matches(&self, path: &str) -> bool {
+        path_matches(&self.pattern, path)
+    }
+
+    #[inline]
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_secs)
+    }
+
+    /// The request path followed by the values of the key params.
+    pub fn cache_key(&self, uri: &Uri) -> String {
+        let query = uri
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+            .collect::<HashMap<_, _>>();
+
+        let mut key = uri.path().to_owned();
+        for (i, param) in self.key_params.iter().enumerate() {
+            key.push(if i == 0 { '?' } else { '&' });
+            key.push_str(param);
+            key.push('=');
+            key.push_str(query.get(param.as_str()).unwrap_or(&""));
+        }
+
+        key
+    }
+}
+
+impl FromStr for CachedRoute {
+    type Err = CachedRouteError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (route, ttl) = s
+            .rsplit_once('=')
+            .ok_or_else(|| CachedRouteError::InvalidFormat(s.into()))?;
+        let (route, ttl) = (route.trim(), ttl.trim());
+
+        let (pattern, key_params) = match route.split_once('?') {
+            Some((pattern, params)) => (
+                pattern,
+                params
+                    .split('&')
+                    .filter(|p| !p.is_empty())
+                    .map(Into::into)
+                    .collect(),
+            ),
+            None => (route, Vec::new()),
+        };
+
+        if !pattern.starts_with('/') {
+            return Err(CachedRouteError::InvalidPattern(pattern.into()));
+        }
+
+        match ttl.parse() {
+            Ok(ttl_secs) if ttl_secs > 0 => {
+                Ok(Self { pattern: pattern.into(), key_params, ttl_secs })
+            },
+            _ => Err(CachedRouteError::InvalidTtl(ttl.into())),
+        }
+    }
+}
+
+impl TryFrom<String> for CachedRoute {
+    type Error = CachedRouteError;
+
+    #[inline]
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<CachedRoute> for String {
+    #[inline]
+    fn from(route: CachedRoute) -> Self {
+        route.to_string()
+    }
+}
+
+impl fmt::Display for CachedRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)?;
+        if !self.key_params.is_empty() {
+            write!(f, "?{}", self.key_params.join("&"))?;
+        }
+        write!(f, "={}", self.ttl_secs)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CachedResponse {
+    expires_at: Instant,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+#[derive(Debug, Default)]
+struct ResponseCacheInner {
+    routes: Vec<CachedRoute>,
+    entries: Mutex<HashMap<String, CachedResponse>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// In-memory cache of the responses of the [`CachedRoute`]s.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseCache {
+    inner: Arc<ResponseCacheInner>,
+}
+
+impl ResponseCache {
+    /// Expired responses are dropped once this many are cached, new
+    /// responses are not cached while it stays full.
+    const MAX_ENTRIES: usize = 4096;
+
+    pub fn new(routes: &[CachedRoute]) -> Self {
+        Self {
+            inner: ResponseCacheInner {
+                routes: routes.to_vec(),
+                ..Default::default()
+            }
+            .into(),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.routes.is_empty()
+    }
+
+    /// Number of requests served from the cache.
+    #[inline]
+    pub fn hits(&self) -> u64 {
+        self.inner.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of cacheable requests not served from the cache.
+    #[inline]
+    pub fn misses(&self) -> u64 {
+        self.inner.misses.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn route(&self, path: &str) -> Option<&CachedRoute> {
+        self.inner.routes.iter().find(|route| route.matches(path))
+    }
+
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        self.inner
+            .entries
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|cached| cached.expires_at > Instant::now())
+            .cloned()
+    }
+
+    fn insert(&self, key: String, response: CachedResponse) {
+        let mut entries = self.inner.entries.lock().unwrap();
+
+        if entries.len() >= Self::MAX_ENTRIES {
+            let now = Instant::now();
+            entries.retain(|_, cached| cached.expires_at > now);
+
+            if entries.len() >= Self::MAX_ENTRIES {
+                return;
+            }
+        }
+
+        entries.insert(key, response);
+    }
+}
+
+fn with_cache_header(mut res: Response, value: &'static str) -> Response {
+    res.headers_mut().insert(CACHE_HEADER, HeaderValue::from_static(value));
+    res
+}
+
+/// Middleware serving the `200` responses of the [`CachedRoute`]s from the
+/// cache until they expire.
+pub async fn response_cache<B>(
+    State(cache): State<ResponseCache>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if req.method() != Method::GET {
+        return next.run(req).await;
+    }
+
+    let (key, ttl) = match cache.route(req.uri().path()) {
+        Some(route) => (route.cache_key(req.uri()), route.ttl()),
+        None => return next.run(req).await,
+    };
+
+    if req.headers().get(CACHE_HEADER).map_or(false, |value| value == "bypass")
+    {
+        return with_cache_header(next.run(req).await, "bypass");
+    }
+
+    if let Some(cached) = cache.get(&key) {
+        cache.inner.hits.fetch_add(1, Ordering::Relaxed);
+
+        let mut res = Response::new(boxed(Full::new(cached.body)));
+        *res.status_mut() = cached.status;
+        *res.headers_mut() = cached.headers;
+        return with_cache_header(res, "hit");
+    }
+
+    cache.inner.misses.fetch_add(1, Ordering::Relaxed);
+
+    let res = next.run(req).await;
+    if res.status() != StatusCode::OK || res.headers().contains_key(SET_COOKIE)
+    {
+        return with_cache_header(res, "miss");
+    }
+
+    let (parts, body) = res.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(err) => {
+            return Error::from(anyhow!("failed to read response: {err}"))
+                .into_response()
+        },
+    };
+
+    cache.insert(
+        key,
+        CachedResponse {
+            expires_at: Instant::now() + ttl,
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body: body.clone(),
+        },
+    );
+
+    with_cache_header(
+        Response::from_parts(parts, boxed(Full::new(body))),
+        "miss",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use std::sync::atomic::AtomicUsize;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_parse_cached_route() {
+        let route: CachedRoute =
+            "/web/check-updates.php?action&stream=300".parse().unwrap();
+        assert_eq!(route.key_params, vec!["action", "stream"]);
+        assert_eq!(route.ttl_secs, 300);
+        assert_eq!(
+            route.to_string(),
+            "/web/check-updates.php?action&stream=300"
+        );
+
+        assert_eq!(
+            route.cache_key(
+                &"/web/check-updates.php?stream=stable40&t=1&action=check"
+                    .parse()
+                    .unwrap()
+            ),
+            "/web/check-updates.php?action=check&stream=stable40"
+        );
+
+        assert!("/web/osu-getseasonal.php".parse::<CachedRoute>().is_err());
+        assert!("/web/osu-getseasonal.php=0".parse::<CachedRoute>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_response_cache_hit() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cache =
+            ResponseCache::new(&["/seasonal?stream=60".parse().unwrap()]);
+
+        let router =
+            Router::new()
+                .route("/seasonal", {
+                    let calls = calls.clone();
+                    get(move || {
+                        let calls = calls.clone();
+                        async move {
+                            calls.fetch_add(1, Ordering::SeqCst).to_string()
+                        }
+                    })
+                })
+                .layer(middleware::from_fn_with_state(
+                    cache.clone(),
+                    response_cache,
+                ));
+
+        let request = |uri: &str, bypass: bool| {
+            let mut req = Request::get(uri);
+            if bypass {
+                req = req.header(CACHE_HEADER, "bypass");
+            }
+            req.body(Body::empty()).unwrap()
+        };
+
+        for (uri, bypass, expected_body, expected_cache) in [
+            ("/seasonal?stream=a", false, "0", "miss"),
+            // ignored param
+            ("/seasonal?stream=a&t=1", false, "0", "hit"),
+            ("/seasonal?stream=a", true, "1", "bypass"),
+            ("/seasonal?stream=b", false, "2", "miss"),
+            ("/seasonal?stream=a", false, "0", "hit"),
+        ] {
+            let res =
+                router.clone().oneshot(request(uri, bypass)).await.unwrap();
+            assert_eq!(res.headers()[CACHE_HEADER], expected_cache);
+
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            assert_eq!(body, expected_body);
+        }
+
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(cache.hits(), 2);
+        assert_eq!(cache.misses(), 2);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::error::Error;
+use axum::{
+    extract::State,
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr, sync::Arc};
+use tokio::sync::Semaphore;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum RouteConcurrencyLimitError {
+    #[error("expected `path=limit`, got `{0}`")]
+    InvalidFormat(String),
+    #[error("path pattern should start with `/`, got `{0}`")]
+    InvalidPattern(String),
+    #[error("limit should be a positive integer, got `{0}`")]
+    InvalidLimit(String),
+}
+
+/// Max number of in-flight requests of the routes matching `pattern`,
+/// written as `path=limit`.
+///
+/// The pattern is a request path, or a path prefix when it ends with `*`,
+/// e.g. `/web/osu-submit-modular-selector.php=16` or `/web/*=256`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RouteConcurrencyLimit {
+    pub pattern: String,
+    pub limit: usize,
+}
+
+impl RouteConcurrencyLimit {
+    #[inline]
+    pub fn matches(&self, path: &str) -> bool {
+        path_matches(&self.pattern, path)
+    }
+}
+
+/// Whether the request path matches the pattern, a pattern ending with `*`
+/// matches by prefix.
+#[inline]
+pub fn path_matches(pattern: &str, path: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => path.starts_with(prefix),
+        None => path == pattern,
+    }
+}
+
+impl FromStr for RouteConcurrencyLimit {
+    type Err = RouteConcurrencyLimitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, limit) = s.rsplit_once('=').ok_or_else(|| {
+            RouteConcurrencyLimitError::InvalidFormat(s.into())
+        })?;
+        let (pattern, limit) = (pattern.trim(), limit.trim());
+
+        if !pattern.starts_with('/') {
+            return Err(RouteConcurrencyLimitError::InvalidPattern(
+                pattern.into(),
+            ));
+        }
+
+        match limit.parse() {
+            Ok(limit) if limit > 0 => {
+                Ok(Self { pattern: pattern.into(), limit })
+            },
+            _ => Err(RouteConcurrencyLimitError::InvalidLimit(limit.into())),
+        }
+    }
+}
+
+impl TryFrom<String> for RouteConcurrencyLimit {
+    type Error = RouteConcurrencyLimitError;
+
+    #[inline]
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<RouteConcurrencyLimit> for String {
+    #[inline]
+    fn from(limit: RouteConcurrencyLimit) -> Self {
+        limit.to_string()
+    }
+}
+
+impl fmt::Display for RouteConcurrencyLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.pattern, self.limit)
+    }
+}
+
+/// Per-route concurrency limits, a request is limited by the first limit
+/// matching its path, requests matching none are only limited globally.
+#[derive(Debug, Clone)]
+pub struct RouteConcurrencyLimits {
+    limits: Arc<[(RouteConcurrencyLimit, Arc<Semaphore>)]>,
+}
+
+impl RouteConcurrencyLimits {
+    pub fn new(limits: &[RouteConcurrencyLimit]) -> Self {
+        Self {
+            limits: limits
+                .iter()
+                .map(|l| (l.clone(), Arc::new(Semaphore::new(l.limit))))
+                .collect(),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.limits.is_empty()
+    }
+
+    #[inline]
+    pub fn semaphore(&self, path: &str) -> Option<&Arc<Semaphore>> {
+        self.limits
+            .iter()
+            .find(|(limit, _)| limit.matches(path))
+            .map(|(_, semaphore)| semaphore)
+    }
+}
+
+/// Middleware load-shedding the requests of a route with `503` once its
+/// limit is reached, the other routes are not affected.
+pub async fn route_concurrency_limit<B>(
+    State(limits): State<RouteConcurrencyLimits>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let _permit = match limits.semaphore(req.uri().path()) {
+        Some(semaphore) => match semaphore.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => return Error::Unavailable.into_response(),
+        },
+        None => None,
+    };
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body, http::StatusCode, middleware, routing::get, Router,
+    };
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_parse_route_concurrency_limit() {
+        let limit: RouteConcurrencyLimit =
+            "/web/osu-submit-modular-selector.php=16".parse().unwrap();
+        assert!(limit.matches("/web/osu-submit-modular-selector.php"));
+        assert!(!limit.matches("/web/osu-osz2-getscores.php"));
+        assert_eq!(
+            limit.to_string(),
+            "/web/osu-submit-modular-selector.php=16"
+        );
+
+        let prefix: RouteConcurrencyLimit = "/web/*=64".parse().unwrap();
+        assert!(prefix.matches("/web/osu-osz2-getscores.php"));
+        assert!(!prefix.matches("/api/v1/users"));
+
+        assert!("/web/*".parse::<RouteConcurrencyLimit>().is_err());
+        assert!("web=1".parse::<RouteConcurrencyLimit>().is_err());
+        assert!("/web=0".parse::<RouteConcurrencyLimit>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_route_concurrency_limit_sheds_route_only() {
+        let release = Arc::new(Notify::new());
+        let limits =
+            RouteConcurrencyLimits::new(&["/submit=1".parse().unwrap()]);
+
+        let router = Router::new()
+            .route("/submit", {
+                let release = release.clone();
+                get(move || {
+                    let release = release.clone();
+                    async move { release.notified().await }
+                })
+            })
+            .route("/ping", get(|| async {}))
+            .layer(middleware::from_fn_with_state(
+                limits.clone(),
+                route_concurrency_limit,
+            ));
+
+        let request = |path| Request::get(path).body(Body::empty()).unwrap();
+
+        // Holds the only permit of `/submit` until released
+        let pending = tokio::spawn(router.clone().oneshot(request("/submit")));
+        let semaphore = limits.semaphore("/submit").unwrap();
+        while semaphore.available_permits() > 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let shed = router.clone().oneshot(request("/submit")).await.unwrap();
+        assert_eq!(shed.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let ping = router.clone().oneshot(request("/ping")).await.unwrap();
+        assert_eq!(ping.status(), StatusCode::OK);
+
+        release.notify_one();
+        assert_eq!(pending.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+}
@@ -1,13 +1,15 @@
 use crate::{
-    responder, responder::shutdown_server, PeaceApiAdminEndpointsDocs,
-    WebApplication,
+    responder, responder::shutdown_server, response_cache::response_cache,
+    route_limit::route_concurrency_limit, PeaceApiAdminEndpointsDocs,
+    ResponseCache, RouteConcurrencyLimits, WebApplication,
 };
 use axum::{
     body::Body,
     error_handling::HandleErrorLayer,
     extract::Host,
     http::Request,
-    routing::{any, delete},
+    middleware,
+    routing::{any, delete, get},
     Router,
 };
 use peace_logs::Level;
@@ -20,8 +22,27 @@ use utoipa_swagger_ui::SwaggerUi;
 /// App router with some middleware.
 pub async fn app(app: impl WebApplication) -> Router {
     let cfg = app.frame_cfg_arc();
-    app_router(app)
-        .await
+    let mut router = app_router(app).await;
+
+    let route_limits =
+        RouteConcurrencyLimits::new(&cfg.route_concurrency_limits);
+    if !route_limits.is_empty() {
+        router = router.layer(middleware::from_fn_with_state(
+            route_limits,
+            route_concurrency_limit,
+        ));
+    }
+
+    // Outside of the route limits, so that hits do not take their slots
+    let response_cache = ResponseCache::new(&cfg.response_cache_routes);
+    if !response_cache.is_empty() {
+        router = router.layer(middleware::from_fn_with_state(
+            response_cache,
+            crate::response_cache::response_cache,
+        ));
+    }
+
+    router
         .layer(
             ServiceBuilder::new()
                 .layer(HandleErrorLayer::new(responder::handle_error))
@@ -41,15 +62,21 @@ pub async fn app(app: impl WebApplication) -> Router {
 /// such as setting the log level and stopping the server.
 ///
 /// You can pass in admin_token to add a layer of Authorization authentication
-/// (using Bearer).
-pub fn admin_routers(admin_token: Option<&str>) -> Router {
-    peace_logs::api::admin_routers(
-        admin_token,
-        Some(Router::new().route(
-            "/admin/server/shutdown/:grace_period_secs",
-            delete(shutdown_server),
-        )),
-    )
+/// (using Bearer), the `app_admin_router` is protected as well.
+pub fn admin_routers(
+    admin_token: Option<&str>,
+    app_admin_router: Option<Router>,
+) -> Router {
+    let mut router = Router::new().route(
+        "/admin/server/shutdown/:grace_period_secs",
+        delete(shutdown_server),
+    );
+
+    if let Some(app_admin_router) = app_admin_router {
+        router = router.merge(app_admin_router)
+    }
+
+    peace_logs::api::admin_routers(admin_token, Some(router))
 }
 
 /// App router
@@ -61,15 +88,26 @@ pub async fn app_router(app: impl WebApplication) -> Router {
             {
                 let mut docs = app.apidocs();
                 if cfg.admin_endpoints {
-                    docs.merge(PeaceApiAdminEndpointsDocs::openapi())
+                    docs.merge(PeaceApiAdminEndpointsDocs::openapi());
+
+                    if let Some(admin_docs) = app.admin_apidocs() {
+                        docs.merge(admin_docs)
+                    }
                 }
                 docs
             },
         ))
-        .merge(app.router().await);
+        .merge(app.router().await)
+        .route("/healthz", {
+            let app = app.clone();
+            get(move || responder::healthz(app))
+        });
 
     if cfg.admin_endpoints {
-        router = router.merge(admin_routers(cfg.admin_token.as_deref()))
+        router = router.merge(admin_routers(
+            cfg.admin_token.as_ref().map(crate::Secret::expose),
+            app.admin_router().await,
+        ))
     };
 
     if cfg.hostname_routing {
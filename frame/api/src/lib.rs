@@ -10,12 +10,13 @@ pub use components::*;
 pub use peace_cfg::{
     macro_define_rpc_client_config as define_rpc_client_config,
     macro_impl_config as impl_config, peace_config, ParseConfig,
-    RpcClientConfig, SingletonConfig,
+    RpcClientConfig, Secret, SingletonConfig,
 };
 
 use axum::{async_trait, body::Body, extract::Host, http::Request, Router};
 use once_cell::sync::OnceCell;
 use std::{net::SocketAddr, sync::Arc};
+use tools::health::HealthStatus;
 use utoipa::openapi::OpenApi;
 
 /// We can build app using `peace_api`,
@@ -46,6 +47,17 @@ pub trait WebApplication: Clone + Send + Sync + 'static {
     /// Returns the OpenApi documentation for this app.
     fn apidocs(&self) -> OpenApi;
 
+    /// Returns the admin endpoints of this app, they are served with the
+    /// builtin admin endpoints, behind the same `Authorization`.
+    async fn admin_router(&self) -> Option<Router> {
+        None
+    }
+
+    /// Returns the OpenApi documentation of the app admin endpoints.
+    fn admin_apidocs(&self) -> Option<OpenApi> {
+        None
+    }
+
     /// This is for `hostname routing`.
     ///
     /// Match the hostname with the specified service, and return a router,
@@ -58,4 +70,19 @@ pub trait WebApplication: Clone + Send + Sync + 'static {
     ) -> Option<Router> {
         None
     }
+
+    /// Checked on each `/healthz` request, override it to verify the
+    /// connectivity of the downstream services.
+    async fn health_check(&self) -> HealthStatus {
+        HealthStatus::Ok
+    }
+
+    /// Called on shutdown before the server is stopped, e.g. to wait for
+    /// pending queues to be consumed.
+    ///
+    /// The server still serves the requests while draining, the app has to
+    /// stop its own intake first (e.g. refuse the new logins).
+    ///
+    /// Cancelled once the shutdown grace period has elapsed.
+    async fn drain(&self) {}
 }
@@ -1,5 +1,5 @@
 use axum::{
-    extract::Path,
+    extract::{Path, Query},
     http::StatusCode,
     response::{IntoResponse, Response},
     routing::{get, put},
@@ -9,7 +9,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tower_http::auth::AddAuthorizationLayer;
 use tracing::log::LevelFilter;
-use tracing_subscriber::reload;
+use tracing_subscriber::{reload, EnvFilter};
 
 #[cfg(feature = "openapi_axum")]
 use utoipa::{
@@ -40,6 +40,14 @@ pub struct SetLogLevelParam {
     pub level: LogLevel,
 }
 
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi_axum", derive(IntoParams))]
+pub struct SetLogLevelQuery {
+    /// Only set the level of this target (module path prefix), e.g.
+    /// `peace_services::chat`
+    pub target: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[cfg_attr(feature = "openapi_axum", derive(IntoParams))]
 pub struct ToggleDebugModeParam {
@@ -63,7 +71,7 @@ impl Modify for AdminAuth {
     }
 }
 
-/// Set the global log level.
+/// Set the global log level, or only the level of `target` if present.
 #[cfg_attr(feature = "openapi_axum", utoipa::path(
     put,
     context_path = "/admin",
@@ -71,18 +79,49 @@ impl Modify for AdminAuth {
     tag = "admin",
     responses(
         (status = 200, description = "Success", body = [CommonHandleResponse]),
+        (status = 400, description = "Invalid target"),
     ),
-    params(SetLogLevelParam),
+    params(SetLogLevelParam, SetLogLevelQuery),
     security(("admin_token" = []))
 ))]
 pub async fn set_level(
     Path(param): Path<LogLevel>,
+    Query(query): Query<SetLogLevelQuery>,
 ) -> Result<Json<CommonHandleResponse>, AppError> {
     let level = LevelFilter::from(param);
-    crate::set_level(level)?;
 
-    info!("<LogsApi> Reload log level to: [{}]", level);
-    Ok(Json(CommonHandleResponse { success: true, msg: None }))
+    match query.target {
+        Some(target) => {
+            if !is_valid_target(&target) {
+                return Err(AppError::ParamError(format!(
+                    "invalid target: \"{target}\""
+                )));
+            }
+
+            crate::set_target_level(&target, level)?;
+
+            let current_filter = crate::env_filter(None).to_string();
+            info!("<LogsApi> Reload log level of [{}] to: [{}]", target, level);
+            Ok(Json(CommonHandleResponse {
+                success: true,
+                msg: Some(current_filter),
+            }))
+        },
+        None => {
+            crate::set_level(level)?;
+
+            info!("<LogsApi> Reload log level to: [{}]", level);
+            Ok(Json(CommonHandleResponse { success: true, msg: None }))
+        },
+    }
+}
+
+/// Targets are module paths, e.g. `peace_services::chat`.
+fn is_valid_target(target: &str) -> bool {
+    !target.is_empty()
+        && target
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | ':' | '-'))
 }
 
 /// Set the log env filter.
@@ -93,6 +132,7 @@ pub async fn set_level(
     tag = "admin",
     responses(
         (status = 200, description = "Success", body = [CommonHandleResponse]),
+        (status = 400, description = "Invalid env filter"),
     ),
     params(
         ("filter" = String, Path, description = "env filter string", example = "peace_logs::api=info")
@@ -102,6 +142,9 @@ pub async fn set_level(
 pub async fn set_env_filter(
     Path(filter): Path<String>,
 ) -> Result<Json<CommonHandleResponse>, AppError> {
+    EnvFilter::try_new(&filter)
+        .map_err(|err| AppError::ParamError(err.to_string()))?;
+
     crate::set_env_filter(&filter)?;
 
     let current_filter = crate::env_filter(None).to_string();
@@ -151,7 +194,7 @@ pub async fn debug_mode(
 /// Admin routers [`Router`]
 ///
 ///
-/// [`set_level`] : `PUT` `/admin/logs/set_level/:level`
+/// [`set_level`] : `PUT` `/admin/logs/set_level/:level?target=`
 /// [`set_env_filter`] : `PUT` `/admin/logs/set_env_filter/:filter`
 /// [`debug_mode`] : `PUT` `/admin/logs/debug_mode/:enabled`
 pub fn admin_routers(
@@ -203,14 +246,44 @@ impl IntoResponse for AppError {
 #[cfg(test)]
 mod test {
     use crate::{
-        api::{debug_mode, set_level, ToggleDebugModeParam},
+        api::{
+            debug_mode, set_env_filter, set_level, AppError, SetLogLevelQuery,
+            ToggleDebugModeParam,
+        },
         LogLevel,
     };
-    use axum::extract::Path;
+    use axum::extract::{Path, Query};
 
     #[tokio::test]
     async fn try_set_level() {
-        assert!(set_level(Path(LogLevel::Debug)).await.is_ok());
+        assert!(set_level(Path(LogLevel::Debug), Query(Default::default()))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn try_set_target_level() {
+        let target = |target: &str| {
+            Query(SetLogLevelQuery { target: Some(target.to_owned()) })
+        };
+
+        let res = set_level(Path(LogLevel::Trace), target("peace_chat::rpc"))
+            .await
+            .unwrap();
+        assert!(res.msg.as_ref().unwrap().contains("peace_chat::rpc=trace"));
+
+        assert!(matches!(
+            set_level(Path(LogLevel::Debug), target("peace_chat=info")).await,
+            Err(AppError::ParamError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn try_set_invalid_env_filter() {
+        assert!(matches!(
+            set_env_filter(Path("peace_chat=loud".to_owned())).await,
+            Err(AppError::ParamError(_))
+        ));
     }
 
     #[tokio::test]
@@ -41,6 +41,9 @@ pub struct ReloadHandles<
     pub env_filter_reload: Handle<EnvFilter, S>,
 }
 
+/// Directives appended to every env filter.
+const ALWAYS: &str = "rustls::conn=off";
+
 /// Get [`EnvFilter`] (set new env if `set_env` not `None`).
 ///
 /// ```rust
@@ -48,7 +51,6 @@ pub struct ReloadHandles<
 /// peace_logs::env_filter(Some("ENV"));
 /// let _ = peace_logs::tracing();
 pub fn env_filter(set_env: Option<&str>) -> EnvFilter {
-    const ALWAYS: &str = ",rustls::conn=off";
     static ENV: OnceCell<Arc<Mutex<String>>> = OnceCell::new();
     let s = ENV.get_or_init(|| {
         Arc::new(Mutex::new(set_env.unwrap_or("").to_string()))
@@ -57,7 +59,7 @@ pub fn env_filter(set_env: Option<&str>) -> EnvFilter {
     if let Some(set) = set_env {
         *sl = set.to_string();
     }
-    EnvFilter::from_str(&format!("{}{}", sl, ALWAYS)).unwrap()
+    EnvFilter::from_str(&format!("{},{}", sl, ALWAYS)).unwrap()
 }
 
 /// Init tracing and returns [`ReloadHandles`] for runtime reloading.
@@ -110,6 +112,40 @@ pub fn set_env_filter(filter: &str) -> Result<(), reload::Error> {
     Ok(())
 }
 
+/// Set the level of the logs of a target (module path prefix), the other
+/// directives of the env filter are kept.
+///
+/// ```rust
+/// use peace_logs;
+///
+/// let _ = peace_logs::tracing();
+/// peace_logs::set_target_level(
+///     "peace_services::chat",
+///     peace_logs::log::LevelFilter::Debug,
+/// )
+/// .unwrap();
+/// ```
+pub fn set_target_level(
+    target: &str,
+    level: LevelFilter,
+) -> Result<(), reload::Error> {
+    let current = env_filter(None).to_string();
+    let directive = format!("{target}={level}");
+
+    let directives = current
+        .split(',')
+        .filter(|d| {
+            !d.is_empty()
+                && *d != ALWAYS
+                && d.split_once('=').map(|(t, _)| t) != Some(target)
+        })
+        .chain([directive.as_str()])
+        .collect::<Vec<_>>()
+        .join(",");
+
+    set_env_filter(&directives)
+}
+
 /// Toggle debug (verbose) mode.
 ///
 /// Turning on debug will display information such as code line number, source
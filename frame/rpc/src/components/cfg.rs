@@ -1,5 +1,8 @@
 use clap_serde_derive::ClapSerde;
-use peace_cfg::{impl_config, peace_config, SingletonConfig, TlsConfig};
+use peace_cfg::{
+    impl_config, peace_config, ConfigError, ConfigErrors, Secret,
+    SingletonConfig, TlsConfig, ValidateConfig,
+};
 use peace_logs::LoggingConfigArgs;
 use std::{default::Default, net::SocketAddr, ops::Deref, path::PathBuf};
 
@@ -24,6 +27,20 @@ impl Deref for RpcFrameConfig {
 
 impl_logging_config!(RpcFrameConfig);
 
+impl ValidateConfig for RpcFrameConfig {
+    fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = ConfigErrors::default();
+
+        errors.check(
+            self.rpc_health_check_interval_secs > 0,
+            "rpc_health_check_interval_secs",
+            "must be greater than 0",
+        );
+
+        errors.into_result()
+    }
+}
+
 #[peace_config]
 pub struct RpcServiceConfig {
     /// The address and port the `gRPC` server listens on.
@@ -43,9 +60,15 @@ pub struct RpcServiceConfig {
     #[arg(short = 'A', long)]
     pub rpc_admin_endpoints: bool,
 
-    /// Admin rpc service `Authorization` `Bearer` token.
+    /// Admin rpc service `Authorization` `Bearer` token, `env:VAR` reads
+    /// it from the environment variable.
     #[arg(short = 'T', long)]
-    pub rpc_admin_token: Option<String>,
+    pub rpc_admin_token: Option<Secret>,
+
+    /// File containing the admin rpc service token, overrides
+    /// `rpc_admin_token`.
+    #[arg(long)]
+    pub rpc_admin_token_file: Option<PathBuf>,
 
     /// Enable reflection service.
     #[cfg(feature = "reflection")]
@@ -148,4 +171,16 @@ pub struct RpcServiceConfig {
     /// By default TCP keepalive probes is disabled.
     #[arg(long)]
     pub rpc_tcp_keepalive: Option<u64>,
+
+    /// Seconds between two health checks of the app, the result is reported
+    /// by the `grpc.health.v1.Health` service.
+    #[default(5)]
+    #[arg(long, default_value = "5")]
+    pub rpc_health_check_interval_secs: u64,
+
+    /// Max seconds to drain the app and stop the server after receiving a
+    /// shutdown signal, the server is force stopped once elapsed.
+    #[default(10)]
+    #[arg(long, default_value = "10")]
+    pub rpc_shutdown_grace_period_secs: u64,
 }
@@ -1,6 +1,8 @@
 mod cfg;
 pub mod extensions;
 pub mod interceptor;
+pub mod request_id;
 pub mod server;
 
 pub use cfg::*;
+pub use request_id::*;
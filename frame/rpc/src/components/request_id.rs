@@ -0,0 +1,74 @@
+use futures_util::future::BoxFuture;
+use peace_logs::Instrument;
+use std::task::{Context, Poll};
+use tonic::{
+    codegen::http::{self, HeaderValue},
+    transport::{server::Router, Server},
+};
+use tools::{constants::X_REQUEST_ID, tonic_utils::RequestId};
+use tower_layer::{Identity, Layer, Stack};
+use tower_service::Service;
+
+/// Server of the rpc apps, every request is handled with a [`RequestId`].
+pub type RpcServer = Server<Stack<RequestIdLayer, Identity>>;
+
+pub type RpcRouter = Router<Stack<RequestIdLayer, Identity>>;
+
+/// Handle each request with the `x-request-id` sent by the caller, or a new
+/// one if there is none.
+///
+/// The request is handled in a `request` span carrying the id, so that all
+/// its logs include it, and the rpc calls made while handling it forward the
+/// id to the next services.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestIdLayer;
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestIdService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestIdService { inner }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestIdService<S> {
+    inner: S,
+}
+
+impl<S, B> Service<http::Request<B>> for RequestIdService<S>
+where
+    S: Service<http::Request<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<S::Response, S::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        let request_id = req
+            .headers()
+            .get(X_REQUEST_ID)
+            .and_then(|value| value.to_str().ok())
+            .and_then(RequestId::parse)
+            .unwrap_or_else(RequestId::generate);
+
+        // Generated ids are visible to the handlers as well
+        if let Ok(value) = HeaderValue::from_str(&request_id.0) {
+            req.headers_mut().insert(X_REQUEST_ID, value);
+        }
+        req.extensions_mut().insert(request_id.clone());
+
+        let span = info_span!("request", request_id = %request_id.0);
+        let fut = self.inner.call(req);
+
+        Box::pin(request_id.scope(fut).instrument(span))
+    }
+}
@@ -1,15 +1,19 @@
-use crate::{RpcApplication, RpcFrameConfig};
+use crate::{
+    RequestIdLayer, RpcApplication, RpcFrameConfig, RpcRouter, RpcServer,
+};
 use once_cell::sync::OnceCell;
 use std::{
+    future::Future,
     net::{IpAddr, Ipv4Addr, SocketAddr},
     path::PathBuf,
     time::Duration,
 };
-use tonic::{
-    metadata::MetadataValue,
-    transport::{server::Router, Server},
+use tonic::{metadata::MetadataValue, transport::Server};
+use tonic_health::{server::HealthReporter, ServingStatus};
+use tools::{
+    async_collections::{shutdown_signal, SignalHandle},
+    health::HealthStatus,
 };
-use tools::async_collections::{shutdown_signal, SignalHandle};
 
 #[cfg(feature = "admin_endpoints")]
 use pb_logs::logs_rpc_server::LogsRpcServer;
@@ -54,7 +58,9 @@ pub async fn serve(app: impl RpcApplication) {
     #[cfg(feature = "admin_endpoints")]
     if cfg.rpc_admin_endpoints {
         let svc = peace_logs::grpc::LogsRpcService::default();
-        if let Some(token) = cfg.rpc_admin_token.clone() {
+        if let Some(token) =
+            cfg.rpc_admin_token.clone().map(crate::Secret::into_inner)
+        {
             // Create the logs service with the authorization interceptor.
             svr = svr.add_service(LogsRpcServer::with_interceptor(
                 svc,
@@ -72,29 +78,82 @@ pub async fn serve(app: impl RpcApplication) {
         }
     };
 
-    // Launch the server and wait for the shutdown signal.
-    let _ = tokio::join!(
-        launch_server(
-            svr,
-            cfg.rpc_tls_config.tls,
-            cfg.rpc_addr.unwrap_or(
-                app.default_listen_addr().unwrap_or(DEFAULT_RPC_ADDR)
-            ),
-            #[cfg(unix)]
-            cfg.rpc_uds.as_ref()
-        ),
-        shutdown_signal(shutdown)
+    // Add the standard gRPC health service, its status is updated by
+    // checking the app health periodically.
+    let (health_reporter, health_service) =
+        tonic_health::server::health_reporter();
+    svr = svr.add_service(health_service);
+    tokio::spawn(report_health(
+        app.clone(),
+        health_reporter,
+        Duration::from_secs(cfg.rpc_health_check_interval_secs),
+    ));
+
+    // Launch the server.
+    let server = launch_server(
+        svr,
+        cfg.rpc_tls_config.tls,
+        cfg.rpc_addr
+            .unwrap_or(app.default_listen_addr().unwrap_or(DEFAULT_RPC_ADDR)),
+        #[cfg(unix)]
+        cfg.rpc_uds.as_ref(),
     );
+    tokio::pin!(server);
+
+    // Wait for the shutdown signal, then drain the app and stop the server.
+    tokio::select! {
+        _ = &mut server => {},
+        _ = shutdown_signal(shutdown) => {
+            graceful_shutdown(
+                &app,
+                &mut server,
+                Duration::from_secs(cfg.rpc_shutdown_grace_period_secs),
+            )
+            .await
+        },
+    }
 
     // Log that the server has stopped.
     warn!("!!! SERVER STOPPED !!!")
 }
 
+/// Check the app health every `interval` and report the changes to the gRPC
+/// health service, the empty service name is the overall server health.
+pub async fn report_health(
+    app: impl RpcApplication,
+    mut reporter: HealthReporter,
+    interval: Duration,
+) {
+    let mut last_status = None;
+
+    loop {
+        let status = app.health_check().await;
+
+        if last_status.as_ref() != Some(&status) {
+            let serving_status = match &status {
+                HealthStatus::Ok => {
+                    info!(">> [gRPC SERVER] health: serving");
+                    ServingStatus::Serving
+                },
+                HealthStatus::Unavailable(reason) => {
+                    warn!(">> [gRPC SERVER] health: not serving ({reason})");
+                    ServingStatus::NotServing
+                },
+            };
+
+            reporter.set_service_status("", serving_status).await;
+            last_status = Some(status);
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
 /// Launches the gRPC server and serves incoming requests.
 ///
 /// * svr - The Router instance that contains the gRPC services to be served.
 pub async fn launch_server(
-    svr: Router,
+    svr: RpcRouter,
     tls: bool,
     rpc_addr: SocketAddr,
     #[cfg(unix)] rpc_uds: Option<&PathBuf>,
@@ -166,7 +225,7 @@ pub fn addr(
 }
 
 /// Returns a `Server` based on the provided `RpcFrameConfig` configuration.
-pub fn server(cfg: &RpcFrameConfig) -> Server {
+pub fn server(cfg: &RpcFrameConfig) -> RpcServer {
     #[cfg(not(feature = "tls"))] // check if the feature "tls" is not enabled
     let svr = Server::builder(); // create a new server builder
 
@@ -209,7 +268,7 @@ pub fn server(cfg: &RpcFrameConfig) -> Server {
                                                         // timeout
     };
 
-    svr // return the created server
+    svr.layer(RequestIdLayer) // return the created server
 }
 
 /// Returns a Tonic `Server` with TLS configuration.
@@ -264,7 +323,7 @@ pub fn tls_server(
 /// # Returns
 ///
 /// The `Router` with the reflection added.
-pub fn add_reflection(svr: Router, app: &impl RpcApplication) -> Router {
+pub fn add_reflection(svr: RpcRouter, app: &impl RpcApplication) -> RpcRouter {
     // Create a reflection builder
     let mut reflection = tonic_reflection::server::Builder::configure();
 
@@ -300,15 +359,43 @@ pub fn server_handle() -> SignalHandle {
     HANDLE.get_or_init(SignalHandle::new).clone()
 }
 
-/// Shuts down the server with the given message.
+/// Drains the application, then stops the server, both have to be done
+/// within `grace_period`.
 ///
 /// # Arguments
 ///
-/// * `s` - The message to display when shutting down.
-fn shutdown(s: &str) {
-    // Log the shutdown message
-    warn!(">> [{}] Signal received, shutdown.", s);
+/// * `app` - The application to drain.
+/// * `server` - The running server future.
+/// * `grace_period` - Max duration of the whole shutdown.
+pub async fn graceful_shutdown<F>(
+    app: &impl RpcApplication,
+    server: F,
+    grace_period: Duration,
+) where
+    F: Future<Output = ()>,
+{
+    let deadline = tokio::time::Instant::now() + grace_period;
+
+    // The server keeps serving while draining, so the pending queues can
+    // still be consumed by the clients.
+    if tokio::time::timeout_at(deadline, app.drain()).await.is_err() {
+        warn!(">> Drain not finished in {:?}, stop anyway.", grace_period);
+    }
 
     // Trigger the server shutdown signal
     server_handle().trigger();
+
+    if tokio::time::timeout_at(deadline, server).await.is_err() {
+        warn!(">> Grace period elapsed, force stop the server.");
+    }
+}
+
+/// Logs the received shutdown signal.
+///
+/// # Arguments
+///
+/// * `s` - The name of the received signal.
+fn shutdown(s: &str) {
+    // Log the shutdown message
+    warn!(">> [{}] Signal received, shutdown.", s);
 }
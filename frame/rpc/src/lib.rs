@@ -7,16 +7,13 @@ pub use components::*;
 pub use peace_cfg::{
     macro_define_rpc_client_config as define_rpc_client_config,
     macro_impl_config as impl_config, peace_config, ParseConfig,
-    RpcClientConfig, SingletonConfig,
+    RpcClientConfig, Secret, SingletonConfig,
 };
 
 use once_cell::sync::OnceCell;
 use std::{net::SocketAddr, sync::Arc};
-use tonic::{
-    async_trait,
-    transport::{server::Router, Server},
-};
-use tower_layer::Identity;
+use tonic::async_trait;
+use tools::health::HealthStatus;
 
 pub type DescriptorBuf<'a> = &'a [u8];
 
@@ -42,5 +39,23 @@ pub trait RpcApplication: Clone + Send + Sync + 'static {
         None
     }
 
-    async fn service(&self, configured_server: Server) -> Router<Identity>;
+    /// Add the services of the app to the server, requests are handled with
+    /// a [`tools::tonic_utils::RequestId`].
+    async fn service(&self, configured_server: RpcServer) -> RpcRouter;
+
+    /// Checked periodically and reported by the standard `gRPC` health
+    /// service, override it to verify the connectivity of the downstream
+    /// services.
+    async fn health_check(&self) -> HealthStatus {
+        HealthStatus::Ok
+    }
+
+    /// Called after receiving a shutdown signal, before the server is
+    /// stopped, e.g. to wait for pending queues to be consumed.
+    ///
+    /// The server still serves the requests while draining, the app has to
+    /// stop its own intake first (e.g. refuse the new logins).
+    ///
+    /// Cancelled once the shutdown grace period has elapsed.
+    async fn drain(&self) {}
 }
@@ -7,6 +7,7 @@ pub mod cfg;
 use std::time::Duration;
 
 use cfg::RuntimeConfig;
+use peace_cfg::ValidateConfig;
 use tokio::runtime::{self, Builder, Runtime};
 
 pub trait CreateRuntime {
@@ -65,3 +66,16 @@ pub fn runtime(
 ) -> Result<tokio::runtime::Runtime, std::io::Error> {
     builder(cfg).build()
 }
+
+/// Check the configuration and exit with `--check-config`, otherwise print
+/// all problems of the configuration and exit if there is any.
+pub fn check_config_or_exit<T>(runtime_cfg: &RuntimeConfig, cfg: &T)
+where
+    T: ValidateConfig + ?Sized,
+{
+    if let Some(mode) = peace_cfg::check_config_mode() {
+        let runtime = runtime(runtime_cfg).unwrap();
+        std::process::exit(runtime.block_on(cfg.check_config(mode)));
+    }
+    cfg.validate_or_exit();
+}
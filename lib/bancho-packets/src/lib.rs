@@ -557,7 +557,8 @@ impl<'a> Iterator for PacketReader<'a> {
     type Item = Packet<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if (self.buf.len() - self.ptr) < BANCHO_PACKET_HEADER_LENGTH {
+        if self.buf.len().saturating_sub(self.ptr) < BANCHO_PACKET_HEADER_LENGTH
+        {
             return None;
         }
         // Slice packet header data `[u8; 7]`,
@@ -576,8 +577,18 @@ impl<'a> Iterator for PacketReader<'a> {
         } else {
             let next_payload_length = payload_length as usize;
 
+            // Truncated packet, nothing after it can be read
+            let payload =
+                match self.buf.get(self.ptr..self.ptr + next_payload_length) {
+                    Some(payload) => payload,
+                    None => {
+                        self.ptr = self.buf.len();
+                        return None;
+                    },
+                };
+
             self.ptr += next_payload_length;
-            self.buf.get(self.ptr - next_payload_length..self.ptr)
+            Some(payload)
         };
 
         Some(Packet { id, payload })
@@ -790,6 +801,91 @@ macro_rules! impl_read_number_array {
 
 impl_read_number_array!(i8, u8, i16, u16, i32, u32, i64, u64);
 
+/// Number of slots of a multiplayer match.
+pub const MATCH_SLOTS: usize = 16;
+
+/// Slot status bits of a slot occupied by a player (not ready, ready, no map,
+/// playing and complete).
+const SLOT_HAS_PLAYER: u8 = 0b0111_1100;
+
+impl BanchoPacketRead<MatchData> for MatchData {
+    /// Read the match sent by the client, such as the `OSU_USER_CREATE_MATCH`
+    /// payload. The client only sends the players of the occupied slots and
+    /// the player mods when freemods is enabled, both are expanded to one
+    /// entry per slot here.
+    fn read(reader: &mut PayloadReader) -> Option<MatchData> {
+        let match_id = reader.read::<u16>()? as i32;
+        let in_progress = reader.read::<bool>()?;
+        let match_type = reader.read::<i8>()?;
+        let play_mods = reader.read::<u32>()?;
+        let match_name = reader.read::<String>()?;
+        // an empty byte is sent instead of the string if no password is set
+        let password = if reader.payload.get(reader.index())? == &0 {
+            reader.increase_index(1);
+            None
+        } else {
+            Some(reader.read::<String>()?).filter(|pw| !pw.is_empty())
+        };
+        let beatmap_name = reader.read::<String>()?;
+        let beatmap_id = reader.read::<i32>()?;
+        let beatmap_md5 = reader.read::<String>()?;
+
+        let mut slot_status = Vec::with_capacity(MATCH_SLOTS);
+        for _ in 0..MATCH_SLOTS {
+            slot_status.push(reader.read::<u8>()?);
+        }
+
+        let mut slot_teams = Vec::with_capacity(MATCH_SLOTS);
+        for _ in 0..MATCH_SLOTS {
+            slot_teams.push(reader.read::<u8>()?);
+        }
+
+        let mut slot_players = vec![0; MATCH_SLOTS];
+        for (slot, status) in slot_status.iter().enumerate() {
+            if status & SLOT_HAS_PLAYER != 0 {
+                slot_players[slot] = reader.read::<i32>()?;
+            }
+        }
+
+        let host_player_id = reader.read::<i32>()?;
+        let match_game_mode = reader.read::<u8>()?;
+        let win_condition = reader.read::<u8>()?;
+        let team_type = reader.read::<u8>()?;
+        let freemods = reader.read::<bool>()?;
+
+        let mut player_mods = vec![0; MATCH_SLOTS];
+        if freemods {
+            for mods in player_mods.iter_mut() {
+                *mods = reader.read::<i32>()?;
+            }
+        }
+
+        let match_seed = reader.read::<i32>()?;
+
+        Some(MatchData {
+            match_id,
+            in_progress,
+            match_type,
+            play_mods,
+            match_name,
+            password,
+            beatmap_name,
+            beatmap_id,
+            beatmap_md5,
+            slot_status,
+            slot_teams,
+            slot_players,
+            host_player_id,
+            match_game_mode,
+            win_condition,
+            team_type,
+            freemods,
+            player_mods,
+            match_seed,
+        })
+    }
+}
+
 /// [`BanchoPacketWrite`] is a trait used to convert rust internal data types to
 /// bancho packets ([`Vec<u8>`]).
 pub trait BanchoPacketWrite {
@@ -370,6 +370,7 @@ packet_struct!(
     UserPresence<'a> {
         user_id: i32,
         username: CowStr<'a>,
+        /// Hours, negative offsets in two's complement (`-5i8 as u8`).
         utc_offset: u8,
         country_code: u8,
         bancho_priv: i32,
@@ -382,7 +383,7 @@ packet_struct!(
             Self::ID,
             self.user_id,
             self.username,
-            self.utc_offset + 24,
+            self.utc_offset.wrapping_add(24),
             self.country_code,
             self.bancho_priv as u8,
             self.longitude,
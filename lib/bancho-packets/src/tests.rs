@@ -1,5 +1,7 @@
 mod packets_reading {
-    use crate::{uleb128_to_u32, BanchoMessage, PacketReader, PayloadReader};
+    use crate::{
+        uleb128_to_u32, BanchoMessage, PacketId, PacketReader, PayloadReader,
+    };
 
     #[test]
     fn test_read_header() {
@@ -38,6 +40,19 @@ mod packets_reading {
         println!("p3 3 (outside): {:?}", p3.next());
     }
 
+    #[test]
+    fn test_read_truncated_packet() {
+        // A ping followed by a notification missing the end of its payload
+        let mut reader = PacketReader::new(&[
+            4, 0, 0, 0, 0, 0, 0, 24, 0, 0, 7, 0, 0, 0, 11, 5, 104,
+        ]);
+
+        assert_eq!(reader.next().map(|p| p.id), Some(PacketId::OSU_PING));
+        assert!(reader.next().is_none());
+        assert!(reader.next().is_none());
+        assert_eq!(reader.index(), 17);
+    }
+
     #[test]
     fn test_read_uleb128() {
         assert_eq!(uleb128_to_u32(&[0xE5, 0x8E, 0x26]), Some((624485, 3)));
@@ -179,6 +194,55 @@ mod packets_reading {
         println!("{:?}", int_list);
         assert_eq!(int_list, Some(vec![1001, 1002, 1003, 1004]))
     }
+
+    #[test]
+    fn test_read_match_data() {
+        use crate::{data, BanchoPacketLength, BanchoPacketWrite, MatchData};
+
+        let mut slot_status = vec![1u8; 16];
+        slot_status[0] = 4;
+        slot_status[3] = 8;
+
+        let payload = data!(
+            0u16,
+            false,
+            0i8,
+            64u32,
+            "match",
+            0u8,
+            "beatmap",
+            1001,
+            "md5",
+            slot_status,
+            vec![0u8; 16],
+            1000,
+            1002,
+            1000,
+            0u8,
+            0u8,
+            0u8,
+            false,
+            42
+        );
+
+        let data = PayloadReader::new(&payload).read::<MatchData>().unwrap();
+
+        assert_eq!(data.match_name, "match");
+        assert_eq!(data.password, None);
+        assert_eq!(data.beatmap_id, 1001);
+        assert_eq!(data.slot_players.len(), 16);
+        assert_eq!(data.slot_players[0], 1000);
+        assert_eq!(data.slot_players[3], 1002);
+        assert_eq!(data.slot_players[1], 0);
+        assert_eq!(data.host_player_id, 1000);
+        assert_eq!(data.player_mods, vec![0; 16]);
+        assert_eq!(data.match_seed, 42);
+
+        // truncated
+        assert!(PayloadReader::new(&payload[..payload.len() - 1])
+            .read::<MatchData>()
+            .is_none());
+    }
 }
 
 mod packets_writing {
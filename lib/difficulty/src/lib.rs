@@ -0,0 +1,482 @@
+use async_trait::async_trait;
+use clap::Parser;
+use clap_serde_derive::ClapSerde;
+use lru::LruCache;
+use rosu_pp::{BeatmapExt, GameMode};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, io,
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use tokio::{fs, task};
+
+pub use rosu_pp::Beatmap;
+
+pub type DynDifficultyService = Arc<dyn DifficultyService + Send + Sync>;
+pub type DynBeatmapLookup = Arc<dyn BeatmapLookup + Send + Sync>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum DifficultyError {
+    #[error("beatmap {0} not found")]
+    BeatmapNotFound(i32),
+    #[error("invalid game mode: {0}")]
+    InvalidMode(u8),
+    #[error("failed to parse beatmap: {0}")]
+    InvalidBeatmap(String),
+    #[error("failed to download beatmap: {0}")]
+    DownloadFailed(#[from] reqwest::Error),
+    #[error("failed to access beatmap file: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to look up beatmap: {0}")]
+    LookupFailed(String),
+    #[error("failed to calculate difficulty: {0}")]
+    CalculationFailed(#[from] task::JoinError),
+}
+
+#[derive(Debug, Clone, Parser, ClapSerde, Serialize, Deserialize)]
+pub struct CliBeatmapFilesConfigs {
+    /// Directory of the `.osu` files used to calculate the difficulty and
+    /// performance points.
+    #[default(".data/beatmaps".to_owned())]
+    #[arg(long, default_value = ".data/beatmaps")]
+    pub beatmaps_dir: String,
+
+    /// Download url of the `.osu` files missing from `beatmaps_dir`, `{id}`
+    /// is replaced with the beatmap id, e.g.
+    /// `https://mirror.example.com/osu/{id}`.
+    ///
+    /// If not configured, only the stored files are used. Only the beatmaps
+    /// found in the database are downloaded, services without database
+    /// access only use the stored files.
+    #[arg(long)]
+    pub beatmap_download_url: Option<String>,
+}
+
+/// Tells whether a beatmap is known, checked before its file is downloaded
+/// so that arbitrary ids are not requested from the mirror.
+#[async_trait]
+pub trait BeatmapLookup {
+    async fn beatmap_exists(
+        &self,
+        beatmap_id: i32,
+    ) -> Result<bool, DifficultyError>;
+}
+
+/// `.osu` files of the beatmaps, stored as `{dir}/{beatmap_id}.osu`.
+///
+/// Missing files of the beatmaps known by `lookup` are downloaded from
+/// `download_url` (`{id}` is replaced with the beatmap id), e.g.
+/// `https://mirror.example.com/osu/{id}`.
+#[derive(Clone)]
+pub struct BeatmapFiles {
+    pub dir: PathBuf,
+    pub download_url: Option<String>,
+    lookup: Option<DynBeatmapLookup>,
+    client: reqwest::Client,
+}
+
+impl fmt::Debug for BeatmapFiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BeatmapFiles")
+            .field("dir", &self.dir)
+            .field("download_url", &self.download_url)
+            .field("lookup", &self.lookup.is_some())
+            .finish()
+    }
+}
+
+impl BeatmapFiles {
+    #[inline]
+    pub fn new<P: Into<PathBuf>>(dir: P, download_url: Option<String>) -> Self {
+        Self {
+            dir: dir.into(),
+            download_url,
+            lookup: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Download the missing files of the beatmaps known by `lookup`,
+    /// without it no file is downloaded.
+    #[inline]
+    pub fn with_lookup(mut self, lookup: DynBeatmapLookup) -> Self {
+        self.lookup = Some(lookup);
+        self
+    }
+
+    #[inline]
+    pub fn with_cfg(cfg: &CliBeatmapFilesConfigs) -> Self {
+        Self::new(&cfg.beatmaps_dir, cfg.beatmap_download_url.clone())
+    }
+
+    #[inline]
+    pub fn path(&self, beatmap_id: i32) -> PathBuf {
+        self.dir.join(format!("{beatmap_id}.osu"))
+    }
+
+    /// Read the beatmap file, downloads it first if it is not stored yet.
+    pub async fn load(
+        &self,
+        beatmap_id: i32,
+    ) -> Result<Vec<u8>, DifficultyError> {
+        let path = self.path(beatmap_id);
+
+        match fs::read(&path).await {
+            Ok(data) => return Ok(data),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+            Err(err) => return Err(err.into()),
+        }
+
+        let data = self.download(beatmap_id).await?;
+
+        fs::create_dir_all(&self.dir).await?;
+        fs::write(&path, &data).await?;
+
+        Ok(data)
+    }
+
+    async fn download(
+        &self,
+        beatmap_id: i32,
+    ) -> Result<Vec<u8>, DifficultyError> {
+        let (url, lookup) =
+            match (self.download_url.as_deref(), self.lookup.as_ref()) {
+                (Some(url), Some(lookup)) => (url, lookup),
+                _ => return Err(DifficultyError::BeatmapNotFound(beatmap_id)),
+            };
+
+        if !lookup.beatmap_exists(beatmap_id).await? {
+            return Err(DifficultyError::BeatmapNotFound(beatmap_id));
+        }
+
+        let url = url.replace("{id}", &beatmap_id.to_string());
+
+        let res = self.client.get(url).send().await?;
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(DifficultyError::BeatmapNotFound(beatmap_id));
+        }
+
+        // unknown beatmaps are served as empty files by some mirrors
+        let data = res.error_for_status()?.bytes().await?;
+        if data.is_empty() {
+            return Err(DifficultyError::BeatmapNotFound(beatmap_id));
+        }
+
+        Ok(data.to_vec())
+    }
+}
+
+/// Game mode of the difficulty calculator, `mode` is the vanilla mode
+/// (`0` standard, `1` taiko, `2` fruits, `3` mania).
+#[inline]
+pub fn game_mode(mode: u8) -> Result<GameMode, DifficultyError> {
+    match mode {
+        0 => Ok(GameMode::Osu),
+        1 => Ok(GameMode::Taiko),
+        2 => Ok(GameMode::Catch),
+        3 => Ok(GameMode::Mania),
+        _ => Err(DifficultyError::InvalidMode(mode)),
+    }
+}
+
+#[inline]
+pub fn parse_beatmap(data: &[u8]) -> Result<Beatmap, DifficultyError> {
+    Beatmap::from_bytes(data)
+        .map_err(|err| DifficultyError::InvalidBeatmap(err.to_string()))
+}
+
+/// Mods changing the star rating (`EZ`, `TD`, `HD`, `HR`, `DT`, `HT`, `FL`),
+/// `NC` is counted as `DT`.
+#[inline]
+pub fn difficulty_mods(mods: u32) -> u32 {
+    const NIGHTCORE: u32 = 512;
+    const DOUBLE_TIME: u32 = 64;
+    const DIFFICULTY_MODS: u32 = 2 | 4 | 8 | 16 | DOUBLE_TIME | 256 | 1024;
+
+    let mods = if mods & NIGHTCORE != 0 { mods | DOUBLE_TIME } else { mods };
+    mods & DIFFICULTY_MODS
+}
+
+/// Star rating of the beatmap with the mods, standard beatmaps are
+/// converted to the other modes.
+#[inline]
+pub fn calculate_star_rating(
+    beatmap: &Beatmap,
+    mode: u8,
+    mods: u32,
+) -> Result<f64, DifficultyError> {
+    Ok(beatmap.stars().mode(game_mode(mode)?).mods(mods).calculate().stars())
+}
+
+/// Hit results of a score.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ScoreHits {
+    pub combo: usize,
+    pub n300: usize,
+    pub n100: usize,
+    pub n50: usize,
+    pub n_misses: usize,
+    pub n_geki: usize,
+    pub n_katu: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PpResult {
+    pub pp: f64,
+    pub stars: f64,
+}
+
+/// Performance points of a score on the beatmap.
+#[inline]
+pub fn calculate_pp(
+    beatmap: &Beatmap,
+    mode: u8,
+    mods: u32,
+    hits: ScoreHits,
+) -> Result<PpResult, DifficultyError> {
+    let attrs = beatmap
+        .pp()
+        .mode(game_mode(mode)?)
+        .mods(mods)
+        .combo(hits.combo)
+        .n300(hits.n300)
+        .n100(hits.n100)
+        .n50(hits.n50)
+        .n_misses(hits.n_misses)
+        .n_geki(hits.n_geki)
+        .n_katu(hits.n_katu)
+        .calculate();
+
+    Ok(PpResult { pp: attrs.pp(), stars: attrs.stars() })
+}
+
+#[async_trait]
+pub trait DifficultyService {
+    async fn star_rating(
+        &self,
+        beatmap_id: i32,
+        mode: u8,
+        mods: u32,
+    ) -> Result<f64, DifficultyError>;
+
+    /// Performance points of a score, `mode` is the vanilla mode.
+    async fn pp(
+        &self,
+        beatmap_id: i32,
+        mode: u8,
+        mods: u32,
+        hits: ScoreHits,
+    ) -> Result<PpResult, DifficultyError>;
+}
+
+/// The most recently used star ratings are cached per
+/// `(beatmap_id, mode, difficulty_mods)`.
+#[derive(Debug)]
+pub struct DifficultyServiceImpl {
+    pub beatmap_files: BeatmapFiles,
+    star_ratings: Mutex<LruCache<(i32, u8, u32), f64>>,
+}
+
+impl DifficultyServiceImpl {
+    const MAX_CACHED_STAR_RATINGS: usize = 65536;
+
+    #[inline]
+    pub fn new(beatmap_files: BeatmapFiles) -> Self {
+        Self {
+            beatmap_files,
+            star_ratings: Mutex::new(LruCache::new(
+                NonZeroUsize::new(Self::MAX_CACHED_STAR_RATINGS).unwrap(),
+            )),
+        }
+    }
+
+    /// Number of cached star ratings.
+    #[inline]
+    pub fn star_rating_cache_len(&self) -> usize {
+        self.star_ratings.lock().unwrap().len()
+    }
+
+    #[inline]
+    pub fn into_service(self) -> DynDifficultyService {
+        Arc::new(self) as DynDifficultyService
+    }
+}
+
+#[async_trait]
+impl DifficultyService for DifficultyServiceImpl {
+    async fn star_rating(
+        &self,
+        beatmap_id: i32,
+        mode: u8,
+        mods: u32,
+    ) -> Result<f64, DifficultyError> {
+        let mods = difficulty_mods(mods);
+        let key = (beatmap_id, mode, mods);
+
+        if let Some(stars) = self.star_ratings.lock().unwrap().get(&key) {
+            return Ok(*stars);
+        }
+
+        let data = self.beatmap_files.load(beatmap_id).await?;
+        let stars = task::spawn_blocking(move || {
+            calculate_star_rating(&parse_beatmap(&data)?, mode, mods)
+        })
+        .await??;
+
+        self.star_ratings.lock().unwrap().put(key, stars);
+
+        Ok(stars)
+    }
+
+    async fn pp(
+        &self,
+        beatmap_id: i32,
+        mode: u8,
+        mods: u32,
+        hits: ScoreHits,
+    ) -> Result<PpResult, DifficultyError> {
+        let data = self.beatmap_files.load(beatmap_id).await?;
+
+        task::spawn_blocking(move || {
+            calculate_pp(&parse_beatmap(&data)?, mode, mods, hits)
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use peace_unique_id::Ulid;
+
+    const BEATMAP: &str = "osu file format v14
+
+[General]
+Mode: 0
+
+[Difficulty]
+HPDrainRate:5
+CircleSize:4
+OverallDifficulty:8
+ApproachRate:9
+SliderMultiplier:1.4
+SliderTickRate:1
+
+[TimingPoints]
+0,300,4,2,0,100,1,0
+
+[HitObjects]
+256,192,1000,1,0,0:0:0:0:
+100,100,1300,1,0,0:0:0:0:
+400,300,1600,1,0,0:0:0:0:
+256,192,1900,1,0,0:0:0:0:
+";
+
+    struct KnownBeatmaps(Vec<i32>);
+
+    #[async_trait]
+    impl BeatmapLookup for KnownBeatmaps {
+        async fn beatmap_exists(
+            &self,
+            beatmap_id: i32,
+        ) -> Result<bool, DifficultyError> {
+            Ok(self.0.contains(&beatmap_id))
+        }
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("peace_difficulty_{}", Ulid::new()))
+    }
+
+    #[test]
+    fn test_difficulty_mods() {
+        // NF, SD, RX, SO
+        assert_eq!(difficulty_mods(1 | 32 | 128 | 4096), 0);
+        // HD DT
+        assert_eq!(difficulty_mods(8 | 64), 8 | 64);
+        // NC
+        assert_eq!(difficulty_mods(512), 64);
+        assert_eq!(difficulty_mods(512 | 64 | 1), 64);
+    }
+
+    #[tokio::test]
+    async fn test_star_rating() {
+        let dir = temp_dir();
+        fs::create_dir_all(&dir).await.unwrap();
+        fs::write(dir.join("1.osu"), BEATMAP).await.unwrap();
+
+        let service = DifficultyServiceImpl::new(BeatmapFiles::new(&dir, None));
+
+        let nomod = service.star_rating(1, 0, 0).await.unwrap();
+        assert!(nomod > 0.0);
+        // DT
+        let dt = service.star_rating(1, 0, 64).await.unwrap();
+        assert!(dt > nomod);
+        // cached
+        assert_eq!(service.star_rating(1, 0, 0).await.unwrap(), nomod);
+        // NC and NF share the DT rating
+        assert_eq!(service.star_rating(1, 0, 512 | 1).await.unwrap(), dt);
+        assert_eq!(service.star_rating_cache_len(), 2);
+
+        assert!(matches!(
+            service.star_rating(2, 0, 0).await,
+            Err(DifficultyError::BeatmapNotFound(2))
+        ));
+        assert!(matches!(
+            service.star_rating(1, 4, 0).await,
+            Err(DifficultyError::InvalidMode(4))
+        ));
+
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_unknown_beatmap() {
+        let dir = temp_dir();
+        // Unreachable, the beatmaps must be rejected before any request
+        let download_url = Some("http://127.0.0.1:1/osu/{id}".to_owned());
+
+        let files = BeatmapFiles::new(&dir, download_url.clone());
+        assert!(matches!(
+            files.load(1).await,
+            Err(DifficultyError::BeatmapNotFound(1))
+        ));
+
+        let files = BeatmapFiles::new(&dir, download_url)
+            .with_lookup(Arc::new(KnownBeatmaps(vec![1])));
+        assert!(matches!(
+            files.load(2).await,
+            Err(DifficultyError::BeatmapNotFound(2))
+        ));
+        assert!(matches!(
+            files.load(1).await,
+            Err(DifficultyError::DownloadFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_calculate_pp() {
+        let beatmap = parse_beatmap(BEATMAP.as_bytes()).unwrap();
+
+        let ss = calculate_pp(
+            &beatmap,
+            0,
+            0,
+            ScoreHits { combo: 4, n300: 4, ..Default::default() },
+        )
+        .unwrap();
+        assert!(ss.pp > 0.0);
+        assert_eq!(ss.stars, calculate_star_rating(&beatmap, 0, 0).unwrap());
+
+        let missed = calculate_pp(
+            &beatmap,
+            0,
+            0,
+            ScoreHits { combo: 2, n300: 3, n_misses: 1, ..Default::default() },
+        )
+        .unwrap();
+        assert!(missed.pp < ss.pp);
+    }
+}
@@ -1,7 +1,10 @@
 use async_trait::async_trait;
 use peace_rpc_error::{RpcError, TonicError};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
 use tokio::fs;
 use tonic::Status;
 
@@ -13,6 +16,12 @@ pub enum LoadSnapshotError {
     DeserializeError(String),
     #[error("failed to read file: {0}")]
     ReadFileError(String),
+    #[error("failed to decompress snapshot: {0}")]
+    DecompressError(String),
+    #[error("unsupported snapshot version: {0} (current: {1})")]
+    UnsupportedVersion(u32, u32),
+    #[error("failed to migrate snapshot from version {0}: {1}")]
+    MigrateError(u32, String),
     #[error("tonic error: {0}")]
     TonicError(String),
     #[error("error: {0}")]
@@ -29,6 +38,8 @@ impl TonicError for LoadSnapshotError {
 pub enum CreateSnapshotError {
     #[error("failed to serialize snapshot: {0}")]
     SerializeError(String),
+    #[error("failed to compress snapshot: {0}")]
+    CompressError(String),
     #[error("failed to create directory: {0}")]
     CreateDirError(String),
     #[error("failed to write file: {0}")]
@@ -54,6 +65,281 @@ pub enum SnapshotType {
     Json,
 }
 
+impl SnapshotType {
+    /// Guess the type of decompressed snapshot data, json snapshots are
+    /// objects.
+    #[inline]
+    pub fn detect(content: &[u8]) -> Self {
+        match content.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{') => Self::Json,
+            _ => Self::Binary,
+        }
+    }
+}
+
+/// Compression codec of snapshot files, determined by the file extension
+/// when reading, so files with different codecs can be mixed.
+#[derive(
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotCompression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl SnapshotCompression {
+    const ZSTD_LEVEL: i32 = 3;
+
+    #[inline]
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::Gzip => Some("gz"),
+            Self::Zstd => Some("zst"),
+        }
+    }
+
+    #[inline]
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Self::Gzip,
+            Some("zst") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+
+    /// Append the codec's extension to `path` if it's not present.
+    #[inline]
+    pub fn with_extension(&self, path: &str) -> String {
+        match self.extension() {
+            Some(ext) if Self::from_path(path) != *self => {
+                format!("{path}.{ext}")
+            },
+            _ => path.to_owned(),
+        }
+    }
+
+    pub fn compress(&self, data: Vec<u8>) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(data),
+            Self::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(
+                    Vec::new(),
+                    flate2::Compression::default(),
+                );
+                encoder.write_all(&data)?;
+                encoder.finish()
+            },
+            Self::Zstd => zstd::encode_all(data.as_slice(), Self::ZSTD_LEVEL),
+        }
+    }
+
+    pub fn decompress(&self, data: Vec<u8>) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(data),
+            Self::Gzip => {
+                let mut buf = Vec::new();
+                flate2::read::GzDecoder::new(data.as_slice())
+                    .read_to_end(&mut buf)?;
+                Ok(buf)
+            },
+            Self::Zstd => zstd::decode_all(data.as_slice()),
+        }
+    }
+}
+
+/// Schema version of a snapshot, written along with the snapshot data.
+///
+/// Bump [`SnapshotSchema::VERSION`] when the snapshot data changes, and
+/// migrate the data of older versions in [`SnapshotSchema::migrate`].
+/// Snapshots from unknown (newer) versions are refused.
+pub trait SnapshotSchema: Sized {
+    const VERSION: u32;
+
+    /// Migrate json snapshot data from `old_version` to the current schema,
+    /// e.g. fill defaults for new fields.
+    ///
+    /// Version `0` is the data of snapshots written before versioning.
+    fn migrate(
+        old_version: u32,
+        data: &mut serde_json::Value,
+    ) -> Result<(), String> {
+        let _ = (old_version, data);
+        Ok(())
+    }
+
+    /// Decode binary snapshot data from `old_version`.
+    ///
+    /// Bincode is not self-describing, so this needs the old schema types
+    /// and is unsupported by default.
+    fn migrate_binary(old_version: u32, data: &[u8]) -> Result<Self, String> {
+        let _ = data;
+        Err(format!(
+            "binary snapshot of version {old_version} is not migratable"
+        ))
+    }
+}
+
+/// Decode bincode data, for [`SnapshotSchema::migrate_binary`] from versions
+/// sharing the same binary layout.
+#[inline]
+pub fn decode_binary<T>(data: &[u8]) -> Result<T, String>
+where
+    T: for<'a> serde::Deserialize<'a>,
+{
+    bincode::deserialize(data).map_err(|err| err.to_string())
+}
+
+/// Magic prefix of versioned binary snapshots.
+const BINARY_SNAPSHOT_MAGIC: &[u8; 4] = b"PSNP";
+
+/// Split versioned binary snapshot data into its version and data, data
+/// without the header was written before versioning (version `0`).
+fn split_binary_header(
+    content: &[u8],
+) -> Result<(u32, &[u8]), LoadSnapshotError> {
+    match content.strip_prefix(BINARY_SNAPSHOT_MAGIC.as_slice()) {
+        Some(rest) if rest.len() >= 4 => Ok((
+            u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]),
+            &rest[4..],
+        )),
+        Some(_) => Err(LoadSnapshotError::DeserializeError(
+            "truncated snapshot header".into(),
+        )),
+        None => Ok((0, content)),
+    }
+}
+
+/// Schema version of decompressed snapshot data, without decoding it.
+pub fn snapshot_version(
+    snapshot_type: SnapshotType,
+    content: &[u8],
+) -> Result<u32, LoadSnapshotError> {
+    match snapshot_type {
+        SnapshotType::Binary => {
+            split_binary_header(content).map(|(version, _)| version)
+        },
+        SnapshotType::Json => {
+            #[derive(Deserialize)]
+            struct Version {
+                version: Option<u32>,
+            }
+
+            serde_json::from_slice::<Version>(content)
+                .map(|v| v.version.unwrap_or_default())
+                .map_err(|err| {
+                    LoadSnapshotError::DeserializeError(err.to_string())
+                })
+        },
+    }
+}
+
+#[derive(Serialize)]
+struct VersionedSnapshotRef<'a, T> {
+    version: u32,
+    data: &'a T,
+}
+
+pub fn encode_snapshot<T>(
+    snapshot_type: SnapshotType,
+    snapshot: &T,
+) -> Result<Vec<u8>, CreateSnapshotError>
+where
+    T: SnapshotSchema + serde::Serialize,
+{
+    match snapshot_type {
+        SnapshotType::Binary => {
+            let mut bytes = Vec::from(*BINARY_SNAPSHOT_MAGIC);
+            bytes.extend_from_slice(&T::VERSION.to_le_bytes());
+            bincode::serialize_into(&mut bytes, snapshot)
+                .map(|_| bytes)
+                .map_err(|err| err.to_string())
+        },
+        SnapshotType::Json => serde_json::to_vec(&VersionedSnapshotRef {
+            version: T::VERSION,
+            data: snapshot,
+        })
+        .map_err(|err| err.to_string()),
+    }
+    .map_err(CreateSnapshotError::SerializeError)
+}
+
+pub fn decode_snapshot<T>(
+    snapshot_type: SnapshotType,
+    content: &[u8],
+) -> Result<T, LoadSnapshotError>
+where
+    T: SnapshotSchema + for<'a> serde::Deserialize<'a>,
+{
+    #[inline]
+    fn check_version<T: SnapshotSchema>(
+        version: u32,
+    ) -> Result<(), LoadSnapshotError> {
+        if version > T::VERSION {
+            return Err(LoadSnapshotError::UnsupportedVersion(
+                version,
+                T::VERSION,
+            ));
+        }
+        Ok(())
+    }
+
+    match snapshot_type {
+        SnapshotType::Binary => {
+            let (version, data) = split_binary_header(content)?;
+
+            check_version::<T>(version)?;
+
+            if version < T::VERSION {
+                return T::migrate_binary(version, data).map_err(|err| {
+                    LoadSnapshotError::MigrateError(version, err)
+                });
+            }
+
+            bincode::deserialize(data).map_err(|err| {
+                LoadSnapshotError::DeserializeError(err.to_string())
+            })
+        },
+        SnapshotType::Json => {
+            let mut value: serde_json::Value = serde_json::from_slice(content)
+                .map_err(|err| {
+                    LoadSnapshotError::DeserializeError(err.to_string())
+                })?;
+
+            let versioned = value.get("version").and_then(|v| v.as_u64());
+            let (version, mut data) =
+                match (versioned, value.get_mut("data").map(|d| d.take())) {
+                    (Some(version), Some(data)) => (version as u32, data),
+                    // Written before versioning
+                    _ => (0, value),
+                };
+
+            check_version::<T>(version)?;
+
+            if version < T::VERSION {
+                T::migrate(version, &mut data).map_err(|err| {
+                    LoadSnapshotError::MigrateError(version, err)
+                })?;
+            }
+
+            serde_json::from_value(data).map_err(|err| {
+                LoadSnapshotError::DeserializeError(err.to_string())
+            })
+        },
+    }
+}
+
 pub trait SnapshotTime {
     fn snapshot_time(&self) -> u64;
 }
@@ -61,9 +347,35 @@ pub trait SnapshotTime {
 pub trait SnapshotConfig {
     fn snapshot_path(&self) -> &str;
     fn snapshot_type(&self) -> SnapshotType;
+    fn snapshot_compression(&self) -> SnapshotCompression;
     fn should_save_snapshot(&self) -> bool;
     fn should_load_snapshot(&self) -> bool;
     fn snapshot_expired_secs(&self) -> u64;
+
+    /// Path of the snapshot file, with the compression extension.
+    #[inline]
+    fn snapshot_file_path(&self) -> String {
+        self.snapshot_compression().with_extension(self.snapshot_path())
+    }
+
+    /// Find an existing snapshot file, prefers the one of the configured
+    /// compression, then falls back to the other codecs.
+    fn find_snapshot_file(&self) -> Option<String> {
+        let preferred = self.snapshot_file_path();
+
+        std::iter::once(preferred.clone())
+            .chain(
+                [
+                    SnapshotCompression::None,
+                    SnapshotCompression::Gzip,
+                    SnapshotCompression::Zstd,
+                ]
+                .iter()
+                .map(|c| c.with_extension(self.snapshot_path()))
+                .filter(|path| path != &preferred),
+            )
+            .find(|path| Path::new(path).is_file())
+    }
 }
 
 #[async_trait]
@@ -82,7 +394,7 @@ pub trait LoadSnapshotFrom: Sized {
 #[async_trait]
 impl<T> LoadSnapshotFrom for T
 where
-    T: for<'a> serde::Deserialize<'a>,
+    T: SnapshotSchema + for<'a> serde::Deserialize<'a>,
 {
     async fn load_snapshot_from(
         snapshot_type: SnapshotType,
@@ -92,18 +404,13 @@ where
             .await
             .map_err(|err| LoadSnapshotError::ReadFileError(err.to_string()))?;
 
-        Ok(match snapshot_type {
-            SnapshotType::Binary => {
-                bincode::deserialize(&content).map_err(|err| {
-                    LoadSnapshotError::DeserializeError(err.to_string())
-                })?
-            },
-            SnapshotType::Json => {
-                serde_json::from_slice(&content).map_err(|err| {
-                    LoadSnapshotError::DeserializeError(err.to_string())
-                })?
-            },
-        })
+        let content = SnapshotCompression::from_path(snapshot_path)
+            .decompress(content)
+            .map_err(|err| {
+                LoadSnapshotError::DecompressError(err.to_string())
+            })?;
+
+        decode_snapshot(snapshot_type, &content)
     }
 }
 
@@ -137,7 +444,7 @@ pub trait SaveSnapshotTo<D> {
 impl<T, D> SaveSnapshotTo<D> for T
 where
     T: CreateSnapshot<D> + Sync + Send,
-    D: serde::Serialize + Send,
+    D: SnapshotSchema + serde::Serialize + Send,
 {
     async fn save_snapshot_to(
         &self,
@@ -146,13 +453,11 @@ where
     ) -> Result<usize, CreateSnapshotError> {
         let create_snapshot = self.create_snapshot().await;
 
-        let bytes_data = match snapshot_type {
-            SnapshotType::Binary => bincode::serialize(&create_snapshot)
-                .map_err(|err| err.to_string()),
-            SnapshotType::Json => serde_json::to_vec(&create_snapshot)
-                .map_err(|err| err.to_string()),
-        }
-        .map_err(CreateSnapshotError::SerializeError)?;
+        let bytes_data = SnapshotCompression::from_path(snapshot_path)
+            .compress(encode_snapshot(snapshot_type, &create_snapshot)?)
+            .map_err(|err| {
+                CreateSnapshotError::CompressError(err.to_string())
+            })?;
 
         let path = Path::new(snapshot_path);
 
@@ -169,3 +474,165 @@ where
         Ok(bytes_data.len())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestSnapshot {
+        name: String,
+        away_message: String,
+    }
+
+    impl SnapshotSchema for TestSnapshot {
+        const VERSION: u32 = 2;
+
+        fn migrate(
+            old_version: u32,
+            data: &mut serde_json::Value,
+        ) -> Result<(), String> {
+            // v2: add `away_message`
+            if old_version < 2 {
+                data.as_object_mut()
+                    .ok_or("snapshot data is not an object")?
+                    .insert("away_message".into(), "".into());
+            }
+            Ok(())
+        }
+    }
+
+    fn snapshot() -> TestSnapshot {
+        TestSnapshot { name: "peace".into(), away_message: "afk".into() }
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        for snapshot_type in [SnapshotType::Binary, SnapshotType::Json] {
+            let bytes = encode_snapshot(snapshot_type, &snapshot()).unwrap();
+            let decoded: TestSnapshot =
+                decode_snapshot(snapshot_type, &bytes).unwrap();
+            assert_eq!(decoded, snapshot());
+        }
+    }
+
+    #[test]
+    fn test_migrate_json_snapshot() {
+        let legacy = br#"{"name":"peace"}"#;
+        let decoded: TestSnapshot =
+            decode_snapshot(SnapshotType::Json, legacy).unwrap();
+        assert_eq!(decoded.name, "peace");
+        assert_eq!(decoded.away_message, "");
+
+        let v1 = br#"{"version":1,"data":{"name":"peace"}}"#;
+        let decoded: TestSnapshot =
+            decode_snapshot(SnapshotType::Json, v1).unwrap();
+        assert_eq!(decoded.away_message, "");
+    }
+
+    #[test]
+    fn test_refuse_future_version() {
+        let future = br#"{"version":3,"data":{"name":"peace"}}"#;
+        assert!(matches!(
+            decode_snapshot::<TestSnapshot>(SnapshotType::Json, future),
+            Err(LoadSnapshotError::UnsupportedVersion(3, 2))
+        ));
+
+        let mut bytes =
+            encode_snapshot(SnapshotType::Binary, &snapshot()).unwrap();
+        bytes[4..8].copy_from_slice(&3u32.to_le_bytes());
+        assert!(matches!(
+            decode_snapshot::<TestSnapshot>(SnapshotType::Binary, &bytes),
+            Err(LoadSnapshotError::UnsupportedVersion(3, 2))
+        ));
+    }
+
+    #[test]
+    fn test_snapshot_version() {
+        for snapshot_type in [SnapshotType::Binary, SnapshotType::Json] {
+            let bytes = encode_snapshot(snapshot_type, &snapshot()).unwrap();
+            assert_eq!(SnapshotType::detect(&bytes), snapshot_type);
+            assert_eq!(snapshot_version(snapshot_type, &bytes).unwrap(), 2);
+        }
+
+        let legacy = br#"{"name":"peace"}"#;
+        assert_eq!(snapshot_version(SnapshotType::Json, legacy).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_compression_extension() {
+        assert_eq!(
+            SnapshotCompression::from_path("a.snapshot.zst"),
+            SnapshotCompression::Zstd
+        );
+        assert_eq!(
+            SnapshotCompression::from_path("a.snapshot.gz"),
+            SnapshotCompression::Gzip
+        );
+        assert_eq!(
+            SnapshotCompression::from_path("a.snapshot"),
+            SnapshotCompression::None
+        );
+
+        assert_eq!(
+            SnapshotCompression::Zstd.with_extension("a.json"),
+            "a.json.zst"
+        );
+        assert_eq!(
+            SnapshotCompression::Zstd.with_extension("a.json.zst"),
+            "a.json.zst"
+        );
+        assert_eq!(
+            SnapshotCompression::None.with_extension("a.json"),
+            "a.json"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compressed_snapshot_file_round_trip() {
+        struct Creator;
+
+        #[async_trait]
+        impl CreateSnapshot<TestSnapshot> for Creator {
+            async fn create_snapshot(&self) -> TestSnapshot {
+                snapshot()
+            }
+        }
+
+        let dir = std::env::temp_dir()
+            .join(format!("peace_snapshot_test_{}", std::process::id()));
+
+        for compression in [
+            SnapshotCompression::None,
+            SnapshotCompression::Gzip,
+            SnapshotCompression::Zstd,
+        ] {
+            for snapshot_type in [SnapshotType::Binary, SnapshotType::Json] {
+                let path = compression.with_extension(
+                    dir.join(format!("{snapshot_type:?}.snapshot"))
+                        .to_str()
+                        .unwrap(),
+                );
+
+                Creator.save_snapshot_to(snapshot_type, &path).await.unwrap();
+
+                let loaded =
+                    TestSnapshot::load_snapshot_from(snapshot_type, &path)
+                        .await
+                        .unwrap();
+                assert_eq!(loaded, snapshot());
+            }
+        }
+
+        let _ = fs::remove_dir_all(dir).await;
+    }
+
+    #[test]
+    fn test_old_binary_snapshot_not_migratable() {
+        let legacy = bincode::serialize(&snapshot()).unwrap();
+        assert!(matches!(
+            decode_snapshot::<TestSnapshot>(SnapshotType::Binary, &legacy),
+            Err(LoadSnapshotError::MigrateError(0, _))
+        ));
+    }
+}
@@ -18,6 +18,10 @@ macro_rules! impl_snapshot_config {
                     self.[<$pf _snapshot_type>]
                 }
 
+                fn snapshot_compression(&self) -> $crate::SnapshotCompression {
+                    self.[<$pf _snapshot_compression>]
+                }
+
                 fn should_save_snapshot(&self) -> bool {
                     self.[<$pf _snapshot>]
                 }
@@ -54,6 +58,12 @@ macro_rules! snapshot_config_struct {
                 #[arg(long, value_enum, default_value = "binary")]
                 pub [<$pf _snapshot_type>]: SnapshotType,
 
+                /// Compression codec of the snapshot file, its extension
+                /// (`.gz` / `.zst`) is appended to the snapshot path.
+                #[default($crate::SnapshotCompression::None)]
+                #[arg(long, value_enum, default_value = "none")]
+                pub [<$pf _snapshot_compression>]: $crate::SnapshotCompression,
+
                 #[arg(long)]
                 pub [<$pf _snapshot>]: bool,
 
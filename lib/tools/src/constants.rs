@@ -1,4 +1,5 @@
 pub const X_REAL_IP: &str = "x-real-ip";
+pub const X_REQUEST_ID: &str = "x-request-id";
 
 #[rustfmt::skip]
 pub const PEACE_BANNER: &str = r"
@@ -0,0 +1,17 @@
+/// Health of an app, reported by the health endpoints so that orchestrators
+/// can stop routing traffic to it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum HealthStatus {
+    #[default]
+    Ok,
+    /// The app is running but can not serve requests, e.g. a downstream
+    /// service is unreachable.
+    Unavailable(String),
+}
+
+impl HealthStatus {
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Self::Ok)
+    }
+}
@@ -7,7 +7,9 @@ pub mod cache;
 pub mod constants;
 #[cfg(feature = "crypto")]
 pub mod crypto;
+pub mod health;
 pub mod macros;
+pub mod rate_limiter;
 #[cfg(feature = "tonic_utils")]
 pub mod tonic_utils;
 
@@ -0,0 +1,133 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Token bucket limiter, one bucket per key (client ip, user id...), use
+/// `()` as the key for a single global limit.
+#[derive(Debug)]
+pub struct KeyedRateLimiter<K> {
+    refill_per_sec: f64,
+    burst: u32,
+    buckets: Mutex<HashMap<K, TokenBucket>>,
+}
+
+impl<K: Eq + Hash> KeyedRateLimiter<K> {
+    /// Full buckets are dropped once this many keys are tracked.
+    const MAX_TRACKED_KEYS: usize = 65536;
+
+    /// `refill_per_sec` of `0` disables the limit.
+    #[inline]
+    pub fn new(refill_per_sec: f64, burst: u32) -> Self {
+        Self { refill_per_sec, burst, buckets: Mutex::default() }
+    }
+
+    /// `rate_per_minute` of `0` disables the limit.
+    #[inline]
+    pub fn per_minute(rate_per_minute: u32, burst: u32) -> Self {
+        Self::new(rate_per_minute as f64 / 60.0, burst)
+    }
+
+    /// One request per `min_interval`, a zero interval disables the limit.
+    #[inline]
+    pub fn with_min_interval(min_interval: Duration) -> Self {
+        if min_interval.is_zero() {
+            return Self::new(0.0, 1);
+        }
+
+        Self::new(1.0 / min_interval.as_secs_f64(), 1)
+    }
+
+    #[inline]
+    fn refilled(&self, bucket: &TokenBucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        (bucket.tokens + elapsed * self.refill_per_sec).min(self.burst as f64)
+    }
+
+    /// Take a request of the key, returns the time to wait before the next
+    /// request if there is none left.
+    pub fn check(&self, key: K) -> Result<(), Duration> {
+        if self.refill_per_sec <= 0.0 {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= Self::MAX_TRACKED_KEYS {
+            buckets.retain(|_, b| self.refilled(b, now) < self.burst as f64);
+        }
+
+        let bucket = buckets.entry(key).or_insert(TokenBucket {
+            tokens: self.burst as f64,
+            updated_at: now,
+        });
+
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated_at = now;
+
+        if bucket.tokens < 1.0 {
+            return Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.refill_per_sec,
+            ));
+        }
+
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+
+    /// Forget the requests of the key.
+    #[inline]
+    pub fn reset(&self, key: K) {
+        self.buckets.lock().unwrap().remove(&key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyed_rate_limiter() {
+        const BURST: u32 = 3;
+
+        let limiter = KeyedRateLimiter::per_minute(6, BURST);
+
+        for _ in 0..BURST {
+            assert!(limiter.check(1).is_ok());
+        }
+        assert!(limiter.check(1).unwrap_err() <= Duration::from_secs(10));
+
+        // other keys are not affected
+        assert!(limiter.check(2).is_ok());
+
+        limiter.reset(1);
+        assert!(limiter.check(1).is_ok());
+
+        let unlimited = KeyedRateLimiter::new(0.0, 0);
+        for _ in 0..100 {
+            assert!(unlimited.check(1).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_min_interval() {
+        let limiter =
+            KeyedRateLimiter::with_min_interval(Duration::from_secs(60));
+
+        assert!(limiter.check(()).is_ok());
+        assert!(limiter.check(()).unwrap_err() <= Duration::from_secs(60));
+
+        let unlimited = KeyedRateLimiter::with_min_interval(Duration::ZERO);
+        assert!(unlimited.check(()).is_ok());
+        assert!(unlimited.check(()).is_ok());
+    }
+}
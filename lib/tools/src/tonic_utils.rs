@@ -1,7 +1,20 @@
-use std::net::IpAddr;
-use tonic::Request;
+use std::{future::Future, net::IpAddr};
+use tonic::{
+    metadata::MetadataMap,
+    service::{interceptor::InterceptedService, Interceptor},
+    transport::Channel,
+    Request, Status,
+};
 
-use crate::constants::X_REAL_IP;
+use crate::constants::{X_REAL_IP, X_REQUEST_ID};
+
+/// Channel of the rpc clients, the id of the request being handled is
+/// added to the outgoing requests.
+pub type TracedChannel = InterceptedService<Channel, PropagateRequestId>;
+
+tokio::task_local! {
+    static REQUEST_ID: RequestId;
+}
 
 pub struct RawRequest;
 
@@ -18,3 +31,87 @@ impl RawRequest {
         req
     }
 }
+
+/// Id shared by the rpc calls made to handle a request (`x-request-id`), so
+/// that their logs can be correlated across the services.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    const MAX_LEN: usize = 64;
+
+    #[inline]
+    pub fn generate() -> Self {
+        Self(uuid::Uuid::new_v4().simple().to_string())
+    }
+
+    /// Ids sent by the clients are only accepted if they are short and
+    /// printable, so that they can not garble the logs.
+    #[inline]
+    pub fn parse(s: &str) -> Option<Self> {
+        let valid = !s.is_empty()
+            && s.len() <= Self::MAX_LEN
+            && s.bytes().all(|b| b.is_ascii_graphic());
+
+        valid.then(|| Self(s.to_owned()))
+    }
+
+    #[inline]
+    pub fn from_metadata(metadata: &MetadataMap) -> Option<Self> {
+        metadata.get(X_REQUEST_ID)?.to_str().ok().and_then(Self::parse)
+    }
+
+    /// Id of the request handled by the current task.
+    #[inline]
+    pub fn current() -> Option<Self> {
+        REQUEST_ID.try_with(|id| id.clone()).ok()
+    }
+
+    /// Run `f` as part of this request, the rpc calls it makes carry the id.
+    #[inline]
+    pub async fn scope<F: Future>(self, f: F) -> F::Output {
+        REQUEST_ID.scope(self, f).await
+    }
+}
+
+/// Client interceptor adding the id of the current request to the outgoing
+/// requests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PropagateRequestId;
+
+impl Interceptor for PropagateRequestId {
+    fn call(
+        &mut self,
+        mut request: Request<()>,
+    ) -> Result<Request<()>, Status> {
+        if let Some(Ok(id)) = RequestId::current().map(|id| id.0.parse()) {
+            request.metadata_mut().insert(X_REQUEST_ID, id);
+        }
+
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_propagate_request_id() {
+        let mut interceptor = PropagateRequestId;
+
+        let request = interceptor.call(Request::new(())).unwrap();
+        assert_eq!(RequestId::from_metadata(request.metadata()), None);
+
+        let id = RequestId::generate();
+        let request = id
+            .clone()
+            .scope(async { interceptor.call(Request::new(())).unwrap() })
+            .await;
+        assert_eq!(RequestId::from_metadata(request.metadata()), Some(id));
+
+        assert_eq!(RequestId::parse(""), None);
+        assert_eq!(RequestId::parse("a b"), None);
+        assert_eq!(RequestId::parse(&"a".repeat(65)), None);
+    }
+}